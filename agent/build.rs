@@ -58,6 +58,9 @@ fn get_branch() -> Result<String, Box<dyn Error>> {
     panic!("no branch name found")
 }
 
+// libtrace (src/ebpf) only builds on these architectures, others are built without eBPF
+const EBPF_ARCHS: [&str; 2] = ["x86_64", "aarch64"];
+
 struct EnvCommand(&'static str, Vec<&'static str>);
 
 fn set_build_info() -> Result<(), Box<dyn Error>> {
@@ -104,45 +107,49 @@ fn set_build_libtrace() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn set_linkage() -> Result<(), Box<dyn Error>> {
+fn set_linkage(target_arch: &str, ebpf_enabled: bool) -> Result<(), Box<dyn Error>> {
     let target_env = env::var("CARGO_CFG_TARGET_ENV")?;
     if target_env.as_str() == "musl" {
-        #[cfg(target_arch = "x86_64")]
-        println!("cargo:rustc-link-search=native=/usr/x86_64-linux-musl/lib64");
-
-        #[cfg(target_arch = "aarch64")]
-        println!("cargo:rustc-link-search=native=/usr/aarch64-linux-musl/lib64");
+        println!(
+            "cargo:rustc-link-search=native=/usr/{}-linux-musl/lib64",
+            target_arch
+        );
     }
     println!("cargo:rustc-link-search=native=/usr/lib");
     println!("cargo:rustc-link-search=native=/usr/lib64");
 
-    println!("cargo:rustc-link-lib=static=GoReSym");
+    if ebpf_enabled {
+        println!("cargo:rustc-link-lib=static=GoReSym");
 
-    #[cfg(target_arch = "x86_64")]
-    println!("cargo:rustc-link-lib=static=bddisasm");
+        if target_arch == "x86_64" {
+            println!("cargo:rustc-link-lib=static=bddisasm");
+        }
 
-    println!("cargo:rustc-link-lib=static=dwarf");
-    println!("cargo:rustc-link-lib=static=bcc_bpf");
+        println!("cargo:rustc-link-lib=static=dwarf");
+        println!("cargo:rustc-link-lib=static=bcc_bpf");
+    }
 
     println!("cargo:rustc-link-lib=static=elf");
 
     match target_env.as_str() {
         "gnu" => {
-            println!("cargo:rustc-link-lib=static=bcc");
+            if ebpf_enabled {
+                println!("cargo:rustc-link-lib=static=bcc");
+            }
             println!("cargo:rustc-link-lib=dylib=pthread");
             println!("cargo:rustc-link-lib=dylib=z");
             println!("cargo:rustc-link-lib=dylib=stdc++");
-            #[cfg(target_arch = "x86_64")]
-            println!("cargo:rustc-link-lib=static=pcap");
-            #[cfg(target_arch = "aarch64")]
-            println!("cargo:rustc-link-lib=dylib=pcap");
+            if target_arch == "x86_64" {
+                println!("cargo:rustc-link-lib=static=pcap");
+            } else {
+                println!("cargo:rustc-link-lib=dylib=pcap");
+            }
         }
         "musl" => {
-            #[cfg(target_arch = "x86_64")]
-            println!("cargo:rustc-link-lib=static=bcc");
-
-            #[cfg(target_arch = "x86_64")]
-            println!("cargo:rustc-link-lib=static=stdc++");
+            if ebpf_enabled && target_arch == "x86_64" {
+                println!("cargo:rustc-link-lib=static=bcc");
+                println!("cargo:rustc-link-lib=static=stdc++");
+            }
 
             println!("cargo:rustc-link-lib=static=pcap");
             println!("cargo:rustc-link-lib=static=c");
@@ -166,6 +173,14 @@ fn compile_wasm_plugin_proto() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+fn compile_cri_proto() -> Result<(), Box<dyn Error>> {
+    tonic_build::configure()
+        .build_server(false)
+        .out_dir("src/rpc/cri")
+        .compile(&["src/rpc/cri/api.proto"], &["src/rpc/cri"])?;
+    Ok(())
+}
+
 fn make_pulsar_proto() -> Result<(), Box<dyn Error>> {
     tonic_build::configure()
         .field_attribute(".", "#[serde(skip_serializing_if = \"Option::is_none\")]")
@@ -223,12 +238,25 @@ fn make_brpc_proto() -> Result<(), Box<dyn Error>> {
 fn main() -> Result<(), Box<dyn Error>> {
     set_build_info()?;
     compile_wasm_plugin_proto()?;
+    compile_cri_proto()?;
     make_pulsar_proto()?;
     make_brpc_proto()?;
+    println!("cargo:rustc-check-cfg=cfg(ebpf_unsupported)");
     let target_os = env::var("CARGO_CFG_TARGET_OS")?;
     if target_os.as_str() == "linux" {
-        set_build_libtrace()?;
-        set_linkage()?;
+        // build.rs runs on the host, so target arch must be read from env instead of cfg
+        let target_arch = env::var("CARGO_CFG_TARGET_ARCH")?;
+        let ebpf_enabled = EBPF_ARCHS.contains(&target_arch.as_str());
+        if ebpf_enabled {
+            set_build_libtrace()?;
+        } else {
+            println!(
+                "cargo:warning=eBPF is not supported on {}, building without it",
+                target_arch
+            );
+            println!("cargo:rustc-cfg=ebpf_unsupported");
+        }
+        set_linkage(&target_arch, ebpf_enabled)?;
     }
     Ok(())
 }
@@ -39,6 +39,20 @@ fn generate_protobuf() -> Result<(), Box<dyn Error>> {
             &["../../../message/opentelemetry/opentelemetry/proto/trace/v1/trace.proto"],
             &["../../../message/opentelemetry"],
         )?;
+    tonic_build::configure()
+        .build_server(false)
+        .out_dir("src/proto/integration")
+        .compile(
+            &["../../../message/jaeger/collector.proto"],
+            &["../../../message/jaeger"],
+        )?;
+    tonic_build::configure()
+        .build_server(false)
+        .out_dir("src/proto/integration")
+        .compile(
+            &["../../../message/pprof/profile.proto"],
+            &["../../../message/pprof"],
+        )?;
 
     // FIXME: Wait for the rustfmt ignore attribute to be removed in stable rust support
     Command::new("cargo")
@@ -14,17 +14,52 @@
  * limitations under the License.
  */
 
-use std::sync::Weak;
+use std::borrow::Cow;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Weak,
+};
 
 use cadence::{
     ext::{MetricValue, ToCounterValue, ToGaugeValue},
     MetricResult,
 };
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum CounterType {
     Counted,
     Gauged,
+    // one point of a histogram, counter name is the name of the histogram
+    Histogram(HistogramPoint),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistogramPoint {
+    // cumulative count of observations less than or equal to the bound, u64::MAX for +Inf
+    Bucket(u64),
+    Sum,
+    Count,
+    // estimated quantile in percent
+    Quantile(u8),
+}
+
+// Flattened name of a point for metric systems without histogram type,
+// e.g. `delay_bucket_1000`, `delay_bucket_inf`, `delay_sum` and `delay_p99`
+pub fn point_name(name: &'static str, counter_type: CounterType) -> Cow<'static, str> {
+    match counter_type {
+        CounterType::Counted | CounterType::Gauged => Cow::Borrowed(name),
+        CounterType::Histogram(HistogramPoint::Bucket(u64::MAX)) => {
+            Cow::Owned(format!("{}_bucket_inf", name))
+        }
+        CounterType::Histogram(HistogramPoint::Bucket(b)) => {
+            Cow::Owned(format!("{}_bucket_{}", name, b))
+        }
+        CounterType::Histogram(HistogramPoint::Sum) => Cow::Owned(format!("{}_sum", name)),
+        CounterType::Histogram(HistogramPoint::Count) => Cow::Owned(format!("{}_count", name)),
+        CounterType::Histogram(HistogramPoint::Quantile(q)) => {
+            Cow::Owned(format!("{}_p{}", name, q))
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -58,6 +93,105 @@ impl ToGaugeValue for CounterValue {
 
 pub type Counter = (&'static str, CounterType, CounterValue);
 
+pub const HISTOGRAM_QUANTILES: [u8; 3] = [50, 90, 99];
+
+// Histogram with fixed bucket bounds chosen by the owner
+//
+// Like `Counted` counters, observations are reset on each collection, so buckets
+// and quantiles describe the last stats interval only.
+#[derive(Debug)]
+pub struct Histogram {
+    name: &'static str,
+    bounds: Box<[u64]>,
+    // buckets[i] counts observations in (bounds[i - 1], bounds[i]], the last one is +Inf
+    buckets: Box<[AtomicU64]>,
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    pub fn new(name: &'static str, bounds: &[u64]) -> Self {
+        let mut bounds = bounds.to_vec();
+        bounds.sort_unstable();
+        bounds.dedup();
+        bounds.retain(|b| *b != u64::MAX);
+        Self {
+            name,
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            bounds: bounds.into_boxed_slice(),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, value: u64) {
+        let index = self.bounds.partition_point(|b| *b < value);
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn get_counters(&self) -> Vec<Counter> {
+        let counts = self
+            .buckets
+            .iter()
+            .map(|b| b.swap(0, Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        let sum = self.sum.swap(0, Ordering::Relaxed);
+        let total = counts.iter().sum::<u64>();
+
+        let mut counters = Vec::with_capacity(counts.len() + 2 + HISTOGRAM_QUANTILES.len());
+        let mut cumulative = 0;
+        for (i, count) in counts.iter().enumerate() {
+            cumulative += count;
+            counters.push((
+                self.name,
+                CounterType::Histogram(HistogramPoint::Bucket(
+                    self.bounds.get(i).copied().unwrap_or(u64::MAX),
+                )),
+                CounterValue::Unsigned(cumulative),
+            ));
+        }
+        counters.push((
+            self.name,
+            CounterType::Histogram(HistogramPoint::Sum),
+            CounterValue::Unsigned(sum),
+        ));
+        counters.push((
+            self.name,
+            CounterType::Histogram(HistogramPoint::Count),
+            CounterValue::Unsigned(total),
+        ));
+        for q in HISTOGRAM_QUANTILES {
+            counters.push((
+                self.name,
+                CounterType::Histogram(HistogramPoint::Quantile(q)),
+                CounterValue::Float(self.quantile(&counts, total, q)),
+            ));
+        }
+        counters
+    }
+
+    // Interpolates linearly inside the bucket holding the rank, the same way as
+    // `histogram_quantile` in prometheus. Ranks in the +Inf bucket get the largest bound.
+    fn quantile(&self, counts: &[u64], total: u64, q: u8) -> f64 {
+        if total == 0 {
+            return 0.0;
+        }
+        let rank = total as f64 * q as f64 / 100.0;
+        let mut cumulative = 0;
+        for (i, count) in counts.iter().enumerate() {
+            if *count > 0 && (cumulative + count) as f64 >= rank {
+                let lower = if i == 0 { 0 } else { self.bounds[i - 1] } as f64;
+                let Some(upper) = self.bounds.get(i) else {
+                    return lower;
+                };
+                return lower
+                    + (*upper as f64 - lower) * (rank - cumulative as f64) / *count as f64;
+            }
+            cumulative += count;
+        }
+        self.bounds.last().copied().unwrap_or_default() as f64
+    }
+}
+
 pub trait RefCountable: Send + Sync {
     fn get_counters(&self) -> Vec<Counter>;
 }
@@ -87,3 +221,79 @@ impl Countable {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram() {
+        let h = Histogram::new("delay", &[100, 10, 1000, 10]);
+        for v in [5, 10, 50, 50, 500, 5000] {
+            h.observe(v);
+        }
+        let counters = h.get_counters();
+        let buckets = counters
+            .iter()
+            .filter_map(|c| match (c.1, c.2) {
+                (CounterType::Histogram(HistogramPoint::Bucket(b)), CounterValue::Unsigned(v)) => {
+                    Some((b, v))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(buckets, vec![(10, 2), (100, 4), (1000, 5), (u64::MAX, 6)]);
+        assert!(counters.contains(&(
+            "delay",
+            CounterType::Histogram(HistogramPoint::Sum),
+            CounterValue::Unsigned(5615)
+        )));
+        assert!(counters.contains(&(
+            "delay",
+            CounterType::Histogram(HistogramPoint::Count),
+            CounterValue::Unsigned(6)
+        )));
+        // rank 3 is the first of 2 observations in (10, 100]
+        assert!(counters.contains(&(
+            "delay",
+            CounterType::Histogram(HistogramPoint::Quantile(50)),
+            CounterValue::Float(55.0)
+        )));
+        // rank 5.94 is in +Inf bucket
+        assert!(counters.contains(&(
+            "delay",
+            CounterType::Histogram(HistogramPoint::Quantile(99)),
+            CounterValue::Float(1000.0)
+        )));
+
+        // reset after collection
+        assert!(h.get_counters().contains(&(
+            "delay",
+            CounterType::Histogram(HistogramPoint::Count),
+            CounterValue::Unsigned(0)
+        )));
+    }
+
+    #[test]
+    fn histogram_point_name() {
+        assert_eq!(point_name("delay", CounterType::Counted), "delay");
+        assert_eq!(
+            point_name("delay", CounterType::Histogram(HistogramPoint::Bucket(10))),
+            "delay_bucket_10"
+        );
+        assert_eq!(
+            point_name(
+                "delay",
+                CounterType::Histogram(HistogramPoint::Bucket(u64::MAX))
+            ),
+            "delay_bucket_inf"
+        );
+        assert_eq!(
+            point_name(
+                "delay",
+                CounterType::Histogram(HistogramPoint::Quantile(99))
+            ),
+            "delay_p99"
+        );
+    }
+}
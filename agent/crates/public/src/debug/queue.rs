@@ -32,10 +32,12 @@ use bincode::{config::Configuration, encode_to_vec, Decode, Encode};
 use log::warn;
 
 use super::{Error as SendError, Result, DEBUG_QUEUE_IDLE_TIMEOUT, MAX_BUF_SIZE};
-use crate::queue::{Error, Receiver};
+use crate::queue::{Error, QueueState, Receiver};
 
 const QUEUE_RELEASE_TIMEOUT: Duration = Duration::from_micros(1);
 const QUEUE_RECV_TIMEOUT: Duration = Duration::from_secs(1);
+// keep each response within MAX_BUF_SIZE
+const QUEUE_STATS_BATCH: usize = 32;
 
 pub fn send_to(
     sock: &UdpSocket,
@@ -68,6 +70,21 @@ pub enum QueueMessage {
     Fin,
     // 如果queue已经关闭，发送关闭消息
     Err(String),
+    // None 表示请求， Some表示响应
+    Stats(Option<Vec<QueueStats>>),
+}
+
+#[derive(PartialEq, Debug, Encode, Decode)]
+pub struct QueueStats {
+    pub name: String,
+    pub capacity: u64,
+    pub pending: u64,
+    pub high_watermark: u64,
+    pub overwritten: u64,
+    pub dropped: u64,
+    // moving average of the time consumer spends between two receives
+    pub consumer_latency: Duration,
+    pub terminated: bool,
 }
 
 #[derive(Clone)]
@@ -75,6 +92,7 @@ struct QueueContext {
     receiver: Arc<Receiver<String>>,
     enabled: Arc<AtomicBool>,
     already_used: Arc<AtomicBool>,
+    state: Arc<QueueState>,
 }
 
 pub struct QueueDebugger {
@@ -96,11 +114,13 @@ impl QueueDebugger {
         name: &'static str,
         queue: Receiver<String>,
         enabled: Arc<AtomicBool>,
+        state: Arc<QueueState>,
     ) {
         let ctx = QueueContext {
             receiver: Arc::new(queue),
             enabled,
             already_used: Arc::new(AtomicBool::new(false)),
+            state,
         };
         self.queues.lock().unwrap().insert(name, ctx);
     }
@@ -141,6 +161,39 @@ impl QueueDebugger {
         vec![QueueMessage::Names(Some(names)), QueueMessage::Fin]
     }
 
+    pub fn queue_stats(&self) -> Vec<QueueMessage> {
+        let mut stats = self
+            .queues
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&name, ctx)| {
+                let state = &ctx.state;
+                QueueStats {
+                    name: String::from(name),
+                    capacity: state.capacity as u64,
+                    pending: state.pending.load(Ordering::Relaxed) as u64,
+                    high_watermark: state.high_watermark.load(Ordering::Relaxed) as u64,
+                    overwritten: state.overwritten.load(Ordering::Relaxed),
+                    dropped: state.dropped.load(Ordering::Relaxed),
+                    consumer_latency: Duration::from_nanos(
+                        state.consumer_latency.load(Ordering::Relaxed),
+                    ),
+                    terminated: ctx.receiver.terminated(),
+                }
+            })
+            .collect::<Vec<_>>();
+        stats.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut msgs = vec![];
+        while !stats.is_empty() {
+            let rest = stats.split_off(stats.len().min(QUEUE_STATS_BATCH));
+            msgs.push(QueueMessage::Stats(Some(stats)));
+            stats = rest;
+        }
+        msgs.push(QueueMessage::Fin);
+        msgs
+    }
+
     pub fn send(
         &self,
         name: impl Into<String>,
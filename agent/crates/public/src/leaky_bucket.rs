@@ -92,13 +92,18 @@ impl LeakyBucket {
     }
 
     pub fn acquire(&self, size: u64) -> bool {
+        self.acquire_reserved(size, 0)
+    }
+
+    // succeeds only if at least `reserved` tokens are left for other acquirers
+    pub fn acquire_reserved(&self, size: u64, reserved: u64) -> bool {
         if self.rate.load(Ordering::Relaxed) == 0 {
             return true;
         }
 
         self.token
             .fetch_update(Ordering::Release, Ordering::Relaxed, |t| {
-                if t < size {
+                if t < size.saturating_add(reserved) {
                     None
                 } else {
                     Some(t - size)
@@ -157,6 +162,13 @@ mod tests {
             );
         }
 
+        let bucket = LeakyBucket::new(Some(1000));
+        thread::sleep(TICK_INTERVAL / 10);
+        assert!(!bucket.acquire_reserved(600, 500));
+        assert!(bucket.acquire_reserved(500, 500));
+        assert!(!bucket.acquire_reserved(1, 500));
+        assert!(bucket.acquire(500));
+
         let unlimited = LeakyBucket::new(None);
         for _ in 0..1000 {
             assert!(
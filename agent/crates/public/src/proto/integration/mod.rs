@@ -33,3 +33,13 @@ pub mod opentelemetry {
         }
     }
 }
+
+pub mod jaeger {
+    pub mod api_v2 {
+        include!("jaeger.api_v2.rs");
+    }
+}
+
+pub mod pprof {
+    include!("perftools.profiles.rs");
+}
@@ -24,7 +24,7 @@ use std::{
 
 use log::debug;
 
-use super::{bounded, Error, Receiver, Sender, StatsHandle};
+use super::{bounded, bounded_with_policy, DropPolicy, Error, Receiver, Sender, StatsHandle};
 
 use crate::debug::{QueueDebugger, QUEUE_LEN};
 
@@ -60,6 +60,10 @@ impl<T: Debug> DebugSender<T> {
         self.send_debug(&msgs);
         self.sender.send_all(msgs)
     }
+
+    pub fn above_watermark(&self, percent: u8) -> bool {
+        self.sender.above_watermark(percent)
+    }
 }
 
 impl<T> Clone for DebugSender<T> {
@@ -76,11 +80,20 @@ pub fn bounded_with_debug<T>(
     name: &'static str,
     debugger: &QueueDebugger,
 ) -> (DebugSender<T>, Receiver<T>, StatsHandle<T>) {
-    let (sender, receiver, handle) = bounded(size);
+    bounded_with_debug_and_policy(size, name, debugger, DropPolicy::DropOld)
+}
+
+pub fn bounded_with_debug_and_policy<T>(
+    size: usize,
+    name: &'static str,
+    debugger: &QueueDebugger,
+    policy: DropPolicy,
+) -> (DebugSender<T>, Receiver<T>, StatsHandle<T>) {
+    let (sender, receiver, handle) = bounded_with_policy(size, policy);
 
     let (debug_sender, debug_receiver, _) = bounded(QUEUE_LEN);
     let enabled = Arc::new(AtomicBool::new(false));
-    debugger.append_queue(name, debug_receiver, enabled.clone());
+    debugger.append_queue(name, debug_receiver, enabled.clone(), handle.state());
 
     let sender = DebugSender {
         debug: (debug_sender, enabled),
@@ -17,8 +17,10 @@
 mod debug;
 mod overwrite_queue;
 
-pub use debug::{bounded_with_debug, DebugSender};
-pub use overwrite_queue::{bounded, Counter, Receiver, Sender, StatsHandle};
+pub use debug::{bounded_with_debug, bounded_with_debug_and_policy, DebugSender};
+pub use overwrite_queue::{
+    bounded, bounded_with_policy, Counter, DropPolicy, QueueState, Receiver, Sender, StatsHandle,
+};
 use thiserror::Error;
 
 #[derive(Debug, Error, PartialEq)]
@@ -20,7 +20,7 @@ use std::marker::PhantomData;
 use std::mem::{self, MaybeUninit};
 use std::sync::{
     atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
-    Condvar, Mutex,
+    Arc, Condvar, Mutex,
 };
 use std::time::{Duration, Instant};
 
@@ -28,14 +28,90 @@ use super::Error;
 use crate::counter as stats;
 
 pub fn bounded<T>(size: usize) -> (Sender<T>, Receiver<T>, StatsHandle<T>) {
-    RefCounter::new(OverwriteQueue::with_capacity(size))
+    bounded_with_policy(size, DropPolicy::DropOld)
 }
 
-#[derive(Debug, Default)]
+pub fn bounded_with_policy<T>(
+    size: usize,
+    policy: DropPolicy,
+) -> (Sender<T>, Receiver<T>, StatsHandle<T>) {
+    RefCounter::new(OverwriteQueue::with_capacity(size, policy))
+}
+
+// Behavior of sending to a full queue
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DropPolicy {
+    // overwrite the oldest elements in the queue
+    DropOld,
+    // discard the elements being sent
+    DropNew,
+    // wait for free space, discard the elements being sent on timeout
+    Block(Duration),
+}
+
+impl Default for DropPolicy {
+    fn default() -> Self {
+        DropPolicy::DropOld
+    }
+}
+
+// 10µs to 1s in µs
+const CONSUMER_LATENCY_BUCKETS: [u64; 6] = [10, 100, 1000, 10000, 100000, 1000000];
+
+#[derive(Debug)]
 pub struct Counter {
     pub input: AtomicU64,
     pub output: AtomicU64,
     pub overwritten: AtomicU64,
+    pub dropped: AtomicU64,
+    // time consumer spends between two receives in µs, which is how long
+    // elements wait in the queue when it is not empty
+    pub consumer_latency: stats::Histogram,
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self {
+            input: Default::default(),
+            output: Default::default(),
+            overwritten: Default::default(),
+            dropped: Default::default(),
+            consumer_latency: stats::Histogram::new("consumer_latency", &CONSUMER_LATENCY_BUCKETS),
+        }
+    }
+}
+
+// Lifetime state of a queue shared with the queue debugger, never reset by stats collection
+#[derive(Debug, Default)]
+pub struct QueueState {
+    pub capacity: usize,
+    pub pending: AtomicUsize,
+    pub high_watermark: AtomicUsize,
+    pub overwritten: AtomicU64,
+    pub dropped: AtomicU64,
+    // moving average of the time consumer spends between two receives, in nanoseconds
+    pub consumer_latency: AtomicU64,
+}
+
+impl QueueState {
+    fn update_pending(&self, pending: usize) {
+        self.pending.store(pending, Ordering::Relaxed);
+        if pending > self.high_watermark.load(Ordering::Relaxed) {
+            self.high_watermark.store(pending, Ordering::Relaxed);
+        }
+    }
+
+    fn update_consumer_latency(&self, latency: u64) {
+        let last = self.consumer_latency.load(Ordering::Relaxed);
+        self.consumer_latency.store(
+            if last == 0 {
+                latency
+            } else {
+                last - last / 8 + latency / 8
+            },
+            Ordering::Relaxed,
+        );
+    }
 }
 
 // fixed size MPSC overwrite queue implemented with ring buffer
@@ -50,16 +126,24 @@ struct OverwriteQueue<T: Sized> {
     reader_lock: Mutex<()>,
     writer_lock: Mutex<()>,
     notify: Condvar,
+    // notified on receiving if the policy is DropPolicy::Block
+    not_full: Condvar,
 
+    policy: DropPolicy,
     terminated: AtomicBool,
 
     counter: Counter,
 
+    state: Arc<QueueState>,
+    created: Instant,
+    // nanoseconds since `created` when the consumer last returned from receiving, 0 if never
+    last_recv: AtomicU64,
+
     _marker: PhantomData<T>,
 }
 
 impl<T> OverwriteQueue<T> {
-    pub fn with_capacity(size: usize) -> Self {
+    pub fn with_capacity(size: usize, policy: DropPolicy) -> Self {
         let size = size.next_power_of_two();
         let buffer = {
             let mut v = Vec::with_capacity(size);
@@ -76,8 +160,16 @@ impl<T> OverwriteQueue<T> {
             reader_lock: Mutex::new(()),
             writer_lock: Mutex::new(()),
             notify: Condvar::new(),
+            not_full: Condvar::new(),
+            policy,
             terminated: AtomicBool::new(false),
             counter: Counter::default(),
+            state: Arc::new(QueueState {
+                capacity: size,
+                ..Default::default()
+            }),
+            created: Instant::now(),
+            last_recv: AtomicU64::new(0),
             _marker: PhantomData,
         }
     }
@@ -86,6 +178,15 @@ impl<T> OverwriteQueue<T> {
         self.terminated.load(Ordering::Relaxed)
     }
 
+    fn pending(&self) -> usize {
+        let start = self.start.load(Ordering::Relaxed);
+        let mut end = self.end.load(Ordering::Relaxed);
+        if end < start {
+            end += 2 * self.size;
+        }
+        end - start
+    }
+
     unsafe fn raw_send(&self, msgs: *const T, count: usize) -> Result<(), Error<T>> {
         if self.terminated.load(Ordering::Acquire) {
             return Err(Error::Terminated(None, None));
@@ -104,34 +205,81 @@ impl<T> OverwriteQueue<T> {
             raw_end
         };
         assert!(end - start <= self.size);
+        let mut accepted = count;
         // queue full
         if end - start + count > self.size {
-            let _lock = self.reader_lock.lock().unwrap();
+            let mut guard = self.reader_lock.lock().unwrap();
             // start could be modified by recv, check again
-            let start = self.start.load(Ordering::Acquire);
-            let end = if raw_end < start {
-                raw_end + 2 * self.size
-            } else {
-                raw_end
+            let mut start = self.start.load(Ordering::Acquire);
+            let used = |start: usize| {
+                let end = if raw_end < start {
+                    raw_end + 2 * self.size
+                } else {
+                    raw_end
+                };
+                assert!(end - start <= self.size);
+                end - start
             };
-            assert!(end - start <= self.size);
-            let free_space = self.size - (end - start);
+            if let DropPolicy::Block(timeout) = self.policy {
+                let now = Instant::now();
+                while self.size - used(start) < count {
+                    if self.terminated.load(Ordering::Acquire) {
+                        return Err(Error::Terminated(None, None));
+                    }
+                    let elapsed = now.elapsed();
+                    if elapsed >= timeout {
+                        break;
+                    }
+                    guard = self
+                        .not_full
+                        .wait_timeout(guard, timeout - elapsed)
+                        .unwrap()
+                        .0;
+                    start = self.start.load(Ordering::Acquire);
+                }
+            }
+            let free_space = self.size - used(start);
             if free_space < count {
-                let to_overwrite = count - free_space;
-                for i in 0..to_overwrite {
-                    self.buffer
-                        .add((start + i) & (self.size - 1))
-                        .drop_in_place();
+                match self.policy {
+                    DropPolicy::DropOld => {
+                        let to_overwrite = count - free_space;
+                        for i in 0..to_overwrite {
+                            self.buffer
+                                .add((start + i) & (self.size - 1))
+                                .drop_in_place();
+                        }
+                        self.start.store(
+                            (start + to_overwrite) & (2 * self.size - 1),
+                            Ordering::Release,
+                        );
+                        self.counter
+                            .overwritten
+                            .fetch_add(to_overwrite as u64, Ordering::Relaxed);
+                        self.state
+                            .overwritten
+                            .fetch_add(to_overwrite as u64, Ordering::Relaxed);
+                    }
+                    DropPolicy::DropNew | DropPolicy::Block(_) => {
+                        // msgs are owned by the queue once sent, drop the ones not fitting in
+                        accepted = free_space;
+                        for i in accepted..count {
+                            (msgs.add(i) as *mut T).drop_in_place();
+                        }
+                        self.counter
+                            .dropped
+                            .fetch_add((count - accepted) as u64, Ordering::Relaxed);
+                        self.state
+                            .dropped
+                            .fetch_add((count - accepted) as u64, Ordering::Relaxed);
+                    }
                 }
-                self.start.store(
-                    (start + to_overwrite) & (2 * self.size - 1),
-                    Ordering::Release,
-                );
-                self.counter
-                    .overwritten
-                    .fetch_add(to_overwrite as u64, Ordering::Relaxed);
             }
+            drop(guard);
         }
+        if accepted == 0 {
+            return Ok(());
+        }
+        let count = accepted;
         let free_after_end = self.size - (raw_end & (self.size - 1));
         if free_after_end >= count {
             self.buffer
@@ -149,6 +297,7 @@ impl<T> OverwriteQueue<T> {
         self.counter
             .input
             .fetch_add(count as u64, Ordering::Relaxed);
+        self.state.update_pending(self.pending());
         self.notify.notify_one();
         Ok(())
     }
@@ -158,6 +307,27 @@ impl<T> OverwriteQueue<T> {
         timeout: Option<Duration>,
         buffer: *mut T,
         buf_size: usize,
+    ) -> Result<usize, Error<T>> {
+        let last_recv = self.last_recv.load(Ordering::Relaxed);
+        if last_recv > 0 {
+            let now = self.created.elapsed().as_nanos() as u64;
+            let latency = now.saturating_sub(last_recv);
+            self.state.update_consumer_latency(latency);
+            self.counter.consumer_latency.observe(latency / 1000);
+        }
+        let result = self.raw_recv_timeout_inner(timeout, buffer, buf_size);
+        self.last_recv.store(
+            self.created.elapsed().as_nanos().max(1) as u64,
+            Ordering::Relaxed,
+        );
+        result
+    }
+
+    unsafe fn raw_recv_timeout_inner(
+        &self,
+        timeout: Option<Duration>,
+        buffer: *mut T,
+        buf_size: usize,
     ) -> Result<usize, Error<T>> {
         let mut guard = self.reader_lock.lock().unwrap();
         let mut start = self.start.load(Ordering::Acquire);
@@ -216,6 +386,10 @@ impl<T> OverwriteQueue<T> {
         self.counter
             .output
             .fetch_add(recv_count as u64, Ordering::Relaxed);
+        self.state.update_pending(pending - recv_count);
+        if let DropPolicy::Block(_) = self.policy {
+            self.not_full.notify_one();
+        }
         Ok(recv_count)
     }
 
@@ -223,6 +397,7 @@ impl<T> OverwriteQueue<T> {
         let _lock = self.reader_lock.lock().unwrap();
         self.terminated.swap(true, Ordering::Release);
         self.notify.notify_one();
+        self.not_full.notify_all();
     }
 }
 
@@ -289,6 +464,21 @@ impl<T> Sender<T> {
         self.counter().queue.terminated()
     }
 
+    // number of elements waiting to be received
+    pub fn pending(&self) -> usize {
+        self.counter().queue.pending()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.counter().queue.size
+    }
+
+    // Whether the queue is filled to `percent` of its capacity, used by producers
+    // to detect that the consumer falls behind
+    pub fn above_watermark(&self, percent: u8) -> bool {
+        self.pending() * 100 >= self.capacity() * percent as usize
+    }
+
     pub fn send(&self, msg: T) -> Result<(), Error<T>> {
         unsafe {
             match self.counter().queue.raw_send(&msg, 1) {
@@ -454,6 +644,10 @@ impl<T> StatsHandle<T> {
     fn counter(&self) -> &RefCounter<T> {
         unsafe { &*self.counter }
     }
+
+    pub fn state(&self) -> Arc<QueueState> {
+        self.counter().queue.state.clone()
+    }
 }
 
 impl<T> Drop for StatsHandle<T> {
@@ -475,12 +669,7 @@ impl<T> Drop for StatsHandle<T> {
 impl<T: Send> stats::OwnedCountable for StatsHandle<T> {
     fn get_counters(&self) -> Vec<stats::Counter> {
         let queue = &self.counter().queue;
-        let start = queue.start.load(Ordering::Relaxed);
-        let mut end = queue.end.load(Ordering::Relaxed);
-        if end < start {
-            end += 2 * queue.size;
-        }
-        vec![
+        let mut counters = vec![
             (
                 "in",
                 stats::CounterType::Counted,
@@ -496,12 +685,19 @@ impl<T: Send> stats::OwnedCountable for StatsHandle<T> {
                 stats::CounterType::Counted,
                 stats::CounterValue::Unsigned(queue.counter.overwritten.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "dropped",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(queue.counter.dropped.swap(0, Ordering::Relaxed)),
+            ),
             (
                 "pending",
                 stats::CounterType::Gauged,
-                stats::CounterValue::Unsigned((end - start) as u64),
+                stats::CounterValue::Unsigned(queue.pending() as u64),
             ),
-        ]
+        ];
+        counters.append(&mut queue.counter.consumer_latency.get_counters());
+        counters
     }
 
     fn closed(&self) -> bool {
@@ -688,6 +884,89 @@ mod tests {
         assert_eq!(c, 0, "new/drop count mismatch: new - drop = {}", c);
     }
 
+    #[test]
+    fn drop_new() {
+        let c = Arc::new(AtomicUsize::new(0));
+
+        {
+            let (s, r, _) = bounded_with_policy(2, DropPolicy::DropNew);
+
+            s.send(CountedU64::new(42, c.clone())).unwrap();
+            s.send_all(&mut vec![
+                CountedU64::new(43, c.clone()),
+                CountedU64::new(44, c.clone()),
+            ])
+            .unwrap();
+            assert!(s.above_watermark(100));
+
+            let co = r.recv_n(100, None).unwrap();
+            assert_eq!(co, vec![42, 43], "expected: [42, 43], result: {:?}", co);
+            assert_eq!(s.pending(), 0);
+        }
+
+        let c = c.load(Ordering::Acquire);
+        assert_eq!(c, 0, "new/drop count mismatch: new - drop = {}", c);
+    }
+
+    #[test]
+    fn block_until_received_or_timeout() {
+        let c = Arc::new(AtomicUsize::new(0));
+
+        {
+            let (s, r, _) = bounded_with_policy(2, DropPolicy::Block(Duration::from_millis(50)));
+
+            s.send_all(&mut vec![
+                CountedU64::new(42, c.clone()),
+                CountedU64::new(43, c.clone()),
+            ])
+            .unwrap();
+
+            // dropped on timeout
+            let now = Instant::now();
+            s.send(CountedU64::new(44, c.clone())).unwrap();
+            assert!(now.elapsed() >= Duration::from_millis(50));
+
+            let h = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                let co = r.recv(None).unwrap();
+                assert_eq!(co, 42, "expected: 42, result: {}", co);
+                r
+            });
+            // unblocked by the receiver
+            s.send(CountedU64::new(45, c.clone())).unwrap();
+            let r = h.join().unwrap();
+
+            let co = r.recv_n(100, None).unwrap();
+            assert_eq!(co, vec![43, 45], "expected: [43, 45], result: {:?}", co);
+        }
+
+        let c = c.load(Ordering::Acquire);
+        assert_eq!(c, 0, "new/drop count mismatch: new - drop = {}", c);
+    }
+
+    #[test]
+    fn queue_state() {
+        let (s, r, h) = bounded_with_policy(4, DropPolicy::DropNew);
+        let state = h.state();
+
+        s.send_all(&mut vec![1, 2, 3]).unwrap();
+        s.send_all(&mut vec![4, 5, 6]).unwrap();
+        assert_eq!(state.capacity, 4);
+        assert_eq!(state.pending.load(Ordering::Relaxed), 4);
+        assert_eq!(state.dropped.load(Ordering::Relaxed), 2);
+
+        assert_eq!(r.recv_n(3, None).unwrap(), vec![1, 2, 3]);
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(r.recv(None).unwrap(), 4);
+        assert_eq!(state.pending.load(Ordering::Relaxed), 0);
+        assert_eq!(state.high_watermark.load(Ordering::Relaxed), 4);
+        assert!(state.consumer_latency.load(Ordering::Relaxed) >= 10_000_000);
+
+        // stats collection does not reset lifetime state
+        let _ = stats::OwnedCountable::get_counters(&h);
+        assert_eq!(state.dropped.load(Ordering::Relaxed), 2);
+    }
+
     #[test]
     #[should_panic]
     fn recv_empty() {
@@ -35,6 +35,10 @@ pub trait Sendable: Debug + Send + 'static {
     }
     // Serialize data to key-value and append to a string
     fn to_kv_string(&self, _: &mut String) {}
+    // Tenant of the data used to pick its export destination, 0 if unknown
+    fn tenant_id(&self) -> u32 {
+        0
+    }
 }
 
 #[derive(Debug, Clone, Copy, IntoPrimitive)]
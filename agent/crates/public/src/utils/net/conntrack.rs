@@ -0,0 +1,342 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// ctnetlink is only available on linux, the tuple types are shared by all platforms
+#![cfg_attr(target_os = "windows", allow(dead_code))]
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::{mem::MaybeUninit, time::Duration};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use nix::libc::{AF_INET, AF_INET6, AF_NETLINK, NETLINK_NETFILTER};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use socket2::{Domain, Protocol, Socket, Type};
+
+use super::{Error, Result};
+
+use crate::bytes::read_u16_be;
+
+const NLMSG_HDRLEN: usize = 16;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const NLM_F_REQUEST: u16 = 1;
+const NLM_F_DUMP: u16 = 0x300;
+
+const NFGENMSG_LEN: usize = 4;
+const NFNL_SUBSYS_CTNETLINK: u16 = 1;
+const IPCTNL_MSG_CT_NEW: u16 = 0;
+const IPCTNL_MSG_CT_GET: u16 = 1;
+
+const NLA_HDRLEN: usize = 4;
+const NLA_TYPE_MASK: u16 = !(1 << 15 | 1 << 14);
+
+const CTA_TUPLE_ORIG: u16 = 1;
+const CTA_TUPLE_REPLY: u16 = 2;
+const CTA_TUPLE_IP: u16 = 1;
+const CTA_TUPLE_PROTO: u16 = 2;
+const CTA_IP_V4_SRC: u16 = 1;
+const CTA_IP_V4_DST: u16 = 2;
+const CTA_IP_V6_SRC: u16 = 3;
+const CTA_IP_V6_DST: u16 = 4;
+const CTA_PROTO_NUM: u16 = 1;
+const CTA_PROTO_SRC_PORT: u16 = 2;
+const CTA_PROTO_DST_PORT: u16 = 3;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const RECV_BUFFER_SIZE: usize = 64 << 10;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const RECV_TIMEOUT: Duration = Duration::from_secs(3);
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConntrackTuple {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub proto: u8,
+}
+
+impl ConntrackTuple {
+    pub fn reversed(&self) -> Self {
+        Self {
+            src: self.dst,
+            dst: self.src,
+            src_port: self.dst_port,
+            dst_port: self.src_port,
+            proto: self.proto,
+        }
+    }
+}
+
+// Connection tracked by kernel, the reply tuple is the reverse of the original
+// tuple unless the connection is NATed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConntrackEntry {
+    pub original: ConntrackTuple,
+    pub reply: ConntrackTuple,
+}
+
+impl ConntrackEntry {
+    pub fn is_nat(&self) -> bool {
+        self.original != self.reply.reversed()
+    }
+}
+
+// Iterates over netlink attributes as (type, payload)
+struct Attributes<'a>(&'a [u8]);
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.len() < NLA_HDRLEN {
+            return None;
+        }
+        let len = u16::from_ne_bytes([self.0[0], self.0[1]]) as usize;
+        let ty = u16::from_ne_bytes([self.0[2], self.0[3]]) & NLA_TYPE_MASK;
+        if len < NLA_HDRLEN || len > self.0.len() {
+            return None;
+        }
+        let payload = &self.0[NLA_HDRLEN..len];
+        self.0 = &self.0[align(len).min(self.0.len())..];
+        Some((ty, payload))
+    }
+}
+
+fn parse_ip(payload: &[u8]) -> Option<IpAddr> {
+    match payload.len() {
+        4 => Some(Ipv4Addr::from(<[u8; 4]>::try_from(payload).unwrap()).into()),
+        16 => Some(Ipv6Addr::from(<[u8; 16]>::try_from(payload).unwrap()).into()),
+        _ => None,
+    }
+}
+
+fn parse_tuple(payload: &[u8]) -> Option<ConntrackTuple> {
+    let (mut src, mut dst) = (None, None);
+    let mut tuple = ConntrackTuple {
+        src: Ipv4Addr::UNSPECIFIED.into(),
+        dst: Ipv4Addr::UNSPECIFIED.into(),
+        src_port: 0,
+        dst_port: 0,
+        proto: 0,
+    };
+    for (ty, payload) in Attributes(payload) {
+        match ty {
+            CTA_TUPLE_IP => {
+                for (ty, payload) in Attributes(payload) {
+                    match ty {
+                        CTA_IP_V4_SRC | CTA_IP_V6_SRC => src = parse_ip(payload),
+                        CTA_IP_V4_DST | CTA_IP_V6_DST => dst = parse_ip(payload),
+                        _ => (),
+                    }
+                }
+            }
+            CTA_TUPLE_PROTO => {
+                for (ty, payload) in Attributes(payload) {
+                    match ty {
+                        CTA_PROTO_NUM if payload.len() >= 1 => tuple.proto = payload[0],
+                        CTA_PROTO_SRC_PORT if payload.len() >= 2 => {
+                            tuple.src_port = read_u16_be(payload)
+                        }
+                        CTA_PROTO_DST_PORT if payload.len() >= 2 => {
+                            tuple.dst_port = read_u16_be(payload)
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    tuple.src = src?;
+    tuple.dst = dst?;
+    Some(tuple)
+}
+
+fn parse_entry(payload: &[u8]) -> Option<ConntrackEntry> {
+    let (mut original, mut reply) = (None, None);
+    for (ty, payload) in Attributes(payload.get(NFGENMSG_LEN..)?) {
+        match ty {
+            CTA_TUPLE_ORIG => original = parse_tuple(payload),
+            CTA_TUPLE_REPLY => reply = parse_tuple(payload),
+            _ => (),
+        }
+    }
+    Some(ConntrackEntry {
+        original: original?,
+        reply: reply?,
+    })
+}
+
+// Parses a batch of netlink messages, returns true if the dump is done
+fn parse_messages(mut buf: &[u8], entries: &mut Vec<ConntrackEntry>) -> Result<bool> {
+    while buf.len() >= NLMSG_HDRLEN {
+        let len = u32::from_ne_bytes(buf[..4].try_into().unwrap()) as usize;
+        let ty = u16::from_ne_bytes([buf[4], buf[5]]);
+        if len < NLMSG_HDRLEN || len > buf.len() {
+            return Err(Error::IoError(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid conntrack message length {}", len),
+            )));
+        }
+        let payload = &buf[NLMSG_HDRLEN..len];
+        match ty {
+            NLMSG_DONE => return Ok(true),
+            NLMSG_ERROR => {
+                let errno = payload
+                    .get(..4)
+                    .map(|e| i32::from_ne_bytes(e.try_into().unwrap()))
+                    .unwrap_or_default();
+                if errno != 0 {
+                    return Err(Error::IoError(io::Error::from_raw_os_error(-errno)));
+                }
+            }
+            _ if ty == NFNL_SUBSYS_CTNETLINK << 8 | IPCTNL_MSG_CT_NEW => {
+                if let Some(entry) = parse_entry(payload) {
+                    entries.push(entry);
+                }
+            }
+            _ => (),
+        }
+        buf = &buf[align(len).min(buf.len())..];
+    }
+    Ok(false)
+}
+
+fn dump_request(family: i32, seq: u32) -> Vec<u8> {
+    let len = NLMSG_HDRLEN + NFGENMSG_LEN;
+    let mut req = Vec::with_capacity(len);
+    req.extend_from_slice(&(len as u32).to_ne_bytes());
+    req.extend_from_slice(&(NFNL_SUBSYS_CTNETLINK << 8 | IPCTNL_MSG_CT_GET).to_ne_bytes());
+    req.extend_from_slice(&(NLM_F_REQUEST | NLM_F_DUMP).to_ne_bytes());
+    req.extend_from_slice(&seq.to_ne_bytes());
+    req.extend_from_slice(&0u32.to_ne_bytes());
+    // nfgenmsg with version NFNETLINK_V0
+    req.extend_from_slice(&[family as u8, 0, 0, 0]);
+    req
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn dump_family(socket: &Socket, family: i32, entries: &mut Vec<ConntrackEntry>) -> Result<()> {
+    socket.send(&dump_request(family, family as u32))?;
+    let mut buf = vec![MaybeUninit::<u8>::uninit(); RECV_BUFFER_SIZE];
+    loop {
+        let n = socket.recv(&mut buf)?;
+        // safe because the first n bytes are initialized by recv
+        let data = unsafe { std::slice::from_raw_parts(buf.as_ptr() as *const u8, n) };
+        if n == 0 || parse_messages(data, entries)? {
+            return Ok(());
+        }
+    }
+}
+
+// Dumps kernel conntrack table through ctnetlink, requires CAP_NET_ADMIN
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn conntrack_list() -> Result<Vec<ConntrackEntry>> {
+    let socket = Socket::new(
+        Domain::from(AF_NETLINK),
+        Type::RAW,
+        Some(Protocol::from(NETLINK_NETFILTER)),
+    )?;
+    socket.set_read_timeout(Some(RECV_TIMEOUT))?;
+    let mut entries = vec![];
+    for family in [AF_INET, AF_INET6] {
+        dump_family(&socket, family, &mut entries)?;
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nix::libc::AF_INET;
+
+    fn attr(ty: u16, payload: &[u8]) -> Vec<u8> {
+        let mut v = vec![];
+        v.extend_from_slice(&((NLA_HDRLEN + payload.len()) as u16).to_ne_bytes());
+        v.extend_from_slice(&ty.to_ne_bytes());
+        v.extend_from_slice(payload);
+        v.resize(align(v.len()), 0);
+        v
+    }
+
+    fn tuple(ty: u16, src: [u8; 4], dst: [u8; 4], sport: u16, dport: u16) -> Vec<u8> {
+        let ip = [attr(CTA_IP_V4_SRC, &src), attr(CTA_IP_V4_DST, &dst)].concat();
+        let proto = [
+            attr(CTA_PROTO_NUM, &[6]),
+            attr(CTA_PROTO_SRC_PORT, &sport.to_be_bytes()),
+            attr(CTA_PROTO_DST_PORT, &dport.to_be_bytes()),
+        ]
+        .concat();
+        // nested flag set by kernel
+        let nested = 1 << 15;
+        attr(
+            ty | nested,
+            &[
+                attr(CTA_TUPLE_IP | nested, &ip),
+                attr(CTA_TUPLE_PROTO | nested, &proto),
+            ]
+            .concat(),
+        )
+    }
+
+    fn message(ty: u16, payload: &[u8]) -> Vec<u8> {
+        let mut v = vec![];
+        v.extend_from_slice(&((NLMSG_HDRLEN + payload.len()) as u32).to_ne_bytes());
+        v.extend_from_slice(&ty.to_ne_bytes());
+        v.extend_from_slice(&[0; 10]);
+        v.extend_from_slice(payload);
+        v
+    }
+
+    #[test]
+    fn parse_dump() {
+        // client 10.0.0.1:40000 -> service 10.96.0.10:80 DNATed to 10.244.1.5:8080
+        let payload = [
+            vec![AF_INET as u8, 0, 0, 0],
+            tuple(CTA_TUPLE_ORIG, [10, 0, 0, 1], [10, 96, 0, 10], 40000, 80),
+            tuple(CTA_TUPLE_REPLY, [10, 244, 1, 5], [10, 0, 0, 1], 8080, 40000),
+        ]
+        .concat();
+        let buf = [
+            message(NFNL_SUBSYS_CTNETLINK << 8 | IPCTNL_MSG_CT_NEW, &payload),
+            message(NLMSG_DONE, &[0; 4]),
+        ]
+        .concat();
+        let mut entries = vec![];
+        assert!(parse_messages(&buf, &mut entries).unwrap());
+        assert_eq!(entries.len(), 1);
+        let entry = entries[0];
+        assert!(entry.is_nat());
+        assert_eq!(entry.original.dst, IpAddr::from([10, 96, 0, 10]));
+        assert_eq!(entry.original.src_port, 40000);
+        assert_eq!(entry.reply.src, IpAddr::from([10, 244, 1, 5]));
+        assert_eq!(entry.reply.src_port, 8080);
+        assert_eq!(entry.reply.proto, 6);
+
+        let error = message(NLMSG_ERROR, &(-1i32).to_ne_bytes());
+        assert!(parse_messages(&error, &mut entries).is_err());
+    }
+}
@@ -51,6 +51,8 @@ use super::{Addr, Link, LinkFlags, LinkStats, MacAddr, NeighborEntry, Route, Rul
 use crate::bytes::{read_u16_le, read_u32_le, read_u64_le};
 
 pub const IF_TYPE_IPVLAN: &'static str = "ipvlan";
+pub const IF_TYPE_BOND: &'static str = "bond";
+pub const IF_TYPE_VLAN: &'static str = "vlan";
 
 const NETLINK_ERROR_NOADDR: i32 = -19;
 
@@ -266,6 +268,7 @@ fn request_link_info(name: Option<&str>) -> Result<Vec<Link>> {
             let mut mac_addr = None;
             let mut if_type = None;
             let mut peer_index = None;
+            let mut master_index = None;
             let mut if_name = None;
             let mut link_netnsid = None;
             let mut link_stats = None;
@@ -301,6 +304,11 @@ fn request_link_info(name: Option<&str>) -> Result<Vec<Link>> {
                             peer_index = Some(read_u32_le(payload));
                         }
                     }
+                    Ifla::Master => {
+                        if let Some(payload) = attr.rta_payload.as_ref().get(..4) {
+                            master_index = Some(read_u32_le(payload));
+                        }
+                    }
                     Ifla::LinkNetnsid => {
                         if let Some(payload) = attr.rta_payload.as_ref().get(..4) {
                             link_netnsid = Some(read_u32_le(payload));
@@ -347,6 +355,7 @@ fn request_link_info(name: Option<&str>) -> Result<Vec<Link>> {
                     flags: (&payload.ifi_flags).into(),
                     if_type,
                     peer_index,
+                    master_index,
                     link_netnsid,
                     stats: link_stats.unwrap_or_default(),
                 });
@@ -427,6 +436,36 @@ pub fn links_by_name_regex<S: AsRef<str>>(regex: S) -> Result<Vec<Link>> {
         .collect())
 }
 
+// Removes links whose traffic is also seen on another link in the list, to avoid
+// double counting when capturing on both of them:
+// - bond slaves with the bond master in the list
+// - vlan sub-interfaces with the underlying link in the list
+// Returns the removed links.
+pub fn remove_stacked_links(links: &mut Vec<Link>) -> Vec<Link> {
+    let is_type = |link: &Link, t: &str| link.if_type.as_deref() == Some(t);
+    let bond_masters: Vec<u32> = links
+        .iter()
+        .filter(|l| is_type(l, IF_TYPE_BOND))
+        .map(|l| l.if_index)
+        .collect();
+    let indices: Vec<u32> = links.iter().map(|l| l.if_index).collect();
+    let mut removed = vec![];
+    links.retain(|l| {
+        let stacked = match (l.master_index, l.peer_index) {
+            (Some(master), _) if bond_masters.contains(&master) => true,
+            (_, Some(lower)) if is_type(l, IF_TYPE_VLAN) && l.link_netnsid.is_none() => {
+                indices.contains(&lower)
+            }
+            _ => false,
+        };
+        if stacked {
+            removed.push(l.clone());
+        }
+        !stacked
+    });
+    removed
+}
+
 pub fn link_list() -> Result<Vec<Link>> {
     request_link_info(None)
 }
@@ -742,6 +781,33 @@ mod tests {
         assert!(links_by_name_regex("***").is_err());
     }
 
+    #[test]
+    fn remove_stacked() {
+        let link = |if_index: u32, if_type: &str, peer_index, master_index| Link {
+            if_index,
+            if_type: Some(if_type.to_owned()),
+            peer_index,
+            master_index,
+            ..Default::default()
+        };
+        let mut links = vec![
+            link(2, "bond", None, None),
+            link(3, "", None, Some(2)),
+            link(4, "", None, Some(9)),
+            link(5, "vlan", Some(4), None),
+            link(6, "vlan", Some(8), None),
+        ];
+        let removed = remove_stacked_links(&mut links);
+        assert_eq!(
+            links.iter().map(|l| l.if_index).collect::<Vec<_>>(),
+            vec![2, 4, 6]
+        );
+        assert_eq!(
+            removed.iter().map(|l| l.if_index).collect::<Vec<_>>(),
+            vec![3, 5]
+        );
+    }
+
     #[test]
     fn get_nonexist_link() {
         match link_by_name("nonexist42") {
@@ -28,6 +28,11 @@ use serde::Serialize;
 #[cfg(any(target_os = "linux", target_os = "android"))]
 mod arp;
 
+mod conntrack;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use conntrack::conntrack_list;
+pub use conntrack::{ConntrackEntry, ConntrackTuple};
+
 mod error;
 pub use error::{Error, Result};
 
@@ -109,6 +114,9 @@ pub struct Link {
     #[cfg(any(target_os = "linux", target_os = "android"))]
     pub if_type: Option<String>,
     pub peer_index: Option<u32>,
+    // bond master or bridge of the link
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub master_index: Option<u32>,
     pub link_netnsid: Option<u32>,
     pub stats: LinkStats,
 }
@@ -14,14 +14,130 @@
  * limitations under the License.
  */
 
+use std::fs;
 use std::io;
 use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use std::time::SystemTime;
 
-use tonic::transport::{Channel, Endpoint};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, ServerName,
+};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tonic::transport::{Channel, Endpoint, Uri};
+use tower::service_fn;
 
 use public::consts::{GRPC_DEFAULT_TIMEOUT, GRPC_SESSION_TIMEOUT};
 
-pub async fn dial(remote: &str, remote_port: u16, _: String) -> Result<Channel, String> {
+// Called with the DER encoded certificate presented by controller after it is verified
+// against trusted CAs, the connection is rejected on error
+pub type CertVerifier = Arc<dyn Fn(&[u8]) -> Result<(), String> + Send + Sync>;
+
+static SUPPORTED_SIG_ALGS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::ED25519,
+    &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
+// Trusts the certificates in file `prefix.controller-ip`, and runs the cert verifier
+// on the end-entity certificate after the chain is verified
+struct ControllerCertVerifier {
+    roots: Vec<Vec<u8>>,
+    cert_verifier: Option<CertVerifier>,
+}
+
+impl ServerCertVerifier for ControllerCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        _: &mut dyn Iterator<Item = &[u8]>,
+        _: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let invalid = |e: webpki::Error| rustls::Error::InvalidCertificateData(e.to_string());
+        let cert = webpki::EndEntityCert::try_from(end_entity.0.as_slice()).map_err(invalid)?;
+        let anchors = self
+            .roots
+            .iter()
+            .map(|r| webpki::TrustAnchor::try_from_cert_der(r))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        let chain = intermediates
+            .iter()
+            .map(|c| c.0.as_slice())
+            .collect::<Vec<_>>();
+        let now = webpki::Time::try_from(now).map_err(|_| rustls::Error::FailedToGetCurrentTime)?;
+        cert.verify_is_valid_tls_server_cert(
+            SUPPORTED_SIG_ALGS,
+            &webpki::TlsServerTrustAnchors(&anchors),
+            &chain,
+            now,
+        )
+        .map_err(invalid)?;
+        // trusted certificates are already specific to the controller ip
+        if let ServerName::DnsName(name) = server_name {
+            let name = webpki::DnsNameRef::try_from_ascii_str(name.as_ref())
+                .map_err(|e| rustls::Error::General(e.to_string()))?;
+            cert.verify_is_valid_for_dns_name(name).map_err(invalid)?;
+        }
+        if let Some(verifier) = self.cert_verifier.as_ref() {
+            verifier(&end_entity.0).map_err(rustls::Error::General)?;
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+fn tls_connector(
+    cert_file: &str,
+    cert_verifier: Option<CertVerifier>,
+) -> Result<TlsConnector, String> {
+    let content = fs::read(cert_file)
+        .map_err(|e| format!("read controller cert {} failed: {}", cert_file, e))?;
+    let mut roots = rustls_pemfile::certs(&mut content.as_slice())
+        .map_err(|e| format!("parse controller cert {} failed: {}", cert_file, e))?;
+    if roots.is_empty() {
+        // DER encoded
+        roots.push(content);
+    }
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(ControllerCertVerifier {
+            roots,
+            cert_verifier,
+        }))
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+// Connects with TLS if `controller_cert_file_prefix` is not empty, see `ControllerCertVerifier`
+pub async fn dial(
+    remote: &str,
+    remote_port: u16,
+    controller_cert_file_prefix: String,
+    cert_verifier: Option<CertVerifier>,
+) -> Result<Channel, String> {
+    // fail closed instead of connecting in plain text without checking the pinned certificates
+    if controller_cert_file_prefix.is_empty() && cert_verifier.is_some() {
+        return Err(format!(
+            "Dial server({} {}) failed: controller certificate pinning requires TLS",
+            remote, remote_port
+        ));
+    }
+
     let socket_address = match (remote, remote_port)
         .to_socket_addrs()
         .and_then(|mut iter| {
@@ -37,22 +153,44 @@ pub async fn dial(remote: &str, remote_port: u16, _: String) -> Result<Channel,
         }
     };
 
-    let endpoint = match Endpoint::from_shared(format!("http://{}", socket_address)) {
+    let scheme = if controller_cert_file_prefix.is_empty() {
+        "http"
+    } else {
+        "https"
+    };
+    let endpoint = match Endpoint::from_shared(format!("{}://{}", scheme, socket_address)) {
         Ok(ep) => ep,
         Err(e) => {
             return Err(format!(
-                "create endpoint http://{} failed {}",
-                socket_address, e
+                "create endpoint {}://{} failed {}",
+                scheme, socket_address, e
             ));
         }
     };
-
-    match endpoint
+    let endpoint = endpoint
         .connect_timeout(GRPC_DEFAULT_TIMEOUT)
-        .timeout(GRPC_SESSION_TIMEOUT)
-        .connect()
-        .await
-    {
+        .timeout(GRPC_SESSION_TIMEOUT);
+
+    let result = if controller_cert_file_prefix.is_empty() {
+        endpoint.connect().await
+    } else {
+        let connector = tls_connector(
+            &format!("{}.{}", controller_cert_file_prefix, remote),
+            cert_verifier,
+        )?;
+        let server_name = ServerName::try_from(remote)
+            .map_err(|e| format!("invalid server name {}: {}", remote, e))?;
+        endpoint
+            .connect_with_connector(service_fn(move |_: Uri| {
+                let (connector, server_name) = (connector.clone(), server_name.clone());
+                async move {
+                    let stream = TcpStream::connect(socket_address).await?;
+                    connector.connect(server_name, stream).await
+                }
+            }))
+            .await
+    };
+    match result {
         Ok(channel) => return Ok(channel),
         Err(e) => {
             return Err(format!(
@@ -29,11 +29,11 @@ use clap::{ArgEnum, Parser, Subcommand};
 use flate2::write::ZlibDecoder;
 
 use deepflow_agent::debug::{
-    Beacon, Client, Message, Module, PolicyMessage, RpcMessage, DEBUG_QUEUE_IDLE_TIMEOUT,
-    DEEPFLOW_AGENT_BEACON,
+    Beacon, Client, LogMessage, Message, Module, PolicyMessage, RecentFilter, RecentMessage,
+    RpcMessage, DEBUG_QUEUE_IDLE_TIMEOUT, DEEPFLOW_AGENT_BEACON,
 };
 #[cfg(target_os = "linux")]
-use deepflow_agent::debug::{EbpfMessage, PlatformMessage};
+use deepflow_agent::debug::{EbpfMessage, PlatformMessage, ProfileMessage};
 use public::{consts::DEFAULT_CONTROLLER_PORT, debug::QueueMessage};
 
 const ERR_PORT_MSG: &str = "error: The following required arguments were not provided:
@@ -68,6 +68,13 @@ enum ControllerCmd {
     #[cfg(target_os = "linux")]
     /// get information about the ebpf
     Ebpf(EbpfCmd),
+    /// get recently closed flows and l7 logs
+    Recent(RecentCmd),
+    /// get or change log levels of modules
+    LogLevel(LogLevelCmd),
+    #[cfg(target_os = "linux")]
+    /// profile cpu or heap of the deepflow-agent itself
+    Profile(ProfileCmd),
     /// get information about the deepflow-agent
     List,
 }
@@ -101,6 +108,11 @@ struct QueueCmd {
     /// eg: deepflow-agent-ctl queue --clear
     #[clap(long)]
     clear: bool,
+    /// dump depth, watermark, drop counters and consumer latency of all queues
+    ///
+    /// eg: deepflow-agent-ctl queue --stats
+    #[clap(long)]
+    stats: bool,
 }
 
 #[cfg(target_os = "linux")]
@@ -140,6 +152,123 @@ struct AnalyzingArgs {
     id: Option<u32>,
 }
 
+#[derive(Debug, Parser)]
+struct RecentCmd {
+    #[clap(subcommand)]
+    subcmd: RecentSubCmd,
+}
+
+#[derive(Subcommand, Debug)]
+enum RecentSubCmd {
+    /// show recently closed flows
+    Flows(RecentArgs),
+    /// show recent l7 logs
+    L7Logs(RecentArgs),
+}
+
+#[derive(Debug, Parser)]
+struct RecentArgs {
+    /// Filter by ip of either side
+    ///
+    /// eg: deepflow-agent-ctl recent flows --ip 10.1.1.1
+    #[clap(long)]
+    ip: Option<String>,
+    /// Filter by port of either side
+    ///
+    /// eg: deepflow-agent-ctl recent l7-logs --port 80
+    #[clap(long, parse(try_from_str))]
+    port: Option<u16>,
+    /// Filter by protocol, tcp, udp, icmp or l7 protocol name
+    ///
+    /// eg: deepflow-agent-ctl recent l7-logs --protocol mysql
+    #[clap(long)]
+    protocol: Option<String>,
+    /// Filter by pod id
+    ///
+    /// eg: deepflow-agent-ctl recent flows --pod-id 10
+    #[clap(long, parse(try_from_str))]
+    pod_id: Option<u32>,
+    /// Show the latest N records, 0 means all
+    #[clap(long, parse(try_from_str), default_value_t = 20)]
+    limit: u32,
+}
+
+#[derive(Debug, Parser)]
+struct LogLevelCmd {
+    #[clap(subcommand)]
+    subcmd: LogLevelSubCmd,
+}
+
+#[derive(Subcommand, Debug)]
+enum LogLevelSubCmd {
+    /// show log levels of modules
+    Show,
+    /// set log level of a module
+    ///
+    /// eg: deepflow-agent-ctl log-level set --module rpc --level debug --duration 600
+    Set(LogLevelSetArgs),
+    /// reset log level of a module to the global level, all modules if not specified
+    ///
+    /// eg: deepflow-agent-ctl log-level reset --module rpc
+    Reset(LogLevelResetArgs),
+}
+
+#[derive(Debug, Parser)]
+struct LogLevelSetArgs {
+    /// dispatcher, ebpf, flow_generator or rpc
+    #[clap(long)]
+    module: String,
+    /// error, warn, info, debug or trace
+    #[clap(long)]
+    level: String,
+    /// revert to the global level after duration in seconds, 0 means never
+    #[clap(long, parse(try_from_str), default_value_t = 0)]
+    duration: u64,
+}
+
+#[derive(Debug, Parser)]
+struct LogLevelResetArgs {
+    #[clap(long)]
+    module: Option<String>,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, Parser)]
+struct ProfileCmd {
+    #[clap(subcommand)]
+    subcmd: ProfileSubCmd,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Subcommand, Debug)]
+enum ProfileSubCmd {
+    /// sample cpu of the deepflow-agent
+    ///
+    /// eg: deepflow-agent-ctl -p 54911 profile cpu --duration 30 --output cpu.svg
+    Cpu(ProfileArgs),
+    /// sample heap allocations of the deepflow-agent
+    ///
+    /// eg: deepflow-agent-ctl -p 54911 profile heap --format pprof --output heap.pb
+    Heap(ProfileArgs),
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, Parser)]
+struct ProfileArgs {
+    /// Profile duration in seconds, at most 300
+    #[clap(long, parse(try_from_str), default_value_t = 30)]
+    duration: u64,
+    /// Cpu sampling frequency in Hz
+    #[clap(long, parse(try_from_str), default_value_t = 99)]
+    frequency: i32,
+    /// Output format, svg (flamegraph) or pprof
+    #[clap(long, default_value = "svg")]
+    format: String,
+    /// Output file
+    #[clap(short, long)]
+    output: String,
+}
+
 #[cfg(target_os = "linux")]
 #[derive(Debug, Parser)]
 struct EbpfCmd {
@@ -272,6 +401,18 @@ enum RpcData {
     Version,
 }
 
+impl From<RecentArgs> for RecentFilter {
+    fn from(args: RecentArgs) -> Self {
+        Self {
+            ip: args.ip,
+            port: args.port,
+            protocol: args.protocol,
+            pod_id: args.pod_id,
+            limit: args.limit,
+        }
+    }
+}
+
 struct Controller {
     cmd: Option<Cmd>,
     addr: IpAddr,
@@ -298,6 +439,10 @@ impl Controller {
             ControllerCmd::Policy(c) => self.policy(c),
             #[cfg(target_os = "linux")]
             ControllerCmd::Ebpf(c) => self.ebpf(c),
+            ControllerCmd::Recent(c) => self.recent(c),
+            ControllerCmd::LogLevel(c) => self.log_level(c),
+            #[cfg(target_os = "linux")]
+            ControllerCmd::Profile(c) => self.profile(c),
         }
     }
 
@@ -478,6 +623,62 @@ impl Controller {
             }
         }
 
+        if c.stats {
+            let msg = Message {
+                module: Module::Queue,
+                msg: QueueMessage::Stats(None),
+            };
+            client.send_to(msg)?;
+
+            println!(
+                "{:<45} {:>10} {:>10} {:>10} {:>12} {:>12} {:>12}",
+                "NAME", "CAPACITY", "PENDING", "WATERMARK", "OVERWRITTEN", "DROPPED", "LATENCY"
+            );
+            let mut slowest: Option<(String, Duration)> = None;
+            loop {
+                let Ok(res) = client.recv::<QueueMessage>() else {
+                    continue;
+                };
+                match res {
+                    QueueMessage::Stats(Some(stats)) => {
+                        for s in stats {
+                            println!(
+                                "{:<45} {:>10} {:>10} {:>10} {:>12} {:>12} {:>12}",
+                                if s.terminated {
+                                    format!("{} (terminated)", s.name)
+                                } else {
+                                    s.name.clone()
+                                },
+                                s.capacity,
+                                s.pending,
+                                s.high_watermark,
+                                s.overwritten,
+                                s.dropped,
+                                format!("{:?}", s.consumer_latency),
+                            );
+                            if !s.terminated
+                                && slowest
+                                    .as_ref()
+                                    .map(|(_, l)| s.consumer_latency > *l)
+                                    .unwrap_or(true)
+                            {
+                                slowest = Some((s.name, s.consumer_latency));
+                            }
+                        }
+                    }
+                    QueueMessage::Stats(None) => return Err(anyhow!("cannot get queue stats")),
+                    QueueMessage::Fin => {
+                        if let Some((name, latency)) = slowest {
+                            println!("\nslowest consumer: {} ({:?})", name, latency);
+                        }
+                        return Ok(());
+                    }
+                    QueueMessage::Err(e) => return Err(anyhow!(e)),
+                    _ => unreachable!(),
+                }
+            }
+        }
+
         if c.clear {
             let msg = Message {
                 module: Module::Queue,
@@ -742,6 +943,123 @@ impl Controller {
         }
     }
 
+    fn recent(&self, c: RecentCmd) -> Result<()> {
+        if self.port.is_none() {
+            return Err(anyhow!(ERR_PORT_MSG));
+        }
+
+        let mut client = self.new_client()?;
+        let msg = match c.subcmd {
+            RecentSubCmd::Flows(args) => RecentMessage::Flows(args.into()),
+            RecentSubCmd::L7Logs(args) => RecentMessage::L7Logs(args.into()),
+        };
+        client.send_to(Message {
+            module: Module::Recent,
+            msg,
+        })?;
+
+        loop {
+            let Ok(res) = client.recv::<RecentMessage>() else {
+                continue;
+            };
+            match res {
+                RecentMessage::Context(c) => println!("{}", c),
+                RecentMessage::Done => return Ok(()),
+                RecentMessage::Err(e) => {
+                    println!("{}", e);
+                    return Ok(());
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn log_level(&self, c: LogLevelCmd) -> Result<()> {
+        if self.port.is_none() {
+            return Err(anyhow!(ERR_PORT_MSG));
+        }
+
+        let mut client = self.new_client()?;
+        let msg = match c.subcmd {
+            LogLevelSubCmd::Show => LogMessage::Show,
+            LogLevelSubCmd::Set(args) => LogMessage::Set(args.module, args.level, args.duration),
+            LogLevelSubCmd::Reset(args) => LogMessage::Reset(args.module),
+        };
+        client.send_to(Message {
+            module: Module::Log,
+            msg,
+        })?;
+
+        loop {
+            let Ok(res) = client.recv::<LogMessage>() else {
+                continue;
+            };
+            match res {
+                LogMessage::Context(c) => println!("{}", c),
+                LogMessage::Done => return Ok(()),
+                LogMessage::Err(e) => {
+                    println!("{}", e);
+                    return Ok(());
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn profile(&self, c: ProfileCmd) -> Result<()> {
+        if self.port.is_none() {
+            return Err(anyhow!(ERR_PORT_MSG));
+        }
+
+        let (profile_type, args) = match c.subcmd {
+            ProfileSubCmd::Cpu(args) => ("cpu", args),
+            ProfileSubCmd::Heap(args) => ("heap", args),
+        };
+        let mut client = self.new_client()?;
+        client.send_to(Message {
+            module: Module::Profile,
+            msg: ProfileMessage::Start(
+                profile_type.to_owned(),
+                args.duration,
+                args.frequency,
+                args.format,
+            ),
+        })?;
+        println!("profiling {} for {}s", profile_type, args.duration);
+
+        let mut decoder = ZlibDecoder::new(vec![]);
+        let mut next_seq = 0;
+        loop {
+            let Ok(res) = client.recv::<ProfileMessage>() else {
+                continue;
+            };
+            match res {
+                ProfileMessage::Chunk((seq, chunk)) => {
+                    if seq != next_seq {
+                        return Err(anyhow!("profile chunk {} lost, please retry", next_seq));
+                    }
+                    decoder.write_all(&chunk)?;
+                    next_seq += 1;
+                }
+                ProfileMessage::Done(chunks) => {
+                    if chunks != next_seq {
+                        return Err(anyhow!("profile chunk {} lost, please retry", next_seq));
+                    }
+                    let data = decoder.finish()?;
+                    std::fs::write(&args.output, &data)?;
+                    println!("{} bytes written to {}", data.len(), args.output);
+                    return Ok(());
+                }
+                ProfileMessage::Err(e) => {
+                    println!("{}", e);
+                    return Ok(());
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
     #[cfg(target_os = "linux")]
     fn ebpf(&self, c: EbpfCmd) -> Result<()> {
         if self.port.is_none() {
@@ -41,7 +41,10 @@ use crate::{
         enums::{EthernetType, IpProtocol},
         flow::{CloseType, L7Protocol, SignalSource},
     },
-    config::handler::{CollectorAccess, CollectorConfig},
+    config::{
+        handler::{CollectorAccess, CollectorConfig},
+        PreAggregationDimension,
+    },
     metric::{
         document::{BoxedDocument, Code, Direction, Document, DocumentFlag, Tagger, TapSide},
         meter::{AppMeter, FlowMeter, Meter, UsageMeter},
@@ -495,6 +498,8 @@ impl Stash {
         directions: &[Direction; 2],
         config: &CollectorConfig,
     ) {
+        let dimensions = config
+            .pre_aggregation_dimensions(acc_flow.flow.peers[FLOW_METRICS_PEER_DST].nat_real_port);
         for ep in 0..2 {
             // Do not count the data of None direction
             if directions[ep] == Direction::None {
@@ -512,7 +517,7 @@ impl Stash {
                 } else {
                     acc_flow.flow_meter
                 };
-                let tagger = get_single_tagger(
+                let mut tagger = get_single_tagger(
                     self.global_thread_id,
                     &acc_flow.flow,
                     ep,
@@ -524,9 +529,10 @@ impl Stash {
                     acc_flow.l7_protocol,
                     self.context.agent_mode,
                 );
+                pre_aggregate(&mut tagger, &mut 0, dimensions, Some(ep));
                 self.fill_single_l4_stats(tagger, flow_meter, acc_flow.flow.close_type);
             }
-            let tagger = get_edge_tagger(
+            let mut tagger = get_edge_tagger(
                 self.global_thread_id,
                 &acc_flow.flow,
                 directions[ep],
@@ -538,6 +544,7 @@ impl Stash {
                 acc_flow.l7_protocol,
                 self.context.agent_mode,
             );
+            pre_aggregate(&mut tagger, &mut 0, dimensions, None);
             // edge_stats: If the direction of a certain end is known, the statistical data
             // will be recorded with the direction (corresponding tap-side), up to two times
             self.fill_edge_l4_stats(tagger, acc_flow.flow_meter, acc_flow.flow.close_type);
@@ -551,7 +558,7 @@ impl Stash {
             } else {
                 Direction::None
             };
-            let tagger = get_edge_tagger(
+            let mut tagger = get_edge_tagger(
                 self.global_thread_id,
                 &acc_flow.flow,
                 direction,
@@ -563,6 +570,7 @@ impl Stash {
                 acc_flow.l7_protocol,
                 self.context.agent_mode,
             );
+            pre_aggregate(&mut tagger, &mut 0, dimensions, None);
             self.fill_edge_l4_stats(tagger, acc_flow.flow_meter, acc_flow.flow.close_type);
         }
     }
@@ -686,6 +694,8 @@ impl Stash {
         config: &CollectorConfig,
     ) {
         let flow = &meter.flow;
+        let dimensions =
+            config.pre_aggregation_dimensions(flow.peers[FLOW_METRICS_PEER_DST].nat_real_port);
         for ep in 0..2 {
             // Do not count the data of None direction
             if directions[ep] == Direction::None {
@@ -711,12 +721,9 @@ impl Stash {
                     self.context.agent_mode,
                 );
                 tagger.code |= Code::L7_PROTOCOL;
-                self.fill_single_l7_stats(
-                    tagger,
-                    meter.endpoint_hash,
-                    meter.app_meter,
-                    flow.close_type,
-                );
+                let mut endpoint_hash = meter.endpoint_hash;
+                pre_aggregate(&mut tagger, &mut endpoint_hash, dimensions, Some(ep));
+                self.fill_single_l7_stats(tagger, endpoint_hash, meter.app_meter, flow.close_type);
             }
             let mut tagger = get_edge_tagger(
                 self.global_thread_id,
@@ -731,14 +738,11 @@ impl Stash {
                 self.context.agent_mode,
             );
             tagger.code |= Code::L7_PROTOCOL;
+            let mut endpoint_hash = meter.endpoint_hash;
+            pre_aggregate(&mut tagger, &mut endpoint_hash, dimensions, None);
             // edge_stats: If the direction of a certain end is known, the statistical data
             // will be recorded with the direction (corresponding tap-side), up to two times
-            self.fill_edge_l7_stats(
-                tagger,
-                meter.endpoint_hash,
-                meter.app_meter,
-                flow.close_type,
-            );
+            self.fill_edge_l7_stats(tagger, endpoint_hash, meter.app_meter, flow.close_type);
         }
         // edge_stats: If both ends of direction are None, record the
         // statistical data with direction=0 (corresponding tap-side=rest)
@@ -762,12 +766,9 @@ impl Stash {
                 self.context.agent_mode,
             );
             tagger.code |= Code::L7_PROTOCOL;
-            self.fill_edge_l7_stats(
-                tagger,
-                meter.endpoint_hash,
-                meter.app_meter,
-                flow.close_type,
-            );
+            let mut endpoint_hash = meter.endpoint_hash;
+            pre_aggregate(&mut tagger, &mut endpoint_hash, dimensions, None);
+            self.fill_edge_l7_stats(tagger, endpoint_hash, meter.app_meter, flow.close_type);
         }
     }
 
@@ -1121,6 +1122,45 @@ fn get_edge_tagger(
     }
 }
 
+// Aggregate away the configured dimensions to reduce the cardinality of metrics,
+// `single_ep` is the side of single point taggers, None for edge taggers
+fn pre_aggregate(
+    tagger: &mut Tagger,
+    endpoint_hash: &mut u32,
+    dimensions: &[PreAggregationDimension],
+    single_ep: Option<usize>,
+) {
+    for d in dimensions {
+        match d {
+            PreAggregationDimension::ClientIp => {
+                if single_ep != Some(FLOW_METRICS_PEER_DST) {
+                    tagger.ip = unspecified_ip(tagger.is_ipv6);
+                }
+            }
+            PreAggregationDimension::ClientGpid => {
+                if single_ep != Some(FLOW_METRICS_PEER_DST) {
+                    tagger.gpid = 0;
+                }
+            }
+            PreAggregationDimension::ServerGpid => match single_ep {
+                Some(FLOW_METRICS_PEER_DST) => tagger.gpid = 0,
+                Some(_) => (),
+                None => tagger.gpid_1 = 0,
+            },
+            PreAggregationDimension::ServerPort => tagger.server_port = 0,
+            PreAggregationDimension::Mac => {
+                tagger.mac = MacAddr::ZERO;
+                tagger.mac1 = MacAddr::ZERO;
+                tagger.code.remove(Code::MAC | Code::MAC_PATH);
+            }
+            PreAggregationDimension::Endpoint => {
+                tagger.endpoint = None;
+                *endpoint_hash = 0;
+            }
+        }
+    }
+}
+
 fn get_l3_epc_id(l3_epc_id: i32, signal_source: SignalSource) -> i16 {
     if l3_epc_id < 0 && signal_source == SignalSource::OTel {
         0 // OTel data l3_epc_id always not from internet
@@ -1554,4 +1594,61 @@ mod tests {
         let key = StashKey::new(&tagger, Ipv4Addr::UNSPECIFIED.into(), None, 0);
         assert_eq!(map.insert(key), true);
     }
+    #[test]
+    fn pre_aggregation() {
+        let mut tagger = Tagger {
+            ip: "10.1.1.1".parse().unwrap(),
+            ip1: "10.1.1.2".parse().unwrap(),
+            gpid: 1,
+            gpid_1: 2,
+            mac: MacAddr::try_from(0x1u64).unwrap(),
+            mac1: MacAddr::try_from(0x2u64).unwrap(),
+            server_port: 80,
+            endpoint: Some("/api".to_owned()),
+            code: StashKey::EDGE_MAC_IP_PORT_APP,
+            ..Default::default()
+        };
+        let mut endpoint_hash = 42;
+        pre_aggregate(
+            &mut tagger,
+            &mut endpoint_hash,
+            &[
+                PreAggregationDimension::ClientIp,
+                PreAggregationDimension::ClientGpid,
+                PreAggregationDimension::Mac,
+                PreAggregationDimension::Endpoint,
+            ],
+            None,
+        );
+        assert_eq!(tagger.ip, IpAddr::from(Ipv4Addr::UNSPECIFIED));
+        assert_eq!(tagger.ip1, "10.1.1.2".parse::<IpAddr>().unwrap());
+        assert_eq!((tagger.gpid, tagger.gpid_1), (0, 2));
+        assert_eq!(tagger.mac1, MacAddr::ZERO);
+        assert_eq!(tagger.code, StashKey::EDGE_IP_PORT_APP);
+        assert_eq!(tagger.endpoint, None);
+        assert_eq!(endpoint_hash, 0);
+        assert_eq!(tagger.server_port, 80);
+
+        // single point taggers of the server side keep the server ip
+        let mut tagger = Tagger {
+            ip: "10.1.1.2".parse().unwrap(),
+            gpid: 2,
+            server_port: 80,
+            code: StashKey::SINGLE_IP_PORT,
+            ..Default::default()
+        };
+        pre_aggregate(
+            &mut tagger,
+            &mut 0,
+            &[
+                PreAggregationDimension::ClientIp,
+                PreAggregationDimension::ServerGpid,
+                PreAggregationDimension::ServerPort,
+            ],
+            Some(FLOW_METRICS_PEER_DST),
+        );
+        assert_eq!(tagger.ip, "10.1.1.2".parse::<IpAddr>().unwrap());
+        assert_eq!(tagger.gpid, 0);
+        assert_eq!(tagger.server_port, 0);
+    }
 }
@@ -38,6 +38,7 @@ use crate::common::{
     tagged_flow::{BoxedTaggedFlow, TaggedFlow},
 };
 use crate::config::handler::CollectorAccess;
+use crate::debug::record_flow;
 use crate::rpc::get_timestamp;
 use crate::utils::stats::{Counter, CounterType, CounterValue, RefCountable};
 use public::{
@@ -54,6 +55,7 @@ pub struct FlowAggrCounter {
     drop_before_window: AtomicU64,
     out: AtomicU64,
     drop_in_throttle: AtomicU64,
+    drop_in_backpressure: AtomicU64,
     stash_total_len: AtomicU64,
     stash_total_capacity: AtomicU64,
     stash_shrinks: AtomicU64,
@@ -272,12 +274,24 @@ impl FlowAggr {
         }
 
         self.metrics.out.fetch_add(1, Ordering::Relaxed);
+        if f.flow.close_type != CloseType::ForcedReport {
+            record_flow(&f.flow);
+        }
 
         let now = get_timestamp(self.ntp_diff.load(Ordering::Relaxed));
         self.output.flush_cache_with_throttling(&now);
         self.output.flush_cache_without_throttling(&now);
         if f.flow.hit_pcap_policy() {
             self.output.send_without_throttling(f);
+        } else if self.output.congested() {
+            // Sender can not keep up, only errors and slow requests are kept
+            if self.is_valuable(&f) {
+                self.output.send_without_throttling(f);
+            } else {
+                self.metrics
+                    .drop_in_backpressure
+                    .fetch_add(1, Ordering::Relaxed);
+            }
         } else {
             if !self.output.send_with_throttling(f) {
                 self.metrics
@@ -287,6 +301,19 @@ impl FlowAggr {
         }
     }
 
+    fn is_valuable(&self, f: &TaggedFlow) -> bool {
+        let flow = &f.flow;
+        if flow.close_type.is_client_error() || flow.close_type.is_server_error() {
+            return true;
+        }
+        let Some(stats) = flow.flow_perf_stats.as_ref() else {
+            return false;
+        };
+        let l7 = &stats.l7;
+        l7.err_client_count + l7.err_server_count + l7.err_timeout > 0
+            || l7.rrt_max >= self.config.load().backpressure.slow_request_threshold_us()
+    }
+
     fn flush_front_slot_and_rotate(&mut self) {
         let mut flow_stash = self.flow_stashs.pop_front().unwrap();
 
@@ -387,6 +414,11 @@ impl RefCountable for FlowAggrCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(self.drop_in_throttle.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "drop-in-backpressure",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.drop_in_backpressure.swap(0, Ordering::Relaxed)),
+            ),
             (
                 "stash-total-len",
                 CounterType::Counted,
@@ -500,6 +532,12 @@ impl ThrottlingQueue {
         self.cache_without_throttling.push(BoxedTaggedFlow(f));
     }
 
+    // Whether the sender queue is filled above the backpressure watermark
+    pub fn congested(&self) -> bool {
+        let backpressure = &self.config.load().backpressure;
+        backpressure.enabled() && self.output.above_watermark(backpressure.watermark)
+    }
+
     pub fn update_throttle(&mut self) {
         let new = self.config.load().l4_log_collect_nps_threshold;
         if new < Self::MIN_L4_LOG_COLLECT_NPS_THRESHOLD
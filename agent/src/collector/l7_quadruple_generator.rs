@@ -625,6 +625,10 @@ impl L7QuadrupleGenerator {
             return;
         }
         let l7_stats = l7_stats.unwrap();
+        // counted on a higher ranked capture point
+        if matches!(&l7_stats.flow, Some(f) if f.flow.capture_point_suppressed) {
+            return;
+        }
 
         let endpoint_hash = match &l7_stats.endpoint {
             Some(e) => hash_to_u64(e) as u32,
@@ -693,6 +697,7 @@ impl L7QuadrupleGenerator {
                     rrt_max: stats.rrt_max,
                     rrt_sum: stats.rrt_sum as u64,
                     rrt_count: stats.rrt_count,
+                    rrt_sketch: stats.rrt_sketch.as_deref().copied(),
                 },
                 anomaly: AppAnomaly {
                     client_error: stats.err_client_count,
@@ -864,6 +864,10 @@ impl QuadrupleGenerator {
             return;
         }
         let tagged_flow = tagged_flow.unwrap();
+        // counted on a higher ranked capture point
+        if tagged_flow.flow.capture_point_suppressed {
+            return;
+        }
 
         let mut no_endpoint_flag = true;
         for i in 0..2 {
@@ -987,6 +991,18 @@ impl QuadrupleGenerator {
                 None => return flow_meter,
             };
 
+            if tagged_flow.flow.flow_key.proto == IpProtocol::ICMPV4
+                || tagged_flow.flow.flow_key.proto == IpProtocol::ICMPV6
+            {
+                let icmp = &stats.icmp;
+                flow_meter.anomaly.icmp_unreachable = icmp.unreachable() as u64;
+                flow_meter.anomaly.icmp_port_unreachable = icmp.port_unreachable as u64;
+                flow_meter.anomaly.icmp_fragmentation_needed = icmp.fragmentation_needed as u64;
+                flow_meter.anomaly.icmp_admin_prohibited = icmp.admin_prohibited as u64;
+                flow_meter.anomaly.icmp_time_exceeded = icmp.time_exceeded as u64;
+                flow_meter.anomaly.icmp_redirect = icmp.redirect as u64;
+            }
+
             if tagged_flow.flow.flow_key.proto == IpProtocol::TCP
                 || tagged_flow.flow.flow_key.proto == IpProtocol::ICMPV4
                 || tagged_flow.flow.flow_key.proto == IpProtocol::ICMPV6
@@ -38,6 +38,9 @@ pub const IO_EVENT: u8 = 4;
 pub const GO_HTTP2_UPROBE_DATA: u8 = 5;
 // socket close event
 pub const SOCKET_CLOSE_EVENT: u8 = 6;
+#[allow(dead_code)]
+// hook in usdt probes
+pub const USDT_EVENT: u8 = 7;
 
 const EBPF_TYPE_TRACEPOINT: u8 = 0;
 const EBPF_TYPE_TLS_UPROBE: u8 = 1;
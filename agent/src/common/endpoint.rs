@@ -38,6 +38,7 @@ pub struct EndpointInfo {
     pub real_ip: IpAddr, // IsVIP为true时，该字段有值
     pub l2_epc_id: i32,  // 负数表示特殊值
     pub l3_epc_id: i32,  // 负数表示特殊值
+    pub tenant_id: u32,  // l3_epc_id所属的租户，0表示未知
     pub l2_end: bool,
     pub l3_end: bool,
     pub is_device: bool,
@@ -97,6 +98,7 @@ impl Default for EndpointInfo {
             real_ip: Ipv4Addr::UNSPECIFIED.into(),
             l2_epc_id: 0,
             l3_epc_id: 0,
+            tenant_id: 0,
             l2_end: false,
             l3_end: false,
             is_device: false,
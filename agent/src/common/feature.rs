@@ -16,6 +16,8 @@
 
 use bitflags::bitflags;
 
+use crate::config::FeatureFlagRollout;
+
 bitflags! {
     pub struct FeatureFlags: u64 {
         const NONE = 0;
@@ -38,3 +40,109 @@ impl From<&Vec<String>> for FeatureFlags {
         features
     }
 }
+
+// FNV-1a, stable across builds so that an agent stays in the same bucket
+// after restarts and upgrades
+fn rollout_bucket(flag: &str, agent_key: &str) -> u8 {
+    let mut hash: u32 = 0x811c9dc5;
+    for b in flag.bytes().chain([b'/']).chain(agent_key.bytes()) {
+        hash ^= b as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    (hash % 100) as u8
+}
+
+// Returns the sorted and deduplicated names of feature flags enabled on this agent.
+// A rollout matches if the agent is in one of its groups, or if the agent falls
+// into the first `percentage` buckets of the flag.
+pub fn active_feature_flags(
+    flags: &[String],
+    rollouts: &[FeatureFlagRollout],
+    agent_key: &str,
+    group_id: &str,
+) -> Vec<String> {
+    let mut active = flags
+        .iter()
+        .map(|f| f.to_lowercase())
+        .chain(
+            rollouts
+                .iter()
+                .filter(|r| {
+                    (!group_id.is_empty() && r.groups.iter().any(|g| g == group_id))
+                        || rollout_bucket(&r.name.to_lowercase(), agent_key) < r.percentage
+                })
+                .map(|r| r.name.to_lowercase()),
+        )
+        .collect::<Vec<_>>();
+    active.sort_unstable();
+    active.dedup();
+    active
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rollout(name: &str, percentage: u8, groups: &[&str]) -> FeatureFlagRollout {
+        FeatureFlagRollout {
+            name: name.to_owned(),
+            percentage,
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn rollout_by_group() {
+        let rollouts = vec![rollout("af_xdp", 0, &["g-1"])];
+        assert_eq!(
+            active_feature_flags(&[], &rollouts, "10.0.0.1/00:00:00:00:00:01", "g-1"),
+            vec!["af_xdp".to_owned()]
+        );
+        assert!(
+            active_feature_flags(&[], &rollouts, "10.0.0.1/00:00:00:00:00:01", "g-2").is_empty()
+        );
+    }
+
+    #[test]
+    fn rollout_by_percentage() {
+        let keys = (0..1000)
+            .map(|i| format!("10.0.{}.{}/00:00:00:00:00:01", i / 256, i % 256))
+            .collect::<Vec<_>>();
+        let enabled = |percentage| {
+            let rollouts = vec![rollout("AF_XDP", percentage, &[])];
+            keys.iter()
+                .filter(|k| !active_feature_flags(&[], &rollouts, k, "").is_empty())
+                .count()
+        };
+        assert_eq!(enabled(0), 0);
+        assert_eq!(enabled(100), keys.len());
+        let half = enabled(50);
+        assert!(
+            half > 400 && half < 600,
+            "{} of {} enabled",
+            half,
+            keys.len()
+        );
+
+        // buckets are stable and grow monotonically with percentage
+        let rollouts_10 = vec![rollout("af_xdp", 10, &[])];
+        let rollouts_20 = vec![rollout("af_xdp", 20, &[])];
+        for k in keys.iter() {
+            let a = active_feature_flags(&[], &rollouts_10, k, "");
+            assert_eq!(a, active_feature_flags(&[], &rollouts_10, k, ""));
+            if !a.is_empty() {
+                assert_eq!(a, active_feature_flags(&[], &rollouts_20, k, ""));
+            }
+        }
+    }
+
+    #[test]
+    fn merge_static_flags() {
+        let flags = vec!["Otel_Metrics".to_owned(), "af_xdp".to_owned()];
+        let rollouts = vec![rollout("af_xdp", 100, &[])];
+        assert_eq!(
+            active_feature_flags(&flags, &rollouts, "", ""),
+            vec!["af_xdp".to_owned(), "otel_metrics".to_owned()]
+        );
+    }
+}
@@ -32,6 +32,7 @@ use super::super::ebpf::{MSG_REQUEST, MSG_REQUEST_END, MSG_RESPONSE, MSG_RESPONS
 use super::{
     decapsulate::TunnelType,
     enums::{EthernetType, IpProtocol, TapType, TcpFlags},
+    meta_packet::IcmpOriginal,
     tap_port::TapPort,
     TaggedFlow,
 };
@@ -39,6 +40,7 @@ use super::{
 use crate::{
     common::{endpoint::EPC_INTERNET, timestamp_to_micros, Timestamp},
     metric::document::Direction,
+    utils::{histogram::Histogram, sketch::Sketch},
 };
 use crate::{
     flow_generator::protocol_logs::to_string_format,
@@ -483,6 +485,10 @@ pub struct FlowPerfStats {
     pub l4_protocol: L4Protocol,
     pub l7_protocol: L7Protocol,
     pub l7_failed_count: u32,
+    #[serde(flatten)]
+    pub icmp: IcmpPerfStats,
+    #[serde(skip)]
+    pub tcp_histograms: Option<Box<TcpHistograms>>,
 }
 
 impl FlowPerfStats {
@@ -499,6 +505,12 @@ impl FlowPerfStats {
 
         self.tcp.sequential_merge(&other.tcp);
         self.l7.sequential_merge(&other.l7);
+        self.icmp.sequential_merge(&other.icmp);
+        match (self.tcp_histograms.as_mut(), other.tcp_histograms.as_ref()) {
+            (Some(h), Some(o)) => h.sequential_merge(o),
+            (None, Some(o)) => self.tcp_histograms = Some(o.clone()),
+            _ => (),
+        }
     }
 
     pub fn reverse(&mut self) {
@@ -532,6 +544,157 @@ impl From<FlowPerfStats> for flow_log::FlowPerfStats {
             l4_protocol: p.l4_protocol as u32,
             l7_protocol: p.l7_protocol as u32,
             l7_failed_count: p.l7_failed_count,
+            icmp: if p.l4_protocol == L4Protocol::Icmp {
+                Some(p.icmp.into())
+            } else {
+                None
+            },
+            tcp_histograms: p.tcp_histograms.map(|h| (*h).into()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TcpHistograms {
+    pub rtt: Histogram,               // us
+    pub retrans_delay: Histogram,     // us
+    pub zero_win_duration: Histogram, // us
+}
+
+impl TcpHistograms {
+    pub fn sequential_merge(&mut self, other: &TcpHistograms) {
+        self.rtt.merge(&other.rtt);
+        self.retrans_delay.merge(&other.retrans_delay);
+        self.zero_win_duration.merge(&other.zero_win_duration);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rtt.is_empty() && self.retrans_delay.is_empty() && self.zero_win_duration.is_empty()
+    }
+}
+
+impl From<TcpHistograms> for flow_log::TcpHistograms {
+    fn from(h: TcpHistograms) -> Self {
+        let convert = |h: Histogram| flow_log::Histogram {
+            buckets: h.buckets().to_vec(),
+        };
+        flow_log::TcpHistograms {
+            rtt: Some(convert(h.rtt)),
+            retrans_delay: Some(convert(h.retrans_delay)),
+            zero_win_duration: Some(convert(h.zero_win_duration)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IcmpError {
+    pub icmp_type: u8,
+    pub icmp_code: u8,
+    pub original: Option<IcmpOriginal>,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct IcmpPerfStats {
+    #[serde(rename = "icmp_echo_request")]
+    pub echo_request: u32,
+    #[serde(rename = "icmp_echo_reply")]
+    pub echo_reply: u32,
+    #[serde(rename = "icmp_net_unreachable")]
+    pub net_unreachable: u32,
+    #[serde(rename = "icmp_host_unreachable")]
+    pub host_unreachable: u32,
+    #[serde(rename = "icmp_protocol_unreachable")]
+    pub protocol_unreachable: u32,
+    #[serde(rename = "icmp_port_unreachable")]
+    pub port_unreachable: u32,
+    #[serde(rename = "icmp_fragmentation_needed")]
+    pub fragmentation_needed: u32,
+    #[serde(rename = "icmp_admin_prohibited")]
+    pub admin_prohibited: u32,
+    #[serde(rename = "icmp_other_unreachable")]
+    pub other_unreachable: u32,
+    #[serde(rename = "icmp_time_exceeded")]
+    pub time_exceeded: u32,
+    #[serde(rename = "icmp_redirect")]
+    pub redirect: u32,
+    #[serde(rename = "icmp_other")]
+    pub other: u32,
+    #[serde(skip)]
+    pub last_error: Option<IcmpError>,
+}
+
+impl IcmpPerfStats {
+    pub fn sequential_merge(&mut self, other: &IcmpPerfStats) {
+        self.echo_request += other.echo_request;
+        self.echo_reply += other.echo_reply;
+        self.net_unreachable += other.net_unreachable;
+        self.host_unreachable += other.host_unreachable;
+        self.protocol_unreachable += other.protocol_unreachable;
+        self.port_unreachable += other.port_unreachable;
+        self.fragmentation_needed += other.fragmentation_needed;
+        self.admin_prohibited += other.admin_prohibited;
+        self.other_unreachable += other.other_unreachable;
+        self.time_exceeded += other.time_exceeded;
+        self.redirect += other.redirect;
+        self.other += other.other;
+        if other.last_error.is_some() {
+            self.last_error = other.last_error;
+        }
+    }
+
+    pub fn unreachable(&self) -> u32 {
+        self.net_unreachable
+            + self.host_unreachable
+            + self.protocol_unreachable
+            + self.port_unreachable
+            + self.fragmentation_needed
+            + self.admin_prohibited
+            + self.other_unreachable
+    }
+}
+
+impl From<IcmpPerfStats> for flow_log::IcmpPerfStats {
+    fn from(p: IcmpPerfStats) -> Self {
+        let (last_error_type, last_error_code, original) = match p.last_error {
+            Some(e) => (e.icmp_type as u32, e.icmp_code as u32, e.original),
+            None => (0, 0, None),
+        };
+        flow_log::IcmpPerfStats {
+            echo_request: p.echo_request,
+            echo_reply: p.echo_reply,
+            net_unreachable: p.net_unreachable,
+            host_unreachable: p.host_unreachable,
+            protocol_unreachable: p.protocol_unreachable,
+            port_unreachable: p.port_unreachable,
+            fragmentation_needed: p.fragmentation_needed,
+            admin_prohibited: p.admin_prohibited,
+            other_unreachable: p.other_unreachable,
+            time_exceeded: p.time_exceeded,
+            redirect: p.redirect,
+            other: p.other,
+            last_error_type,
+            last_error_code,
+            last_error_original: original.map(|o| {
+                let (ip4_src, ip4_dst, ip6_src, ip6_dst) = match (o.ip_src, o.ip_dst) {
+                    (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                        (u32::from(src), u32::from(dst), vec![], vec![])
+                    }
+                    (IpAddr::V6(src), IpAddr::V6(dst)) => {
+                        (0, 0, src.octets().to_vec(), dst.octets().to_vec())
+                    }
+                    _ => (0, 0, vec![], vec![]),
+                };
+                flow_log::FlowKey {
+                    ip_src: ip4_src,
+                    ip_dst: ip4_dst,
+                    ip6_src,
+                    ip6_dst,
+                    port_src: o.port_src as u32,
+                    port_dst: o.port_dst as u32,
+                    proto: o.proto as u32,
+                    ..Default::default()
+                }
+            }),
         }
     }
 }
@@ -564,6 +727,8 @@ pub struct L7PerfStats {
     pub rrt_sum: u64,   // us RRT(Request Response Time)
     pub rrt_max: u32,   // us agent保证在3600s以内
     pub tls_rtt: u32,
+    #[serde(skip)]
+    pub rrt_sketch: Option<Box<Sketch>>, // us
 }
 
 impl L7PerfStats {
@@ -579,6 +744,11 @@ impl L7PerfStats {
             self.rrt_max = other.rrt_max
         }
         self.tls_rtt += other.tls_rtt;
+        match (self.rrt_sketch.as_mut(), other.rrt_sketch.as_ref()) {
+            (Some(s), Some(o)) => s.merge(o),
+            (None, Some(o)) => self.rrt_sketch = Some(o.clone()),
+            _ => (),
+        }
     }
 
     pub fn merge_perf(
@@ -658,6 +828,7 @@ pub struct FlowMetricsPeer {
     pub last: Timestamp,         // 整个Flow生命周期尾包的时间戳
 
     pub l3_epc_id: i32,
+    pub tenant_id: u32, // l3_epc_id所属的租户，从grpc Interface和Cidr中获取
     pub is_l2_end: bool,
     pub is_l3_end: bool,
     pub is_active_host: bool,
@@ -715,6 +886,8 @@ where
         total_packet_rx: u64,
         l3_epc_id_0: i32,
         l3_epc_id_1: i32,
+        tenant_id_0: u32,
+        tenant_id_1: u32,
         l2_end_0: bool,
         l2_end_1: bool,
         l3_end_0: bool,
@@ -746,6 +919,8 @@ where
         total_packet_rx: v[1].total_packet_count,
         l3_epc_id_0: v[0].l3_epc_id,
         l3_epc_id_1: v[1].l3_epc_id,
+        tenant_id_0: v[0].tenant_id,
+        tenant_id_1: v[1].tenant_id,
         l2_end_0: v[0].is_l2_end,
         l2_end_1: v[1].is_l2_end,
         l3_end_0: v[0].is_l3_end,
@@ -778,6 +953,7 @@ impl Default for FlowMetricsPeer {
             last: Default::default(),
 
             l3_epc_id: 0,
+            tenant_id: 0,
             is_l2_end: false,
             is_l3_end: false,
             is_active_host: false,
@@ -810,6 +986,7 @@ impl FlowMetricsPeer {
         self.last = other.last;
 
         self.l3_epc_id = other.l3_epc_id;
+        self.tenant_id = other.tenant_id;
         self.is_l2_end = other.is_l2_end;
         self.is_l3_end = other.is_l3_end;
         self.is_active_host = other.is_active_host;
@@ -855,6 +1032,7 @@ impl From<FlowMetricsPeer> for flow_log::FlowMetricsPeer {
             real_ip,
             real_port: m.nat_real_port as u32,
             gpid: m.gpid,
+            tenant_id: m.tenant_id,
         }
     }
 }
@@ -963,6 +1141,13 @@ pub struct Flow {
     pub direction_score: u8,
     pub pod_id: u32,
     pub request_domain: String,
+    pub capture_nic: u32,
+    // captured by a higher ranked capture point as well, excluded from metrics
+    pub capture_point_suppressed: bool,
+    // extracted from TLS handshake
+    pub tls_sni: String,
+    pub tls_cert_subject: String,
+    pub tls_cert_issuer: String,
 }
 
 fn tunnel_is_none(t: &TunnelField) -> bool {
@@ -976,6 +1161,16 @@ impl Flow {
             + second_in_minute
     }
 
+    // Tenant the flow is exported for, that of the client if known, otherwise that of the server
+    pub fn tenant_id(&self) -> u32 {
+        let peers = &self.flow_metrics_peers;
+        if peers[FlowMetricsPeer::SRC as usize].tenant_id > 0 {
+            peers[FlowMetricsPeer::SRC as usize].tenant_id
+        } else {
+            peers[FlowMetricsPeer::DST as usize].tenant_id
+        }
+    }
+
     fn swap_flow_ip_and_real_ip(&mut self) {
         let metric = &mut self.flow_metrics_peers[PacketDirection::ClientToServer as usize];
         swap(&mut self.flow_key.port_src, &mut metric.nat_real_port);
@@ -1037,6 +1232,14 @@ impl Flow {
         if !other.request_domain.is_empty() {
             self.request_domain = other.request_domain.clone();
         }
+        self.capture_point_suppressed &= other.capture_point_suppressed;
+        if !other.tls_sni.is_empty() {
+            self.tls_sni = other.tls_sni.clone();
+        }
+        if !other.tls_cert_subject.is_empty() {
+            self.tls_cert_subject = other.tls_cert_subject.clone();
+            self.tls_cert_issuer = other.tls_cert_issuer.clone();
+        }
     }
 
     // FIXME 注意：由于FlowGenerator中TcpPerfStats在Flow方向调整之后才获取到，
@@ -1165,14 +1368,14 @@ impl fmt::Display for Flow {
             "flow_id:{} signal_source:{:?} tunnel:{} close_type:{:?} is_active_service:{} is_new_flow:{} queue_hash:{} \
         syn_seq:{} synack_seq:{} last_keepalive_seq:{} last_keepalive_ack:{} flow_stat_time:{:?} \
         \t start_time:{:?} end_time:{:?} duration:{:?} \
-        \t vlan:{} eth_type:{:?} reversed:{} otel_service:{:?} otel_instance:{:?} request_domain:{:?} flow_key:{} \
+        \t vlan:{} eth_type:{:?} reversed:{} otel_service:{:?} otel_instance:{:?} request_domain:{:?} tls_sni:{:?} flow_key:{} \
         \n\t flow_metrics_peers_src:{:?} \
         \n\t flow_metrics_peers_dst:{:?} \
         \n\t flow_perf_stats:{:?}",
             self.flow_id, self.signal_source, self.tunnel, self.close_type, self.is_active_service, self.is_new_flow, self.queue_hash,
             self.syn_seq, self.synack_seq, self.last_keepalive_seq, self.last_keepalive_ack, self.flow_stat_time,
             self.start_time, self.end_time, self.duration,
-            self.vlan, self.eth_type, self.reversed, self.otel_service, self.otel_instance, self.request_domain, self.flow_key,
+            self.vlan, self.eth_type, self.reversed, self.otel_service, self.otel_instance, self.request_domain, self.tls_sni, self.flow_key,
             self.flow_metrics_peers[0],
             self.flow_metrics_peers[1],
             self.flow_perf_stats
@@ -1217,6 +1420,11 @@ impl From<Flow> for flow_log::Flow {
             acl_gids: f.acl_gids.into_iter().map(|g| g as u32).collect(),
             direction_score: f.direction_score as u32,
             request_domain: f.request_domain,
+            capture_nic: f.capture_nic,
+            capture_point_suppressed: f.capture_point_suppressed as u32,
+            tls_sni: f.tls_sni,
+            tls_cert_subject: f.tls_cert_subject,
+            tls_cert_issuer: f.tls_cert_issuer,
         }
     }
 }
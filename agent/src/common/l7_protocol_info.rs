@@ -26,9 +26,9 @@ use crate::{
     flow_generator::{
         protocol_logs::{
             fastcgi::FastCGIInfo, pb_adapter::L7ProtocolSendLog, AmqpInfo, BrpcInfo, DnsInfo,
-            DubboInfo, HttpInfo, KafkaInfo, MongoDBInfo, MqttInfo, MysqlInfo, NatsInfo,
-            OpenWireInfo, OracleInfo, PostgreInfo, PulsarInfo, RedisInfo, SofaRpcInfo, TlsInfo,
-            ZmtpInfo,
+            DubboInfo, HttpInfo, KafkaInfo, L7ResponseStatus, MongoDBInfo, MqttInfo, MysqlInfo,
+            NatsInfo, OpenWireInfo, OracleInfo, PostgreInfo, PulsarInfo, RedisInfo, SofaRpcInfo,
+            TlsInfo, ZmtpInfo,
         },
         AppProtoHead, LogMessageType, Result,
     },
@@ -231,6 +231,7 @@ pub trait L7ProtocolInfoInterface: Into<L7ProtocolSendLog> {
                     );
                     None
                 } else {
+                    perf_cache.record_rrt(param.flow_id, rrt);
                     Some(rrt)
                 }
 
@@ -249,6 +250,7 @@ pub trait L7ProtocolInfoInterface: Into<L7ProtocolSendLog> {
                     perf_cache.rrt_cache.put(cache_key, previous_log_info);
                     None
                 } else {
+                    perf_cache.record_rrt(param.flow_id, rrt);
                     Some(rrt)
                 }
             } else {
@@ -399,6 +401,7 @@ pub trait L7ProtocolInfoInterface: Into<L7ProtocolSendLog> {
                 }
                 None
             } else {
+                perf_cache.record_rrt(param.flow_id, rrt);
                 Some(rrt)
             };
 
@@ -429,6 +432,11 @@ pub trait L7ProtocolInfoInterface: Into<L7ProtocolSendLog> {
         0
     }
 
+    // used to keep error logs when the sender queue is under backpressure
+    fn get_response_status(&self) -> L7ResponseStatus {
+        L7ResponseStatus::Ok
+    }
+
     fn is_on_blacklist(&self) -> bool {
         false
     }
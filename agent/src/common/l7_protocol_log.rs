@@ -46,6 +46,7 @@ use crate::flow_generator::{LogMessageType, Result};
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use crate::plugin::c_ffi::SoPluginFunc;
 use crate::plugin::wasm::WasmVm;
+use crate::utils::sketch::Sketch;
 
 use public::enums::IpProtocol;
 use public::l7_protocol::{CustomProtocol, L7Protocol, L7ProtocolChecker, L7ProtocolEnum};
@@ -291,6 +292,10 @@ pub struct L7PerfCache {
     pub rrt_cache: LruCache<u128, LogCache>,
     // LruCache<flow_id, (in_cache_req, count)>
     pub timeout_cache: LruCache<u64, (usize, usize)>,
+    // LruCache<flow_id, rrt sketch>, rrt in the current statistical interval
+    pub rrt_sketch_cache: LruCache<u64, Sketch>,
+    // relative accuracy of rrt sketches, None if disabled
+    rrt_sketch_accuracy: Option<f64>,
     // time in microseconds
     pub last_log_time: u64,
 }
@@ -303,6 +308,8 @@ impl L7PerfCache {
         L7PerfCache {
             rrt_cache: LruCache::new(cap.try_into().unwrap()),
             timeout_cache: LruCache::new(cap.try_into().unwrap()),
+            rrt_sketch_cache: LruCache::new(cap.try_into().unwrap()),
+            rrt_sketch_accuracy: None,
             last_log_time: 0,
         }
     }
@@ -318,6 +325,25 @@ impl L7PerfCache {
         self.rrt_cache.put(key, value)
     }
 
+    pub fn set_rrt_sketch_accuracy(&mut self, relative_accuracy: Option<f64>) {
+        if self.rrt_sketch_accuracy.is_some() && relative_accuracy.is_none() {
+            self.rrt_sketch_cache.clear();
+        }
+        self.rrt_sketch_accuracy = relative_accuracy;
+    }
+
+    pub fn record_rrt(&mut self, flow_id: u64, rrt: u64) {
+        if let Some(relative_accuracy) = self.rrt_sketch_accuracy {
+            self.rrt_sketch_cache
+                .get_or_insert_mut(flow_id, || Sketch::new(relative_accuracy))
+                .record(rrt.min(u32::MAX as u64) as u32);
+        }
+    }
+
+    pub fn pop_rrt_sketch(&mut self, flow_id: &u64) -> Option<Box<Sketch>> {
+        self.rrt_sketch_cache.pop(flow_id).map(Box::new)
+    }
+
     pub fn pop_timeout_count(&mut self, flow_id: &u64, flow_end: bool) -> usize {
         let (in_cache, t) = self.timeout_cache.pop(flow_id).unwrap_or((0, 0));
         if flow_end {
@@ -214,6 +214,16 @@ impl<const N: usize> MatchedFieldN<N> {
         self.set(flag, if is_set { 0xFFFF } else { 0 })
     }
 
+    // IP按网络序转为整数，用于区间比较
+    pub fn get_ip_bits(&self, flag: MatchedFlag) -> u128 {
+        let ip = match flag {
+            MatchedFlag::SrcIp => &self.src_ip,
+            MatchedFlag::DstIp => &self.dst_ip,
+            _ => unimplemented!(),
+        };
+        ip.iter().fold(0, |acc, b| acc << 8 | *b as u128)
+    }
+
     pub fn set_bits(&mut self, bits: &Vec<usize>) {
         self.src_ip = [0; N];
         self.dst_ip = [0; N];
@@ -17,12 +17,12 @@
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use std::any::Any;
 use std::fmt;
-use std::net::{IpAddr, Ipv4Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::ops::Deref;
 use std::sync::Arc;
 use std::time::Duration;
 #[cfg(any(target_os = "linux", target_os = "android"))]
-use std::{error::Error, net::Ipv6Addr, ptr};
+use std::{error::Error, ptr};
 
 use bitflags::bitflags;
 use pnet::packet::{
@@ -49,7 +49,7 @@ use crate::error;
 use crate::{
     common::ebpf::{GO_HTTP2_UPROBE, GO_HTTP2_UPROBE_DATA},
     ebpf::{
-        MSG_REASM_SEG, MSG_REASM_START, MSG_REQUEST_END, MSG_RESPONSE_END,
+        c_char, MSG_REASM_SEG, MSG_REASM_START, MSG_REQUEST_END, MSG_RESPONSE_END,
         PACKET_KNAME_MAX_PADDING, SK_BPF_DATA, SOCK_DATA_HTTP2, SOCK_DATA_TLS_HTTP2, SOCK_DIR_RCV,
         SOCK_DIR_SND,
     },
@@ -179,6 +179,8 @@ pub struct MetaPacket<'a> {
     pub vlan: u16,
     pub is_active_service: bool,
     pub queue_hash: u8,
+    // physical interface the packet is received on, 0 if unknown
+    pub capture_nic: u32,
 
     /********** for xFlow (NetFlow/sFlow/NetStream) **********/
     // TODO support xFlow
@@ -710,6 +712,12 @@ impl<'a> MetaPacket<'a> {
                 let icmp_type_index = FIELD_OFFSET_ICMP_TYPE_CODE + self.l2_l3_opt_size as usize;
                 let mut icmp_data = IcmpData::default();
                 icmp_data.icmp_type = packet[icmp_type_index];
+                icmp_data.icmp_code = packet[icmp_type_index + 1];
+                if IcmpData::is_error(icmp_data.icmp_type, false) {
+                    icmp_data.original = packet
+                        .get(icmp_type_index + IcmpOriginal::OFFSET..)
+                        .and_then(|p| IcmpOriginal::parse(p, false));
+                }
 
                 match IcmpType::new(
                     packet[FIELD_OFFSET_ICMP_TYPE_CODE + self.l2_l3_opt_size as usize],
@@ -851,6 +859,12 @@ impl<'a> MetaPacket<'a> {
                 if size_checker > 0 {
                     let icmpv6_type_index = ICMPV6_TYPE_OFFSET + self.l2_l3_opt_size as usize;
                     icmp_data.icmp_type = packet[icmpv6_type_index];
+                    icmp_data.icmp_code = packet[icmpv6_type_index + 1];
+                    if IcmpData::is_error(icmp_data.icmp_type, true) {
+                        icmp_data.original = packet
+                            .get(icmpv6_type_index + IcmpOriginal::OFFSET..)
+                            .and_then(|p| IcmpOriginal::parse(p, true));
+                    }
 
                     match Icmpv6Type::new(packet[icmpv6_type_index]) {
                         Icmpv6Types::NeighborAdvert => {
@@ -994,12 +1008,8 @@ impl<'a> MetaPacket<'a> {
         let cap_len = data.cap_len as usize;
 
         packet.raw_from_ebpf = vec![0u8; cap_len as usize];
-        #[cfg(target_arch = "aarch64")]
-        data.cap_data
-            .copy_to_nonoverlapping(packet.raw_from_ebpf.as_mut_ptr() as *mut u8, cap_len);
-        #[cfg(target_arch = "x86_64")]
         data.cap_data
-            .copy_to_nonoverlapping(packet.raw_from_ebpf.as_mut_ptr() as *mut i8, cap_len);
+            .copy_to_nonoverlapping(packet.raw_from_ebpf.as_mut_ptr() as *mut c_char, cap_len);
         packet.packet_len = data.syscall_len as u32 + 54; // 目前仅支持TCP
         packet.payload_len = data.cap_len as u16;
         packet.l4_payload_len = data.cap_len as u16;
@@ -1012,16 +1022,9 @@ impl<'a> MetaPacket<'a> {
         packet.coroutine_id = data.coroutine_id;
         packet.syscall_trace_id = data.syscall_trace_id_call;
         packet.socket_role = data.socket_role;
-        #[cfg(target_arch = "aarch64")]
         ptr::copy(
-            data.process_kname.as_ptr() as *const u8,
-            packet.process_kname.as_mut_ptr() as *mut u8,
-            PACKET_KNAME_MAX_PADDING,
-        );
-        #[cfg(target_arch = "x86_64")]
-        ptr::copy(
-            data.process_kname.as_ptr() as *const i8,
-            packet.process_kname.as_mut_ptr() as *mut i8,
+            data.process_kname.as_ptr() as *const c_char,
+            packet.process_kname.as_mut_ptr() as *mut c_char,
             PACKET_KNAME_MAX_PADDING,
         );
         packet.socket_id = data.socket_id;
@@ -1200,7 +1203,87 @@ pub struct MetaPacketTcpHeader {
 #[derive(Clone, Debug, Default)]
 pub struct IcmpData {
     pub icmp_type: u8,
+    pub icmp_code: u8,
     pub echo_id_seq: u32,
+    // tuple of the datagram that triggered the error message
+    pub original: Option<IcmpOriginal>,
+}
+
+impl IcmpData {
+    pub fn is_error(icmp_type: u8, is_ipv6: bool) -> bool {
+        if is_ipv6 {
+            // error messages have types from 0 to 127
+            icmp_type < 128
+        } else {
+            let icmp_type = IcmpType::new(icmp_type);
+            icmp_type == IcmpTypes::DestinationUnreachable
+                || icmp_type == IcmpTypes::SourceQuench
+                || icmp_type == IcmpTypes::RedirectMessage
+                || icmp_type == IcmpTypes::TimeExceeded
+                || icmp_type == IcmpTypes::ParameterProblem
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IcmpOriginal {
+    pub ip_src: IpAddr,
+    pub ip_dst: IpAddr,
+    pub proto: u8,
+    // 0 if the original datagram is not TCP or UDP, or truncated
+    pub port_src: u16,
+    pub port_dst: u16,
+}
+
+impl IcmpOriginal {
+    // the original datagram follows the 8 bytes ICMP header
+    pub const OFFSET: usize = 8;
+    const IPV4_HEADER_LEN: usize = 20;
+    const IPV6_HEADER_LEN: usize = 40;
+
+    // Parses the IP header and the first 4 bytes of L4 header of the original datagram,
+    // IPv6 extension headers are not skipped
+    pub fn parse(payload: &[u8], is_ipv6: bool) -> Option<Self> {
+        let (ip_src, ip_dst, proto, header_len): (IpAddr, IpAddr, u8, usize) = if is_ipv6 {
+            if payload.len() < Self::IPV6_HEADER_LEN || payload[0] >> 4 != 6 {
+                return None;
+            }
+            let src: [u8; 16] = payload[8..24].try_into().unwrap();
+            let dst: [u8; 16] = payload[24..40].try_into().unwrap();
+            (
+                Ipv6Addr::from(src).into(),
+                Ipv6Addr::from(dst).into(),
+                payload[6],
+                Self::IPV6_HEADER_LEN,
+            )
+        } else {
+            if payload.len() < Self::IPV4_HEADER_LEN || payload[0] >> 4 != 4 {
+                return None;
+            }
+            let src: [u8; 4] = payload[12..16].try_into().unwrap();
+            let dst: [u8; 4] = payload[16..20].try_into().unwrap();
+            (
+                Ipv4Addr::from(src).into(),
+                Ipv4Addr::from(dst).into(),
+                payload[9],
+                ((payload[0] & 0xf) as usize * 4).max(Self::IPV4_HEADER_LEN),
+            )
+        };
+        let mut original = Self {
+            ip_src,
+            ip_dst,
+            proto,
+            port_src: 0,
+            port_dst: 0,
+        };
+        let is_tcp_or_udp =
+            proto == u8::from(IpProtocol::TCP) || proto == u8::from(IpProtocol::UDP);
+        if is_tcp_or_udp && payload.len() >= header_len + 4 {
+            original.port_src = read_u16_be(&payload[header_len..]);
+            original.port_dst = read_u16_be(&payload[header_len + 2..]);
+        }
+        Some(original)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -1242,4 +1325,34 @@ mod tests {
             pkt
         );
     }
+
+    #[test]
+    fn parse_icmp_original() {
+        let mut payload = vec![0u8; 28];
+        payload[0] = 0x45;
+        payload[9] = u8::from(IpProtocol::UDP);
+        payload[12..16].copy_from_slice(&[10, 0, 0, 1]);
+        payload[16..20].copy_from_slice(&[10, 0, 0, 2]);
+        payload[20..24].copy_from_slice(&[0x9c, 0x40, 0x00, 0x35]);
+        assert_eq!(
+            IcmpOriginal::parse(&payload, false),
+            Some(IcmpOriginal {
+                ip_src: Ipv4Addr::new(10, 0, 0, 1).into(),
+                ip_dst: Ipv4Addr::new(10, 0, 0, 2).into(),
+                proto: u8::from(IpProtocol::UDP),
+                port_src: 40000,
+                port_dst: 53,
+            })
+        );
+        // ports are not available in truncated payload
+        assert_eq!(
+            IcmpOriginal::parse(&payload[..22], false).map(|o| o.port_dst),
+            Some(0)
+        );
+        assert!(IcmpOriginal::parse(&payload, true).is_none());
+        assert!(!IcmpData::is_error(8, false));
+        assert!(IcmpData::is_error(11, false));
+        assert!(IcmpData::is_error(1, true));
+        assert!(!IcmpData::is_error(128, true));
+    }
 }
@@ -37,7 +37,7 @@ pub mod tap_types;
 pub mod timestamp;
 
 pub use consts::*;
-pub use feature::FeatureFlags;
+pub use feature::{active_feature_flags, FeatureFlags};
 pub use meta_packet::MetaPacket;
 pub use platform_data::PlatformData;
 pub use public::enums;
@@ -85,9 +85,11 @@ pub enum FlowAclListenerId {
     Policy = 0,
     NpbBandWatcher = 1,
     EbpfDispatcher = 2,
+    NpbHealthChecker = 3,
+    PcapStorage = 4,
     // There are multiple Dispatcher in Agent, and Dispatcher ID increases from FlowAclListenerId::Dispatcher.
     // FlowAclListenerId::Dispatcher must be the last one.
-    Dispatcher = 3,
+    Dispatcher = 5,
 }
 
 pub trait FlowAclListener: Send + Sync {
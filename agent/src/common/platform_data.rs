@@ -58,6 +58,8 @@ pub struct PlatformData {
     pub mac: u64,
     pub ips: Vec<IpSubnet>,
     pub epc_id: i32,
+    // 租户ID，由平台数据中VPC所属的租户决定，0表示未知
+    pub tenant_id: u32,
     pub id: u32,
     pub region_id: u32,
     pub pod_cluster_id: u32,
@@ -82,6 +84,7 @@ impl Default for PlatformData {
             mac: 0,
             ips: Vec::new(),
             epc_id: 0,
+            tenant_id: 0,
             id: 0,
             region_id: 0,
             pod_cluster_id: 0,
@@ -138,6 +141,7 @@ impl TryFrom<&trident::Interface> for PlatformData {
             })?,
             ips,
             epc_id,
+            tenant_id: p.tenant_id(),
             id: p.id(),
             region_id: p.region_id(),
             pod_cluster_id: p.pod_cluster_id(),
@@ -271,7 +271,7 @@ impl PortSegment {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct IpSegment {
     ip: IpAddr,
     mask: IpAddr,
@@ -465,6 +465,8 @@ pub struct Acl {
     pub proto: u16, // 256表示全采集, 0表示采集采集协议0
 
     pub npb_actions: Vec<NpbAction>,
+    // backup endpoints of each npb tunnel ip, in the order to fail over to
+    pub npb_tunnel_backups: Vec<(IpAddr, Vec<IpAddr>)>,
 
     pub match_field: Vec<Arc<Fieldv4>>,
     pub match_field6: Vec<Arc<Fieldv6>>,
@@ -501,6 +503,17 @@ impl Acl {
         self.match_field6.clear();
     }
 
+    // 匹配条件相同时，生成的match_field只取决于资源组内容
+    pub fn is_same_rule(&self, other: &Acl) -> bool {
+        self.id == other.id
+            && self.tap_type == other.tap_type
+            && self.src_groups == other.src_groups
+            && self.dst_groups == other.dst_groups
+            && self.src_port_ranges == other.src_port_ranges
+            && self.dst_port_ranges == other.dst_port_ranges
+            && self.proto == other.proto
+    }
+
     fn get_port_range(ports: &Vec<u16>) -> Vec<PortRange> {
         let mut port_ranges = Vec::new();
         let mut min = 0;
@@ -751,6 +764,21 @@ impl TryFrom<trident::FlowAcl> for Acl {
                 )
             })
             .collect();
+        let npb_tunnel_backups = a
+            .npb_actions
+            .iter()
+            .filter(|n| !n.backup_tunnel_ips.is_empty())
+            .filter_map(|n| {
+                let tunnel_ip = n.tunnel_ip().parse::<IpAddr>().ok()?;
+                let backups = n
+                    .backup_tunnel_ips
+                    .iter()
+                    .filter_map(|ip| ip.parse::<IpAddr>().ok())
+                    .filter(|ip| ip.is_ipv6() == tunnel_ip.is_ipv6())
+                    .collect();
+                Some((tunnel_ip, backups))
+            })
+            .collect();
 
         Ok(Acl {
             id: a.id.unwrap_or_default(),
@@ -769,6 +797,7 @@ impl TryFrom<trident::FlowAcl> for Acl {
             dst_port_ranges: dst_ports.unwrap().element().to_vec(),
             proto: (a.protocol.unwrap_or_default() & 0xffff) as u16,
             npb_actions: npb_actions.clone(),
+            npb_tunnel_backups,
             policy: Arc::new(PolicyData::new(npb_actions, a.id.unwrap_or_default())),
             ..Default::default()
         })
@@ -791,6 +820,7 @@ pub struct Cidr {
     pub ip: IpNet,
     pub tunnel_id: u32,
     pub epc_id: i32,
+    pub tenant_id: u32,
     pub cidr_type: CidrType,
     pub is_vip: bool,
     pub region_id: u32,
@@ -833,6 +863,7 @@ impl TryFrom<&trident::Cidr> for Cidr {
             ip,
             tunnel_id: c.tunnel_id(),
             epc_id,
+            tenant_id: c.tenant_id(),
             cidr_type: c.r#type().into(),
             is_vip: c.is_vip(),
             region_id: c.region_id(),
@@ -855,6 +886,7 @@ impl Default for Cidr {
             ip: Ipv4Net::from(Ipv4Addr::UNSPECIFIED).into(),
             tunnel_id: 0,
             epc_id: 0,
+            tenant_id: 0,
             region_id: 0,
             cidr_type: CidrType::Lan,
             is_vip: false,
@@ -27,10 +27,10 @@ use public::{
 };
 
 use crate::common::{
-    ebpf::IO_EVENT,
+    ebpf::{IO_EVENT, USDT_EVENT},
     error::Error::{self, ParseEventData},
 };
-use crate::ebpf::SK_BPF_DATA;
+use crate::ebpf::{c_char, SK_BPF_DATA};
 
 const FILENAME_MAX_PADDING: usize = 64;
 const IO_BYTES_COUNT_OFFSET: usize = 4;
@@ -80,9 +80,56 @@ impl From<IoEventData> for metric::IoEventData {
     }
 }
 
+const USDT_ARGS_MAX: usize = 6;
+const USDT_ARG_COUNT_OFFSET: usize = 4;
+const USDT_ARGS_OFFSET: usize = 8;
+const USDT_NAME_OFFSET: usize = USDT_ARGS_OFFSET + USDT_ARGS_MAX * 8;
+const USDT_NAME_MAX_PADDING: usize = 64;
+struct UsdtEventData {
+    probe: String,  // provider:name
+    args: Vec<u64>, // raw values of probe arguments, at most 6
+}
+
+impl TryFrom<&[u8]> for UsdtEventData {
+    type Error = Error;
+
+    fn try_from(raw_data: &[u8]) -> Result<Self, self::Error> {
+        let length = raw_data.len();
+        if length < USDT_NAME_OFFSET + USDT_NAME_MAX_PADDING {
+            return Err(ParseEventData(format!(
+                "parse usdt event data failed, raw data length: {} < {}",
+                length,
+                USDT_NAME_OFFSET + USDT_NAME_MAX_PADDING
+            )));
+        }
+        let arg_count =
+            (read_u32_le(&raw_data[USDT_ARG_COUNT_OFFSET..]) as usize).min(USDT_ARGS_MAX);
+        let name = &raw_data[USDT_NAME_OFFSET..];
+        Ok(Self {
+            probe: String::from_utf8_lossy(
+                &name[..name.iter().position(|&b| b == b'\0').unwrap_or(name.len())],
+            )
+            .into_owned(),
+            args: (0..arg_count)
+                .map(|i| read_u64_le(&raw_data[USDT_ARGS_OFFSET + i * 8..]))
+                .collect(),
+        })
+    }
+}
+
+impl From<UsdtEventData> for metric::UsdtEventData {
+    fn from(usdt_event_data: UsdtEventData) -> Self {
+        Self {
+            probe: usdt_event_data.probe,
+            args: usdt_event_data.args,
+        }
+    }
+}
+
 enum EventData {
     OtherEvent,
     IoEvent(IoEventData),
+    UsdtEvent(UsdtEventData),
 }
 
 impl Debug for EventData {
@@ -95,6 +142,10 @@ impl Debug for EventData {
                 d.bytes_count,
                 d.latency
             )),
+            EventData::UsdtEvent(d) => f.write_fmt(format_args!(
+                "UsdtEventData {{ probe: {}, args: {:?} }}",
+                d.probe, d.args
+            )),
             _ => f.write_str("other event"),
         }
     }
@@ -104,12 +155,14 @@ impl Debug for EventData {
 pub enum EventType {
     OtherEvent = 0,
     IoEvent = 1,
+    UsdtEvent = 2,
 }
 
 impl From<u8> for EventType {
     fn from(source: u8) -> Self {
         match source {
             IO_EVENT => Self::IoEvent,
+            USDT_EVENT => Self::UsdtEvent,
             _ => Self::OtherEvent,
         }
     }
@@ -126,6 +179,7 @@ impl fmt::Display for EventType {
         match self {
             Self::OtherEvent => write!(f, "other_event"),
             Self::IoEvent => write!(f, "io_event"),
+            Self::UsdtEvent => write!(f, "usdt_event"),
         }
     }
 }
@@ -150,12 +204,8 @@ impl ProcEvent {
         let data = &mut data.read_unaligned();
         let cap_len = data.cap_len as usize;
         let mut raw_data = vec![0u8; cap_len as usize]; // Copy from data.cap_data where stores event's data
-        #[cfg(target_arch = "aarch64")]
         data.cap_data
-            .copy_to_nonoverlapping(raw_data.as_mut_ptr() as *mut u8, cap_len);
-        #[cfg(target_arch = "x86_64")]
-        data.cap_data
-            .copy_to_nonoverlapping(raw_data.as_mut_ptr() as *mut i8, cap_len);
+            .copy_to_nonoverlapping(raw_data.as_mut_ptr() as *mut c_char, cap_len);
 
         let mut event_data: EventData = EventData::OtherEvent;
         let start_time = data.timestamp * 1000; // The unit of data.timestamp is microsecond, and the unit of start_time is nanosecond
@@ -166,6 +216,11 @@ impl ProcEvent {
                 end_time = start_time + io_event_data.latency;
                 event_data = EventData::IoEvent(io_event_data);
             }
+            EventType::UsdtEvent => {
+                // USDT probe is an instant, the end time is the same as the start time
+                end_time = start_time;
+                event_data = EventData::UsdtEvent(UsdtEventData::try_from(raw_data.as_ref())?);
+            }
             _ => {}
         }
 
@@ -220,6 +275,9 @@ impl Sendable for BoxedProcEvents {
             EventData::IoEvent(io_event_data) => {
                 pb_proc_event.io_event_data = Some(io_event_data.into())
             }
+            EventData::UsdtEvent(usdt_event_data) => {
+                pb_proc_event.usdt_event_data = Some(usdt_event_data.into())
+            }
             _ => {}
         }
         pb_proc_event
@@ -231,3 +289,26 @@ impl Sendable for BoxedProcEvents {
         SendMessageType::ProcEvents
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_usdt_event_data() {
+        let mut raw_data = vec![0u8; USDT_NAME_OFFSET + USDT_NAME_MAX_PADDING];
+        raw_data[..4].copy_from_slice(&3u32.to_le_bytes());
+        raw_data[USDT_ARG_COUNT_OFFSET..USDT_ARGS_OFFSET].copy_from_slice(&2u32.to_le_bytes());
+        raw_data[USDT_ARGS_OFFSET..USDT_ARGS_OFFSET + 8].copy_from_slice(&42u64.to_le_bytes());
+        raw_data[USDT_ARGS_OFFSET + 8..USDT_ARGS_OFFSET + 16]
+            .copy_from_slice(&(-1i64 as u64).to_le_bytes());
+        let probe = b"postgresql:query__start";
+        raw_data[USDT_NAME_OFFSET..USDT_NAME_OFFSET + probe.len()].copy_from_slice(probe);
+
+        let data = UsdtEventData::try_from(raw_data.as_slice()).unwrap();
+        assert_eq!(data.probe, "postgresql:query__start");
+        assert_eq!(data.args, vec![42, u64::MAX]);
+
+        assert!(UsdtEventData::try_from(&raw_data[..USDT_NAME_OFFSET]).is_err());
+    }
+}
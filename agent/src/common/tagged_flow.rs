@@ -102,6 +102,10 @@ impl Sendable for BoxedTaggedFlow {
         "l4_flow_log"
     }
 
+    fn tenant_id(&self) -> u32 {
+        self.0.flow.tenant_id()
+    }
+
     fn message_type(&self) -> SendMessageType {
         SendMessageType::TaggedFlow
     }
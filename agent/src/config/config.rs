@@ -14,25 +14,28 @@
  * limitations under the License.
  */
 
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::fmt;
 use std::fs;
 use std::io;
 use std::net::{IpAddr, ToSocketAddrs};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+use ipnet::IpNet;
 use log::{debug, error, info, warn};
 use md5::{Digest, Md5};
 use regex::Regex;
 use serde::{
-    de::{self, Unexpected},
-    Deserialize, Deserializer,
+    de::{self, DeserializeOwned, Unexpected},
+    Deserialize, Deserializer, Serialize, Serializer,
 };
 use thiserror::Error;
 use tokio::runtime::Runtime;
 
-use crate::common::l7_protocol_log::L7ProtocolParser;
+use crate::common::l7_protocol_log::{L7ProtocolBitmap, L7ProtocolParser};
 use crate::flow_generator::{DnsLog, OracleLog, TlsLog};
 use crate::{
     common::{
@@ -41,10 +44,12 @@ use crate::{
         l7_protocol_log::{get_all_protocol, L7ProtocolParserInterface},
         DEFAULT_LOG_FILE, L7_PROTOCOL_INFERENCE_MAX_FAIL_COUNT, L7_PROTOCOL_INFERENCE_TTL,
     },
+    dispatcher::recv_engine::af_packet::options::OptXdpScope,
     flow_generator::protocol_logs::SLOT_WIDTH,
     metric::document::TapSide,
-    rpc::Session,
+    rpc::{CertPins, Session},
     trident::RunningMode,
+    utils::logger::LOG_MODULES,
 };
 use public::{
     bitmap::Bitmap,
@@ -53,6 +58,7 @@ use public::{
         common,
         trident::{self, KubernetesClusterIdRequest, TapMode},
     },
+    queue::DropPolicy,
     utils::bitmap::parse_u16_range_list_to_bitmap,
 };
 
@@ -95,6 +101,18 @@ impl<'de> Deserialize<'de> for AgentIdType {
     }
 }
 
+impl Serialize for AgentIdType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(match self {
+            Self::IpMac => "ip-and-mac",
+            Self::Ip => "ip",
+        })
+    }
+}
+
 impl From<AgentIdType> for trident::AgentIdentifier {
     fn from(t: AgentIdType) -> Self {
         match t {
@@ -104,13 +122,17 @@ impl From<AgentIdType> for trident::AgentIdentifier {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct Config {
+    #[serde(deserialize_with = "references_de")]
     pub controller_ips: Vec<String>,
     pub controller_port: u16,
     pub controller_tls_port: u16,
+    #[serde(deserialize_with = "reference_de")]
     pub controller_cert_file_prefix: String,
+    #[serde(deserialize_with = "references_de")]
+    pub controller_cert_pins: Vec<String>,
     pub log_file: String,
     pub kubernetes_cluster_id: String,
     pub kubernetes_cluster_name: Option<String>,
@@ -124,6 +146,13 @@ pub struct Config {
     #[cfg(target_os = "linux")]
     pub pid_file: String,
     pub team_id: String,
+    pub local_api_port: u16,
+    pub local_api_token_file: String,
+    // hex encoded ed25519 public key to verify upgrade binary signature
+    #[serde(deserialize_with = "reference_de")]
+    pub upgrade_public_key: String,
+    pub plugin_cache_dir: String,
+    pub disable_cloud_metadata: bool,
 }
 
 impl Config {
@@ -139,8 +168,8 @@ impl Config {
             // parsing empty string leads to EOF error
             Ok(Self::default())
         } else {
-            let mut cfg: Self = serde_yaml::from_str(contents)
-                .map_err(|e| ConfigError::YamlConfigInvalid(e.to_string()))?;
+            let mut cfg: Self =
+                from_yaml_with_references(contents).map_err(ConfigError::YamlConfigInvalid)?;
 
             for i in 0..cfg.controller_ips.len() {
                 if cfg.controller_ips[i].parse::<IpAddr>().is_err() {
@@ -176,6 +205,94 @@ impl Config {
         }
     }
 
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.controller_ips.is_empty() {
+            return Err(ConfigError::ControllerIpsEmpty);
+        }
+        if self.controller_port == 0 {
+            return Err(ConfigError::YamlConfigInvalid(
+                "controller-port must not be 0".to_owned(),
+            ));
+        }
+        if self.controller_tls_port == 0 {
+            return Err(ConfigError::YamlConfigInvalid(
+                "controller-tls-port must not be 0".to_owned(),
+            ));
+        }
+        if !self.controller_cert_file_prefix.is_empty()
+            && self.controller_port == self.controller_tls_port
+        {
+            return Err(ConfigError::YamlConfigInvalid(format!(
+                "controller-port and controller-tls-port must differ when controller-cert-file-prefix is set, both are {}",
+                self.controller_port
+            )));
+        }
+        if !self.controller_cert_pins.is_empty() {
+            if self.controller_cert_file_prefix.is_empty() {
+                return Err(ConfigError::YamlConfigInvalid(
+                    "controller-cert-pins requires controller-cert-file-prefix".to_owned(),
+                ));
+            }
+            if let Err(e) = CertPins::parse(&self.controller_cert_pins) {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "controller-cert-pins: {}",
+                    e
+                )));
+            }
+        }
+        if self.async_worker_thread_number == 0 {
+            return Err(ConfigError::YamlConfigInvalid(
+                "async-worker-thread-number must be greater than 0".to_owned(),
+            ));
+        }
+        #[cfg(target_os = "linux")]
+        if !self.pid_file.is_empty() && Path::new(&self.pid_file).is_relative() {
+            return Err(ConfigError::YamlConfigInvalid(format!(
+                "pid-file {} must be an absolute path",
+                self.pid_file
+            )));
+        }
+        if !self.upgrade_public_key.is_empty()
+            && hex::decode(&self.upgrade_public_key)
+                .map(|k| k.len() != 32)
+                .unwrap_or(true)
+        {
+            return Err(ConfigError::YamlConfigInvalid(
+                "upgrade-public-key must be a hex encoded 32 bytes ed25519 public key".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+
+    // Defaults to `local-api.token` in the log directory
+    pub fn local_api_token_file(&self) -> PathBuf {
+        if !self.local_api_token_file.is_empty() {
+            return PathBuf::from(&self.local_api_token_file);
+        }
+        Path::new(&self.log_file)
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join("local-api.token")
+    }
+
+    // Defaults to `plugins` in the log directory
+    pub fn plugin_cache_dir(&self) -> PathBuf {
+        if !self.plugin_cache_dir.is_empty() {
+            return PathBuf::from(&self.plugin_cache_dir);
+        }
+        Path::new(&self.log_file)
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join("plugins")
+    }
+
+    pub fn upgrade_public_key(&self) -> Option<Vec<u8>> {
+        if self.upgrade_public_key.is_empty() {
+            return None;
+        }
+        hex::decode(&self.upgrade_public_key).ok()
+    }
+
     pub async fn async_get_k8s_cluster_id(session: &Session, config: &Config) -> Option<String> {
         let ca_md5 = match fs::read_to_string(K8S_CA_CRT_PATH) {
             Ok(c) => Some(
@@ -262,6 +379,7 @@ impl Default for Config {
             controller_port: 30035,
             controller_tls_port: 30135,
             controller_cert_file_prefix: "".into(),
+            controller_cert_pins: vec![],
             log_file: DEFAULT_LOG_FILE.into(),
             kubernetes_cluster_id: "".into(),
             kubernetes_cluster_name: Default::default(),
@@ -274,6 +392,11 @@ impl Default for Config {
             #[cfg(target_os = "linux")]
             pid_file: Default::default(),
             team_id: "".into(),
+            local_api_port: 0,
+            local_api_token_file: "".into(),
+            upgrade_public_key: "".into(),
+            plugin_cache_dir: "".into(),
+            disable_cloud_metadata: false,
         }
     }
 }
@@ -284,6 +407,8 @@ pub struct UprobeProcRegExp {
     pub golang_symbol: String,
     pub golang: String,
     pub openssl: String,
+    pub usdt: String,
+    pub rustls: String,
 }
 
 impl Default for UprobeProcRegExp {
@@ -292,6 +417,8 @@ impl Default for UprobeProcRegExp {
             golang_symbol: String::new(),
             golang: String::new(),
             openssl: String::new(),
+            usdt: String::new(),
+            rustls: String::new(),
         }
     }
 }
@@ -313,12 +440,191 @@ pub struct OsProcRegexp {
     pub action: String, // one of accept or drop
 }
 
+// Group processes into a logical service, all non-empty conditions must match
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct OsProcServiceRule {
+    pub exe_regex: String,
+    pub cmdline_regex: String,
+    pub container_labels: BTreeMap<String, String>,
+    pub service_name: String,
+    pub tags: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RemoteExecOutputFormat {
+    #[default]
+    Text,
+    Binary,
+}
+
+// Values allowed for a param of remote exec commands
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RemoteExecParamRule {
+    // letters, digits, `-` and `_`
+    Identifier,
+    // hostname or ip address
+    Host,
+    Ip,
+    // absolute path without `..`, whitespaces or control characters
+    Path,
+    // non-negative decimal integer
+    Integer,
+    // the whole value matches
+    Regex(String),
+}
+
+// Site-specific command allowed to be executed on request of the controller
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RemoteExecCommand {
+    // executed without shell, `$name` arguments are replaced with params of the request
+    pub cmdline: String,
+    pub params: Vec<String>,
+    pub output_format: RemoteExecOutputFormat,
+    pub desc: String,
+    // allowed to run in the full namespace set of a container
+    pub full_ns: bool,
+    // params not listed are identifiers
+    pub param_rules: BTreeMap<String, RemoteExecParamRule>,
+}
+
+impl RemoteExecCommand {
+    pub fn validate(&self) -> Result<(), String> {
+        let mut args = self.cmdline.split_whitespace();
+        match args.next() {
+            None => return Err("empty cmdline".to_owned()),
+            Some(exe) if exe.starts_with('$') => {
+                return Err(format!("executable \"{}\" is a param", exe))
+            }
+            _ => (),
+        }
+        let mut placeholders = vec![];
+        for arg in args.filter_map(|arg| arg.strip_prefix('$')) {
+            if arg.is_empty() || !arg.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_') {
+                return Err(format!("malformed param \"${}\"", arg));
+            }
+            if placeholders.contains(&arg) {
+                return Err(format!("duplicated param \"${}\"", arg));
+            }
+            placeholders.push(arg);
+        }
+        let mut params = self.params.iter().map(|p| p.as_str()).collect::<Vec<_>>();
+        params.sort_unstable();
+        placeholders.sort_unstable();
+        if params != placeholders {
+            return Err(format!(
+                "params {:?} mismatch with {:?} in cmdline",
+                self.params, placeholders
+            ));
+        }
+        for (name, rule) in self.param_rules.iter() {
+            if !self.params.contains(name) {
+                return Err(format!("rule of undeclared param \"{}\"", name));
+            }
+            if let RemoteExecParamRule::Regex(re) = rule {
+                if let Err(e) = Regex::new(re) {
+                    return Err(format!("malformed regex \"{}\" of \"{}\": {}", re, name, e));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Limits of a remote command, 0 for unlimited
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RemoteExecLimits {
+    // RLIMIT_CPU of spawned process
+    #[serde(with = "humantime_serde")]
+    pub cpu_time: Duration,
+    // RLIMIT_DATA of spawned process in MiB
+    pub memory: u64,
+    // added to nice value of spawned process
+    pub nice: u8,
+    // stdout in MiB, the command is aborted when exceeded
+    pub max_output: u64,
+    // truncate stdout at max_output instead of aborting the command
+    pub truncate_output: bool,
+}
+
+impl Default for RemoteExecLimits {
+    fn default() -> Self {
+        Self {
+            cpu_time: Duration::from_secs(60),
+            memory: 1024,
+            nice: 10,
+            max_output: 256,
+            truncate_output: false,
+        }
+    }
+}
+
+impl RemoteExecLimits {
+    const MAX_NICE: u8 = 19;
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RemoteExecLimitsConfig {
+    pub default: RemoteExecLimits,
+    // keyed by executable of cmdline, `kubectl` for kubernetes commands
+    pub overrides: HashMap<String, RemoteExecLimits>,
+}
+
+impl RemoteExecLimitsConfig {
+    pub fn get(&self, cmdline: &str) -> &RemoteExecLimits {
+        cmdline
+            .split_whitespace()
+            .next()
+            .and_then(|exe| self.overrides.get(exe))
+            .unwrap_or(&self.default)
+    }
+}
+
+// Kubernetes API access of kubectl commands executed in agent, inferred from in-cluster
+// service account or default kubeconfig if all empty
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RemoteExecKubeConfig {
+    pub kubeconfig: String,
+    // overrides the server in kubeconfig or of the cluster
+    pub api_server: String,
+    // bearer token reloaded periodically, overrides credentials of kubeconfig or service account
+    pub token_file: String,
+    // skip verifying the certificate of the API server when any of the above is set
+    pub insecure_skip_tls_verify: bool,
+}
+
+impl RemoteExecKubeConfig {
+    pub fn overridden(&self) -> bool {
+        !self.kubeconfig.is_empty() || !self.api_server.is_empty() || !self.token_file.is_empty()
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Default)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct EbpfKprobePortlist {
     pub port_list: String,
 }
 
+// Regexes matching kernel process names and cgroup paths of processes, and
+// label selectors matching pods of processes
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct EbpfScope {
+    pub process_name_allowlist: Vec<String>,
+    pub process_name_denylist: Vec<String>,
+    pub cgroup_allowlist: Vec<String>,
+    pub cgroup_denylist: Vec<String>,
+    // label selectors of pods, e.g. "app=nginx,tier!=cache"
+    pub pod_allowlist: Vec<String>,
+    pub pod_denylist: Vec<String>,
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct OnCpuProfile {
@@ -326,6 +632,7 @@ pub struct OnCpuProfile {
     pub frequency: u16,
     pub cpu: u16,
     pub regex: String,
+    pub dwarf_regex: String,
 }
 
 impl Default for OnCpuProfile {
@@ -335,6 +642,7 @@ impl Default for OnCpuProfile {
             frequency: 99,
             cpu: 0,
             regex: "^deepflow-.*".to_string(),
+            dwarf_regex: "".to_string(),
         }
     }
 }
@@ -360,6 +668,25 @@ impl Default for OffCpuProfile {
     }
 }
 
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct PprofExport {
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub window: Duration,
+    pub max_stacks: usize,
+}
+
+impl Default for PprofExport {
+    fn default() -> Self {
+        PprofExport {
+            enabled: false,
+            window: Duration::from_secs(60),
+            max_stacks: 65536,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct EbpfYamlConfig {
@@ -370,6 +697,7 @@ pub struct EbpfYamlConfig {
     pub kprobe_blacklist: EbpfKprobePortlist,
     #[serde(rename = "uprobe-process-name-regexs")]
     pub uprobe_proc_regexp: UprobeProcRegExp,
+    pub usdt_probes: Vec<String>,
     pub thread_num: usize,
     pub perf_pages_count: usize,
     pub ring_size: usize,
@@ -385,9 +713,11 @@ pub struct EbpfYamlConfig {
     pub java_symbol_file_refresh_defer_interval: Duration,
     pub on_cpu_profile: OnCpuProfile,
     pub off_cpu_profile: OffCpuProfile,
+    pub pprof_export: PprofExport,
     pub syscall_out_of_order_cache_size: usize,
     pub syscall_out_of_order_reassembly: Vec<String>,
     pub syscall_segmentation_reassembly: Vec<String>,
+    pub scope: EbpfScope,
 }
 
 impl Default for EbpfYamlConfig {
@@ -405,6 +735,7 @@ impl Default for EbpfYamlConfig {
             kprobe_whitelist: EbpfKprobePortlist::default(),
             kprobe_blacklist: EbpfKprobePortlist::default(),
             uprobe_proc_regexp: UprobeProcRegExp::default(),
+            usdt_probes: vec![],
             go_tracing_timeout: 120,
             io_event_collect_mode: 1,
             io_event_minimal_duration: Duration::from_millis(1),
@@ -412,9 +743,11 @@ impl Default for EbpfYamlConfig {
             java_symbol_file_refresh_defer_interval: Duration::from_secs(600),
             on_cpu_profile: OnCpuProfile::default(),
             off_cpu_profile: OffCpuProfile::default(),
+            pprof_export: PprofExport::default(),
             syscall_out_of_order_reassembly: vec![],
             syscall_segmentation_reassembly: vec![],
             syscall_out_of_order_cache_size: 16,
+            scope: EbpfScope::default(),
         }
     }
 }
@@ -426,6 +759,7 @@ pub struct PrometheusExtraConfig {
     pub labels: Vec<String>,
     pub labels_limit: u32,
     pub values_limit: u32,
+    pub forward_urls: Vec<String>,
 }
 
 impl Default for PrometheusExtraConfig {
@@ -435,6 +769,7 @@ impl Default for PrometheusExtraConfig {
             labels: vec![],
             labels_limit: 1024,
             values_limit: 4096,
+            forward_urls: vec![],
         }
     }
 }
@@ -507,6 +842,210 @@ pub struct L7LogBlacklist {
     pub value: String,
 }
 
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NpbTunnelPriority {
+    // underlay tos/traffic class is left unchanged
+    #[default]
+    Default,
+    Low,
+    Normal,
+    High,
+}
+
+impl NpbTunnelPriority {
+    // DSCP of underlay ip header
+    pub fn dscp(&self) -> Option<u8> {
+        match self {
+            Self::Default => None,
+            Self::Low => Some(8),    // CS1, lower than best effort
+            Self::Normal => Some(0), // CS0, best effort
+            Self::High => Some(26),  // AF31
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct NpbTunnelShaping {
+    pub tunnel_ip: String,
+    pub max_bps: u64, // unit: bit per second, 0 means unlimited
+    pub burst_bytes: u64,
+    pub priority: NpbTunnelPriority,
+}
+
+impl Default for NpbTunnelShaping {
+    fn default() -> Self {
+        Self {
+            tunnel_ip: String::new(),
+            max_bps: 0,
+            burst_bytes: 1 << 20,
+            priority: NpbTunnelPriority::Default,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct NpbTunnelBackup {
+    pub tunnel_ip: String,
+    // tried in order when the tunnel endpoint is down
+    pub backup_ips: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NpbProbeProtocol {
+    #[default]
+    Icmp,
+    // heartbeat datagrams to udp-port, endpoints are expected to echo them back
+    Udp,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct NpbHealthCheck {
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    // consecutive lost probes to consider an endpoint down
+    pub threshold: u32,
+    pub probe: NpbProbeProtocol,
+    pub udp_port: u16,
+    // used for tunnels without backups in npb policy
+    pub backups: Vec<NpbTunnelBackup>,
+}
+
+impl Default for NpbHealthCheck {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: Duration::from_secs(1),
+            threshold: 3,
+            probe: NpbProbeProtocol::Icmp,
+            udp_port: 0,
+            backups: vec![],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AfPacketFanoutMode {
+    // both directions of a flow go to the same thread, fragments are reassembled first
+    #[default]
+    FlowHash,
+    // packets are steered by the cpu they arrive on
+    Cpu,
+    RoundRobin,
+    // packets are steered by the recorded nic queue
+    QueueMapping,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct AfPacketFanout {
+    pub interface_regex: String,
+    pub mode: AfPacketFanoutMode,
+    pub thread_count: usize,
+}
+
+impl Default for AfPacketFanout {
+    fn default() -> Self {
+        Self {
+            interface_regex: String::new(),
+            mode: AfPacketFanoutMode::FlowHash,
+            thread_count: 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct AfPacketPromisc {
+    pub interface_regex: String,
+    pub enabled: bool,
+}
+
+impl Default for AfPacketPromisc {
+    fn default() -> Self {
+        Self {
+            interface_regex: String::new(),
+            enabled: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct XdpCaptureScope {
+    pub interface_regex: String,
+    pub cidrs: Vec<String>,
+    pub native_mode: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct TenantExportDestination {
+    pub tenant_id: u32,
+    pub ip: String,
+    pub port: u16,
+}
+
+// Classes of data sharing the uplink to ingester
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignalClass {
+    Metrics,
+    FlowLog,
+    L7Log,
+    Profile,
+    Pcap,
+}
+
+impl SignalClass {
+    pub const COUNT: usize = 5;
+    pub const ALL: [Self; Self::COUNT] = [
+        Self::Metrics,
+        Self::FlowLog,
+        Self::L7Log,
+        Self::Profile,
+        Self::Pcap,
+    ];
+}
+
+impl fmt::Display for SignalClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Metrics => write!(f, "metrics"),
+            Self::FlowLog => write!(f, "flow-log"),
+            Self::L7Log => write!(f, "l7-log"),
+            Self::Profile => write!(f, "profile"),
+            Self::Pcap => write!(f, "pcap"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct SignalQuota {
+    pub signal: SignalClass,
+    // smaller value means higher priority
+    #[serde(default)]
+    pub priority: u8,
+    // 0 means only limited by total-mbps
+    #[serde(default)]
+    pub mbps: u64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SenderBandwidthQuota {
+    // 0 means unlimited
+    pub total_mbps: u64,
+    pub quotas: Vec<SignalQuota>,
+}
+
 #[derive(Clone, Copy, Default, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct OracleParseConfig {
@@ -517,9 +1056,41 @@ pub struct OracleParseConfig {
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default, rename_all = "kebab-case")]
+pub struct ModuleLogLevel {
+    pub module: String,
+    #[serde(with = "LevelDef")]
+    pub level: log::Level,
+    // zero means no expiration
+    #[serde(with = "humantime_serde")]
+    pub duration: Duration,
+}
+
+impl Default for ModuleLogLevel {
+    fn default() -> Self {
+        ModuleLogLevel {
+            module: String::new(),
+            level: log::Level::Info,
+            duration: Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct FeatureFlagRollout {
+    pub name: String,
+    // percentage of agents the flag is enabled on, in [0, 100]
+    pub percentage: u8,
+    // agent groups the flag is always enabled on
+    pub groups: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, rename_all = "kebab-case")]
 pub struct YamlConfig {
     #[serde(with = "LevelDef")]
     pub log_level: log::Level,
+    pub module_log_levels: Vec<ModuleLogLevel>,
     pub profiler: bool,
     #[serde(alias = "afpacket-blocks-enabled")]
     pub af_packet_blocks_enabled: bool,
@@ -534,6 +1105,13 @@ pub struct YamlConfig {
     pub first_path_level: u32,
     pub local_dispatcher_count: usize,
     pub src_interfaces: Vec<String>,
+    pub af_packet_fanout: Vec<AfPacketFanout>,
+    pub af_packet_promisc: Vec<AfPacketPromisc>,
+    pub xdp_capture_scope: XdpCaptureScope,
+    pub tenant_export_destinations: Vec<TenantExportDestination>,
+    pub sender_bandwidth_quota: SenderBandwidthQuota,
+    pub loopback_capture_enabled: bool,
+    pub capture_nic_attribution_enabled: bool,
     pub mirror_traffic_pcp: u16,
     pub vtap_group_id_request: String,
     pub pcap: PcapConfig,
@@ -553,12 +1131,17 @@ pub struct YamlConfig {
     pub collector_sender_queue_count: usize,
     pub toa_sender_queue_size: usize,
     pub toa_lru_cache_size: usize,
+    pub conntrack_nat_enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub conntrack_sync_interval: Duration,
     pub flow_sender_queue_size: usize,
     pub flow_sender_queue_count: usize,
+    pub queue_overflow: QueueOverflowConfig,
     #[serde(rename = "second-flow-extra-delay-second", with = "humantime_serde")]
     pub second_flow_extra_delay: Duration,
     #[serde(with = "humantime_serde")]
     pub packet_delay: Duration,
+    pub pre_aggregation: Vec<PreAggregationRule>,
     pub triple: TripleMapConfig,
     pub kubernetes_poller_type: KubernetesPollerType,
     pub decap_erspan: bool,
@@ -574,6 +1157,14 @@ pub struct YamlConfig {
     #[serde(with = "humantime_serde")]
     pub kubernetes_api_list_interval: Duration,
     pub kubernetes_resources: Vec<KubernetesResourceConfig>,
+    // interfaces matching the regex are not reported in platform sync
+    pub platform_sync_exclude_interface_regex: String,
+    pub docker_discovery_enabled: bool,
+    pub docker_socket_path: String,
+    // containerd and CRI-O sockets are tried if empty
+    pub cri_socket_path: String,
+    // only report host ips in these cidrs if not empty
+    pub platform_sync_host_ip_cidrs: Vec<String>,
     pub external_metrics_sender_queue_size: usize,
     pub ebpf_collector_queue_size: usize,
     pub l7_protocol_inference_max_fail_count: usize,
@@ -583,7 +1174,9 @@ pub struct YamlConfig {
     pub packet_sequence_queue_count: usize, // Enterprise Edition Feature: packet-sequence
     pub packet_sequence_flag: u8,          // Enterprise Edition Feature: packet-sequence
     pub feature_flags: Vec<String>,
+    pub feature_flag_rollouts: Vec<FeatureFlagRollout>,
     pub l7_protocol_enabled: Vec<String>,
+    pub l7_protocol_disabled: Vec<String>,
     pub ebpf: EbpfYamlConfig,
     pub external_agent_http_proxy_compressed: bool,
     pub standalone_data_file_size: u32,
@@ -594,6 +1187,12 @@ pub struct YamlConfig {
     pub l7_protocol_ports: HashMap<String, String>,
     pub l7_log_blacklist: HashMap<String, Vec<L7LogBlacklist>>,
     pub npb_port: u16,
+    #[serde(with = "humantime_serde")]
+    pub npb_dedup_window: Duration,
+    pub npb_dedup_capacity: usize,
+    pub npb_encryption_key: Secret,
+    pub npb_tunnel_shaping: Vec<NpbTunnelShaping>,
+    pub npb_health_check: NpbHealthCheck,
     // process and socket scan config
     pub os_proc_root: String,
     pub os_proc_socket_sync_interval: u32, // for sec
@@ -606,8 +1205,31 @@ pub struct YamlConfig {
     pub os_proc_sync_enabled: bool,
     // sync os socket and proc info only when the process has been tagged.
     pub os_proc_sync_tagged_only: bool,
+    pub os_proc_service_rules: Vec<OsProcServiceRule>,
     #[serde(with = "humantime_serde")]
     pub guard_interval: Duration,
+    #[serde(with = "humantime_serde")]
+    pub remote_exec_command_timeout: Duration,
+    #[serde(with = "humantime_serde")]
+    pub remote_exec_heartbeat_interval: Duration,
+    // stream is reconnected if nothing received from server for this many heartbeats, 0 to disable
+    pub remote_exec_heartbeat_misses: u32,
+    pub remote_exec_commands: Vec<RemoteExecCommand>,
+    // accept commands pushed by the controller
+    pub remote_exec_controller_commands: bool,
+    // hex encoded ed25519 public key to verify signatures of controller commands
+    #[serde(deserialize_with = "reference_de")]
+    pub remote_exec_commands_public_key: String,
+    // empty to disable
+    pub remote_exec_audit_log: String,
+    // per minute, 0 for unlimited
+    pub remote_exec_command_rate: u32,
+    // recent commands kept for LIST_HISTORY, 0 to disable
+    pub remote_exec_history_len: usize,
+    pub remote_exec_limits: RemoteExecLimitsConfig,
+    pub remote_exec_kubernetes: RemoteExecKubeConfig,
+    // files allowed to be fetched, `/*` suffix for files in the directory
+    pub remote_exec_fetch_paths: Vec<String>,
     pub check_core_file_disabled: bool,
     pub memory_trim_disabled: bool,
     pub forward_capacity: usize,
@@ -624,6 +1246,7 @@ pub struct YamlConfig {
     pub external_trace_integration_disabled: bool,
     pub external_metric_integration_disabled: bool,
     pub external_log_integration_disabled: bool,
+    pub external_jaeger_compact_port: u16,
     #[serde(with = "humantime_serde")]
     pub ntp_max_interval: Duration,
     #[serde(with = "humantime_serde")]
@@ -636,6 +1259,7 @@ impl YamlConfig {
     const DEFAULT_DNS_PORTS: &'static str = "53,5353";
     const DEFAULT_TLS_PORTS: &'static str = "443,6443";
     const DEFAULT_ORACLE_PORTS: &'static str = "1521";
+    const MAX_REMOTE_EXEC_HISTORY_LEN: usize = 1000;
 
     pub fn load_from_file<T: AsRef<Path>>(path: T, tap_mode: TapMode) -> Result<Self, io::Error> {
         let contents = fs::read_to_string(path)?;
@@ -664,6 +1288,15 @@ impl YamlConfig {
         if c.pcap.flush_interval < MINUTE {
             c.pcap.flush_interval = MINUTE;
         }
+        if c.pcap.local_storage_file_size == 0 {
+            c.pcap.local_storage_file_size = 16;
+        }
+        if c.pcap.local_storage_file_count == 0 {
+            c.pcap.local_storage_file_count = 8;
+        }
+        if c.pcap.local_storage_capture_duration < Duration::from_secs(1) {
+            c.pcap.local_storage_capture_duration = Duration::from_secs(300);
+        }
 
         if c.flow.flush_interval < Duration::from_secs(1)
             || c.flow.flush_interval > Duration::from_secs(10)
@@ -702,6 +1335,14 @@ impl YamlConfig {
         if c.packet_delay < Duration::from_secs(1) || c.packet_delay > Duration::from_secs(10) {
             c.packet_delay = Duration::from_secs(1);
         }
+        if c.queue_overflow.block_timeout < Duration::from_millis(1)
+            || c.queue_overflow.block_timeout > Duration::from_secs(10)
+        {
+            c.queue_overflow.block_timeout = Duration::from_millis(100);
+        }
+        if c.queue_overflow.backpressure_watermark > 100 {
+            c.queue_overflow.backpressure_watermark = 90;
+        }
         if c.first_path_level < 1 || c.first_path_level > 16 {
             c.first_path_level = 8;
         }
@@ -770,6 +1411,12 @@ impl YamlConfig {
         if c.npb_port == 0 {
             c.npb_port = NPB_DEFAULT_PORT;
         }
+        if c.npb_dedup_window > Duration::from_secs(1) {
+            c.npb_dedup_window = Duration::from_secs(1);
+        }
+        if c.npb_dedup_capacity < 1024 {
+            c.npb_dedup_capacity = 1 << 16;
+        }
         if c.ebpf.thread_num == 0 {
             c.ebpf.thread_num = 1;
         }
@@ -803,6 +1450,14 @@ impl YamlConfig {
             .off_cpu_profile
             .min_block
             .clamp(Duration::from_micros(0), Duration::from_micros(3600000000));
+        if c.ebpf.pprof_export.window < Duration::from_secs(10)
+            || c.ebpf.pprof_export.window > Duration::from_secs(3600)
+        {
+            c.ebpf.pprof_export.window = Duration::from_secs(60);
+        }
+        if !(1024..=1048576).contains(&c.ebpf.pprof_export.max_stacks) {
+            c.ebpf.pprof_export.max_stacks = 65536;
+        }
         if !(8..=1024).contains(&c.ebpf.syscall_out_of_order_cache_size) {
             c.ebpf.syscall_out_of_order_cache_size = 16;
         }
@@ -812,6 +1467,21 @@ impl YamlConfig {
             c.guard_interval = Duration::from_secs(10);
         }
 
+        if c.remote_exec_command_timeout < Duration::from_secs(1)
+            || c.remote_exec_command_timeout > Duration::from_secs(3600)
+        {
+            c.remote_exec_command_timeout = Duration::from_secs(300);
+        }
+
+        if c.remote_exec_heartbeat_interval < Duration::from_secs(1)
+            || c.remote_exec_heartbeat_interval > Duration::from_secs(300)
+        {
+            c.remote_exec_heartbeat_interval = Duration::from_secs(30);
+        }
+        if c.remote_exec_heartbeat_misses > 100 {
+            c.remote_exec_heartbeat_misses = 3;
+        }
+
         if c.kubernetes_api_list_limit < 10 {
             c.kubernetes_api_list_limit = 10;
         }
@@ -859,6 +1529,15 @@ impl YamlConfig {
             }
         }
         c.prometheus_extra_config.labels = valid_labels;
+        c.prometheus_extra_config
+            .forward_urls
+            .retain(|url| match reqwest::Url::parse(url) {
+                Ok(u) if u.scheme() == "http" || u.scheme() == "https" => true,
+                _ => {
+                    warn!("invalid prometheus_extra_config forward url: {:?}", url);
+                    false
+                }
+            });
 
         if c.process_scheduling_priority < -20 || c.process_scheduling_priority > 19 {
             c.process_scheduling_priority = 0;
@@ -866,14 +1545,373 @@ impl YamlConfig {
         if c.local_dispatcher_count == 0 {
             c.local_dispatcher_count = 1;
         }
+        for f in c.af_packet_fanout.iter_mut() {
+            f.thread_count = f.thread_count.max(1);
+        }
 
         Ok(c)
     }
 
     fn validate(&self) -> Result<(), ConfigError> {
+        if self.ntp_min_interval > self.ntp_max_interval {
+            return Err(ConfigError::YamlConfigInvalid(format!(
+                "ntp-min-interval {:?} greater than ntp-max-interval {:?}",
+                self.ntp_min_interval, self.ntp_max_interval
+            )));
+        }
+        for (i, l) in self.module_log_levels.iter().enumerate() {
+            if !LOG_MODULES.contains(&l.module.as_str()) {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "module-log-levels[{}] unknown module \"{}\", supported: {:?}",
+                    i, l.module, LOG_MODULES
+                )));
+            }
+        }
+        for p in self.l7_protocol_disabled.iter() {
+            if L7ProtocolParser::try_from(p.as_str()).is_err() {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "l7-protocol-disabled unknown protocol \"{}\"",
+                    p
+                )));
+            }
+        }
+        for (i, r) in self.feature_flag_rollouts.iter().enumerate() {
+            if r.name.is_empty() {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "feature-flag-rollouts[{}] missing name",
+                    i
+                )));
+            }
+            if r.percentage > 100 {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "feature-flag-rollouts[{}] percentage {} out of range [0, 100]",
+                    i, r.percentage
+                )));
+            }
+        }
+        for (i, r) in self.os_proc_regex.iter().enumerate() {
+            if let Err(e) = Regex::new(&r.match_regex) {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "os-proc-regex[{}] malformed match-regex \"{}\": {}",
+                    i, r.match_regex, e
+                )));
+            }
+        }
+        if let Err(e) = Regex::new(&self.platform_sync_exclude_interface_regex) {
+            return Err(ConfigError::YamlConfigInvalid(format!(
+                "malformed platform-sync-exclude-interface-regex \"{}\": {}",
+                self.platform_sync_exclude_interface_regex, e
+            )));
+        }
+        for cidr in self.platform_sync_host_ip_cidrs.iter() {
+            if let Err(e) = cidr.parse::<IpNet>() {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "malformed platform-sync-host-ip-cidrs \"{}\": {}",
+                    cidr, e
+                )));
+            }
+        }
+        for (i, r) in self.os_proc_service_rules.iter().enumerate() {
+            if r.exe_regex.is_empty() && r.cmdline_regex.is_empty() && r.container_labels.is_empty()
+            {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "os-proc-service-rules[{}] has no match condition",
+                    i
+                )));
+            }
+            for re in [&r.exe_regex, &r.cmdline_regex] {
+                if let Err(e) = Regex::new(re) {
+                    return Err(ConfigError::YamlConfigInvalid(format!(
+                        "os-proc-service-rules[{}] malformed regex \"{}\": {}",
+                        i, re, e
+                    )));
+                }
+            }
+        }
+        for (i, c) in self.remote_exec_commands.iter().enumerate() {
+            if let Err(e) = c.validate() {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "remote-exec-commands[{}] \"{}\": {}",
+                    i, c.cmdline, e
+                )));
+            }
+            if self.remote_exec_commands[..i]
+                .iter()
+                .any(|p| p.cmdline == c.cmdline)
+            {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "remote-exec-commands[{}] duplicated cmdline \"{}\"",
+                    i, c.cmdline
+                )));
+            }
+        }
+        if !self.remote_exec_commands_public_key.is_empty()
+            && self.remote_exec_commands_public_key().is_none()
+        {
+            return Err(ConfigError::YamlConfigInvalid(
+                "remote-exec-commands-public-key must be a hex encoded 32 bytes ed25519 public key"
+                    .to_owned(),
+            ));
+        }
+        if self.remote_exec_history_len > Self::MAX_REMOTE_EXEC_HISTORY_LEN {
+            return Err(ConfigError::YamlConfigInvalid(format!(
+                "remote-exec-history-len {} not in [0, {}]",
+                self.remote_exec_history_len,
+                Self::MAX_REMOTE_EXEC_HISTORY_LEN
+            )));
+        }
+        let limits = &self.remote_exec_limits;
+        for (name, l) in std::iter::once(("default", &limits.default))
+            .chain(limits.overrides.iter().map(|(k, v)| (k.as_str(), v)))
+        {
+            if l.nice > RemoteExecLimits::MAX_NICE {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "remote-exec-limits {} nice {} not in [0, {}]",
+                    name,
+                    l.nice,
+                    RemoteExecLimits::MAX_NICE
+                )));
+            }
+        }
+        for (i, p) in self.remote_exec_fetch_paths.iter().enumerate() {
+            let dir = p.strip_suffix("/*").unwrap_or(p);
+            if !dir.starts_with('/')
+                || dir.contains('*')
+                || dir.split('/').any(|seg| seg == "." || seg == "..")
+            {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "remote-exec-fetch-paths[{}] \"{}\" is not an absolute path or directory with `/*`",
+                    i, p
+                )));
+            }
+        }
+        let kube = &self.remote_exec_kubernetes;
+        if !kube.api_server.is_empty()
+            && !kube.api_server.starts_with("https://")
+            && !kube.api_server.starts_with("http://")
+        {
+            return Err(ConfigError::YamlConfigInvalid(format!(
+                "remote-exec-kubernetes api-server \"{}\" is not a http(s) url",
+                kube.api_server
+            )));
+        }
+        for (i, f) in self.af_packet_fanout.iter().enumerate() {
+            if let Err(e) = Regex::new(&f.interface_regex) {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "af-packet-fanout[{}] malformed interface-regex \"{}\": {}",
+                    i, f.interface_regex, e
+                )));
+            }
+        }
+        for (i, p) in self.af_packet_promisc.iter().enumerate() {
+            if let Err(e) = Regex::new(&p.interface_regex) {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "af-packet-promisc[{}] malformed interface-regex \"{}\": {}",
+                    i, p.interface_regex, e
+                )));
+            }
+        }
+        let scope = &self.xdp_capture_scope;
+        if let Err(e) = Regex::new(&scope.interface_regex) {
+            return Err(ConfigError::YamlConfigInvalid(format!(
+                "xdp-capture-scope malformed interface-regex \"{}\": {}",
+                scope.interface_regex, e
+            )));
+        }
+        if !scope.interface_regex.is_empty() && scope.cidrs.is_empty() {
+            return Err(ConfigError::YamlConfigInvalid(
+                "xdp-capture-scope cidrs is empty".to_string(),
+            ));
+        }
+        if scope.cidrs.len() > OptXdpScope::MAX_CIDRS {
+            return Err(ConfigError::YamlConfigInvalid(format!(
+                "xdp-capture-scope cidrs exceeds {}",
+                OptXdpScope::MAX_CIDRS
+            )));
+        }
+        for cidr in scope.cidrs.iter() {
+            if cidr.parse::<IpNet>().is_err() {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "xdp-capture-scope malformed cidr \"{}\"",
+                    cidr
+                )));
+            }
+        }
+        for (i, d) in self.tenant_export_destinations.iter().enumerate() {
+            if d.tenant_id == 0 {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "tenant-export-destinations[{}] tenant-id must be positive",
+                    i
+                )));
+            }
+            if d.ip.parse::<IpAddr>().is_err() || d.port == 0 {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "tenant-export-destinations[{}] malformed destination {}:{}",
+                    i, d.ip, d.port
+                )));
+            }
+            if self.tenant_export_destinations[..i]
+                .iter()
+                .any(|o| o.tenant_id == d.tenant_id)
+            {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "tenant-export-destinations[{}] duplicated tenant-id {}",
+                    i, d.tenant_id
+                )));
+            }
+        }
+        let quotas = &self.sender_bandwidth_quota.quotas;
+        for (i, q) in quotas.iter().enumerate() {
+            if quotas[..i].iter().any(|o| o.signal == q.signal) {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "sender-bandwidth-quota.quotas[{}] duplicated signal {}",
+                    i, q.signal
+                )));
+            }
+        }
+        for (i, r) in self.pre_aggregation.iter().enumerate() {
+            if !r.server_ports.is_empty()
+                && parse_u16_range_list_to_bitmap(&r.server_ports, true).is_none()
+            {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "pre-aggregation[{}] malformed server-ports \"{}\"",
+                    i, r.server_ports
+                )));
+            }
+        }
+        for (i, s) in self.npb_tunnel_shaping.iter().enumerate() {
+            if s.tunnel_ip.parse::<IpAddr>().is_err() {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "npb-tunnel-shaping[{}] malformed tunnel-ip \"{}\"",
+                    i, s.tunnel_ip
+                )));
+            }
+        }
+        let health_check = &self.npb_health_check;
+        if health_check.interval < Duration::from_millis(100)
+            || health_check.interval > Duration::from_secs(60)
+        {
+            return Err(ConfigError::YamlConfigInvalid(format!(
+                "npb-health-check interval {:?} not in [100ms, 60s]",
+                health_check.interval
+            )));
+        }
+        if health_check.threshold == 0 {
+            return Err(ConfigError::YamlConfigInvalid(
+                "npb-health-check threshold must be positive".to_owned(),
+            ));
+        }
+        if health_check.probe == NpbProbeProtocol::Udp && health_check.udp_port == 0 {
+            return Err(ConfigError::YamlConfigInvalid(
+                "npb-health-check udp-port must be set for udp probe".to_owned(),
+            ));
+        }
+        for (i, b) in health_check.backups.iter().enumerate() {
+            let Ok(tunnel_ip) = b.tunnel_ip.parse::<IpAddr>() else {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "npb-health-check backups[{}] malformed tunnel-ip \"{}\"",
+                    i, b.tunnel_ip
+                )));
+            };
+            for ip in b.backup_ips.iter() {
+                match ip.parse::<IpAddr>() {
+                    Ok(ip) if ip.is_ipv6() == tunnel_ip.is_ipv6() => (),
+                    _ => {
+                        return Err(ConfigError::YamlConfigInvalid(format!(
+                            "npb-health-check backups[{}] backup-ip \"{}\" is malformed or not in the same address family as tunnel-ip",
+                            i, ip
+                        )))
+                    }
+                }
+            }
+        }
+        let accuracy = self.flow.rrt_sketch_relative_accuracy;
+        if !(0.005..=0.1).contains(&accuracy) {
+            return Err(ConfigError::YamlConfigInvalid(format!(
+                "rrt-sketch-relative-accuracy {} not in [0.005, 0.1]",
+                accuracy
+            )));
+        }
+        Ok(())
+    }
+
+    // malformed port ranges are silently ignored when parsing, check them strictly here
+    pub fn validate_l7_protocol_ports(&self) -> Result<(), ConfigError> {
+        for (protocol, ports) in self.l7_protocol_ports.iter() {
+            if parse_u16_range_list_to_bitmap(ports, true).is_none() {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "l7-protocol-ports {} has malformed port range \"{}\"",
+                    protocol, ports
+                )));
+            }
+        }
         Ok(())
     }
 
+    // Protocols in l7-protocol-enabled but not in l7-protocol-disabled
+    pub fn get_l7_protocol_enabled_bitmap(&self) -> L7ProtocolBitmap {
+        let mut bitmap = L7ProtocolBitmap::from(&self.l7_protocol_enabled);
+        for p in self.l7_protocol_disabled.iter() {
+            if let Ok(p) = L7ProtocolParser::try_from(p.as_str()) {
+                bitmap.set_disabled(p.protocol());
+            }
+        }
+        bitmap
+    }
+
+    pub fn get_active_l7_protocols(&self) -> Vec<String> {
+        let bitmap = self.get_l7_protocol_enabled_bitmap();
+        get_all_protocol()
+            .into_iter()
+            .filter(|p| bitmap.is_enabled(p.protocol()))
+            .map(|p| p.as_str().to_owned())
+            .collect()
+    }
+
+    // The first fanout entry matching the interface name
+    pub fn get_af_packet_fanout(&self, interface: &str) -> Option<&AfPacketFanout> {
+        self.af_packet_fanout.iter().find(|f| {
+            Regex::new(&f.interface_regex)
+                .map(|re| re.is_match(interface))
+                .unwrap_or(false)
+        })
+    }
+
+    // Promiscuous mode of the interface by the first matching entry, None if not configured
+    pub fn get_af_packet_promisc(&self, interface: &str) -> Option<bool> {
+        self.af_packet_promisc
+            .iter()
+            .find(|p| {
+                Regex::new(&p.interface_regex)
+                    .map(|re| re.is_match(interface))
+                    .unwrap_or(false)
+            })
+            .map(|p| p.enabled)
+    }
+
+    // Capture scope of the interface, None if xdp pre-filter is not configured for it
+    pub fn get_xdp_capture_scope(&self, interface: &str) -> Option<OptXdpScope> {
+        let scope = &self.xdp_capture_scope;
+        if scope.interface_regex.is_empty()
+            || !Regex::new(&scope.interface_regex)
+                .map(|re| re.is_match(interface))
+                .unwrap_or(false)
+        {
+            return None;
+        }
+        Some(OptXdpScope {
+            cidrs: scope.cidrs.iter().filter_map(|c| c.parse().ok()).collect(),
+            native_mode: scope.native_mode,
+        })
+    }
+
+    // None if not configured or not a valid 32 bytes key
+    pub fn remote_exec_commands_public_key(&self) -> Option<Vec<u8>> {
+        hex::decode(&self.remote_exec_commands_public_key)
+            .ok()
+            .filter(|k| k.len() == 32)
+    }
+
     pub fn get_protocol_port(&self) -> HashMap<String, String> {
         let mut new = self.l7_protocol_ports.clone();
 
@@ -925,6 +1963,7 @@ impl Default for YamlConfig {
     fn default() -> Self {
         Self {
             log_level: log::Level::Info,
+            module_log_levels: vec![],
             profiler: false,
             af_packet_blocks_enabled: false,
             af_packet_blocks: 128,
@@ -936,6 +1975,13 @@ impl Default for YamlConfig {
             fast_path_map_size: 1 << 14,
             first_path_level: 0,
             src_interfaces: vec![],
+            af_packet_fanout: vec![],
+            af_packet_promisc: vec![],
+            xdp_capture_scope: Default::default(),
+            tenant_export_destinations: vec![],
+            sender_bandwidth_quota: Default::default(),
+            loopback_capture_enabled: false,
+            capture_nic_attribution_enabled: false,
             mirror_traffic_pcp: 0,
             vtap_group_id_request: "".into(),
             pcap: Default::default(),
@@ -959,11 +2005,15 @@ impl Default for YamlConfig {
             collector_sender_queue_count: 1,
             toa_sender_queue_size: 1 << 16,
             toa_lru_cache_size: 1 << 16,
+            conntrack_nat_enabled: false,
+            conntrack_sync_interval: Duration::from_secs(5),
             // default size changes according to tap_mode
             flow_sender_queue_size: 1 << 16,
             flow_sender_queue_count: 1,
+            queue_overflow: QueueOverflowConfig::default(),
             second_flow_extra_delay: Duration::from_secs(0),
             packet_delay: Duration::from_secs(1),
+            pre_aggregation: vec![],
             triple: Default::default(),
             kubernetes_poller_type: KubernetesPollerType::Adaptive,
             decap_erspan: false,
@@ -977,6 +2027,11 @@ impl Default for YamlConfig {
             kubernetes_api_list_limit: 1000,
             kubernetes_api_list_interval: Duration::from_secs(600),
             kubernetes_resources: vec![],
+            platform_sync_exclude_interface_regex: "".into(),
+            docker_discovery_enabled: false,
+            docker_socket_path: "/var/run/docker.sock".into(),
+            cri_socket_path: "".into(),
+            platform_sync_host_ip_cidrs: vec![],
             external_metrics_sender_queue_size: 1 << 12,
             l7_protocol_inference_max_fail_count: L7_PROTOCOL_INFERENCE_MAX_FAIL_COUNT,
             l7_protocol_inference_ttl: L7_PROTOCOL_INFERENCE_TTL,
@@ -985,6 +2040,7 @@ impl Default for YamlConfig {
             packet_sequence_queue_count: 1,  // Enterprise Edition Feature: packet-sequence
             packet_sequence_flag: 0,         // Enterprise Edition Feature: packet-sequence
             feature_flags: vec![],
+            feature_flag_rollouts: vec![],
             l7_protocol_enabled: {
                 let mut protos = vec![];
                 for i in get_all_protocol() {
@@ -994,6 +2050,7 @@ impl Default for YamlConfig {
                 }
                 protos
             },
+            l7_protocol_disabled: vec![],
             external_agent_http_proxy_compressed: false,
             standalone_data_file_size: 200,
             standalone_data_file_dir: Path::new(DEFAULT_LOG_FILE)
@@ -1011,6 +2068,11 @@ impl Default for YamlConfig {
             l7_log_blacklist: HashMap::new(),
             ebpf: EbpfYamlConfig::default(),
             npb_port: NPB_DEFAULT_PORT,
+            npb_dedup_window: Duration::from_millis(10),
+            npb_dedup_capacity: 1 << 16,
+            npb_encryption_key: Secret::default(),
+            npb_tunnel_shaping: vec![],
+            npb_health_check: NpbHealthCheck::default(),
             os_proc_root: "/proc".into(),
             os_proc_socket_sync_interval: 10,
             os_proc_socket_min_lifetime: 3,
@@ -1024,7 +2086,25 @@ impl Default for YamlConfig {
             os_app_tag_exec: vec![],
             os_proc_sync_enabled: false,
             os_proc_sync_tagged_only: false,
+            os_proc_service_rules: vec![],
             guard_interval: Duration::from_secs(10),
+            remote_exec_command_timeout: Duration::from_secs(300),
+            remote_exec_heartbeat_interval: Duration::from_secs(30),
+            remote_exec_heartbeat_misses: 3,
+            remote_exec_commands: vec![],
+            remote_exec_controller_commands: false,
+            remote_exec_commands_public_key: "".to_string(),
+            remote_exec_audit_log: "/var/log/deepflow-agent/remote-exec-audit.log".to_string(),
+            remote_exec_command_rate: 60,
+            remote_exec_history_len: 100,
+            remote_exec_limits: RemoteExecLimitsConfig::default(),
+            remote_exec_kubernetes: RemoteExecKubeConfig::default(),
+            remote_exec_fetch_paths: vec![
+                "/proc/net/*".to_string(),
+                "/etc/resolv.conf".to_string(),
+                "/etc/hosts".to_string(),
+                "/var/log/deepflow-agent/*".to_string(),
+            ],
             check_core_file_disabled: false,
             memory_trim_disabled: false,
             fast_path_disabled: false,
@@ -1038,6 +2118,7 @@ impl Default for YamlConfig {
             external_trace_integration_disabled: false,
             external_metric_integration_disabled: false,
             external_log_integration_disabled: false,
+            external_jaeger_compact_port: 0,
             ntp_max_interval: Duration::from_secs(300),
             ntp_min_interval: Duration::from_secs(10),
             l7_protocol_advanced_features: L7ProtocolAdvancedFeatures::default(),
@@ -1093,6 +2174,46 @@ impl Default for PortConfig {
     }
 }
 
+// String which should not be printed in logs, such as keys and passwords, references
+// are resolved in local config files
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        reference_de(deserializer).map(Self)
+    }
+}
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<String> for Secret {
+    fn from(s: String) -> Self {
+        Self(s)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            write!(f, "\"\"")
+        } else {
+            write!(f, "\"******\"")
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct PcapConfig {
@@ -1101,6 +2222,13 @@ pub struct PcapConfig {
     pub flush_interval: Duration,
     pub buffer_size: u64,
     pub flow_buffer_size: u32,
+    // local storage of packets hitting pcap policies
+    pub local_storage_enabled: bool,
+    pub local_storage_path: String,
+    pub local_storage_file_size: u32, // unit: MB
+    pub local_storage_file_count: u32,
+    #[serde(with = "humantime_serde")]
+    pub local_storage_capture_duration: Duration,
 }
 
 impl Default for PcapConfig {
@@ -1110,11 +2238,26 @@ impl Default for PcapConfig {
             flush_interval: Duration::from_secs(60),
             buffer_size: 96 << 10,      // 96K
             flow_buffer_size: 64 << 10, // 64K
+            local_storage_enabled: false,
+            local_storage_path: "/var/log/deepflow-agent/pcap".to_string(),
+            local_storage_file_size: 16,
+            local_storage_file_count: 8,
+            local_storage_capture_duration: Duration::from_secs(300),
         }
     }
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FlowEvictionPolicy {
+    // new flows are dropped when flow map is full, flows are only removed by timeout
+    #[default]
+    TimeoutOnly,
+    // the least recently active flow is evicted to make room for new flows
+    Lru,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct FlowGeneratorConfig {
     // tcp timeout config
@@ -1126,11 +2269,19 @@ pub struct FlowGeneratorConfig {
     pub others_timeout: Duration,
     #[serde(with = "humantime_serde")]
     pub opening_rst_timeout: Duration,
+    // udp and other protocols timeout config
+    #[serde(with = "humantime_serde")]
+    pub udp_timeout: Duration,
+    #[serde(with = "humantime_serde")]
+    pub other_protocol_timeout: Duration,
 
     #[serde(rename = "flow-slots-size")]
     pub hash_slots: u32,
     #[serde(rename = "flow-count-limit")]
     pub capacity: u32,
+    // 0 means no limit other than flow-count-limit
+    pub memory_budget_mb: u32,
+    pub eviction_policy: FlowEvictionPolicy,
     #[serde(with = "humantime_serde")]
     pub flush_interval: Duration,
     #[serde(rename = "flow-aggr-queue-size")]
@@ -1140,6 +2291,15 @@ pub struct FlowGeneratorConfig {
     pub ignore_tor_mac: bool,
     pub ignore_l2_end: bool,
     pub ignore_idc_vlan: bool,
+
+    pub tcp_histogram_enabled: bool,
+    pub rrt_sketch_enabled: bool,
+    pub rrt_sketch_relative_accuracy: f64,
+
+    pub capture_point_dedup_enabled: bool,
+    pub capture_point_priority: Vec<String>,
+
+    pub tls_handshake_tags_enabled: bool,
 }
 
 impl Default for FlowGeneratorConfig {
@@ -1149,9 +2309,13 @@ impl Default for FlowGeneratorConfig {
             closing_rst_timeout: Duration::from_secs(35),
             others_timeout: Duration::from_secs(5),
             opening_rst_timeout: Duration::from_secs(1),
+            udp_timeout: Duration::from_secs(5),
+            other_protocol_timeout: Duration::from_secs(35),
 
             hash_slots: 131072,
             capacity: 65535,
+            memory_budget_mb: 0,
+            eviction_policy: FlowEvictionPolicy::TimeoutOnly,
             flush_interval: Duration::from_secs(1),
             aggr_queue_size: 65535,
             memory_pool_size: 65536,
@@ -1159,6 +2323,27 @@ impl Default for FlowGeneratorConfig {
             ignore_tor_mac: false,
             ignore_l2_end: false,
             ignore_idc_vlan: false,
+
+            tcp_histogram_enabled: false,
+            rrt_sketch_enabled: false,
+            rrt_sketch_relative_accuracy: 0.01,
+
+            capture_point_dedup_enabled: false,
+            capture_point_priority: vec![
+                "c".into(),
+                "s".into(),
+                "local".into(),
+                "c-nd".into(),
+                "s-nd".into(),
+                "c-hv".into(),
+                "s-hv".into(),
+                "c-gw-hv".into(),
+                "s-gw-hv".into(),
+                "c-gw".into(),
+                "s-gw".into(),
+            ],
+
+            tls_handshake_tags_enabled: true,
         }
     }
 }
@@ -1196,6 +2381,71 @@ impl Default for TripleMapConfig {
     }
 }
 
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PreAggregationDimension {
+    ClientIp,
+    ClientGpid,
+    ServerGpid,
+    ServerPort,
+    Mac,
+    Endpoint,
+}
+
+// Dimensions aggregated away in metrics of flows to the matching server ports
+#[derive(Clone, Default, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct PreAggregationRule {
+    // port range list like "80,8000-8080", empty matches all ports
+    pub server_ports: String,
+    pub drop_dimensions: Vec<PreAggregationDimension>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum QueueDropPolicy {
+    DropOld,
+    DropNew,
+    Block,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct QueueOverflowConfig {
+    pub l4_flow_log: QueueDropPolicy,
+    pub l7_flow_log: QueueDropPolicy,
+    pub metrics: QueueDropPolicy,
+    #[serde(with = "humantime_serde")]
+    pub block_timeout: Duration,
+    // percentage of the sender queue usage, 0 disables backpressure
+    pub backpressure_watermark: u8,
+    #[serde(with = "humantime_serde")]
+    pub slow_request_threshold: Duration,
+}
+
+impl Default for QueueOverflowConfig {
+    fn default() -> Self {
+        QueueOverflowConfig {
+            l4_flow_log: QueueDropPolicy::DropOld,
+            l7_flow_log: QueueDropPolicy::DropOld,
+            metrics: QueueDropPolicy::DropOld,
+            block_timeout: Duration::from_millis(100),
+            backpressure_watermark: 90,
+            slow_request_threshold: Duration::from_secs(1),
+        }
+    }
+}
+
+impl QueueOverflowConfig {
+    pub fn drop_policy(&self, policy: QueueDropPolicy) -> DropPolicy {
+        match policy {
+            QueueDropPolicy::DropOld => DropPolicy::DropOld,
+            QueueDropPolicy::DropNew => DropPolicy::DropNew,
+            QueueDropPolicy::Block => DropPolicy::Block(self.block_timeout),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
 #[serde(rename_all = "kebab-case")]
 pub enum KubernetesPollerType {
@@ -1291,6 +2541,10 @@ pub struct RuntimeConfig {
     pub inactive_server_port_enabled: bool,
     #[serde(deserialize_with = "bool_from_int")]
     pub inactive_ip_enabled: bool,
+    #[serde(skip)]
+    pub capture_paused: bool,
+    #[serde(skip)]
+    pub ebpf_paused: bool,
     #[serde(rename = "vm_xml_path")]
     pub libvirt_xml_path: String,
     pub l7_log_packet_size: u32,
@@ -1341,8 +2595,8 @@ impl RuntimeConfig {
             // parsing empty string leads to EOF error
             Self::standalone_default()
         } else {
-            serde_yaml::from_str(contents.as_str())
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?
+            from_yaml_with_references(contents.as_str())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
         };
 
         // reset below switch in standalone mode
@@ -1406,6 +2660,8 @@ impl RuntimeConfig {
             capture_packet_size: 65535,
             inactive_server_port_enabled: true,
             inactive_ip_enabled: true,
+            capture_paused: false,
+            ebpf_paused: false,
             libvirt_xml_path: "/etc/libvirt/qemu/".into(),
             l7_log_packet_size: 1024,
             l4_log_collect_nps_threshold: 10000,
@@ -1613,6 +2869,8 @@ impl TryFrom<trident::Config> for RuntimeConfig {
             capture_packet_size: conf.capture_packet_size(),
             inactive_server_port_enabled: conf.inactive_server_port_enabled(),
             inactive_ip_enabled: conf.inactive_ip_enabled(),
+            capture_paused: conf.capture_paused(),
+            ebpf_paused: conf.ebpf_paused(),
             libvirt_xml_path: conf.libvirt_xml_path().to_owned(),
             l7_log_packet_size: conf.l7_log_packet_size(),
             l4_log_collect_nps_threshold: conf.l4_log_collect_nps_threshold(),
@@ -1829,6 +3087,80 @@ where
         .collect()
 }
 
+const FILE_REFERENCE_PREFIX: &str = "file:/";
+
+thread_local! {
+    // references are only resolved in local config files, not in config from controller
+    static RESOLVE_REFERENCES: Cell<bool> = Cell::new(false);
+}
+
+// Parses local config files with references resolved in fields deserialized with
+// `reference_de`, `references_de` or as `Secret`, so that secrets can be provided by
+// environment or mounted files instead of plaintext yaml
+fn from_yaml_with_references<T: DeserializeOwned>(contents: &str) -> Result<T, String> {
+    RESOLVE_REFERENCES.with(|r| r.set(true));
+    let result = serde_yaml::from_str(contents).map_err(|e| e.to_string());
+    RESOLVE_REFERENCES.with(|r| r.set(false));
+    result
+}
+
+// Resolves references in a parsed string value
+// - `file:/path/to/secret` is replaced with the file content without trailing newlines
+// - `${ENV_VAR}` is replaced with the variable, `$${` escapes a literal `${`
+fn resolve_references(value: String) -> Result<String, String> {
+    if !RESOLVE_REFERENCES.with(|r| r.get()) {
+        return Ok(value);
+    }
+    if value.starts_with(FILE_REFERENCE_PREFIX) {
+        let path = &value["file:".len()..];
+        let content =
+            fs::read_to_string(path).map_err(|e| format!("reference file:{}: {}", path, e))?;
+        return Ok(content.trim_end_matches(&['\r', '\n'][..]).to_owned());
+    }
+    if !value.contains("${") {
+        return Ok(value);
+    }
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value.as_str();
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            result.push_str(&rest[..start - 1]);
+            result.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        result.push_str(&rest[..start]);
+        let Some(len) = rest[start + 2..].find('}') else {
+            return Err(format!("unclosed reference in \"{}\"", value));
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        match env::var(name) {
+            Ok(v) => result.push_str(&v),
+            Err(e) => return Err(format!("reference ${{{}}}: {}", name, e)),
+        }
+        rest = &rest[start + 3 + len..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn reference_de<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    resolve_references(String::deserialize(deserializer)?).map_err(de::Error::custom)
+}
+
+fn references_de<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|s| resolve_references(s).map_err(de::Error::custom))
+        .collect()
+}
+
 // resolve domain name (without port) to ip address
 fn resolve_domain(addr: &str) -> Option<String> {
     match format!("{}:1", addr).to_socket_addrs() {
@@ -1855,4 +3187,384 @@ mod tests {
         assert_eq!(c.controller_ips.len(), 1);
         assert_eq!(&c.controller_ips[0], "127.0.0.1");
     }
+
+    #[test]
+    fn config_references() {
+        env::set_var("DEEPFLOW_TEST_CONTROLLER_IP", "127.0.0.1");
+        env::set_var(
+            "DEEPFLOW_TEST_CERT_PREFIX",
+            "/etc/ca\nlog-file: /tmp/injected.log",
+        );
+        let c = Config::load(
+            "controller-ips:\n  - ${DEEPFLOW_TEST_CONTROLLER_IP}\ncontroller-cert-file-prefix: ${DEEPFLOW_TEST_CERT_PREFIX}\nupgrade-public-key: $${DEEPFLOW_TEST_CONTROLLER_IP}\nlog-file: /tmp/${DEEPFLOW_TEST_NOT_SET}.log\n",
+        )
+        .unwrap();
+        assert_eq!(c.controller_ips, vec!["127.0.0.1"]);
+        // values never change the yaml structure
+        assert_eq!(
+            c.controller_cert_file_prefix,
+            "/etc/ca\nlog-file: /tmp/injected.log"
+        );
+        assert_eq!(c.upgrade_public_key, "${DEEPFLOW_TEST_CONTROLLER_IP}");
+        // only opted-in fields are resolved
+        assert_eq!(c.log_file, "/tmp/${DEEPFLOW_TEST_NOT_SET}.log");
+        assert!(Config::load("controller-ips:\n  - ${DEEPFLOW_TEST_NOT_SET}\n").is_err());
+        assert!(Config::load("controller-ips:\n  - ${DEEPFLOW_TEST_CONTROLLER_IP\n").is_err());
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"127.0.0.2\n").unwrap();
+        let c = Config::load(format!(
+            "controller-ips:\n  - file:{}\n",
+            file.path().display()
+        ))
+        .unwrap();
+        assert_eq!(c.controller_ips, vec!["127.0.0.2"]);
+        assert!(Config::load("controller-ips:\n  - file:/nonexistent/secret\n").is_err());
+        let c = Config::load("controller-ips:\n  - 127.0.0.1\nlog-file: file:/nonexistent\n");
+        assert_eq!(c.unwrap().log_file, "file:/nonexistent");
+
+        // config from controller is never resolved
+        let c = YamlConfig::load(
+            "npb-encryption-key: file:/nonexistent\nos-proc-regex:\n- match-regex: python\n  rewrite-name: ${PROC_NAME}-%HOSTNAME%\n",
+            TapMode::Local,
+        )
+        .unwrap();
+        assert_eq!(c.npb_encryption_key.expose(), "file:/nonexistent");
+        assert_eq!(c.os_proc_regex[0].rewrite_name, "${PROC_NAME}-%HOSTNAME%");
+    }
+
+    #[test]
+    fn dump_config() {
+        let c =
+            Config::load("controller-ips:\n  - 127.0.0.1\nagent-unique-identifier: ip\n").unwrap();
+        assert_eq!(Config::load(serde_yaml::to_string(&c).unwrap()).unwrap(), c);
+    }
+
+    #[test]
+    fn validate_config() {
+        let c = Config::load("controller-ips:\n  - 127.0.0.1\n").unwrap();
+        assert!(c.validate().is_ok());
+        let c = Config::load("controller-port: 0\ncontroller-ips:\n  - 127.0.0.1\n").unwrap();
+        assert!(c.validate().is_err());
+        assert!(Config::default().validate().is_err());
+        let c = Config::load("upgrade-public-key: abcd\ncontroller-ips:\n  - 127.0.0.1\n").unwrap();
+        assert!(c.validate().is_err());
+        let pins = "controller-cert-pins: [\"sha256//wIbOxz5qYuHsxuJs2efxqHlpWsdAKjkdjveNyGz32wc=\"]\ncontroller-ips:\n  - 127.0.0.1\n";
+        assert!(Config::load(pins).unwrap().validate().is_err());
+        let c = Config::load(&format!("controller-cert-file-prefix: /etc/ca\n{}", pins)).unwrap();
+        assert!(c.validate().is_ok());
+        let c = Config::load(
+            "controller-cert-file-prefix: /etc/ca\ncontroller-cert-pins: [\"sha256//AAAA\"]\ncontroller-ips:\n  - 127.0.0.1\n",
+        )
+        .unwrap();
+        assert!(c.validate().is_err());
+
+        let c = YamlConfig::load("l7-protocol-ports:\n  HTTP: \"80,x\"\n", TapMode::Local).unwrap();
+        assert!(c.validate_l7_protocol_ports().is_err());
+        assert!(YamlConfig::load("l7-protocol-disabled: [FOO]\n", TapMode::Local).is_err());
+        let c = YamlConfig::load(
+            "l7-protocol-enabled: [HTTP, DNS, MySQL]\nl7-protocol-disabled: [DNS, Redis]\n",
+            TapMode::Local,
+        )
+        .unwrap();
+        assert_eq!(c.get_active_l7_protocols(), vec!["HTTP", "MySQL"]);
+        assert!(YamlConfig::load(
+            "pre-aggregation:\n- server-ports: \"80-\"\n  drop-dimensions: [client-ip]\n",
+            TapMode::Local
+        )
+        .is_err());
+        assert!(YamlConfig::load(
+            "ntp-min-interval: 10m\nntp-max-interval: 1m\n",
+            TapMode::Local
+        )
+        .is_err());
+        assert!(YamlConfig::load(
+            "af-packet-fanout:\n- interface-regex: \"(\"\n",
+            TapMode::Local
+        )
+        .is_err());
+        assert!(YamlConfig::load(
+            "platform-sync-host-ip-cidrs: [10.0.0.0/33]\n",
+            TapMode::Local
+        )
+        .is_err());
+        assert!(YamlConfig::load(
+            "os-proc-service-rules:\n- service-name: foo\n",
+            TapMode::Local
+        )
+        .is_err());
+        assert!(YamlConfig::load(
+            "os-proc-service-rules:\n- cmdline-regex: \"(\"\n  service-name: foo\n",
+            TapMode::Local
+        )
+        .is_err());
+        assert!(YamlConfig::load(
+            "module-log-levels:\n- module: platform\n  level: debug\n",
+            TapMode::Local
+        )
+        .is_err());
+        let c = YamlConfig::load(
+            "module-log-levels:\n- module: rpc\n  level: debug\n  duration: 10m\n",
+            TapMode::Local,
+        )
+        .unwrap();
+        assert_eq!(c.module_log_levels[0].level, log::Level::Debug);
+        assert_eq!(c.module_log_levels[0].duration, Duration::from_secs(600));
+        assert!(YamlConfig::load(
+            "feature-flag-rollouts:\n- name: af_xdp\n  percentage: 101\n",
+            TapMode::Local
+        )
+        .is_err());
+        assert!(
+            YamlConfig::load("feature-flag-rollouts:\n- percentage: 10\n", TapMode::Local).is_err()
+        );
+    }
+
+    #[test]
+    fn remote_exec_commands() {
+        let c = YamlConfig::load(
+            "remote-exec-commands:\n- cmdline: ethtool -S $dev\n  params: [dev]\n  desc: ethtool\n",
+            TapMode::Local,
+        )
+        .unwrap();
+        assert_eq!(c.remote_exec_commands[0].params, vec!["dev"]);
+        assert_eq!(
+            c.remote_exec_commands[0].output_format,
+            RemoteExecOutputFormat::Text
+        );
+        let c = YamlConfig::load(
+            "remote-exec-commands:\n- cmdline: tail -n $lines $file\n  params: [lines, file]\n  param-rules:\n    lines: integer\n    file:\n      regex: ^/var/log/\n",
+            TapMode::Local,
+        )
+        .unwrap();
+        let rules = &c.remote_exec_commands[0].param_rules;
+        assert_eq!(rules["lines"], RemoteExecParamRule::Integer);
+        assert_eq!(
+            rules["file"],
+            RemoteExecParamRule::Regex("^/var/log/".to_owned())
+        );
+        for commands in [
+            "- cmdline: \"\"\n",
+            "- cmdline: $exe -S\n  params: [exe]\n",
+            "- cmdline: ethtool -S $dev\n",
+            "- cmdline: ethtool -S $dev\n  params: [dev, iface]\n",
+            "- cmdline: ip link show $dev.1\n  params: [dev.1]\n",
+            "- cmdline: uptime\n- cmdline: uptime\n",
+            "- cmdline: uptime\n  param-rules:\n    dev: ip\n",
+            "- cmdline: tail $file\n  params: [file]\n  param-rules:\n    file:\n      regex: \"(\"\n",
+        ] {
+            assert!(
+                YamlConfig::load(
+                    format!("remote-exec-commands:\n{}", commands),
+                    TapMode::Local
+                )
+                .is_err(),
+                "{}",
+                commands
+            );
+        }
+    }
+
+    #[test]
+    fn remote_exec_limits() {
+        let c = YamlConfig::load(
+            "remote-exec-limits:\n  default:\n    cpu-time: 30s\n  overrides:\n    tcpdump:\n      max-output: 1024\n",
+            TapMode::Local,
+        )
+        .unwrap();
+        let limits = &c.remote_exec_limits;
+        assert_eq!(limits.get("ps auxf").cpu_time, Duration::from_secs(30));
+        assert_eq!(limits.get("ps auxf").max_output, 256);
+        // fields not set in overrides are defaults
+        let l = limits.get("tcpdump -i $ifname -c $count -w -");
+        assert_eq!(l.max_output, 1024);
+        assert_eq!(l.cpu_time, Duration::from_secs(60));
+        assert!(!l.truncate_output);
+        assert!(YamlConfig::load(
+            "remote-exec-limits:\n  overrides:\n    ps:\n      nice: 20\n",
+            TapMode::Local
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn remote_exec_fetch_paths() {
+        let c = YamlConfig::load("", TapMode::Local).unwrap();
+        assert!(c
+            .remote_exec_fetch_paths
+            .contains(&"/etc/resolv.conf".to_string()));
+        for paths in ["[etc/hosts]", "[/proc/*/net]", "[/var/log/../../etc/*]"] {
+            assert!(
+                YamlConfig::load(
+                    format!("remote-exec-fetch-paths: {}\n", paths),
+                    TapMode::Local
+                )
+                .is_err(),
+                "{}",
+                paths
+            );
+        }
+    }
+
+    #[test]
+    fn remote_exec_commands_public_key() {
+        let c = YamlConfig::load("", TapMode::Local).unwrap();
+        assert!(c.remote_exec_commands_public_key().is_none());
+        let key = "ab".repeat(32);
+        let c = YamlConfig::load(
+            format!("remote-exec-commands-public-key: {}\n", key),
+            TapMode::Local,
+        )
+        .unwrap();
+        assert_eq!(c.remote_exec_commands_public_key(), Some(vec![0xab; 32]));
+        for key in ["abcd", "xy"] {
+            assert!(YamlConfig::load(
+                format!("remote-exec-commands-public-key: {}\n", key),
+                TapMode::Local
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn remote_exec_heartbeat() {
+        let c = YamlConfig::load("", TapMode::Local).unwrap();
+        assert_eq!(c.remote_exec_heartbeat_interval, Duration::from_secs(30));
+        assert_eq!(c.remote_exec_heartbeat_misses, 3);
+        let c = YamlConfig::load(
+            "remote-exec-heartbeat-interval: 10s\nremote-exec-heartbeat-misses: 0\n",
+            TapMode::Local,
+        )
+        .unwrap();
+        assert_eq!(c.remote_exec_heartbeat_interval, Duration::from_secs(10));
+        assert_eq!(c.remote_exec_heartbeat_misses, 0);
+        // out of range values are reset to default
+        let c = YamlConfig::load(
+            "remote-exec-heartbeat-interval: 1h\nremote-exec-heartbeat-misses: 1000\n",
+            TapMode::Local,
+        )
+        .unwrap();
+        assert_eq!(c.remote_exec_heartbeat_interval, Duration::from_secs(30));
+        assert_eq!(c.remote_exec_heartbeat_misses, 3);
+    }
+
+    #[test]
+    fn remote_exec_kubernetes() {
+        let c = YamlConfig::load(
+            "remote-exec-kubernetes:\n  api-server: https://10.0.0.1:6443\n  token-file: /etc/deepflow-agent/token\n",
+            TapMode::Local,
+        )
+        .unwrap();
+        let kube = &c.remote_exec_kubernetes;
+        assert!(kube.kubeconfig.is_empty());
+        assert_eq!(kube.api_server, "https://10.0.0.1:6443");
+        assert_eq!(kube.token_file, "/etc/deepflow-agent/token");
+        assert!(kube.overridden());
+        assert!(!kube.insecure_skip_tls_verify);
+        assert!(!RemoteExecKubeConfig::default().overridden());
+        assert!(YamlConfig::load(
+            "remote-exec-kubernetes:\n  api-server: 10.0.0.1:6443\n",
+            TapMode::Local
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn af_packet_fanout() {
+        let c = YamlConfig::load(
+            "af-packet-fanout:\n- interface-regex: ^vxlan\n  mode: cpu\n  thread-count: 4\n- interface-regex: ^eth\n  thread-count: 0\n",
+            TapMode::Mirror,
+        )
+        .unwrap();
+        let f = c.get_af_packet_fanout("vxlan0").unwrap();
+        assert_eq!(f.mode, AfPacketFanoutMode::Cpu);
+        assert_eq!(f.thread_count, 4);
+        let f = c.get_af_packet_fanout("eth0").unwrap();
+        assert_eq!(f.mode, AfPacketFanoutMode::FlowHash);
+        assert_eq!(f.thread_count, 1);
+        assert!(c.get_af_packet_fanout("lo").is_none());
+    }
+
+    #[test]
+    fn af_packet_promisc() {
+        let c = YamlConfig::load(
+            "af-packet-promisc:\n- interface-regex: ^mirror\n- interface-regex: .*\n  enabled: false\n",
+            TapMode::Mirror,
+        )
+        .unwrap();
+        assert_eq!(c.get_af_packet_promisc("mirror0"), Some(true));
+        assert_eq!(c.get_af_packet_promisc("eth0"), Some(false));
+        assert!(YamlConfig::default()
+            .get_af_packet_promisc("eth0")
+            .is_none());
+    }
+
+    #[test]
+    fn xdp_capture_scope() {
+        let c = YamlConfig::load(
+            "xdp-capture-scope:\n  interface-regex: ^mirror\n  cidrs: [10.0.0.0/8, fd00::/8]\n",
+            TapMode::Mirror,
+        )
+        .unwrap();
+        let scope = c.get_xdp_capture_scope("mirror0").unwrap();
+        assert_eq!(scope.cidrs.len(), 2);
+        assert!(!scope.native_mode);
+        assert!(c.get_xdp_capture_scope("eth0").is_none());
+        assert!(YamlConfig::default()
+            .get_xdp_capture_scope("mirror0")
+            .is_none());
+        assert!(YamlConfig::load(
+            "xdp-capture-scope:\n  interface-regex: ^mirror\n  cidrs: [10.0.0.0/33]\n",
+            TapMode::Mirror,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn prometheus_forward_urls() {
+        let c = YamlConfig::load(
+            "prometheus-extra-config:\n  forward-urls: [http://vm:8428/api/v1/write, ftp://vm, vm:8428]\n",
+            TapMode::Mirror,
+        )
+        .unwrap();
+        assert_eq!(
+            c.prometheus_extra_config.forward_urls,
+            vec!["http://vm:8428/api/v1/write".to_owned()]
+        );
+    }
+
+    #[test]
+    fn sender_bandwidth_quota() {
+        let c = YamlConfig::load(
+            "sender-bandwidth-quota:\n  total-mbps: 10\n  quotas:\n  - signal: l7-log\n    mbps: 2\n",
+            TapMode::Mirror,
+        )
+        .unwrap();
+        assert_eq!(c.sender_bandwidth_quota.total_mbps, 10);
+        assert_eq!(
+            c.sender_bandwidth_quota.quotas[0].signal,
+            SignalClass::L7Log
+        );
+        assert_eq!(c.sender_bandwidth_quota.quotas[0].priority, 0);
+        assert!(YamlConfig::load(
+            "sender-bandwidth-quota:\n  quotas:\n  - signal: pcap\n  - signal: pcap\n",
+            TapMode::Mirror,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn tenant_export_destinations() {
+        let c = YamlConfig::load(
+            "tenant-export-destinations:\n- tenant-id: 1\n  ip: 10.1.1.1\n  port: 30033\n",
+            TapMode::Mirror,
+        )
+        .unwrap();
+        assert_eq!(c.tenant_export_destinations[0].tenant_id, 1);
+        assert!(YamlConfig::load(
+            "tenant-export-destinations:\n- tenant-id: 1\n  ip: 10.1.1.1\n  port: 30033\n- tenant-id: 1\n  ip: 10.1.1.2\n  port: 30033\n",
+            TapMode::Mirror,
+        )
+        .is_err());
+    }
 }
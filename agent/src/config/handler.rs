@@ -24,6 +24,7 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
+use anyhow::anyhow;
 use arc_swap::{access::Map, ArcSwap};
 use base64::{prelude::BASE64_STANDARD, Engine};
 use bytesize::ByteSize;
@@ -43,7 +44,10 @@ use sysinfo::SystemExt;
 use sysinfo::{CpuRefreshKind, RefreshKind, System};
 use tokio::runtime::Runtime;
 
-use super::config::{ExtraLogFields, L7LogBlacklist, OracleParseConfig};
+use super::config::{
+    ExtraLogFields, L7LogBlacklist, NpbHealthCheck, NpbTunnelShaping, OracleParseConfig, Secret,
+    SenderBandwidthQuota, TenantExportDestination,
+};
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use super::{
     config::EbpfYamlConfig, OsProcRegexp, OS_PROC_REGEXP_MATCH_ACTION_ACCEPT,
@@ -51,27 +55,35 @@ use super::{
 };
 use super::{
     config::{
-        Config, HttpEndpointExtraction, KubernetesResourceConfig, MatchRule, PcapConfig,
-        PortConfig, YamlConfig,
+        Config, HttpEndpointExtraction, KubernetesResourceConfig, MatchRule, ModuleLogLevel,
+        PcapConfig, PortConfig, PreAggregationDimension, PreAggregationRule, QueueOverflowConfig,
+        YamlConfig,
     },
-    ConfigError, KubernetesPollerType, RuntimeConfig,
+    ConfigError, FlowEvictionPolicy, KubernetesPollerType, RuntimeConfig,
 };
 use crate::rpc::Session;
 use crate::{
     common::{decapsulate::TunnelTypeBitmap, enums::TapType, l7_protocol_log::L7ProtocolBitmap},
     dispatcher::recv_engine,
     exception::ExceptionHandler,
-    flow_generator::{protocol_logs::SOFA_NEW_RPC_TRACE_CTX_KEY, FlowTimeout, TcpTimeout},
+    flow_generator::{
+        capture_point_dedup::parse_tap_side, flow_node::FlowNode,
+        protocol_logs::SOFA_NEW_RPC_TRACE_CTX_KEY, FlowTimeout, TcpTimeout,
+    },
     handler::PacketHandlerBuilder,
     metric::document::TapSide,
+    plugin::cache::PluginCache,
     trident::{AgentComponents, RunningMode},
-    utils::environment::{free_memory_check, running_in_container},
+    utils::{
+        environment::{free_memory_check, running_in_container},
+        logger,
+    },
 };
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use crate::{
     dispatcher::recv_engine::af_packet::OptTpacketVersion,
     ebpf::CAP_LEN_MAX,
-    platform::ProcRegRewrite,
+    platform::{ProcRegRewrite, ProcServiceRule},
     utils::environment::{
         get_container_resource_limits, get_ctrl_ip_and_mac, is_tt_workload,
         set_container_resource_limit,
@@ -91,6 +103,7 @@ use public::proto::{
 };
 
 use crate::{trident::AgentId, utils::cgroups::is_kernel_available_for_cgroups};
+use public::utils::bitmap::parse_u16_range_list_to_bitmap;
 use public::utils::net::MacAddr;
 
 const MB: u64 = 1048576;
@@ -146,6 +159,22 @@ pub struct CollectorConfig {
     pub vtap_id: u16,
     pub cloud_gateway_traffic: bool,
     pub packet_delay: Duration,
+    pub backpressure: BackpressureConfig,
+    pub pre_aggregation: Vec<PreAggregation>,
+}
+
+impl CollectorConfig {
+    // dimensions to aggregate away by the first pre-aggregation rule matching the server port
+    pub fn pre_aggregation_dimensions(&self, server_port: u16) -> &[PreAggregationDimension] {
+        self.pre_aggregation
+            .iter()
+            .find(|p| match p.server_ports.as_ref() {
+                Some(ports) => ports.get(server_port as usize).unwrap_or_default(),
+                None => true,
+            })
+            .map(|p| p.drop_dimensions.as_slice())
+            .unwrap_or_default()
+    }
 }
 
 impl fmt::Debug for CollectorConfig {
@@ -191,10 +220,62 @@ impl fmt::Debug for CollectorConfig {
             .field("vtap_id", &self.vtap_id)
             .field("cloud_gateway_traffic", &self.cloud_gateway_traffic)
             .field("packet_delay", &self.packet_delay)
+            .field("backpressure", &self.backpressure)
+            .field("pre_aggregation", &self.pre_aggregation)
             .finish()
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreAggregation {
+    // None matches all server ports
+    pub server_ports: Option<Bitmap>,
+    pub drop_dimensions: Vec<PreAggregationDimension>,
+}
+
+impl From<&PreAggregationRule> for PreAggregation {
+    fn from(r: &PreAggregationRule) -> Self {
+        Self {
+            server_ports: if r.server_ports.is_empty() {
+                None
+            } else {
+                parse_u16_range_list_to_bitmap(&r.server_ports, false)
+            },
+            drop_dimensions: r.drop_dimensions.clone(),
+        }
+    }
+}
+
+// Producers check the occupancy of their sender queue against `watermark`,
+// once it is exceeded only errors and slow requests are passed on.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BackpressureConfig {
+    // percentage of the sender queue, 0 means disabled
+    pub watermark: u8,
+    pub slow_request_threshold: Duration,
+}
+
+impl BackpressureConfig {
+    pub fn enabled(&self) -> bool {
+        self.watermark > 0
+    }
+
+    pub fn slow_request_threshold_us(&self) -> u32 {
+        self.slow_request_threshold
+            .as_micros()
+            .min(u32::MAX as u128) as u32
+    }
+}
+
+impl From<&QueueOverflowConfig> for BackpressureConfig {
+    fn from(c: &QueueOverflowConfig) -> Self {
+        Self {
+            watermark: c.backpressure_watermark,
+            slow_request_threshold: c.slow_request_threshold,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct EnvironmentConfig {
     pub max_memory: u64,
@@ -230,6 +311,9 @@ pub struct SenderConfig {
     pub standalone_data_file_dir: String,
     pub server_tx_bandwidth_threshold: u64,
     pub bandwidth_probe_interval: Duration,
+    // flows and l7 logs of these tenants are sent to their own destinations
+    pub tenant_destinations: Vec<TenantExportDestination>,
+    pub bandwidth_quota: SenderBandwidthQuota,
     pub enabled: bool,
 }
 
@@ -246,6 +330,11 @@ pub struct NpbConfig {
     pub vxlan_flags: u8,
     pub npb_port: u16,
     pub dedup_enabled: bool,
+    pub dedup_window: Duration,
+    pub dedup_capacity: usize,
+    pub encryption_key: Secret,
+    pub tunnel_shaping: Vec<NpbTunnelShaping>,
+    pub health_check: NpbHealthCheck,
     pub enable_qos_bypass: bool,
     pub output_vlan: u16,
     pub mtu: u32,
@@ -276,6 +365,7 @@ pub struct OsProcScanConfig {
     pub os_proc_sync_enabled: bool,
     // sync os socket and proc info only when the process has been tagged.
     pub os_proc_sync_tagged_only: bool,
+    pub os_proc_service_rules: Vec<ProcServiceRule>,
 }
 #[cfg(target_os = "windows")]
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -300,9 +390,14 @@ pub struct PlatformConfig {
     pub thread_threshold: u32,
     pub tap_mode: TapMode,
     pub os_proc_scan_conf: OsProcScanConfig,
+    pub exclude_interface_regex: String,
     pub agent_enabled: bool,
     #[cfg(target_os = "linux")]
     pub extra_netns_regex: String,
+    #[cfg(target_os = "linux")]
+    pub docker_discovery_enabled: bool,
+    #[cfg(target_os = "linux")]
+    pub docker_socket_path: String,
 }
 
 #[derive(Clone, PartialEq, Debug, Eq)]
@@ -323,6 +418,7 @@ pub struct DispatcherConfig {
     #[cfg(target_os = "linux")]
     pub extra_netns_regex: String,
     pub tap_interface_regex: String,
+    pub loopback_capture_enabled: bool,
     pub if_mac_source: IfMacSource,
     pub analyzer_ip: String,
     pub analyzer_port: u16,
@@ -337,6 +433,7 @@ pub struct DispatcherConfig {
     pub region_id: u32,
     pub pod_cluster_id: u32,
     pub enabled: bool,
+    pub capture_paused: bool,
     pub npb_dedup_enabled: bool,
     pub dpdk_enabled: bool,
     pub dispatcher_queue: bool,
@@ -345,6 +442,7 @@ pub struct DispatcherConfig {
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct LogConfig {
     pub log_level: Level,
+    pub module_log_levels: Vec<ModuleLogLevel>,
     pub log_threshold: u32,
     pub log_retention: u32,
     pub rsyslog_enabled: bool,
@@ -355,7 +453,8 @@ pub struct LogConfig {
 pub struct PluginConfig {
     pub last_updated: u32,
     pub digest: u64, // for change detection
-    pub names: Vec<(String, trident::PluginType)>,
+    // name, type, md5 of content if provided by server
+    pub names: Vec<(String, trident::PluginType, String)>,
     // name, data
     pub wasm_plugins: Vec<(String, Vec<u8>)>,
     pub so_plugins: Vec<(String, Vec<u8>)>,
@@ -387,14 +486,52 @@ impl PluginConfig {
         rt: &Runtime,
         session: &Session,
         agent_id: &AgentId,
+        cache: &PluginCache,
     ) {
         self.wasm_plugins.clear();
         self.so_plugins.clear();
 
+        let mut cached_keys = HashSet::new();
+        let mut cache_hits = 0;
         rt.block_on(async {
-            for (name, ptype) in self.names.iter() {
-                log::trace!("get {:?} plugin {}", ptype, name);
-                match session.get_plugin(name, *ptype, agent_id).await {
+            for (name, ptype, md5) in self.names.iter() {
+                let prog = match cache.get(md5) {
+                    Some(prog) => {
+                        log::trace!("get {:?} plugin {} from cache", ptype, name);
+                        cache_hits += 1;
+                        cached_keys.insert(md5.to_ascii_lowercase());
+                        Ok(prog)
+                    }
+                    None => {
+                        log::trace!("get {:?} plugin {}", ptype, name);
+                        session
+                            .get_plugin(name, *ptype, agent_id)
+                            .await
+                            .and_then(|prog| {
+                                // plugins without md5 from server are loaded but not cached
+                                if md5.is_empty() {
+                                    return Ok(prog);
+                                }
+                                if !PluginCache::verify(md5, &prog) {
+                                    return Err(anyhow!(
+                                        "md5 mismatch, expected {} got {}",
+                                        md5,
+                                        PluginCache::digest(&prog)
+                                    ));
+                                }
+                                match cache.put(md5, &prog) {
+                                    Ok(key) => {
+                                        cached_keys.insert(key);
+                                    }
+                                    Err(e) => {
+                                        warn!("cache {:?} plugin {} failed: {}", ptype, name, e)
+                                    }
+                                }
+                                Ok(prog)
+                            })
+                    }
+                };
+                match prog {
                     Ok(prog) => match ptype {
                         trident::PluginType::Wasm => self.wasm_plugins.push((name.clone(), prog)),
                         #[cfg(any(target_os = "linux", target_os = "android"))]
@@ -410,15 +547,18 @@ impl PluginConfig {
             }
         });
 
+        cache.retain(&cached_keys);
+
         info!(
-            "{} wasm and {} so plugins pulled from server",
+            "{} wasm and {} so plugins loaded, {} from cache",
             self.wasm_plugins.len(),
-            self.so_plugins.len()
+            self.so_plugins.len(),
+            cache_hits
         );
     }
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq)]
 pub struct FlowConfig {
     pub vtap_id: u16,
     pub trident_type: TridentType,
@@ -427,6 +567,7 @@ pub struct FlowConfig {
     pub l7_log_tap_types: [bool; 256],
 
     pub capacity: u32,
+    pub eviction_policy: FlowEvictionPolicy,
     pub hash_slots: u32,
     pub packet_delay: Duration,
     pub flush_interval: Duration,
@@ -440,8 +581,15 @@ pub struct FlowConfig {
     pub l7_metrics_enabled: bool,
     pub app_proto_log_enabled: bool,
     pub l4_performance_enabled: bool,
+    pub tcp_histogram_enabled: bool,
+    // None if rrt sketches are disabled
+    pub rrt_sketch_accuracy: Option<f64>,
     pub l7_log_packet_size: u32,
 
+    // empty if capture point dedup is disabled
+    pub capture_point_priority: Vec<TapSide>,
+    pub tls_handshake_tags_enabled: bool,
+
     pub l7_protocol_inference_max_fail_count: usize,
     pub l7_protocol_inference_ttl: usize,
 
@@ -485,7 +633,16 @@ impl From<&RuntimeConfig> for FlowConfig {
                 }
                 tap_types
             },
-            capacity: flow_config.capacity,
+            capacity: if flow_config.memory_budget_mb > 0 {
+                let budget = flow_config.memory_budget_mb as usize * MB as usize;
+                flow_config
+                    .capacity
+                    .min((budget / FlowNode::ESTIMATED_SIZE) as u32)
+                    .max(1)
+            } else {
+                flow_config.capacity
+            },
+            eviction_policy: flow_config.eviction_policy,
             hash_slots: flow_config.hash_slots,
             packet_delay: conf.yaml_config.packet_delay,
             flush_interval: flow_config.flush_interval,
@@ -494,7 +651,11 @@ impl From<&RuntimeConfig> for FlowConfig {
                 closing_rst: flow_config.closing_rst_timeout.into(),
                 others: flow_config.others_timeout.into(),
                 opening_rst: flow_config.opening_rst_timeout.into(),
-            }),
+            })
+            .with_udp_other(
+                flow_config.udp_timeout.into(),
+                flow_config.other_protocol_timeout.into(),
+            ),
             ignore_tor_mac: flow_config.ignore_tor_mac,
             ignore_l2_end: flow_config.ignore_l2_end,
             ignore_idc_vlan: flow_config.ignore_idc_vlan,
@@ -502,16 +663,36 @@ impl From<&RuntimeConfig> for FlowConfig {
             l7_metrics_enabled: conf.l7_metrics_enabled,
             app_proto_log_enabled: conf.app_proto_log_enabled,
             l4_performance_enabled: conf.l4_performance_enabled,
+            tcp_histogram_enabled: flow_config.tcp_histogram_enabled,
+            rrt_sketch_accuracy: flow_config
+                .rrt_sketch_enabled
+                .then_some(flow_config.rrt_sketch_relative_accuracy),
             l7_log_packet_size: conf.l7_log_packet_size,
+            capture_point_priority: if flow_config.capture_point_dedup_enabled {
+                let mut priority = vec![];
+                for s in flow_config.capture_point_priority.iter() {
+                    match parse_tap_side(s) {
+                        Some(side) if !priority.contains(&side) => priority.push(side),
+                        Some(_) => (),
+                        None => warn!("invalid capture point {} in capture-point-priority", s),
+                    }
+                }
+                if priority.is_empty() {
+                    // all capture points rank the same, the first reporting one wins
+                    priority.push(TapSide::Rest);
+                }
+                priority
+            } else {
+                vec![]
+            },
+            tls_handshake_tags_enabled: flow_config.tls_handshake_tags_enabled,
             l7_protocol_inference_max_fail_count: conf
                 .yaml_config
                 .l7_protocol_inference_max_fail_count,
             l7_protocol_inference_ttl: conf.yaml_config.l7_protocol_inference_ttl,
             packet_sequence_flag: conf.yaml_config.packet_sequence_flag, // Enterprise Edition Feature: packet-sequence
             packet_sequence_block_size: conf.yaml_config.packet_sequence_block_size, // Enterprise Edition Feature: packet-sequence
-            l7_protocol_enabled_bitmap: L7ProtocolBitmap::from(
-                &conf.yaml_config.l7_protocol_enabled,
-            ),
+            l7_protocol_enabled_bitmap: conf.yaml_config.get_l7_protocol_enabled_bitmap(),
             l7_protocol_parse_port_bitmap: Arc::new(
                 (&conf.yaml_config).get_protocol_port_parse_bitmap(),
             ),
@@ -533,22 +714,30 @@ impl From<&RuntimeConfig> for FlowConfig {
                             plugin.hash(&mut hasher);
                             trident::PluginType::So.hash(&mut hasher);
                         }
+                        plugins.wasm_plugin_md5s.hash(&mut hasher);
+                        plugins.so_plugin_md5s.hash(&mut hasher);
                     }
                     hasher.finish()
                 },
                 names: {
                     let mut plugins = vec![];
                     if let Some(p) = &conf.plugins {
-                        plugins.extend(
-                            p.wasm_plugins
-                                .iter()
-                                .map(|p| (p.clone(), trident::PluginType::Wasm)),
-                        );
-                        plugins.extend(
-                            p.so_plugins
-                                .iter()
-                                .map(|p| (p.clone(), trident::PluginType::So)),
-                        );
+                        let md5 =
+                            |md5s: &Vec<String>, i: usize| md5s.get(i).cloned().unwrap_or_default();
+                        plugins.extend(p.wasm_plugins.iter().enumerate().map(|(i, name)| {
+                            (
+                                name.clone(),
+                                trident::PluginType::Wasm,
+                                md5(&p.wasm_plugin_md5s, i),
+                            )
+                        }));
+                        plugins.extend(p.so_plugins.iter().enumerate().map(|(i, name)| {
+                            (
+                                name.clone(),
+                                trident::PluginType::So,
+                                md5(&p.so_plugin_md5s, i),
+                            )
+                        }));
                     }
                     plugins
                 },
@@ -586,6 +775,7 @@ impl fmt::Debug for FlowConfig {
                     .collect::<Vec<_>>(),
             )
             .field("capacity", &self.capacity)
+            .field("eviction_policy", &self.eviction_policy)
             .field("hash_slots", &self.hash_slots)
             .field("packet_delay", &self.packet_delay)
             .field("flush_interval", &self.flush_interval)
@@ -595,7 +785,14 @@ impl fmt::Debug for FlowConfig {
             .field("l7_metrics_enabled", &self.l7_metrics_enabled)
             .field("app_proto_log_enabled", &self.app_proto_log_enabled)
             .field("l4_performance_enabled", &self.l4_performance_enabled)
+            .field("tcp_histogram_enabled", &self.tcp_histogram_enabled)
+            .field("rrt_sketch_accuracy", &self.rrt_sketch_accuracy)
             .field("l7_log_packet_size", &self.l7_log_packet_size)
+            .field("capture_point_priority", &self.capture_point_priority)
+            .field(
+                "tls_handshake_tags_enabled",
+                &self.tls_handshake_tags_enabled,
+            )
             .field(
                 "l7_protocol_inference_max_fail_count",
                 &self.l7_protocol_inference_max_fail_count,
@@ -868,6 +1065,7 @@ pub struct LogParserConfig {
     pub l7_log_blacklist_trie: HashMap<L7Protocol, BlacklistTrie>,
     pub unconcerned_dns_nxdomain_response_suffixes: Vec<String>,
     pub unconcerned_dns_nxdomain_trie: DnsNxdomainTrie,
+    pub backpressure: BackpressureConfig,
 }
 
 impl Default for LogParserConfig {
@@ -885,6 +1083,7 @@ impl Default for LogParserConfig {
             l7_log_blacklist_trie: HashMap::new(),
             unconcerned_dns_nxdomain_response_suffixes: vec![],
             unconcerned_dns_nxdomain_trie: DnsNxdomainTrie::default(),
+            backpressure: BackpressureConfig::default(),
         }
     }
 }
@@ -925,6 +1124,7 @@ impl fmt::Debug for LogParserConfig {
                 "unconcerned_dns_nxdomain_trie",
                 &self.unconcerned_dns_nxdomain_response_suffixes,
             )
+            .field("backpressure", &self.backpressure)
             .finish()
     }
 }
@@ -967,6 +1167,7 @@ pub struct EbpfConfig {
     // 动态配置
     pub collector_enabled: bool,
     pub l7_metrics_enabled: bool,
+    pub paused: bool,
     pub vtap_id: u16,
     pub epc_id: u32,
     pub l7_log_packet_size: usize,
@@ -989,6 +1190,7 @@ impl fmt::Debug for EbpfConfig {
         f.debug_struct("EbpfConfig")
             .field("collector_enabled", &self.collector_enabled)
             .field("l7_metrics_enabled", &self.l7_metrics_enabled)
+            .field("paused", &self.paused)
             .field("vtap_id", &self.vtap_id)
             .field("epc_id", &self.epc_id)
             .field("l7_log_packet_size", &self.l7_log_packet_size)
@@ -1463,6 +1665,7 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 #[cfg(target_os = "linux")]
                 extra_netns_regex: conf.extra_netns_regex.to_string(),
                 tap_interface_regex: conf.tap_interface_regex.to_string(),
+                loopback_capture_enabled: conf.yaml_config.loopback_capture_enabled,
                 if_mac_source: conf.if_mac_source,
                 analyzer_ip: dest_ip.clone(),
                 analyzer_port: conf.analyzer_port,
@@ -1479,6 +1682,7 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 region_id: conf.region_id,
                 pod_cluster_id: conf.pod_cluster_id,
                 enabled: conf.enabled,
+                capture_paused: conf.capture_paused,
                 npb_dedup_enabled: conf.npb_dedup_enabled,
             },
             sender: SenderConfig {
@@ -1501,6 +1705,8 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 collector_socket_type: conf.collector_socket_type,
                 standalone_data_file_size: conf.yaml_config.standalone_data_file_size,
                 standalone_data_file_dir: conf.yaml_config.standalone_data_file_dir.clone(),
+                tenant_destinations: conf.yaml_config.tenant_export_destinations.clone(),
+                bandwidth_quota: conf.yaml_config.sender_bandwidth_quota.clone(),
                 enabled: conf.collector_enabled,
             },
             npb: NpbConfig {
@@ -1513,6 +1719,11 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 output_vlan: conf.output_vlan,
                 vlan_mode: conf.npb_vlan_mode,
                 dedup_enabled: conf.npb_dedup_enabled,
+                dedup_window: conf.yaml_config.npb_dedup_window,
+                dedup_capacity: conf.yaml_config.npb_dedup_capacity,
+                encryption_key: conf.yaml_config.npb_encryption_key.clone(),
+                tunnel_shaping: conf.yaml_config.npb_tunnel_shaping.clone(),
+                health_check: conf.yaml_config.npb_health_check.clone(),
                 socket_type: conf.npb_socket_type,
                 queue_size: conf.yaml_config.collector_sender_queue_size,
             },
@@ -1546,6 +1757,13 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 },
                 cloud_gateway_traffic: conf.yaml_config.cloud_gateway_traffic,
                 packet_delay: conf.yaml_config.packet_delay,
+                backpressure: BackpressureConfig::from(&conf.yaml_config.queue_overflow),
+                pre_aggregation: conf
+                    .yaml_config
+                    .pre_aggregation
+                    .iter()
+                    .map(PreAggregation::from)
+                    .collect(),
             },
             handler: HandlerConfig {
                 npb_dedup_enabled: conf.npb_dedup_enabled,
@@ -1602,12 +1820,26 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                     os_app_tag_exec: conf.yaml_config.os_app_tag_exec.clone(),
                     os_proc_sync_enabled: conf.yaml_config.os_proc_sync_enabled,
                     os_proc_sync_tagged_only: conf.yaml_config.os_proc_sync_tagged_only,
+                    os_proc_service_rules: conf
+                        .yaml_config
+                        .os_proc_service_rules
+                        .iter()
+                        .filter_map(|r| ProcServiceRule::try_from(r).ok())
+                        .collect(),
                 },
                 #[cfg(target_os = "windows")]
                 os_proc_scan_conf: OsProcScanConfig {},
+                exclude_interface_regex: conf
+                    .yaml_config
+                    .platform_sync_exclude_interface_regex
+                    .clone(),
                 agent_enabled: conf.enabled,
                 #[cfg(target_os = "linux")]
                 extra_netns_regex: conf.extra_netns_regex.to_string(),
+                #[cfg(target_os = "linux")]
+                docker_discovery_enabled: conf.yaml_config.docker_discovery_enabled,
+                #[cfg(target_os = "linux")]
+                docker_socket_path: conf.yaml_config.docker_socket_path.clone(),
             },
             flow: (&conf).into(),
             log_parser: LogParserConfig {
@@ -1682,6 +1914,7 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                         .l7_protocol_advanced_features
                         .unconcerned_dns_nxdomain_response_suffixes,
                 ),
+                backpressure: BackpressureConfig::from(&conf.yaml_config.queue_overflow),
             },
             debug: DebugConfig {
                 vtap_id: conf.vtap_id as u16,
@@ -1697,6 +1930,7 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
             },
             log: LogConfig {
                 log_level: conf.log_level,
+                module_log_levels: conf.yaml_config.module_log_levels.clone(),
                 log_threshold: conf.log_threshold,
                 log_retention: conf.log_retention,
                 rsyslog_enabled: {
@@ -1715,6 +1949,7 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
             ebpf: EbpfConfig {
                 collector_enabled: conf.collector_enabled,
                 l7_metrics_enabled: conf.l7_metrics_enabled,
+                paused: conf.ebpf_paused,
                 vtap_id: conf.vtap_id as u16,
                 epc_id: conf.epc_id,
                 l7_log_session_timeout: conf.yaml_config.l7_log_session_aggr_timeout,
@@ -1769,9 +2004,7 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 } else {
                     MacAddr::ZERO
                 },
-                l7_protocol_enabled_bitmap: L7ProtocolBitmap::from(
-                    &conf.yaml_config.l7_protocol_enabled,
-                ),
+                l7_protocol_enabled_bitmap: conf.yaml_config.get_l7_protocol_enabled_bitmap(),
                 l7_protocol_parse_port_bitmap: Arc::new(
                     (&conf.yaml_config).get_protocol_port_parse_bitmap(),
                 ),
@@ -1830,6 +2063,7 @@ impl ConfigHandler {
     }
 
     pub fn set_logger_handle(&mut self, handle: LoggerHandle) {
+        logger::set_log_handle(handle.clone(), self.candidate_config.log.log_level);
         self.logger_handle.replace(handle);
     }
 
@@ -2166,6 +2400,15 @@ impl ConfigHandler {
                 }
             }
 
+            if candidate_config.dispatcher.loopback_capture_enabled
+                != new_config.dispatcher.loopback_capture_enabled
+            {
+                info!(
+                    "loopback_capture_enabled set to {}",
+                    new_config.dispatcher.loopback_capture_enabled
+                );
+            }
+
             #[cfg(target_os = "windows")]
             if candidate_config.tap_mode == TapMode::Local
                 && candidate_config.dispatcher.tap_interface_regex
@@ -2211,6 +2454,9 @@ impl ConfigHandler {
                 info!("enabled set to {}", new_config.dispatcher.enabled);
                 if new_config.dispatcher.enabled {
                     fn start_dispatcher(handler: &ConfigHandler, components: &mut AgentComponents) {
+                        if handler.candidate_config.dispatcher.capture_paused {
+                            return;
+                        }
                         match handler.candidate_config.tap_mode {
                             TapMode::Analyzer => {
                                 for d in components.dispatcher_components.iter_mut() {
@@ -2249,6 +2495,41 @@ impl ConfigHandler {
                 }
             }
 
+            if candidate_config.dispatcher.capture_paused != new_config.dispatcher.capture_paused
+                && new_config.dispatcher.enabled
+            {
+                if new_config.dispatcher.capture_paused {
+                    info!("Packet capture paused by controller");
+                    fn pause_capture(_: &ConfigHandler, components: &mut AgentComponents) {
+                        for d in components.dispatcher_components.iter_mut() {
+                            d.stop();
+                        }
+                    }
+                    callbacks.push(pause_capture);
+                } else {
+                    info!("Packet capture resumed by controller");
+                    fn resume_capture(handler: &ConfigHandler, components: &mut AgentComponents) {
+                        if handler.candidate_config.tap_mode != TapMode::Analyzer
+                            && !running_in_container()
+                            && !is_kernel_available_for_cgroups()
+                        // In the environment where cgroups is not supported, we need to check free memory
+                        {
+                            if let Err(e) = free_memory_check(
+                                handler.candidate_config.environment.max_memory,
+                                &components.exception_handler,
+                            ) {
+                                warn!("{}", e);
+                                return;
+                            }
+                        }
+                        for d in components.dispatcher_components.iter_mut() {
+                            d.start();
+                        }
+                    }
+                    callbacks.push(resume_capture);
+                }
+            }
+
             if candidate_config.dispatcher.max_memory != new_config.dispatcher.max_memory {
                 if yaml_config
                     .get_af_packet_blocks(new_config.tap_mode, new_config.dispatcher.max_memory)
@@ -2318,17 +2599,31 @@ impl ConfigHandler {
                 }
             }
             if candidate_config.log.log_level != new_config.log.log_level {
-                match self.logger_handle.as_mut() {
-                    Some(h) => match h
-                        .parse_and_push_temp_spec(new_config.log.log_level.as_str().to_lowercase())
+                match logger::set_global_log_level(new_config.log.log_level) {
+                    Ok(_) => {
+                        candidate_config.log.log_level = new_config.log.log_level;
+                        info!("log level set to {}", new_config.log.log_level);
+                    }
+                    Err(e) => warn!("failed to set log_level: {}", e),
+                }
+            }
+            if candidate_config.log.module_log_levels != new_config.log.module_log_levels {
+                for old in candidate_config.log.module_log_levels.iter() {
+                    if !new_config
+                        .log
+                        .module_log_levels
+                        .iter()
+                        .any(|l| l.module == old.module)
                     {
-                        Ok(_) => {
-                            candidate_config.log.log_level = new_config.log.log_level;
-                            info!("log level set to {}", new_config.log.log_level);
+                        if let Err(e) = logger::reset_module_log_level(Some(&old.module)) {
+                            warn!("failed to reset log level of {}: {}", old.module, e);
                         }
-                        Err(e) => warn!("failed to set log_level: {}", e),
-                    },
-                    None => warn!("logger_handle not set"),
+                    }
+                }
+                for l in new_config.log.module_log_levels.iter() {
+                    if let Err(e) = logger::set_module_log_level(&l.module, l.level, l.duration) {
+                        warn!("failed to set log level of {}: {}", l.module, e);
+                    }
                 }
             }
             if candidate_config.log.host != new_config.log.host {
@@ -2571,10 +2866,12 @@ impl ConfigHandler {
                     "plugins changed, pulling {} plugins from server",
                     new_config.flow.plugins.names.len()
                 );
-                new_config
-                    .flow
-                    .plugins
-                    .fill_plugin_prog_from_server(runtime, session, agent_id);
+                new_config.flow.plugins.fill_plugin_prog_from_server(
+                    runtime,
+                    session,
+                    agent_id,
+                    &PluginCache::new(static_config.plugin_cache_dir()),
+                );
             }
             candidate_config.flow = new_config.flow;
         }
@@ -2881,6 +3178,29 @@ impl ConfigHandler {
             candidate_config.synchronizer = new_config.synchronizer;
         }
 
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if candidate_config.ebpf.paused != new_config.ebpf.paused
+            && candidate_config.tap_mode != TapMode::Analyzer
+        {
+            if new_config.ebpf.paused {
+                info!("eBPF collection paused by controller");
+                fn pause_ebpf(_: &ConfigHandler, components: &mut AgentComponents) {
+                    if let Some(d) = components.ebpf_dispatcher_component.as_mut() {
+                        d.stop();
+                    }
+                }
+                callbacks.push(pause_ebpf);
+            } else {
+                info!("eBPF collection resumed by controller");
+                fn resume_ebpf(_: &ConfigHandler, components: &mut AgentComponents) {
+                    if let Some(d) = components.ebpf_dispatcher_component.as_mut() {
+                        d.start();
+                    }
+                }
+                callbacks.push(resume_ebpf);
+            }
+        }
+
         #[cfg(any(target_os = "linux", target_os = "android"))]
         if candidate_config.ebpf != new_config.ebpf
             && candidate_config.tap_mode != TapMode::Analyzer
@@ -2966,6 +3286,9 @@ impl ConfigHandler {
                 components.npb_arp_table.set_need_resolve_mac(
                     handler.candidate_config.npb.socket_type == SocketType::RawUdp,
                 );
+                components
+                    .npb_health_checker
+                    .on_config_change(&handler.candidate_config.npb.health_check);
             }
             if components.is_some() {
                 callbacks.push(dispatcher_callback);
@@ -2979,7 +3302,11 @@ impl ConfigHandler {
         }
 
         // avoid first config changed to restart dispatcher
-        if components.is_some() && restart_dispatcher && candidate_config.dispatcher.enabled {
+        if components.is_some()
+            && restart_dispatcher
+            && candidate_config.dispatcher.enabled
+            && !candidate_config.dispatcher.capture_paused
+        {
             fn dispatcher_callback(handler: &ConfigHandler, components: &mut AgentComponents) {
                 for d in components.dispatcher_components.iter_mut() {
                     d.stop();
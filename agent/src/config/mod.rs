@@ -18,15 +18,19 @@ mod config;
 pub mod handler;
 
 pub use config::{
-    AgentIdType, Config, ConfigError, KubernetesPollerType, OracleParseConfig, PcapConfig,
-    PrometheusExtraConfig, RuntimeConfig, YamlConfig, K8S_CA_CRT_PATH,
+    AfPacketFanoutMode, AgentIdType, Config, ConfigError, FeatureFlagRollout, FlowEvictionPolicy,
+    KubernetesPollerType, NpbHealthCheck, NpbProbeProtocol, NpbTunnelBackup, NpbTunnelPriority,
+    NpbTunnelShaping, OracleParseConfig, PcapConfig, PprofExport, PreAggregationDimension,
+    PrometheusExtraConfig, RemoteExecCommand, RemoteExecKubeConfig, RemoteExecLimits,
+    RemoteExecOutputFormat, RemoteExecParamRule, RuntimeConfig, Secret, SenderBandwidthQuota,
+    SignalClass, YamlConfig, K8S_CA_CRT_PATH,
 };
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub use config::{
-    KubernetesResourceConfig, OsProcRegexp, OS_PROC_REGEXP_MATCH_ACTION_ACCEPT,
-    OS_PROC_REGEXP_MATCH_ACTION_DROP, OS_PROC_REGEXP_MATCH_TYPE_CMD,
-    OS_PROC_REGEXP_MATCH_TYPE_PARENT_PROC_NAME, OS_PROC_REGEXP_MATCH_TYPE_PROC_NAME,
-    OS_PROC_REGEXP_MATCH_TYPE_TAG,
+    EbpfScope, KubernetesResourceConfig, OsProcRegexp, OsProcServiceRule,
+    OS_PROC_REGEXP_MATCH_ACTION_ACCEPT, OS_PROC_REGEXP_MATCH_ACTION_DROP,
+    OS_PROC_REGEXP_MATCH_TYPE_CMD, OS_PROC_REGEXP_MATCH_TYPE_PARENT_PROC_NAME,
+    OS_PROC_REGEXP_MATCH_TYPE_PROC_NAME, OS_PROC_REGEXP_MATCH_TYPE_TAG,
 };
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub use handler::FlowAccess;
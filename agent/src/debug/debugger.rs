@@ -38,9 +38,12 @@ use tokio::runtime::Runtime;
 use super::{
     ebpf::{EbpfDebugger, EbpfMessage},
     platform::{PlatformDebugger, PlatformMessage},
+    profile::{ProfileDebugger, ProfileMessage},
 };
 use super::{
+    log_level::{LogDebugger, LogMessage},
     policy::{PolicyDebugger, PolicyMessage},
+    recent::{RecentDebugger, RecentMessage},
     rpc::{RpcDebugger, RpcMessage},
     Beacon, Message, Module, BEACON_INTERVAL, BEACON_INTERVAL_MIN, DEEPFLOW_AGENT_BEACON,
 };
@@ -66,6 +69,10 @@ struct ModuleDebuggers {
     pub policy: PolicyDebugger,
     #[cfg(target_os = "linux")]
     pub ebpf: EbpfDebugger,
+    pub recent: RecentDebugger,
+    pub log: LogDebugger,
+    #[cfg(target_os = "linux")]
+    pub profile: ProfileDebugger,
 }
 
 pub struct Debugger {
@@ -506,6 +513,10 @@ impl Debugger {
                         let msgs = debugger.queue_names();
                         iter_send_to(conn.0, conn.1, msgs.iter(), serialize_conf)?;
                     }
+                    QueueMessage::Stats(_) => {
+                        let msgs = debugger.queue_stats();
+                        iter_send_to(conn.0, conn.1, msgs.iter(), serialize_conf)?;
+                    }
                     QueueMessage::On((name, duration)) => {
                         let msg = debugger.turn_on_queue(name.as_str());
                         send_to(conn.0, conn.1, msg, serialize_conf)?;
@@ -547,6 +558,27 @@ impl Debugger {
                     _ => unreachable!(),
                 }
             }
+            Module::Recent => {
+                let req: Message<RecentMessage> =
+                    decode_from_std_read(&mut payload, serialize_conf)?;
+                debuggers
+                    .recent
+                    .send(conn.0, conn.1, serialize_conf, req.into_inner());
+            }
+            Module::Log => {
+                let req: Message<LogMessage> = decode_from_std_read(&mut payload, serialize_conf)?;
+                debuggers
+                    .log
+                    .send(conn.0, conn.1, serialize_conf, req.into_inner());
+            }
+            #[cfg(target_os = "linux")]
+            Module::Profile => {
+                let req: Message<ProfileMessage> =
+                    decode_from_std_read(&mut payload, serialize_conf)?;
+                debuggers
+                    .profile
+                    .send(conn.0, conn.1, serialize_conf, req.into_inner());
+            }
             _ => warn!("invalid module or invalid request, skip it"),
         }
 
@@ -572,6 +604,10 @@ impl Debugger {
             policy: PolicyDebugger::new(context.policy_setter),
             #[cfg(target_os = "linux")]
             ebpf: EbpfDebugger::new(),
+            recent: RecentDebugger,
+            log: LogDebugger,
+            #[cfg(target_os = "linux")]
+            profile: ProfileDebugger,
         };
 
         Self {
@@ -0,0 +1,91 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    net::{SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+use bincode::{config::Configuration, Decode, Encode};
+use log::{warn, Level};
+
+use crate::utils::logger;
+use public::debug::send_to;
+
+#[derive(PartialEq, Debug, Encode, Decode)]
+pub enum LogMessage {
+    Show,
+    // module, level, duration in seconds, 0 means no expiration
+    Set(String, String, u64),
+    // None resets all modules
+    Reset(Option<String>),
+    Context(String),
+    Done,
+    Err(String),
+}
+
+pub(super) struct LogDebugger;
+
+impl LogDebugger {
+    fn handle(msg: LogMessage) -> Result<Vec<String>, String> {
+        match msg {
+            LogMessage::Show => Ok(logger::log_levels()
+                .into_iter()
+                .map(|(module, level, remaining)| match remaining {
+                    Some(d) => format!(
+                        "{:<16}{:<8}reverts in {:?}",
+                        module,
+                        level,
+                        Duration::from_secs(d.as_secs())
+                    ),
+                    None => format!("{:<16}{}", module, level),
+                })
+                .collect()),
+            LogMessage::Set(module, level, duration) => {
+                let level = level
+                    .parse::<Level>()
+                    .map_err(|_| format!("invalid log level {}", level))?;
+                logger::set_module_log_level(&module, level, Duration::from_secs(duration))?;
+                Ok(vec![])
+            }
+            LogMessage::Reset(module) => {
+                logger::reset_module_log_level(module.as_deref())?;
+                Ok(vec![])
+            }
+            _ => Err("invalid request".to_owned()),
+        }
+    }
+
+    pub(super) fn send(
+        &self,
+        sock: &UdpSocket,
+        conn: SocketAddr,
+        serialize_conf: Configuration,
+        msg: LogMessage,
+    ) {
+        let result = match Self::handle(msg) {
+            Ok(lines) => lines
+                .into_iter()
+                .map(LogMessage::Context)
+                .chain([LogMessage::Done])
+                .try_for_each(|m| send_to(sock, conn, m, serialize_conf)),
+            Err(e) => send_to(sock, conn, LogMessage::Err(e), serialize_conf),
+        };
+        if let Err(e) = result {
+            warn!("send log level message error: {}", e);
+        }
+    }
+}
@@ -17,18 +17,26 @@
 mod debugger;
 #[cfg(target_os = "linux")]
 mod ebpf;
+mod log_level;
 #[cfg(target_os = "linux")]
 mod platform;
 mod policy;
+#[cfg(target_os = "linux")]
+mod profile;
+mod recent;
 mod rpc;
 
 use bincode::{Decode, Encode};
 pub use debugger::{Client, ConstructDebugCtx, Debugger};
 #[cfg(target_os = "linux")]
 pub use ebpf::EbpfMessage;
+pub use log_level::LogMessage;
 #[cfg(target_os = "linux")]
 pub use platform::PlatformMessage;
 pub use policy::PolicyMessage;
+#[cfg(target_os = "linux")]
+pub use profile::ProfileMessage;
+pub use recent::{record_flow, record_l7_log, RecentFilter, RecentMessage};
 pub use rpc::{ConfigResp, RpcMessage};
 
 use std::str;
@@ -54,6 +62,10 @@ pub enum Module {
     Policy,
     #[cfg(target_os = "linux")]
     Ebpf,
+    Recent,
+    Log,
+    #[cfg(target_os = "linux")]
+    Profile,
 }
 
 impl Default for Module {
@@ -0,0 +1,101 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    io::Write,
+    net::{SocketAddr, UdpSocket},
+    thread,
+    time::Duration,
+};
+
+use bincode::{config::Configuration, Decode, Encode};
+use flate2::{write::ZlibEncoder, Compression};
+use log::warn;
+
+use crate::utils::self_profiler::{self, ProfileFormat, ProfileType};
+use public::debug::send_to;
+
+// leaves room for the message header in MAX_BUF_SIZE
+const CHUNK_SIZE: usize = 8192;
+// paces the chunks to avoid overflowing the receive buffer of ctl
+const CHUNK_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(PartialEq, Debug, Encode, Decode)]
+pub enum ProfileMessage {
+    // profile type, duration in seconds, cpu sampling frequency, output format
+    Start(String, u64, i32, String),
+    // sequence and zlib compressed chunk of the profile
+    Chunk((u32, Vec<u8>)),
+    // number of chunks
+    Done(u32),
+    Err(String),
+}
+
+pub(super) struct ProfileDebugger;
+
+impl ProfileDebugger {
+    fn handle(msg: ProfileMessage) -> Result<Vec<u8>, String> {
+        let ProfileMessage::Start(profile_type, duration, frequency, format) = msg else {
+            return Err("invalid request".to_owned());
+        };
+        let data = self_profiler::profile(
+            ProfileType::try_from(profile_type.as_str())?,
+            Duration::from_secs(duration),
+            frequency,
+            ProfileFormat::try_from(format.as_str())?,
+        )?;
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&data)
+            .and_then(|_| encoder.finish())
+            .map_err(|e| format!("compress profile failed: {}", e))
+    }
+
+    pub(super) fn send(
+        &self,
+        sock: &UdpSocket,
+        conn: SocketAddr,
+        serialize_conf: Configuration,
+        msg: ProfileMessage,
+    ) {
+        let result = match Self::handle(msg) {
+            Ok(data) => data
+                .chunks(CHUNK_SIZE)
+                .enumerate()
+                .try_for_each(|(seq, chunk)| {
+                    thread::sleep(CHUNK_INTERVAL);
+                    send_to(
+                        sock,
+                        conn,
+                        ProfileMessage::Chunk((seq as u32, chunk.to_vec())),
+                        serialize_conf,
+                    )
+                })
+                .and_then(|_| {
+                    send_to(
+                        sock,
+                        conn,
+                        ProfileMessage::Done(data.chunks(CHUNK_SIZE).len() as u32),
+                        serialize_conf,
+                    )
+                }),
+            Err(e) => send_to(sock, conn, ProfileMessage::Err(e), serialize_conf),
+        };
+        if let Err(e) = result {
+            warn!("send profile message error: {}", e);
+        }
+    }
+}
@@ -0,0 +1,343 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    sync::Mutex,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use bincode::{config::Configuration, Decode, Encode};
+use chrono::{DateTime, Utc};
+use log::warn;
+
+use crate::{
+    common::{
+        flow::{CloseType, Flow},
+        l7_protocol_info::L7ProtocolInfoInterface,
+        Timestamp,
+    },
+    flow_generator::protocol_logs::{L7ResponseStatus, LogMessageType, MetaAppProto},
+};
+use public::{debug::send_to, enums::IpProtocol, l7_protocol::L7Protocol};
+
+// Recently closed flows and l7 logs are kept in memory, so that data collection
+// can be checked on the node without going through the server
+const RECENT_CAPACITY: usize = 1024;
+
+static RECENT_FLOWS: Mutex<VecDeque<Record>> = Mutex::new(VecDeque::new());
+static RECENT_L7_LOGS: Mutex<VecDeque<Record>> = Mutex::new(VecDeque::new());
+
+#[derive(PartialEq, Debug, Encode, Decode)]
+pub enum RecentMessage {
+    Flows(RecentFilter),
+    L7Logs(RecentFilter),
+    Context(String),
+    Done,
+    Err(String),
+}
+
+#[derive(PartialEq, Debug, Default, Encode, Decode)]
+pub struct RecentFilter {
+    // matches either side of the flow
+    pub ip: Option<String>,
+    pub port: Option<u16>,
+    // tcp, udp, icmp or l7 protocol name
+    pub protocol: Option<String>,
+    pub pod_id: Option<u32>,
+    // 0 means all records
+    pub limit: u32,
+}
+
+#[derive(Clone, Debug)]
+enum Detail {
+    Flow {
+        close_type: CloseType,
+        packets: [u64; 2],
+        bytes: [u64; 2],
+    },
+    L7Log {
+        msg_type: LogMessageType,
+        status: L7ResponseStatus,
+        rrt: u64,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Record {
+    time: Timestamp,
+    ip_src: IpAddr,
+    ip_dst: IpAddr,
+    port_src: u16,
+    port_dst: u16,
+    protocol: IpProtocol,
+    l7_protocol: L7Protocol,
+    pod_ids: [u32; 2],
+    detail: Detail,
+}
+
+impl Record {
+    fn matches(&self, filter: &RecentFilter, ip: Option<IpAddr>) -> bool {
+        if let Some(ip) = ip {
+            if self.ip_src != ip && self.ip_dst != ip {
+                return false;
+            }
+        }
+        if let Some(port) = filter.port {
+            if self.port_src != port && self.port_dst != port {
+                return false;
+            }
+        }
+        if let Some(pod_id) = filter.pod_id {
+            if !self.pod_ids.contains(&pod_id) {
+                return false;
+            }
+        }
+        match filter.protocol.as_ref().map(|p| p.to_lowercase()) {
+            Some(p) if p == "tcp" => self.protocol == IpProtocol::TCP,
+            Some(p) if p == "udp" => self.protocol == IpProtocol::UDP,
+            Some(p) if p == "icmp" => {
+                self.protocol == IpProtocol::ICMPV4 || self.protocol == IpProtocol::ICMPV6
+            }
+            Some(p) => self.l7_protocol == L7Protocol::from(p),
+            None => true,
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time: DateTime<Utc> = (UNIX_EPOCH + Duration::from(self.time)).into();
+        write!(
+            f,
+            "{} {} -> {} proto: {} l7_proto: {:?} pod_ids: {:?}",
+            time.format("%H:%M:%S%.3f"),
+            SocketAddr::new(self.ip_src, self.port_src),
+            SocketAddr::new(self.ip_dst, self.port_dst),
+            u8::from(self.protocol),
+            self.l7_protocol,
+            self.pod_ids,
+        )?;
+        match &self.detail {
+            Detail::Flow {
+                close_type,
+                packets,
+                bytes,
+            } => write!(
+                f,
+                " close_type: {:?} packets: {:?} bytes: {:?}",
+                close_type, packets, bytes
+            ),
+            Detail::L7Log {
+                msg_type,
+                status,
+                rrt,
+            } => write!(
+                f,
+                " msg_type: {:?} status: {:?} rrt: {}us",
+                msg_type, status, rrt
+            ),
+        }
+    }
+}
+
+fn push(ring: &Mutex<VecDeque<Record>>, record: Record) {
+    // skip the record rather than blocking the data path
+    let Ok(mut ring) = ring.try_lock() else {
+        return;
+    };
+    if ring.len() >= RECENT_CAPACITY {
+        ring.pop_front();
+    }
+    ring.push_back(record);
+}
+
+pub fn record_flow(flow: &Flow) {
+    let peers = &flow.flow_metrics_peers;
+    push(
+        &RECENT_FLOWS,
+        Record {
+            time: flow.end_time,
+            ip_src: flow.flow_key.ip_src,
+            ip_dst: flow.flow_key.ip_dst,
+            port_src: flow.flow_key.port_src,
+            port_dst: flow.flow_key.port_dst,
+            protocol: flow.flow_key.proto,
+            l7_protocol: flow
+                .flow_perf_stats
+                .as_ref()
+                .map(|s| s.l7_protocol)
+                .unwrap_or_default(),
+            pod_ids: [flow.pod_id, 0],
+            detail: Detail::Flow {
+                close_type: flow.close_type,
+                packets: [peers[0].total_packet_count, peers[1].total_packet_count],
+                bytes: [peers[0].total_byte_count, peers[1].total_byte_count],
+            },
+        },
+    );
+}
+
+pub fn record_l7_log(log: &MetaAppProto) {
+    let base = &log.base_info;
+    push(
+        &RECENT_L7_LOGS,
+        Record {
+            time: base.end_time,
+            ip_src: base.ip_src,
+            ip_dst: base.ip_dst,
+            port_src: base.port_src,
+            port_dst: base.port_dst,
+            protocol: base.protocol,
+            l7_protocol: base.head.proto,
+            pod_ids: [base.pod_id_0, base.pod_id_1],
+            detail: Detail::L7Log {
+                msg_type: base.head.msg_type,
+                status: log.l7_info.get_response_status(),
+                rrt: base.head.rrt,
+            },
+        },
+    );
+}
+
+pub(super) struct RecentDebugger;
+
+impl RecentDebugger {
+    pub(super) fn send(
+        &self,
+        sock: &UdpSocket,
+        conn: SocketAddr,
+        serialize_conf: Configuration,
+        msg: RecentMessage,
+    ) {
+        let (ring, filter) = match msg {
+            RecentMessage::Flows(f) => (&RECENT_FLOWS, f),
+            RecentMessage::L7Logs(f) => (&RECENT_L7_LOGS, f),
+            _ => return,
+        };
+        let ip = match filter.ip.as_ref().map(|ip| ip.parse::<IpAddr>()) {
+            Some(Ok(ip)) => Some(ip),
+            Some(Err(e)) => {
+                let _ = send_to(
+                    sock,
+                    conn,
+                    RecentMessage::Err(format!("invalid ip {:?}: {}", filter.ip, e)),
+                    serialize_conf,
+                );
+                return;
+            }
+            None => None,
+        };
+
+        // collect before sending to keep the lock short
+        let records = {
+            let ring = ring.lock().unwrap();
+            let limit = if filter.limit == 0 {
+                usize::MAX
+            } else {
+                filter.limit as usize
+            };
+            let mut records = ring
+                .iter()
+                .rev()
+                .filter(|r| r.matches(&filter, ip))
+                .take(limit)
+                .map(|r| r.to_string())
+                .collect::<Vec<_>>();
+            records.reverse();
+            records
+        };
+        for r in records {
+            if let Err(e) = send_to(sock, conn, RecentMessage::Context(r), serialize_conf) {
+                warn!("send recent record error: {}", e);
+            }
+        }
+        let _ = send_to(sock, conn, RecentMessage::Done, serialize_conf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(ip_dst: &str, port_dst: u16, l7_protocol: L7Protocol) -> Record {
+        Record {
+            time: Timestamp::from_secs(1),
+            ip_src: "10.0.0.1".parse().unwrap(),
+            ip_dst: ip_dst.parse().unwrap(),
+            port_src: 40000,
+            port_dst,
+            protocol: IpProtocol::TCP,
+            l7_protocol,
+            pod_ids: [7, 0],
+            detail: Detail::Flow {
+                close_type: CloseType::TcpFin,
+                packets: [1, 1],
+                bytes: [64, 64],
+            },
+        }
+    }
+
+    #[test]
+    fn filter() {
+        let r = record("10.0.0.2", 80, L7Protocol::Http1);
+        assert!(r.matches(&RecentFilter::default(), None));
+        assert!(r.matches(
+            &RecentFilter {
+                port: Some(80),
+                protocol: Some("HTTP".to_owned()),
+                pod_id: Some(7),
+                ..Default::default()
+            },
+            Some("10.0.0.2".parse().unwrap())
+        ));
+        assert!(r.matches(
+            &RecentFilter {
+                protocol: Some("tcp".to_owned()),
+                ..Default::default()
+            },
+            Some("10.0.0.1".parse().unwrap())
+        ));
+        assert!(!r.matches(
+            &RecentFilter {
+                port: Some(443),
+                ..Default::default()
+            },
+            None
+        ));
+        assert!(!r.matches(
+            &RecentFilter {
+                protocol: Some("mysql".to_owned()),
+                ..Default::default()
+            },
+            None
+        ));
+        assert!(!r.matches(&RecentFilter::default(), Some("10.0.0.3".parse().unwrap())));
+    }
+
+    #[test]
+    fn ring_capacity() {
+        let ring = Mutex::new(VecDeque::new());
+        for port in 0..RECENT_CAPACITY as u16 + 10 {
+            push(&ring, record("10.0.0.2", port, L7Protocol::Unknown));
+        }
+        let ring = ring.lock().unwrap();
+        assert_eq!(ring.len(), RECENT_CAPACITY);
+        assert_eq!(ring.front().unwrap().port_dst, 10);
+    }
+}
@@ -271,6 +271,8 @@ impl AnalyzerModeDispatcher {
         let collector_config = base.collector_config.clone();
         let packet_sequence_output_queue = base.packet_sequence_output_queue.clone(); // Enterprise Edition Feature: packet-sequence
         let stats = base.stats.clone();
+        let capture_point_dedup = base.capture_point_dedup.clone();
+        let conntrack_table = base.conntrack_table.clone();
 
         self.flow_generator_thread_handler.replace(
             thread::Builder::new()
@@ -289,6 +291,8 @@ impl AnalyzerModeDispatcher {
                         &flow_map_config.load(),
                         Some(packet_sequence_output_queue), // Enterprise Edition Feature: packet-sequence
                         stats,
+                        capture_point_dedup,
+                        conntrack_table,
                         false, // !from_ebpf
                     );
 
@@ -48,8 +48,9 @@ use crate::{
     },
     config::{handler::FlowAccess, DispatcherConfig},
     exception::ExceptionHandler,
-    flow_generator::AppProto,
+    flow_generator::{AppProto, CapturePointDedup},
     handler::PacketHandlerBuilder,
+    platform::ConntrackTable,
     policy::PolicyGetter,
     rpc::get_timestamp,
     utils::{bytes::read_u16_be, stats::Collector},
@@ -104,6 +105,8 @@ pub(super) struct BaseDispatcher {
     pub(super) counter: Arc<PacketCounter>,
     pub(super) terminated: Arc<AtomicBool>,
     pub(super) stats: Arc<Collector>,
+    pub(super) capture_point_dedup: Option<Arc<CapturePointDedup>>,
+    pub(super) conntrack_table: Option<Arc<ConntrackTable>>,
     #[cfg(target_os = "linux")]
     pub(super) platform_poller: Arc<crate::platform::GenericPoller>,
 
@@ -74,6 +74,8 @@ impl LocalModeDispatcher {
             &base.flow_map_config.load(),
             Some(base.packet_sequence_output_queue.clone()), // Enterprise Edition Feature: packet-sequence
             base.stats.clone(),
+            base.capture_point_dedup.clone(),
+            base.conntrack_table.clone(),
             false, // !from_ebpf
         );
 
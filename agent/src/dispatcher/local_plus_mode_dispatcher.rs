@@ -110,6 +110,8 @@ impl LocalPlusModeDispatcher {
         let collector_config = base.collector_config.clone();
         let packet_sequence_output_queue = base.packet_sequence_output_queue.clone(); // Enterprise Edition Feature: packet-sequence
         let stats = base.stats.clone();
+        let capture_point_dedup = base.capture_point_dedup.clone();
+        let conntrack_table = base.conntrack_table.clone();
         let pipelines = base.pipelines.clone();
         let tunnel_type_bitmap = base.tunnel_type_bitmap.clone();
         let tap_type_handler = base.tap_type_handler.clone();
@@ -134,6 +136,8 @@ impl LocalPlusModeDispatcher {
                         &flow_map_config.load(),
                         Some(packet_sequence_output_queue), // Enterprise Edition Feature: packet-sequence
                         stats,
+                        capture_point_dedup,
+                        conntrack_table,
                         false, // !from_ebpf
                     );
 
@@ -353,6 +353,7 @@ impl MirrorModeDispatcher {
         trident_type: TridentType,
         mac: u32,
         npb_dedup: bool,
+        capture_nic: u32,
     ) -> Result<()> {
         let pipeline = Self::get_pipeline(updated, pipelines, key, id, handler_builder);
 
@@ -365,6 +366,7 @@ impl MirrorModeDispatcher {
             dst_local,
             original_length,
         )?;
+        meta_packet.capture_nic = capture_nic;
 
         Self::prepare_flow(
             &mut meta_packet,
@@ -401,6 +403,8 @@ impl MirrorModeDispatcher {
             &self.base.flow_map_config.load(),
             Some(self.base.packet_sequence_output_queue.clone()), // Enterprise Edition Feature: packet-sequence
             self.base.stats.clone(),
+            self.base.capture_point_dedup.clone(),
+            self.base.conntrack_table.clone(),
             false, // !from_ebpf
         );
 
@@ -474,6 +478,18 @@ impl MirrorModeDispatcher {
                 len
             };
 
+            // With PACKET_ORIGDEV, packets received on bond masters or vlan sub-interfaces
+            // carry the index of the physical interface
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            let capture_nic =
+                if packet.if_index > 0 && packet.if_index as u32 != self.base.src_interface_index {
+                    packet.if_index as u32
+                } else {
+                    0
+                };
+            #[cfg(target_os = "windows")]
+            let capture_nic = 0;
+
             let original_length = packet.data.len() - decap_length;
             let overlay_packet = &mut packet.data[decap_length..decap_length + original_length];
 
@@ -510,6 +526,7 @@ impl MirrorModeDispatcher {
                     trident_type,
                     self.mac,
                     self.base.npb_dedup_enabled.load(Ordering::Relaxed),
+                    capture_nic,
                 );
                 continue;
             }
@@ -537,6 +554,7 @@ impl MirrorModeDispatcher {
                         self.mac
                     },
                     self.base.npb_dedup_enabled.load(Ordering::Relaxed),
+                    capture_nic,
                 );
             }
             if da_gateway_vmac > 0 {
@@ -562,6 +580,7 @@ impl MirrorModeDispatcher {
                         self.mac
                     },
                     self.base.npb_dedup_enabled.load(Ordering::Relaxed),
+                    capture_nic,
                 );
             }
         }
@@ -52,7 +52,9 @@ use mirror_mode_dispatcher::{MirrorModeDispatcher, MirrorModeDispatcherListener}
 pub use recv_engine::RecvEngine;
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub use recv_engine::{
-    af_packet::{self, bpf::*, BpfSyntax, OptTpacketVersion, RawInstruction, Tpacket},
+    af_packet::{
+        self, bpf::*, BpfSyntax, OptFanout, OptTpacketVersion, OptXdpScope, RawInstruction, Tpacket,
+    },
     DEFAULT_BLOCK_SIZE, FRAME_SIZE_MAX, FRAME_SIZE_MIN, POLL_TIMEOUT,
 };
 
@@ -70,8 +72,9 @@ use crate::{
         DispatcherConfig,
     },
     exception::ExceptionHandler,
-    flow_generator::AppProto,
+    flow_generator::{AppProto, CapturePointDedup},
     handler::{PacketHandler, PacketHandlerBuilder},
+    platform::ConntrackTable,
     policy::PolicyGetter,
     utils::{
         environment::get_mac_by_name,
@@ -457,21 +460,11 @@ impl BpfOptions {
             bf_insns: std::ptr::null_mut(),
         };
         unsafe {
-            #[cfg(target_arch = "x86_64")]
             let ret = pcap_sys::pcap_compile_nopcap(
                 0xffff as libc::c_int,
                 1,
                 &mut prog,
-                self.capture_bpf.as_ptr() as *const i8,
-                1,
-                0xffffffff,
-            );
-            #[cfg(target_arch = "aarch64")]
-            let ret = pcap_sys::pcap_compile_nopcap(
-                0xffff as libc::c_int,
-                1,
-                &mut prog,
-                self.capture_bpf.as_ptr() as *const u8,
+                self.capture_bpf.as_ptr() as *const libc::c_char,
                 1,
                 0xffffffff,
             );
@@ -569,6 +562,14 @@ pub struct Options {
     pub packet_blocks: usize,
     #[cfg(any(target_os = "linux", target_os = "android"))]
     pub af_packet_version: OptTpacketVersion,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub af_packet_fanout: Option<OptFanout>,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub af_packet_promisc: Option<bool>,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub af_packet_xdp_scope: Option<OptXdpScope>,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub af_packet_origdev: bool,
     pub snap_len: usize,
     pub tap_mode: TapMode,
     pub dpdk_enabled: bool,
@@ -700,6 +701,8 @@ pub struct DispatcherBuilder {
     packet_sequence_output_queue:
         Option<DebugSender<Box<packet_sequence_block::PacketSequenceBlock>>>, // Enterprise Edition Feature: packet-sequence
     stats_collector: Option<Arc<Collector>>,
+    capture_point_dedup: Option<Arc<CapturePointDedup>>,
+    conntrack_table: Option<Arc<ConntrackTable>>,
     flow_map_config: Option<FlowAccess>,
     log_parse_config: Option<LogParserAccess>,
     collector_config: Option<CollectorAccess>,
@@ -817,6 +820,16 @@ impl DispatcherBuilder {
         self
     }
 
+    pub fn capture_point_dedup(mut self, v: Arc<CapturePointDedup>) -> Self {
+        self.capture_point_dedup = Some(v);
+        self
+    }
+
+    pub fn conntrack_table(mut self, v: Option<Arc<ConntrackTable>>) -> Self {
+        self.conntrack_table = v;
+        self
+    }
+
     pub fn flow_map_config(mut self, v: FlowAccess) -> Self {
         self.flow_map_config = Some(v);
         self
@@ -996,6 +1009,8 @@ impl DispatcherBuilder {
             counter: stat_counter.clone(),
             terminated: terminated.clone(),
             stats: collector.clone(),
+            capture_point_dedup: self.capture_point_dedup.take(),
+            conntrack_table: self.conntrack_table.take(),
             flow_map_config: self
                 .flow_map_config
                 .take()
@@ -1218,6 +1233,10 @@ impl DispatcherBuilder {
                     poll_timeout: POLL_TIMEOUT.as_nanos() as isize,
                     version: options.af_packet_version,
                     iface: src_interface.as_ref().unwrap_or(&"".to_string()).clone(),
+                    fanout: options.af_packet_fanout,
+                    promisc: options.af_packet_promisc,
+                    xdp_scope: options.af_packet_xdp_scope.clone(),
+                    origdev: options.af_packet_origdev,
                     ..Default::default()
                 };
                 info!("Afpacket init with {:?}", afp);
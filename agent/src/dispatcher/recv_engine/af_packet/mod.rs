@@ -19,10 +19,14 @@ mod header;
 pub mod options;
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub mod tpacket;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod xdp;
 
 pub use bpf::*;
 #[cfg(any(target_os = "linux", target_os = "android"))]
-pub use options::{OptSocketType, OptTpacketVersion, Options};
+pub use options::{
+    OptFanout, OptFanoutMode, OptSocketType, OptTpacketVersion, OptXdpScope, Options,
+};
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub use tpacket::Tpacket;
 
@@ -20,6 +20,8 @@ pub use public::error::af_packet::{Error, Result};
 
 use public::proto::trident::CaptureSocketType;
 
+use crate::config::AfPacketFanoutMode;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd)]
 pub enum OptTpacketVersion {
     TpacketVersionHighestavailablet = -1,
@@ -69,6 +71,47 @@ impl OptSocketType {
     }
 }
 
+// PACKET_FANOUT_* in linux/if_packet.h
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptFanoutMode {
+    Hash = 0,
+    Lb = 1,
+    Cpu = 2,
+    Qm = 5,
+}
+
+impl From<AfPacketFanoutMode> for OptFanoutMode {
+    fn from(m: AfPacketFanoutMode) -> Self {
+        match m {
+            AfPacketFanoutMode::FlowHash => Self::Hash,
+            AfPacketFanoutMode::Cpu => Self::Cpu,
+            AfPacketFanoutMode::RoundRobin => Self::Lb,
+            AfPacketFanoutMode::QueueMapping => Self::Qm,
+        }
+    }
+}
+
+// Sockets in the same fanout group share packets of the interface
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OptFanout {
+    pub group_id: u16,
+    pub mode: OptFanoutMode,
+}
+
+// Packets whose source and destination are both outside cidrs are dropped by
+// an XDP program before reaching the socket
+#[derive(Clone, Debug, PartialEq)]
+pub struct OptXdpScope {
+    pub cidrs: Vec<ipnet::IpNet>,
+    // attach in driver mode instead of generic (skb) mode
+    pub native_mode: bool,
+}
+
+impl OptXdpScope {
+    // each cidr compiles to a few instructions per address
+    pub const MAX_CIDRS: usize = 256;
+}
+
 #[derive(Clone, Debug)]
 pub struct Options {
     pub frame_size: u32,
@@ -80,6 +123,12 @@ pub struct Options {
     pub version: OptTpacketVersion,
     pub socket_type: OptSocketType,
     pub iface: String,
+    pub fanout: Option<OptFanout>,
+    // None leaves promiscuous mode of the interface unchanged
+    pub promisc: Option<bool>,
+    // report the original device of packets received on bond masters
+    pub origdev: bool,
+    pub xdp_scope: Option<OptXdpScope>,
 }
 
 impl Default for Options {
@@ -94,6 +143,10 @@ impl Default for Options {
             version: OptTpacketVersion::TpacketVersionHighestavailablet,
             socket_type: OptSocketType::SocketTypeRaw,
             iface: "".to_string(),
+            fanout: None,
+            promisc: None,
+            origdev: false,
+            xdp_scope: None,
         }
     }
 }
@@ -120,6 +173,15 @@ impl Options {
         if self.version.invalid() {
             return Err(Error::InvalidOption("tpacket version is invalid."));
         }
+        if self.fanout.is_some() && self.iface.is_empty() {
+            return Err(Error::InvalidOption("fanout requires an interface."));
+        }
+        if self.promisc.is_some() && self.iface.is_empty() {
+            return Err(Error::InvalidOption("promisc requires an interface."));
+        }
+        if self.xdp_scope.is_some() && self.iface.is_empty() {
+            return Err(Error::InvalidOption("xdp scope requires an interface."));
+        }
         Ok(())
     }
 
@@ -149,5 +211,14 @@ mod tests {
             ..Default::default()
         };
         assert!(opts.check().is_err());
+
+        let opts = Options {
+            fanout: Some(OptFanout {
+                group_id: 1,
+                mode: AfPacketFanoutMode::RoundRobin.into(),
+            }),
+            ..Default::default()
+        };
+        assert!(opts.check().is_err());
     }
 }
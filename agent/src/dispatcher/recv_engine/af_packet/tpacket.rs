@@ -19,19 +19,20 @@ use std::io;
 use std::mem;
 use std::net::Shutdown;
 use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::sync::Arc;
 
 use libc::{
-    c_int, c_uint, c_void, getsockopt, mmap, munmap, off_t, poll, pollfd, setsockopt, size_t,
-    sockaddr, sockaddr_ll, socket, socklen_t, write, AF_PACKET, ETH_P_ALL, MAP_LOCKED,
-    MAP_NORESERVE, MAP_SHARED, POLLERR, POLLIN, PROT_READ, PROT_WRITE, SOL_PACKET, SOL_SOCKET,
-    SO_ATTACH_FILTER,
+    c_int, c_short, c_uint, c_ushort, c_void, getsockopt, ioctl, mmap, munmap, off_t, poll, pollfd,
+    setsockopt, size_t, sockaddr, sockaddr_ll, socket, socklen_t, write, AF_PACKET, ETH_P_ALL,
+    MAP_LOCKED, MAP_NORESERVE, MAP_SHARED, POLLERR, POLLIN, PROT_READ, PROT_WRITE, SOL_PACKET,
+    SOL_SOCKET, SO_ATTACH_FILTER,
 };
-use log::warn;
+use log::{info, warn};
 use public::error::*;
 use public::packet::Packet;
 use socket2::Socket;
 
-use super::{bpf, header, options};
+use super::{bpf, header, options, xdp};
 
 use crate::utils::stats;
 use public::utils::net::{self, link_by_name};
@@ -39,6 +40,15 @@ use public::utils::net::{self, link_by_name};
 const PACKET_VERSION: c_int = 10;
 const PACKET_RX_RING: c_int = 5;
 const PACKET_STATISTICS: c_int = 6;
+const PACKET_FANOUT: c_int = 18;
+const PACKET_FANOUT_FLAG_DEFRAG: c_int = 0x8000;
+const PACKET_ORIGDEV: c_int = 9;
+const PACKET_ADD_MEMBERSHIP: c_int = 1;
+const PACKET_MR_PROMISC: c_ushort = 1;
+const SIOCGIFFLAGS: u64 = 0x8913;
+const SIOCSIFFLAGS: u64 = 0x8914;
+const IFF_PROMISC: c_short = 0x100;
+const IFNAMSIZ: usize = 16;
 const MILLI_SECONDS: u32 = 1000000;
 
 // https://www.ietf.org/archive/id/draft-gharris-opsawg-pcap-01.html
@@ -66,6 +76,21 @@ pub struct TpacketStatsV3 {
     pub tp_freeze_q_cnt: c_uint,
 }
 
+#[repr(C)]
+struct PacketMreq {
+    mr_ifindex: c_int,
+    mr_type: c_ushort,
+    mr_alen: c_ushort,
+    mr_address: [u8; 8],
+}
+
+#[repr(C)]
+struct IfReqFlags {
+    ifr_name: [u8; IFNAMSIZ],
+    ifr_flags: c_short,
+    _pad: [u8; 22],
+}
+
 pub struct Tpacket {
     _stats: Stats,
 
@@ -82,6 +107,12 @@ pub struct Tpacket {
     tp_version: options::OptTpacketVersion,
 
     v3: Option<*mut header::V3Wrapper>,
+
+    // promiscuous flag of the interface cleared by us, restored on drop
+    promisc_cleared: bool,
+
+    // detached when the last socket on the interface drops it
+    xdp_filter: Option<Arc<xdp::XdpFilter>>,
 }
 
 impl Debug for Tpacket {
@@ -97,7 +128,7 @@ impl Debug for Tpacket {
 unsafe impl Send for Tpacket {}
 
 impl Tpacket {
-    fn bind(&self) -> af_packet::Result<()> {
+    fn bind(&self) -> af_packet::Result<i32> {
         let mut if_index: i32 = 0;
 
         if self.opts.iface != "" {
@@ -124,25 +155,86 @@ impl Tpacket {
                 return Err(io::Error::last_os_error().into());
             }
         }
-        Ok(())
+        Ok(if_index)
     }
 
-    // TODO: 这里看起来不需要，golang版本未涉及该配置，后续有需要再添加
-    #[allow(dead_code)]
-    fn set_promisc(&self) -> Result<()> {
-        // 设置混杂模式
-
-        //raw_socket.set_flag(IFF_PROMISC as u64)?;
+    fn if_flags_request(&self) -> IfReqFlags {
+        let mut req = IfReqFlags {
+            ifr_name: [0; IFNAMSIZ],
+            ifr_flags: 0,
+            _pad: [0; 22],
+        };
+        let name = self.opts.iface.as_bytes();
+        let len = name.len().min(IFNAMSIZ - 1);
+        req.ifr_name[..len].copy_from_slice(&name[..len]);
+        req
+    }
 
-        // TODO:
-        //let mut mreq: packet_mreq = std::mem::zeroed();
-        //mreq.mr_ifindex = interface.index as i32;
-        //mreq.mr_type = PACKET_MR_PROMISC as u16;
+    fn get_if_flags(&self) -> af_packet::Result<c_short> {
+        let mut req = self.if_flags_request();
+        if unsafe { ioctl(self.raw_socket.as_raw_fd(), SIOCGIFFLAGS as _, &mut req) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(req.ifr_flags)
+    }
 
-        //raw_socket.setsockopt(SOL_PACKET, PACKET_ADD_MEMBERSHIP, (&mreq as *const packet_mreq) as *const libc::c_void);
+    fn set_if_flags(&self, flags: c_short) -> af_packet::Result<()> {
+        let mut req = self.if_flags_request();
+        req.ifr_flags = flags;
+        if unsafe { ioctl(self.raw_socket.as_raw_fd(), SIOCSIFFLAGS as _, &mut req) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
         Ok(())
     }
 
+    fn set_promisc(&mut self, if_index: i32) -> af_packet::Result<()> {
+        match self.opts.promisc {
+            // membership is dropped by kernel when the socket is closed,
+            // so the interface is restored even if the agent crashes
+            Some(true) => self.setsockopt(
+                SOL_PACKET,
+                PACKET_ADD_MEMBERSHIP,
+                PacketMreq {
+                    mr_ifindex: if_index,
+                    mr_type: PACKET_MR_PROMISC,
+                    mr_alen: 0,
+                    mr_address: [0; 8],
+                },
+            ),
+            Some(false) => {
+                let flags = self.get_if_flags()?;
+                if flags & IFF_PROMISC != 0 {
+                    self.set_if_flags(flags & !IFF_PROMISC)?;
+                    self.promisc_cleared = true;
+                    info!("Afpacket promiscuous mode of {} disabled", self.opts.iface);
+                }
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn restore_promisc(&mut self) {
+        if !self.promisc_cleared {
+            return;
+        }
+        self.promisc_cleared = false;
+        match self.get_if_flags() {
+            Ok(flags) => {
+                if let Err(e) = self.set_if_flags(flags | IFF_PROMISC) {
+                    warn!(
+                        "Afpacket restore promiscuous mode of {} failed: {:?}",
+                        self.opts.iface, e
+                    );
+                }
+            }
+            Err(e) => warn!(
+                "Afpacket restore promiscuous mode of {} failed: {:?}",
+                self.opts.iface, e
+            ),
+        }
+    }
+
     fn setsockopt<T>(&self, level: i32, name: i32, value: T) -> af_packet::Result<()> {
         unsafe {
             let value = &value as *const T as *const c_void;
@@ -161,6 +253,50 @@ impl Tpacket {
         }
     }
 
+    fn set_fanout(&self) -> af_packet::Result<()> {
+        let Some(fanout) = self.opts.fanout else {
+            return Ok(());
+        };
+        let mut fanout_type = fanout.mode as c_int;
+        if fanout.mode == options::OptFanoutMode::Hash {
+            // reassemble ip fragments so that they are hashed to the same socket
+            fanout_type |= PACKET_FANOUT_FLAG_DEFRAG;
+        }
+        self.setsockopt(
+            SOL_PACKET,
+            PACKET_FANOUT,
+            fanout.group_id as c_int | fanout_type << 16,
+        )
+    }
+
+    fn set_origdev(&self) -> af_packet::Result<()> {
+        if !self.opts.origdev {
+            return Ok(());
+        }
+        self.setsockopt(SOL_PACKET, PACKET_ORIGDEV, 1 as c_int)
+    }
+
+    // Failing to attach the filter only costs performance, capture goes on without it
+    fn set_xdp_filter(&mut self, if_index: i32) {
+        let Some(scope) = self.opts.xdp_scope.as_ref() else {
+            return;
+        };
+        match xdp::XdpFilter::attach(if_index, scope) {
+            Ok(filter) => {
+                info!(
+                    "Afpacket xdp pre-filter of {} attached with {} cidrs",
+                    self.opts.iface,
+                    scope.cidrs.len()
+                );
+                self.xdp_filter = Some(filter);
+            }
+            Err(e) => warn!(
+                "Afpacket attach xdp pre-filter to {} failed: {}, capture without it",
+                self.opts.iface, e
+            ),
+        }
+    }
+
     fn set_version_internal(&mut self, tp_version: options::OptTpacketVersion) -> bool {
         // 设置af packet版本
         self.setsockopt(SOL_PACKET, PACKET_VERSION, tp_version as c_int)
@@ -385,11 +521,17 @@ impl Tpacket {
             header_next_needed: false,
             tp_version: opts.version,
             v3: Option::None,
+            promisc_cleared: false,
+            xdp_filter: None,
         };
-        tpacket.bind()?;
+        let if_index = tpacket.bind()?;
+        tpacket.set_promisc(if_index)?;
+        tpacket.set_origdev()?;
+        tpacket.set_xdp_filter(if_index);
         tpacket.set_version()?;
         tpacket.set_ring()?;
         tpacket.mmap_ring()?;
+        tpacket.set_fanout()?;
         tpacket.set_bpf(vec![bpf::BpfSyntax::RetConstant(bpf::RetConstant {
             val: 0,
         })
@@ -400,6 +542,7 @@ impl Tpacket {
 
 impl Drop for Tpacket {
     fn drop(&mut self) {
+        self.restore_promisc();
         if !self.ring.is_null() {
             unsafe {
                 munmap(
@@ -0,0 +1,559 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// XDP pre-filter dropping packets out of capture scope before they reach af_packet
+//
+// The program is assembled here from the scope CIDRs, IPv4/IPv6 packets (with at most
+// one VLAN tag) whose source and destination are both out of scope are dropped, other
+// packets are passed. It is attached with a bpf link, which is detached by kernel when
+// the link fd is closed, so the interface is restored even if the agent exits abnormally.
+
+use std::{
+    io, mem,
+    net::IpAddr,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    sync::{Arc, Mutex, Weak},
+};
+
+use ipnet::IpNet;
+use libc::{c_long, syscall, SYS_bpf};
+use log::info;
+
+use super::options::OptXdpScope;
+
+const BPF_PROG_LOAD: c_long = 5;
+const BPF_LINK_CREATE: c_long = 28;
+const BPF_PROG_TYPE_XDP: u32 = 6;
+const BPF_XDP: u32 = 37;
+const XDP_FLAGS_SKB_MODE: u32 = 1 << 1;
+const XDP_FLAGS_DRV_MODE: u32 = 1 << 2;
+const XDP_DROP: i32 = 1;
+const XDP_PASS: i32 = 2;
+const VERIFIER_LOG_SIZE: usize = 64 << 10;
+
+// instruction classes and fields
+const BPF_LDX: u8 = 0x01;
+const BPF_ALU: u8 = 0x04;
+const BPF_JMP: u8 = 0x05;
+const BPF_ALU64: u8 = 0x07;
+const BPF_W: u8 = 0x00;
+const BPF_H: u8 = 0x08;
+const BPF_MEM: u8 = 0x60;
+const BPF_K: u8 = 0x00;
+const BPF_X: u8 = 0x08;
+const BPF_ADD: u8 = 0x00;
+const BPF_AND: u8 = 0x50;
+const BPF_MOV: u8 = 0xb0;
+const BPF_JA: u8 = 0x00;
+const BPF_JEQ: u8 = 0x10;
+const BPF_JGT: u8 = 0x20;
+const BPF_JNE: u8 = 0x50;
+const BPF_EXIT: u8 = 0x90;
+
+const ETH_HEADER_SIZE: i32 = 14;
+const ETH_TYPE_OFFSET: i16 = 12;
+const VLAN_HEADER_SIZE: i32 = 4;
+const IPV4_SRC_OFFSET: i16 = 14 + 12;
+const IPV4_HEADER_END: i32 = 14 + 20;
+const IPV6_SRC_OFFSET: i16 = 14 + 8;
+const IPV6_HEADER_END: i32 = 14 + 40;
+
+// registers
+const R0: u8 = 0;
+const R1: u8 = 1; // context
+const R2: u8 = 2; // packet start, advanced by vlan header
+const R3: u8 = 3; // packet end
+const R4: u8 = 4;
+const R5: u8 = 5;
+const R6: u8 = 6;
+const R7: u8 = 7;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct BpfInsn {
+    code: u8,
+    // dst_reg:4 and src_reg:4 bit fields
+    regs: u8,
+    off: i16,
+    imm: i32,
+}
+
+impl BpfInsn {
+    fn new(code: u8, dst: u8, src: u8, off: i16, imm: i32) -> Self {
+        #[cfg(target_endian = "little")]
+        let regs = (src << 4) | dst;
+        #[cfg(target_endian = "big")]
+        let regs = (dst << 4) | src;
+        Self {
+            code,
+            regs,
+            off,
+            imm,
+        }
+    }
+
+    #[cfg(test)]
+    fn dst(&self) -> usize {
+        #[cfg(target_endian = "little")]
+        return (self.regs & 0xf) as usize;
+        #[cfg(target_endian = "big")]
+        return (self.regs >> 4) as usize;
+    }
+
+    #[cfg(test)]
+    fn src(&self) -> usize {
+        #[cfg(target_endian = "little")]
+        return (self.regs >> 4) as usize;
+        #[cfg(target_endian = "big")]
+        return (self.regs & 0xf) as usize;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Label {
+    Pass,
+    Drop,
+    Ipv4,
+    Ipv6,
+    L3,
+    Next(usize),
+}
+
+#[derive(Default)]
+struct Assembler {
+    insns: Vec<BpfInsn>,
+    jumps: Vec<(usize, Label)>,
+    labels: Vec<(Label, usize)>,
+}
+
+impl Assembler {
+    fn push(&mut self, insn: BpfInsn) {
+        self.insns.push(insn);
+    }
+
+    fn label(&mut self, label: Label) {
+        self.labels.push((label, self.insns.len()));
+    }
+
+    fn jump(&mut self, code: u8, dst: u8, src: u8, imm: i32, target: Label) {
+        self.jumps.push((self.insns.len(), target));
+        self.push(BpfInsn::new(BPF_JMP | code, dst, src, 0, imm));
+    }
+
+    fn load(&mut self, size: u8, dst: u8, src: u8, off: i16) {
+        self.push(BpfInsn::new(BPF_LDX | BPF_MEM | size, dst, src, off, 0));
+    }
+
+    fn mov_reg(&mut self, dst: u8, src: u8) {
+        self.push(BpfInsn::new(BPF_ALU64 | BPF_MOV | BPF_X, dst, src, 0, 0));
+    }
+
+    fn mov32_imm(&mut self, dst: u8, imm: u32) {
+        self.push(BpfInsn::new(
+            BPF_ALU | BPF_MOV | BPF_K,
+            dst,
+            0,
+            0,
+            imm as i32,
+        ));
+    }
+
+    fn add_imm(&mut self, dst: u8, imm: i32) {
+        self.push(BpfInsn::new(BPF_ALU64 | BPF_ADD | BPF_K, dst, 0, 0, imm));
+    }
+
+    fn and32_imm(&mut self, dst: u8, imm: u32) {
+        self.push(BpfInsn::new(
+            BPF_ALU | BPF_AND | BPF_K,
+            dst,
+            0,
+            0,
+            imm as i32,
+        ));
+    }
+
+    // goto PASS if packet is shorter than start + len
+    fn check_bounds(&mut self, len: i32) {
+        self.mov_reg(R4, R2);
+        self.add_imm(R4, len);
+        self.jump(BPF_JGT | BPF_X, R4, R3, 0, Label::Pass);
+    }
+
+    fn ret(&mut self, action: i32) {
+        self.push(BpfInsn::new(BPF_ALU64 | BPF_MOV | BPF_K, R0, 0, 0, action));
+        self.push(BpfInsn::new(BPF_JMP | BPF_EXIT, 0, 0, 0, 0));
+    }
+
+    fn build(mut self) -> io::Result<Vec<BpfInsn>> {
+        for (at, target) in self.jumps.iter() {
+            let Some((_, to)) = self.labels.iter().find(|(l, _)| l == target) else {
+                return Err(io::Error::new(io::ErrorKind::Other, "label not found"));
+            };
+            let off = i16::try_from(*to as isize - *at as isize - 1)
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "jump out of range"))?;
+            self.insns[*at].off = off;
+        }
+        Ok(self.insns)
+    }
+}
+
+// Packet bytes loaded into register are in host byte order
+fn load_order_u16(v: u16) -> i32 {
+    u16::from_ne_bytes(v.to_be_bytes()) as i32
+}
+
+fn load_order_u32(bytes: &[u8]) -> u32 {
+    u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+// Compare address at off against cidrs, goto PASS if any matches
+fn match_cidrs(asm: &mut Assembler, off: i16, cidrs: &[(Vec<u8>, Vec<u8>)], next: &mut usize) {
+    for (addr, mask) in cidrs {
+        if mask.iter().all(|b| *b == 0) {
+            asm.jump(BPF_JA, 0, 0, 0, Label::Pass);
+            continue;
+        }
+        let label = Label::Next(*next);
+        *next += 1;
+        for w in 0..addr.len() / 4 {
+            let mask = load_order_u32(&mask[w * 4..]);
+            if mask == 0 {
+                continue;
+            }
+            asm.load(BPF_W, R6, R2, off + w as i16 * 4);
+            asm.and32_imm(R6, mask);
+            asm.mov32_imm(R7, load_order_u32(&addr[w * 4..]));
+            asm.jump(BPF_JNE | BPF_X, R6, R7, 0, label);
+        }
+        asm.jump(BPF_JA, 0, 0, 0, Label::Pass);
+        asm.label(label);
+    }
+}
+
+fn compile(cidrs: &[IpNet]) -> io::Result<Vec<BpfInsn>> {
+    if cidrs.len() > OptXdpScope::MAX_CIDRS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("more than {} cidrs", OptXdpScope::MAX_CIDRS),
+        ));
+    }
+    let (mut v4, mut v6) = (vec![], vec![]);
+    for cidr in cidrs {
+        match (cidr.network(), cidr.netmask()) {
+            (IpAddr::V4(a), IpAddr::V4(m)) => v4.push((a.octets().to_vec(), m.octets().to_vec())),
+            (IpAddr::V6(a), IpAddr::V6(m)) => v6.push((a.octets().to_vec(), m.octets().to_vec())),
+            _ => unreachable!(),
+        }
+    }
+
+    let mut asm = Assembler::default();
+    asm.load(BPF_W, R2, R1, 0); // xdp_md.data
+    asm.load(BPF_W, R3, R1, 4); // xdp_md.data_end
+    asm.check_bounds(ETH_HEADER_SIZE);
+    asm.load(BPF_H, R5, R2, ETH_TYPE_OFFSET);
+    asm.jump(BPF_JNE | BPF_K, R5, 0, load_order_u16(0x8100), Label::L3);
+    asm.add_imm(R2, VLAN_HEADER_SIZE);
+    asm.check_bounds(ETH_HEADER_SIZE);
+    asm.load(BPF_H, R5, R2, ETH_TYPE_OFFSET);
+    asm.label(Label::L3);
+    asm.jump(BPF_JEQ | BPF_K, R5, 0, load_order_u16(0x0800), Label::Ipv4);
+    asm.jump(BPF_JEQ | BPF_K, R5, 0, load_order_u16(0x86dd), Label::Ipv6);
+    asm.jump(BPF_JA, 0, 0, 0, Label::Pass);
+
+    let mut next = 0;
+    asm.label(Label::Ipv4);
+    asm.check_bounds(IPV4_HEADER_END);
+    match_cidrs(&mut asm, IPV4_SRC_OFFSET, &v4, &mut next);
+    match_cidrs(&mut asm, IPV4_SRC_OFFSET + 4, &v4, &mut next);
+    asm.jump(BPF_JA, 0, 0, 0, Label::Drop);
+
+    asm.label(Label::Ipv6);
+    asm.check_bounds(IPV6_HEADER_END);
+    match_cidrs(&mut asm, IPV6_SRC_OFFSET, &v6, &mut next);
+    match_cidrs(&mut asm, IPV6_SRC_OFFSET + 16, &v6, &mut next);
+
+    asm.label(Label::Drop);
+    asm.ret(XDP_DROP);
+    asm.label(Label::Pass);
+    asm.ret(XDP_PASS);
+    asm.build()
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct ProgLoadAttr {
+    prog_type: u32,
+    insn_cnt: u32,
+    insns: u64,
+    license: u64,
+    log_level: u32,
+    log_size: u32,
+    log_buf: u64,
+    kern_version: u32,
+    prog_flags: u32,
+    prog_name: [u8; 16],
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct LinkCreateAttr {
+    prog_fd: u32,
+    target_ifindex: u32,
+    attach_type: u32,
+    flags: u32,
+}
+
+fn bpf<T>(cmd: c_long, attr: &mut T) -> io::Result<OwnedFd> {
+    // SAFETY: attr is a valid bpf_attr prefix of size_of::<T>() bytes
+    let fd = unsafe { syscall(SYS_bpf, cmd, attr as *mut T, mem::size_of::<T>()) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: fd is newly created and owned by nobody else
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as i32) })
+}
+
+fn load_program(insns: &[BpfInsn]) -> io::Result<OwnedFd> {
+    let license = b"GPL\0";
+    let mut attr = ProgLoadAttr {
+        prog_type: BPF_PROG_TYPE_XDP,
+        insn_cnt: insns.len() as u32,
+        insns: insns.as_ptr() as u64,
+        license: license.as_ptr() as u64,
+        ..Default::default()
+    };
+    attr.prog_name[..12].copy_from_slice(b"deepflow_xdp");
+    match bpf(BPF_PROG_LOAD, &mut attr) {
+        Ok(fd) => Ok(fd),
+        Err(e)
+            if e.raw_os_error() == Some(libc::EACCES) || e.raw_os_error() == Some(libc::EINVAL) =>
+        {
+            // load again with verifier log for diagnosis
+            let mut log = vec![0u8; VERIFIER_LOG_SIZE];
+            attr.log_level = 1;
+            attr.log_size = log.len() as u32;
+            attr.log_buf = log.as_mut_ptr() as u64;
+            let _ = bpf(BPF_PROG_LOAD, &mut attr);
+            let len = log.iter().position(|b| *b == 0).unwrap_or(log.len());
+            Err(io::Error::new(
+                e.kind(),
+                format!(
+                    "{}, verifier log: {}",
+                    e,
+                    String::from_utf8_lossy(&log[..len])
+                ),
+            ))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub struct XdpFilter {
+    if_index: i32,
+    scope: OptXdpScope,
+    _link: OwnedFd,
+}
+
+// Filters are shared by sockets of the same interface, e.g. in a fanout group,
+// as only one xdp program can be attached with bpf link
+static FILTERS: Mutex<Vec<Weak<XdpFilter>>> = Mutex::new(Vec::new());
+
+impl XdpFilter {
+    pub fn attach(if_index: i32, scope: &OptXdpScope) -> io::Result<Arc<Self>> {
+        let mut filters = FILTERS.lock().unwrap();
+        filters.retain(|f| f.strong_count() > 0);
+        if let Some(filter) = filters
+            .iter()
+            .filter_map(|f| f.upgrade())
+            .find(|f| f.if_index == if_index)
+        {
+            if &filter.scope != scope {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "xdp filter with another scope is attached",
+                ));
+            }
+            return Ok(filter);
+        }
+
+        let insns = compile(&scope.cidrs)?;
+        let prog = load_program(&insns)?;
+        let mut attr = LinkCreateAttr {
+            prog_fd: prog.as_raw_fd() as u32,
+            target_ifindex: if_index as u32,
+            attach_type: BPF_XDP,
+            flags: if scope.native_mode {
+                XDP_FLAGS_DRV_MODE
+            } else {
+                XDP_FLAGS_SKB_MODE
+            },
+        };
+        // the program is referenced by link and closing prog fd is fine
+        let link = bpf(BPF_LINK_CREATE, &mut attr)?;
+        info!(
+            "Afpacket xdp filter attached to interface {} with {} instructions, scope: {:?}",
+            if_index,
+            insns.len(),
+            scope.cidrs
+        );
+        let filter = Arc::new(Self {
+            if_index,
+            scope: scope.clone(),
+            _link: link,
+        });
+        filters.push(Arc::downgrade(&filter));
+        Ok(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Minimal interpreter of instructions generated by compile()
+    fn run(insns: &[BpfInsn], packet: &[u8]) -> i32 {
+        const DATA: u64 = 0x1000_0000;
+        let mut regs = [0u64; 11];
+        let mut pc = 0;
+        loop {
+            let insn = insns[pc];
+            let (dst, src) = (insn.dst(), insn.src());
+            pc += 1;
+            let class = insn.code & 0x07;
+            match class {
+                BPF_LDX => {
+                    let addr = regs[src].wrapping_add(insn.off as i64 as u64);
+                    regs[dst] = if src == R1 as usize {
+                        // xdp_md
+                        match insn.off {
+                            0 => DATA,
+                            4 => DATA + packet.len() as u64,
+                            _ => unreachable!(),
+                        }
+                    } else {
+                        let at = (addr - DATA) as usize;
+                        match insn.code & 0x18 {
+                            BPF_W => load_order_u32(&packet[at..at + 4]) as u64,
+                            BPF_H => u16::from_ne_bytes([packet[at], packet[at + 1]]) as u64,
+                            _ => unreachable!(),
+                        }
+                    };
+                }
+                BPF_ALU | BPF_ALU64 => {
+                    let operand = if insn.code & BPF_X != 0 {
+                        regs[src]
+                    } else if class == BPF_ALU {
+                        insn.imm as u32 as u64
+                    } else {
+                        insn.imm as i64 as u64
+                    };
+                    let r = match insn.code & 0xf0 {
+                        BPF_MOV => operand,
+                        BPF_ADD => regs[dst].wrapping_add(operand),
+                        BPF_AND => regs[dst] & operand,
+                        _ => unreachable!(),
+                    };
+                    regs[dst] = if class == BPF_ALU { r as u32 as u64 } else { r };
+                }
+                BPF_JMP => {
+                    let operand = if insn.code & BPF_X != 0 {
+                        regs[src]
+                    } else {
+                        insn.imm as i64 as u64
+                    };
+                    let taken = match insn.code & 0xf0 {
+                        BPF_EXIT => return regs[R0 as usize] as i32,
+                        BPF_JA => true,
+                        BPF_JEQ => regs[dst] == operand,
+                        BPF_JNE => regs[dst] != operand,
+                        BPF_JGT => regs[dst] > operand,
+                        _ => unreachable!(),
+                    };
+                    if taken {
+                        pc = (pc as isize + insn.off as isize) as usize;
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn packet(vlan: bool, src: IpAddr, dst: IpAddr) -> Vec<u8> {
+        let mut p = vec![0u8; 12];
+        if vlan {
+            p.extend_from_slice(&[0x81, 0x00, 0x00, 0x64]);
+        }
+        match (src, dst) {
+            (IpAddr::V4(s), IpAddr::V4(d)) => {
+                p.extend_from_slice(&[0x08, 0x00]);
+                p.extend_from_slice(&[0x45, 0, 0, 20, 0, 0, 0, 0, 64, 6, 0, 0]);
+                p.extend_from_slice(&s.octets());
+                p.extend_from_slice(&d.octets());
+            }
+            (IpAddr::V6(s), IpAddr::V6(d)) => {
+                p.extend_from_slice(&[0x86, 0xdd]);
+                p.extend_from_slice(&[0x60, 0, 0, 0, 0, 0, 6, 64]);
+                p.extend_from_slice(&s.octets());
+                p.extend_from_slice(&d.octets());
+            }
+            _ => unreachable!(),
+        }
+        p
+    }
+
+    #[test]
+    fn prefilter() {
+        let cidrs = ["10.1.0.0/16", "192.168.1.10/32", "fd00:1::/32"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect::<Vec<IpNet>>();
+        let insns = compile(&cidrs).unwrap();
+
+        let cases = [
+            ("10.1.2.3", "8.8.8.8", XDP_PASS),
+            ("8.8.8.8", "10.1.200.1", XDP_PASS),
+            ("8.8.8.8", "192.168.1.10", XDP_PASS),
+            ("8.8.8.8", "192.168.1.11", XDP_DROP),
+            ("10.2.0.1", "172.16.0.1", XDP_DROP),
+            ("fd00:1::1", "2001:db8::1", XDP_PASS),
+            ("2001:db8::1", "fd00:2::1", XDP_DROP),
+        ];
+        for (src, dst, action) in cases {
+            for vlan in [false, true] {
+                let p = packet(vlan, src.parse().unwrap(), dst.parse().unwrap());
+                assert_eq!(run(&insns, &p), action, "{} -> {} vlan {}", src, dst, vlan);
+                // truncated packets are passed
+                assert_eq!(run(&insns, &p[..p.len() - 1]), XDP_PASS);
+            }
+        }
+        // arp
+        let mut arp = vec![0u8; 12];
+        arp.extend_from_slice(&[0x08, 0x06]);
+        arp.extend_from_slice(&[0; 28]);
+        assert_eq!(run(&insns, &arp), XDP_PASS);
+
+        // no ipv6 cidrs, all ipv6 dropped
+        let insns = compile(&["0.0.0.0/0".parse().unwrap()]).unwrap();
+        let p = packet(
+            false,
+            "8.8.8.8".parse().unwrap(),
+            "1.1.1.1".parse().unwrap(),
+        );
+        assert_eq!(run(&insns, &p), XDP_PASS);
+        let p = packet(false, "::1".parse().unwrap(), "::2".parse().unwrap());
+        assert_eq!(run(&insns, &p), XDP_DROP);
+    }
+}
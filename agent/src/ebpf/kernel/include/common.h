@@ -96,6 +96,7 @@ enum process_data_extra_source {
 	DATA_SOURCE_IO_EVENT,
 	DATA_SOURCE_GO_HTTP2_DATAFRAME_UPROBE,
 	DATA_SOURCE_CLOSE,
+	DATA_SOURCE_USDT_EVENT,
 };
 
 struct protocol_message_t {
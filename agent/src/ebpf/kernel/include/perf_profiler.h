@@ -38,6 +38,9 @@ typedef enum {
 	ENABLE_IDX,		/* Enable profiler sampling flag.
 				   0: disable sampling; 1: enable sampling. */
 	MINBLOCK_TIME_IDX,	/* The minimum blocking time, applied in the profiler extension.*/
+	TASK_STACK_OFFSET_IDX,	/* Offset of 'stack' in 'struct task_struct', used to
+				   find user registers of samples taken in kernel. */
+	DWARF_UNWIND_CNT_IDX,	/* Count the number of stacks unwound with DWARF. */
 	PROFILER_CNT
 } profiler_idx;
 
@@ -52,4 +55,79 @@ struct stack_trace_key_t {
 	__u64 duration_ns;
 };
 
+/*
+ * DWARF-based unwinding of user stacks, for binaries built without frame
+ * pointers. User space converts the '.eh_frame' section of each binary into
+ * a table of rows sorted by address, each row describes how to compute the
+ * CFA (Canonical Frame Address) and the caller's frame pointer (rbp) at the
+ * address. Tables of all binaries share one array, the executable mappings
+ * of each process refer to the range of their binary's table.
+ */
+#define DWARF_MAPPINGS_MAX		32
+#define DWARF_PROCS_MAX			1024
+#define DWARF_UNWIND_ENTRIES_MAX	(1 << 20)
+/* Binary search steps to cover DWARF_UNWIND_ENTRIES_MAX entries */
+#define DWARF_BSEARCH_STEPS		21
+#define DWARF_FRAMES_PER_PROG		8
+#define DWARF_TAIL_CALLS_MAX		7
+#define DWARF_STACK_MAP_ENTRIES		16384
+/*
+ * Stack IDs of stacks unwound with DWARF are flagged, the frames are
+ * stored in '__dwarf_stack_map' instead of '__stack_map_a/b'.
+ */
+#define DWARF_STACK_ID_FLAG		(1 << 30)
+
+/* tail calls of perf event programs */
+#define PROG_DWARF_UNWIND_PE_IDX	0
+#define PROG_PE_NUM			1
+
+enum dwarf_cfa_type {
+	DWARF_CFA_TYPE_RSP,	/* CFA = rsp + offset */
+	DWARF_CFA_TYPE_RBP,	/* CFA = rbp + offset */
+	DWARF_CFA_TYPE_END,	/* Outermost frame, return address undefined */
+	DWARF_CFA_TYPE_NONE,	/* Not covered, e.g. gaps between functions,
+				   or rules not supported, e.g. expressions */
+};
+
+enum dwarf_rbp_type {
+	DWARF_RBP_TYPE_SAME,	/* rbp unchanged */
+	DWARF_RBP_TYPE_OFFSET,	/* rbp saved at CFA + offset */
+};
+
+struct dwarf_unwind_entry {
+	__u64 pc;		/* ELF virtual address */
+	__s16 cfa_offset;
+	__s16 rbp_offset;
+	__u8 cfa_type;
+	__u8 rbp_type;
+	__u8 reserved[2];
+};
+
+struct dwarf_mapping {
+	__u64 start;		/* Runtime address range of the executable mapping */
+	__u64 end;
+	__u64 bias;		/* Runtime address - ELF virtual address */
+	__u32 entries_start;	/* Range of the table in '__dwarf_unwind_entries' */
+	__u32 entries_end;
+};
+
+/* key: tgid */
+struct dwarf_proc_info {
+	__u32 count;
+	__u32 reserved;
+	struct dwarf_mapping mappings[DWARF_MAPPINGS_MAX];
+};
+
+/* Per-CPU state passed between tail calls */
+struct dwarf_unwind_state {
+	struct stack_trace_key_t key;
+	__u64 pc;
+	__u64 sp;
+	__u64 bp;
+	__u32 depth;
+	__u32 tail_calls;
+	bool use_a_map;
+	__u64 ips[PERF_MAX_STACK_DEPTH];
+};
+
 #endif /* DF_BPF_PERF_PROFILER_H */
@@ -23,6 +23,7 @@
 #define DF_BPF_SOCKET_TRACE_COMMON_H
 #define CAP_DATA_SIZE 1024		// For no-brust send buffer
 #define BURST_DATA_BUF_SIZE  16384	// For brust send buffer
+#define SCOPE_MAP_ENTRIES 65536		// Processes or cgroups out of collection scope
 
 enum endpoint_role {
 	ROLE_UNKNOWN,
@@ -186,6 +187,45 @@ struct __io_event_buffer {
 	char filename[64];
 } __attribute__((packed));
 
+#define USDT_PROBES_MAX 8
+#define USDT_ARGS_MAX 6
+#define USDT_NAME_LEN 64
+
+enum usdt_arg_type {
+	USDT_ARG_CONST,
+	USDT_ARG_REG,
+	USDT_ARG_REG_DEREF,
+};
+
+// Location of a USDT argument, parsed from the argument format
+// of the probe note, e.g. "-4@-8(%rbp)" on x86_64
+struct usdt_arg_spec {
+	// constant value or offset of the dereferenced address
+	__u64 val_off;
+	enum usdt_arg_type arg_type;
+	// offset of the register in struct pt_regs
+	__s16 reg_off;
+	bool arg_signed;
+	// shift to truncate and extend the value read as u64
+	char arg_bitshift;
+};
+
+// key: tgid << 32 | probe index
+struct usdt_spec {
+	struct usdt_arg_spec args[USDT_ARGS_MAX];
+	__u32 arg_cnt;
+	// "provider:name", strings terminated with \0
+	char name[USDT_NAME_LEN];
+};
+
+struct __usdt_event_buffer {
+	__u32 probe_index;
+	__u32 arg_cnt;
+	__u64 args[USDT_ARGS_MAX];
+	// "provider:name", strings terminated with \0
+	char name[USDT_NAME_LEN];
+} __attribute__((packed));
+
 // struct ebpf_proc_info -> offsets[]  arrays index.
 enum offsets_index {
 	OFFSET_IDX_GOID_RUNTIME_G,
@@ -19,25 +19,6 @@
  * SPDX-License-Identifier: GPL-2.0
  */
 
-struct ssl_ctx_struct {
-	void *buf;
-	int num;
-
-	int fd;
-	// Since the length of the plaintext is not equal to the length of the
-	// ciphertext, the TCP sequence number at the beginning cannot be
-	// calculated based on the TCP seq at the end and the length of the
-	// message.
-	__u32 tcp_seq;
-} __attribute__ ((packed));
-
-/* *INDENT-OFF* */
-// Save function arguments and use them when the function returns
-// key: pid_tgid
-// value: SSL_* arguments
-BPF_HASH(ssl_ctx_map, __u64, struct ssl_ctx_struct)
-/* *INDENT-ON* */
-
 static int get_fd_from_openssl_ssl(void *ssl)
 {
 	int fd;
@@ -78,17 +59,52 @@ int uprobe_openssl_write_enter(struct pt_regs *ctx)
 	return 0;
 }
 
+/*
+ * BoringSSL linked in Envoy uses custom BIOs on top of its own IO handles,
+ * the fd is not available in the SSL structure and is learned from the
+ * socket syscall made by SSL_write()/SSL_read(), see ssl_ctx_learn_fd().
+ */
 // int SSL_write(SSL *ssl, const void *buf, int num);
-SEC("uretprobe/openssl_write_exit")
-int uprobe_openssl_write_exit(struct pt_regs *ctx)
+SEC("uprobe/boringssl_write_enter")
+int uprobe_boringssl_write_enter(struct pt_regs *ctx)
+{
+	__u64 id = bpf_get_current_pid_tgid();
+	struct ssl_ctx_struct ssl_ctx = {
+		.fd = -1,
+		.buf = (void *)PT_REGS_PARM2(ctx),
+		.num = (int)PT_REGS_PARM3(ctx),
+		.tcp_seq = 0,
+	};
+	ssl_ctx_map__update(&id, &ssl_ctx);
+	return 0;
+}
+
+// int SSL_read(SSL *ssl, void *buf, int num);
+SEC("uprobe/boringssl_read_enter")
+int uprobe_boringssl_read_enter(struct pt_regs *ctx)
+{
+	__u64 id = bpf_get_current_pid_tgid();
+	struct ssl_ctx_struct ssl_ctx = {
+		.fd = -1,
+		.buf = (void *)PT_REGS_PARM2(ctx),
+		.num = (int)PT_REGS_PARM3(ctx),
+		.tcp_seq = 0,
+	};
+	ssl_ctx_map__update(&id, &ssl_ctx);
+	return 0;
+}
+
+// Submit the plaintext saved in ssl_ctx_map, shared by TLS libraries
+static __inline int ssl_uprobe_write_exit(struct pt_regs *ctx, int size)
 {
 	__u64 id = bpf_get_current_pid_tgid();
 	struct ssl_ctx_struct *ssl_ctx = ssl_ctx_map__lookup(&id);
 	if (!ssl_ctx)
 		return 0;
 
-	int size = (int)PT_REGS_RC(ctx);
-	if (size <= 0) {
+	// The fd is unknown if no socket syscall is made, e.g. SSL_read()
+	// returns data already decrypted and buffered.
+	if (size <= 0 || ssl_ctx->fd <= 2) {
 		ssl_ctx_map__delete(&id);
 		return 0;
 	}
@@ -117,34 +133,14 @@ int uprobe_openssl_write_exit(struct pt_regs *ctx)
 	return 0;
 }
 
-// int SSL_read(SSL *ssl, void *buf, int num);
-SEC("uprobe/openssl_read_enter")
-int uprobe_openssl_read_enter(struct pt_regs *ctx)
-{
-	void *ssl = (void *)PT_REGS_PARM1(ctx);
-	int fd = get_fd_from_openssl_ssl(ssl);
-	__u64 id = bpf_get_current_pid_tgid();
-	struct ssl_ctx_struct ssl_ctx = {
-		.fd = fd,
-		.buf = (void *)PT_REGS_PARM2(ctx),
-		.num = (int)PT_REGS_PARM3(ctx),
-		.tcp_seq = get_tcp_read_seq_from_fd(fd),
-	};
-	ssl_ctx_map__update(&id, &ssl_ctx);
-	return 0;
-}
-
-// int SSL_read(SSL *ssl, void *buf, int num);
-SEC("uretprobe/openssl_read_exit")
-int uprobe_openssl_read_exit(struct pt_regs *ctx)
+static __inline int ssl_uprobe_read_exit(struct pt_regs *ctx, int size)
 {
 	__u64 id = bpf_get_current_pid_tgid();
 	struct ssl_ctx_struct *ssl_ctx = ssl_ctx_map__lookup(&id);
 	if (!ssl_ctx)
 		return 0;
 
-	int size = (int)PT_REGS_RC(ctx);
-	if (size <= 0) {
+	if (size <= 0 || ssl_ctx->fd <= 2) {
 		ssl_ctx_map__delete(&id);
 		return 0;
 	}
@@ -172,3 +168,34 @@ int uprobe_openssl_read_exit(struct pt_regs *ctx)
 	active_read_args_map__delete(&id);
 	return 0;
 }
+
+// int SSL_write(SSL *ssl, const void *buf, int num);
+SEC("uretprobe/openssl_write_exit")
+int uprobe_openssl_write_exit(struct pt_regs *ctx)
+{
+	return ssl_uprobe_write_exit(ctx, (int)PT_REGS_RC(ctx));
+}
+
+// int SSL_read(SSL *ssl, void *buf, int num);
+SEC("uprobe/openssl_read_enter")
+int uprobe_openssl_read_enter(struct pt_regs *ctx)
+{
+	void *ssl = (void *)PT_REGS_PARM1(ctx);
+	int fd = get_fd_from_openssl_ssl(ssl);
+	__u64 id = bpf_get_current_pid_tgid();
+	struct ssl_ctx_struct ssl_ctx = {
+		.fd = fd,
+		.buf = (void *)PT_REGS_PARM2(ctx),
+		.num = (int)PT_REGS_PARM3(ctx),
+		.tcp_seq = get_tcp_read_seq_from_fd(fd),
+	};
+	ssl_ctx_map__update(&id, &ssl_ctx);
+	return 0;
+}
+
+// int SSL_read(SSL *ssl, void *buf, int num);
+SEC("uretprobe/openssl_read_exit")
+int uprobe_openssl_read_exit(struct pt_regs *ctx)
+{
+	return ssl_uprobe_read_exit(ctx, (int)PT_REGS_RC(ctx));
+}
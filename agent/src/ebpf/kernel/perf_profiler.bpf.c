@@ -68,22 +68,15 @@ MAP_STACK_TRACE(stack_map_b, STACK_MAP_ENTRIES)
  * switching between buffer a and buffer b.
  */
 MAP_ARRAY(profiler_state_map, __u32, __u64, PROFILER_CNT)
- SEC("perf_event")
-int bpf_perf_event(struct bpf_perf_event_data *ctx)
+
+static __inline void submit_stack_trace(struct bpf_perf_event_data *ctx,
+					struct stack_trace_key_t *key,
+					bool use_a_map)
 {
 	__u32 count_idx;
 
-	count_idx = TRANSFER_CNT_IDX;
-	__u64 *transfer_count_ptr = profiler_state_map__lookup(&count_idx);
-
-	count_idx = SAMPLE_CNT_A_IDX;
-	__u64 *sample_count_a_ptr = profiler_state_map__lookup(&count_idx);
-
-	count_idx = SAMPLE_CNT_B_IDX;
-	__u64 *sample_count_b_ptr = profiler_state_map__lookup(&count_idx);
-
-	count_idx = SAMPLE_CNT_DROP;
-	__u64 *drop_count_ptr = profiler_state_map__lookup(&count_idx);
+	count_idx = use_a_map ? SAMPLE_CNT_A_IDX : SAMPLE_CNT_B_IDX;
+	__u64 *sample_count_ptr = profiler_state_map__lookup(&count_idx);
 
 	count_idx = SAMPLE_ITER_CNT_MAX;
 	__u64 *iter_count_ptr = profiler_state_map__lookup(&count_idx);
@@ -91,16 +84,332 @@ int bpf_perf_event(struct bpf_perf_event_data *ctx)
 	count_idx = OUTPUT_CNT_IDX;
 	__u64 *output_count_ptr = profiler_state_map__lookup(&count_idx);
 
-	count_idx = ENABLE_IDX;
-	__u64 *enable_ptr = profiler_state_map__lookup(&count_idx);
-
 	count_idx = ERROR_IDX;
 	__u64 *error_count_ptr = profiler_state_map__lookup(&count_idx);
 
-	if (transfer_count_ptr == NULL || sample_count_a_ptr == NULL ||
-	    sample_count_b_ptr == NULL || drop_count_ptr == NULL ||
-	    iter_count_ptr == NULL || error_count_ptr == NULL ||
-	    output_count_ptr == NULL || enable_ptr == NULL) {
+	if (sample_count_ptr == NULL || iter_count_ptr == NULL ||
+	    output_count_ptr == NULL || error_count_ptr == NULL)
+		return;
+
+	__u64 sample_count = *sample_count_ptr;
+	__sync_fetch_and_add(sample_count_ptr, 1);
+
+	int ret;
+	if (use_a_map)
+		ret = bpf_perf_event_output(ctx, &NAME(profiler_output_a),
+					    BPF_F_CURRENT_CPU, key,
+					    sizeof(*key));
+	else
+		ret = bpf_perf_event_output(ctx, &NAME(profiler_output_b),
+					    BPF_F_CURRENT_CPU, key,
+					    sizeof(*key));
+	if (ret)
+		__sync_fetch_and_add(error_count_ptr, 1);
+	else
+		__sync_fetch_and_add(output_count_ptr, 1);
+
+	/*
+	 * Each iteration in user mode sets the sample_count to 0. If
+	 * sample_count > 0, it means that the user mode program is
+	 * currently in the process of iteration and has not completed
+	 * the stringifier task. If sample_count is too large, it is
+	 * likely to cause stack-trace loss of records. We hope to set
+	 * a larger value for STACK_MAP_ENTRIES to ensure that data is
+	 * not lost. The implementation method requires calculating the
+	 * maximum value of the stackmap during the loading phase and
+	 * resetting it.
+	 *
+	 * Record the maximum sample count for each iteration.
+	 */
+	if (sample_count > *iter_count_ptr)
+		*iter_count_ptr = sample_count;
+}
+
+/*
+ * DWARF unwinding requires bounded loops in the verifier, and is only
+ * implemented for x86_64.
+ */
+#if defined(LINUX_VER_5_2_PLUS) && defined(__x86_64__)
+#define DWARF_UNWIND_SUPPORT
+#endif
+
+#ifdef DWARF_UNWIND_SUPPORT
+#define DWARF_FRAMES_MAX (DWARF_FRAMES_PER_PROG * (DWARF_TAIL_CALLS_MAX + 1))
+/* THREAD_SIZE of x86_64 without KASAN, user registers are saved at the top */
+#define DWARF_THREAD_SIZE (16384)
+
+MAP_PROG_ARRAY(progs_jmp_pe_map, __u32, __u32, PROG_PE_NUM)
+
+/* Processes to be unwound with DWARF, written by user space */
+MAP_HASH(dwarf_proc_map, __u32, struct dwarf_proc_info, DWARF_PROCS_MAX)
+
+/* Unwind tables of all binaries */
+MAP_ARRAY(dwarf_unwind_entries, __u32, struct dwarf_unwind_entry,
+	  DWARF_UNWIND_ENTRIES_MAX)
+
+MAP_PERARRAY(dwarf_unwind_state, __u32, struct dwarf_unwind_state, 1)
+
+/*
+ * Frames of stacks unwound with DWARF, in the same layout as 'stack_map_*'.
+ * key: stack ID (a hash of the frames flagged with DWARF_STACK_ID_FLAG)
+ * value: frames, from the innermost one
+ */
+struct bpf_map_def SEC("maps") __dwarf_stack_map = {
+	.type = BPF_MAP_TYPE_LRU_HASH,
+	.key_size = sizeof(__u32),
+	.value_size = PERF_MAX_STACK_DEPTH * sizeof(__u64),
+	.max_entries = DWARF_STACK_MAP_ENTRIES,
+};
+
+static __inline int dwarf_get_user_regs(struct bpf_perf_event_data *ctx,
+					struct dwarf_unwind_state *state)
+{
+	// Sampled in user mode
+	if (ctx->regs.cs & 3) {
+		state->pc = PT_REGS_IP(&ctx->regs);
+		state->sp = PT_REGS_SP(&ctx->regs);
+		state->bp = PT_REGS_FP(&ctx->regs);
+		return 0;
+	}
+
+	/*
+	 * Sampled in kernel, user registers are saved at the top of the
+	 * kernel stack of the task, see task_pt_regs().
+	 */
+	__u32 idx = TASK_STACK_OFFSET_IDX;
+	__u64 *offset = profiler_state_map__lookup(&idx);
+	if (offset == NULL || *offset == 0)
+		return -1;
+
+	void *task = (void *)bpf_get_current_task();
+	void *stack;
+	if (bpf_probe_read(&stack, sizeof(stack), task + *offset))
+		return -1;
+
+	struct pt_regs regs;
+	if (bpf_probe_read(&regs, sizeof(regs),
+			   stack + DWARF_THREAD_SIZE - sizeof(regs)))
+		return -1;
+
+	// Kernel threads
+	if (!(regs.cs & 3))
+		return -1;
+
+	state->pc = PT_REGS_IP(&regs);
+	state->sp = PT_REGS_SP(&regs);
+	state->bp = PT_REGS_FP(&regs);
+	return 0;
+}
+
+/*
+ * Falls back to frame pointers for code not covered by unwind tables,
+ * e.g. JIT compiled code.
+ * Returns 0 to continue, 1 if unwinding is done.
+ */
+static __inline int dwarf_unwind_frame_pointer(struct dwarf_unwind_state *state)
+{
+	__u64 ra;
+
+	if (state->bp == 0)
+		return 1;
+	if (bpf_probe_read(&ra, sizeof(ra), (void *)(state->bp + 8)))
+		return 1;
+	state->sp = state->bp + 16;
+	state->pc = ra;
+	if (bpf_probe_read(&state->bp, sizeof(state->bp), (void *)state->bp))
+		return 1;
+	return ra == 0;
+}
+
+// Returns 0 to continue, 1 if unwinding is done.
+static __inline int dwarf_unwind_frame(struct dwarf_unwind_state *state,
+				       struct dwarf_proc_info *info)
+{
+	// Return addresses may be right after the last instruction of
+	// the calling function, e.g. a call to a noreturn function.
+	__u64 pc = state->depth > 1 ? state->pc - 1 : state->pc;
+	__u64 bias = 0;
+	__u32 lo = 0, hi = 0;
+	bool found = false;
+	int i;
+
+#pragma unroll
+	for (i = 0; i < DWARF_MAPPINGS_MAX; i++) {
+		if (i >= info->count)
+			break;
+		if (pc >= info->mappings[i].start && pc < info->mappings[i].end) {
+			bias = info->mappings[i].bias;
+			lo = info->mappings[i].entries_start;
+			hi = info->mappings[i].entries_end;
+			found = true;
+			break;
+		}
+	}
+
+	if (!found || lo >= hi)
+		return dwarf_unwind_frame_pointer(state);
+
+	// Find the last row with an address not after pc
+	__u64 target = pc - bias;
+	struct dwarf_unwind_entry *e;
+#pragma unroll
+	for (i = 0; i < DWARF_BSEARCH_STEPS; i++) {
+		if (lo + 1 >= hi)
+			break;
+		__u32 mid = lo + (hi - lo) / 2;
+		e = dwarf_unwind_entries__lookup(&mid);
+		if (e == NULL)
+			return 1;
+		if (e->pc <= target)
+			lo = mid;
+		else
+			hi = mid;
+	}
+
+	e = dwarf_unwind_entries__lookup(&lo);
+	if (e == NULL)
+		return 1;
+	if (e->pc > target)
+		return dwarf_unwind_frame_pointer(state);
+
+	__u64 cfa;
+	switch (e->cfa_type) {
+	case DWARF_CFA_TYPE_RSP:
+		cfa = state->sp + e->cfa_offset;
+		break;
+	case DWARF_CFA_TYPE_RBP:
+		cfa = state->bp + e->cfa_offset;
+		break;
+	case DWARF_CFA_TYPE_END:
+		return 1;
+	default:
+		return dwarf_unwind_frame_pointer(state);
+	}
+
+	// The return address is pushed right below the CFA
+	__u64 ra;
+	if (bpf_probe_read(&ra, sizeof(ra), (void *)(cfa - 8)))
+		return 1;
+	if (e->rbp_type == DWARF_RBP_TYPE_OFFSET &&
+	    bpf_probe_read(&state->bp, sizeof(state->bp),
+			   (void *)(cfa + e->rbp_offset)))
+		return 1;
+	state->sp = cfa;
+	state->pc = ra;
+	return ra == 0;
+}
+
+static __inline void dwarf_unwind_finish(struct bpf_perf_event_data *ctx,
+					 struct dwarf_unwind_state *state)
+{
+	// FNV-1a
+	__u64 hash = 14695981039346656037ULL;
+	int i;
+
+#pragma unroll
+	for (i = 0; i < DWARF_FRAMES_MAX; i++) {
+		if (i >= state->depth)
+			break;
+		hash ^= state->ips[i];
+		hash *= 1099511628211ULL;
+	}
+
+	/*
+	 * Stacks colliding on the ID overwrite each other, the chance is
+	 * low with DWARF_STACK_MAP_ENTRIES live stacks in a 30 bits space.
+	 */
+	__u32 stack_id = (__u32) (hash % DWARF_STACK_ID_FLAG) | DWARF_STACK_ID_FLAG;
+	if (bpf_map_update_elem(&__dwarf_stack_map, &stack_id, state->ips,
+				BPF_ANY))
+		return;
+
+	__u32 count_idx = DWARF_UNWIND_CNT_IDX;
+	__u64 *dwarf_count_ptr = profiler_state_map__lookup(&count_idx);
+	if (dwarf_count_ptr)
+		__sync_fetch_and_add(dwarf_count_ptr, 1);
+
+	state->key.userstack = stack_id;
+	submit_stack_trace(ctx, &state->key, state->use_a_map);
+}
+
+PROGPE(dwarf_unwind) (struct bpf_perf_event_data *ctx) {
+	__u32 k0 = 0;
+	struct dwarf_unwind_state *state = dwarf_unwind_state__lookup(&k0);
+	if (state == NULL)
+		return 0;
+
+	__u32 tgid = state->key.tgid;
+	struct dwarf_proc_info *info = dwarf_proc_map__lookup(&tgid);
+	if (info == NULL)
+		goto finish;
+
+#pragma unroll
+	for (int i = 0; i < DWARF_FRAMES_PER_PROG; i++) {
+		__u32 depth = state->depth;
+		if (depth >= PERF_MAX_STACK_DEPTH)
+			goto finish;
+		state->ips[depth] = state->pc;
+		state->depth = depth + 1;
+		if (dwarf_unwind_frame(state, info))
+			goto finish;
+	}
+
+	if (state->tail_calls < DWARF_TAIL_CALLS_MAX) {
+		state->tail_calls++;
+		bpf_tail_call(ctx, &NAME(progs_jmp_pe_map),
+			      PROG_DWARF_UNWIND_PE_IDX);
+	}
+
+finish:
+	dwarf_unwind_finish(ctx, state);
+	return 0;
+}
+
+/*
+ * Unwind the user stack with DWARF if the process has unwind tables,
+ * returns only if the user stack is to be taken with frame pointers.
+ */
+static __inline void dwarf_unwind_start(struct bpf_perf_event_data *ctx,
+					struct stack_trace_key_t *key,
+					bool use_a_map)
+{
+	__u32 tgid = key->tgid;
+	if (dwarf_proc_map__lookup(&tgid) == NULL)
+		return;
+
+	__u32 k0 = 0;
+	struct dwarf_unwind_state *state = dwarf_unwind_state__lookup(&k0);
+	if (state == NULL)
+		return;
+
+	if (dwarf_get_user_regs(ctx, state))
+		return;
+
+	state->key = *key;
+	state->depth = 0;
+	state->tail_calls = 0;
+	state->use_a_map = use_a_map;
+	__builtin_memset(state->ips, 0, sizeof(state->ips));
+	bpf_tail_call(ctx, &NAME(progs_jmp_pe_map), PROG_DWARF_UNWIND_PE_IDX);
+}
+#endif /* DWARF_UNWIND_SUPPORT */
+
+SEC("perf_event")
+int bpf_perf_event(struct bpf_perf_event_data *ctx)
+{
+	__u32 count_idx;
+
+	count_idx = TRANSFER_CNT_IDX;
+	__u64 *transfer_count_ptr = profiler_state_map__lookup(&count_idx);
+
+	count_idx = SAMPLE_CNT_DROP;
+	__u64 *drop_count_ptr = profiler_state_map__lookup(&count_idx);
+
+	count_idx = ENABLE_IDX;
+	__u64 *enable_ptr = profiler_state_map__lookup(&count_idx);
+
+	if (transfer_count_ptr == NULL || drop_count_ptr == NULL ||
+	    enable_ptr == NULL) {
 		count_idx = ERROR_IDX;
 		__u64 err_val = 1;
 		profiler_state_map__update(&count_idx, &err_val);
@@ -143,74 +452,36 @@ int bpf_perf_event(struct bpf_perf_event_data *ctx)
 	 *    -EEXIST (duplicate value of *stackid*) 
 	 */
 
-	__u64 sample_count = 0;
-	if (!((*transfer_count_ptr) & 0x1ULL)) {
+	bool use_a_map = !((*transfer_count_ptr) & 0x1ULL);
+	if (use_a_map) {
 		key.kernstack = bpf_get_stackid(ctx, &NAME(stack_map_a),
 						KERN_STACKID_FLAGS);
-		key.userstack = bpf_get_stackid(ctx, &NAME(stack_map_a),
-						USER_STACKID_FLAGS);
-
-		if (-EEXIST == key.kernstack)
-			__sync_fetch_and_add(drop_count_ptr, 1);
-
-		if (-EEXIST == key.userstack)
-			__sync_fetch_and_add(drop_count_ptr, 1);
-
-		if (key.userstack < 0 && key.kernstack < 0)
-			return 0;
-
-		sample_count = *sample_count_a_ptr;
-		__sync_fetch_and_add(sample_count_a_ptr, 1);
-
-		if (bpf_perf_event_output(ctx,
-					  &NAME(profiler_output_a),
-					  BPF_F_CURRENT_CPU, &key, sizeof(key)))
-			__sync_fetch_and_add(error_count_ptr, 1);
-		else
-			__sync_fetch_and_add(output_count_ptr, 1);
-
 	} else {
 		key.kernstack = bpf_get_stackid(ctx, &NAME(stack_map_b),
 						KERN_STACKID_FLAGS);
-		key.userstack = bpf_get_stackid(ctx, &NAME(stack_map_b),
-						USER_STACKID_FLAGS);
-
-		if (-EEXIST == key.kernstack)
-			__sync_fetch_and_add(drop_count_ptr, 1);
-
-		if (-EEXIST == key.userstack)
-			__sync_fetch_and_add(drop_count_ptr, 1);
+	}
 
-		if (key.userstack < 0 && key.kernstack < 0)
-			return 0;
+	if (-EEXIST == key.kernstack)
+		__sync_fetch_and_add(drop_count_ptr, 1);
 
-		sample_count = *sample_count_b_ptr;
-		__sync_fetch_and_add(sample_count_b_ptr, 1);
+#ifdef DWARF_UNWIND_SUPPORT
+	dwarf_unwind_start(ctx, &key, use_a_map);
+#endif
 
-		if (bpf_perf_event_output(ctx,
-					  &NAME(profiler_output_b),
-					  BPF_F_CURRENT_CPU, &key, sizeof(key)))
-			__sync_fetch_and_add(error_count_ptr, 1);
-		else
-			__sync_fetch_and_add(output_count_ptr, 1);
+	if (use_a_map) {
+		key.userstack = bpf_get_stackid(ctx, &NAME(stack_map_a),
+						USER_STACKID_FLAGS);
+	} else {
+		key.userstack = bpf_get_stackid(ctx, &NAME(stack_map_b),
+						USER_STACKID_FLAGS);
 	}
 
-	/*
-	 * Each iteration in user mode sets the sample_count to 0. If
-	 * sample_count > 0, it means that the user mode program is
-	 * currently in the process of iteration and has not completed
-	 * the stringifier task. If sample_count is too large, it is
-	 * likely to cause stack-trace loss of records. We hope to set
-	 * a larger value for STACK_MAP_ENTRIES to ensure that data is
-	 * not lost. The implementation method requires calculating the
-	 * maximum value of the stackmap during the loading phase and
-	 * resetting it.
-	 *
-	 * Record the maximum sample count for each iteration.
-	 */
-	if (sample_count > *iter_count_ptr)
-		*iter_count_ptr = sample_count;
+	if (-EEXIST == key.userstack)
+		__sync_fetch_and_add(drop_count_ptr, 1);
+
+	if (key.userstack < 0 && key.kernstack < 0)
+		return 0;
 
+	submit_stack_trace(ctx, &key, use_a_map);
 	return 0;
 }
-
@@ -0,0 +1,108 @@
+/*
+ * This code runs using bpf in the Linux kernel.
+ * Copyright 2022- The Yunshan Networks Authors.
+ *
+ * This program is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU General Public License
+ * as published by the Free Software Foundation; either version 2
+ * of the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+ *
+ * SPDX-License-Identifier: GPL-2.0
+ */
+
+/*
+ * rustls plaintext APIs, resolved by user space from the mangled symbols:
+ *   impl std::io::Write for rustls::conn::Writer
+ *     fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+ *   impl std::io::Read for rustls::conn::Reader
+ *     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+ *
+ * The connection is decoupled from socket IO, which is done by the caller
+ * with write_tls()/read_tls(). The fd is taken from the latest socket
+ * syscall of the thread (see ssl_ctx_learn_fd()), which is the connection
+ * being served unless the thread interleaves several connections between
+ * the socket IO and the plaintext call, e.g. in some async runtimes.
+ */
+
+// io::Result<usize> is returned in a register pair, the discriminant (0 for
+// Ok) in the first one and the value in the second one.
+#if defined(__x86_64__)
+#define PT_REGS_RC2(x) PT_REGS_PARM3(x)	// rdx
+#elif defined(__aarch64__)
+#define PT_REGS_RC2(x) PT_REGS_PARM2(x)	// x1
+#endif
+
+// fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+SEC("uprobe/rustls_write_enter")
+int uprobe_rustls_write_enter(struct pt_regs *ctx)
+{
+	__u64 id = bpf_get_current_pid_tgid();
+	int *fd = bpf_map_lookup_elem(&rustls_fd_map, &id);
+	if (!fd)
+		return 0;
+
+	struct ssl_ctx_struct ssl_ctx = {
+		.fd = *fd,
+		.buf = (void *)PT_REGS_PARM2(ctx),
+		.num = (int)PT_REGS_PARM3(ctx),
+		.tcp_seq = get_tcp_write_seq_from_fd(*fd),
+	};
+	ssl_ctx_map__update(&id, &ssl_ctx);
+	return 0;
+}
+
+// fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+SEC("uretprobe/rustls_write_exit")
+int uprobe_rustls_write_exit(struct pt_regs *ctx)
+{
+	if (PT_REGS_RC(ctx) != 0) {
+		__u64 id = bpf_get_current_pid_tgid();
+		ssl_ctx_map__delete(&id);
+		return 0;
+	}
+
+	return ssl_uprobe_write_exit(ctx, (int)PT_REGS_RC2(ctx));
+}
+
+// fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+SEC("uprobe/rustls_read_enter")
+int uprobe_rustls_read_enter(struct pt_regs *ctx)
+{
+	__u64 id = bpf_get_current_pid_tgid();
+	int *fd = bpf_map_lookup_elem(&rustls_fd_map, &id);
+	if (!fd)
+		return 0;
+
+	// The ciphertext has been read by read_tls(), the sequence is the
+	// end of the received data.
+	struct ssl_ctx_struct ssl_ctx = {
+		.fd = *fd,
+		.buf = (void *)PT_REGS_PARM2(ctx),
+		.num = (int)PT_REGS_PARM3(ctx),
+		.tcp_seq = get_tcp_read_seq_from_fd(*fd),
+	};
+	ssl_ctx_map__update(&id, &ssl_ctx);
+	return 0;
+}
+
+// fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+SEC("uretprobe/rustls_read_exit")
+int uprobe_rustls_read_exit(struct pt_regs *ctx)
+{
+	if (PT_REGS_RC(ctx) != 0) {
+		__u64 id = bpf_get_current_pid_tgid();
+		ssl_ctx_map__delete(&id);
+		return 0;
+	}
+
+	return ssl_uprobe_read_exit(ctx, (int)PT_REGS_RC2(ctx));
+}
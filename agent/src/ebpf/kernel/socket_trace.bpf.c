@@ -146,6 +146,14 @@ BPF_HASH(trace_map, struct trace_key_t, struct trace_info_t)
 // Stores the identity used to fit the kernel, key: 0, vlaue:{tgid, pid}
 MAP_ARRAY(adapt_kern_uid_map, __u32, __u64, 1)
 
+/*
+ * Collection scope, processes and cgroups out of scope are decided by the
+ * agent, and data of them is dropped before protocol inference.
+ * key: tgid or cgroup v2 id, value: unused
+ */
+BPF_HASH(scope_pid_map, __u64, __u8, SCOPE_MAP_ENTRIES)
+BPF_HASH(scope_cgroup_map, __u64, __u8, SCOPE_MAP_ENTRIES)
+
 #ifdef LINUX_VER_5_2_PLUS
 /*
  * Fast matching cache, used to speed up protocol inference.
@@ -892,6 +900,24 @@ static __inline void infer_tcp_seq_offset(void *sk,
 	}
 }
 
+/*
+ * Processes not decided by the agent yet are submitted, and decided
+ * in user space.
+ */
+static __inline bool is_out_of_scope(__u64 tgid)
+{
+	if (scope_pid_map__lookup(&tgid))
+		return true;
+
+#ifdef LINUX_VER_5_2_PLUS
+	__u64 cgroup_id = bpf_get_current_cgroup_id();
+	if (scope_cgroup_map__lookup(&cgroup_id))
+		return true;
+#endif
+
+	return false;
+}
+
 static __inline bool check_pid_validity(void)
 {
 	__u32 k0 = 0;
@@ -1426,10 +1452,8 @@ static __inline int process_data(struct pt_regs *ctx, __u64 id,
 	if (unlikely(args->fd < 0 || (int)bytes_count <= 0))
 		return -1;
 
-	/*
-	 * TODO:
-	 * Here you can filter the pid according to the configuration.
-	 */
+	if (is_out_of_scope(id >> 32))
+		return -1;
 
 	__u32 k0 = 0, k1 = 1;
 	struct member_fields_offset *offset = members_offset__lookup(&k0);
@@ -1557,6 +1581,46 @@ static __inline void process_syscall_data_vecs(struct pt_regs *ctx, __u64 id,
 	}
 }
 
+struct ssl_ctx_struct {
+	void *buf;
+	int num;
+
+	int fd;
+	// Since the length of the plaintext is not equal to the length of the
+	// ciphertext, the TCP sequence number at the beginning cannot be
+	// calculated based on the TCP seq at the end and the length of the
+	// message.
+	__u32 tcp_seq;
+} __attribute__ ((packed));
+
+/* *INDENT-OFF* */
+// Save function arguments and use them when the function returns
+// key: pid_tgid
+// value: SSL_* arguments
+BPF_HASH(ssl_ctx_map, __u64, struct ssl_ctx_struct)
+/* *INDENT-ON* */
+
+/*
+ * Some TLS libraries do not keep the socket fd in the SSL structure, e.g.
+ * BoringSSL with custom BIOs. The fd and TCP sequence are taken from the
+ * first socket syscall made by SSL_write()/SSL_read() on the same thread.
+ *
+ * rustls never makes socket syscalls in its plaintext APIs, the latest fd
+ * used by the thread is recorded instead, see rustls.bpf.c.
+ */
+static __inline void ssl_ctx_learn_fd(__u64 id, int fd, __u32 tcp_seq)
+{
+	struct ssl_ctx_struct *ssl_ctx = ssl_ctx_map__lookup(&id);
+	if (ssl_ctx && ssl_ctx->fd <= 2 && fd > 2) {
+		ssl_ctx->fd = fd;
+		ssl_ctx->tcp_seq = tcp_seq;
+	}
+
+	__u32 tgid = (__u32) (id >> 32);
+	if (fd > 2 && bpf_map_lookup_elem(&rustls_proc_map, &tgid))
+		bpf_map_update_elem(&rustls_fd_map, &id, &fd, BPF_ANY);
+}
+
 /***********************************************************
  * BPF syscall probe/tracepoint function entry-points
  ***********************************************************/
@@ -1572,6 +1636,7 @@ TPPROG(sys_enter_write) (struct syscall_comm_enter_ctx * ctx) {
 	write_args.enter_ts = bpf_ktime_get_ns();
 	write_args.tcp_seq = get_tcp_write_seq_from_fd(fd);
 	active_write_args_map__update(&id, &write_args);
+	ssl_ctx_learn_fd(id, write_args.fd, write_args.tcp_seq);
 
 	return 0;
 }
@@ -1606,6 +1671,7 @@ TPPROG(sys_enter_read) (struct syscall_comm_enter_ctx * ctx) {
 	read_args.enter_ts = bpf_ktime_get_ns();
 	read_args.tcp_seq = get_tcp_read_seq_from_fd(fd);
 	active_read_args_map__update(&id, &read_args);
+	ssl_ctx_learn_fd(id, read_args.fd, read_args.tcp_seq);
 
 	return 0;
 }
@@ -1644,6 +1710,7 @@ TPPROG(sys_enter_sendto) (struct syscall_comm_enter_ctx * ctx) {
 	write_args.enter_ts = bpf_ktime_get_ns();
 	write_args.tcp_seq = get_tcp_write_seq_from_fd(sockfd);
 	active_write_args_map__update(&id, &write_args);
+	ssl_ctx_learn_fd(id, write_args.fd, write_args.tcp_seq);
 
 	return 0;
 }
@@ -1688,6 +1755,7 @@ TPPROG(sys_enter_recvfrom) (struct syscall_comm_enter_ctx * ctx) {
 	read_args.enter_ts = bpf_ktime_get_ns();
 	read_args.tcp_seq = get_tcp_read_seq_from_fd(sockfd);
 	active_read_args_map__update(&id, &read_args);
+	ssl_ctx_learn_fd(id, read_args.fd, read_args.tcp_seq);
 
 	return 0;
 }
@@ -1730,6 +1798,7 @@ KPROG(__sys_sendmsg) (struct pt_regs * ctx) {
 		write_args.enter_ts = bpf_ktime_get_ns();
 		write_args.tcp_seq = get_tcp_write_seq_from_fd(sockfd);
 		active_write_args_map__update(&id, &write_args);
+		ssl_ctx_learn_fd(id, write_args.fd, write_args.tcp_seq);
 	}
 
 	return 0;
@@ -1773,6 +1842,7 @@ KPROG(__sys_sendmmsg) (struct pt_regs * ctx) {
 		write_args.enter_ts = bpf_ktime_get_ns();
 		write_args.tcp_seq = get_tcp_write_seq_from_fd(sockfd);
 		active_write_args_map__update(&id, &write_args);
+		ssl_ctx_learn_fd(id, write_args.fd, write_args.tcp_seq);
 	}
 
 	return 0;
@@ -1824,6 +1894,7 @@ KPROG(__sys_recvmsg) (struct pt_regs * ctx) {
 		read_args.enter_ts = bpf_ktime_get_ns();
 		read_args.tcp_seq = get_tcp_read_seq_from_fd(sockfd);
 		active_read_args_map__update(&id, &read_args);
+		ssl_ctx_learn_fd(id, read_args.fd, read_args.tcp_seq);
 	}
 
 	return 0;
@@ -1881,6 +1952,7 @@ KPROG(__sys_recvmmsg) (struct pt_regs * ctx) {
 		    (void *)msgvec + offsetof(typeof(struct mmsghdr), msg_len);
 		read_args.tcp_seq = get_tcp_read_seq_from_fd(sockfd);
 		active_read_args_map__update(&id, &read_args);
+		ssl_ctx_learn_fd(id, read_args.fd, read_args.tcp_seq);
 	}
 
 	return 0;
@@ -1926,6 +1998,7 @@ KPROG(do_writev) (struct pt_regs * ctx) {
 	write_args.enter_ts = bpf_ktime_get_ns();
 	write_args.tcp_seq = get_tcp_write_seq_from_fd(fd);
 	active_write_args_map__update(&id, &write_args);
+	ssl_ctx_learn_fd(id, write_args.fd, write_args.tcp_seq);
 	return 0;
 }
 
@@ -1966,6 +2039,7 @@ KPROG(do_readv) (struct pt_regs * ctx) {
 	read_args.enter_ts = bpf_ktime_get_ns();
 	read_args.tcp_seq = get_tcp_read_seq_from_fd(fd);
 	active_read_args_map__update(&id, &read_args);
+	ssl_ctx_learn_fd(id, read_args.fd, read_args.tcp_seq);
 
 	return 0;
 }
@@ -2137,6 +2211,16 @@ TPPROG(sys_enter_connect) (struct syscall_comm_enter_ctx * ctx) {
 // Store IO event information
 MAP_PERARRAY(io_event_buffer, __u32, struct __io_event_buffer, 1)
 
+// Store USDT event information, filled in by usdt.bpf.c
+MAP_PERARRAY(usdt_event_buffer, __u32, struct __usdt_event_buffer, 1)
+
+// Data of these sources is prepared in kernel buffers instead of user buffers
+static __inline bool is_kernel_buffer_source(__u8 source)
+{
+	return source == DATA_SOURCE_IO_EVENT
+	    || source == DATA_SOURCE_USDT_EVENT;
+}
+
 /*
  * This eBPF program is specially used to transmit data to the agent. The purpose
  * of this is to solve the problem that the number of instructions exceeds the limit.
@@ -2197,6 +2281,11 @@ static __inline int output_data_common(void *ctx)
 		if (buffer == NULL) {
 			goto clear_args_map_1;
 		}
+	} else if (v->source == DATA_SOURCE_USDT_EVENT) {
+		buffer = (char *)usdt_event_buffer__lookup(&k0);
+		if (buffer == NULL) {
+			goto clear_args_map_1;
+		}
 	} else {
 		buffer = (char *)args->buf;
 	}
@@ -2220,7 +2309,7 @@ static __inline int output_data_common(void *ctx)
 		len = iovecs_copy(v, v_buff, args, v->syscall_len, len);
 	} else {
 		if (__len >= sizeof(v->data)) {
-			if (v->source != DATA_SOURCE_IO_EVENT) {
+			if (!is_kernel_buffer_source(v->source)) {
 				if (unlikely
 				    (bpf_probe_read_user
 				     (v->data, sizeof(v->data), buffer) != 0))
@@ -2242,7 +2331,7 @@ static __inline int output_data_common(void *ctx)
 			 * "invalid access to map value, value_size=10888 off=135 size=0"
 			 * 使用'len + 1'代替'len'，来规避（Linux 4.14.x）这个检查。
 			 */
-			if (v->source != DATA_SOURCE_IO_EVENT) {
+			if (!is_kernel_buffer_source(v->source)) {
 				if (unlikely(bpf_probe_read_user(v->data,
 								 len + 1,
 								 buffer) != 0))
@@ -2646,3 +2735,5 @@ int bpf_push_socket_data(struct bpf_perf_event_data *ctx)
 #include "go_tls.bpf.c"
 #include "go_http2.bpf.c"
 #include "openssl.bpf.c"
+#include "rustls.bpf.c"
+#include "usdt.bpf.c"
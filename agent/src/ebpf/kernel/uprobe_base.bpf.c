@@ -56,6 +56,30 @@ struct bpf_map_def SEC("maps") proc_info_map = {
 	.max_entries = HASH_ENTRIES_MAX,
 };
 
+/*
+ * Processes with rustls uprobes attached, written by user space
+ * key: pid
+ * value: unused
+ */
+struct bpf_map_def SEC("maps") rustls_proc_map = {
+	.type = BPF_MAP_TYPE_HASH,
+	.key_size = sizeof(int),
+	.value_size = sizeof(__u32),
+	.max_entries = 1024,
+};
+
+/*
+ * The fd of the latest socket syscall of threads in rustls processes
+ * key: pid_tgid
+ * value: fd
+ */
+struct bpf_map_def SEC("maps") rustls_fd_map = {
+	.type = BPF_MAP_TYPE_LRU_HASH,
+	.key_size = sizeof(__u64),
+	.value_size = sizeof(int),
+	.max_entries = HASH_ENTRIES_MAX,
+};
+
 // Process ID and coroutine ID, marking the coroutine in the system
 struct go_key {
 	__u32 tgid;
@@ -629,6 +653,7 @@ int bpf_func_sched_process_exit(struct sched_comm_exit_ctx *ctx)
 	// If is a process, clear proc_info_map element and submit event.
 	if (pid == tid) {
 		bpf_map_delete_elem(&proc_info_map, &pid);
+		bpf_map_delete_elem(&rustls_proc_map, &pid);
 		struct process_event_t data;
 		data.pid = pid;
 		data.meta.event_type = EVENT_TYPE_PROC_EXIT;
@@ -0,0 +1,159 @@
+/*
+ * This code runs using bpf in the Linux kernel.
+ * Copyright 2022- The Yunshan Networks Authors.
+ *
+ * This program is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU General Public License
+ * as published by the Free Software Foundation; either version 2
+ * of the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+ *
+ * SPDX-License-Identifier: GPL-2.0
+ */
+
+/*
+ * USDT (User Statically-Defined Tracing) probes. Each configured probe is
+ * bound to one of the uprobe_usdt_<index> programs, the argument locations
+ * of the probe in each process are resolved by user space and stored in
+ * usdt_spec_map, since they differ between binaries.
+ */
+
+/* *INDENT-OFF* */
+// key: tgid << 32 | probe index
+// value: argument specs of the probe
+BPF_HASH(usdt_spec_map, __u64, struct usdt_spec, 4096)
+/* *INDENT-ON* */
+
+// Refer to https://github.com/libbpf/libbpf/blob/master/src/usdt.bpf.h
+static __inline int usdt_read_arg(struct pt_regs *ctx,
+				  struct usdt_arg_spec *spec, __u64 * res)
+{
+	__u64 val = 0;
+
+	switch (spec->arg_type) {
+	case USDT_ARG_CONST:
+		val = spec->val_off;
+		break;
+	case USDT_ARG_REG:
+		if (bpf_probe_read_kernel(&val, sizeof(val),
+					  (void *)ctx + spec->reg_off))
+			return -1;
+		break;
+	case USDT_ARG_REG_DEREF:
+		if (bpf_probe_read_kernel(&val, sizeof(val),
+					  (void *)ctx + spec->reg_off))
+			return -1;
+		if (bpf_probe_read_user(&val, sizeof(val),
+					(void *)val + spec->val_off))
+			return -1;
+#if __BYTE_ORDER__ == __ORDER_BIG_ENDIAN__
+		val >>= spec->arg_bitshift;
+#endif
+		break;
+	default:
+		return -1;
+	}
+
+	val <<= spec->arg_bitshift;
+	if (spec->arg_signed)
+		val = ((long long)val) >> spec->arg_bitshift;
+	else
+		val = val >> spec->arg_bitshift;
+	*res = val;
+	return 0;
+}
+
+static __inline int usdt_event_common(struct pt_regs *ctx, __u32 probe_index)
+{
+	__u32 k0 = 0;
+	__u64 id = bpf_get_current_pid_tgid();
+	__u32 tgid = (__u32) (id >> 32);
+	__u64 key = ((__u64) tgid << 32) | probe_index;
+
+	struct usdt_spec *spec = usdt_spec_map__lookup(&key);
+	if (!spec)
+		return 0;
+
+	struct trace_conf_t *trace_conf = trace_conf_map__lookup(&k0);
+	if (!trace_conf)
+		return 0;
+
+	struct __usdt_event_buffer *buffer = usdt_event_buffer__lookup(&k0);
+	if (!buffer)
+		return 0;
+
+	buffer->probe_index = probe_index;
+	buffer->arg_cnt = 0;
+	__u64 val;
+#pragma unroll
+	for (int i = 0; i < USDT_ARGS_MAX; i++) {
+		if (i >= spec->arg_cnt)
+			break;
+		if (usdt_read_arg(ctx, &spec->args[i], &val))
+			break;
+		buffer->args[i] = val;
+		buffer->arg_cnt++;
+	}
+	__builtin_memcpy(buffer->name, spec->name, sizeof(buffer->name));
+	buffer->name[sizeof(buffer->name) - 1] = '\0';
+
+	struct __socket_data_buffer *v_buff =
+	    bpf_map_lookup_elem(&NAME(data_buf), &k0);
+	if (!v_buff)
+		return 0;
+
+	struct __socket_data *v = (struct __socket_data *)&v_buff->data[0];
+
+	if (v_buff->len > (sizeof(v_buff->data) - sizeof(*v)))
+		return 0;
+
+	v = (struct __socket_data *)(v_buff->data + v_buff->len);
+	__builtin_memset(v, 0, offsetof(typeof(struct __socket_data), data));
+	v->tgid = tgid;
+	v->pid = (__u32) id;
+	v->timestamp = bpf_ktime_get_ns();
+	v->syscall_len = sizeof(*buffer);
+	v->source = DATA_SOURCE_USDT_EVENT;
+	bpf_get_current_comm(v->comm, sizeof(v->comm));
+
+	struct tail_calls_context *context =
+	    (struct tail_calls_context *)v->data;
+	context->max_size_limit = trace_conf->data_limit_max;
+	context->push_reassembly_bytes = 0;
+	context->vecs = false;
+	context->is_close = false;
+	context->dir = T_EGRESS;
+
+	// output_data expects the arguments of the current call
+	struct data_args_t write_args = {
+		.enter_ts = v->timestamp,
+	};
+	active_write_args_map__update(&id, &write_args);
+	bpf_tail_call(ctx, &NAME(progs_jmp_kp_map), PROG_OUTPUT_DATA_KP_IDX);
+	active_write_args_map__delete(&id);
+	return 0;
+}
+
+#define USDT_PROG(idx)						\
+SEC("uprobe/usdt_" #idx)					\
+int uprobe_usdt_##idx(struct pt_regs *ctx)			\
+{								\
+	return usdt_event_common(ctx, idx);			\
+}
+
+USDT_PROG(0)
+USDT_PROG(1)
+USDT_PROG(2)
+USDT_PROG(3)
+USDT_PROG(4)
+USDT_PROG(5)
+USDT_PROG(6)
+USDT_PROG(7)
@@ -26,6 +26,12 @@ pub use std::ffi::CStr;
 use std::fmt;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
+#[cfg(ebpf_unsupported)]
+mod stub;
+
+// libtrace is not built for some architectures, see build.rs
+pub const SUPPORTED: bool = !cfg!(ebpf_unsupported);
+
 // 最大长度
 pub const CAP_LEN_MAX: usize = 16384;
 
@@ -96,6 +102,10 @@ pub const FEATURE_UPROBE_GOLANG_SYMBOL: c_int = 0;
 pub const FEATURE_UPROBE_OPENSSL: c_int = 1;
 #[allow(dead_code)]
 pub const FEATURE_UPROBE_GOLANG: c_int = 2;
+#[allow(dead_code)]
+pub const FEATURE_UPROBE_USDT: c_int = 3;
+#[allow(dead_code)]
+pub const FEATURE_UPROBE_RUSTLS: c_int = 4;
 
 //L7层协议是否需要重新核实
 #[allow(dead_code)]
@@ -134,6 +144,8 @@ pub const DATA_SOURCE_IO_EVENT: u8 = 4;
 pub const DATA_SOURCE_GO_HTTP2_DATAFRAME_UPROBE: u8 = 5;
 #[allow(dead_code)]
 pub const DATA_SOURCE_CLOSE: u8 = 6;
+#[allow(dead_code)]
+pub const DATA_SOURCE_USDT_EVENT: u8 = 7;
 
 // 消息类型
 // 目前除了 source=EBPF_TYPE_GO_HTTP2_UPROBE 以外,都不能保证这个方向的正确性.
@@ -287,13 +299,7 @@ impl fmt::Display for SK_BPF_DATA {
             (self.tuple.rport, self.tuple.lport)
         };
         unsafe {
-            #[cfg(target_arch = "aarch64")]
-            let process_kname = CStr::from_ptr(self.process_kname.as_ptr() as *const u8)
-                .to_str()
-                .unwrap();
-
-            #[cfg(target_arch = "x86_64")]
-            let process_kname = CStr::from_ptr(self.process_kname.as_ptr() as *const i8)
+            let process_kname = CStr::from_ptr(self.process_kname.as_ptr() as *const c_char)
                 .to_str()
                 .unwrap();
 
@@ -438,11 +444,31 @@ extern "C" {
     pub fn set_go_tracing_timeout(timeout: c_int) -> c_int;
     pub fn set_io_event_collect_mode(mode: c_int) -> c_int;
     pub fn set_io_event_minimal_duration(duration: c_ulonglong) -> c_int;
+    /*
+     * Push the collection scope down to eBPF, data of processes out of scope
+     * is dropped before protocol inference.
+     * @pid : Process out of scope
+     * @cgroup_id : Cgroup out of scope, the inode number of the cgroup v2
+     *              directory, only effective on Linux 5.2+
+     *
+     * @return 0 on success, < 0 on failure.
+     */
+    pub fn set_scope_pid(pid: c_int) -> c_int;
+    pub fn set_scope_cgroup(cgroup_id: c_ulonglong) -> c_int;
+    // Remove all processes and cgroups out of scope
+    pub fn clear_scope() -> c_int;
     pub fn set_allow_port_bitmap(bitmap: *const c_uchar) -> c_int;
     pub fn set_bypass_port_bitmap(bitmap: *const c_uchar) -> c_int;
     pub fn enable_ebpf_protocol(protocol: c_int) -> c_int;
     pub fn enable_ebpf_seg_reasm_protocol(protocol: c_int) -> c_int;
     pub fn set_feature_regex(idx: c_int, pattern: *const c_char) -> c_int;
+    /*
+     * Set USDT probes to attach in processes matching FEATURE_UPROBE_USDT.
+     * @probes : Comma separated "provider:name" list, at most 8 probes.
+     *
+     * @return 0 on success, < 0 on failure.
+     */
+    pub fn set_usdt_probes(probes: *const c_char) -> c_int;
     /*
      * Configuring application layer protocol ports
      *
@@ -474,16 +500,26 @@ extern "C" {
      */
     pub fn set_protocol_ports_bitmap(proto_type: c_int, ports: *const c_char) -> c_int;
 
+    /*
+     * Enable or disable eBPF l7 protocol inference at runtime
+     *
+     * Parameters:
+     * @protocol
+     *   l7 protocol number
+     * @enabled
+     *   true to enable, false to disable
+     *
+     * @return 0 on success, non-zero on error
+     */
+    pub fn set_ebpf_protocol_enabled(protocol: c_int, enabled: bool) -> c_int;
+
     // 初始化tracer用于设置eBPF环境初始化。
     // 参数：
     //   log_file  日志文件路径，如果是传递一个空指针将不会有日志写到文件。
     //   is_stdout 日志是否输出到标准输出，true 写到标准输出，false 不写到标准输出。
     // 返回值：
     //   成功返回0，否则返回非0
-    #[cfg(target_arch = "x86_64")]
-    pub fn bpf_tracer_init(log_file: *const i8, is_stdout: bool) -> c_int;
-    #[cfg(target_arch = "aarch64")]
-    pub fn bpf_tracer_init(log_file: *const u8, is_stdout: bool) -> c_int;
+    pub fn bpf_tracer_init(log_file: *const c_char, is_stdout: bool) -> c_int;
 
     // 所有tracer启动完毕后，最后显示调用bpf_tracer_finish()来通知主程序
     pub fn bpf_tracer_finish();
@@ -597,6 +633,20 @@ extern "C" {
      */
     pub fn set_profiler_regex(pattern: *const c_char) -> c_int;
 
+    /*
+     * Set the regular expression of process names whose user stacks are
+     * unwound with DWARF unwind tables instead of frame pointers. Only takes
+     * effect on x86_64 with Linux 5.2+, and should be called before
+     * start_continuous_profiler() so that existing processes are matched.
+     *
+     * The default expression is empty (''), indicating DWARF unwinding is
+     * disabled.
+     *
+     * @pattern : Regular expression pattern. e.g. "^(envoy|mysqld)$"
+     * @returns 0 on success, < 0 on error
+     */
+    pub fn set_dwarf_regex(pattern: *const c_char) -> c_int;
+
     /*
      * This interface is used to set whether CPUID should be included in the
      * aggregation of stack trace data.
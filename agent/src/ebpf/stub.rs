@@ -0,0 +1,242 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Symbols of libtrace for architectures it does not build on (see build.rs),
+// so that the agent links and eBPF fails at initialization instead.
+// Every call fails with -1.
+
+use std::mem;
+
+use super::{
+    c_char, c_int, c_uchar, c_uint, c_ulonglong, stack_profile_data, PROCESS_EVENT, SK_BPF_DATA,
+    SK_TRACE_STATS,
+};
+
+const UNSUPPORTED: c_int = -1;
+
+#[no_mangle]
+pub extern "C" fn set_data_limit_max(_limit_size: c_int) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn set_go_tracing_timeout(_timeout: c_int) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn set_io_event_collect_mode(_mode: c_int) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn set_io_event_minimal_duration(_duration: c_ulonglong) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn set_scope_pid(_pid: c_int) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn set_scope_cgroup(_cgroup_id: c_ulonglong) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn clear_scope() -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn set_allow_port_bitmap(_bitmap: *const c_uchar) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn set_bypass_port_bitmap(_bitmap: *const c_uchar) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn enable_ebpf_protocol(_protocol: c_int) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn enable_ebpf_seg_reasm_protocol(_protocol: c_int) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn set_feature_regex(_idx: c_int, _pattern: *const c_char) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn set_usdt_probes(_probes: *const c_char) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn set_protocol_ports_bitmap(_proto_type: c_int, _ports: *const c_char) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn set_ebpf_protocol_enabled(_protocol: c_int, _enabled: bool) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn bpf_tracer_init(_log_file: *const c_char, _is_stdout: bool) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn bpf_tracer_finish() {}
+
+#[no_mangle]
+pub extern "C" fn socket_tracer_stats() -> SK_TRACE_STATS {
+    // SAFETY: SK_TRACE_STATS is a plain C struct of integers
+    unsafe { mem::zeroed() }
+}
+
+#[no_mangle]
+pub extern "C" fn register_event_handle(
+    _event_type: c_uint,
+    _callback: extern "C" fn(data: *mut PROCESS_EVENT),
+) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn running_socket_tracer(
+    _callback: extern "C" fn(sd: *mut SK_BPF_DATA),
+    _thread_nr: c_int,
+    _perf_pages_cnt: c_uint,
+    _ring_size: c_uint,
+    _max_socket_entries: c_uint,
+    _max_trace_entries: c_uint,
+    _socket_map_max_reclaim: c_uint,
+) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn socket_tracer_stop() -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn socket_tracer_start() -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn start_continuous_profiler(
+    _freq: c_int,
+    _java_syms_space_limit: c_int,
+    _java_syms_update_delay: c_int,
+    _callback: extern "C" fn(_data: *mut stack_profile_data),
+) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn stop_continuous_profiler() -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn set_profiler_regex(_pattern: *const c_char) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn set_dwarf_regex(_pattern: *const c_char) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn set_profiler_cpu_aggregation(_flag: c_int) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn process_stack_trace_data_for_flame_graph(_data: *mut stack_profile_data) {}
+
+#[no_mangle]
+pub extern "C" fn release_flame_graph_hash() {}
+
+#[no_mangle]
+pub extern "C" fn datadump_set_config(
+    _pid: c_int,
+    _comm: *const c_char,
+    _proto: c_int,
+    _timeout: c_int,
+    _callback: extern "C" fn(data: *mut c_char, len: c_int),
+) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn cpdbg_set_config(
+    _timeout: c_int,
+    _callback: extern "C" fn(data: *mut c_char, len: c_int),
+) -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn enable_oncpu_profiler() -> c_int {
+    UNSUPPORTED
+}
+
+#[no_mangle]
+pub extern "C" fn disable_oncpu_profiler() -> c_int {
+    UNSUPPORTED
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "off_cpu")] {
+        #[no_mangle]
+        pub extern "C" fn set_offcpu_profiler_regex(_pattern: *const c_char) -> c_int {
+            UNSUPPORTED
+        }
+
+        #[no_mangle]
+        pub extern "C" fn enable_offcpu_profiler() -> c_int {
+            UNSUPPORTED
+        }
+
+        #[no_mangle]
+        pub extern "C" fn disable_offcpu_profiler() -> c_int {
+            UNSUPPORTED
+        }
+
+        #[no_mangle]
+        pub extern "C" fn set_offcpu_cpuid_aggregation(_flag: c_int) -> c_int {
+            UNSUPPORTED
+        }
+
+        #[no_mangle]
+        pub extern "C" fn set_offcpu_minblock_time(_block_time: c_uint) -> c_int {
+            UNSUPPORTED
+        }
+    }
+}
@@ -33,6 +33,8 @@
 #define MAP_ADAPT_KERN_UID_NAME		"__adapt_kern_uid_map"
 #define MAP_PROTO_PORTS_BITMAPS_NAME	"__proto_ports_bitmap"
 #define MAP_ALLOW_REASM_PROTOS_NAME     "__allow_reasm_protos_map"
+#define MAP_SCOPE_PID_NAME		"__scope_pid_map"
+#define MAP_SCOPE_CGROUP_NAME		"__scope_cgroup_map"
 
 //Program jmp tables
 #define MAP_PROGS_JMP_KP_NAME		"__progs_jmp_kp_map"
@@ -92,6 +94,7 @@ enum {
 #define SOCKET_DATA_LIMIT_MAX_DEF	4096
 
 #define MAP_PROC_INFO_MAP_NAME		"proc_info_map"
+#define MAP_RUSTLS_PROC_NAME		"rustls_proc_map"
 
 // execute/exit events delayed processing time, unit: second
 #define PROC_EVENT_DELAY_HANDLE_DEF     60
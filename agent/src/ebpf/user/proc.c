@@ -54,6 +54,7 @@
 #include "bihash_8_8.h"
 #include "profile/stringifier.h"
 #include "profile/profile_common.h"
+#include "profile/dwarf_unwind.h"
 
 static u64 add_symcache_count;
 static u64 free_symcache_count;
@@ -294,6 +295,7 @@ static inline struct symbolizer_proc_info *add_proc_info_to_cache(struct
 	} else {
 		/* Extended handling associated with process execute event. */
 		extended_proc_event_handler(pid, p->comm, PROC_EXEC);
+		dwarf_proc_exec(pid, p->comm);
 		__sync_fetch_and_add(&h->hash_elems_count, 1);
 	}
 
@@ -307,6 +309,7 @@ static inline int del_proc_info_from_cache(struct symbolizer_cache_kvp *kv)
 		p = (struct symbolizer_proc_info *)kv->v.proc_info_p;
 		/* Extended handling associated with process exit event. */
 		extended_proc_event_handler((int)kv->k.pid, p->comm, PROC_EXIT);
+		dwarf_proc_exit((int)kv->k.pid);
 	}
 
 	free_symbolizer_cache_kvp(kv);
@@ -0,0 +1,1136 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+/*
+ * Unwind tables for the eBPF DWARF unwinder.
+ *
+ * The '.eh_frame' section of each executable mapping of a matched process
+ * is interpreted here and flattened into rows of 'struct dwarf_unwind_entry'
+ * sorted by address, each row describes how to compute the CFA and where rbp
+ * is saved from that address up to the next row. Tables are cached by the
+ * inode of the binary and shared between processes, all tables are stored
+ * in the '__dwarf_unwind_entries' array, a process only references ranges of
+ * the array through its '__dwarf_proc_map' entry.
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <regex.h>
+#include <pthread.h>
+#include <sys/stat.h>
+#include <linux/limits.h>
+#include "../config.h"
+#include "../common.h"
+#include "../log.h"
+#include "../types.h"
+#include "../list.h"
+#include "../tracer.h"
+#include "../table.h"
+#include "../elf.h"
+#include "../load.h"
+#include "../btf_vmlinux.h"
+#include "../../kernel/include/perf_profiler.h"
+#include "dwarf_unwind.h"
+
+#define LOG_DWARF_TAG	"[DWARF] "
+
+#if defined(__x86_64__)
+
+/* DWARF register numbers on x86_64 */
+#define DWARF_REG_RBP		6
+#define DWARF_REG_RSP		7
+#define DWARF_REG_RA		16
+
+#define CFA_STATE_STACK_MAX	8
+#define EXEC_SEGMENTS_MAX	4
+
+/* Pointer encodings (DW_EH_PE_*) */
+#define DW_EH_PE_absptr		0x00
+#define DW_EH_PE_uleb128	0x01
+#define DW_EH_PE_udata2		0x02
+#define DW_EH_PE_udata4		0x03
+#define DW_EH_PE_udata8		0x04
+#define DW_EH_PE_sleb128	0x09
+#define DW_EH_PE_sdata2		0x0a
+#define DW_EH_PE_sdata4		0x0b
+#define DW_EH_PE_sdata8		0x0c
+#define DW_EH_PE_pcrel		0x10
+#define DW_EH_PE_indirect	0x80
+#define DW_EH_PE_omit		0xff
+
+/* Call frame instructions (DW_CFA_*) */
+enum {
+	DW_CFA_nop = 0x00,
+	DW_CFA_set_loc = 0x01,
+	DW_CFA_advance_loc1 = 0x02,
+	DW_CFA_advance_loc2 = 0x03,
+	DW_CFA_advance_loc4 = 0x04,
+	DW_CFA_offset_extended = 0x05,
+	DW_CFA_restore_extended = 0x06,
+	DW_CFA_undefined = 0x07,
+	DW_CFA_same_value = 0x08,
+	DW_CFA_register = 0x09,
+	DW_CFA_remember_state = 0x0a,
+	DW_CFA_restore_state = 0x0b,
+	DW_CFA_def_cfa = 0x0c,
+	DW_CFA_def_cfa_register = 0x0d,
+	DW_CFA_def_cfa_offset = 0x0e,
+	DW_CFA_def_cfa_expression = 0x0f,
+	DW_CFA_expression = 0x10,
+	DW_CFA_offset_extended_sf = 0x11,
+	DW_CFA_def_cfa_sf = 0x12,
+	DW_CFA_def_cfa_offset_sf = 0x13,
+	DW_CFA_val_offset = 0x14,
+	DW_CFA_val_offset_sf = 0x15,
+	DW_CFA_val_expression = 0x16,
+	DW_CFA_GNU_args_size = 0x2e,
+	DW_CFA_GNU_negative_offset_extended = 0x2f,
+	DW_CFA_advance_loc = 0x40,
+	DW_CFA_offset = 0x80,
+	DW_CFA_restore = 0xc0,
+};
+
+enum rbp_rule {
+	RBP_RULE_SAME,
+	RBP_RULE_OFFSET,
+	RBP_RULE_UNSUPPORTED,
+};
+
+struct eh_reader {
+	const u8 *buf;		// '.eh_frame' data
+	size_t size;
+	size_t pos;
+	u64 addr;		// sh_addr of '.eh_frame', for pc-relative pointers
+	bool err;
+};
+
+struct cie_info {
+	u64 code_align;
+	i64 data_align;
+	u8 fde_enc;
+	bool has_aug_data;
+	size_t insns_start;
+	size_t insns_end;
+};
+
+struct cfa_rules {
+	u64 cfa_reg;
+	i64 cfa_offset;
+	bool cfa_expr;
+	enum rbp_rule rbp_rule;
+	i64 rbp_offset;
+	bool ra_undefined;
+};
+
+struct unwind_row {
+	struct dwarf_unwind_entry entry;
+	u32 seq;		// keep emission order for rows at the same pc
+};
+
+struct row_buf {
+	struct unwind_row *rows;
+	u32 count;
+	u32 cap;
+	bool err;
+};
+
+struct exec_segment {
+	u64 vaddr;
+	u64 offset;
+	u64 filesz;
+};
+
+// Unwind table of a binary, shared by all processes mapping it
+struct dwarf_table {
+	struct list_head list;	// sorted by 'start'
+	dev_t dev;
+	ino_t ino;
+	u32 start;		// first index in '__dwarf_unwind_entries'
+	u32 count;
+	int refcnt;
+	int segs_cnt;
+	struct exec_segment segs[EXEC_SEGMENTS_MAX];
+};
+
+struct dwarf_proc {
+	struct list_head list;
+	int pid;
+	int tables_cnt;
+	struct dwarf_table *tables[DWARF_MAPPINGS_MAX];
+};
+
+static struct bpf_tracer *dwarf_tracer;
+static pthread_mutex_t dwarf_lock = PTHREAD_MUTEX_INITIALIZER;
+static regex_t dwarf_regex;
+static bool dwarf_regex_existed;
+static struct list_head tables_head = { &tables_head, &tables_head };
+static struct list_head procs_head = { &procs_head, &procs_head };
+
+static u8 read_u8(struct eh_reader *r)
+{
+	if (r->pos + 1 > r->size) {
+		r->err = true;
+		return 0;
+	}
+	return r->buf[r->pos++];
+}
+
+static u64 read_uint(struct eh_reader *r, int size)
+{
+	u64 val = 0;
+	if (r->pos + size > r->size) {
+		r->err = true;
+		return 0;
+	}
+	/* .eh_frame of x86_64 binaries is little-endian */
+	for (int i = size - 1; i >= 0; i--)
+		val = (val << 8) | r->buf[r->pos + i];
+	r->pos += size;
+	return val;
+}
+
+static u64 read_uleb(struct eh_reader *r)
+{
+	u64 val = 0;
+	int shift = 0;
+	u8 b;
+	do {
+		b = read_u8(r);
+		if (shift < 64)
+			val |= (u64) (b & 0x7f) << shift;
+		shift += 7;
+	} while ((b & 0x80) && !r->err);
+	return val;
+}
+
+static i64 read_sleb(struct eh_reader *r)
+{
+	i64 val = 0;
+	int shift = 0;
+	u8 b;
+	do {
+		b = read_u8(r);
+		if (shift < 64)
+			val |= (i64) (b & 0x7f) << shift;
+		shift += 7;
+	} while ((b & 0x80) && !r->err);
+	if (shift < 64 && (b & 0x40))
+		val |= -((i64) 1 << shift);
+	return val;
+}
+
+static u64 read_encoded(struct eh_reader *r, u8 enc)
+{
+	u64 base = r->addr + r->pos;
+	u64 val;
+
+	if (enc == DW_EH_PE_omit)
+		return 0;
+
+	switch (enc & 0x0f) {
+	case DW_EH_PE_absptr:
+	case DW_EH_PE_udata8:
+	case DW_EH_PE_sdata8:
+		val = read_uint(r, 8);
+		break;
+	case DW_EH_PE_uleb128:
+		val = read_uleb(r);
+		break;
+	case DW_EH_PE_udata2:
+		val = read_uint(r, 2);
+		break;
+	case DW_EH_PE_sdata2:
+		val = (i64) (i16) read_uint(r, 2);
+		break;
+	case DW_EH_PE_udata4:
+		val = read_uint(r, 4);
+		break;
+	case DW_EH_PE_sdata4:
+		val = (i64) (i32) read_uint(r, 4);
+		break;
+	case DW_EH_PE_sleb128:
+		val = read_sleb(r);
+		break;
+	default:
+		r->err = true;
+		return 0;
+	}
+
+	/* Indirect, text and data relative pointers are not used by FDEs */
+	if (enc & DW_EH_PE_indirect) {
+		r->err = true;
+		return 0;
+	}
+
+	switch (enc & 0x70) {
+	case 0:
+		break;
+	case DW_EH_PE_pcrel:
+		val += base;
+		break;
+	default:
+		r->err = true;
+		return 0;
+	}
+
+	return val;
+}
+
+// Read the length of a CIE/FDE, returns the end offset of the entry
+static size_t read_entry_length(struct eh_reader *r, bool * terminator)
+{
+	u64 len = read_uint(r, 4);
+	*terminator = (len == 0);
+	if (len == 0xffffffff)
+		len = read_uint(r, 8);
+	if (r->err || len > r->size - r->pos) {
+		r->err = true;
+		return r->size;
+	}
+	return r->pos + len;
+}
+
+static int parse_cie(const struct eh_reader *src, size_t offset,
+		     struct cie_info *cie)
+{
+	struct eh_reader r = *src;
+	bool terminator;
+	r.pos = offset;
+	r.err = false;
+
+	size_t end = read_entry_length(&r, &terminator);
+	if (r.err || terminator || read_uint(&r, 4) != 0)
+		return ETR_INVAL;
+
+	u8 version = read_u8(&r);
+	const char *aug = (const char *)&r.buf[r.pos];
+	size_t aug_len = strnlen(aug, end - r.pos);
+	if (r.pos + aug_len >= end)
+		return ETR_INVAL;
+	r.pos += aug_len + 1;
+
+	// "eh" augmentation of old GCC, followed by a pointer
+	if (strstr(aug, "eh"))
+		r.pos += 8;
+
+	cie->code_align = read_uleb(&r);
+	cie->data_align = read_sleb(&r);
+	if (version == 1)
+		read_u8(&r);
+	else
+		read_uleb(&r);
+
+	cie->fde_enc = DW_EH_PE_absptr;
+	cie->has_aug_data = (aug[0] == 'z');
+	if (cie->has_aug_data) {
+		u64 len = read_uleb(&r);
+		size_t aug_end = r.pos + len;
+		for (int i = 1; aug[i] != '\0' && !r.err; i++) {
+			if (aug[i] == 'R') {
+				cie->fde_enc = read_u8(&r);
+			} else if (aug[i] == 'P') {
+				u8 enc = read_u8(&r);
+				// Skip the pointer of the personality routine
+				read_encoded(&r, enc & ~DW_EH_PE_indirect);
+			} else if (aug[i] == 'L') {
+				read_u8(&r);
+			} else if (aug[i] != 'S' && aug[i] != 'B') {
+				break;
+			}
+		}
+		r.pos = aug_end;
+	}
+
+	if (r.err || r.pos > end)
+		return ETR_INVAL;
+
+	cie->insns_start = r.pos;
+	cie->insns_end = end;
+	return ETR_OK;
+}
+
+static void rules_to_entry(const struct cfa_rules *rules, u64 pc,
+			   struct dwarf_unwind_entry *e)
+{
+	memset(e, 0, sizeof(*e));
+	e->pc = pc;
+
+	if (rules->ra_undefined) {
+		e->cfa_type = DWARF_CFA_TYPE_END;
+		return;
+	}
+
+	if (rules->cfa_expr || rules->rbp_rule == RBP_RULE_UNSUPPORTED ||
+	    rules->cfa_offset < INT16_MIN || rules->cfa_offset > INT16_MAX ||
+	    rules->rbp_offset < INT16_MIN || rules->rbp_offset > INT16_MAX) {
+		e->cfa_type = DWARF_CFA_TYPE_NONE;
+		return;
+	}
+
+	if (rules->cfa_reg == DWARF_REG_RSP)
+		e->cfa_type = DWARF_CFA_TYPE_RSP;
+	else if (rules->cfa_reg == DWARF_REG_RBP)
+		e->cfa_type = DWARF_CFA_TYPE_RBP;
+	else {
+		e->cfa_type = DWARF_CFA_TYPE_NONE;
+		return;
+	}
+
+	e->cfa_offset = (i16) rules->cfa_offset;
+	if (rules->rbp_rule == RBP_RULE_OFFSET) {
+		e->rbp_type = DWARF_RBP_TYPE_OFFSET;
+		e->rbp_offset = (i16) rules->rbp_offset;
+	} else {
+		e->rbp_type = DWARF_RBP_TYPE_SAME;
+	}
+}
+
+static void push_row(struct row_buf *out, const struct cfa_rules *rules,
+		     u64 pc)
+{
+	if (out->err)
+		return;
+
+	if (out->count == out->cap) {
+		u32 cap = out->cap ? out->cap * 2 : 1024;
+		struct unwind_row *rows =
+		    realloc(out->rows, sizeof(*rows) * cap);
+		if (rows == NULL) {
+			out->err = true;
+			return;
+		}
+		out->rows = rows;
+		out->cap = cap;
+	}
+
+	struct unwind_row *row = &out->rows[out->count];
+	rules_to_entry(rules, pc, &row->entry);
+	row->seq = out->count++;
+}
+
+static void set_reg_offset(struct cfa_rules *rules, u64 reg, i64 offset)
+{
+	if (reg == DWARF_REG_RBP) {
+		rules->rbp_rule = RBP_RULE_OFFSET;
+		rules->rbp_offset = offset;
+	}
+	/* The return address is always at CFA - 8 on x86_64 */
+}
+
+static void set_reg_unsupported(struct cfa_rules *rules, u64 reg)
+{
+	if (reg == DWARF_REG_RBP)
+		rules->rbp_rule = RBP_RULE_UNSUPPORTED;
+	else if (reg == DWARF_REG_RA)
+		rules->cfa_expr = true;
+}
+
+static void restore_reg(struct cfa_rules *rules,
+			const struct cfa_rules *initial, u64 reg)
+{
+	if (reg == DWARF_REG_RBP) {
+		rules->rbp_rule = initial->rbp_rule;
+		rules->rbp_offset = initial->rbp_offset;
+	} else if (reg == DWARF_REG_RA) {
+		rules->ra_undefined = initial->ra_undefined;
+	}
+}
+
+/*
+ * Execute call frame instructions in [r->pos, end). A row is pushed to
+ * 'out' each time the location advances, 'out' is NULL for the initial
+ * instructions of a CIE.
+ */
+static int run_cfa_program(struct eh_reader *r, size_t end,
+			   const struct cie_info *cie, struct cfa_rules *rules,
+			   const struct cfa_rules *initial, u64 loc,
+			   u64 loc_end, struct row_buf *out)
+{
+	struct cfa_rules stack[CFA_STATE_STACK_MAX];
+	int depth = 0;
+	u64 reg, delta;
+	i64 offset;
+
+	while (r->pos < end && !r->err) {
+		u8 op = read_u8(r);
+		u8 low = op & 0x3f;
+		delta = 0;
+
+		switch (op & 0xc0) {
+		case DW_CFA_advance_loc:
+			delta = low * cie->code_align;
+			goto advance;
+		case DW_CFA_offset:
+			offset = (i64) read_uleb(r) * cie->data_align;
+			set_reg_offset(rules, low, offset);
+			continue;
+		case DW_CFA_restore:
+			restore_reg(rules, initial, low);
+			continue;
+		}
+
+		switch (op) {
+		case DW_CFA_nop:
+			break;
+		case DW_CFA_set_loc:
+			delta = read_encoded(r, cie->fde_enc) - loc;
+			goto advance;
+		case DW_CFA_advance_loc1:
+			delta = read_u8(r) * cie->code_align;
+			goto advance;
+		case DW_CFA_advance_loc2:
+			delta = read_uint(r, 2) * cie->code_align;
+			goto advance;
+		case DW_CFA_advance_loc4:
+			delta = read_uint(r, 4) * cie->code_align;
+			goto advance;
+		case DW_CFA_offset_extended:
+			reg = read_uleb(r);
+			offset = (i64) read_uleb(r) * cie->data_align;
+			set_reg_offset(rules, reg, offset);
+			break;
+		case DW_CFA_offset_extended_sf:
+			reg = read_uleb(r);
+			offset = read_sleb(r) * cie->data_align;
+			set_reg_offset(rules, reg, offset);
+			break;
+		case DW_CFA_GNU_negative_offset_extended:
+			reg = read_uleb(r);
+			offset = -((i64) read_uleb(r) * cie->data_align);
+			set_reg_offset(rules, reg, offset);
+			break;
+		case DW_CFA_restore_extended:
+			restore_reg(rules, initial, read_uleb(r));
+			break;
+		case DW_CFA_undefined:
+			reg = read_uleb(r);
+			if (reg == DWARF_REG_RA)
+				rules->ra_undefined = true;
+			else if (reg == DWARF_REG_RBP)
+				rules->rbp_rule = RBP_RULE_SAME;
+			break;
+		case DW_CFA_same_value:
+			reg = read_uleb(r);
+			if (reg == DWARF_REG_RBP)
+				rules->rbp_rule = RBP_RULE_SAME;
+			break;
+		case DW_CFA_register:
+			reg = read_uleb(r);
+			read_uleb(r);
+			set_reg_unsupported(rules, reg);
+			break;
+		case DW_CFA_remember_state:
+			if (depth >= CFA_STATE_STACK_MAX)
+				return ETR_NOROOM;
+			stack[depth++] = *rules;
+			break;
+		case DW_CFA_restore_state:
+			if (depth == 0)
+				return ETR_INVAL;
+			*rules = stack[--depth];
+			break;
+		case DW_CFA_def_cfa:
+			rules->cfa_reg = read_uleb(r);
+			rules->cfa_offset = read_uleb(r);
+			rules->cfa_expr = false;
+			break;
+		case DW_CFA_def_cfa_sf:
+			rules->cfa_reg = read_uleb(r);
+			rules->cfa_offset = read_sleb(r) * cie->data_align;
+			rules->cfa_expr = false;
+			break;
+		case DW_CFA_def_cfa_register:
+			rules->cfa_reg = read_uleb(r);
+			rules->cfa_expr = false;
+			break;
+		case DW_CFA_def_cfa_offset:
+			rules->cfa_offset = read_uleb(r);
+			break;
+		case DW_CFA_def_cfa_offset_sf:
+			rules->cfa_offset = read_sleb(r) * cie->data_align;
+			break;
+		case DW_CFA_def_cfa_expression:
+			/* e.g. PLT entries, such frames are not unwound */
+			r->pos += read_uleb(r);
+			rules->cfa_expr = true;
+			break;
+		case DW_CFA_expression:
+		case DW_CFA_val_expression:
+			reg = read_uleb(r);
+			r->pos += read_uleb(r);
+			set_reg_unsupported(rules, reg);
+			break;
+		case DW_CFA_val_offset:
+			reg = read_uleb(r);
+			read_uleb(r);
+			set_reg_unsupported(rules, reg);
+			break;
+		case DW_CFA_val_offset_sf:
+			reg = read_uleb(r);
+			read_sleb(r);
+			set_reg_unsupported(rules, reg);
+			break;
+		case DW_CFA_GNU_args_size:
+			read_uleb(r);
+			break;
+		default:
+			return ETR_NOTSUPP;
+		}
+		continue;
+
+advance:
+		if (out != NULL && loc < loc_end)
+			push_row(out, rules, loc);
+		loc += delta;
+	}
+
+	if (r->err)
+		return ETR_INVAL;
+
+	if (out != NULL && loc < loc_end)
+		push_row(out, rules, loc);
+
+	return ETR_OK;
+}
+
+static int parse_fde(struct eh_reader *r, size_t id_pos, u32 cie_ptr,
+		     size_t end, struct row_buf *out)
+{
+	struct cie_info cie;
+	struct cfa_rules rules, initial;
+	int ret;
+
+	/* The CIE pointer is relative to the position of the field itself */
+	if (cie_ptr > id_pos)
+		return ETR_INVAL;
+	if ((ret = parse_cie(r, id_pos - cie_ptr, &cie)) != ETR_OK)
+		return ret;
+
+	u64 pc_begin = read_encoded(r, cie.fde_enc);
+	u64 pc_range = read_encoded(r, cie.fde_enc & 0x0f);
+	if (cie.has_aug_data)
+		r->pos += read_uleb(r);
+	if (r->err || r->pos > end)
+		return ETR_INVAL;
+
+	memset(&rules, 0, sizeof(rules));
+	struct eh_reader cie_r = *r;
+	cie_r.pos = cie.insns_start;
+	ret = run_cfa_program(&cie_r, cie.insns_end, &cie, &rules, &rules,
+			      0, 0, NULL);
+	if (ret != ETR_OK)
+		return ret;
+
+	initial = rules;
+	ret = run_cfa_program(r, end, &cie, &rules, &initial, pc_begin,
+			      pc_begin + pc_range, out);
+	if (ret != ETR_OK)
+		return ret;
+
+	// Addresses after the end of the function are not covered
+	memset(&rules, 0, sizeof(rules));
+	rules.cfa_expr = true;
+	push_row(out, &rules, pc_begin + pc_range);
+	return ETR_OK;
+}
+
+static int row_cmp(const void *a, const void *b)
+{
+	const struct unwind_row *x = a, *y = b;
+	if (x->entry.pc != y->entry.pc)
+		return x->entry.pc < y->entry.pc ? -1 : 1;
+	return x->seq < y->seq ? -1 : (x->seq > y->seq);
+}
+
+static bool same_rule(const struct dwarf_unwind_entry *a,
+		      const struct dwarf_unwind_entry *b)
+{
+	return a->cfa_type == b->cfa_type && a->cfa_offset == b->cfa_offset
+	    && a->rbp_type == b->rbp_type && a->rbp_offset == b->rbp_offset;
+}
+
+/*
+ * Sort rows by address, for rows at the same address the last one wins,
+ * e.g. the start of a function following the end of the previous one.
+ * Rows repeating the rule of the previous row are dropped.
+ */
+static u32 compact_rows(struct row_buf *out)
+{
+	u32 n = 0;
+	qsort(out->rows, out->count, sizeof(*out->rows), row_cmp);
+	for (u32 i = 0; i < out->count; i++) {
+		if (i + 1 < out->count &&
+		    out->rows[i + 1].entry.pc == out->rows[i].entry.pc)
+			continue;
+		if (n > 0 && same_rule(&out->rows[n - 1].entry,
+				       &out->rows[i].entry))
+			continue;
+		out->rows[n++] = out->rows[i];
+	}
+	return n;
+}
+
+static Elf_Scn *find_section(Elf * elf, const char *name, GElf_Shdr * shdr)
+{
+	Elf_Scn *scn = NULL;
+	size_t str_index;
+	char *sec_name;
+
+	if (elf_getshdrstrndx(elf, &str_index) != 0)
+		return NULL;
+
+	while ((scn = elf_nextscn(elf, scn)) != NULL) {
+		if (!gelf_getshdr(scn, shdr))
+			continue;
+		sec_name = elf_strptr(elf, str_index, shdr->sh_name);
+		if (sec_name && !strcmp(sec_name, name))
+			return scn;
+	}
+
+	return NULL;
+}
+
+static int load_exec_segments(Elf * elf, struct dwarf_table *table)
+{
+	size_t phnum;
+	GElf_Phdr phdr;
+
+	if (elf_getphdrnum(elf, &phnum) != 0)
+		return ETR_INVAL;
+
+	table->segs_cnt = 0;
+	for (size_t i = 0; i < phnum; i++) {
+		if (!gelf_getphdr(elf, i, &phdr))
+			continue;
+		if (phdr.p_type != PT_LOAD || !(phdr.p_flags & PF_X))
+			continue;
+		if (table->segs_cnt >= EXEC_SEGMENTS_MAX)
+			break;
+		struct exec_segment *seg = &table->segs[table->segs_cnt++];
+		seg->vaddr = phdr.p_vaddr;
+		seg->offset = phdr.p_offset;
+		seg->filesz = phdr.p_filesz;
+	}
+
+	return table->segs_cnt > 0 ? ETR_OK : ETR_NOTEXIST;
+}
+
+// Parse '.eh_frame' of the binary, the caller frees out->rows
+static int build_unwind_rows(Elf * elf, struct row_buf *out)
+{
+	GElf_Shdr shdr;
+	Elf_Scn *scn = find_section(elf, ".eh_frame", &shdr);
+	if (scn == NULL)
+		return ETR_NOTEXIST;
+
+	Elf_Data *data = elf_getdata(scn, NULL);
+	if (data == NULL || data->d_buf == NULL)
+		return ETR_NOTEXIST;
+
+	struct eh_reader r = {
+		.buf = data->d_buf,
+		.size = data->d_size,
+		.pos = 0,
+		.addr = shdr.sh_addr,
+		.err = false,
+	};
+
+	while (r.pos < r.size && !out->err) {
+		bool terminator;
+		size_t end = read_entry_length(&r, &terminator);
+		if (r.err || terminator)
+			break;
+
+		size_t id_pos = r.pos;
+		u32 id = read_uint(&r, 4);
+		if (id != 0) {
+			/* Skip FDEs which can not be interpreted */
+			parse_fde(&r, id_pos, id, end, out);
+			r.err = false;
+		}
+		r.pos = end;
+	}
+
+	if (out->err)
+		return ETR_NOMEM;
+
+	return ETR_OK;
+}
+
+static struct dwarf_table *find_table(dev_t dev, ino_t ino)
+{
+	struct dwarf_table *t;
+	list_for_each_entry(t, &tables_head, list) {
+		if (t->dev == dev && t->ino == ino)
+			return t;
+	}
+	return NULL;
+}
+
+static void evict_unused_tables(void)
+{
+	struct dwarf_table *t, *n;
+	list_for_each_entry_safe(t, n, &tables_head, list) {
+		if (t->refcnt == 0) {
+			list_head_del(&t->list);
+			free(t);
+		}
+	}
+}
+
+/*
+ * Find the first gap of '__dwarf_unwind_entries' large enough for
+ * 'count' rows, and insert the table into the sorted list.
+ */
+static bool alloc_table_space(struct dwarf_table *table, u32 count)
+{
+	struct dwarf_table *t;
+	u32 prev_end = 0;
+
+	list_for_each_entry(t, &tables_head, list) {
+		if (t->start - prev_end >= count) {
+			table->start = prev_end;
+			table->count = count;
+			list_add_tail(&table->list, &t->list);
+			return true;
+		}
+		prev_end = t->start + t->count;
+	}
+
+	if (DWARF_UNWIND_ENTRIES_MAX - prev_end >= count) {
+		table->start = prev_end;
+		table->count = count;
+		list_add_tail(&table->list, &tables_head);
+		return true;
+	}
+
+	return false;
+}
+
+static struct dwarf_table *load_table(const char *path, struct stat *st)
+{
+	struct dwarf_table *table;
+	struct row_buf out = { 0 };
+	Elf *elf;
+	int fd;
+	u32 count;
+
+	if ((table = find_table(st->st_dev, st->st_ino)) != NULL)
+		return table;
+
+	if (openelf(path, &elf, &fd) != 0)
+		return NULL;
+
+	table = calloc(1, sizeof(*table));
+	if (table == NULL)
+		goto failed;
+
+	table->dev = st->st_dev;
+	table->ino = st->st_ino;
+	if (load_exec_segments(elf, table) != ETR_OK)
+		goto failed;
+
+	if (build_unwind_rows(elf, &out) != ETR_OK || out.count == 0)
+		goto failed;
+
+	count = compact_rows(&out);
+	if (count > DWARF_UNWIND_ENTRIES_MAX) {
+		ebpf_warning(LOG_DWARF_TAG "%s: %u unwind entries exceed"
+			     " the table size %u\n", path, count,
+			     DWARF_UNWIND_ENTRIES_MAX);
+		goto failed;
+	}
+
+	if (!alloc_table_space(table, count)) {
+		evict_unused_tables();
+		if (!alloc_table_space(table, count)) {
+			ebpf_warning(LOG_DWARF_TAG "%s: no room for %u unwind"
+				     " entries\n", path, count);
+			goto failed;
+		}
+	}
+
+	for (u32 i = 0; i < count; i++) {
+		if (!bpf_table_set_value(dwarf_tracer,
+					 MAP_DWARF_UNWIND_ENTRIES_NAME,
+					 table->start + i,
+					 &out.rows[i].entry)) {
+			list_head_del(&table->list);
+			goto failed;
+		}
+	}
+
+	ebpf_info(LOG_DWARF_TAG "Load unwind table of %s, entries %u"
+		  " (index %u)\n", path, count, table->start);
+	free(out.rows);
+	elf_end(elf);
+	close(fd);
+	return table;
+
+failed:
+	free(out.rows);
+	free(table);
+	elf_end(elf);
+	close(fd);
+	return NULL;
+}
+
+// Runtime address - ELF virtual address of the mapping
+static bool mapping_bias(struct dwarf_table *table, u64 start, u64 offset,
+			 u64 * bias)
+{
+	u64 page_mask = (u64) sysconf(_SC_PAGESIZE) - 1;
+	for (int i = 0; i < table->segs_cnt; i++) {
+		struct exec_segment *seg = &table->segs[i];
+		u64 seg_off = seg->offset & ~page_mask;
+		if (offset < seg_off || offset >= seg->offset + seg->filesz)
+			continue;
+		*bias = start - (seg->vaddr & ~page_mask) - (offset - seg_off);
+		return true;
+	}
+	return false;
+}
+
+static struct dwarf_proc *find_proc(int pid)
+{
+	struct dwarf_proc *p;
+	list_for_each_entry(p, &procs_head, list) {
+		if (p->pid == pid)
+			return p;
+	}
+	return NULL;
+}
+
+static void release_proc(struct dwarf_proc *proc)
+{
+	for (int i = 0; i < proc->tables_cnt; i++)
+		proc->tables[i]->refcnt--;
+	free(proc);
+}
+
+static int load_proc_mappings(struct dwarf_proc *proc,
+			      struct dwarf_proc_info *info)
+{
+	char maps_path[64], line[PATH_MAX + 128], path[PATH_MAX];
+	struct stat st;
+	FILE *fp;
+
+	snprintf(maps_path, sizeof(maps_path), "/proc/%d/maps", proc->pid);
+	if ((fp = fopen(maps_path, "r")) == NULL)
+		return ETR_NOTEXIST;
+
+	while (fgets(line, sizeof(line), fp) != NULL) {
+		unsigned long start, end, offset, inode;
+		char perms[8];
+		int n = 0;
+
+		if (sscanf(line, "%lx-%lx %7s %lx %*x:%*x %lu %n", &start,
+			   &end, perms, &offset, &inode, &n) < 5 || n == 0)
+			continue;
+		if (perms[2] != 'x' || inode == 0 || line[n] != '/')
+			continue;
+
+		line[strcspn(line, "\n")] = '\0';
+		snprintf(path, sizeof(path), "/proc/%d/root%s", proc->pid,
+			 &line[n]);
+		if (stat(path, &st) != 0)
+			continue;
+
+		struct dwarf_table *table = load_table(path, &st);
+		if (table == NULL)
+			continue;
+
+		u64 bias;
+		if (!mapping_bias(table, start, offset, &bias))
+			continue;
+
+		struct dwarf_mapping *m = &info->mappings[info->count++];
+		m->start = start;
+		m->end = end;
+		m->bias = bias;
+		m->entries_start = table->start;
+		m->entries_end = table->start + table->count;
+		proc->tables[proc->tables_cnt++] = table;
+		table->refcnt++;
+		if (info->count >= DWARF_MAPPINGS_MAX)
+			break;
+	}
+
+	fclose(fp);
+	return ETR_OK;
+}
+
+void dwarf_proc_exec(int pid, const char *comm)
+{
+	struct dwarf_proc_info info;
+	struct dwarf_proc *proc;
+
+	if (dwarf_tracer == NULL)
+		return;
+
+	pthread_mutex_lock(&dwarf_lock);
+	if (!dwarf_regex_existed
+	    || regexec(&dwarf_regex, comm, 0, NULL, 0) != 0)
+		goto unlock;
+
+	if (find_proc(pid) != NULL)
+		goto unlock;
+
+	proc = calloc(1, sizeof(*proc));
+	if (proc == NULL)
+		goto unlock;
+
+	proc->pid = pid;
+	memset(&info, 0, sizeof(info));
+	load_proc_mappings(proc, &info);
+	if (info.count == 0 ||
+	    !bpf_table_set_value(dwarf_tracer, MAP_DWARF_PROC_NAME, pid,
+				 &info)) {
+		release_proc(proc);
+		goto unlock;
+	}
+
+	list_add_tail(&proc->list, &procs_head);
+	ebpf_info(LOG_DWARF_TAG "Unwind process %d (%s) with DWARF, mappings"
+		  " %u\n", pid, comm, info.count);
+
+unlock:
+	pthread_mutex_unlock(&dwarf_lock);
+}
+
+void dwarf_proc_exit(int pid)
+{
+	struct dwarf_proc *proc;
+
+	if (dwarf_tracer == NULL)
+		return;
+
+	pthread_mutex_lock(&dwarf_lock);
+	if ((proc = find_proc(pid)) != NULL) {
+		bpf_table_delete_key(dwarf_tracer, MAP_DWARF_PROC_NAME, pid);
+		list_head_del(&proc->list);
+		release_proc(proc);
+	}
+	pthread_mutex_unlock(&dwarf_lock);
+}
+
+int set_dwarf_regex(const char *pattern)
+{
+	int ret = 0;
+
+	pthread_mutex_lock(&dwarf_lock);
+	if (dwarf_regex_existed) {
+		regfree(&dwarf_regex);
+		dwarf_regex_existed = false;
+	}
+
+	if (*pattern != '\0') {
+		ret = regcomp(&dwarf_regex, pattern, REG_EXTENDED);
+		if (ret != 0) {
+			char error_buffer[100];
+			regerror(ret, &dwarf_regex, error_buffer,
+				 sizeof(error_buffer));
+			ebpf_warning(LOG_DWARF_TAG "Pattern %s failed to compile"
+				     " the regular expression: %s\n", pattern,
+				     error_buffer);
+			ret = -1;
+		} else {
+			dwarf_regex_existed = true;
+		}
+	}
+	pthread_mutex_unlock(&dwarf_lock);
+
+	if (ret == 0)
+		ebpf_info(LOG_DWARF_TAG "Set 'dwarf_regex' successful, pattern"
+			  " : '%s'\n", pattern);
+	return ret;
+}
+
+int dwarf_unwind_init(struct bpf_tracer *tracer)
+{
+	struct ebpf_prog *prog =
+	    ebpf_obj__get_prog_by_name(tracer->obj,
+				       PROG_DWARF_UNWIND_NAME_FOR_PE);
+	if (prog == NULL) {
+		ebpf_warning(LOG_DWARF_TAG "Program %s not found.\n",
+			     PROG_DWARF_UNWIND_NAME_FOR_PE);
+		return ETR_NOTEXIST;
+	}
+
+	if (!bpf_table_set_value(tracer, MAP_PROGS_JMP_PE_NAME,
+				 PROG_DWARF_UNWIND_PE_IDX, &prog->prog_fd)) {
+		return ETR_UPDATE_MAP_FAILD;
+	}
+
+	/*
+	 * Samples taken in kernel mode read the user registers from the
+	 * top of the task stack, without the offset of 'task_struct->stack'
+	 * such samples are unwound with frame pointers only.
+	 */
+	int offset = kernel_struct_field_offset(tracer->obj, "task_struct",
+						"stack");
+	if (offset > 0) {
+		u64 val = offset;
+		bpf_table_set_value(tracer, MAP_PROFILER_STATE_NAME,
+				    TASK_STACK_OFFSET_IDX, &val);
+	} else {
+		ebpf_warning(LOG_DWARF_TAG "Offset of 'task_struct->stack' not"
+			     " found, kernel mode samples use frame"
+			     " pointers.\n");
+	}
+
+	dwarf_tracer = tracer;
+	ebpf_info(LOG_DWARF_TAG "DWARF unwinding enabled.\n");
+	return ETR_OK;
+}
+
+bool dwarf_unwind_enabled(void)
+{
+	return dwarf_tracer != NULL;
+}
+
+#else /* !defined(__x86_64__) */
+
+int dwarf_unwind_init(struct bpf_tracer *tracer)
+{
+	return ETR_NOTSUPP;
+}
+
+bool dwarf_unwind_enabled(void)
+{
+	return false;
+}
+
+int set_dwarf_regex(const char *pattern)
+{
+	if (*pattern != '\0')
+		ebpf_warning(LOG_DWARF_TAG "DWARF unwinding is only supported"
+			     " on x86_64.\n");
+	return (-1);
+}
+
+void dwarf_proc_exec(int pid, const char *comm)
+{
+}
+
+void dwarf_proc_exit(int pid)
+{
+}
+
+#endif /* defined(__x86_64__) */
@@ -0,0 +1,53 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#ifndef DF_USER_DWARF_UNWIND_H
+#define DF_USER_DWARF_UNWIND_H
+
+#define MAP_DWARF_PROC_NAME		"__dwarf_proc_map"
+#define MAP_DWARF_UNWIND_ENTRIES_NAME	"__dwarf_unwind_entries"
+#define MAP_DWARF_STACK_NAME		"__dwarf_stack_map"
+#define MAP_PROGS_JMP_PE_NAME		"__progs_jmp_pe_map"
+#define PROG_DWARF_UNWIND_NAME_FOR_PE	"bpf_prog_pe__dwarf_unwind"
+
+/*
+ * Enable DWARF unwinding for the profiler tracer, called after the eBPF
+ * programs of the 'perf_profiler_5_2_plus' variant are loaded.
+ *
+ * @return 0 on success, non-zero on error
+ */
+int dwarf_unwind_init(struct bpf_tracer *tracer);
+
+bool dwarf_unwind_enabled(void);
+
+/*
+ * Set the regular expression of process names to unwind with DWARF,
+ * an empty pattern disables DWARF unwinding.
+ *
+ * @return 0 on success, non-zero on error
+ */
+int set_dwarf_regex(const char *pattern);
+
+/*
+ * Build the unwind tables of the process if it matches, called when the
+ * process is added to the symbolizer cache.
+ */
+void dwarf_proc_exec(int pid, const char *comm);
+
+// Process exit, reclaim the unwind tables no longer used
+void dwarf_proc_exit(int pid);
+
+#endif /* DF_USER_DWARF_UNWIND_H */
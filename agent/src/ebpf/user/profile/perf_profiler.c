@@ -46,8 +46,10 @@
 #include "java/df_jattach.h"
 #include "profile_common.h"
 #include "../proc.h"
+#include "dwarf_unwind.h"
 
 #include "../perf_profiler_bpf_common.c"
+#include "../perf_profiler_bpf_5_2_plus.c"
 
 #define LOG_CP_TAG	"[CP] "
 #define CP_TRACER_NAME	"continuous_profiler"
@@ -68,6 +70,10 @@ static pthread_t java_syms_update_thread;
 
 extern char linux_release[128];
 extern __thread uword thread_index;
+extern int major, minor;
+
+/* The 5.2+ variant carries the DWARF unwinder, x86_64 only. */
+static bool use_dwarf_variant;
 
 struct bpf_tracer *profiler_tracer;
 
@@ -294,6 +300,10 @@ static int create_profiler(struct bpf_tracer *tracer)
 	if (tracer_bpf_load(tracer))
 		return ETR_LOAD;
 
+	if (use_dwarf_variant && dwarf_unwind_init(tracer) != ETR_OK)
+		ebpf_warning(LOG_CP_TAG "DWARF unwinding is unavailable,"
+			     " user stacks are unwound with frame pointers.\n");
+
 	/* clear old perf files */
 	exec_command("/usr/bin/rm -rf /tmp/perf-*.map", "");
 	exec_command("/usr/bin/rm -rf /tmp/perf-*.log", "");
@@ -454,6 +464,12 @@ static void print_cp_tracer_status(struct bpf_tracer *t,
 			     ctx->state_map_name);
 	}
 
+	u64 dwarf_unwind_cnt = 0;
+	if (dwarf_unwind_enabled())
+		bpf_table_get_value(t, ctx->state_map_name,
+				    DWARF_UNWIND_CNT_IDX,
+				    (void *)&dwarf_unwind_cnt);
+
 	ebpf_info("\n\n----------------------------\nrecv envent:\t%lu\n"
 		  "process-cnt:\t%lu\nkern_lost:\t%lu perf_buf_lost_a:\t%lu, "
 		  "perf_buf_lost_b:\t%lu process_lost_count:\t%lu "
@@ -467,10 +483,10 @@ static void print_cp_tracer_status(struct bpf_tracer *t,
 		  " - sample_drop_cnt:\t%lu\n"
 		  " - output_err_cnt:\t%lu\n"
 		  " - iter_max_cnt:\t%lu\n"
+		  " - dwarf_unwind_cnt:\t%lu\n"
 		  "----------------------------\n\n",
 		  atomic64_read(&t->recv), ctx->process_count,
 		  atomic64_read(&t->lost), ctx->perf_buf_lost_a_count,
-		  ctx->perf_buf_lost_b_count, ctx->perf_buf_lost_a_count,
 		  ctx->perf_buf_lost_b_count, get_process_lost_count(ctx),
 		  get_stack_table_data_miss_count(),
 		  ctx->stackmap_clear_failed_count, ctx->stack_trace_err,
@@ -478,7 +494,7 @@ static void print_cp_tracer_status(struct bpf_tracer *t,
 		  ((double)atomic64_read(&t->recv) /
 		   (double)ctx->transfer_count), alloc_b, free_b,
 		  alloc_b - free_b, output_count, sample_drop_cnt,
-		  output_err_cnt, iter_max_cnt);
+		  output_err_cnt, iter_max_cnt, dwarf_unwind_cnt);
 }
 
 static int cpdbg_sockopt_get(sockoptid_t opt, const void *conf, size_t size,
@@ -587,8 +603,16 @@ int start_continuous_profiler(int freq, int java_syms_space_limit,
 		return (-1);
 
 	snprintf(bpf_load_buffer_name, NAME_LEN, "continuous_profiler");
-	bpf_bin_buffer = (void *)perf_profiler_common_ebpf_data;
-	buffer_sz = sizeof(perf_profiler_common_ebpf_data);
+#if defined(__x86_64__)
+	use_dwarf_variant = (major > 5 || (major == 5 && minor >= 2));
+#endif
+	if (use_dwarf_variant) {
+		bpf_bin_buffer = (void *)perf_profiler_5_2_plus_ebpf_data;
+		buffer_sz = sizeof(perf_profiler_5_2_plus_ebpf_data);
+	} else {
+		bpf_bin_buffer = (void *)perf_profiler_common_ebpf_data;
+		buffer_sz = sizeof(perf_profiler_common_ebpf_data);
+	}
 
 	struct tracer_probes_conf *tps =
 	    malloc(sizeof(struct tracer_probes_conf));
@@ -505,7 +505,11 @@ static int init_stack_trace_msg_hash(stack_trace_msg_hash_t * h,
 static void add_stack_id_to_bitmap(struct profiler_context *ctx,
 				   int stack_id, bool is_a)
 {
-	if (stack_id < 0)
+	/*
+	 * Stacks unwound with DWARF are kept in an LRU map, they are
+	 * never cleaned up by stack ID.
+	 */
+	if (stack_id < 0 || (stack_id & DWARF_STACK_ID_FLAG))
 		return;
 
 	struct stack_ids_bitmap *ids;
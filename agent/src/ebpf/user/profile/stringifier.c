@@ -58,6 +58,7 @@
 #include "../bihash_16_8.h"
 #include "java/gen_syms_file.h"
 #include "stringifier.h"
+#include "dwarf_unwind.h"
 #include <bcc/bcc_syms.h>
 #include "../proc.h"
 
@@ -308,6 +309,10 @@ static int get_stack_ips(struct bpf_tracer *t,
 {
 	ASSERT(stack_id >= 0);
 
+	/* Stacks unwound with DWARF are not stored in the stack trace maps */
+	if (stack_id & DWARF_STACK_ID_FLAG)
+		stack_map_name = MAP_DWARF_STACK_NAME;
+
 	if (!bpf_table_get_value(t, stack_map_name, stack_id, (void *)ips)) {
 		return ETR_NOTEXIST;
 	}
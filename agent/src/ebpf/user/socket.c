@@ -33,6 +33,7 @@
 #include "log.h"
 #include "go_tracer.h"
 #include "ssl_tracer.h"
+#include "usdt_tracer.h"
 #include "load.h"
 #include "btf_vmlinux.h"
 #include "config.h"
@@ -197,6 +198,8 @@ static void socket_tracer_set_probes(struct tracer_probes_conf *tps)
 	collect_go_uprobe_syms_from_procfs(tps);
 
 	collect_ssl_uprobe_syms_from_procfs(tps);
+
+	collect_usdt_uprobe_syms_from_procfs(tps);
 }
 
 /* ==========================================================
@@ -612,12 +615,14 @@ static void process_event(struct process_event_t *e)
 		update_proc_info_cache(e->pid, PROC_EXEC);
 		go_process_exec(e->pid);
 		ssl_process_exec(e->pid);
+		usdt_process_exec(e->pid);
 	} else if (e->meta.event_type == EVENT_TYPE_PROC_EXIT) {
 		/* Cache for updating process information used in
 		 * symbol resolution. */
 		update_proc_info_cache(e->pid, PROC_EXIT);
 		go_process_exit(e->pid);
 		ssl_process_exit(e->pid);
+		usdt_process_exit(e->pid);
 	}
 }
 
@@ -1210,6 +1215,7 @@ static void process_events_handle_main(__unused void *arg)
 
 		go_process_events_handle();
 		ssl_events_handle();
+		usdt_events_handle();
 		check_datadump_timeout();
 		/* check and clean symbol cache */
 		exec_proc_info_cache_update();
@@ -1665,6 +1671,86 @@ int set_io_event_minimal_duration(uint64_t duration)
 	return 0;
 }
 
+/*
+ * Mark a process out of the collection scope, data of it is dropped in eBPF.
+ * @pid : process id
+ *
+ * @return 0 on success, < 0 on failure.
+ */
+int set_scope_pid(int pid)
+{
+	struct bpf_tracer *tracer = find_bpf_tracer(SK_TRACER_NAME);
+	if (tracer == NULL)
+		return ETR_NOTEXIST;
+
+	uint8_t value = 1;
+	if (!bpf_table_set_value(tracer, MAP_SCOPE_PID_NAME, pid, &value))
+		return ETR_UPDATE_MAP_FAILD;
+
+	return ETR_OK;
+}
+
+/*
+ * Mark a cgroup out of the collection scope, data of processes in it is
+ * dropped in eBPF. Only effective on Linux 5.2+.
+ * @cgroup_id : id of the cgroup in cgroup v2 hierarchy, i.e. inode number
+ *              of the cgroup directory
+ *
+ * @return 0 on success, < 0 on failure.
+ */
+int set_scope_cgroup(uint64_t cgroup_id)
+{
+	struct bpf_tracer *tracer = find_bpf_tracer(SK_TRACER_NAME);
+	if (tracer == NULL)
+		return ETR_NOTEXIST;
+
+	uint8_t value = 1;
+	if (!bpf_table_set_value
+	    (tracer, MAP_SCOPE_CGROUP_NAME, cgroup_id, &value))
+		return ETR_UPDATE_MAP_FAILD;
+
+	return ETR_OK;
+}
+
+static int clear_scope_map(struct bpf_tracer *tracer, const char *name)
+{
+	struct ebpf_map *map = ebpf_obj__get_map_by_name(tracer->obj, name);
+	if (map == NULL) {
+		ebpf_warning("[%s] map(name:%s) is NULL.\n", __func__, name);
+		return ETR_NOTEXIST;
+	}
+
+	/*
+	 * The iteration restarts from the first key once the current
+	 * key is deleted.
+	 */
+	uint64_t key = 0, next_key;
+	while (bpf_get_next_key(map->fd, &key, &next_key) == 0) {
+		bpf_delete_elem(map->fd, &next_key);
+		key = next_key;
+	}
+
+	return ETR_OK;
+}
+
+/*
+ * Remove all processes and cgroups out of the collection scope.
+ *
+ * @return 0 on success, < 0 on failure.
+ */
+int clear_scope(void)
+{
+	struct bpf_tracer *tracer = find_bpf_tracer(SK_TRACER_NAME);
+	if (tracer == NULL)
+		return ETR_NOTEXIST;
+
+	int ret = clear_scope_map(tracer, MAP_SCOPE_PID_NAME);
+	if (ret != ETR_OK)
+		return ret;
+
+	return clear_scope_map(tracer, MAP_SCOPE_CGROUP_NAME);
+}
+
 static void __insert_output_prog_to_map(struct bpf_tracer *tracer,
 					const char *map_name,
 					const char *prog_name, int key)
@@ -2110,6 +2196,12 @@ int running_socket_tracer(tracer_callback_t handle,
 	// Update go offsets to eBPF "proc_info_map"
 	update_proc_info_to_map(tracer);
 
+	// Update USDT argument specs to eBPF "__usdt_spec_map"
+	update_usdt_specs_to_map(tracer);
+
+	// Update processes with rustls uprobes to eBPF "rustls_proc_map"
+	update_rustls_procs_to_map(tracer);
+
 	// Insert prog of output data into map for using BPF Tail Calls.
 	insert_output_prog_to_map(tracer);
 
@@ -2872,6 +2964,35 @@ static inline int __set_protocol_ports_bitmap(int proto_type,
 	return 0;
 }
 
+/*
+ * Enable or disable l7 protocol inference at runtime, the protocol
+ * filter map is updated directly if socket tracer is running.
+ */
+int set_ebpf_protocol_enabled(int protocol, bool enabled)
+{
+	if (protocol < 0 || protocol >= PROTO_NUM)
+		return ETR_INVAL;
+
+	ebpf_config_protocol_filter[protocol] = enabled;
+
+	struct bpf_tracer *tracer = find_bpf_tracer(SK_TRACER_NAME);
+	if (tracer == NULL) {
+		/*
+		 * Called before running_socket_tracer(),
+		 * no need to update protocol filter map
+		 */
+		return ETR_OK;
+	}
+
+	if (!bpf_table_set_value(tracer, MAP_PROTO_FILTER_NAME, protocol,
+				 &ebpf_config_protocol_filter[protocol])) {
+		ebpf_warning("Set '%s' failed\n", MAP_PROTO_FILTER_NAME);
+		return ETR_UPDATE_MAP_FAILD;
+	}
+
+	return ETR_OK;
+}
+
 int set_protocol_ports_bitmap(int proto_type, const char *ports)
 {
 	ASSERT(proto_type < ARRAY_SIZE(ports_bitmap));
@@ -340,6 +340,9 @@ int set_data_limit_max(int limit_size);
 int set_go_tracing_timeout(int timeout);
 int set_io_event_collect_mode(uint32_t mode);
 int set_io_event_minimal_duration(uint64_t duration);
+int set_scope_pid(int pid);
+int set_scope_cgroup(uint64_t cgroup_id);
+int clear_scope(void);
 struct socket_trace_stats socket_tracer_stats(void);
 int running_socket_tracer(tracer_callback_t handle,
 			  int thread_nr,
@@ -353,4 +356,5 @@ int socket_tracer_stop(void);
 int socket_tracer_start(void);
 enum tracer_state get_socket_tracer_state(void);
 int set_protocol_ports_bitmap(int proto_type, const char *ports);
+int set_ebpf_protocol_enabled(int protocol, bool enabled);
 #endif /* DF_USER_SOCKET_H */
@@ -19,6 +19,8 @@
 #include "socket.h"
 #include "common.h"
 #include "log.h"
+#include "table.h"
+#include "config.h"
 #include <bcc/bcc_proc.h>
 #include <bcc/bcc_elf.h>
 #include <dirent.h>
@@ -39,9 +41,19 @@ struct ssl_process_create_event {
 	struct bpf_tracer *tracer;
 };
 
+// Process with rustls uprobes attached, written to "rustls_proc_map"
+struct rustls_proc_entry {
+	struct list_head list;
+	int pid;
+	bool has_updated;
+};
+
 static struct list_head proc_events_list;
 static pthread_mutex_t proc_events_list_mutex;
 
+// Protected by 'mutex_probes_lock' of the socket tracer
+static struct list_head rustls_procs_head = { &rustls_procs_head, &rustls_procs_head };
+
 static struct symbol openssl_syms[] = {
 	{
 		.type = OPENSSL_UPROBE,
@@ -69,6 +81,130 @@ static struct symbol openssl_syms[] = {
 	},
 };
 
+// BoringSSL shares the return probes with openssl
+static struct symbol boringssl_syms[] = {
+	{
+		.type = OPENSSL_UPROBE,
+		.symbol = "SSL_write",
+		.probe_func = "uprobe_boringssl_write_enter",
+		.is_probe_ret = false,
+	},
+	{
+		.type = OPENSSL_UPROBE,
+		.symbol = "SSL_write",
+		.probe_func = "uprobe_openssl_write_exit",
+		.is_probe_ret = true,
+	},
+	{
+		.type = OPENSSL_UPROBE,
+		.symbol = "SSL_read",
+		.probe_func = "uprobe_boringssl_read_enter",
+		.is_probe_ret = false,
+	},
+	{
+		.type = OPENSSL_UPROBE,
+		.symbol = "SSL_read",
+		.probe_func = "uprobe_openssl_read_exit",
+		.is_probe_ret = true,
+	},
+};
+
+/*
+ * rustls is linked into the executable, the symbols are matched by the
+ * fragments of the mangled names (legacy mangling) of the trait methods:
+ *   <rustls::conn::Writer as std::io::Write>::write
+ *   <rustls::conn::Reader as std::io::Read>::read
+ * Reader/Writer are moved to rustls::conn::connection since rustls 0.22.
+ */
+#define RUSTLS_WRITE_SYM "rustls..conn..Writer$u20$as$u20$std..io..Write$GT$5write17h"
+#define RUSTLS_READ_SYM "rustls..conn..Reader$u20$as$u20$std..io..Read$GT$4read17h"
+#define RUSTLS_0_22_WRITE_SYM \
+	"rustls..conn..connection..Writer$u20$as$u20$std..io..Write$GT$5write17h"
+#define RUSTLS_0_22_READ_SYM \
+	"rustls..conn..connection..Reader$u20$as$u20$std..io..Read$GT$4read17h"
+
+static struct symbol rustls_syms[] = {
+	{
+		.type = OPENSSL_UPROBE,
+		.symbol = RUSTLS_WRITE_SYM,
+		.probe_func = "uprobe_rustls_write_enter",
+		.is_probe_ret = false,
+	},
+	{
+		.type = OPENSSL_UPROBE,
+		.symbol = RUSTLS_WRITE_SYM,
+		.probe_func = "uprobe_rustls_write_exit",
+		.is_probe_ret = true,
+	},
+	{
+		.type = OPENSSL_UPROBE,
+		.symbol = RUSTLS_READ_SYM,
+		.probe_func = "uprobe_rustls_read_enter",
+		.is_probe_ret = false,
+	},
+	{
+		.type = OPENSSL_UPROBE,
+		.symbol = RUSTLS_READ_SYM,
+		.probe_func = "uprobe_rustls_read_exit",
+		.is_probe_ret = true,
+	},
+	{
+		.type = OPENSSL_UPROBE,
+		.symbol = RUSTLS_0_22_WRITE_SYM,
+		.probe_func = "uprobe_rustls_write_enter",
+		.is_probe_ret = false,
+	},
+	{
+		.type = OPENSSL_UPROBE,
+		.symbol = RUSTLS_0_22_WRITE_SYM,
+		.probe_func = "uprobe_rustls_write_exit",
+		.is_probe_ret = true,
+	},
+	{
+		.type = OPENSSL_UPROBE,
+		.symbol = RUSTLS_0_22_READ_SYM,
+		.probe_func = "uprobe_rustls_read_enter",
+		.is_probe_ret = false,
+	},
+	{
+		.type = OPENSSL_UPROBE,
+		.symbol = RUSTLS_0_22_READ_SYM,
+		.probe_func = "uprobe_rustls_read_exit",
+		.is_probe_ret = true,
+	},
+};
+
+enum ssl_lib_type {
+	SSL_LIB_OPENSSL,
+	SSL_LIB_BORINGSSL,
+};
+
+/*
+ * Tuned profiles of the most common proxies terminating TLS. Processes not
+ * listed here are only hooked in libssl.so.
+ */
+struct ssl_uprobe_profile {
+	// basename of the executable
+	const char *process_name;
+	enum ssl_lib_type lib;
+	// SSL_* may be statically linked in the executable
+	bool static_link;
+	// Delay of handling exec/fork events in seconds. Workers forked by
+	// the master process on reload have the libraries loaded already,
+	// so that they are attached shortly to keep decrypting traffic.
+	uint32_t event_delay;
+};
+
+static struct ssl_uprobe_profile ssl_profiles[] = {
+	// Envoy statically links BoringSSL with custom BIOs
+	{ "envoy", SSL_LIB_BORINGSSL, true, 5 },
+	// nginx links libssl.so, or openssl statically with --with-openssl
+	{ "nginx", SSL_LIB_OPENSSL, true, 5 },
+	{ "openresty", SSL_LIB_OPENSSL, true, 5 },
+};
+
+#define SSL_PROC_EVENT_HANDLE_DELAY 120
+
 #if defined(__powerpc64__) && defined(_CALL_ELF) && _CALL_ELF == 2
 #define bcc_use_symbol_type (65535 | (1 << STT_PPC64_ELFV2_SYM_LEP))
 #else
@@ -82,10 +218,20 @@ static struct bcc_symbol_option bcc_elf_foreach_sym_option = {
 	.use_symbol_type = bcc_use_symbol_type,
 };
 
+// Rust executables often ship the symbols in a separate debug file
+static struct bcc_symbol_option bcc_elf_foreach_sym_debug_option = {
+	.use_debug_file = 1,
+	.check_debug_file_crc = 1,
+	.lazy_symbolize = 1,
+	.use_symbol_type = bcc_use_symbol_type,
+};
+
 struct bcc_elf_foreach_sym_payload {
 	uint64_t addr;
 	uint64_t size;
 	const char *name;
+	// match a fragment of the symbol instead of the suffix
+	bool pattern;
 };
 
 // Lower version kernels do not support hooking so files in containers
@@ -113,7 +259,7 @@ static int bcc_elf_foreach_sym_callback(const char *name, uint64_t addr,
 	struct bcc_elf_foreach_sym_payload *p = payload;
 	char *pos;
 	if ((pos = strstr(name, p->name))) {
-		if (pos[strlen(p->name)] == '\0') {
+		if (p->pattern || pos[strlen(p->name)] == '\0') {
 			p->addr = addr;
 			p->size = size;
 			return -1;
@@ -122,29 +268,52 @@ static int bcc_elf_foreach_sym_callback(const char *name, uint64_t addr,
 	return 0;
 }
 
+static struct ssl_uprobe_profile *find_ssl_profile(const char *path)
+{
+	const char *name;
+	int i;
+
+	if (!path)
+		return NULL;
+
+	name = strrchr(path, '/');
+	name = name ? name + 1 : path;
+	for (i = 0; i < NELEMS(ssl_profiles); i++) {
+		if (!strcmp(name, ssl_profiles[i].process_name))
+			return &ssl_profiles[i];
+	}
+	return NULL;
+}
+
+// Returns the number of symbols added
 static int add_probe_sym_to_tracer_probes(int pid, const char *path,
+					  struct symbol *syms, int syms_count,
+					  bool pattern,
 					  struct tracer_probes_conf *conf)
 {
 	int ret = 0;
 	int idx = 0;
+	int count = 0;
 	struct symbol_uprobe *probe_sym = NULL;
 	struct symbol *cur = NULL;
 	struct bcc_elf_foreach_sym_payload payload;
 
-	for (idx = 0; idx < NELEMS(openssl_syms); ++idx) {
+	for (idx = 0; idx < syms_count; ++idx) {
 		memset(&payload, 0, sizeof(payload));
-		cur = &openssl_syms[idx];
+		cur = &syms[idx];
 
 		// Use memory on the stack, no need to allocate on the heap
 		payload.name = cur->symbol;
+		payload.pattern = pattern;
 		ret = bcc_elf_foreach_sym(path, bcc_elf_foreach_sym_callback,
+					  pattern ?
+					  &bcc_elf_foreach_sym_debug_option :
 					  &bcc_elf_foreach_sym_option,
 					  &payload);
 		if (ret)
 			break;
 
-		// It has been confirmed earlier that the incoming binary file
-		// must be libssl.so and should not be hit here
+		// Not hit for libssl.so, while executables may be stripped
 		if (!payload.addr || !payload.size)
 			continue;
 
@@ -170,13 +339,14 @@ static int add_probe_sym_to_tracer_probes(int pid, const char *path,
 		if (probe_sym->probe_func && probe_sym->name &&
 		    probe_sym->binary_path) {
 			add_uprobe_symbol(pid, probe_sym, conf);
+			count++;
 		} else {
 			free((void *)probe_sym->probe_func);
 			free((void *)probe_sym->name);
 			free((void *)probe_sym->binary_path);
 		}
 	}
-	return 0;
+	return count;
 }
 
 // https://github.com/iovisor/bcc/blob/15fccdb9a4dbdc3d41e669a7ad5be73d2ac44b00/src/cc/bcc_proc.c#L419
@@ -241,6 +411,8 @@ static char *get_openssl_so_path_by_pid(int pid)
 static void openssl_parse_and_register(int pid, struct tracer_probes_conf *conf)
 {
 	char *path = NULL;
+	char *exe_path = NULL;
+	struct ssl_uprobe_profile *profile;
 
 	if (pid <= 1)
 		goto out;
@@ -248,16 +420,148 @@ static void openssl_parse_and_register(int pid, struct tracer_probes_conf *conf)
 	if (!is_user_process(pid))
 		goto out;
 
-	path = get_openssl_so_path_by_pid(pid);
-	if (!path)
+	exe_path = get_elf_path_by_pid(pid);
+	profile = find_ssl_profile(exe_path);
+
+	if (!profile || profile->lib == SSL_LIB_OPENSSL) {
+		path = get_openssl_so_path_by_pid(pid);
+		if (path) {
+			ebpf_info("openssl uprobe, pid:%d, path:%s\n", pid, path);
+			add_probe_sym_to_tracer_probes(pid, path, openssl_syms,
+						       NELEMS(openssl_syms),
+						       false, conf);
+			goto out;
+		}
+	}
+
+	if (!profile || !profile->static_link || !exe_path)
 		goto out;
 
-	ebpf_info("openssl uprobe, pid:%d, path:%s\n", pid, path);
-	add_probe_sym_to_tracer_probes(pid, path, conf);
+	if (profile->lib == SSL_LIB_BORINGSSL) {
+		ebpf_info("boringssl uprobe, pid:%d, path:%s, profile:%s\n",
+			  pid, exe_path, profile->process_name);
+		if (!add_probe_sym_to_tracer_probes(pid, exe_path, boringssl_syms,
+						    NELEMS(boringssl_syms),
+						    false, conf))
+			goto not_found;
+	} else {
+		ebpf_info("openssl uprobe, pid:%d, path:%s, profile:%s\n",
+			  pid, exe_path, profile->process_name);
+		if (!add_probe_sym_to_tracer_probes(pid, exe_path, openssl_syms,
+						    NELEMS(openssl_syms),
+						    false, conf))
+			goto not_found;
+	}
 
 out:
 	free(path);
+	free(exe_path);
 	return;
+
+not_found:
+	ebpf_warning("SSL_write/SSL_read not found in %s, pid:%d, the "
+		     "symbol table may be stripped\n", exe_path, pid);
+	goto out;
+}
+
+static void add_rustls_proc(int pid)
+{
+	struct rustls_proc_entry *entry;
+
+	list_for_each_entry(entry, &rustls_procs_head, list) {
+		if (entry->pid == pid)
+			return;
+	}
+
+	entry = calloc(1, sizeof(*entry));
+	if (!entry) {
+		ebpf_warning("no memory.\n");
+		return;
+	}
+	entry->pid = pid;
+	list_add_tail(&entry->list, &rustls_procs_head);
+}
+
+/*
+ * rustls in the executable, or openssl statically linked by native-tls
+ * (the vendored feature of openssl-sys). native-tls with the system
+ * libssl.so is covered by the openssl configuration.
+ */
+static void rustls_parse_and_register(int pid, struct tracer_probes_conf *conf)
+{
+	char *exe_path = NULL;
+
+	if (pid <= 1)
+		goto out;
+
+	if (!is_user_process(pid))
+		goto out;
+
+	exe_path = get_elf_path_by_pid(pid);
+	if (!exe_path)
+		goto out;
+
+	if (add_probe_sym_to_tracer_probes(pid, exe_path, rustls_syms,
+					   NELEMS(rustls_syms), true, conf)) {
+		ebpf_info("rustls uprobe, pid:%d, path:%s\n", pid, exe_path);
+		// The fd is learned from socket syscalls of the process
+		add_rustls_proc(pid);
+		goto out;
+	}
+
+	if (add_probe_sym_to_tracer_probes(pid, exe_path, openssl_syms,
+					   NELEMS(openssl_syms), false, conf)) {
+		ebpf_info("openssl uprobe, pid:%d, path:%s, static linked\n",
+			  pid, exe_path);
+		goto out;
+	}
+
+	ebpf_warning("rustls or openssl symbols not found in %s, pid:%d, the "
+		     "symbol table may be stripped\n", exe_path, pid);
+out:
+	free(exe_path);
+}
+
+static void ssl_parse_and_register(int pid, struct tracer_probes_conf *conf)
+{
+	char *path = get_elf_path_by_pid(pid);
+
+	if (is_feature_matched(FEATURE_UPROBE_OPENSSL, path))
+		openssl_parse_and_register(pid, conf);
+	if (is_feature_matched(FEATURE_UPROBE_RUSTLS, path))
+		rustls_parse_and_register(pid, conf);
+	free(path);
+}
+
+void update_rustls_procs_to_map(struct bpf_tracer *tracer)
+{
+	struct rustls_proc_entry *entry;
+	uint32_t val = 1;
+
+	list_for_each_entry(entry, &rustls_procs_head, list) {
+		if (entry->has_updated)
+			continue;
+		if (!bpf_table_set_value(tracer, MAP_RUSTLS_PROC_NAME,
+					 entry->pid, (void *)&val))
+			continue;
+		entry->has_updated = true;
+	}
+}
+
+static void clear_rustls_proc(struct bpf_tracer *tracer, int pid)
+{
+	struct rustls_proc_entry *entry, *n;
+
+	list_for_each_entry_safe(entry, n, &rustls_procs_head, list) {
+		if (entry->pid != pid)
+			continue;
+		// The kernel removes the element when the process exits,
+		// which may have been done already.
+		if (entry->has_updated)
+			bpf_table_delete_key(tracer, MAP_RUSTLS_PROC_NAME, pid);
+		list_head_del(&entry->list);
+		free(entry);
+	}
 }
 
 static void clear_ssl_probes_by_pid(struct bpf_tracer *tracer, int pid)
@@ -286,9 +590,9 @@ static void clear_ssl_probes_by_pid(struct bpf_tracer *tracer, int pid)
 	}
 }
 
-static void add_event_to_proc_list(struct bpf_tracer *tracer, int pid)
+static void add_event_to_proc_list(struct bpf_tracer *tracer, int pid,
+				   uint32_t delay)
 {
-	static const uint32_t PROC_EVENT_HANDLE_DELAY = 120;
 	struct ssl_process_create_event *event = NULL;
 
 	event = calloc(1, sizeof(struct ssl_process_create_event));
@@ -299,7 +603,7 @@ static void add_event_to_proc_list(struct bpf_tracer *tracer, int pid)
 
 	event->tracer = tracer;
 	event->pid = pid;
-	event->expire_time = get_sys_uptime() + PROC_EVENT_HANDLE_DELAY;
+	event->expire_time = get_sys_uptime() + delay;
 
 	pthread_mutex_lock(&proc_events_list_mutex);
 	list_add_tail(&event->list, &proc_events_list);
@@ -307,13 +611,17 @@ static void add_event_to_proc_list(struct bpf_tracer *tracer, int pid)
 	return;
 }
 
-static struct ssl_process_create_event *get_first_event(void)
+// Events have different delays, so that expired events are not always the first
+static struct ssl_process_create_event *get_first_expired_event(void)
 {
-	struct ssl_process_create_event *event = NULL;
+	struct ssl_process_create_event *event = NULL, *e;
+	uint32_t now = get_sys_uptime();
 	pthread_mutex_lock(&proc_events_list_mutex);
-	if (!list_empty(&proc_events_list)) {
-		event = list_first_entry(&proc_events_list,
-					 struct ssl_process_create_event, list);
+	list_for_each_entry(e, &proc_events_list, list) {
+		if (now >= e->expire_time) {
+			event = e;
+			break;
+		}
 	}
 	pthread_mutex_unlock(&proc_events_list_mutex);
 	return event;
@@ -333,7 +641,8 @@ int collect_ssl_uprobe_syms_from_procfs(struct tracer_probes_conf *conf)
 	int pid = 0;
 	char *path = NULL;
 
-	if (!is_feature_enabled(FEATURE_UPROBE_OPENSSL))
+	if (!is_feature_enabled(FEATURE_UPROBE_OPENSSL) &&
+	    !is_feature_enabled(FEATURE_UPROBE_RUSTLS))
 		return ETR_OK;
 
 	if (!openssl_kern_check()) {
@@ -360,6 +669,9 @@ int collect_ssl_uprobe_syms_from_procfs(struct tracer_probes_conf *conf)
 		if (is_feature_matched(FEATURE_UPROBE_OPENSSL, path)) {
 			openssl_parse_and_register(pid, conf);
 		}
+		if (is_feature_matched(FEATURE_UPROBE_RUSTLS, path)) {
+			rustls_parse_and_register(pid, conf);
+		}
 		free(path);
 	}
 
@@ -370,12 +682,17 @@ int collect_ssl_uprobe_syms_from_procfs(struct tracer_probes_conf *conf)
 void ssl_process_exec(int pid)
 {
 	struct bpf_tracer *tracer = NULL;
+	struct ssl_uprobe_profile *profile = NULL;
 	char *path = NULL;
 	int matched = false;
 	if (!openssl_kern_check())
 		return;
 	path = get_elf_path_by_pid(pid);
 	matched = is_feature_matched(FEATURE_UPROBE_OPENSSL, path);
+	if (matched)
+		profile = find_ssl_profile(path);
+	else
+		matched = is_feature_matched(FEATURE_UPROBE_RUSTLS, path);
 	free(path);
 	if (!matched)
 		return;
@@ -393,14 +710,17 @@ void ssl_process_exec(int pid)
 		return;
 	}
 
-	add_event_to_proc_list(tracer, pid);
+	add_event_to_proc_list(tracer, pid,
+			       profile ? profile->event_delay :
+			       SSL_PROC_EVENT_HANDLE_DELAY);
 }
 
 void ssl_process_exit(int pid)
 {
 	struct bpf_tracer *tracer = NULL;
 
-	if (!is_feature_enabled(FEATURE_UPROBE_OPENSSL))
+	if (!is_feature_enabled(FEATURE_UPROBE_OPENSSL) &&
+	    !is_feature_enabled(FEATURE_UPROBE_RUSTLS))
 		return;
 
 	if (!openssl_kern_check())
@@ -415,6 +735,7 @@ void ssl_process_exit(int pid)
 
 	pthread_mutex_lock(&tracer->mutex_probes_lock);
 	clear_ssl_probes_by_pid(tracer, pid);
+	clear_rustls_proc(tracer, pid);
 	pthread_mutex_unlock(&tracer->mutex_probes_lock);
 }
 
@@ -424,19 +745,17 @@ void ssl_events_handle(void)
 	struct bpf_tracer *tracer = NULL;
 	int count = 0;
 	do {
-		event = get_first_event();
+		event = get_first_expired_event();
 		if (!event)
 			break;
 
-		if (get_sys_uptime() < event->expire_time)
-			break;
-
 		tracer = event->tracer;
 		if (tracer) {
 			pthread_mutex_lock(&tracer->mutex_probes_lock);
-			openssl_parse_and_register(event->pid, tracer->tps);
+			ssl_parse_and_register(event->pid, tracer->tps);
 			tracer_uprobes_update(tracer);
 			tracer_hooks_process(tracer, HOOK_ATTACH, &count);
+			update_rustls_procs_to_map(tracer);
 			pthread_mutex_unlock(&tracer->mutex_probes_lock);
 		}
 
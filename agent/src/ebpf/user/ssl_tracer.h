@@ -22,6 +22,9 @@
 // Scan /proc/ to get all processes when the agent starts
 int collect_ssl_uprobe_syms_from_procfs(struct tracer_probes_conf *conf);
 
+// Mark processes with rustls uprobes in eBPF map
+void update_rustls_procs_to_map(struct bpf_tracer *tracer);
+
 // Get the process creation event and put the event into the queue
 void ssl_process_exec(int pid);
 
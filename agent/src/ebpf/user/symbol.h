@@ -30,6 +30,7 @@ struct tracer_probes_conf;
 enum uprobe_type {
 	GO_UPROBE = 0,
 	OPENSSL_UPROBE,
+	USDT_UPROBE,
 	OTHER_UPROBE
 };
 
@@ -94,4 +95,6 @@ struct symbol_uprobe *resolve_and_gen_uprobe_symbol(const char *bin_file,
 						    const uint64_t addr,
 						    int pid);
 uint64_t get_symbol_addr_from_binary(const char *bin, const char *symname);
+int find_load(uint64_t v_addr, uint64_t mem_sz, uint64_t file_offset,
+	      void *payload);
 #endif /* _USER_SYMBOL_H_ */
@@ -981,7 +981,9 @@ perf_event:
 	if (type == HOOK_ATTACH) {
 		struct ebpf_object *obj = tracer->obj;
 		for (i = 0; i < obj->progs_cnt; i++) {
-			if (obj->progs[i].type == BPF_PROG_TYPE_PERF_EVENT) {
+			// Skip programs of tail calls
+			if (obj->progs[i].type == BPF_PROG_TYPE_PERF_EVENT &&
+			    memcmp(obj->progs[i].sec_name, "prog/", 5)) {
 				errno = 0;
 				int ret =
 				    program__attach_perf_event(obj->
@@ -116,6 +116,10 @@ enum cfg_feature_idx {
 	FEATURE_UPROBE_OPENSSL,
 	// golang uprobe
 	FEATURE_UPROBE_GOLANG,
+	// usdt uprobe
+	FEATURE_UPROBE_USDT,
+	// rustls uprobe
+	FEATURE_UPROBE_RUSTLS,
 	FEATURE_MAX,
 };
 
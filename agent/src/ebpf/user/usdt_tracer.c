@@ -0,0 +1,726 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+/*
+ * USDT (User Statically-Defined Tracing) probes are compiled into applications
+ * such as PostgreSQL, MySQL, the JVM (libjvm.so) and Node.js as nop instructions,
+ * and described by notes in the ".note.stapsdt" ELF section. Each note carries
+ * the probe address and the locations of its arguments, e.g. "-4@-8(%rbp)".
+ *
+ * Probes are attached as uprobes at the probe address, the argument locations
+ * are resolved here and written into "__usdt_spec_map" for the eBPF program.
+ */
+
+#include "usdt_tracer.h"
+#include "tracer.h"
+#include "table.h"
+#include "socket.h"
+#include "common.h"
+#include "log.h"
+#include <bcc/bcc_elf.h>
+#include <asm/ptrace.h>
+#include <dirent.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+#include <linux/limits.h>
+#include <linux/version.h>
+#include <string.h>
+#include <stddef.h>
+
+extern uint32_t k_version;
+
+struct usdt_process_create_event {
+	struct list_head list;
+	int pid;
+	uint32_t expire_time;
+	struct bpf_tracer *tracer;
+};
+
+// Argument specs of a probe in a process
+struct usdt_spec_entry {
+	struct list_head list;
+	int pid;
+	int index;
+	bool has_updated;
+	struct usdt_spec spec;
+};
+
+struct usdt_foreach_payload {
+	int pid;
+	const char *path;
+	struct tracer_probes_conf *conf;
+};
+
+static struct list_head proc_events_list;
+static pthread_mutex_t proc_events_list_mutex;
+
+// Protected by 'mutex_probes_lock' of the socket tracer
+static struct list_head usdt_specs_head = { &usdt_specs_head, &usdt_specs_head };
+
+// "provider:name" of configured probes, indexed by probe index
+static char *usdt_probes[USDT_PROBES_MAX];
+static int usdt_probes_count;
+
+// Lower version kernels do not support hooking files in containers
+static inline bool usdt_kern_check(void)
+{
+	return ((k_version == KERNEL_VERSION(3, 10, 0))
+		|| (k_version >= KERNEL_VERSION(4, 17, 0)));
+}
+
+int set_usdt_probes(const char *probes)
+{
+	char *dup, *token, *saveptr = NULL;
+	int count = 0;
+
+	if (!probes)
+		return ETR_INVAL;
+
+	dup = strdup(probes);
+	if (!dup)
+		return ETR_NOMEM;
+
+	for (token = strtok_r(dup, ",", &saveptr); token;
+	     token = strtok_r(NULL, ",", &saveptr)) {
+		if (!strchr(token, ':') || strlen(token) >= USDT_NAME_LEN) {
+			ebpf_warning("Invalid USDT probe '%s', expect "
+				     "'provider:name'\n", token);
+			continue;
+		}
+		if (count >= USDT_PROBES_MAX) {
+			ebpf_warning("Too many USDT probes, the maximum is %d, "
+				     "'%s' is ignored\n", USDT_PROBES_MAX, token);
+			continue;
+		}
+		usdt_probes[count++] = strdup(token);
+	}
+
+	usdt_probes_count = count;
+	free(dup);
+	return count > 0 ? 0 : ETR_INVAL;
+}
+
+static int find_usdt_probe_index(const char *provider, const char *name)
+{
+	int i;
+	size_t len = strlen(provider);
+	for (i = 0; i < usdt_probes_count; i++) {
+		if (usdt_probes[i] && !strncmp(usdt_probes[i], provider, len) &&
+		    usdt_probes[i][len] == ':' &&
+		    !strcmp(usdt_probes[i] + len + 1, name))
+			return i;
+	}
+	return -1;
+}
+
+#if defined(__x86_64__)
+// Refer to https://github.com/libbpf/libbpf/blob/master/src/usdt.c
+static int calc_pt_regs_off(const char *reg_name)
+{
+	static struct {
+		const char *names[4];
+		size_t pt_regs_off;
+	} reg_map[] = {
+#define reg_off(reg) offsetof(struct pt_regs, reg)
+		{ {"rip", "eip", "", ""}, reg_off(rip) },
+		{ {"rax", "eax", "ax", "al"}, reg_off(rax) },
+		{ {"rbx", "ebx", "bx", "bl"}, reg_off(rbx) },
+		{ {"rcx", "ecx", "cx", "cl"}, reg_off(rcx) },
+		{ {"rdx", "edx", "dx", "dl"}, reg_off(rdx) },
+		{ {"rsi", "esi", "si", "sil"}, reg_off(rsi) },
+		{ {"rdi", "edi", "di", "dil"}, reg_off(rdi) },
+		{ {"rbp", "ebp", "bp", "bpl"}, reg_off(rbp) },
+		{ {"rsp", "esp", "sp", "spl"}, reg_off(rsp) },
+		{ {"r8", "r8d", "r8w", "r8b"}, reg_off(r8) },
+		{ {"r9", "r9d", "r9w", "r9b"}, reg_off(r9) },
+		{ {"r10", "r10d", "r10w", "r10b"}, reg_off(r10) },
+		{ {"r11", "r11d", "r11w", "r11b"}, reg_off(r11) },
+		{ {"r12", "r12d", "r12w", "r12b"}, reg_off(r12) },
+		{ {"r13", "r13d", "r13w", "r13b"}, reg_off(r13) },
+		{ {"r14", "r14d", "r14w", "r14b"}, reg_off(r14) },
+		{ {"r15", "r15d", "r15w", "r15b"}, reg_off(r15) },
+#undef reg_off
+	};
+	int i, j;
+
+	for (i = 0; i < NELEMS(reg_map); i++) {
+		for (j = 0; j < NELEMS(reg_map[i].names); j++) {
+			if (strcmp(reg_name, reg_map[i].names[j]) == 0)
+				return reg_map[i].pt_regs_off;
+		}
+	}
+
+	return -1;
+}
+
+// Parses one argument such as "8@%rdi", "-4@-8(%rbp)" or "4@$5",
+// returns the number of characters consumed, or -1 on failure.
+static int parse_usdt_arg(const char *arg_str, struct usdt_arg_spec *arg,
+			  int *arg_sz)
+{
+	char reg_name[16];
+	int len, reg_off;
+	long off;
+
+	if (sscanf(arg_str, " %d @ %ld ( %%%15[^)] ) %n", arg_sz, &off,
+		   reg_name, &len) == 3) {
+		// Memory dereference case, e.g., -4@-20(%rbp)
+		arg->arg_type = USDT_ARG_REG_DEREF;
+		arg->val_off = off;
+		reg_off = calc_pt_regs_off(reg_name);
+	} else if (sscanf(arg_str, " %d @ ( %%%15[^)] ) %n", arg_sz,
+			  reg_name, &len) == 2) {
+		// Memory dereference case without offset, e.g., 8@(%rax)
+		arg->arg_type = USDT_ARG_REG_DEREF;
+		arg->val_off = 0;
+		reg_off = calc_pt_regs_off(reg_name);
+	} else if (sscanf(arg_str, " %d @ %%%15s %n", arg_sz, reg_name,
+			  &len) == 2) {
+		// Register read case, e.g., -4@%eax
+		arg->arg_type = USDT_ARG_REG;
+		arg->val_off = 0;
+		reg_off = calc_pt_regs_off(reg_name);
+	} else if (sscanf(arg_str, " %d @ $%ld %n", arg_sz, &off, &len) == 2) {
+		// Constant value case, e.g., 4@$71
+		arg->arg_type = USDT_ARG_CONST;
+		arg->val_off = off;
+		reg_off = 0;
+	} else {
+		return -1;
+	}
+
+	if (reg_off < 0)
+		return -1;
+	arg->reg_off = reg_off;
+	return len;
+}
+#elif defined(__aarch64__)
+static int calc_pt_regs_off(const char *reg_name)
+{
+	int reg_num;
+
+	if (sscanf(reg_name, "x%d", &reg_num) == 1) {
+		if (reg_num >= 0 && reg_num < 31)
+			return offsetof(struct user_pt_regs, regs[reg_num]);
+	} else if (strcmp(reg_name, "sp") == 0) {
+		return offsetof(struct user_pt_regs, sp);
+	}
+
+	return -1;
+}
+
+// Parses one argument such as "8@x0", "-4@[sp, 12]" or "4@5"
+static int parse_usdt_arg(const char *arg_str, struct usdt_arg_spec *arg,
+			  int *arg_sz)
+{
+	char reg_name[16];
+	int len, reg_off;
+	long off;
+
+	if (sscanf(arg_str, " %d @ [ %15[a-z0-9] , %ld ] %n", arg_sz,
+		   reg_name, &off, &len) == 3) {
+		// Memory dereference case, e.g., -4@[sp, 96]
+		arg->arg_type = USDT_ARG_REG_DEREF;
+		arg->val_off = off;
+		reg_off = calc_pt_regs_off(reg_name);
+	} else if (sscanf(arg_str, " %d @ [ %15[a-z0-9] ] %n", arg_sz,
+			  reg_name, &len) == 2) {
+		// Memory dereference case, e.g., -4@[sp]
+		arg->arg_type = USDT_ARG_REG_DEREF;
+		arg->val_off = 0;
+		reg_off = calc_pt_regs_off(reg_name);
+	} else if (sscanf(arg_str, " %d @ %ld %n", arg_sz, &off, &len) == 2) {
+		// Constant value case, e.g., 4@5
+		arg->arg_type = USDT_ARG_CONST;
+		arg->val_off = off;
+		reg_off = 0;
+	} else if (sscanf(arg_str, " %d @ %15[a-z0-9] %n", arg_sz, reg_name,
+			  &len) == 2) {
+		// Register read case, e.g., -8@x4
+		arg->arg_type = USDT_ARG_REG;
+		arg->val_off = 0;
+		reg_off = calc_pt_regs_off(reg_name);
+	} else {
+		return -1;
+	}
+
+	if (reg_off < 0)
+		return -1;
+	arg->reg_off = reg_off;
+	return len;
+}
+#else
+static int parse_usdt_arg(const char *arg_str, struct usdt_arg_spec *arg,
+			  int *arg_sz)
+{
+	return -1;
+}
+#endif
+
+// Arguments that can not be parsed are not collected, the probe
+// is still attached and reported without arguments.
+static void parse_usdt_spec(const char *arg_fmt, struct usdt_spec *spec)
+{
+	struct usdt_arg_spec *arg;
+	const char *s = arg_fmt;
+	int len, arg_sz;
+
+	spec->arg_cnt = 0;
+	while (s && *s != '\0' && spec->arg_cnt < USDT_ARGS_MAX) {
+		arg = &spec->args[spec->arg_cnt];
+		len = parse_usdt_arg(s, arg, &arg_sz);
+		if (len <= 0)
+			return;
+
+		arg->arg_signed = arg_sz < 0;
+		if (arg_sz < 0)
+			arg_sz = -arg_sz;
+		if (arg_sz != 1 && arg_sz != 2 && arg_sz != 4 && arg_sz != 8)
+			return;
+		arg->arg_bitshift = 64 - arg_sz * 8;
+
+		spec->arg_cnt++;
+		s += len;
+	}
+}
+
+// Convert virtual address in ELF to file offset for attaching uprobes
+static uint64_t usdt_addr_to_offset(const char *path, uint64_t v_addr)
+{
+	struct load_addr_t addr = {
+		.target_addr = v_addr,
+		.binary_addr = 0x0,
+	};
+
+	if (bcc_elf_foreach_load_section(path, &find_load, &addr) < 0)
+		return 0;
+
+	return addr.binary_addr;
+}
+
+/*
+ * Probes guarded by semaphores, e.g. those in Node.js, only fire when the
+ * semaphore counter is non-zero. Find the mapping of the semaphore in the
+ * process and increase the counter through "/proc/<pid>/mem".
+ */
+static int usdt_semaphore_enable(int pid, const char *path, uint64_t sem_off)
+{
+	char maps_file[64], mem_file[64], line[PATH_MAX + 128];
+	char map_path[PATH_MAX];
+	uint64_t start, end, offset, sem_addr = 0;
+	const char *bin_path = path;
+	uint16_t counter;
+	FILE *fp;
+	int fd, ret = -1;
+
+	// Paths in maps are in the mount namespace of the process
+	snprintf(maps_file, sizeof(maps_file), "/proc/%d/root", pid);
+	if (!strncmp(path, maps_file, strlen(maps_file)))
+		bin_path = path + strlen(maps_file);
+
+	snprintf(maps_file, sizeof(maps_file), "/proc/%d/maps", pid);
+	fp = fopen(maps_file, "r");
+	if (!fp)
+		return -1;
+
+	while (fgets(line, sizeof(line), fp)) {
+		if (sscanf(line, "%lx-%lx %*s %lx %*s %*d %s", &start, &end,
+			   &offset, map_path) != 4)
+			continue;
+		if (strcmp(map_path, bin_path))
+			continue;
+		if (sem_off >= offset && sem_off < offset + (end - start)) {
+			sem_addr = start + (sem_off - offset);
+			break;
+		}
+	}
+	fclose(fp);
+
+	if (!sem_addr)
+		return -1;
+
+	snprintf(mem_file, sizeof(mem_file), "/proc/%d/mem", pid);
+	fd = open(mem_file, O_RDWR);
+	if (fd < 0)
+		return -1;
+
+	if (pread(fd, &counter, sizeof(counter), sem_addr) == sizeof(counter)) {
+		counter++;
+		if (pwrite(fd, &counter, sizeof(counter), sem_addr) ==
+		    sizeof(counter))
+			ret = 0;
+	}
+	close(fd);
+	return ret;
+}
+
+static void add_usdt_spec(int pid, int index, struct usdt_spec *spec)
+{
+	struct usdt_spec_entry *entry;
+
+	list_for_each_entry(entry, &usdt_specs_head, list) {
+		if (entry->pid != pid || entry->index != index)
+			continue;
+		/*
+		 * The probe is inlined in several places with different
+		 * argument locations, which can not be told apart in eBPF
+		 * without bpf cookies, arguments are not collected then.
+		 */
+		if (memcmp(entry->spec.args, spec->args, sizeof(spec->args)) ||
+		    entry->spec.arg_cnt != spec->arg_cnt) {
+			entry->spec.arg_cnt = 0;
+			entry->has_updated = false;
+		}
+		return;
+	}
+
+	entry = calloc(1, sizeof(*entry));
+	if (!entry) {
+		ebpf_warning("no memory.\n");
+		return;
+	}
+	entry->pid = pid;
+	entry->index = index;
+	entry->spec = *spec;
+	list_add_tail(&entry->list, &usdt_specs_head);
+}
+
+static void usdt_probe_callback(const char *binpath,
+				const struct bcc_elf_usdt *probe, void *payload)
+{
+	struct usdt_foreach_payload *p = payload;
+	struct symbol_uprobe *probe_sym;
+	struct usdt_spec spec;
+	char probe_func[32];
+	uint64_t entry, sem_off;
+	int index;
+
+	index = find_usdt_probe_index(probe->provider, probe->name);
+	if (index < 0)
+		return;
+
+	entry = usdt_addr_to_offset(p->path, probe->pc);
+	if (!entry)
+		return;
+
+	if (probe->semaphore) {
+		sem_off = usdt_addr_to_offset(p->path, probe->semaphore);
+		if (!sem_off || usdt_semaphore_enable(p->pid, p->path, sem_off)) {
+			ebpf_warning("usdt %s:%s pid:%d enable semaphore failed\n",
+				     probe->provider, probe->name, p->pid);
+			return;
+		}
+	}
+
+	memset(&spec, 0, sizeof(spec));
+	parse_usdt_spec(probe->arg_fmt, &spec);
+	snprintf(spec.name, sizeof(spec.name), "%s", usdt_probes[index]);
+
+	// This memory will be maintained in conf, no need to release
+	probe_sym = calloc(1, sizeof(struct symbol_uprobe));
+	if (!probe_sym)
+		return;
+
+	snprintf(probe_func, sizeof(probe_func), "uprobe_usdt_%d", index);
+	probe_sym->type = USDT_UPROBE;
+	probe_sym->isret = false;
+	probe_sym->entry = entry;
+	// USDT probe is a single nop instruction
+	probe_sym->size = 1;
+	probe_sym->probe_func = strdup(probe_func);
+	probe_sym->name = strdup(usdt_probes[index]);
+	probe_sym->binary_path = strdup(p->path);
+	probe_sym->pid = p->pid;
+
+	if (probe_sym->probe_func && probe_sym->name && probe_sym->binary_path) {
+		ebpf_info("usdt uprobe, pid:%d, path:%s, probe:%s, args:%d "
+			  "(%s)\n", p->pid, p->path, probe_sym->name,
+			  spec.arg_cnt, probe->arg_fmt);
+		add_uprobe_symbol(p->pid, probe_sym, p->conf);
+		add_usdt_spec(p->pid, index, &spec);
+	} else {
+		free((void *)probe_sym->probe_func);
+		free((void *)probe_sym->name);
+		free((void *)probe_sym->binary_path);
+		free(probe_sym);
+	}
+}
+
+// Probes are defined in the executable or in shared libraries such
+// as libjvm.so, so all executable file mappings are searched.
+static void usdt_parse_and_register(int pid, struct tracer_probes_conf *conf)
+{
+	char maps_file[64], line[PATH_MAX + 128];
+	char map_path[PATH_MAX], last_path[PATH_MAX] = { 0 };
+	char path[PATH_MAX + 32];
+	char perms[8];
+	struct usdt_foreach_payload payload;
+	FILE *fp;
+
+	if (pid <= 1)
+		return;
+
+	if (!is_user_process(pid))
+		return;
+
+	snprintf(maps_file, sizeof(maps_file), "/proc/%d/maps", pid);
+	fp = fopen(maps_file, "r");
+	if (!fp)
+		return;
+
+	while (fgets(line, sizeof(line), fp)) {
+		if (sscanf(line, "%*x-%*x %7s %*x %*s %*d %s", perms,
+			   map_path) != 2)
+			continue;
+		if (perms[2] != 'x' || map_path[0] != '/')
+			continue;
+		if (!strcmp(map_path, last_path))
+			continue;
+		memcpy(last_path, map_path, sizeof(last_path));
+
+		snprintf(path, sizeof(path), "/proc/%d/root%s", pid, map_path);
+		if (access(path, F_OK) != 0)
+			snprintf(path, sizeof(path), "%s", map_path);
+
+		payload.pid = pid;
+		payload.path = path;
+		payload.conf = conf;
+		bcc_elf_foreach_usdt(path, usdt_probe_callback, &payload);
+	}
+
+	fclose(fp);
+}
+
+void update_usdt_specs_to_map(struct bpf_tracer *tracer)
+{
+	struct usdt_spec_entry *entry;
+	uint64_t key;
+
+	list_for_each_entry(entry, &usdt_specs_head, list) {
+		if (entry->has_updated)
+			continue;
+		key = ((uint64_t) entry->pid << 32) | entry->index;
+		if (!bpf_table_set_value(tracer, MAP_USDT_SPEC_NAME, key,
+					 (void *)&entry->spec))
+			continue;
+		entry->has_updated = true;
+	}
+}
+
+static void clear_usdt_probes_by_pid(struct bpf_tracer *tracer, int pid)
+{
+	struct probe *probe;
+	struct list_head *p, *n;
+	struct symbol_uprobe *sym_uprobe;
+	struct usdt_spec_entry *entry;
+
+	list_for_each_safe (p, n, &tracer->probes_head) {
+		probe = container_of(p, struct probe, list);
+		if (!(probe->type == UPROBE && probe->private_data != NULL))
+			continue;
+		sym_uprobe = probe->private_data;
+
+		if (sym_uprobe->type != USDT_UPROBE)
+			continue;
+
+		if (sym_uprobe->pid != pid)
+			continue;
+
+		if (probe_detach(probe)) {
+			ebpf_warning("probe_detach failed, path:%s, name:%s\n",
+				     sym_uprobe->binary_path, sym_uprobe->name);
+		}
+		free_probe_from_tracer(probe);
+	}
+
+	list_for_each_safe (p, n, &usdt_specs_head) {
+		entry = container_of(p, struct usdt_spec_entry, list);
+		if (entry->pid != pid)
+			continue;
+		if (entry->has_updated)
+			bpf_table_delete_key(tracer, MAP_USDT_SPEC_NAME,
+					     ((uint64_t) pid << 32) |
+					     entry->index);
+		list_head_del(&entry->list);
+		free(entry);
+	}
+}
+
+static void add_event_to_proc_list(struct bpf_tracer *tracer, int pid)
+{
+	static const uint32_t PROC_EVENT_HANDLE_DELAY = 120;
+	struct usdt_process_create_event *event = NULL;
+
+	event = calloc(1, sizeof(struct usdt_process_create_event));
+	if (!event) {
+		ebpf_warning("no memory.\n");
+		return;
+	}
+
+	event->tracer = tracer;
+	event->pid = pid;
+	event->expire_time = get_sys_uptime() + PROC_EVENT_HANDLE_DELAY;
+
+	pthread_mutex_lock(&proc_events_list_mutex);
+	list_add_tail(&event->list, &proc_events_list);
+	pthread_mutex_unlock(&proc_events_list_mutex);
+}
+
+static struct usdt_process_create_event *get_first_event(void)
+{
+	struct usdt_process_create_event *event = NULL;
+	pthread_mutex_lock(&proc_events_list_mutex);
+	if (!list_empty(&proc_events_list)) {
+		event = list_first_entry(&proc_events_list,
+					 struct usdt_process_create_event, list);
+	}
+	pthread_mutex_unlock(&proc_events_list_mutex);
+	return event;
+}
+
+static void remove_event(struct usdt_process_create_event *event)
+{
+	pthread_mutex_lock(&proc_events_list_mutex);
+	list_head_del(&event->list);
+	pthread_mutex_unlock(&proc_events_list_mutex);
+}
+
+int collect_usdt_uprobe_syms_from_procfs(struct tracer_probes_conf *conf)
+{
+	struct dirent *entry = NULL;
+	DIR *fddir = NULL;
+	int pid = 0;
+	char *path = NULL;
+
+	if (!is_feature_enabled(FEATURE_UPROBE_USDT) || usdt_probes_count == 0)
+		return ETR_OK;
+
+	if (!usdt_kern_check()) {
+		ebpf_warning("Uprobe usdt requires Linux version 4.17+ or Linux 3.10.0\n");
+		return ETR_OK;
+	}
+
+	init_list_head(&proc_events_list);
+	pthread_mutex_init(&proc_events_list_mutex, NULL);
+
+	fddir = opendir("/proc/");
+	if (!fddir) {
+		ebpf_warning("Failed to open %s.\n", "/proc/");
+		return ETR_PROC_FAIL;
+	}
+
+	while ((entry = readdir(fddir))) {
+		if (entry->d_type != DT_DIR)
+			continue;
+		pid = atoi(entry->d_name);
+		path = get_elf_path_by_pid(pid);
+		if (is_feature_matched(FEATURE_UPROBE_USDT, path)) {
+			usdt_parse_and_register(pid, conf);
+		}
+		free(path);
+	}
+
+	closedir(fddir);
+	return ETR_OK;
+}
+
+void usdt_process_exec(int pid)
+{
+	struct bpf_tracer *tracer = NULL;
+	char *path = NULL;
+	int matched = false;
+
+	if (usdt_probes_count == 0 || !usdt_kern_check())
+		return;
+
+	path = get_elf_path_by_pid(pid);
+	matched = is_feature_matched(FEATURE_UPROBE_USDT, path);
+	free(path);
+	if (!matched)
+		return;
+
+	tracer = find_bpf_tracer(SK_TRACER_NAME);
+	if (tracer == NULL)
+		return;
+
+	if (tracer->state != TRACER_RUNNING)
+		return;
+
+	if (tracer->probes_count > OPEN_FILES_MAX) {
+		ebpf_warning("Probes count too many. The maximum is %d\n",
+			     OPEN_FILES_MAX);
+		return;
+	}
+
+	add_event_to_proc_list(tracer, pid);
+}
+
+void usdt_process_exit(int pid)
+{
+	struct bpf_tracer *tracer = NULL;
+
+	if (!is_feature_enabled(FEATURE_UPROBE_USDT) || usdt_probes_count == 0)
+		return;
+
+	if (!usdt_kern_check())
+		return;
+
+	tracer = find_bpf_tracer(SK_TRACER_NAME);
+	if (tracer == NULL)
+		return;
+
+	if (tracer->state != TRACER_RUNNING)
+		return;
+
+	pthread_mutex_lock(&tracer->mutex_probes_lock);
+	clear_usdt_probes_by_pid(tracer, pid);
+	pthread_mutex_unlock(&tracer->mutex_probes_lock);
+}
+
+void usdt_events_handle(void)
+{
+	struct usdt_process_create_event *event = NULL;
+	struct bpf_tracer *tracer = NULL;
+	int count = 0;
+
+	if (!is_feature_enabled(FEATURE_UPROBE_USDT) || usdt_probes_count == 0)
+		return;
+
+	do {
+		event = get_first_event();
+		if (!event)
+			break;
+
+		if (get_sys_uptime() < event->expire_time)
+			break;
+
+		tracer = event->tracer;
+		if (tracer) {
+			pthread_mutex_lock(&tracer->mutex_probes_lock);
+			usdt_parse_and_register(event->pid, tracer->tps);
+			tracer_uprobes_update(tracer);
+			tracer_hooks_process(tracer, HOOK_ATTACH, &count);
+			update_usdt_specs_to_map(tracer);
+			pthread_mutex_unlock(&tracer->mutex_probes_lock);
+		}
+
+		remove_event(event);
+		free(event);
+
+	} while (true);
+}
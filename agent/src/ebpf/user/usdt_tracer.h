@@ -0,0 +1,49 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#ifndef _BPF_USDT_TRACER_H_
+#define _BPF_USDT_TRACER_H_
+
+#include "tracer.h"
+
+#define MAP_USDT_SPEC_NAME	"__usdt_spec_map"
+
+/*
+ * Set USDT probes to attach, called before the socket tracer is started.
+ * @probes : Comma separated "provider:name" list, e.g.
+ *           "postgresql:query__start,node:http__server__request".
+ *           At most USDT_PROBES_MAX probes are accepted.
+ *
+ * @return 0 on success, < 0 on failure.
+ */
+int set_usdt_probes(const char *probes);
+
+// Scan /proc/ to get all processes when the agent starts
+int collect_usdt_uprobe_syms_from_procfs(struct tracer_probes_conf *conf);
+
+// Write argument specs of attached probes into eBPF map
+void update_usdt_specs_to_map(struct bpf_tracer *tracer);
+
+// Get the process creation event and put the event into the queue
+void usdt_process_exec(int pid);
+
+// Process events in the queue
+void usdt_events_handle(void);
+
+// Process exit, reclaim resources
+void usdt_process_exit(int pid);
+
+#endif
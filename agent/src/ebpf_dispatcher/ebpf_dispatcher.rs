@@ -24,10 +24,10 @@ use std::time::Duration;
 
 use ahash::HashSet;
 use arc_swap::access::Access;
-use libc::{c_int, c_ulonglong};
+use libc::{c_char, c_int, c_ulonglong};
 use log::{debug, error, info, warn};
 
-use super::{Error, Result};
+use super::{scope::ProcessScope, Error, Result};
 use crate::common::ebpf::EbpfType;
 use crate::common::flow::L7Stats;
 use crate::common::l7_protocol_log::{
@@ -42,9 +42,14 @@ use crate::ebpf;
 use crate::exception::ExceptionHandler;
 use crate::flow_generator::{flow_map::Config, AppProto, FlowMap};
 use crate::integration_collector::Profile;
+#[cfg(target_os = "linux")]
+use crate::platform::ApiWatcher;
 use crate::policy::PolicyGetter;
 use crate::rpc::get_timestamp;
-use crate::utils::stats;
+use crate::utils::{
+    pprof::{self, ProfileKind},
+    stats,
+};
 
 use public::{
     buffer::BatchedBox,
@@ -61,6 +66,7 @@ use reorder::{Reorder, ReorderCounter, StatsReorderCounter};
 pub struct EbpfCounter {
     rx: AtomicU64,
     get_token_failed: AtomicU64,
+    out_of_scope: AtomicU64,
 }
 
 pub struct SyncEbpfCounter {
@@ -71,6 +77,7 @@ impl OwnedCountable for SyncEbpfCounter {
     fn get_counters(&self) -> Vec<Counter> {
         let rx = self.counter.rx.swap(0, Ordering::Relaxed);
         let get_token_failed = self.counter.get_token_failed.swap(0, Ordering::Relaxed);
+        let out_of_scope = self.counter.out_of_scope.swap(0, Ordering::Relaxed);
         let ebpf_counter = unsafe { ebpf::socket_tracer_stats() };
 
         vec![
@@ -84,6 +91,11 @@ impl OwnedCountable for SyncEbpfCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(get_token_failed),
             ),
+            (
+                "out_of_scope",
+                CounterType::Counted,
+                CounterValue::Unsigned(out_of_scope),
+            ),
             (
                 "perf_pages_count",
                 CounterType::Counted,
@@ -199,6 +211,10 @@ struct EbpfDispatcher {
     flow_output: DebugSender<Arc<BatchedBox<TaggedFlow>>>, // Send TaggedFlows to the QuadrupleGenerator
     l7_stats_output: DebugSender<BatchedBox<L7Stats>>,     // Send L7Stats to the QuadrupleGenerator
     stats_collector: Arc<stats::Collector>,
+
+    // Pod labels for ebpf scope
+    #[cfg(target_os = "linux")]
+    api_watcher: Arc<ApiWatcher>,
 }
 
 impl EbpfDispatcher {
@@ -303,9 +319,12 @@ impl EbpfDispatcher {
             &self.flow_map_config.load(),
             None, // Enterprise Edition Feature: packet-sequence
             self.stats_collector.clone(),
+            None,
+            None,
             true, // from_ebpf
         );
         let leaky_bucket = LeakyBucket::new(Some(ebpf_config.ebpf.global_ebpf_pps_threshold));
+        let mut scope = ProcessScope::new(&ebpf_config.ebpf.scope);
         const QUEUE_BATCH_SIZE: usize = 1024;
         let mut batch = Vec::with_capacity(QUEUE_BATCH_SIZE);
         while unsafe { SWITCH } {
@@ -334,7 +353,25 @@ impl EbpfDispatcher {
                 continue;
             }
 
+            let current_config = self.config.load();
+            if scope.config() != &current_config.ebpf.scope {
+                info!("ebpf scope changed to {:?}", current_config.ebpf.scope);
+                scope = ProcessScope::new(&current_config.ebpf.scope);
+            }
+            let now = get_timestamp(self.time_diff.load(Ordering::Relaxed));
+            if scope.need_refresh(now) {
+                self.refresh_scope(&mut scope, now);
+            }
+
             for mut packet in batch.drain(..) {
+                if !scope.allowed(
+                    packet.process_id,
+                    &packet.process_kname,
+                    packet.lookup_key.timestamp.into(),
+                ) {
+                    counter.out_of_scope.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
                 if !leaky_bucket.acquire(1) {
                     counter.get_token_failed.fetch_add(1, Ordering::Relaxed);
                     exception_handler.set(Exception::RxPpsThresholdExceeded);
@@ -347,6 +384,36 @@ impl EbpfDispatcher {
                 packet.set_loopback_mac(ebpf_config.ctrl_mac);
                 Self::inject_meta_packet(packet, &mut flow_map, &config, &mut reorder);
             }
+            for pid in scope.take_denied_pids() {
+                unsafe {
+                    if ebpf::set_scope_pid(pid as c_int) != 0 {
+                        warn!("ebpf set scope pid {} failed", pid);
+                    }
+                }
+            }
+        }
+    }
+
+    // Processes pushed down to eBPF are cleared, so that reused pids are evaluated again
+    fn refresh_scope(&self, scope: &mut ProcessScope, now: Duration) {
+        #[cfg(target_os = "linux")]
+        let pods = if scope.has_pod_rules() {
+            self.api_watcher.get_pod_labels()
+        } else {
+            None
+        };
+        #[cfg(target_os = "android")]
+        let pods = None;
+        let cgroups = scope.refresh(now, pods);
+        unsafe {
+            if ebpf::clear_scope() != 0 {
+                warn!("ebpf clear scope failed");
+            }
+            for id in cgroups {
+                if ebpf::set_scope_cgroup(id as c_ulonglong) != 0 {
+                    warn!("ebpf set scope cgroup {} failed", id);
+                }
+            }
         }
     }
 }
@@ -382,6 +449,8 @@ pub struct EbpfCollector {
     counter: Arc<EbpfCounter>,
 
     exception_handler: ExceptionHandler,
+
+    l7_protocol_enabled_bitmap: L7ProtocolBitmap,
 }
 
 static mut SWITCH: bool = false;
@@ -393,18 +462,12 @@ static mut ON_CPU_PROFILE_FREQUENCY: u32 = 0;
 static mut TIME_DIFF: Option<Arc<AtomicI64>> = None;
 
 impl EbpfCollector {
-    #[cfg(target_arch = "x86_64")]
     unsafe fn convert_to_string(ptr: *const u8) -> String {
-        CStr::from_ptr(ptr as *const i8)
+        CStr::from_ptr(ptr as *const c_char)
             .to_string_lossy()
             .into_owned()
     }
 
-    #[cfg(target_arch = "aarch64")]
-    unsafe fn convert_to_string(ptr: *const u8) -> String {
-        CStr::from_ptr(ptr).to_string_lossy().into_owned()
-    }
-
     extern "C" fn ebpf_l7_callback(sd: *mut ebpf::SK_BPF_DATA) {
         unsafe {
             if !SWITCH || SENDER.is_none() {
@@ -491,6 +554,21 @@ impl EbpfCollector {
             if let Some(policy_getter) = POLICY_GETTER.as_ref() {
                 profile.pod_id = policy_getter.lookup_pod_id(&container_id);
             }
+            if let Ok(stack) = std::str::from_utf8(&profile.data) {
+                let kind = if profile.event_type == metric::ProfileEventType::EbpfOffCpu as i32 {
+                    ProfileKind::OffCpu
+                } else {
+                    ProfileKind::OnCpu
+                };
+                pprof::record(
+                    kind,
+                    profile.pid,
+                    profile.pod_id,
+                    &profile.process_name,
+                    profile.count,
+                    stack,
+                );
+            }
             if let Err(e) = EBPF_PROFILE_SENDER.as_mut().unwrap().send(Profile(profile)) {
                 warn!("ebpf profile send error: {:?}", e);
             }
@@ -540,6 +618,22 @@ impl EbpfCollector {
                 info!("ebpf openssl uprobe proc regexp is empty, skip set")
             }
 
+            if !config.ebpf.uprobe_proc_regexp.rustls.is_empty() {
+                info!(
+                    "ebpf set rustls uprobe proc regexp: {}",
+                    config.ebpf.uprobe_proc_regexp.rustls.as_str()
+                );
+                ebpf::set_feature_regex(
+                    ebpf::FEATURE_UPROBE_RUSTLS,
+                    CString::new(config.ebpf.uprobe_proc_regexp.rustls.as_str().as_bytes())
+                        .unwrap()
+                        .as_c_str()
+                        .as_ptr(),
+                );
+            } else {
+                info!("ebpf rustls uprobe proc regexp is empty, skip set")
+            }
+
             if !config.ebpf.uprobe_proc_regexp.golang_symbol.is_empty() {
                 info!(
                     "ebpf set golang symbol uprobe proc regexp: {}",
@@ -563,6 +657,34 @@ impl EbpfCollector {
                 info!("ebpf golang symbol proc regexp is empty, skip set")
             }
 
+            if !config.ebpf.uprobe_proc_regexp.usdt.is_empty()
+                && !config.ebpf.usdt_probes.is_empty()
+            {
+                info!(
+                    "ebpf set usdt uprobe proc regexp: {}, probes: {:?}",
+                    config.ebpf.uprobe_proc_regexp.usdt.as_str(),
+                    config.ebpf.usdt_probes
+                );
+                ebpf::set_feature_regex(
+                    ebpf::FEATURE_UPROBE_USDT,
+                    CString::new(config.ebpf.uprobe_proc_regexp.usdt.as_str().as_bytes())
+                        .unwrap()
+                        .as_c_str()
+                        .as_ptr(),
+                );
+                if ebpf::set_usdt_probes(
+                    CString::new(config.ebpf.usdt_probes.join(",").as_bytes())
+                        .unwrap()
+                        .as_c_str()
+                        .as_ptr(),
+                ) != 0
+                {
+                    warn!("ebpf set usdt probes {:?} failed", config.ebpf.usdt_probes);
+                }
+            } else {
+                info!("ebpf usdt uprobe proc regexp or probes is empty, skip set")
+            }
+
             for i in get_all_protocol().into_iter() {
                 if l7_protocol_enabled_bitmap.is_enabled(i.protocol()) {
                     info!("l7 protocol {:?} parse enabled", i.protocol());
@@ -691,6 +813,15 @@ impl EbpfCollector {
                     ebpf::disable_offcpu_profiler();
                 }
 
+                if !on_cpu.disabled && !on_cpu.dwarf_regex.is_empty() {
+                    ebpf::set_dwarf_regex(
+                        CString::new(on_cpu.dwarf_regex.as_bytes())
+                            .unwrap()
+                            .as_c_str()
+                            .as_ptr(),
+                    );
+                }
+
                 if ebpf::start_continuous_profiler(
                     on_cpu.frequency as i32,
                     ebpf_conf.java_symbol_file_max_space_limit as i32,
@@ -740,6 +871,10 @@ impl EbpfCollector {
             ON_CPU_PROFILE_FREQUENCY = config.ebpf.on_cpu_profile.frequency as u32;
             TIME_DIFF = Some(time_diff);
         }
+        pprof::set_config(
+            &config.ebpf.pprof_export,
+            config.ebpf.on_cpu_profile.frequency as u32,
+        );
 
         Ok(())
     }
@@ -815,12 +950,20 @@ impl EbpfCollector {
         queue_debugger: &QueueDebugger,
         stats_collector: Arc<stats::Collector>,
         exception_handler: ExceptionHandler,
+        #[cfg(target_os = "linux")] api_watcher: Arc<ApiWatcher>,
     ) -> Result<Box<Self>> {
         let ebpf_config = config.load();
         if ebpf_config.ebpf.disabled {
             info!("ebpf collector disabled.");
             return Err(Error::EbpfDisabled);
         }
+        if !ebpf::SUPPORTED {
+            warn!(
+                "ebpf collector disabled, not supported on {}.",
+                std::env::consts::ARCH
+            );
+            return Err(Error::EbpfUnsupported(std::env::consts::ARCH));
+        }
         info!("ebpf collector init...");
         let queue_name = "0-ebpf-to-ebpf-collector";
         let (sender, receiver, counter) =
@@ -860,13 +1003,17 @@ impl EbpfCollector {
                 stats_collector,
                 collector_config,
                 pause: Arc::new(AtomicBool::new(true)),
+                #[cfg(target_os = "linux")]
+                api_watcher,
             },
             thread_handle: None,
             counter: Arc::new(EbpfCounter {
                 rx: AtomicU64::new(0),
                 get_token_failed: AtomicU64::new(0),
+                out_of_scope: AtomicU64::new(0),
             }),
             exception_handler,
+            l7_protocol_enabled_bitmap: ebpf_config.l7_protocol_enabled_bitmap,
         }))
     }
 
@@ -882,8 +1029,40 @@ impl EbpfCollector {
         }
     }
 
+    fn update_l7_protocol_enabled(&mut self, bitmap: L7ProtocolBitmap) {
+        if self.l7_protocol_enabled_bitmap == bitmap {
+            return;
+        }
+        for i in get_all_protocol().into_iter() {
+            let protocol = i.protocol();
+            let enabled = bitmap.is_enabled(protocol);
+            if self.l7_protocol_enabled_bitmap.is_enabled(protocol) == enabled {
+                continue;
+            }
+            info!(
+                "l7 protocol {:?} parse {}",
+                protocol,
+                if enabled { "enabled" } else { "disabled" }
+            );
+            unsafe {
+                if ebpf::set_ebpf_protocol_enabled(protocol as ebpf::c_int, enabled) != 0 {
+                    warn!(
+                        "ebpf set protocol {:?} enabled {} failed",
+                        protocol, enabled
+                    );
+                }
+            }
+        }
+        self.l7_protocol_enabled_bitmap = bitmap;
+    }
+
     pub fn on_config_change(&mut self, config: &EbpfConfig) {
-        if config.l7_log_enabled() {
+        pprof::set_config(
+            &config.ebpf.pprof_export,
+            config.ebpf.on_cpu_profile.frequency as u32,
+        );
+        self.update_l7_protocol_enabled(config.l7_protocol_enabled_bitmap);
+        if config.l7_log_enabled() && !config.paused {
             unsafe {
                 if SWITCH {
                     self.stop();
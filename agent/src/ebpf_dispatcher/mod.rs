@@ -15,6 +15,7 @@
  */
 
 pub mod ebpf_dispatcher;
+mod scope;
 
 use thiserror::Error;
 
@@ -30,6 +31,8 @@ pub enum Error {
     EbpfL7GetLogInfoError,
     #[error("ebpf disabled.")]
     EbpfDisabled,
+    #[error("ebpf is not supported on {0}.")]
+    EbpfUnsupported(&'static str),
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
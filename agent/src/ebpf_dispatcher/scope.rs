@@ -0,0 +1,486 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    num::NonZeroUsize,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::{info, warn};
+use lru::LruCache;
+use regex::Regex;
+
+use crate::config::EbpfScope;
+
+// Pids are reused, decisions expire so that new processes are evaluated again,
+// pods and cgroups out of scope are resolved again in the same interval
+const DECISION_TTL: Duration = Duration::from_secs(60);
+const DECISION_CACHE_SIZE: usize = 4096;
+// cgroup v2 hierarchy of the host, /proc/1/root is used when running in a container
+const CGROUP_ROOTS: [&str; 2] = ["/proc/1/root/sys/fs/cgroup", "/sys/fs/cgroup"];
+
+// Pod uid and labels
+pub type PodLabels = (String, BTreeMap<String, String>);
+
+fn compile(patterns: &[String]) -> Vec<Regex> {
+    patterns
+        .iter()
+        .filter_map(|p| match Regex::new(p) {
+            Ok(r) => Some(r),
+            Err(e) => {
+                warn!("invalid ebpf scope regex {}: {}", p, e);
+                None
+            }
+        })
+        .collect()
+}
+
+// Denied if any deny pattern matches, otherwise allowed if the allowlist
+// is empty or any allow pattern matches
+fn is_allowed<'a, I: Iterator<Item = &'a str> + Clone>(
+    allow: &[Regex],
+    deny: &[Regex],
+    values: I,
+) -> bool {
+    if deny.iter().any(|r| values.clone().any(|v| r.is_match(v))) {
+        return false;
+    }
+    allow.is_empty() || allow.iter().any(|r| values.clone().any(|v| r.is_match(v)))
+}
+
+#[derive(Debug, PartialEq)]
+enum Requirement {
+    Equals(String, String),
+    NotEquals(String, String),
+    Exists(String),
+    NotExists(String),
+}
+
+// Equality based label selector, requirements are separated by commas:
+// "key=value", "key==value", "key!=value", "key" or "!key"
+#[derive(Debug, PartialEq)]
+struct LabelSelector(Vec<Requirement>);
+
+impl LabelSelector {
+    fn parse(s: &str) -> Option<Self> {
+        let mut requirements = vec![];
+        for r in s.split(',').map(|r| r.trim()) {
+            let requirement = if let Some((k, v)) = r.split_once("!=") {
+                Requirement::NotEquals(k.trim().to_owned(), v.trim().to_owned())
+            } else if let Some((k, v)) = r.split_once("==").or_else(|| r.split_once('=')) {
+                Requirement::Equals(k.trim().to_owned(), v.trim().to_owned())
+            } else if let Some(k) = r.strip_prefix('!') {
+                Requirement::NotExists(k.trim().to_owned())
+            } else {
+                Requirement::Exists(r.to_owned())
+            };
+            match &requirement {
+                Requirement::Equals(k, _)
+                | Requirement::NotEquals(k, _)
+                | Requirement::Exists(k)
+                | Requirement::NotExists(k)
+                    if k.is_empty() =>
+                {
+                    return None
+                }
+                _ => requirements.push(requirement),
+            }
+        }
+        Some(Self(requirements))
+    }
+
+    fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.0.iter().all(|r| match r {
+            Requirement::Equals(k, v) => labels.get(k) == Some(v),
+            Requirement::NotEquals(k, v) => labels.get(k) != Some(v),
+            Requirement::Exists(k) => labels.contains_key(k),
+            Requirement::NotExists(k) => !labels.contains_key(k),
+        })
+    }
+}
+
+fn parse_selectors(selectors: &[String]) -> Vec<LabelSelector> {
+    selectors
+        .iter()
+        .filter_map(|s| match LabelSelector::parse(s) {
+            Some(selector) => Some(selector),
+            None => {
+                warn!("invalid ebpf scope pod label selector {}", s);
+                None
+            }
+        })
+        .collect()
+}
+
+// Cgroup paths of containers contain the pod uid, with '-' replaced by '_' in
+// systemd cgroup driver, e.g.
+// - /kubepods/burstable/pod<uid>/<container-id>
+// - /kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod<uid>.slice/...
+fn pod_uid(cgroup: &str) -> Option<String> {
+    let start = cgroup.find("pod")? + 3;
+    let uid = cgroup.get(start..start + 36)?;
+    let valid = uid.char_indices().all(|(i, c)| match i {
+        8 | 13 | 18 | 23 => c == '-' || c == '_',
+        _ => c.is_ascii_hexdigit(),
+    });
+    if valid {
+        Some(uid.replace('_', "-"))
+    } else {
+        pod_uid(&cgroup[start..])
+    }
+}
+
+// Returns paths of all cgroup hierarchies the process belongs to
+fn read_cgroups(pid: u32) -> Vec<String> {
+    let Ok(content) = fs::read_to_string(format!("/proc/{}/cgroup", pid)) else {
+        return vec![];
+    };
+    content
+        .lines()
+        .filter_map(|l| l.splitn(3, ':').nth(2))
+        .map(|p| p.to_owned())
+        .collect()
+}
+
+// Returns paths and ids of all cgroups in cgroup v2 hierarchy, ids are inode numbers
+// of cgroup directories, the same as bpf_get_current_cgroup_id() in eBPF
+fn walk_cgroups() -> Vec<(String, u64)> {
+    let Some(root) = CGROUP_ROOTS
+        .into_iter()
+        .find(|p| Path::new(p).join("cgroup.controllers").exists())
+    else {
+        return vec![];
+    };
+    let root = Path::new(root);
+    let mut cgroups = vec![];
+    let mut dirs = vec![PathBuf::from(root)];
+    while let Some(dir) = dirs.pop() {
+        let Ok(metadata) = fs::metadata(&dir) else {
+            continue;
+        };
+        let path = match dir.strip_prefix(root) {
+            Ok(p) => format!("/{}", p.display()),
+            Err(_) => continue,
+        };
+        cgroups.push((path, metadata.ino()));
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                dirs.push(entry.path());
+            }
+        }
+    }
+    cgroups
+}
+
+// Scopes eBPF collection to processes by kernel process name, cgroup path and pod
+// labels. Processes are evaluated in userspace when their data is first seen, and
+// those out of scope are pushed down to eBPF, so that their data is dropped in kernel.
+// Cgroups out of scope, including cgroups of pods selected by labels, are resolved
+// periodically and pushed down as well, so that they are dropped in kernel before any
+// data is seen in userspace.
+pub struct ProcessScope {
+    config: EbpfScope,
+    process_allow: Vec<Regex>,
+    process_deny: Vec<Regex>,
+    cgroup_allow: Vec<Regex>,
+    cgroup_deny: Vec<Regex>,
+    pod_allow: Vec<LabelSelector>,
+    pod_deny: Vec<LabelSelector>,
+    // pod uid -> allowed, None if pods are not available
+    pods: Option<HashMap<String, bool>>,
+    // pid -> (allowed, expire time)
+    decisions: LruCache<u32, (bool, Duration)>,
+    // pids denied since last taken, to be pushed down to eBPF
+    denied_pids: Vec<u32>,
+    next_refresh: Duration,
+}
+
+impl ProcessScope {
+    pub fn new(config: &EbpfScope) -> Self {
+        Self {
+            config: config.clone(),
+            process_allow: compile(&config.process_name_allowlist),
+            process_deny: compile(&config.process_name_denylist),
+            cgroup_allow: compile(&config.cgroup_allowlist),
+            cgroup_deny: compile(&config.cgroup_denylist),
+            pod_allow: parse_selectors(&config.pod_allowlist),
+            pod_deny: parse_selectors(&config.pod_denylist),
+            pods: None,
+            decisions: LruCache::new(NonZeroUsize::new(DECISION_CACHE_SIZE).unwrap()),
+            denied_pids: vec![],
+            next_refresh: Duration::ZERO,
+        }
+    }
+
+    pub fn config(&self) -> &EbpfScope {
+        &self.config
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.process_allow.is_empty()
+            && self.process_deny.is_empty()
+            && self.cgroup_allow.is_empty()
+            && self.cgroup_deny.is_empty()
+            && !self.has_pod_rules()
+    }
+
+    pub fn has_pod_rules(&self) -> bool {
+        !self.pod_allow.is_empty() || !self.pod_deny.is_empty()
+    }
+
+    fn has_cgroup_rules(&self) -> bool {
+        !self.cgroup_allow.is_empty() || !self.cgroup_deny.is_empty() || self.has_pod_rules()
+    }
+
+    fn pod_allowed(&self, labels: &BTreeMap<String, String>) -> bool {
+        if self.pod_deny.iter().any(|s| s.matches(labels)) {
+            return false;
+        }
+        self.pod_allow.is_empty() || self.pod_allow.iter().any(|s| s.matches(labels))
+    }
+
+    // Pod rules are ignored if pods are not available
+    fn cgroups_allowed(&self, cgroups: &[String]) -> bool {
+        if !is_allowed(
+            &self.cgroup_allow,
+            &self.cgroup_deny,
+            cgroups.iter().map(|c| c.as_str()),
+        ) {
+            return false;
+        }
+        let Some(pods) = self.pods.as_ref() else {
+            return true;
+        };
+        match cgroups.iter().find_map(|c| pod_uid(c)) {
+            Some(uid) => pods.get(&uid).copied().unwrap_or(self.pod_allow.is_empty()),
+            None => self.pod_allow.is_empty(),
+        }
+    }
+
+    fn evaluate(&self, process_name: &str, cgroups: impl FnOnce() -> Vec<String>) -> bool {
+        if !is_allowed(
+            &self.process_allow,
+            &self.process_deny,
+            std::iter::once(process_name),
+        ) {
+            return false;
+        }
+        if !self.has_cgroup_rules() {
+            return true;
+        }
+        self.cgroups_allowed(&cgroups())
+    }
+
+    // `process_kname` is the kernel process name, `now` is used to expire cached decisions
+    pub fn allowed(&mut self, pid: u32, process_kname: &[u8], now: Duration) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        if let Some((allowed, expire)) = self.decisions.get(&pid) {
+            if *expire > now {
+                return *allowed;
+            }
+        }
+        let end = process_kname
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(process_kname.len());
+        let process_name = String::from_utf8_lossy(&process_kname[..end]);
+        let allowed = self.evaluate(&process_name, || read_cgroups(pid));
+        self.decisions.put(pid, (allowed, now + DECISION_TTL));
+        if !allowed {
+            self.denied_pids.push(pid);
+        }
+        allowed
+    }
+
+    pub fn take_denied_pids(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.denied_pids)
+    }
+
+    pub fn need_refresh(&self, now: Duration) -> bool {
+        now >= self.next_refresh
+    }
+
+    // Clears decisions of processes, and returns ids of cgroups out of scope.
+    // `pods` are pods from the kubernetes API watcher, None if not available.
+    pub fn refresh(&mut self, now: Duration, pods: Option<Vec<PodLabels>>) -> Vec<u64> {
+        let first = self.next_refresh.is_zero();
+        self.next_refresh = now + DECISION_TTL;
+        self.decisions.clear();
+        self.denied_pids.clear();
+        if self.has_pod_rules() {
+            if pods.is_none() && (first || self.pods.is_some()) {
+                warn!("ebpf scope pod rules ignored because pods are not available from kubernetes api watcher");
+            }
+            self.pods = pods.map(|pods| {
+                pods.into_iter()
+                    .map(|(uid, labels)| {
+                        let allowed = self.pod_allowed(&labels);
+                        (uid, allowed)
+                    })
+                    .collect()
+            });
+        }
+        if !self.has_cgroup_rules() {
+            return vec![];
+        }
+        let cgroups = walk_cgroups()
+            .into_iter()
+            .filter(|(path, _)| !self.cgroups_allowed(std::slice::from_ref(path)))
+            .map(|(_, id)| id)
+            .collect::<Vec<_>>();
+        info!("ebpf scope resolved {} cgroups out of scope", cgroups.len());
+        cgroups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(s: &[&str]) -> Vec<String> {
+        s.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn labels(s: &[(&str, &str)]) -> BTreeMap<String, String> {
+        s.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn scope() {
+        let scope = ProcessScope::new(&EbpfScope::default());
+        assert!(scope.is_empty());
+
+        let scope = ProcessScope::new(&EbpfScope {
+            process_name_allowlist: strings(&["^java$", "^nginx"]),
+            process_name_denylist: strings(&["^nginx-debug$", "("]),
+            cgroup_denylist: strings(&["kubepods.*pod1234"]),
+            ..Default::default()
+        });
+        assert_eq!(scope.process_deny.len(), 1);
+        let cgroups = || strings(&["/kubepods/burstable/pod5678/abcdef"]);
+        assert!(scope.evaluate("java", cgroups));
+        assert!(scope.evaluate("nginx", cgroups));
+        assert!(!scope.evaluate("nginx-debug", cgroups));
+        assert!(!scope.evaluate("python", cgroups));
+        assert!(!scope.evaluate("java", || strings(&["/kubepods/burstable/pod1234/abcdef"])));
+
+        let scope = ProcessScope::new(&EbpfScope {
+            cgroup_allowlist: strings(&["pod5678"]),
+            ..Default::default()
+        });
+        assert!(scope.evaluate("python", cgroups));
+        // process gone or cgroup not readable
+        assert!(!scope.evaluate("python", Vec::new));
+    }
+
+    #[test]
+    fn cached_decision() {
+        let mut scope = ProcessScope::new(&EbpfScope {
+            process_name_denylist: strings(&["^curl$"]),
+            ..Default::default()
+        });
+        let mut kname = [0u8; 16];
+        kname[..4].copy_from_slice(b"curl");
+        assert!(!scope.allowed(1, &kname, Duration::ZERO));
+        // pid reused by another process after the decision expires
+        kname[..4].copy_from_slice(b"wget");
+        assert!(!scope.allowed(1, &kname, Duration::from_secs(1)));
+        assert!(scope.allowed(1, &kname, DECISION_TTL * 2));
+        assert_eq!(scope.take_denied_pids(), vec![1]);
+        assert!(scope.take_denied_pids().is_empty());
+    }
+
+    #[test]
+    fn label_selector() {
+        let s = LabelSelector::parse("app=nginx, tier!=cache,canary,!debug").unwrap();
+        assert!(s.matches(&labels(&[("app", "nginx"), ("canary", "")])));
+        assert!(!s.matches(&labels(&[("app", "nginx")])));
+        assert!(!s.matches(&labels(&[
+            ("app", "nginx"),
+            ("canary", ""),
+            ("tier", "cache")
+        ])));
+        assert!(!s.matches(&labels(&[("app", "nginx"), ("canary", ""), ("debug", "1")])));
+        assert_eq!(
+            LabelSelector::parse("app==nginx"),
+            LabelSelector::parse("app=nginx")
+        );
+        assert_eq!(LabelSelector::parse("=nginx"), None);
+        assert_eq!(LabelSelector::parse("app=nginx,"), None);
+    }
+
+    #[test]
+    fn pod_uid_in_cgroup() {
+        let uid = "0a1b2c3d-0000-1111-2222-333344445555";
+        assert_eq!(
+            pod_uid(&format!("/kubepods/burstable/pod{}/abcdef", uid)).as_deref(),
+            Some(uid)
+        );
+        assert_eq!(
+            pod_uid(&format!(
+                "/kubepods.slice/kubepods-podruntime.slice/kubepods-pod{}.slice/cri-containerd-abcdef.scope",
+                uid.replace('-', "_")
+            ))
+            .as_deref(),
+            Some(uid)
+        );
+        assert_eq!(pod_uid("/system.slice/podman.service"), None);
+    }
+
+    #[test]
+    fn pod_rules() {
+        let mut scope = ProcessScope::new(&EbpfScope {
+            pod_allowlist: strings(&["app=nginx"]),
+            pod_denylist: strings(&["app=nginx,debug"]),
+            ..Default::default()
+        });
+        let uid = |i: u32| format!("{:08x}-0000-1111-2222-333344445555", i);
+        let cgroups = |i: u32| vec![format!("/kubepods/besteffort/pod{}/abcdef", uid(i))];
+        // ignored before pods are available
+        assert!(scope.evaluate("python", || cgroups(1)));
+
+        scope.pods = Some(
+            [
+                (uid(1), labels(&[("app", "nginx")])),
+                (uid(2), labels(&[("app", "nginx"), ("debug", "")])),
+                (uid(3), labels(&[("app", "redis")])),
+            ]
+            .into_iter()
+            .map(|(uid, labels)| {
+                let allowed = scope.pod_allowed(&labels);
+                (uid, allowed)
+            })
+            .collect(),
+        );
+        assert!(scope.evaluate("python", || cgroups(1)));
+        assert!(!scope.evaluate("python", || cgroups(2)));
+        assert!(!scope.evaluate("python", || cgroups(3)));
+        // unknown pods and processes not in pods
+        assert!(!scope.evaluate("python", || cgroups(4)));
+        assert!(!scope.evaluate("python", || strings(&["/system.slice/sshd.service"])));
+    }
+}
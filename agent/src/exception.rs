@@ -35,7 +35,8 @@ impl ExceptionHandler {
         | Exception::ControllerSocketError as u64
         | Exception::AnalyzerSocketError as u64
         | Exception::IntegrationSocketError as u64
-        | Exception::NpbSocketError as u64;
+        | Exception::NpbSocketError as u64
+        | Exception::NpbEndpointDown as u64;
 
     pub fn set(&self, e: Exception) {
         self.0.fetch_or(e as u64, Ordering::SeqCst);
@@ -46,6 +47,11 @@ impl ExceptionHandler {
         self.0.load(Ordering::Relaxed) & e == e
     }
 
+    // Raised exceptions without clearing them
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
     pub fn clear(&self, e: Exception) {
         self.0.fetch_and(!(e as u64), Ordering::SeqCst);
     }
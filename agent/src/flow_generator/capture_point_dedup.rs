@@ -0,0 +1,230 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{collections::HashMap, net::IpAddr, time::Duration};
+
+use parking_lot::Mutex;
+
+use crate::{
+    common::{enums::TapType, flow::Flow},
+    metric::document::TapSide,
+};
+
+// Observation not reported within the timeout is considered gone,
+// and lower ranked capture points can take over the flow
+const OBSERVATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn parse_tap_side(s: &str) -> Option<TapSide> {
+    let side = match s {
+        "rest" => TapSide::Rest,
+        "c" => TapSide::Client,
+        "s" => TapSide::Server,
+        "local" => TapSide::Local,
+        "c-nd" => TapSide::ClientNode,
+        "s-nd" => TapSide::ServerNode,
+        "c-hv" => TapSide::ClientHypervisor,
+        "s-hv" => TapSide::ServerHypervisor,
+        "c-gw-hv" => TapSide::ClientGatewayHypervisor,
+        "s-gw-hv" => TapSide::ServerGatewayHypervisor,
+        "c-gw" => TapSide::ClientGateway,
+        "s-gw" => TapSide::ServerGateway,
+        _ => return None,
+    };
+    Some(side)
+}
+
+// Flow identity regardless of direction and capture point
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct DedupKey {
+    ip_0: IpAddr,
+    ip_1: IpAddr,
+    port_0: u16,
+    port_1: u16,
+    proto: u8,
+}
+
+impl From<&Flow> for DedupKey {
+    fn from(flow: &Flow) -> Self {
+        let key = &flow.flow_key;
+        let (ip_0, port_0, ip_1, port_1) =
+            if (key.ip_src, key.port_src) <= (key.ip_dst, key.port_dst) {
+                (key.ip_src, key.port_src, key.ip_dst, key.port_dst)
+            } else {
+                (key.ip_dst, key.port_dst, key.ip_src, key.port_src)
+            };
+        Self {
+            ip_0,
+            ip_1,
+            port_0,
+            port_1,
+            proto: key.proto.into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct CapturePoint {
+    tap_type: TapType,
+    tap_port: u64,
+    tap_side: TapSide,
+}
+
+impl From<&Flow> for CapturePoint {
+    fn from(flow: &Flow) -> Self {
+        Self {
+            tap_type: flow.flow_key.tap_type,
+            tap_port: flow.flow_key.tap_port.ignore_nat_source(),
+            tap_side: flow.tap_side,
+        }
+    }
+}
+
+struct Observation {
+    point: CapturePoint,
+    rank: usize,
+    last_seen: Duration,
+}
+
+// Shared by flow maps of all dispatchers to find out flows captured on multiple
+// capture points, e.g. on both the pod veth and the node uplink, or on two mirrored
+// switches. The flow is attributed to the highest ranked capture point, or to the
+// first one reporting it when ranks are equal, other observations are suppressed.
+pub struct CapturePointDedup {
+    capacity: usize,
+    observations: Mutex<HashMap<DedupKey, Observation>>,
+}
+
+impl CapturePointDedup {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            observations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Rank of capture point is its index in priority, unlisted tap sides rank the lowest
+    pub fn rank(priority: &[TapSide], tap_side: TapSide) -> usize {
+        priority
+            .iter()
+            .position(|s| *s == tap_side)
+            .unwrap_or(priority.len())
+    }
+
+    // Returns true if the flow is captured by a higher ranked capture point,
+    // `now` is the statistical time of the flow
+    pub fn suppressed(&self, flow: &Flow, rank: usize, now: Duration) -> bool {
+        let key = DedupKey::from(flow);
+        let point = CapturePoint::from(flow);
+        let mut observations = self.observations.lock();
+        if let Some(o) = observations.get_mut(&key) {
+            if o.point == point || rank < o.rank || o.last_seen + OBSERVATION_TIMEOUT < now {
+                o.point = point;
+                o.rank = rank;
+                o.last_seen = o.last_seen.max(now);
+                return false;
+            }
+            return true;
+        }
+        if observations.len() >= self.capacity {
+            observations.retain(|_, o| o.last_seen + OBSERVATION_TIMEOUT >= now);
+            if observations.len() >= self.capacity {
+                // not able to tell, count it anyway
+                return false;
+            }
+        }
+        observations.insert(
+            key,
+            Observation {
+                point,
+                rank,
+                last_seen: now,
+            },
+        );
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use crate::common::tap_port::TapPort;
+
+    fn new_flow(tap_port: u32, tap_side: TapSide, reversed: bool) -> Flow {
+        let mut flow = Flow::default();
+        flow.flow_key.tap_type = TapType::Cloud;
+        flow.flow_key.tap_port = TapPort::from_local_mac(0, Default::default(), tap_port);
+        flow.flow_key.ip_src = Ipv4Addr::new(10, 0, 0, 1).into();
+        flow.flow_key.ip_dst = Ipv4Addr::new(10, 0, 0, 2).into();
+        flow.flow_key.port_src = 34567;
+        flow.flow_key.port_dst = 80;
+        if reversed {
+            flow.flow_key.reverse();
+        }
+        flow.tap_side = tap_side;
+        flow
+    }
+
+    #[test]
+    fn suppress_lower_ranked() {
+        let priority = [TapSide::Client, TapSide::ClientNode];
+        let dedup = CapturePointDedup::new(16);
+        let veth = new_flow(1, TapSide::Client, false);
+        let uplink = new_flow(2, TapSide::ClientNode, true);
+        let veth_rank = CapturePointDedup::rank(&priority, veth.tap_side);
+        let uplink_rank = CapturePointDedup::rank(&priority, uplink.tap_side);
+
+        // uplink reported first, taken over by veth afterwards
+        let now = Duration::from_secs(100);
+        assert!(!dedup.suppressed(&uplink, uplink_rank, now));
+        assert!(!dedup.suppressed(&veth, veth_rank, now));
+        assert!(dedup.suppressed(&uplink, uplink_rank, now));
+        assert!(!dedup.suppressed(&veth, veth_rank, now + Duration::from_secs(1)));
+
+        // veth is gone
+        let now = now + Duration::from_secs(1) + OBSERVATION_TIMEOUT * 2;
+        assert!(!dedup.suppressed(&uplink, uplink_rank, now));
+
+        // equal ranks, first reported wins
+        let mirror = new_flow(3, TapSide::Rest, false);
+        let rank = CapturePointDedup::rank(&priority, TapSide::Rest);
+        assert_eq!(rank, priority.len());
+        let other = new_flow(4, TapSide::Rest, false);
+        let now = now + OBSERVATION_TIMEOUT * 2;
+        assert!(!dedup.suppressed(&mirror, rank, now));
+        assert!(dedup.suppressed(&other, rank, now));
+    }
+
+    #[test]
+    fn capacity() {
+        let dedup = CapturePointDedup::new(1);
+        let mut flow = new_flow(1, TapSide::Client, false);
+        assert!(!dedup.suppressed(&flow, 0, Duration::from_secs(1)));
+        flow.flow_key.port_src += 1;
+        // table full
+        assert!(!dedup.suppressed(&flow, 0, Duration::from_secs(1)));
+        let other = new_flow(2, TapSide::Client, false);
+        assert!(dedup.suppressed(&other, 1, Duration::from_secs(1)));
+        // expired entries evicted
+        let later = Duration::from_secs(1) + OBSERVATION_TIMEOUT * 2;
+        assert!(!dedup.suppressed(&flow, 0, later));
+        let mut other = flow.clone();
+        other.flow_key.tap_port = TapPort::from_local_mac(0, Default::default(), 2);
+        assert!(dedup.suppressed(&other, 1, later));
+    }
+}
@@ -55,6 +55,8 @@ pub struct FlowTimeout {
     pub closed_fin: Timestamp,
     pub single_direction: Timestamp,
     pub opening_rst: Timestamp,
+    pub udp: Timestamp,
+    pub other: Timestamp,
 
     pub min: Timestamp,
     pub max: Timestamp, // time window
@@ -71,6 +73,8 @@ impl From<TcpTimeout> for FlowTimeout {
             closed_fin: Timestamp::from_secs(2),
             single_direction: t.others,
             opening_rst: t.opening_rst,
+            udp: t.others,
+            other: t.closing_rst,
             min: Timestamp::from_secs(0),
             max: Timestamp::from_secs(0),
         };
@@ -80,6 +84,15 @@ impl From<TcpTimeout> for FlowTimeout {
 }
 
 impl FlowTimeout {
+    // udp flows use `udp` all the time, other non-tcp flows use `opening`
+    // until packets are seen in both directions, then `other`
+    pub fn with_udp_other(mut self, udp: Timestamp, other: Timestamp) -> Self {
+        self.udp = udp;
+        self.other = other;
+        self.update_min_max();
+        self
+    }
+
     fn update_min_max(&mut self) {
         self.min = self
             .opening
@@ -89,7 +102,9 @@ impl FlowTimeout {
             .min(self.exception)
             .min(self.closed_fin)
             .min(self.single_direction)
-            .min(self.opening_rst);
+            .min(self.opening_rst)
+            .min(self.udp)
+            .min(self.other);
         self.max = self
             .opening
             .max(self.established)
@@ -98,7 +113,9 @@ impl FlowTimeout {
             .max(self.exception)
             .max(self.closed_fin)
             .max(self.single_direction)
-            .max(self.opening_rst);
+            .max(self.opening_rst)
+            .max(self.udp)
+            .max(self.other);
     }
 }
 
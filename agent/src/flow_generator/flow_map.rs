@@ -31,11 +31,12 @@ use std::{
 };
 
 use ahash::AHashMap;
-use log::{debug, warn};
+use log::{debug, info, warn};
 use lru::LruCache;
 
 use super::{
     app_table::AppTable,
+    capture_point_dedup::CapturePointDedup,
     error::Error,
     flow_state::{StateMachine, StateValue},
     perf::{tcp::TcpPerf, FlowLog, FlowPerfCounter, L7ProtocolChecker},
@@ -45,6 +46,7 @@ use super::{
         AppProto, MetaAppProto,
     },
     service_table::{ServiceKey, ServiceTable},
+    tls_handshake::{self, TlsHandshake, TlsTag},
     FlowMapKey, FlowNode, FlowState, FlowTimeout, COUNTER_FLOW_ID_MASK, FLOW_METRICS_PEER_DST,
     FLOW_METRICS_PEER_SRC, QUEUE_BATCH_SIZE, SERVICE_TABLE_IPV4_CAPACITY,
     SERVICE_TABLE_IPV6_CAPACITY, STATISTICAL_INTERVAL, THREAD_FLOW_ID_MASK, TIMER_FLOW_ID_MASK,
@@ -72,10 +74,11 @@ use crate::{
     },
     config::{
         handler::{CollectorConfig, LogParserConfig, PluginConfig},
-        FlowConfig, ModuleConfig, RuntimeConfig,
+        FlowConfig, FlowEvictionPolicy, ModuleConfig, RuntimeConfig,
     },
     flow_generator::protocol_logs::PseudoAppProto,
     metric::document::TapSide,
+    platform::ConntrackTable,
     plugin::wasm::WasmVm,
     policy::{Policy, PolicyGetter},
     rpc::get_timestamp,
@@ -85,7 +88,7 @@ use crate::{
 use crate::{config::handler::EbpfConfig, plugin::c_ffi::SoPluginFunc};
 use public::{
     buffer::{Allocator, BatchedBox},
-    counter::{Counter, CounterType, CounterValue, RefCountable},
+    counter::{Counter, CounterType, CounterValue, Histogram, RefCountable},
     debug::QueueDebugger,
     l7_protocol::L7ProtocolEnum,
     packet::SECONDS_IN_MINUTE,
@@ -97,6 +100,8 @@ use public::{
 use packet_sequence_block::PacketSequenceBlock;
 
 const DEFAULT_SOCKET_CLOSE_TIMEOUT: Timestamp = Timestamp::from_secs(1);
+// SYN and ACK are expected before ClientHello, or ClientHello is carried in SYN with TCP Fast Open
+const TLS_CLIENT_HELLO_MAX_PACKETS: u64 = 4;
 
 pub struct Config<'a> {
     pub flow: &'a FlowConfig,
@@ -191,6 +196,7 @@ pub struct FlowMap {
     system_time: Duration,
 
     l7_protocol_checker: L7ProtocolChecker,
+    l7_protocol_enabled_bitmap: L7ProtocolBitmap,
 
     time_key_buffer: Option<Vec<(u64, FlowMapKey)>>,
 
@@ -206,6 +212,9 @@ pub struct FlowMap {
     stats_collector: Arc<stats::Collector>,
 
     obfuscate_cache: Option<ObfuscateCache>,
+
+    capture_point_dedup: Option<Arc<CapturePointDedup>>,
+    conntrack_table: Option<Arc<ConntrackTable>>,
 }
 
 impl FlowMap {
@@ -219,6 +228,8 @@ impl FlowMap {
         config: &FlowConfig,
         packet_sequence_queue: Option<DebugSender<Box<PacketSequenceBlock>>>, // Enterprise Edition Feature: packet-sequence
         stats_collector: Arc<stats::Collector>,
+        capture_point_dedup: Option<Arc<CapturePointDedup>>,
+        conntrack_table: Option<Arc<ConntrackTable>>,
         from_ebpf: bool,
     ) -> Self {
         let flow_perf_counter = Arc::new(FlowPerfCounter::default());
@@ -303,18 +314,8 @@ impl FlowMap {
             packet_sequence_enabled,
             stats_counter,
             system_time,
-            l7_protocol_checker: L7ProtocolChecker::new(
-                &config.l7_protocol_enabled_bitmap,
-                &config
-                    .l7_protocol_parse_port_bitmap
-                    .iter()
-                    .filter_map(|(name, bitmap)| {
-                        L7ProtocolParser::try_from(name.as_ref())
-                            .ok()
-                            .map(|p| (p.protocol(), bitmap.clone()))
-                    })
-                    .collect(),
-            ),
+            l7_protocol_checker: Self::new_l7_protocol_checker(config),
+            l7_protocol_enabled_bitmap: config.l7_protocol_enabled_bitmap,
             time_key_buffer: None,
             plugin_digest: 0, // force initial load
             wasm_vm: Default::default(),
@@ -333,9 +334,40 @@ impl FlowMap {
             stats_collector,
             capacity: config.capacity as usize,
             size: 0,
+            capture_point_dedup,
+            conntrack_table,
         }
     }
 
+    fn new_l7_protocol_checker(config: &FlowConfig) -> L7ProtocolChecker {
+        L7ProtocolChecker::new(
+            &config.l7_protocol_enabled_bitmap,
+            &config
+                .l7_protocol_parse_port_bitmap
+                .iter()
+                .filter_map(|(name, bitmap)| {
+                    L7ProtocolParser::try_from(name.as_ref())
+                        .ok()
+                        .map(|p| (p.protocol(), bitmap.clone()))
+                })
+                .collect(),
+        )
+    }
+
+    // protocols can be enabled or disabled by controller at runtime,
+    // flows already created keep their parsers
+    fn update_l7_protocol_checker(&mut self, config: &FlowConfig) {
+        if self.l7_protocol_enabled_bitmap == config.l7_protocol_enabled_bitmap {
+            return;
+        }
+        info!(
+            "flow map id: {} l7 protocol enabled changed to {:?}",
+            self.id, config.l7_protocol_enabled_bitmap
+        );
+        self.l7_protocol_enabled_bitmap = config.l7_protocol_enabled_bitmap;
+        self.l7_protocol_checker = Self::new_l7_protocol_checker(config);
+    }
+
     fn load_plugins(&mut self, config: &PluginConfig) {
         if self.plugin_digest == config.digest {
             return;
@@ -598,6 +630,10 @@ impl FlowMap {
                     // Only node whose SignalSource is EBPF needs to send close event when it is timeout.
                     if node.tagged_flow.flow.signal_source == SignalSource::EBPF {
                         self.send_socket_close_event(&node);
+                    } else if Self::is_tcp_open(&node) {
+                        self.stats_counter
+                            .evict_before_fin
+                            .fetch_add(1, Ordering::Relaxed);
                     }
                     self.node_removed_aftercare(&config, node, timeout.into(), None);
                 }
@@ -655,6 +691,71 @@ impl FlowMap {
         true
     }
 
+    fn is_tcp_open(node: &FlowNode) -> bool {
+        node.tagged_flow.flow.flow_key.proto == IpProtocol::TCP
+            && matches!(
+                node.flow_state,
+                FlowState::Opening1 | FlowState::Opening2 | FlowState::Established
+            )
+    }
+
+    // Evict the idle node closest to its timeout to make room for a new flow, which is the
+    // least recently used one among nodes with the same timeout.
+    //
+    // Nodes having packets since last flush are still in the time slot of their latest
+    // packet and are not evicted. Idle nodes have been moved to the time slot of their
+    // timeout by flush, so scanning from the earliest time slot finds the one to evict.
+    fn evict_idle_node(
+        &mut self,
+        config: &FlowConfig,
+        node_map: &mut AHashMap<FlowMapKey, Vec<Box<FlowNode>>>,
+        time_set: &mut [HashSet<FlowMapKey>],
+    ) {
+        for time_in_unit in
+            self.start_time_in_unit..self.start_time_in_unit + self.time_window_size as u64
+        {
+            let time_hashset = &mut time_set[time_in_unit as usize & (self.time_window_size - 1)];
+            // keys in time_set are not removed when nodes are moved to other slots,
+            // find one which still has a node in this slot
+            let found = time_hashset.iter().find_map(|flow_key| {
+                node_map.get(flow_key).and_then(|nodes| {
+                    nodes
+                        .iter()
+                        .position(|n| {
+                            n.timestamp_key == time_in_unit
+                                && n.timestamp_key > n.recent_time.as_secs()
+                        })
+                        .map(|index| (*flow_key, index))
+                })
+            });
+            let Some((flow_key, index)) = found else {
+                continue;
+            };
+            let nodes = node_map.get_mut(&flow_key).unwrap();
+            let node = nodes.swap_remove(index);
+            if !nodes.iter().any(|n| n.timestamp_key == time_in_unit) {
+                time_hashset.remove(&flow_key);
+            }
+            if nodes.is_empty() {
+                node_map.remove(&flow_key);
+            }
+
+            self.stats_counter
+                .evict_by_capacity
+                .fetch_add(1, Ordering::Relaxed);
+            if node.tagged_flow.flow.signal_source == SignalSource::EBPF {
+                self.send_socket_close_event(&node);
+            } else if Self::is_tcp_open(&node) {
+                self.stats_counter
+                    .evict_before_fin
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            let end_time = node.recent_time.into();
+            self.node_removed_aftercare(config, node, end_time, None);
+            return;
+        }
+    }
+
     fn lookup_without_flow(&mut self, config: &Config, meta_packet: &mut MetaPacket) {
         // 补充由于超时导致未查询策略，用于其它流程（如PCAP存储）
         #[cfg(any(target_os = "linux", target_os = "android"))]
@@ -676,6 +777,10 @@ impl FlowMap {
         let flow_config = &config.flow;
 
         self.load_plugins(&flow_config.plugins);
+        self.update_l7_protocol_checker(flow_config);
+        self.perf_cache
+            .borrow_mut()
+            .set_rrt_sketch_accuracy(flow_config.rrt_sketch_accuracy);
 
         let pkt_key = FlowMapKey::new(&meta_packet.lookup_key, meta_packet.tap_port);
 
@@ -710,11 +815,16 @@ impl FlowMap {
                         return;
                     }
                     // No exact match of FlowNode was found, insert new Node
+                    if self.size as usize >= self.capacity
+                        && flow_config.eviction_policy == FlowEvictionPolicy::Lru
+                    {
+                        self.evict_idle_node(flow_config, &mut node_map, &mut time_set);
+                    }
                     let node = self.new_flow_node(config, meta_packet);
                     if let Some(node) = node {
                         time_set[node.timestamp_key as usize & (self.time_window_size - 1)]
                             .insert(pkt_key);
-                        nodes.push(node);
+                        node_map.entry(pkt_key).or_default().push(node);
                         max_depth += 1;
                     }
                     Self::update_stats_counter(
@@ -790,6 +900,11 @@ impl FlowMap {
                     self.node_map.replace((node_map, time_set));
                     return;
                 }
+                if self.size as usize >= self.capacity
+                    && flow_config.eviction_policy == FlowEvictionPolicy::Lru
+                {
+                    self.evict_idle_node(flow_config, &mut node_map, &mut time_set);
+                }
                 let node = self.new_flow_node(config, meta_packet);
                 if let Some(node) = node {
                     time_set[node.timestamp_key as usize & (self.time_window_size - 1)]
@@ -914,7 +1029,7 @@ impl FlowMap {
             && peers[FLOW_METRICS_PEER_DST].packet_count > 0
         {
             // For udp, eBPF and Packet data use the same timeout
-            node.timeout = flow_config.flow_timeout.closing;
+            node.timeout = flow_config.flow_timeout.udp;
         }
         meta_packet.is_active_service = node.tagged_flow.flow.is_active_service;
         if flow_config.collector_enabled {
@@ -949,7 +1064,7 @@ impl FlowMap {
         if peers[FLOW_METRICS_PEER_SRC].packet_count > 0
             && peers[FLOW_METRICS_PEER_DST].packet_count > 0
         {
-            node.timeout = config.flow.flow_timeout.established_rst;
+            node.timeout = config.flow.flow_timeout.other;
         }
         if let Some(meta_flow_log) = node.meta_flow_log.as_mut() {
             let _ = meta_flow_log.parse_l3(meta_packet);
@@ -1219,6 +1334,7 @@ impl FlowMap {
             ],
             signal_source: meta_packet.signal_source,
             is_active_service,
+            capture_nic: meta_packet.capture_nic,
             ..Default::default()
         };
         tagged_flow.flow = flow;
@@ -1246,6 +1362,7 @@ impl FlowMap {
         node.policy_data_cache = Default::default();
         node.endpoint_data_cache = Default::default();
         node.packet_sequence_block = None; // Enterprise Edition Feature: packet-sequence
+        node.tls_handshake = None;
         node.residual_request = 0;
         #[cfg(any(target_os = "linux", target_os = "android"))]
         let local_epc_id = match config.ebpf.as_ref() {
@@ -1395,6 +1512,7 @@ impl FlowMap {
                 last,
                 self.ntp_diff.clone(),
                 self.obfuscate_cache.as_ref().map(|o| o.clone()),
+                flow_config.tcp_histogram_enabled,
             )
             .map(|o| Box::new(o));
         }
@@ -1556,6 +1674,42 @@ impl FlowMap {
 
             (self.policy_getter).lookup(meta_packet, self.id as usize, local_epc_id);
         }
+
+        if config.flow.tls_handshake_tags_enabled {
+            Self::update_tls_handshake_tags(node, meta_packet);
+        }
+    }
+
+    // Extract SNI and certificate names from the first packets of TLS flows
+    fn update_tls_handshake_tags(node: &mut FlowNode, meta_packet: &MetaPacket) {
+        if meta_packet.lookup_key.proto != IpProtocol::TCP {
+            return;
+        }
+        let payload = match meta_packet.get_l4_payload() {
+            Some(p) if !p.is_empty() => p,
+            _ => return,
+        };
+        let flow = &mut node.tagged_flow.flow;
+        let from_client = meta_packet.lookup_key.direction == PacketDirection::ClientToServer;
+        if node.tls_handshake.is_none() {
+            if !from_client
+                || flow.flow_metrics_peers[FLOW_METRICS_PEER_SRC].total_packet_count
+                    > TLS_CLIENT_HELLO_MAX_PACKETS
+                || !tls_handshake::is_tls_handshake(payload)
+            {
+                return;
+            }
+            node.tls_handshake = Some(Box::new(TlsHandshake::default()));
+        }
+        let handshake = node.tls_handshake.as_mut().unwrap();
+        match handshake.update(from_client, payload) {
+            Some(TlsTag::ServerName(name)) => flow.tls_sni = name,
+            Some(TlsTag::Certificate { subject, issuer }) => {
+                flow.tls_cert_subject = subject;
+                flow.tls_cert_issuer = issuer;
+            }
+            None => (),
+        }
     }
 
     fn collect_l7_stats(
@@ -1577,11 +1731,12 @@ impl FlowMap {
                     .perf_cache
                     .borrow_mut()
                     .pop_timeout_count(flow_id, false); // TODO: flow_end is most likely false, but may also be true
-                let (l7_perf_stats, l7_protocol) = node
+                let (mut l7_perf_stats, l7_protocol) = node
                     .meta_flow_log
                     .as_mut()
                     .unwrap()
                     .copy_and_reset_l7_perf_data(l7_timeout_count as u32);
+                l7_perf_stats.rrt_sketch = self.perf_cache.borrow_mut().pop_rrt_sketch(flow_id);
 
                 // FIXME: Because the endpoint changes, the index of the first packet of the current endpoint
                 // will also be counted into the index of the previous endpoint, so there will be a slight error
@@ -1740,6 +1895,12 @@ impl FlowMap {
             }
         }
 
+        // ClientHello may be carried in SYN with TCP Fast Open, or the flow is picked up in the
+        // middle of handshake
+        if flow_config.tls_handshake_tags_enabled {
+            Self::update_tls_handshake_tags(&mut node, meta_packet);
+        }
+
         // Enterprise Edition Feature: packet-sequence
         if self.packet_sequence_enabled
             && !collector_config.l4_log_ignore_tap_sides[node.tagged_flow.flow.tap_side as usize]
@@ -1758,7 +1919,7 @@ impl FlowMap {
         meta_packet.is_active_service = node.tagged_flow.flow.is_active_service;
         node.flow_state = FlowState::Established;
         // For eBPF UDP Flow, there is no special treatment for timeout.
-        node.timeout = flow_config.flow_timeout.opening; // use opening timeout
+        node.timeout = flow_config.flow_timeout.udp;
         let mut reverse = false;
         if node.tagged_flow.flow.signal_source != SignalSource::EBPF {
             // eBPF Flow only use server_port to correct the direction.
@@ -1846,6 +2007,57 @@ impl FlowMap {
         }
     }
 
+    // Fills real addresses of NATed peers from kernel conntrack table,
+    // unless obtained from a source with higher priority
+    fn update_conntrack_nat(&self, flow: &mut Flow) {
+        let Some(table) = self.conntrack_table.as_ref() else {
+            return;
+        };
+        if flow.signal_source != SignalSource::Packet {
+            return;
+        }
+        let Some(mapping) = table.lookup(&flow.flow_key) else {
+            return;
+        };
+        for (peer, real) in flow
+            .flow_metrics_peers
+            .iter_mut()
+            .zip([mapping.src, mapping.dst])
+        {
+            if let Some((ip, port)) = real {
+                if peer.nat_source <= TapPort::NAT_SOURCE_CONNTRACK {
+                    peer.nat_source = TapPort::NAT_SOURCE_CONNTRACK;
+                    peer.nat_real_ip = ip;
+                    peer.nat_real_port = port;
+                }
+            }
+        }
+        if flow.flow_key.tap_port.get_nat_source() < TapPort::NAT_SOURCE_CONNTRACK {
+            flow.flow_key
+                .tap_port
+                .set_nat_source(TapPort::NAT_SOURCE_CONNTRACK);
+        }
+    }
+
+    // Marks the flow if it is also captured by a higher ranked capture point,
+    // so that it's excluded from metrics to avoid double counting
+    fn check_capture_point(&self, config: &FlowConfig, flow: &mut Flow) {
+        let Some(dedup) = self.capture_point_dedup.as_ref() else {
+            return;
+        };
+        if config.capture_point_priority.is_empty() || flow.signal_source != SignalSource::Packet {
+            flow.capture_point_suppressed = false;
+            return;
+        }
+        let rank = CapturePointDedup::rank(&config.capture_point_priority, flow.tap_side);
+        flow.capture_point_suppressed = dedup.suppressed(flow, rank, flow.flow_stat_time.into());
+        if flow.capture_point_suppressed {
+            self.stats_counter
+                .capture_point_suppressed
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
     fn push_to_flow_stats_queue(&mut self, tagged_flow: Arc<BatchedBox<TaggedFlow>>) {
         if self.l7_stats_buffer.len() >= QUEUE_BATCH_SIZE {
             if let Err(e) = self
@@ -1922,8 +2134,10 @@ impl FlowMap {
                     .perf_cache
                     .borrow_mut()
                     .pop_timeout_count(&flow.flow_id, true);
-                let (l7_perf_stats, l7_protocol) =
+                let (mut l7_perf_stats, l7_protocol) =
                     perf.copy_and_reset_l7_perf_data(l7_timeout_count as u32);
+                l7_perf_stats.rrt_sketch =
+                    self.perf_cache.borrow_mut().pop_rrt_sketch(&flow.flow_id);
 
                 let flow_perf_stats = flow.flow_perf_stats.as_mut().unwrap();
                 flow_perf_stats.l7.sequential_merge(&l7_perf_stats);
@@ -1942,6 +2156,8 @@ impl FlowMap {
             .concurrent
             .fetch_sub(1, Ordering::Relaxed);
         self.stats_counter.closed.fetch_add(1, Ordering::Relaxed);
+        self.update_conntrack_nat(&mut node.tagged_flow.flow);
+        self.check_capture_point(config, &mut node.tagged_flow.flow);
 
         let tagged_flow = Arc::new(
             self.tagged_flow_allocator
@@ -1996,8 +2212,10 @@ impl FlowMap {
                         .perf_cache
                         .borrow_mut()
                         .pop_timeout_count(&flow.flow_id, false);
-                    let (l7_perf_stats, l7_protocol) =
+                    let (mut l7_perf_stats, l7_protocol) =
                         perf.copy_and_reset_l7_perf_data(l7_timeout_count as u32);
+                    l7_perf_stats.rrt_sketch =
+                        self.perf_cache.borrow_mut().pop_rrt_sketch(&flow.flow_id);
 
                     let flow_perf_stats = flow.flow_perf_stats.as_mut().unwrap();
                     flow_perf_stats.l7.sequential_merge(&l7_perf_stats);
@@ -2012,6 +2230,8 @@ impl FlowMap {
                 }
             }
 
+            self.update_conntrack_nat(&mut node.tagged_flow.flow);
+            self.check_capture_point(config, &mut node.tagged_flow.flow);
             let tagged_flow = Arc::new(
                 self.tagged_flow_allocator
                     .allocate_one_with(node.tagged_flow.clone()),
@@ -2315,6 +2535,7 @@ impl FlowMap {
             peer_src.is_l2_end = src_info.l2_end;
             peer_src.is_l3_end = src_info.l3_end;
             peer_src.l3_epc_id = src_info.l3_epc_id;
+            peer_src.tenant_id = src_info.tenant_id;
             peer_src.is_vip = src_info.is_vip;
             peer_src.is_local_mac = src_info.is_local_mac;
             peer_src.is_local_ip = src_info.is_local_ip;
@@ -2335,6 +2556,7 @@ impl FlowMap {
             peer_dst.is_l2_end = dst_info.l2_end;
             peer_dst.is_l3_end = dst_info.l3_end;
             peer_dst.l3_epc_id = dst_info.l3_epc_id;
+            peer_dst.tenant_id = dst_info.tenant_id;
             peer_dst.is_vip = dst_info.is_vip;
             peer_dst.is_local_mac = dst_info.is_local_mac;
             peer_dst.is_local_ip = dst_info.is_local_ip;
@@ -2364,8 +2586,10 @@ impl FlowMap {
     }
 }
 
+// 1µs to 10ms in ns
+const L7_PARSE_TIME_BUCKETS: [u64; 7] = [1000, 5000, 10000, 50000, 100000, 1000000, 10000000];
+
 #[rustfmt::skip]
-#[derive(Default)]
 pub struct FlowMapCounter {
     new: AtomicU64,                      // the number of created flow
     closed: AtomicU64,                   // the number of closed flow
@@ -2381,6 +2605,35 @@ pub struct FlowMapCounter {
     time_set_shrinks: AtomicU64,         // the total number of time_set HashSet shrinks
     pub l7_perf_cache_len: AtomicU64,    // the number of struct L7PerfCache::rrt_cache length
     pub l7_timeout_cache_len: AtomicU64, // the number of struct L7PerfCache::timeout_cache length
+    capture_point_suppressed: AtomicU64, // output flow excluded from metrics by capture point dedup
+    evict_by_capacity: AtomicU64,        // the number of flow evicted to make room for new flow
+    evict_before_fin: AtomicU64,         // the number of tcp flow evicted or timed out before FIN/RST
+    pub l7_parse_time: Histogram,        // time of parsing one l7 payload in ns
+}
+
+impl Default for FlowMapCounter {
+    fn default() -> Self {
+        Self {
+            new: Default::default(),
+            closed: Default::default(),
+            drop_by_window: Default::default(),
+            drop_by_capacity: Default::default(),
+            packet_delay: Default::default(),
+            flush_delay: Default::default(),
+            flow_delay: Default::default(),
+            concurrent: Default::default(),
+            slots: Default::default(),
+            slot_max_depth: Default::default(),
+            total_scan: Default::default(),
+            time_set_shrinks: Default::default(),
+            l7_perf_cache_len: Default::default(),
+            l7_timeout_cache_len: Default::default(),
+            capture_point_suppressed: Default::default(),
+            evict_by_capacity: Default::default(),
+            evict_before_fin: Default::default(),
+            l7_parse_time: Histogram::new("l7_parse_time", &L7_PARSE_TIME_BUCKETS),
+        }
+    }
 }
 
 impl RefCountable for FlowMapCounter {
@@ -2388,7 +2641,7 @@ impl RefCountable for FlowMapCounter {
         let concurrent = self.concurrent.load(Ordering::Relaxed);
         let slots = self.slots.swap(0, Ordering::Relaxed);
 
-        vec![
+        let mut counters = vec![
             (
                 "new",
                 CounterType::Gauged,
@@ -2455,7 +2708,24 @@ impl RefCountable for FlowMapCounter {
                 CounterType::Gauged,
                 CounterValue::Unsigned(self.l7_timeout_cache_len.swap(0, Ordering::Relaxed)),
             ),
-        ]
+            (
+                "capture_point_suppressed",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.capture_point_suppressed.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "evict_by_capacity",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.evict_by_capacity.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "evict_before_fin",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.evict_before_fin.swap(0, Ordering::Relaxed)),
+            ),
+        ];
+        counters.append(&mut self.l7_parse_time.get_counters());
+        counters
     }
 }
 
@@ -2509,6 +2779,8 @@ pub fn _new_flow_map_and_receiver(
         &config.flow,
         Some(packet_sequence_queue), // Enterprise Edition Feature: packet-sequence
         Arc::new(stats::Collector::new("", Arc::new(AtomicI64::new(0)))),
+        None,
+        None,
         false,
     );
 
@@ -2556,6 +2828,7 @@ pub fn _new_meta_packet<'a>() -> MetaPacket<'a> {
 
             l2_epc_id: EPC_DEEPFLOW,
             l3_epc_id: 1,
+            tenant_id: 0,
         },
         dst_info: EndpointInfo {
             real_ip: Ipv4Addr::UNSPECIFIED.into(),
@@ -2569,6 +2842,7 @@ pub fn _new_meta_packet<'a>() -> MetaPacket<'a> {
 
             l2_epc_id: EPC_DEEPFLOW,
             l3_epc_id: EPC_INTERNET,
+            tenant_id: 0,
         },
     })));
     packet
@@ -3130,6 +3404,8 @@ mod tests {
                 exception: Timestamp::from_secs(5),
                 closed_fin: Timestamp::ZERO,
                 single_direction: Timestamp::from_millis(10),
+                udp: Timestamp::ZERO,
+                other: Timestamp::from_secs(30),
                 max: Timestamp::from_secs(300),
                 min: Timestamp::ZERO,
             }),
@@ -3367,6 +3643,53 @@ mod tests {
         );
     }
 
+    #[test]
+    fn evict_by_capacity() {
+        for policy in [FlowEvictionPolicy::TimeoutOnly, FlowEvictionPolicy::Lru] {
+            let (mut module_config, mut flow_map, _) =
+                _new_flow_map_and_receiver(TridentType::TtProcess, None, false);
+            module_config.flow.eviction_policy = policy;
+            flow_map.capacity = 1;
+            let config = Config {
+                flow: &module_config.flow,
+                log_parser: &module_config.log_parser,
+                collector: &module_config.collector,
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                ebpf: None,
+            };
+
+            let mut packet0 = _new_meta_packet();
+            let timestamp: Duration = packet0.lookup_key.timestamp.into();
+            flow_map.inject_meta_packet(&config, &mut packet0);
+            // packet0's flow becomes idle after flush
+            flow_map.inject_flush_ticker(&config, timestamp + Duration::from_secs(3));
+
+            let mut packet1 = _new_meta_packet();
+            packet1.lookup_key.src_port += 1;
+            packet1.lookup_key.timestamp = (timestamp + Duration::from_secs(3)).into();
+            flow_map.inject_meta_packet(&config, &mut packet1);
+
+            let counter = &flow_map.stats_counter;
+            assert_eq!(flow_map.size, 1);
+            match policy {
+                FlowEvictionPolicy::TimeoutOnly => {
+                    assert_eq!(counter.drop_by_capacity.load(Ordering::Relaxed), 1);
+                    assert_eq!(counter.evict_by_capacity.load(Ordering::Relaxed), 0);
+                }
+                FlowEvictionPolicy::Lru => {
+                    assert_eq!(counter.drop_by_capacity.load(Ordering::Relaxed), 0);
+                    assert_eq!(counter.evict_by_capacity.load(Ordering::Relaxed), 1);
+                    assert_eq!(counter.evict_before_fin.load(Ordering::Relaxed), 1);
+                    let (node_map, _) = flow_map.node_map.as_ref().unwrap();
+                    let nodes = node_map.values().flatten().collect::<Vec<_>>();
+                    assert_eq!(nodes.len(), 1);
+                    let flow_key = &nodes[0].tagged_flow.flow.flow_key;
+                    assert!(flow_key.port_src == 12346 || flow_key.port_dst == 12346);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_handshake_retrans() {
         let (module_config, mut flow_map, output_queue_receiver) =
@@ -14,9 +14,12 @@
  * limitations under the License.
  */
 
-use std::{net::IpAddr, sync::Arc};
+use std::{mem, net::IpAddr, sync::Arc};
 
-use super::{perf::FlowLog, FlowState, FLOW_METRICS_PEER_DST, FLOW_METRICS_PEER_SRC};
+use super::{
+    perf::FlowLog, tls_handshake::TlsHandshake, FlowState, FLOW_METRICS_PEER_DST,
+    FLOW_METRICS_PEER_SRC,
+};
 use crate::common::{
     decapsulate::TunnelType,
     endpoint::EndpointDataPov,
@@ -142,9 +145,16 @@ pub struct FlowNode {
 
     // Enterprise Edition Feature: packet-sequence
     pub packet_sequence_block: Option<Box<PacketSequenceBlock>>,
+
+    // Only for TLS flows with handshake tags enabled
+    pub tls_handshake: Option<Box<TlsHandshake>>,
 }
 
 impl FlowNode {
+    // approximate memory used by a flow node with flow log, for deriving flow count limit
+    // from memory budget
+    pub const ESTIMATED_SIZE: usize = mem::size_of::<FlowNode>() + mem::size_of::<FlowLog>();
+
     pub(super) fn reset_flow_stat_info(&mut self) {
         self.policy_in_tick = [false; 2];
         self.packet_in_tick = false;
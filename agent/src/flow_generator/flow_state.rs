@@ -795,6 +795,7 @@ mod tests {
                         real_ip: Ipv4Addr::UNSPECIFIED.into(),
                         l2_epc_id: 0,
                         l3_epc_id: 0,
+                        tenant_id: 0,
                         l2_end: false,
                         l3_end: false,
                         is_device: false,
@@ -807,6 +808,7 @@ mod tests {
                         real_ip: Ipv4Addr::UNSPECIFIED.into(),
                         l2_epc_id: 0,
                         l3_epc_id: 0,
+                        tenant_id: 0,
                         l2_end: false,
                         l3_end: false,
                         is_device: false,
@@ -864,6 +866,7 @@ mod tests {
                         real_ip: Ipv4Addr::UNSPECIFIED.into(),
                         l2_epc_id: 0,
                         l3_epc_id: 0,
+                        tenant_id: 0,
                         l2_end: false,
                         l3_end: false,
                         is_device: false,
@@ -876,6 +879,7 @@ mod tests {
                         real_ip: Ipv4Addr::UNSPECIFIED.into(),
                         l2_epc_id: 0,
                         l3_epc_id: 0,
+                        tenant_id: 0,
                         l2_end: false,
                         l3_end: false,
                         is_device: false,
@@ -935,6 +939,7 @@ mod tests {
                 real_ip: Ipv4Addr::UNSPECIFIED.into(),
                 l2_epc_id: EPC_DEEPFLOW,
                 l3_epc_id: 1,
+                tenant_id: 0,
                 l2_end: false,
                 l3_end: false,
                 is_device: false,
@@ -947,6 +952,7 @@ mod tests {
                 real_ip: Ipv4Addr::UNSPECIFIED.into(),
                 l2_epc_id: EPC_DEEPFLOW,
                 l3_epc_id: EPC_INTERNET,
+                tenant_id: 0,
                 l2_end: false,
                 l3_end: false,
                 is_device: false,
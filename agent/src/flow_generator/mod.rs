@@ -15,6 +15,7 @@
  */
 
 mod app_table;
+pub mod capture_point_dedup;
 mod error;
 mod flow_config;
 pub mod flow_map;
@@ -25,7 +26,9 @@ pub mod perf;
 mod pool;
 pub mod protocol_logs;
 mod service_table;
+pub mod tls_handshake;
 
+pub use capture_point_dedup::CapturePointDedup;
 pub use error::{Error, Result};
 pub use flow_config::{FlowTimeout, TcpTimeout};
 pub use flow_map::FlowMap;
@@ -23,8 +23,8 @@ use pnet::packet::{
 
 use crate::{
     common::{
-        flow::{FlowPerfStats, L4Protocol},
-        meta_packet::{MetaPacket, ProtocolData},
+        flow::{FlowPerfStats, IcmpError, IcmpPerfStats, L4Protocol},
+        meta_packet::{IcmpData, MetaPacket, ProtocolData},
         Timestamp,
     },
     flow_generator::error::{Error, Result},
@@ -34,6 +34,26 @@ use super::{L4FlowPerf, ART_MAX};
 
 const MAX_CACHE_COUNT: usize = 16;
 
+// ICMPv4 destination unreachable codes
+const ICMP_NET_UNREACHABLE: u8 = 0;
+const ICMP_HOST_UNREACHABLE: u8 = 1;
+const ICMP_PROTOCOL_UNREACHABLE: u8 = 2;
+const ICMP_PORT_UNREACHABLE: u8 = 3;
+const ICMP_FRAGMENTATION_NEEDED: u8 = 4;
+const ICMP_NET_UNKNOWN: u8 = 6;
+const ICMP_HOST_UNKNOWN: u8 = 7;
+const ICMP_NET_ADMIN_PROHIBITED: u8 = 9;
+const ICMP_HOST_ADMIN_PROHIBITED: u8 = 10;
+const ICMP_ADMIN_PROHIBITED: u8 = 13;
+
+// ICMPv6 destination unreachable codes
+const ICMPV6_NO_ROUTE: u8 = 0;
+const ICMPV6_ADMIN_PROHIBITED: u8 = 1;
+const ICMPV6_ADDRESS_UNREACHABLE: u8 = 3;
+const ICMPV6_PORT_UNREACHABLE: u8 = 4;
+const ICMPV6_POLICY_FAILED: u8 = 5;
+const ICMPV6_REJECT_ROUTE: u8 = 6;
+
 #[derive(Debug)]
 struct LastIcmp {
     timestamp: Timestamp,
@@ -47,6 +67,7 @@ pub struct IcmpPerf {
     srt_count: u32,
     last_requests: VecDeque<LastIcmp>,
     last_replies: VecDeque<LastIcmp>,
+    stats: IcmpPerfStats,
     data_update_flag: bool,
 }
 
@@ -68,20 +89,73 @@ impl IcmpPerf {
         self.srt_max = Timestamp::default();
         self.srt_sum = Timestamp::default();
         self.srt_count = 0;
+        self.stats = IcmpPerfStats::default();
         self.data_update_flag = false;
     }
+
+    fn classify(&mut self, icmp_data: &IcmpData, is_ipv6: bool) {
+        let stats = &mut self.stats;
+        let counter = if is_ipv6 {
+            match Icmpv6Type::new(icmp_data.icmp_type) {
+                Icmpv6Types::EchoRequest => &mut stats.echo_request,
+                Icmpv6Types::EchoReply => &mut stats.echo_reply,
+                Icmpv6Types::DestinationUnreachable => match icmp_data.icmp_code {
+                    ICMPV6_NO_ROUTE => &mut stats.net_unreachable,
+                    ICMPV6_ADDRESS_UNREACHABLE => &mut stats.host_unreachable,
+                    ICMPV6_PORT_UNREACHABLE => &mut stats.port_unreachable,
+                    ICMPV6_ADMIN_PROHIBITED | ICMPV6_POLICY_FAILED | ICMPV6_REJECT_ROUTE => {
+                        &mut stats.admin_prohibited
+                    }
+                    _ => &mut stats.other_unreachable,
+                },
+                Icmpv6Types::PacketTooBig => &mut stats.fragmentation_needed,
+                Icmpv6Types::TimeExceeded => &mut stats.time_exceeded,
+                Icmpv6Types::Redirect => &mut stats.redirect,
+                _ => &mut stats.other,
+            }
+        } else {
+            match IcmpType::new(icmp_data.icmp_type) {
+                IcmpTypes::EchoRequest => &mut stats.echo_request,
+                IcmpTypes::EchoReply => &mut stats.echo_reply,
+                IcmpTypes::DestinationUnreachable => match icmp_data.icmp_code {
+                    ICMP_NET_UNREACHABLE | ICMP_NET_UNKNOWN => &mut stats.net_unreachable,
+                    ICMP_HOST_UNREACHABLE | ICMP_HOST_UNKNOWN => &mut stats.host_unreachable,
+                    ICMP_PROTOCOL_UNREACHABLE => &mut stats.protocol_unreachable,
+                    ICMP_PORT_UNREACHABLE => &mut stats.port_unreachable,
+                    ICMP_FRAGMENTATION_NEEDED => &mut stats.fragmentation_needed,
+                    ICMP_NET_ADMIN_PROHIBITED
+                    | ICMP_HOST_ADMIN_PROHIBITED
+                    | ICMP_ADMIN_PROHIBITED => &mut stats.admin_prohibited,
+                    _ => &mut stats.other_unreachable,
+                },
+                IcmpTypes::TimeExceeded => &mut stats.time_exceeded,
+                IcmpTypes::RedirectMessage => &mut stats.redirect,
+                _ => &mut stats.other,
+            }
+        };
+        *counter += 1;
+        if IcmpData::is_error(icmp_data.icmp_type, is_ipv6) {
+            stats.last_error = Some(IcmpError {
+                icmp_type: icmp_data.icmp_type,
+                icmp_code: icmp_data.icmp_code,
+                original: icmp_data.original,
+            });
+        }
+        self.data_update_flag = true;
+    }
 }
 
 impl L4FlowPerf for IcmpPerf {
     fn parse(&mut self, packet: &MetaPacket, _: bool) -> Result<()> {
-        if packet.payload_len == 0 {
-            return Err(Error::ZeroPayloadLen);
-        }
         let icmp_data = if let ProtocolData::IcmpData(icmp_data) = &packet.protocol_data {
             icmp_data
         } else {
             return Err(Error::InvalidIpProtocol);
         };
+        self.classify(icmp_data, !packet.lookup_key.is_ipv4());
+        if packet.payload_len == 0 {
+            return Err(Error::ZeroPayloadLen);
+        }
         let pkt_timestamp = packet.lookup_key.timestamp;
         let (is_request, is_reply) = if packet.lookup_key.is_ipv4() {
             let icmp_type = IcmpType::new(icmp_data.icmp_type);
@@ -174,6 +248,7 @@ impl L4FlowPerf for IcmpPerf {
         stats.tcp.srt_max = (self.srt_max.as_nanos() / Timestamp::from_micros(1).as_nanos()) as u32;
         stats.tcp.srt_sum = (self.srt_sum.as_nanos() / Timestamp::from_micros(1).as_nanos()) as u32;
         stats.tcp.srt_count = self.srt_count;
+        stats.icmp = self.stats.clone();
         self.reset();
 
         stats
@@ -25,6 +25,7 @@ use std::rc::Rc;
 use std::slice;
 use std::sync::atomic::{AtomicI64, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
 use enum_dispatch::enum_dispatch;
 use public::bitmap::Bitmap;
@@ -267,6 +268,7 @@ impl FlowLog {
                 parser.set_obfuscate_cache(self.obfuscate_cache.as_ref().map(|o| o.clone()));
             }
 
+            let start = Instant::now();
             let ret = parser.parse_payload(
                 {
                     let pkt_size = flow_config.l7_log_packet_size as usize;
@@ -278,6 +280,9 @@ impl FlowLog {
                 },
                 &parse_param,
             );
+            self.stats_counter
+                .l7_parse_time
+                .observe(start.elapsed().as_nanos() as u64);
 
             let mut cache_proto = |proto: L7ProtocolEnum| match packet.signal_source {
                 SignalSource::EBPF => {
@@ -503,17 +508,20 @@ impl FlowLog {
         last_time: Option<u64>,
         ntp_diff: Arc<AtomicI64>,
         obfuscate_cache: Option<ObfuscateCache>,
+        tcp_histogram_enabled: bool,
     ) -> Option<Self> {
         if !l4_enabled && !l7_enabled {
             return None;
         }
         let l4 = if l4_enabled {
             match l4_proto {
-                L4Protocol::Tcp => Some(L4FlowPerfTable::Tcp(
-                    tcp_perf_pool
+                L4Protocol::Tcp => {
+                    let mut perf = tcp_perf_pool
                         .get()
-                        .unwrap_or_else(|| Box::new(TcpPerf::new(counter))),
-                )),
+                        .unwrap_or_else(|| Box::new(TcpPerf::new(counter)));
+                    perf.set_histogram_enabled(tcp_histogram_enabled);
+                    Some(L4FlowPerfTable::Tcp(perf))
+                }
                 L4Protocol::Udp => Some(L4FlowPerfTable::Udp(UdpPerf::new())),
                 L4Protocol::Icmp => Some(L4FlowPerfTable::Icmp(IcmpPerf::new())),
                 _ => None,
@@ -597,6 +605,9 @@ impl FlowLog {
                 let flow_perf_stats = l4.copy_and_reset_data(flow_reversed);
                 flow.flow_perf_stats.as_mut().unwrap().l4_protocol = flow_perf_stats.l4_protocol;
                 flow.flow_perf_stats.as_mut().unwrap().tcp = flow_perf_stats.tcp;
+                flow.flow_perf_stats.as_mut().unwrap().icmp = flow_perf_stats.icmp;
+                flow.flow_perf_stats.as_mut().unwrap().tcp_histograms =
+                    flow_perf_stats.tcp_histograms;
             }
         }
     }
@@ -25,7 +25,7 @@ use super::{FlowPerfCounter, L4FlowPerf, ART_MAX};
 use crate::{
     common::{
         enums::TcpFlags,
-        flow::{FlowPerfStats, L4Protocol},
+        flow::{FlowPerfStats, L4Protocol, TcpHistograms},
         lookup_key::LookupKey,
         meta_packet::{MetaPacket, MetaPacketTcpHeader, ProtocolData},
         Timestamp,
@@ -597,11 +597,43 @@ impl PerfData {
     }
 }
 
+#[derive(Default)]
+struct HistogramData {
+    histograms: TcpHistograms,
+    // start of the current zero window, zero if the window is open
+    zero_win_since: [Timestamp; 2],
+}
+
+impl HistogramData {
+    fn record(h: &mut Option<Box<Self>>, f: impl FnOnce(&mut TcpHistograms)) {
+        if let Some(h) = h.as_mut() {
+            f(&mut h.histograms);
+        }
+    }
+
+    fn update_zero_win(&mut self, timestamp: Timestamp, zero_win: bool, fpd: bool) {
+        let since = &mut self.zero_win_since[!fpd as usize];
+        if zero_win {
+            if since.is_zero() {
+                *since = timestamp;
+            }
+        } else if !since.is_zero() {
+            if timestamp > *since {
+                self.histograms
+                    .zero_win_duration
+                    .record((timestamp - *since).as_micros() as u32);
+            }
+            *since = Timestamp::ZERO;
+        }
+    }
+}
+
 pub struct TcpPerf {
     ctrl_info: PerfControl,
     perf_data: PerfData,
     counter: Arc<FlowPerfCounter>,
     handshaking: bool,
+    histogram_data: Option<Box<HistogramData>>,
 }
 
 impl TcpPerf {
@@ -611,6 +643,7 @@ impl TcpPerf {
             perf_data: Default::default(),
             counter,
             handshaking: false,
+            histogram_data: None,
         }
     }
 
@@ -618,6 +651,17 @@ impl TcpPerf {
         self.ctrl_info = Default::default();
         self.perf_data = Default::default();
         self.handshaking = false;
+        if let Some(h) = self.histogram_data.as_mut() {
+            **h = Default::default();
+        }
+    }
+
+    pub fn set_histogram_enabled(&mut self, enabled: bool) {
+        match (enabled, self.histogram_data.is_some()) {
+            (true, false) => self.histogram_data = Some(Default::default()),
+            (false, true) => self.histogram_data = None,
+            _ => (),
+        }
     }
 
     // fpd for first packet direction
@@ -681,6 +725,12 @@ impl TcpPerf {
             PacketSeqType::Retrans => {
                 // established retrans
                 self.perf_data.calc_retrans(fpd);
+                if p.lookup_key.timestamp > same_dir.timestamp && !same_dir.timestamp.is_zero() {
+                    let delay = p.lookup_key.timestamp - same_dir.timestamp;
+                    HistogramData::record(&mut self.histogram_data, |h| {
+                        h.retrans_delay.record(delay.as_micros() as u32)
+                    });
+                }
                 (false, true)
             }
             PacketSeqType::Error => {
@@ -763,6 +813,9 @@ impl TcpPerf {
                     );
                     if !rtt.is_zero() {
                         self.perf_data.calc_rtt(rtt, fpd);
+                        HistogramData::record(&mut self.histogram_data, |h| {
+                            h.rtt.record(rtt.as_micros() as u32)
+                        });
                     }
                 }
             }
@@ -836,6 +889,9 @@ impl TcpPerf {
                 );
                 if !srt.is_zero() {
                     self.perf_data.calc_srt(srt, fpd);
+                    HistogramData::record(&mut self.histogram_data, |h| {
+                        h.rtt.record(srt.as_micros() as u32)
+                    });
                 }
             }
         }
@@ -888,6 +944,9 @@ impl TcpPerf {
         if win_size == 0 {
             self.perf_data.calc_zero_win(fpd);
         }
+        if let Some(h) = self.histogram_data.as_mut() {
+            h.update_zero_win(p.lookup_key.timestamp, win_size == 0, fpd);
+        }
 
         // PSH/URG
         if tcp_data.flags & TcpFlags::MASK == TcpFlags::PSH_ACK_URG {
@@ -1041,6 +1100,11 @@ impl L4FlowPerf for TcpPerf {
         stats.l4_protocol = L4Protocol::Tcp;
         self.perf_data.update_perf_stats(&mut stats, flow_reversed);
         self.perf_data = Default::default();
+        if let Some(h) = self.histogram_data.as_mut() {
+            if !h.histograms.is_empty() {
+                stats.tcp_histograms = Some(Box::new(std::mem::take(&mut h.histograms)));
+            }
+        }
         stats
     }
 }
@@ -1887,6 +1951,19 @@ mod tests {
         assert_eq!(perf.perf_data, perf_data);
     }
 
+    #[test]
+    fn zero_win_histogram() {
+        let mut h = HistogramData::default();
+        h.update_zero_win(Timestamp::from_micros(100), true, true);
+        h.update_zero_win(Timestamp::from_micros(150), true, true);
+        h.update_zero_win(Timestamp::from_micros(200), false, false);
+        assert!(h.histograms.zero_win_duration.is_empty());
+        h.update_zero_win(Timestamp::from_micros(300), false, true);
+        assert_eq!(h.histograms.zero_win_duration.count(), 1);
+        assert_eq!(h.histograms.zero_win_duration.quantile(1.0), Some(223));
+        assert!(h.zero_win_since[0].is_zero());
+    }
+
     #[test]
     fn report() {
         let pcap_file = Path::new(FILE_DIR).join("art-continues-payload-len-larger-than-1.pcap");
@@ -111,6 +111,10 @@ impl L7ProtocolInfoInterface for DnsInfo {
     fn is_on_blacklist(&self) -> bool {
         self.is_on_blacklist
     }
+
+    fn get_response_status(&self) -> L7ResponseStatus {
+        self.status
+    }
 }
 
 impl DnsInfo {
@@ -157,6 +157,10 @@ impl L7ProtocolInfoInterface for FastCGIInfo {
     fn is_on_blacklist(&self) -> bool {
         self.is_on_blacklist
     }
+
+    fn get_response_status(&self) -> L7ResponseStatus {
+        self.status
+    }
 }
 
 impl FastCGIInfo {
@@ -185,6 +185,11 @@ pub struct AppProtoLogsBaseInfo {
     /* L3EpcID */
     pub l3_epc_id_src: i32,
     pub l3_epc_id_dst: i32,
+    /* Tenant of L3EpcID */
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub tenant_id_0: u32,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub tenant_id_1: u32,
     /* L4 */
     pub port_src: u16,
     pub port_dst: u16,
@@ -316,6 +321,8 @@ impl From<AppProtoLogsBaseInfo> for flow_log::AppProtoLogsBaseInfo {
             pod_id_0: f.pod_id_0,
             pod_id_1: f.pod_id_1,
             biz_type: f.biz_type as u32,
+            tenant_id_0: f.tenant_id_0,
+            tenant_id_1: f.tenant_id_1,
         }
     }
 }
@@ -408,6 +415,15 @@ impl Sendable for BoxAppProtoLogsData {
         "l7_flow_log"
     }
 
+    fn tenant_id(&self) -> u32 {
+        let base = &self.0.base_info;
+        if base.tenant_id_0 > 0 {
+            base.tenant_id_0
+        } else {
+            base.tenant_id_1
+        }
+    }
+
     fn message_type(&self) -> SendMessageType {
         SendMessageType::ProtocolLog
     }
@@ -428,7 +444,7 @@ impl fmt::Display for AppProtoLogsBaseInfo {
         write!(
             f,
             "Timestamp: {:?} Vtap_id: {} Flow_id: {} TapType: {} TapPort: {} TapSide: {:?}\n \
-                \t{}_{}_{} -> {}_{}_{} Proto: {:?} Seq: {} -> {} VIP: {} -> {} EPC: {} -> {}\n \
+                \t{}_{}_{} -> {}_{}_{} Proto: {:?} Seq: {} -> {} VIP: {} -> {} EPC: {} -> {} Tenant: {} -> {}\n \
                 \tProcess: {}:{} -> {}:{} Trace-id: {} -> {} Thread: {} -> {} cap_seq: {} -> {}\n \
                 \tL7Protocol: {:?} MsgType: {:?} Rrt: {}",
             self.start_time,
@@ -450,6 +466,8 @@ impl fmt::Display for AppProtoLogsBaseInfo {
             self.is_vip_interface_dst,
             self.l3_epc_id_src,
             self.l3_epc_id_dst,
+            self.tenant_id_0,
+            self.tenant_id_1,
             self.process_kname_0,
             self.process_id_0,
             self.process_kname_1,
@@ -143,6 +143,10 @@ impl L7ProtocolInfoInterface for MqttInfo {
     fn is_on_blacklist(&self) -> bool {
         self.is_on_blacklist
     }
+
+    fn get_response_status(&self) -> L7ResponseStatus {
+        self.status
+    }
 }
 
 pub fn topics_format<S>(t: &Option<Vec<MqttTopic>>, serializer: S) -> Result<S::Ok, S::Error>
@@ -1777,6 +1777,10 @@ impl L7ProtocolInfoInterface for OpenWireInfo {
     fn is_on_blacklist(&self) -> bool {
         self.is_on_blacklist
     }
+
+    fn get_response_status(&self) -> L7ResponseStatus {
+        self.status
+    }
 }
 
 impl From<OpenWireInfo> for L7ProtocolSendLog {
@@ -839,6 +839,10 @@ impl L7ProtocolInfoInterface for PulsarInfo {
     fn is_on_blacklist(&self) -> bool {
         self.is_on_blacklist
     }
+
+    fn get_response_status(&self) -> L7ResponseStatus {
+        self.resp_status.unwrap_or_default()
+    }
 }
 
 impl L7ProtocolParserInterface for PulsarLog {
@@ -258,6 +258,10 @@ impl L7ProtocolInfoInterface for ZmtpInfo {
     fn is_on_blacklist(&self) -> bool {
         self.is_on_blacklist
     }
+
+    fn get_response_status(&self) -> L7ResponseStatus {
+        self.status
+    }
 }
 
 #[derive(Default)]
@@ -33,7 +33,9 @@ use lru::LruCache;
 use rand::prelude::{Rng, SeedableRng, SmallRng};
 use serde::Serialize;
 
-use super::{AppProtoHead, AppProtoLogsBaseInfo, BoxAppProtoLogsData, LogMessageType};
+use super::{
+    AppProtoHead, AppProtoLogsBaseInfo, BoxAppProtoLogsData, L7ResponseStatus, LogMessageType,
+};
 
 use crate::{
     common::{
@@ -43,6 +45,7 @@ use crate::{
         MetaPacket, TaggedFlow, Timestamp,
     },
     config::handler::LogParserAccess,
+    debug,
     flow_generator::{error::Result, FLOW_METRICS_PEER_DST, FLOW_METRICS_PEER_SRC},
     metric::document::TapSide,
     rpc::get_timestamp,
@@ -173,6 +176,8 @@ impl MetaAppProto {
             port_dst: flow.flow.flow_metrics_peers[FLOW_METRICS_PEER_DST].nat_real_port,
             l3_epc_id_src: flow.flow.flow_metrics_peers[FLOW_METRICS_PEER_SRC].l3_epc_id,
             l3_epc_id_dst: flow.flow.flow_metrics_peers[FLOW_METRICS_PEER_DST].l3_epc_id,
+            tenant_id_0: flow.flow.flow_metrics_peers[FLOW_METRICS_PEER_SRC].tenant_id,
+            tenant_id_1: flow.flow.flow_metrics_peers[FLOW_METRICS_PEER_DST].tenant_id,
             req_tcp_seq: 0,
             resp_tcp_seq: 0,
             process_id_0: 0,
@@ -313,6 +318,7 @@ pub struct SessionAggrCounter {
     cached: AtomicU64, // It is used to record the number of logs that exist in session queue
     cached_request_resource: AtomicU64, // It is used to record the cache request-resource occupation space, the unit is B
     throttle_drop: AtomicU64,
+    backpressure_drop: AtomicU64, // It is used to record the number of logs dropped when the sender queue is congested
     over_limit: AtomicU64, // It is used to record the number of logs that exceed the limit to the forced flush
 }
 
@@ -349,6 +355,11 @@ impl RefCountable for SessionAggrCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(self.throttle_drop.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "backpressure-drop",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.backpressure_drop.swap(0, Ordering::Relaxed)),
+            ),
             (
                 "over-limit",
                 CounterType::Counted,
@@ -788,11 +799,32 @@ impl SessionQueue {
             return;
         }
 
+        let backpressure = &self.config.load().backpressure;
+        if backpressure.enabled()
+            && self.output_queue.above_watermark(backpressure.watermark)
+            && !Self::is_valuable(&item, backpressure.slow_request_threshold_us())
+        {
+            self.counter
+                .backpressure_drop
+                .fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        debug::record_l7_log(&item);
+
         if let Err(e) = self.output_queue.send(BoxAppProtoLogsData(item)) {
             warn!("output queue failed to send data, because: {:?}", e);
         }
     }
 
+    // errors and slow requests are kept even if the sender queue is congested
+    fn is_valuable(item: &MetaAppProto, slow_request_threshold_us: u32) -> bool {
+        match item.l7_info.get_response_status() {
+            L7ResponseStatus::ServerError | L7ResponseStatus::ClientError => true,
+            _ => item.base_info.head.rrt >= slow_request_threshold_us as u64,
+        }
+    }
+
     fn send_all(&mut self, items: Vec<Box<MetaAppProto>>) {
         for item in items {
             self.send(item);
@@ -309,6 +309,10 @@ impl L7ProtocolInfoInterface for DubboInfo {
     fn is_on_blacklist(&self) -> bool {
         self.is_on_blacklist
     }
+
+    fn get_response_status(&self) -> L7ResponseStatus {
+        self.resp_status
+    }
 }
 
 impl From<DubboInfo> for L7ProtocolSendLog {
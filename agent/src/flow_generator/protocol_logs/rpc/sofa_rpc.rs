@@ -263,6 +263,10 @@ impl L7ProtocolInfoInterface for SofaRpcInfo {
     fn is_on_blacklist(&self) -> bool {
         self.is_on_blacklist
     }
+
+    fn get_response_status(&self) -> L7ResponseStatus {
+        self.status
+    }
 }
 
 impl From<SofaRpcInfo> for L7ProtocolSendLog {
@@ -149,6 +149,10 @@ impl L7ProtocolInfoInterface for OracleInfo {
     fn is_on_blacklist(&self) -> bool {
         self.is_on_blacklist
     }
+
+    fn get_response_status(&self) -> L7ResponseStatus {
+        self.status
+    }
 }
 
 impl From<OracleInfo> for L7ProtocolSendLog {
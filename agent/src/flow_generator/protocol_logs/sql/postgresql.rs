@@ -158,6 +158,10 @@ impl L7ProtocolInfoInterface for PostgreInfo {
     fn get_request_resource_length(&self) -> usize {
         self.context.len()
     }
+
+    fn get_response_status(&self) -> L7ResponseStatus {
+        self.status
+    }
 }
 
 impl From<PostgreInfo> for L7ProtocolSendLog {
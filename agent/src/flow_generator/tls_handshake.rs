@@ -0,0 +1,533 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Lightweight TLS handshake inspection for L4 flow logs
+//
+// Only the ClientHello server_name extension and the subject/issuer of the leaf
+// certificate are extracted, without depending on the l7 TLS parser. Certificates
+// are encrypted since TLS 1.3, so only the SNI is available for TLS 1.3 flows.
+
+use std::mem;
+
+const CONTENT_TYPE_HANDSHAKE: u8 = 22;
+const RECORD_HEADER_SIZE: usize = 5;
+const HANDSHAKE_HEADER_SIZE: usize = 4;
+
+const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+const HANDSHAKE_CERTIFICATE: u8 = 11;
+
+const EXTENSION_SERVER_NAME: u16 = 0;
+const SERVER_NAME_TYPE_HOST: u8 = 0;
+
+const DER_INTEGER: u8 = 0x02;
+const DER_OID: u8 = 0x06;
+const DER_SEQUENCE: u8 = 0x30;
+const DER_SET: u8 = 0x31;
+const DER_BMP_STRING: u8 = 0x1E;
+const DER_VERSION: u8 = 0xA0;
+
+// OID 2.5.4.x, attribute types of X.520 names
+const OID_ATTRIBUTE_TYPE: [u8; 2] = [0x55, 0x04];
+
+// Payload packets of each direction inspected before giving up
+const MAX_PACKETS: u8 = 8;
+const MAX_BUFFER_SIZE: usize = 16384;
+
+#[derive(Debug, PartialEq)]
+enum ParseResult<T> {
+    Complete(T),
+    // More data is required
+    Incomplete,
+    Invalid,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TlsTag {
+    ServerName(String),
+    Certificate { subject: String, issuer: String },
+}
+
+pub fn is_tls_handshake(payload: &[u8]) -> bool {
+    payload.len() > 2 && payload[0] == CONTENT_TYPE_HANDSHAKE && payload[1] == 3
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u24(&mut self) -> Option<usize> {
+        self.bytes(3)
+            .map(|b| ((b[0] as usize) << 16) | ((b[1] as usize) << 8) | b[2] as usize)
+    }
+
+    fn u8_prefixed(&mut self) -> Option<&'a [u8]> {
+        let n = self.u8()? as usize;
+        self.bytes(n)
+    }
+
+    fn u16_prefixed(&mut self) -> Option<&'a [u8]> {
+        let n = self.u16()? as usize;
+        self.bytes(n)
+    }
+}
+
+// Concatenate fragments of leading handshake records, returns the handshake
+// stream and whether it is truncated at the end of payload
+fn handshake_stream(payload: &[u8]) -> Option<(Vec<u8>, bool)> {
+    if !is_tls_handshake(payload) {
+        return None;
+    }
+    let mut stream = vec![];
+    let mut reader = Reader(payload);
+    while !reader.0.is_empty() {
+        let Some(header) = reader.bytes(RECORD_HEADER_SIZE) else {
+            return Some((stream, true));
+        };
+        if header[0] != CONTENT_TYPE_HANDSHAKE || header[1] != 3 {
+            return Some((stream, false));
+        }
+        let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+        match reader.bytes(len) {
+            Some(fragment) => stream.extend_from_slice(fragment),
+            None => {
+                stream.extend_from_slice(reader.0);
+                return Some((stream, true));
+            }
+        }
+    }
+    Some((stream, true))
+}
+
+// Find the handshake message of msg_type, the body returned may be truncated
+fn find_message(stream: &[u8], msg_type: u8) -> Option<&[u8]> {
+    let mut reader = Reader(stream);
+    while let Some(header) = reader.bytes(HANDSHAKE_HEADER_SIZE) {
+        let len = ((header[1] as usize) << 16) | ((header[2] as usize) << 8) | header[3] as usize;
+        let body = reader.bytes(len);
+        if header[0] == msg_type {
+            return Some(body.unwrap_or(reader.0));
+        }
+        body?;
+    }
+    None
+}
+
+fn parse_message<T, F>(payload: &[u8], msg_type: u8, f: F) -> ParseResult<T>
+where
+    F: FnOnce(&[u8]) -> Option<T>,
+{
+    let Some((stream, truncated)) = handshake_stream(payload) else {
+        return ParseResult::Invalid;
+    };
+    match find_message(&stream, msg_type).and_then(f) {
+        Some(t) => ParseResult::Complete(t),
+        None if truncated => ParseResult::Incomplete,
+        None => ParseResult::Invalid,
+    }
+}
+
+fn client_hello_server_name(body: &[u8]) -> Option<Option<String>> {
+    let mut reader = Reader(body);
+    // version and random
+    reader.bytes(2 + 32)?;
+    reader.u8_prefixed()?; // session id
+    reader.u16_prefixed()?; // cipher suites
+    reader.u8_prefixed()?; // compression methods
+    if reader.0.is_empty() {
+        // no extensions
+        return Some(None);
+    }
+    let mut extensions = Reader(reader.u16_prefixed()?);
+    while !extensions.0.is_empty() {
+        let ext_type = extensions.u16()?;
+        let data = extensions.u16_prefixed()?;
+        if ext_type != EXTENSION_SERVER_NAME {
+            continue;
+        }
+        let mut names = Reader(Reader(data).u16_prefixed()?);
+        while !names.0.is_empty() {
+            let name_type = names.u8()?;
+            let name = names.u16_prefixed()?;
+            if name_type == SERVER_NAME_TYPE_HOST {
+                return Some(Some(String::from_utf8_lossy(name).into_owned()));
+            }
+        }
+        return Some(None);
+    }
+    Some(None)
+}
+
+// Read a DER element, returns tag, value and the rest. If partial is set, the
+// value is allowed to be truncated at the end of data.
+fn der_element(data: &[u8], partial: bool) -> Option<(u8, &[u8], &[u8])> {
+    let mut reader = Reader(data);
+    let tag = reader.u8()?;
+    let first = reader.u8()?;
+    let len = if first & 0x80 == 0 {
+        first as usize
+    } else {
+        let n = (first & 0x7F) as usize;
+        if n == 0 || n > mem::size_of::<u32>() {
+            return None;
+        }
+        reader
+            .bytes(n)?
+            .iter()
+            .fold(0, |len, b| (len << 8) | *b as usize)
+    };
+    match reader.bytes(len) {
+        Some(value) => Some((tag, value, reader.0)),
+        None if partial => Some((tag, reader.0, &[])),
+        None => None,
+    }
+}
+
+fn der_expect(data: &[u8], expected: u8, partial: bool) -> Option<(&[u8], &[u8])> {
+    match der_element(data, partial)? {
+        (tag, value, rest) if tag == expected => Some((value, rest)),
+        _ => None,
+    }
+}
+
+fn attribute_name(oid: &[u8]) -> Option<&'static str> {
+    if oid.len() != 3 || oid[..2] != OID_ATTRIBUTE_TYPE {
+        return None;
+    }
+    let name = match oid[2] {
+        3 => "CN",
+        6 => "C",
+        7 => "L",
+        8 => "ST",
+        10 => "O",
+        11 => "OU",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn attribute_value(tag: u8, value: &[u8]) -> String {
+    match tag {
+        DER_BMP_STRING => {
+            let units = value
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect::<Vec<_>>();
+            String::from_utf16_lossy(&units)
+        }
+        // UTF8String, PrintableString, IA5String and TeletexString
+        _ => String::from_utf8_lossy(value).into_owned(),
+    }
+}
+
+// Format X.501 Name in the order it is encoded, e.g. "C=US, O=Let's Encrypt, CN=R3"
+fn format_name(name: &[u8]) -> Option<String> {
+    let mut formatted = String::new();
+    let mut rdns = name;
+    while !rdns.is_empty() {
+        let (mut attributes, rest) = der_expect(rdns, DER_SET, false)?;
+        rdns = rest;
+        while !attributes.is_empty() {
+            let (attribute, rest) = der_expect(attributes, DER_SEQUENCE, false)?;
+            attributes = rest;
+            let (oid, value) = der_expect(attribute, DER_OID, false)?;
+            let (tag, value, _) = der_element(value, false)?;
+            let Some(attribute_name) = attribute_name(oid) else {
+                continue;
+            };
+            if !formatted.is_empty() {
+                formatted.push_str(", ");
+            }
+            formatted.push_str(attribute_name);
+            formatted.push('=');
+            formatted.push_str(&attribute_value(tag, value));
+        }
+    }
+    Some(formatted)
+}
+
+// Returns (subject, issuer) of the first certificate in Certificate message
+fn certificate_names(body: &[u8]) -> Option<(String, String)> {
+    let mut reader = Reader(body);
+    reader.u24()?; // certificate list length
+    reader.u24()?; // leaf certificate length
+
+    // the certificate may be truncated, only names in TBSCertificate are required
+    let (certificate, _) = der_expect(reader.0, DER_SEQUENCE, true)?;
+    let (tbs, _) = der_expect(certificate, DER_SEQUENCE, true)?;
+    let mut tbs = tbs;
+    if let Some((_, rest)) = der_expect(tbs, DER_VERSION, false) {
+        tbs = rest;
+    }
+    let (_, tbs) = der_expect(tbs, DER_INTEGER, false)?; // serial number
+    let (_, tbs) = der_expect(tbs, DER_SEQUENCE, false)?; // signature algorithm
+    let (issuer, tbs) = der_expect(tbs, DER_SEQUENCE, false)?;
+    let (_, tbs) = der_expect(tbs, DER_SEQUENCE, false)?; // validity
+    let (subject, _) = der_expect(tbs, DER_SEQUENCE, false)?;
+    Some((format_name(subject)?, format_name(issuer)?))
+}
+
+// Collects SNI from ClientHello and certificate names from server handshake
+// messages, which may span multiple TCP segments
+#[derive(Default)]
+pub struct TlsHandshake {
+    buffers: [Vec<u8>; 2],
+    packets: [u8; 2],
+    finished: [bool; 2],
+}
+
+impl TlsHandshake {
+    pub fn is_finished(&self) -> bool {
+        self.finished[0] && self.finished[1]
+    }
+
+    fn parse(from_client: bool, data: &[u8]) -> ParseResult<Option<TlsTag>> {
+        if from_client {
+            match parse_message(data, HANDSHAKE_CLIENT_HELLO, client_hello_server_name) {
+                ParseResult::Complete(name) => ParseResult::Complete(name.map(TlsTag::ServerName)),
+                ParseResult::Incomplete => ParseResult::Incomplete,
+                ParseResult::Invalid => ParseResult::Invalid,
+            }
+        } else {
+            match parse_message(data, HANDSHAKE_CERTIFICATE, certificate_names) {
+                ParseResult::Complete((subject, issuer)) => {
+                    ParseResult::Complete(Some(TlsTag::Certificate { subject, issuer }))
+                }
+                ParseResult::Incomplete => ParseResult::Incomplete,
+                ParseResult::Invalid => ParseResult::Invalid,
+            }
+        }
+    }
+
+    // Feed payload of one packet, returns tag parsed if any
+    pub fn update(&mut self, from_client: bool, payload: &[u8]) -> Option<TlsTag> {
+        let i = !from_client as usize;
+        if self.finished[i] || payload.is_empty() {
+            return None;
+        }
+        self.packets[i] += 1;
+
+        let buffer = &mut self.buffers[i];
+        let result = if buffer.is_empty() {
+            Self::parse(from_client, payload)
+        } else {
+            let n = payload.len().min(MAX_BUFFER_SIZE - buffer.len());
+            buffer.extend_from_slice(&payload[..n]);
+            Self::parse(from_client, buffer)
+        };
+        match result {
+            ParseResult::Incomplete
+                if self.packets[i] < MAX_PACKETS && buffer.len() < MAX_BUFFER_SIZE =>
+            {
+                if buffer.is_empty() {
+                    buffer.extend_from_slice(&payload[..payload.len().min(MAX_BUFFER_SIZE)]);
+                }
+                None
+            }
+            ParseResult::Complete(tag) => {
+                self.finish(i);
+                tag
+            }
+            ParseResult::Incomplete => {
+                self.finish(i);
+                None
+            }
+            ParseResult::Invalid => {
+                // no server handshake to expect if client is not speaking TLS
+                if from_client {
+                    self.finish(1);
+                }
+                self.finish(i);
+                None
+            }
+        }
+    }
+
+    fn finish(&mut self, i: usize) {
+        self.finished[i] = true;
+        self.buffers[i] = vec![];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(content_type: u8, fragment: &[u8]) -> Vec<u8> {
+        let mut r = vec![content_type, 3, 3];
+        r.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+        r.extend_from_slice(fragment);
+        r
+    }
+
+    fn handshake(msg_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut h = vec![msg_type];
+        h.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        h.extend_from_slice(body);
+        h
+    }
+
+    fn u16_prefixed(data: &[u8]) -> Vec<u8> {
+        let mut v = (data.len() as u16).to_be_bytes().to_vec();
+        v.extend_from_slice(data);
+        v
+    }
+
+    fn client_hello(server_name: Option<&str>) -> Vec<u8> {
+        let mut body = vec![3, 3];
+        body.extend_from_slice(&[0; 32]);
+        body.push(0); // session id
+        body.extend_from_slice(&u16_prefixed(&[0x13, 0x01, 0xC0, 0x2F]));
+        body.extend_from_slice(&[1, 0]);
+
+        let mut extensions = vec![];
+        // supported_groups
+        extensions.extend_from_slice(&[0, 10]);
+        extensions.extend_from_slice(&u16_prefixed(&u16_prefixed(&[0, 29])));
+        if let Some(name) = server_name {
+            let mut entry = vec![SERVER_NAME_TYPE_HOST];
+            entry.extend_from_slice(&u16_prefixed(name.as_bytes()));
+            extensions.extend_from_slice(&[0, 0]);
+            extensions.extend_from_slice(&u16_prefixed(&u16_prefixed(&entry)));
+        }
+        body.extend_from_slice(&u16_prefixed(&extensions));
+        record(
+            CONTENT_TYPE_HANDSHAKE,
+            &handshake(HANDSHAKE_CLIENT_HELLO, &body),
+        )
+    }
+
+    fn der(tag: u8, value: &[u8]) -> Vec<u8> {
+        let mut v = vec![tag];
+        if value.len() < 0x80 {
+            v.push(value.len() as u8);
+        } else {
+            v.push(0x82);
+            v.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        }
+        v.extend_from_slice(value);
+        v
+    }
+
+    fn name(attributes: &[(u8, &str)]) -> Vec<u8> {
+        let mut rdns = vec![];
+        for (t, value) in attributes {
+            let mut attribute = der(DER_OID, &[0x55, 0x04, *t]);
+            attribute.extend_from_slice(&der(0x13, value.as_bytes()));
+            rdns.extend_from_slice(&der(DER_SET, &der(DER_SEQUENCE, &attribute)));
+        }
+        der(DER_SEQUENCE, &rdns)
+    }
+
+    fn server_flight() -> Vec<u8> {
+        let mut tbs = der(DER_VERSION, &der(DER_INTEGER, &[2]));
+        tbs.extend_from_slice(&der(DER_INTEGER, &[0x12, 0x34]));
+        tbs.extend_from_slice(&der(DER_SEQUENCE, &der(DER_OID, &[0x2A, 0x86, 0x48])));
+        tbs.extend_from_slice(&name(&[(6, "US"), (10, "Let's Encrypt"), (3, "R3")]));
+        tbs.extend_from_slice(&der(DER_SEQUENCE, &[0; 30]));
+        tbs.extend_from_slice(&name(&[(3, "www.example.com")]));
+        // subject public key info and extensions
+        tbs.extend_from_slice(&der(DER_SEQUENCE, &[0; 300]));
+        let mut certificate = der(DER_SEQUENCE, &tbs);
+        certificate.extend_from_slice(&der(DER_SEQUENCE, &[0; 10]));
+        certificate.extend_from_slice(&der(0x03, &[0; 256]));
+        let certificate = der(DER_SEQUENCE, &certificate);
+
+        let mut list = (certificate.len() as u32).to_be_bytes()[1..].to_vec();
+        list.extend_from_slice(&certificate);
+        let mut body = ((list.len()) as u32).to_be_bytes()[1..].to_vec();
+        body.extend_from_slice(&list);
+
+        let mut flight = record(CONTENT_TYPE_HANDSHAKE, &handshake(2, &[3, 3, 0, 0]));
+        flight.extend_from_slice(&record(
+            CONTENT_TYPE_HANDSHAKE,
+            &handshake(HANDSHAKE_CERTIFICATE, &body),
+        ));
+        flight
+    }
+
+    #[test]
+    fn server_name() {
+        let mut h = TlsHandshake::default();
+        assert_eq!(
+            h.update(true, &client_hello(Some("api.example.com"))),
+            Some(TlsTag::ServerName("api.example.com".into()))
+        );
+        assert!(!h.is_finished());
+
+        let mut h = TlsHandshake::default();
+        assert_eq!(h.update(true, &client_hello(None)), None);
+        assert!(!h.is_finished());
+
+        // ClientHello split across segments
+        let payload = client_hello(Some("cdn.example.net"));
+        let mut h = TlsHandshake::default();
+        assert_eq!(h.update(true, &payload[..20]), None);
+        assert_eq!(
+            h.update(true, &payload[20..]),
+            Some(TlsTag::ServerName("cdn.example.net".into()))
+        );
+
+        // not TLS
+        let mut h = TlsHandshake::default();
+        assert_eq!(h.update(true, b"GET / HTTP/1.1\r\n\r\n"), None);
+        assert!(h.is_finished());
+    }
+
+    #[test]
+    fn certificate() {
+        let expected = Some(TlsTag::Certificate {
+            subject: "CN=www.example.com".into(),
+            issuer: "C=US, O=Let's Encrypt, CN=R3".into(),
+        });
+        let flight = server_flight();
+
+        let mut h = TlsHandshake::default();
+        assert_eq!(h.update(false, &flight), expected);
+
+        // names are available before the end of certificate
+        let mut h = TlsHandshake::default();
+        assert_eq!(h.update(false, &flight[..200]), expected);
+
+        let mut h = TlsHandshake::default();
+        assert_eq!(h.update(false, &flight[..60]), None);
+        assert_eq!(h.update(false, &flight[60..]), expected);
+
+        // TLS 1.3, certificate is encrypted
+        let mut flight = record(CONTENT_TYPE_HANDSHAKE, &handshake(2, &[3, 3, 0, 0]));
+        flight.extend_from_slice(&record(20, &[1]));
+        flight.extend_from_slice(&record(23, &[0; 100]));
+        let mut h = TlsHandshake::default();
+        assert_eq!(h.update(false, &flight), None);
+        h.update(true, b"not tls");
+        assert!(h.is_finished());
+    }
+}
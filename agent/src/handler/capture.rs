@@ -0,0 +1,206 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    Arc, Mutex, RwLock,
+};
+
+use log::info;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+use super::MiniPacket;
+use public::consts::{PCAP_MAGIC, RECORD_HEADER_LEN};
+
+pub(super) const PCAP_HEADER_LEN: usize = 24;
+const PCAP_VERSION_MAJOR: u16 = 2;
+const PCAP_VERSION_MINOR: u16 = 4;
+const LINKTYPE_ETHERNET: u32 = 1;
+// records buffered for each capture, packets are dropped when it is full
+const CAPTURE_QUEUE_SIZE: usize = 1024;
+
+// Global header of the pcap stream
+pub fn pcap_header(snap_len: u32) -> [u8; PCAP_HEADER_LEN] {
+    let mut header = [0u8; PCAP_HEADER_LEN];
+    header[0..4].copy_from_slice(&PCAP_MAGIC.to_le_bytes());
+    header[4..6].copy_from_slice(&PCAP_VERSION_MAJOR.to_le_bytes());
+    header[6..8].copy_from_slice(&PCAP_VERSION_MINOR.to_le_bytes());
+    // thiszone and sigfigs are always 0
+    header[16..20].copy_from_slice(&snap_len.to_le_bytes());
+    header[20..24].copy_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+    header
+}
+
+struct BpfFilter(pcap::BpfProgram);
+
+// SAFETY: the program is not modified after compiled and only read when filtering
+unsafe impl Send for BpfFilter {}
+unsafe impl Sync for BpfFilter {}
+
+impl BpfFilter {
+    fn compile(filter: &str) -> Result<Self, String> {
+        pcap::Capture::dead(pcap::Linktype::ETHERNET)
+            .and_then(|c| c.compile(filter, true))
+            .map(Self)
+            .map_err(|e| format!("invalid capture filter \"{}\": {}", filter, e))
+    }
+}
+
+struct CaptureSession {
+    id: u64,
+    // packets of all interfaces are captured if not set
+    if_index: Option<u32>,
+    filter: Option<BpfFilter>,
+    snap_len: u32,
+    max_packets: u32,
+
+    packets: AtomicU32,
+    dropped: AtomicU64,
+    // taken when the capture stops, which closes the receiver
+    sender: Mutex<Option<Sender<Vec<u8>>>>,
+}
+
+impl CaptureSession {
+    fn matches(&self, if_index: u32, packet: &[u8]) -> bool {
+        self.if_index.map(|i| i == if_index).unwrap_or(true)
+            && self
+                .filter
+                .as_ref()
+                .map(|f| f.0.filter(packet))
+                .unwrap_or(true)
+    }
+
+    fn capture(&self, packet: &MiniPacket) {
+        let data = &packet.raw()[..packet.packet_size as usize];
+        let len = data.len().min(self.snap_len as usize);
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN + len);
+        let seconds = (packet.timestamp / 1_000_000_000) as u32;
+        let micros = (packet.timestamp % 1_000_000_000 / 1000) as u32;
+        record.extend_from_slice(&seconds.to_le_bytes());
+        record.extend_from_slice(&micros.to_le_bytes());
+        record.extend_from_slice(&(len as u32).to_le_bytes());
+        record.extend_from_slice(&packet.packet_len.max(len as u32).to_le_bytes());
+        record.extend_from_slice(&data[..len]);
+
+        let mut sender = self.sender.lock().unwrap();
+        let Some(s) = sender.as_ref() else {
+            return;
+        };
+        if s.try_send(record).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        if self.packets.fetch_add(1, Ordering::Relaxed) + 1 >= self.max_packets {
+            sender.take();
+        }
+    }
+}
+
+// Packets captured on demand for remote execution, instead of forking tcpdump which may
+// not be available in the agent container
+#[derive(Default)]
+pub struct PacketCapture {
+    // checked by dispatchers before locking sessions
+    active: AtomicBool,
+    sessions: RwLock<Vec<Arc<CaptureSession>>>,
+}
+
+impl PacketCapture {
+    // Captures packets on `if_index` matching BPF `filter` until `max_packets` are captured or
+    // stopped. Pcap records without global header are received from the returned receiver,
+    // which is closed when the capture stops
+    pub fn start(
+        &self,
+        id: u64,
+        if_index: Option<u32>,
+        filter: &str,
+        snap_len: u32,
+        max_packets: u32,
+    ) -> Result<Receiver<Vec<u8>>, String> {
+        let filter = if filter.trim().is_empty() {
+            None
+        } else {
+            Some(BpfFilter::compile(filter)?)
+        };
+        let (sender, receiver) = mpsc::channel(CAPTURE_QUEUE_SIZE);
+        let mut sessions = self.sessions.write().unwrap();
+        if sessions.iter().any(|s| s.id == id) {
+            return Err(format!("capture {} already started", id));
+        }
+        sessions.push(Arc::new(CaptureSession {
+            id,
+            if_index,
+            filter,
+            snap_len,
+            max_packets,
+            packets: Default::default(),
+            dropped: Default::default(),
+            sender: Mutex::new(Some(sender)),
+        }));
+        self.active.store(true, Ordering::Relaxed);
+        Ok(receiver)
+    }
+
+    // Stops the capture, records already captured can still be received.
+    // Returns false if the capture is not running
+    pub fn stop(&self, id: u64) -> bool {
+        let mut sessions = self.sessions.write().unwrap();
+        let Some(index) = sessions.iter().position(|s| s.id == id) else {
+            return false;
+        };
+        let session = sessions.swap_remove(index);
+        self.active.store(!sessions.is_empty(), Ordering::Relaxed);
+        session.sender.lock().unwrap().take();
+        info!(
+            "capture {} stopped with {} packets captured and {} dropped",
+            id,
+            session.packets.load(Ordering::Relaxed),
+            session.dropped.load(Ordering::Relaxed)
+        );
+        true
+    }
+
+    pub(super) fn handle(&self, if_index: u32, packet: &MiniPacket) {
+        if !self.active.load(Ordering::Relaxed) {
+            return;
+        }
+        let data = &packet.raw()[..packet.packet_size as usize];
+        for session in self.sessions.read().unwrap().iter() {
+            if session.matches(if_index, data) {
+                session.capture(packet);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_stop() {
+        let capture = PacketCapture::default();
+        assert!(capture.start(1, None, "tcp port", 65535, 10).is_err());
+        let mut receiver = capture.start(1, Some(2), "tcp port 80", 65535, 10).unwrap();
+        assert!(capture.active.load(Ordering::Relaxed));
+        assert!(capture.start(1, None, "", 65535, 10).is_err());
+        assert!(capture.stop(1));
+        assert!(!capture.stop(1));
+        assert!(!capture.active.load(Ordering::Relaxed));
+        assert!(receiver.try_recv().is_err());
+        assert!(receiver.blocking_recv().is_none());
+    }
+}
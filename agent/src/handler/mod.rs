@@ -14,8 +14,16 @@
  * limitations under the License.
  */
 
+mod capture;
 mod npb;
+mod npb_dedup;
+mod pcap_storage;
+mod policy_hit;
+pub use capture::{pcap_header, PacketCapture};
 pub use npb::NpbBuilder;
+pub use npb_dedup::NpbDedup;
+pub use pcap_storage::{PcapPolicyWindows, PcapStorage};
+pub use policy_hit::{PolicyHitHandler, PolicyHitStats};
 
 use std::net::IpAddr;
 use std::sync::Arc;
@@ -116,7 +124,10 @@ impl<'a> MiniPacket<'a> {
 pub enum PacketHandler {
     // pcap_assembler sender, use for send mini packet to assemble
     Pcap(DebugSender<packet::MiniPacket>),
-    Npb(NpbHandler),
+    Npb(NpbHandler, Arc<NpbDedup>),
+    PolicyHit(PolicyHitHandler),
+    // remote capture and if_index of the pipeline, 0 if pipelines are not per interface
+    Capture(Arc<PacketCapture>, u32),
 }
 
 impl PacketHandler {
@@ -170,18 +181,37 @@ impl PacketHandler {
                     debug!("send mini packet to pcap assembler error: {e:?}");
                 }
             }
-            Self::Npb(n) => n.handle(
-                packet.policy.as_ref(),
-                &packet.npb_mode,
-                packet.timestamp,
-                &packet.packet,
-                packet.packet_size as usize,
-                packet.l2_opt_size as usize,
-                packet.l3_opt_size as usize,
-                packet.l4_opt_size as usize,
-                packet.ipv6_last_option_offset as usize,
-                packet.ipv6_fragment_option_offset as usize,
-            ),
+            Self::Npb(n, dedup) => {
+                if packet
+                    .policy
+                    .as_ref()
+                    .map(|p| p.contain_npb())
+                    .unwrap_or(false)
+                    && dedup.duplicate(
+                        &packet.npb_mode,
+                        &packet.packet[..packet.packet_size as usize],
+                        packet.l2_opt_size as usize,
+                        packet.l3_opt_size as usize,
+                        Duration::from_nanos(packet.timestamp),
+                    )
+                {
+                    return;
+                }
+                n.handle(
+                    packet.policy.as_ref(),
+                    &packet.npb_mode,
+                    packet.timestamp,
+                    &packet.packet,
+                    packet.packet_size as usize,
+                    packet.l2_opt_size as usize,
+                    packet.l3_opt_size as usize,
+                    packet.l4_opt_size as usize,
+                    packet.ipv6_last_option_offset as usize,
+                    packet.ipv6_fragment_option_offset as usize,
+                )
+            }
+            Self::PolicyHit(h) => h.handle(packet.policy.as_ref(), packet.packet_len as u64),
+            Self::Capture(c, if_index) => c.handle(*if_index, packet),
         }
     }
 }
@@ -189,26 +219,38 @@ impl PacketHandler {
 pub enum PacketHandlerBuilder {
     Pcap(DebugSender<packet::MiniPacket>),
     Npb(Box<NpbBuilder>),
+    PolicyHit(Arc<PolicyHitStats>),
+    Capture(Arc<PacketCapture>),
 }
 
 impl PacketHandlerBuilder {
     pub fn build_with(&self, id: usize, if_index: u32, mac: MacAddr) -> PacketHandler {
         match self {
             PacketHandlerBuilder::Pcap(s) => PacketHandler::Pcap(s.clone()),
-            PacketHandlerBuilder::Npb(b) => PacketHandler::Npb(b.build_with(id, if_index, mac)),
+            PacketHandlerBuilder::Npb(b) => {
+                PacketHandler::Npb(b.build_with(id, if_index, mac), b.dedup())
+            }
+            PacketHandlerBuilder::PolicyHit(s) => {
+                PacketHandler::PolicyHit(PolicyHitHandler::new(s.clone()))
+            }
+            PacketHandlerBuilder::Capture(c) => PacketHandler::Capture(c.clone(), if_index),
         }
     }
 
     pub fn notify_stop(&mut self) -> Option<JoinHandle<()>> {
         match self {
-            PacketHandlerBuilder::Pcap(_) => None,
+            PacketHandlerBuilder::Pcap(_)
+            | PacketHandlerBuilder::PolicyHit(_)
+            | PacketHandlerBuilder::Capture(_) => None,
             PacketHandlerBuilder::Npb(b) => b.notify_stop(),
         }
     }
 
     pub fn stop(&mut self) {
         match self {
-            PacketHandlerBuilder::Pcap(_) => {}
+            PacketHandlerBuilder::Pcap(_)
+            | PacketHandlerBuilder::PolicyHit(_)
+            | PacketHandlerBuilder::Capture(_) => {}
             PacketHandlerBuilder::Npb(b) => {
                 b.stop();
             }
@@ -217,7 +259,9 @@ impl PacketHandlerBuilder {
 
     pub fn start(&mut self) {
         match self {
-            PacketHandlerBuilder::Pcap(_) => {}
+            PacketHandlerBuilder::Pcap(_)
+            | PacketHandlerBuilder::PolicyHit(_)
+            | PacketHandlerBuilder::Capture(_) => {}
             PacketHandlerBuilder::Npb(b) => {
                 b.start();
             }
@@ -31,13 +31,18 @@ use pnet::packet::{
 };
 use public::enums::IpProtocol;
 
+use super::NpbDedup;
 use crate::common::{
     erspan, vxlan, ERSPAN_HEADER_SIZE, ETH_HEADER_SIZE, GRE_HEADER_SIZE, IPV4_HEADER_SIZE,
     IPV6_HEADER_SIZE, TCP6_PACKET_SIZE, TCP_PACKET_SIZE, UDP_HEADER_SIZE, VLAN_HEADER_SIZE,
     VXLAN_HEADER_SIZE,
 };
 use crate::config::NpbConfig;
-use crate::sender::npb_sender::{NpbArpTable, NpbPacketSender};
+use crate::sender::{
+    npb_cipher::NpbCipher,
+    npb_health::NpbHealthChecker,
+    npb_sender::{NpbArpTable, NpbPacketSender},
+};
 use crate::utils::stats::{self, QueueStats, StatsOption};
 use npb_handler::{NpbHandler, NpbHandlerCounter, NpbHeader, StatsNpbHandlerCounter, NOT_SUPPORT};
 use public::{
@@ -83,12 +88,14 @@ pub struct NpbBuilder {
 
     npb_packet_sender: Option<Arc<NpbPacketSender>>,
     arp: Arc<NpbArpTable>,
+    health: Arc<NpbHealthChecker>,
 
     pseudo_tunnel_header: [Vec<u8>; NpbTunnelType::Max as usize],
 
     thread_handle: Mutex<Option<JoinHandle<()>>>,
 
     bps_limit: Arc<LeakyBucket>,
+    dedup: Arc<NpbDedup>,
     stats_collector: Arc<stats::Collector>,
 }
 
@@ -200,7 +207,17 @@ impl NpbBuilder {
         vec![u8::from(IpProtocol::TCP); packet_size]
     }
 
+    // Reserve space for the nonce and tag when npb encryption is enabled
+    fn effective_mtu(config: &NpbConfig) -> usize {
+        if config.encryption_key.is_empty() {
+            config.mtu as usize
+        } else {
+            (config.mtu as usize).saturating_sub(NpbCipher::OVERHEAD)
+        }
+    }
+
     pub fn on_config_change(&mut self, config: &NpbConfig, queue_debugger: &QueueDebugger) {
+        self.dedup.on_config_change(config);
         if self.npb_packet_sender.is_none() {
             return;
         }
@@ -224,10 +241,11 @@ impl NpbBuilder {
             receiver,
             config,
             self.arp.clone(),
+            self.health.clone(),
             self.stats_collector.clone(),
         ));
 
-        self.mtu = config.mtu as usize;
+        self.mtu = Self::effective_mtu(config);
         self.underlay_is_ipv6 = config.underlay_is_ipv6;
         self.underlay_has_vlan = config.output_vlan > 0;
         self.overlay_vlan_mode = config.vlan_mode;
@@ -251,7 +269,9 @@ impl NpbBuilder {
         config: &NpbConfig,
         queue_debugger: &QueueDebugger,
         npb_bps_limit: Arc<LeakyBucket>,
+        npb_dedup: Arc<NpbDedup>,
         arp: Arc<NpbArpTable>,
+        health: Arc<NpbHealthChecker>,
         stats_collector: Arc<stats::Collector>,
     ) -> Box<Self> {
         let queue_name = "1-packet-to-npb-sender";
@@ -267,7 +287,7 @@ impl NpbBuilder {
 
         let builder = Box::new(Self {
             id,
-            mtu: config.mtu as usize,
+            mtu: Self::effective_mtu(config),
             enable_qos_bypass: config.enable_qos_bypass, // TODO
             underlay_is_ipv6: config.underlay_is_ipv6,
             underlay_has_vlan: config.output_vlan > 0,
@@ -279,6 +299,7 @@ impl NpbBuilder {
                 receiver,
                 &config,
                 arp.clone(),
+                health.clone(),
                 stats_collector.clone(),
             ))),
             pseudo_tunnel_header: [
@@ -290,8 +311,10 @@ impl NpbBuilder {
             ],
             thread_handle: Mutex::new(None),
             arp,
+            health,
             stats_collector,
             bps_limit: npb_bps_limit,
+            dedup: npb_dedup,
         });
 
         builder
@@ -330,6 +353,10 @@ impl NpbBuilder {
         )
     }
 
+    pub fn dedup(&self) -> Arc<NpbDedup> {
+        self.dedup.clone()
+    }
+
     pub fn start(&mut self) {
         if self.npb_packet_sender.is_some() && self.npb_packet_sender.as_ref().unwrap().is_running()
         {
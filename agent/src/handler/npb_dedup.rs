@@ -0,0 +1,276 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashSet;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use std::time::Duration;
+
+use ahash::RandomState;
+use log::info;
+
+use npb_handler::NpbMode;
+use public::counter::{Counter, CounterType, CounterValue, RefCountable};
+
+use crate::common::{ETH_HEADER_SIZE, IPV4_HEADER_SIZE, IPV6_HEADER_SIZE};
+use crate::config::NpbConfig;
+
+const SHARD_COUNT: usize = 16;
+
+#[derive(Default)]
+pub struct NpbDedupCounter {
+    checked: AtomicU64,
+    duplicated: AtomicU64,
+    duplicated_bytes: AtomicU64,
+    early_rotated: AtomicU64,
+}
+
+impl RefCountable for NpbDedupCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "checked",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.checked.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "duplicated",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.duplicated.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "duplicated-bytes",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.duplicated_bytes.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "early-rotated",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.early_rotated.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
+// Packet digests seen in the current and the previous window
+#[derive(Default)]
+struct DedupShard {
+    current: HashSet<u64>,
+    previous: HashSet<u64>,
+    current_start: u64,
+}
+
+// Drops packets observed more than once by different capture points within
+// the dedup window, shared by npb handlers of all dispatchers.
+//
+// The digest covers the fields of L3 and L4 which stay unchanged along the
+// forwarding path (ttl/hop-limit and ip checksum excluded) and the payload.
+pub struct NpbDedup {
+    enabled: AtomicBool,
+    window: AtomicU64, // unit: ns
+    capacity: usize,   // max digests in each window of each shard
+
+    hasher: RandomState,
+    shards: Vec<Mutex<DedupShard>>,
+    pub counter: Arc<NpbDedupCounter>,
+}
+
+impl NpbDedup {
+    pub fn new(config: &NpbConfig) -> Self {
+        Self {
+            enabled: AtomicBool::new(config.dedup_enabled && !config.dedup_window.is_zero()),
+            window: AtomicU64::new(config.dedup_window.as_nanos() as u64),
+            capacity: (config.dedup_capacity / SHARD_COUNT).max(1),
+            hasher: RandomState::new(),
+            shards: (0..SHARD_COUNT).map(|_| Default::default()).collect(),
+            counter: Default::default(),
+        }
+    }
+
+    pub fn on_config_change(&self, config: &NpbConfig) {
+        let enabled = config.dedup_enabled && !config.dedup_window.is_zero();
+        if self.enabled.swap(enabled, Ordering::Relaxed) != enabled {
+            info!(
+                "npb dedup {} with window {:?}",
+                if enabled { "enabled" } else { "disabled" },
+                config.dedup_window
+            );
+        }
+        self.window
+            .store(config.dedup_window.as_nanos() as u64, Ordering::Relaxed);
+        if !enabled {
+            for shard in self.shards.iter() {
+                *shard.lock().unwrap() = Default::default();
+            }
+        }
+    }
+
+    fn digest(
+        &self,
+        mode: &NpbMode,
+        packet: &[u8],
+        l2_opt_size: usize,
+        l3_opt_size: usize,
+    ) -> Option<u64> {
+        let l3 = ETH_HEADER_SIZE + l2_opt_size;
+        let mut hasher = self.hasher.build_hasher();
+        let l4 = match mode {
+            NpbMode::IPv4 | NpbMode::IPv4TCP => {
+                if packet.len() < l3 + IPV4_HEADER_SIZE {
+                    return None;
+                }
+                let ip = &packet[l3..];
+                // total length, identification, flags and fragment offset
+                hasher.write(&ip[2..8]);
+                // protocol
+                hasher.write_u8(ip[9]);
+                // source and destination address
+                hasher.write(&ip[12..20]);
+                l3 + ((ip[0] & 0xf) as usize) * 4
+            }
+            NpbMode::IPv6 | NpbMode::IPv6TCP => {
+                if packet.len() < l3 + IPV6_HEADER_SIZE {
+                    return None;
+                }
+                let ip = &packet[l3..];
+                // flow label, payload length and next header
+                hasher.write(&ip[1..7]);
+                // source and destination address
+                hasher.write(&ip[8..40]);
+                l3 + IPV6_HEADER_SIZE + l3_opt_size
+            }
+            NpbMode::L2 => return None,
+        };
+        if l4 > packet.len() {
+            return None;
+        }
+        // L4 header and payload
+        hasher.write(&packet[l4..]);
+        Some(hasher.finish())
+    }
+
+    // Returns true if the same packet has been seen in the dedup window
+    pub fn duplicate(
+        &self,
+        mode: &NpbMode,
+        packet: &[u8],
+        l2_opt_size: usize,
+        l3_opt_size: usize,
+        timestamp: Duration,
+    ) -> bool {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return false;
+        }
+        let Some(digest) = self.digest(mode, packet, l2_opt_size, l3_opt_size) else {
+            return false;
+        };
+        self.counter.checked.fetch_add(1, Ordering::Relaxed);
+
+        let window = self.window.load(Ordering::Relaxed);
+        let now = timestamp.as_nanos() as u64;
+        let mut shard = self.shards[digest as usize % SHARD_COUNT].lock().unwrap();
+        if now >= shard.current_start + window || shard.current.len() >= self.capacity {
+            if now < shard.current_start + window {
+                self.counter.early_rotated.fetch_add(1, Ordering::Relaxed);
+            }
+            let shard = &mut *shard;
+            std::mem::swap(&mut shard.current, &mut shard.previous);
+            shard.current.clear();
+            if now >= shard.current_start + window * 2 {
+                // idle for more than a whole window
+                shard.previous.clear();
+            }
+            shard.current_start = now;
+        }
+        if shard.previous.contains(&digest) || !shard.current.insert(digest) {
+            self.counter.duplicated.fetch_add(1, Ordering::Relaxed);
+            self.counter
+                .duplicated_bytes
+                .fetch_add(packet.len() as u64, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ipv4_packet(ttl: u8, dst_mac: u8) -> Vec<u8> {
+        let mut packet = vec![0u8; ETH_HEADER_SIZE + IPV4_HEADER_SIZE + 8 + 4];
+        packet[0] = dst_mac;
+        packet[12] = 0x08;
+        let ip = &mut packet[ETH_HEADER_SIZE..];
+        ip[0] = 0x45;
+        ip[8] = ttl;
+        ip[9] = 17;
+        ip[12..16].copy_from_slice(&[10, 0, 0, 1]);
+        ip[16..20].copy_from_slice(&[10, 0, 0, 2]);
+        packet[ETH_HEADER_SIZE + IPV4_HEADER_SIZE..]
+            .copy_from_slice(&[0x30, 0x39, 0x00, 0x35, 0, 12, 0, 0, b'a', b'b', b'c', b'd']);
+        packet
+    }
+
+    #[test]
+    fn dedup_window() {
+        let config = NpbConfig {
+            dedup_enabled: true,
+            dedup_window: Duration::from_millis(10),
+            dedup_capacity: 1024,
+            ..Default::default()
+        };
+        let dedup = NpbDedup::new(&config);
+        let mode = NpbMode::IPv4;
+        let t = Duration::from_secs(100);
+
+        assert!(!dedup.duplicate(&mode, &ipv4_packet(64, 1), 0, 0, t));
+        // observed again at another capture point after one hop
+        assert!(dedup.duplicate(
+            &mode,
+            &ipv4_packet(63, 2),
+            0,
+            0,
+            t + Duration::from_millis(1)
+        ));
+        let mut other = ipv4_packet(64, 1);
+        *other.last_mut().unwrap() = b'e';
+        assert!(!dedup.duplicate(&mode, &other, 0, 0, t + Duration::from_millis(2)));
+        // out of window
+        assert!(!dedup.duplicate(
+            &mode,
+            &ipv4_packet(64, 1),
+            0,
+            0,
+            t + Duration::from_millis(30)
+        ));
+
+        dedup.on_config_change(&NpbConfig {
+            dedup_enabled: false,
+            ..config
+        });
+        assert!(!dedup.duplicate(
+            &mode,
+            &ipv4_packet(64, 1),
+            0,
+            0,
+            t + Duration::from_millis(31)
+        ));
+    }
+}
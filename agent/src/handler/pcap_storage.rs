@@ -0,0 +1,454 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use log::{info, warn};
+use npb_pcap_policy::NpbTunnelType;
+
+use super::capture::{pcap_header, PCAP_HEADER_LEN};
+use crate::common::platform_data::PlatformData;
+use crate::common::policy::{Acl, Cidr, IpGroupData, PeerConnection};
+use crate::common::{FlowAclListener, FlowAclListenerId};
+use crate::config::PcapConfig;
+use public::{
+    consts::RECORD_HEADER_LEN,
+    counter::{Counter, CounterType, CounterValue, RefCountable},
+    packet::MiniPacket,
+    proto::common::TridentType,
+    queue::{Error, Receiver},
+};
+
+const QUEUE_BATCH_SIZE: usize = 1024;
+const RCV_TIMEOUT: Duration = Duration::from_secs(1);
+const FILE_PREFIX: &str = "deepflow-agent";
+const FILE_SUFFIX: &str = ".pcap";
+const PCAP_SNAPLEN: u32 = 65535;
+
+#[derive(Default)]
+pub struct PcapStorageCounter {
+    rx: AtomicU64,
+    written: AtomicU64,
+    written_bytes: AtomicU64,
+    window_expired: AtomicU64,
+    file_rotated: AtomicU64,
+    write_error: AtomicU64,
+}
+
+impl RefCountable for PcapStorageCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "rx",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.rx.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "written",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.written.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "written-bytes",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.written_bytes.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "window-expired",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.window_expired.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "file-rotated",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.file_rotated.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "write-error",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.write_error.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
+// Start of the capture window of each pcap policy, a window starts when its policy
+// is pushed to the agent and is kept across pushes until the policy is removed
+#[derive(Default)]
+pub struct PcapPolicyWindows(RwLock<HashMap<u16, Duration>>);
+
+impl PcapPolicyWindows {
+    fn update(&self, gids: impl Iterator<Item = u16>, now: Duration) {
+        let mut windows = self.0.write().unwrap();
+        let updated = gids
+            .map(|gid| (gid, windows.get(&gid).copied().unwrap_or(now)))
+            .collect::<HashMap<_, _>>();
+        *windows = updated;
+    }
+
+    // Returns true if the packet is inside the capture window of any of its policies
+    fn in_window(&self, capture_duration: Duration, packet: &MiniPacket) -> bool {
+        let windows = self.0.read().unwrap();
+        packet.acl_gids.iter().any(|gid| match windows.get(gid) {
+            Some(start) => {
+                packet.timestamp >= *start && packet.timestamp < *start + capture_duration
+            }
+            None => false,
+        })
+    }
+}
+
+impl FlowAclListener for Arc<PcapPolicyWindows> {
+    fn flow_acl_change(
+        &mut self,
+        _trident_type: TridentType,
+        _local_epc: i32,
+        _ip_groups: &Vec<Arc<IpGroupData>>,
+        _platform_data: &Vec<Arc<PlatformData>>,
+        _peers: &Vec<Arc<PeerConnection>>,
+        _cidrs: &Vec<Arc<Cidr>>,
+        acls: &Vec<Arc<Acl>>,
+    ) -> Result<(), String> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        self.update(
+            acls.iter()
+                .flat_map(|a| a.npb_actions.iter())
+                .filter(|a| a.tunnel_type() == NpbTunnelType::Pcap)
+                .flat_map(|a| a.acl_gids().iter().copied()),
+            now,
+        );
+        Ok(())
+    }
+
+    fn id(&self) -> usize {
+        u16::from(FlowAclListenerId::PcapStorage) as usize
+    }
+}
+
+// Rotating pcap files of one dispatcher, the oldest file is removed when
+// the number of files exceeds `max_files`
+struct PcapRing {
+    dir: PathBuf,
+    id: usize,
+    max_file_size: u64,
+    max_files: usize,
+
+    files: VecDeque<PathBuf>,
+    writer: Option<BufWriter<File>>,
+    current_size: u64,
+}
+
+impl PcapRing {
+    fn new(dir: PathBuf, id: usize, max_file_size: u64, max_files: usize) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        // files written before restart are still part of the ring
+        let prefix = format!("{}-{}-", FILE_PREFIX, id);
+        let mut files = fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .map(|n| n.starts_with(&prefix) && n.ends_with(FILE_SUFFIX))
+                    .unwrap_or(false)
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+        Ok(Self {
+            dir,
+            id,
+            max_file_size,
+            max_files,
+            files: files.into(),
+            writer: None,
+            current_size: 0,
+        })
+    }
+
+    fn file_path(&self, timestamp: Duration) -> PathBuf {
+        let mut seq = 0;
+        loop {
+            let path = self.dir.join(format!(
+                "{}-{}-{:010}-{:03}{}",
+                FILE_PREFIX,
+                self.id,
+                timestamp.as_secs(),
+                seq,
+                FILE_SUFFIX
+            ));
+            if !path.exists() {
+                return path;
+            }
+            seq += 1;
+        }
+    }
+
+    fn open(&mut self, timestamp: Duration) -> io::Result<()> {
+        self.close();
+        while self.files.len() >= self.max_files {
+            if let Some(oldest) = self.files.pop_front() {
+                if let Err(e) = fs::remove_file(&oldest) {
+                    warn!("remove pcap file {} failed: {}", oldest.display(), e);
+                }
+            }
+        }
+
+        let path = self.file_path(timestamp);
+        let mut writer = BufWriter::new(File::create(&path)?);
+        writer.write_all(&pcap_header(PCAP_SNAPLEN))?;
+
+        self.files.push_back(path);
+        self.writer = Some(writer);
+        self.current_size = PCAP_HEADER_LEN as u64;
+        Ok(())
+    }
+
+    // returns true if a new file is opened
+    fn write(&mut self, packet: &MiniPacket) -> io::Result<bool> {
+        let mut rotated = false;
+        if self.writer.is_none()
+            || self.current_size + packet.record_len() as u64 > self.max_file_size
+        {
+            self.open(packet.timestamp)?;
+            rotated = true;
+        }
+
+        let mut record = [0u8; RECORD_HEADER_LEN];
+        let len = packet.packet.len() as u32;
+        record[0..4].copy_from_slice(&(packet.timestamp.as_secs() as u32).to_le_bytes());
+        record[4..8].copy_from_slice(&packet.timestamp.subsec_micros().to_le_bytes());
+        record[8..12].copy_from_slice(&len.to_le_bytes());
+        record[12..16].copy_from_slice(&len.to_le_bytes());
+
+        let writer = self.writer.as_mut().unwrap();
+        writer.write_all(&record)?;
+        writer.write_all(&packet.packet)?;
+        self.current_size += packet.record_len() as u64;
+        Ok(rotated)
+    }
+
+    fn flush(&mut self) {
+        if let Some(w) = self.writer.as_mut() {
+            if let Err(e) = w.flush() {
+                warn!("flush pcap file failed: {}", e);
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        self.flush();
+        self.writer = None;
+        self.current_size = 0;
+    }
+}
+
+// Stores packets hitting pcap policies into local files, so that packets matching
+// a policy in `capture_duration` since it is pushed can be fetched later
+pub struct PcapStorage {
+    id: usize,
+    path: PathBuf,
+    file_size: u64,
+    file_count: usize,
+    capture_duration: Duration,
+    windows: Arc<PcapPolicyWindows>,
+
+    receiver: Arc<Receiver<MiniPacket>>,
+    running: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    pub counter: Arc<PcapStorageCounter>,
+}
+
+impl PcapStorage {
+    pub fn new(
+        id: usize,
+        config: &PcapConfig,
+        windows: Arc<PcapPolicyWindows>,
+        receiver: Receiver<MiniPacket>,
+    ) -> Self {
+        Self {
+            id,
+            path: Path::new(&config.local_storage_path).to_path_buf(),
+            file_size: (config.local_storage_file_size as u64) << 20,
+            file_count: config.local_storage_file_count as usize,
+            capture_duration: config.local_storage_capture_duration,
+            windows,
+            receiver: Arc::new(receiver),
+            running: Arc::new(AtomicBool::new(false)),
+            thread: Mutex::new(None),
+            counter: Arc::new(PcapStorageCounter::default()),
+        }
+    }
+
+    pub fn start(&self) {
+        if self.running.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let mut ring =
+            match PcapRing::new(self.path.clone(), self.id, self.file_size, self.file_count) {
+                Ok(r) => r,
+                Err(e) => {
+                    warn!(
+                        "pcap storage {} init directory {} failed: {}",
+                        self.id,
+                        self.path.display(),
+                        e
+                    );
+                    self.running.store(false, Ordering::Relaxed);
+                    return;
+                }
+            };
+        let running = self.running.clone();
+        let receiver = self.receiver.clone();
+        let counter = self.counter.clone();
+        let capture_duration = self.capture_duration;
+        let windows = self.windows.clone();
+
+        let thread = thread::Builder::new()
+            .name("pcap-storage".to_owned())
+            .spawn(move || {
+                let mut batch = Vec::with_capacity(QUEUE_BATCH_SIZE);
+                while running.load(Ordering::Relaxed) {
+                    match receiver.recv_all(&mut batch, Some(RCV_TIMEOUT)) {
+                        Ok(_) => {
+                            for packet in batch.drain(..) {
+                                counter.rx.fetch_add(1, Ordering::Relaxed);
+                                if !windows.in_window(capture_duration, &packet) {
+                                    counter.window_expired.fetch_add(1, Ordering::Relaxed);
+                                    continue;
+                                }
+                                match ring.write(&packet) {
+                                    Ok(rotated) => {
+                                        if rotated {
+                                            counter.file_rotated.fetch_add(1, Ordering::Relaxed);
+                                        }
+                                        counter.written.fetch_add(1, Ordering::Relaxed);
+                                        counter.written_bytes.fetch_add(
+                                            packet.record_len() as u64,
+                                            Ordering::Relaxed,
+                                        );
+                                    }
+                                    Err(e) => {
+                                        counter.write_error.fetch_add(1, Ordering::Relaxed);
+                                        warn!("write pcap file failed: {}", e);
+                                        ring.close();
+                                    }
+                                }
+                            }
+                        }
+                        Err(Error::Terminated(..)) => break,
+                        Err(Error::Timeout) => ring.flush(),
+                        Err(Error::BatchTooLarge(_)) => unreachable!(),
+                    }
+                }
+                ring.close();
+            })
+            .unwrap();
+
+        self.thread.lock().unwrap().replace(thread);
+        info!("pcap storage {} started", self.id);
+    }
+
+    pub fn notify_stop(&self) -> Option<JoinHandle<()>> {
+        if !self.running.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+        info!("notified pcap storage {} to stop", self.id);
+        self.thread.lock().unwrap().take()
+    }
+
+    pub fn stop(&self) {
+        if !self.running.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        if let Some(t) = self.thread.lock().unwrap().take() {
+            let _ = t.join();
+        }
+        info!("pcap storage {} stopped", self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mini_packet(secs: u64, acl_gids: Vec<u16>) -> MiniPacket {
+        MiniPacket {
+            packet: vec![0u8; 64],
+            timestamp: Duration::from_secs(secs),
+            flow_id: 1,
+            acl_gids,
+            second_in_minute: 0,
+        }
+    }
+
+    #[test]
+    fn capture_window() {
+        let windows = PcapPolicyWindows::default();
+        let d = Duration::from_secs(300);
+        windows.update([1].into_iter(), Duration::from_secs(1000));
+        assert!(!windows.in_window(d, &mini_packet(999, vec![1])));
+        assert!(windows.in_window(d, &mini_packet(1000, vec![1])));
+        assert!(windows.in_window(d, &mini_packet(1299, vec![1])));
+        assert!(!windows.in_window(d, &mini_packet(1300, vec![1])));
+        assert!(!windows.in_window(d, &mini_packet(1000, vec![2])));
+
+        // pushing the policy again keeps its window, new policies have their own
+        windows.update([1, 2].into_iter(), Duration::from_secs(1200));
+        assert!(!windows.in_window(d, &mini_packet(1300, vec![1])));
+        assert!(windows.in_window(d, &mini_packet(1300, vec![1, 2])));
+
+        // restarted after the policy is removed and pushed again
+        windows.update([2].into_iter(), Duration::from_secs(1600));
+        windows.update([1, 2].into_iter(), Duration::from_secs(1700));
+        assert!(windows.in_window(d, &mini_packet(1700, vec![1])));
+        assert!(!windows.in_window(d, &mini_packet(1700, vec![2])));
+    }
+
+    #[test]
+    fn ring_rotation() {
+        let dir = std::env::temp_dir().join(format!("pcap-storage-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        // each file holds the header and one record
+        let file_size = (PCAP_HEADER_LEN + RECORD_HEADER_LEN + 64) as u64;
+        let mut ring = PcapRing::new(dir.clone(), 0, file_size, 2).unwrap();
+        for i in 0..4 {
+            assert!(ring.write(&mini_packet(i, vec![1])).unwrap());
+        }
+        ring.close();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        let ring = PcapRing::new(dir.clone(), 0, file_size, 2).unwrap();
+        assert_eq!(ring.files.len(), 2);
+        assert_eq!(
+            fs::metadata(&ring.files[0]).unwrap().len(),
+            file_size,
+            "one record per file"
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
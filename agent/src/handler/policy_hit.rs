@@ -0,0 +1,287 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::{
+    atomic::{AtomicU32, AtomicU64, Ordering},
+    Arc, Mutex, RwLock, Weak,
+};
+
+use ahash::AHashMap;
+
+use npb_pcap_policy::{NpbTunnelType, PolicyData};
+use public::counter::{Countable, Counter, CounterType, CounterValue, RefCountable};
+
+use crate::collector::types::U16Set;
+use crate::utils::stats::{self, StatsOption};
+
+// Counters of policies not hit in the last 6 reports are removed
+const IDLE_REPORT_LIMIT: u32 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PolicyHitType {
+    Acl,
+    Npb,
+    Pcap,
+}
+
+impl PolicyHitType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Acl => "acl",
+            Self::Npb => "npb",
+            Self::Pcap => "pcap",
+        }
+    }
+}
+
+type PolicyHitKey = (PolicyHitType, u32);
+// packets and bytes of each policy hit by one handler since the last stats report
+type HandlerHits = Mutex<AHashMap<PolicyHitKey, (u64, u64)>>;
+
+struct PolicyHitModule(PolicyHitKey);
+
+impl stats::Module for PolicyHitModule {
+    fn name(&self) -> &'static str {
+        "policy_hit"
+    }
+
+    fn tags(&self) -> Vec<StatsOption> {
+        vec![
+            StatsOption::Tag("policy_type", self.0 .0.as_str().to_owned()),
+            StatsOption::Tag("policy_id", self.0 .1.to_string()),
+        ]
+    }
+}
+
+#[derive(Default)]
+pub struct PolicyHitCounter {
+    packets: AtomicU64,
+    bytes: AtomicU64,
+    idle_reports: AtomicU32,
+}
+
+impl RefCountable for PolicyHitCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        let packets = self.packets.swap(0, Ordering::Relaxed);
+        let bytes = self.bytes.swap(0, Ordering::Relaxed);
+        if packets == 0 {
+            self.idle_reports.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.idle_reports.store(0, Ordering::Relaxed);
+        }
+        vec![
+            (
+                "packets",
+                CounterType::Counted,
+                CounterValue::Unsigned(packets),
+            ),
+            ("bytes", CounterType::Counted, CounterValue::Unsigned(bytes)),
+        ]
+    }
+}
+
+// Packet and byte hits of each ACL, NPB and pcap policy, shared by the policy hit
+// handlers of all dispatchers and reported with the agent stats.
+pub struct PolicyHitStats {
+    stats_collector: Arc<stats::Collector>,
+    counters: RwLock<AHashMap<PolicyHitKey, Arc<PolicyHitCounter>>>,
+    handler_hits: Mutex<Vec<Weak<HandlerHits>>>,
+}
+
+impl PolicyHitStats {
+    pub fn new(stats_collector: Arc<stats::Collector>) -> Arc<Self> {
+        let hit_stats = Arc::new(Self {
+            stats_collector: stats_collector.clone(),
+            counters: Default::default(),
+            handler_hits: Default::default(),
+        });
+        let weak = Arc::downgrade(&hit_stats);
+        // hits of handlers are merged on each report even if no packet arrives
+        stats_collector.register_pre_hook(Box::new(move || {
+            if let Some(s) = weak.upgrade() {
+                s.flush_handlers();
+                s.remove_idle();
+            }
+        }));
+        hit_stats
+    }
+
+    fn add(&self, key: &PolicyHitKey, packets: u64, bytes: u64) {
+        if let Some(c) = self.counters.read().unwrap().get(key) {
+            c.packets.fetch_add(packets, Ordering::Relaxed);
+            c.bytes.fetch_add(bytes, Ordering::Relaxed);
+            return;
+        }
+        let mut counters = self.counters.write().unwrap();
+        let c = counters.entry(*key).or_insert_with(|| {
+            let c = Arc::new(PolicyHitCounter::default());
+            self.stats_collector.register_countable(
+                &PolicyHitModule(*key),
+                Countable::Ref(Arc::downgrade(&c) as Weak<dyn RefCountable>),
+            );
+            c
+        });
+        c.packets.fetch_add(packets, Ordering::Relaxed);
+        c.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn merge(&self, hits: &HandlerHits) {
+        let hits = hits.lock().unwrap().drain().collect::<Vec<_>>();
+        for (key, (packets, bytes)) in hits {
+            self.add(&key, packets, bytes);
+        }
+    }
+
+    fn flush_handlers(&self) {
+        self.handler_hits
+            .lock()
+            .unwrap()
+            .retain(|hits| match hits.upgrade() {
+                Some(hits) => {
+                    self.merge(&hits);
+                    true
+                }
+                None => false,
+            });
+    }
+
+    // Dropping the counter closes the countable registered in stats collector
+    fn remove_idle(&self) {
+        self.counters.write().unwrap().retain(|_, c| {
+            c.idle_reports.load(Ordering::Relaxed) < IDLE_REPORT_LIMIT
+                || c.packets.load(Ordering::Relaxed) > 0
+        });
+    }
+}
+
+// Accumulates hits of one dispatcher pipeline to keep the shared counters out of
+// the packet path, merged into the shared counters before each stats report
+pub struct PolicyHitHandler {
+    stats: Arc<PolicyHitStats>,
+    hits: Arc<HandlerHits>,
+}
+
+impl PolicyHitHandler {
+    pub fn new(stats: Arc<PolicyHitStats>) -> Self {
+        let hits = Arc::new(HandlerHits::default());
+        stats
+            .handler_hits
+            .lock()
+            .unwrap()
+            .push(Arc::downgrade(&hits));
+        Self { stats, hits }
+    }
+
+    pub fn handle(&mut self, policy: Option<&Arc<PolicyData>>, bytes: u64) {
+        let Some(policy) = policy else {
+            return;
+        };
+        let mut hits = self.hits.lock().unwrap();
+        let mut hit = |key: PolicyHitKey| {
+            let h = hits.entry(key).or_default();
+            h.0 += 1;
+            h.1 += bytes;
+        };
+        if policy.acl_id != 0 {
+            hit((PolicyHitType::Acl, policy.acl_id));
+        }
+        if !policy.npb_actions.is_empty() {
+            // a policy may have multiple actions, each gid is counted once per packet
+            let (mut npb_gids, mut pcap_gids) = (U16Set::new(), U16Set::new());
+            for action in policy.npb_actions.iter() {
+                let gids = if action.tunnel_type() == NpbTunnelType::Pcap {
+                    &mut pcap_gids
+                } else {
+                    &mut npb_gids
+                };
+                for gid in action.acl_gids() {
+                    gids.add(*gid);
+                }
+            }
+            for gid in npb_gids.list() {
+                hit((PolicyHitType::Npb, *gid as u32));
+            }
+            for gid in pcap_gids.list() {
+                hit((PolicyHitType::Pcap, *gid as u32));
+            }
+        }
+    }
+}
+
+impl Drop for PolicyHitHandler {
+    fn drop(&mut self) {
+        self.stats.merge(&self.hits);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::AtomicI64;
+
+    use npb_pcap_policy::{DirectionType, NpbAction, TapSide};
+
+    #[test]
+    fn policy_hit() {
+        let collector = Arc::new(stats::Collector::new("test", Arc::new(AtomicI64::new(0))));
+        let stats = PolicyHitStats::new(collector);
+        let mut handler = PolicyHitHandler::new(stats.clone());
+
+        let action = NpbAction::new(
+            10,
+            0,
+            "10.0.0.1".parse().unwrap(),
+            0,
+            NpbTunnelType::VxLan,
+            TapSide::SRC,
+            DirectionType::FORWARD,
+            0,
+        );
+        let policy = Arc::new(PolicyData::new(vec![action.clone(), action], 1));
+        handler.handle(Some(&policy), 100);
+        handler.handle(None, 100);
+        handler.handle(Some(&policy), 50);
+        assert!(stats.counters.read().unwrap().is_empty());
+        // merged on stats report tick without new packets
+        stats.flush_handlers();
+
+        let counters = stats.counters.read().unwrap();
+        let npb = counters.get(&(PolicyHitType::Npb, 10)).unwrap();
+        assert_eq!(npb.packets.load(Ordering::Relaxed), 2);
+        assert_eq!(npb.bytes.load(Ordering::Relaxed), 150);
+        let acl = counters.get(&(PolicyHitType::Acl, 1)).unwrap();
+        assert_eq!(acl.packets.load(Ordering::Relaxed), 2);
+        drop(counters);
+
+        for _ in 0..IDLE_REPORT_LIMIT + 1 {
+            stats
+                .counters
+                .read()
+                .unwrap()
+                .values()
+                .for_each(|c| drop(c.get_counters()));
+        }
+        stats.remove_idle();
+        assert!(stats.counters.read().unwrap().is_empty());
+
+        handler.handle(Some(&policy), 100);
+        drop(handler);
+        stats.flush_handlers();
+        assert!(stats.handler_hits.lock().unwrap().is_empty());
+        assert_eq!(stats.counters.read().unwrap().len(), 2);
+    }
+}
@@ -14,6 +14,8 @@
  * limitations under the License.
  */
 
+mod jaeger;
+
 use std::collections::HashMap;
 use std::fmt::{self, Debug, Formatter};
 use std::io::{Read, Write};
@@ -39,6 +41,7 @@ use public::{
     sender::{SendMessageType, Sendable},
 };
 use tokio::{
+    net::UdpSocket,
     runtime::Runtime,
     select,
     sync::{mpsc, oneshot},
@@ -57,6 +60,7 @@ use crate::{
     flow_generator::protocol_logs::{http::handle_endpoint, L7ResponseStatus},
     metric::document::{Direction, TapSide},
     policy::PolicyGetter,
+    utils::pprof::{self, ProfileFilter, ProfileKind},
 };
 
 use public::{
@@ -64,12 +68,18 @@ use public::{
     enums::{EthernetType, L4Protocol, TapType},
     l7_protocol::L7Protocol,
     proto::{
-        integration::opentelemetry::proto::{
-            common::v1::{
-                any_value::Value::{IntValue, StringValue},
-                AnyValue, KeyValue,
+        integration::{
+            jaeger::api_v2::{
+                PostSpansRequest as JaegerPostSpansRequest,
+                PostSpansResponse as JaegerPostSpansResponse,
+            },
+            opentelemetry::proto::{
+                common::v1::{
+                    any_value::Value::{IntValue, StringValue},
+                    AnyValue, KeyValue,
+                },
+                trace::v1::{span::SpanKind, Span, TracesData},
             },
-            trace::v1::{span::SpanKind, Span, TracesData},
         },
         metric,
         trident::Exception,
@@ -87,6 +97,24 @@ const OPEN_TELEMETRY_COMPRESSED: u32 = 20221024;
 const PROMETHEUS: u32 = 20220613;
 const TELEGRAF: u32 = 20220613;
 
+const GRPC_CONTENT_TYPE: &str = "application/grpc";
+const GRPC_STATUS: &str = "grpc-status";
+const GRPC_STATUS_OK: u32 = 0;
+const GRPC_STATUS_INVALID_ARGUMENT: u32 = 3;
+const GRPC_STATUS_UNIMPLEMENTED: u32 = 12;
+// max size of a jaeger UDP compact thrift packet
+const JAEGER_UDP_PACKET_SIZE: usize = 65000;
+// path commonly configured in remote_write of Prometheus and vmagent
+const PROMETHEUS_REMOTE_WRITE_PATH: &str = "/api/v1/write";
+const PROMETHEUS_FORWARD_TIMEOUT: Duration = Duration::from_secs(10);
+// headers required by remote-write receivers
+const PROMETHEUS_FORWARD_HEADERS: [&str; 4] = [
+    "content-encoding",
+    "content-type",
+    "user-agent",
+    "x-prometheus-remote-write-version",
+];
+
 // Otel的protobuf数据
 // ingester使用该proto https://github.com/open-telemetry/opentelemetry-proto/blob/main/opentelemetry/proto/trace/v1/trace.proto进行解析
 #[derive(Debug, PartialEq)]
@@ -133,13 +161,16 @@ pub struct PrometheusExtra {
     metrics: Vec<u8>,
     extra_label_names: Vec<String>,
     extra_label_values: Vec<String>,
+    // sender of the remote-write request
+    ip: IpAddr,
+    l3_epc_id: i32,
 }
 
 impl Debug for PrometheusExtra {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!(
-            "PrometheusExtra {{ metrics's len: {}, extra_label_names: {:?}, extra_label_values: {:?}",
-            self.metrics.len(), self.extra_label_names, self.extra_label_values
+            "PrometheusExtra {{ metrics's len: {}, extra_label_names: {:?}, extra_label_values: {:?}, ip: {}, l3_epc_id: {}",
+            self.metrics.len(), self.extra_label_names, self.extra_label_values, self.ip, self.l3_epc_id
         ))
     }
 }
@@ -153,6 +184,11 @@ impl Sendable for BoxedPrometheusExtra {
             metrics: self.0.metrics,
             extra_label_names: self.0.extra_label_names,
             extra_label_values: self.0.extra_label_values,
+            ip: match self.0.ip {
+                IpAddr::V4(ip4) => ip4.octets().to_vec(),
+                IpAddr::V6(ip6) => ip6.octets().to_vec(),
+            },
+            l3_epc_id: self.0.l3_epc_id,
         };
         let _ = pb_prometheus_metric.encode(buf)?;
         Ok(pb_prometheus_metric.encoded_len())
@@ -266,8 +302,28 @@ fn decode_otel_trace_data(
     flow_id: Arc<AtomicU64>,
     log_parser_config: Arc<LogParserConfig>,
 ) -> Result<(Vec<u8>, Vec<BatchedBox<L7Stats>>), GenericError> {
+    let d = TracesData::decode(data.as_slice())?;
+    Ok(handle_otel_trace_data(
+        peer_addr,
+        d,
+        local_epc_id,
+        policy_getter,
+        time_diff,
+        flow_id,
+        log_parser_config,
+    ))
+}
+
+fn handle_otel_trace_data(
+    peer_addr: SocketAddr,
+    mut d: TracesData,
+    local_epc_id: u32,
+    policy_getter: Arc<PolicyGetter>,
+    time_diff: i64,
+    flow_id: Arc<AtomicU64>,
+    log_parser_config: Arc<LogParserConfig>,
+) -> (Vec<u8>, Vec<BatchedBox<L7Stats>>) {
     let mut l7_stats: Vec<BatchedBox<L7Stats>> = vec![];
-    let mut d = TracesData::decode(data.as_slice())?;
     // 因为collector传过来traceData的全部resource都有"app.host.ip"的属性，所以只检查第一个resource有没有“app.host.ip”即可，
     // sdk传过来的traceData因没有该属性则要补上(key: “app.host.ip”, value: 对端IP)属性值
     // =======================================================================
@@ -349,7 +405,7 @@ fn decode_otel_trace_data(
     }
     let sdk_data = d.encode_to_vec();
     debug!("send otel sdk traces_data to sender: {:?}", d);
-    return Ok((sdk_data, l7_stats));
+    (sdk_data, l7_stats)
 }
 
 fn fill_l7_stats(
@@ -585,6 +641,57 @@ fn get_ip(ip_addr: IpAddr) -> IpAddr {
     }
 }
 
+// resolves the vpc of an integration data sender, so that universal tags can be attached
+fn lookup_sender_epc(ip: IpAddr, policy_getter: &PolicyGetter, local_epc_id: u32) -> i32 {
+    let mut lookup_key = LookupKey {
+        src_ip: ip,
+        dst_ip: if ip.is_ipv4() {
+            IpAddr::from(Ipv4Addr::UNSPECIFIED)
+        } else {
+            IpAddr::from(Ipv6Addr::UNSPECIFIED)
+        },
+        l2_end_0: true,
+        ..Default::default()
+    };
+    let (endpoint, _) = policy_getter
+        .policy()
+        .lookup_all_by_epc(&mut lookup_key, local_epc_id as i32);
+    endpoint.src_info.l3_epc_id
+}
+
+// forwards the remote-write request as is, without waiting for the responses
+fn forward_prometheus(
+    client: &reqwest::Client,
+    urls: &[String],
+    headers: &HeaderMap,
+    metric: &[u8],
+    counter: &Arc<CompressedMetric>,
+) {
+    for url in urls {
+        let mut request = client.post(url).body(metric.to_vec());
+        for name in PROMETHEUS_FORWARD_HEADERS {
+            if let Some(value) = headers.get(name) {
+                request = request.header(name, value.clone());
+            }
+        }
+        let counter = counter.clone();
+        let url = url.clone();
+        tokio::spawn(async move {
+            match request.send().await.and_then(|r| r.error_for_status()) {
+                Ok(_) => {
+                    counter.prometheus_forwarded.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    counter
+                        .prometheus_forward_failed
+                        .fetch_add(1, Ordering::Relaxed);
+                    debug!("forward prometheus remote-write to {} failed: {}", url, e);
+                }
+            }
+        });
+    }
+}
+
 fn http_code_to_response_status(status_code: i64) -> L7ResponseStatus {
     if status_code >= 400 && status_code <= 499 {
         L7ResponseStatus::ClientError
@@ -601,6 +708,69 @@ fn compress_data(input: Vec<u8>) -> std::io::Result<Vec<u8>> {
     e.finish()
 }
 
+fn send_otel_trace_data(
+    mut decode_data: (Vec<u8>, Vec<BatchedBox<L7Stats>>),
+    compressed: bool,
+    counter: &CompressedMetric,
+    otel_sender: &DebugSender<OpenTelemetry>,
+    compressed_otel_sender: &DebugSender<OpenTelemetryCompressed>,
+    otel_l7_stats_sender: &DebugSender<BatchedBox<L7Stats>>,
+) -> std::io::Result<()> {
+    if !decode_data.1.is_empty() {
+        if let Err(e) = otel_l7_stats_sender.send_all(&mut decode_data.1) {
+            warn!("otel_l7_stats_sender failed to send data, because {:?}", e);
+        }
+    }
+    if compressed {
+        counter
+            .uncompressed
+            .fetch_add(decode_data.0.len() as u64, Ordering::Relaxed);
+        let compressed_data = compress_data(decode_data.0)?;
+        counter
+            .compressed
+            .fetch_add(compressed_data.len() as u64, Ordering::Relaxed);
+        if let Err(e) = compressed_otel_sender.send(OpenTelemetryCompressed(compressed_data)) {
+            warn!(
+                "compressed_otel_sender failed to send data, because {:?}",
+                e
+            );
+        }
+    } else {
+        if let Err(e) = otel_sender.send(OpenTelemetry(decode_data.0)) {
+            warn!("otel_sender failed to send data, because {:?}", e);
+        }
+    }
+    Ok(())
+}
+
+// gRPC responses always have status 200, the result is carried in the grpc-status trailer.
+// A successful PostSpans returns an empty PostSpansResponse message.
+fn grpc_response(grpc_status: u32) -> Response<Body> {
+    let builder = Response::builder().header(CONTENT_TYPE, GRPC_CONTENT_TYPE);
+    if grpc_status != GRPC_STATUS_OK {
+        // trailers-only response
+        return builder
+            .header(GRPC_STATUS, grpc_status)
+            .body(Body::empty())
+            .unwrap();
+    }
+    let (mut tx, body) = Body::channel();
+    tokio::spawn(async move {
+        let message = JaegerPostSpansResponse::default().encode_to_vec();
+        if tx
+            .send_data(jaeger::encode_grpc_frame(&message).into())
+            .await
+            .is_err()
+        {
+            return;
+        }
+        let mut trailers = HeaderMap::new();
+        trailers.insert(GRPC_STATUS, grpc_status.into());
+        let _ = tx.send_trailers(trailers).await;
+    });
+    builder.body(body).unwrap()
+}
+
 /// 接收metric server发送的请求，根据路由处理分发
 async fn handler(
     peer_addr: SocketAddr,
@@ -619,6 +789,7 @@ async fn handler(
     policy_getter: Arc<PolicyGetter>,
     time_diff: Arc<AtomicI64>,
     prometheus_extra_config: Arc<PrometheusExtraConfig>,
+    prometheus_forward_client: reqwest::Client,
     log_parser_config: Arc<LogParserConfig>,
     flow_id: Arc<AtomicU64>,
     external_profile_integration_disabled: bool,
@@ -661,41 +832,68 @@ async fn handler(
                 debug!("decode otel trace data error: {}", e);
                 e
             })?;
-            if !decode_data.1.is_empty() {
-                if let Err(e) = otel_l7_stats_sender.send_all(&mut decode_data.1) {
-                    warn!("otel_l7_stats_sender failed to send data, because {:?}", e);
-                }
-            }
-            if compressed {
-                counter
-                    .uncompressed
-                    .fetch_add(decode_data.0.len() as u64, Ordering::Relaxed);
-                let compressed_data = compress_data(decode_data.0)?;
-                counter
-                    .compressed
-                    .fetch_add(compressed_data.len() as u64, Ordering::Relaxed);
-                if let Err(e) =
-                    compressed_otel_sender.send(OpenTelemetryCompressed(compressed_data))
-                {
-                    warn!(
-                        "compressed_otel_sender failed to send data, because {:?}",
-                        e
-                    );
-                }
-            } else {
-                if let Err(e) = otel_sender.send(OpenTelemetry(decode_data.0)) {
-                    warn!("otel_sender failed to send data, because {:?}", e);
-                }
-            }
+            send_otel_trace_data(
+                decode_data,
+                compressed,
+                &counter,
+                &otel_sender,
+                &compressed_otel_sender,
+                &otel_l7_stats_sender,
+            )?;
 
             Ok(Response::builder().body(Body::empty()).unwrap())
         }
-        // Prometheus integration
-        (&Method::POST, "/api/v1/prometheus") => {
+        // Jaeger collector gRPC integration
+        (&Method::POST, jaeger::GRPC_POST_SPANS_PATH) => {
+            if external_trace_integration_disabled {
+                return Ok(grpc_response(GRPC_STATUS_UNIMPLEMENTED));
+            }
+            let whole_body =
+                match aggregate_with_catch_exception(req.into_body(), &exception_handler).await {
+                    Ok(b) => b,
+                    Err(e) => {
+                        return Ok(e);
+                    }
+                };
+            let mut frame = vec![0u8; whole_body.remaining()];
+            whole_body.copy_to_slice(frame.as_mut_slice());
+            let request = match jaeger::decode_grpc_frame(&frame)
+                .map_err(GenericError::from)
+                .and_then(|m| JaegerPostSpansRequest::decode(m).map_err(GenericError::from))
+            {
+                Ok(r) => r,
+                Err(e) => {
+                    debug!("decode jaeger grpc request error: {}", e);
+                    return Ok(grpc_response(GRPC_STATUS_INVALID_ARGUMENT));
+                }
+            };
+            let decode_data = handle_otel_trace_data(
+                peer_addr,
+                jaeger::convert_post_spans(request),
+                local_epc_id,
+                policy_getter,
+                time_diff.load(Ordering::Relaxed),
+                flow_id.clone(),
+                log_parser_config.clone(),
+            );
+            send_otel_trace_data(
+                decode_data,
+                compressed,
+                &counter,
+                &otel_sender,
+                &compressed_otel_sender,
+                &otel_l7_stats_sender,
+            )?;
+
+            Ok(grpc_response(GRPC_STATUS_OK))
+        }
+        // Prometheus remote-write integration
+        (&Method::POST, "/api/v1/prometheus" | PROMETHEUS_REMOTE_WRITE_PATH) => {
             if external_metric_integration_disabled {
                 return Ok(Response::builder().body(Body::empty()).unwrap());
             }
-            let headers = req.headers();
+            let (part, body) = req.into_parts();
+            let headers = &part.headers;
             let labels = &prometheus_extra_config.labels;
             let labels_limit = prometheus_extra_config.labels_limit;
             let values_limit = prometheus_extra_config.values_limit;
@@ -726,7 +924,7 @@ async fn handler(
             }
 
             let mut whole_body =
-                match aggregate_with_catch_exception(req.into_body(), &exception_handler).await {
+                match aggregate_with_catch_exception(body, &exception_handler).await {
                     Ok(b) => b,
                     Err(e) => {
                         return Ok(e);
@@ -735,10 +933,23 @@ async fn handler(
             let mut metric = vec![0u8; whole_body.remaining()];
             whole_body.copy_to_slice(metric.as_mut_slice());
 
+            if !prometheus_extra_config.forward_urls.is_empty() {
+                forward_prometheus(
+                    &prometheus_forward_client,
+                    &prometheus_extra_config.forward_urls,
+                    headers,
+                    &metric,
+                    &counter,
+                );
+            }
+
+            let ip = get_ip(peer_addr.ip());
             let prometheus_with_extra = PrometheusExtra {
                 metrics: metric,
                 extra_label_names,
                 extra_label_values,
+                ip,
+                l3_epc_id: lookup_sender_epc(ip, &policy_getter, local_epc_id),
             };
             if let Err(e) =
                 prometheus_sender.send(BoxedPrometheusExtra(Box::new(prometheus_with_extra)))
@@ -803,6 +1014,28 @@ async fn handler(
 
             Ok(Response::builder().body(Body::empty()).unwrap())
         }
+        // eBPF profiles in pprof format
+        (&Method::GET, "/api/v1/profile/pprof") => {
+            let (kind, filter) = match parse_pprof_query(req.uri().query().unwrap_or_default()) {
+                Ok(q) => q,
+                Err(e) => {
+                    return Ok(Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body(e.into())
+                        .unwrap());
+                }
+            };
+            match pprof::export(kind, &filter) {
+                Some(data) => Ok(Response::builder()
+                    .header(CONTENT_TYPE, "application/octet-stream")
+                    .body(data.into())
+                    .unwrap()),
+                None => Ok(Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(NOT_FOUND.into())
+                    .unwrap()),
+            }
+        }
         // log integration
         (&Method::POST, "/api/v1/log") => {
             if external_log_integration_disabled {
@@ -867,10 +1100,39 @@ fn parse_profile_query(query: &str, profile: &mut metric::Profile) {
     };
 }
 
+// query parameters: event=on-cpu|off-cpu, pid=<pid>, pod_id=<pod id>
+fn parse_pprof_query(query: &str) -> Result<(ProfileKind, ProfileFilter), String> {
+    let mut kind = ProfileKind::OnCpu;
+    let mut filter = ProfileFilter::default();
+    for (key, value) in query.split('&').filter_map(|s| s.split_once('=')) {
+        match key {
+            "event" => kind = ProfileKind::try_from(value)?,
+            "pid" => {
+                filter.pid = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid pid {}", value))?,
+                )
+            }
+            "pod_id" => {
+                filter.pod_id = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid pod_id {}", value))?,
+                )
+            }
+            _ => (),
+        }
+    }
+    Ok((kind, filter))
+}
+
 #[derive(Default)]
 struct CompressedMetric {
     compressed: AtomicU64,   // unit (bytes)
     uncompressed: AtomicU64, // unit (bytes)
+    prometheus_forwarded: AtomicU64,
+    prometheus_forward_failed: AtomicU64,
 }
 
 #[derive(Default)]
@@ -884,6 +1146,12 @@ impl OwnedCountable for IntegrationCounter {
             self.metrics.compressed.swap(0, Ordering::Relaxed),
             self.metrics.uncompressed.swap(0, Ordering::Relaxed),
         );
+        let (prometheus_forwarded, prometheus_forward_failed) = (
+            self.metrics.prometheus_forwarded.swap(0, Ordering::Relaxed),
+            self.metrics
+                .prometheus_forward_failed
+                .swap(0, Ordering::Relaxed),
+        );
         vec![
             (
                 "compressed",
@@ -905,6 +1173,16 @@ impl OwnedCountable for IntegrationCounter {
                     uncomressed as f64 / compressed as f64
                 }),
             ),
+            (
+                "prometheus_forwarded",
+                CounterType::Counted,
+                CounterValue::Unsigned(prometheus_forwarded),
+            ),
+            (
+                "prometheus_forward_failed",
+                CounterType::Counted,
+                CounterValue::Unsigned(prometheus_forward_failed),
+            ),
         ]
     }
 
@@ -934,11 +1212,14 @@ pub struct MetricServer {
     policy_getter: Arc<PolicyGetter>,
     time_diff: Arc<AtomicI64>,
     prometheus_extra_config: Arc<PrometheusExtraConfig>,
+    prometheus_forward_client: reqwest::Client,
     log_parser_config: Arc<LogParserConfig>,
     external_profile_integration_disabled: bool,
     external_trace_integration_disabled: bool,
     external_metric_integration_disabled: bool,
     external_log_integration_disabled: bool,
+    jaeger_compact_port: u16,
+    jaeger_thread: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl MetricServer {
@@ -963,6 +1244,7 @@ impl MetricServer {
         external_trace_integration_disabled: bool,
         external_metric_integration_disabled: bool,
         external_log_integration_disabled: bool,
+        jaeger_compact_port: u16,
     ) -> (Self, IntegrationCounter) {
         let counter = IntegrationCounter::default();
         (
@@ -985,12 +1267,18 @@ impl MetricServer {
                 policy_getter: Arc::new(policy_getter),
                 time_diff,
                 prometheus_extra_config: Arc::new(prometheus_extra_config),
+                prometheus_forward_client: reqwest::Client::builder()
+                    .timeout(PROMETHEUS_FORWARD_TIMEOUT)
+                    .build()
+                    .unwrap_or_default(),
                 log_parser_config: Arc::new(log_parser_config),
                 otel_l7_stats_sender,
                 external_profile_integration_disabled,
                 external_trace_integration_disabled,
                 external_metric_integration_disabled,
                 external_log_integration_disabled,
+                jaeger_compact_port,
+                jaeger_thread: Default::default(),
             },
             counter,
         )
@@ -1014,6 +1302,9 @@ impl MetricServer {
         if self.running.swap(true, Ordering::Relaxed) {
             return;
         }
+        if self.jaeger_compact_port != 0 && !self.external_trace_integration_disabled {
+            self.start_jaeger_compact_receiver();
+        }
 
         let otel_sender = self.otel_sender.clone();
         let compressed_otel_sender = self.compressed_otel_sender.clone();
@@ -1033,6 +1324,7 @@ impl MetricServer {
         let policy_getter = self.policy_getter.clone();
         let time_diff = self.time_diff.clone();
         let prometheus_extra_config = self.prometheus_extra_config.clone();
+        let prometheus_forward_client = self.prometheus_forward_client.clone();
         let log_parser_config = self.log_parser_config.clone();
         let external_profile_integration_disabled = self.external_profile_integration_disabled;
         let external_trace_integration_disabled = self.external_trace_integration_disabled;
@@ -1100,6 +1392,7 @@ impl MetricServer {
                     let policy_getter = policy_getter.clone();
                     let time_diff = time_diff.clone();
                     let prometheus_extra_config = prometheus_extra_config.clone();
+                    let prometheus_forward_client = prometheus_forward_client.clone();
                     let log_parser_config = log_parser_config.clone();
                     let service = make_service_fn(move |conn: &AddrStream| {
                         let otel_sender = otel_sender.clone();
@@ -1117,6 +1410,7 @@ impl MetricServer {
                         let policy_getter = policy_getter.clone();
                         let time_diff = time_diff.clone();
                         let prometheus_extra_config = prometheus_extra_config.clone();
+                        let prometheus_forward_client = prometheus_forward_client.clone();
                         let log_parser_config = log_parser_config.clone();
                         let flow_id = Arc::new(AtomicU64::new(0));
                         async move {
@@ -1138,6 +1432,7 @@ impl MetricServer {
                                     policy_getter.clone(),
                                     time_diff.clone(),
                                     prometheus_extra_config.clone(),
+                                    prometheus_forward_client.clone(),
                                     log_parser_config.clone(),
                                     flow_id.clone(),
                                     external_profile_integration_disabled,
@@ -1177,10 +1472,89 @@ impl MetricServer {
         if let Some(t) = self.thread.lock().unwrap().take() {
             t.abort();
         }
+        if let Some(t) = self.jaeger_thread.lock().unwrap().take() {
+            t.abort();
+        }
 
         info!("integration collector stopped");
     }
 
+    // jaeger clients and jaeger-agent send spans in UDP compact thrift, which can not be
+    // served by the HTTP server, so a separated socket is listened
+    fn start_jaeger_compact_receiver(&self) {
+        let port = self.jaeger_compact_port;
+        let otel_sender = self.otel_sender.clone();
+        let compressed_otel_sender = self.compressed_otel_sender.clone();
+        let otel_l7_stats_sender = self.otel_l7_stats_sender.clone();
+        let exception_handler = self.exception_handler.clone();
+        let counter = self.counter.clone();
+        let compressed = self.compressed.clone();
+        let local_epc_id = self.local_epc_id;
+        let policy_getter = self.policy_getter.clone();
+        let time_diff = self.time_diff.clone();
+        let log_parser_config = self.log_parser_config.clone();
+        let flow_id = Arc::new(AtomicU64::new(0));
+
+        self.jaeger_thread
+            .lock()
+            .unwrap()
+            .replace(self.runtime.spawn(async move {
+                let addr: SocketAddr = if ipv6_enabled() {
+                    (Ipv6Addr::UNSPECIFIED, port).into()
+                } else {
+                    (Ipv4Addr::UNSPECIFIED, port).into()
+                };
+                let socket = match UdpSocket::bind(addr).await {
+                    Ok(s) => s,
+                    Err(e) => {
+                        error!("jaeger compact receiver error: {} with addr={}", e, addr);
+                        exception_handler.set(Exception::IntegrationSocketError);
+                        return;
+                    }
+                };
+                info!("jaeger compact receiver listening on udp://{}", addr);
+                let mut buf = vec![0u8; JAEGER_UDP_PACKET_SIZE];
+                loop {
+                    let (n, peer_addr) = match socket.recv_from(&mut buf).await {
+                        Ok(r) => r,
+                        Err(e) => {
+                            warn!("jaeger compact receiver recv error: {}", e);
+                            continue;
+                        }
+                    };
+                    let data = match jaeger::decode_compact_thrift(&buf[..n]) {
+                        Ok(d) => d,
+                        Err(e) => {
+                            debug!(
+                                "decode jaeger compact thrift from {} error: {}",
+                                peer_addr, e
+                            );
+                            continue;
+                        }
+                    };
+                    let decode_data = handle_otel_trace_data(
+                        peer_addr,
+                        data,
+                        local_epc_id,
+                        policy_getter.clone(),
+                        time_diff.load(Ordering::Relaxed),
+                        flow_id.clone(),
+                        log_parser_config.clone(),
+                    );
+                    if let Err(e) = send_otel_trace_data(
+                        decode_data,
+                        compressed.load(Ordering::Relaxed),
+                        &counter,
+                        &otel_sender,
+                        &compressed_otel_sender,
+                        &otel_l7_stats_sender,
+                    ) {
+                        warn!("jaeger compact receiver send error: {}", e);
+                    }
+                }
+            }));
+    }
+
     //FIXME: 现在integration collector 在K8S环境下，会概率性出现监听端口一段时间后会失去监听。所以先探测下发的端口是否监听，
     // 没监听的话重启collector再监听。等找到根因后再去掉下面的代码
     // =============================================
@@ -0,0 +1,732 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Converts spans in Jaeger wire formats to OpenTelemetry TracesData, so that
+// they share the same processing with OpenTelemetry traces
+//
+// Supported formats:
+// - UDP compact thrift used by jaeger-agent and jaeger client SDKs, `emitBatch` of
+//   https://github.com/jaegertracing/jaeger-idl/blob/main/thrift/agent.thrift
+// - gRPC `PostSpans` of jaeger-collector, only uncompressed messages are supported
+
+use thiserror::Error;
+
+use public::proto::integration::{
+    jaeger::api_v2 as jaeger,
+    opentelemetry::proto::{
+        common::v1::{any_value::Value, AnyValue, KeyValue},
+        resource::v1::Resource,
+        trace::v1::{
+            span::{Event, SpanKind},
+            status::StatusCode,
+            ResourceSpans, ScopeSpans, Span, Status, TracesData,
+        },
+    },
+};
+
+pub const GRPC_POST_SPANS_PATH: &str = "/jaeger.api_v2.CollectorService/PostSpans";
+
+const COMPACT_PROTOCOL_ID: u8 = 0x82;
+const COMPACT_VERSION: u8 = 1;
+const EMIT_BATCH: &str = "emitBatch";
+
+// jaeger thrift TagType
+const TAG_TYPE_STRING: i32 = 0;
+const TAG_TYPE_DOUBLE: i32 = 1;
+const TAG_TYPE_BOOL: i32 = 2;
+const TAG_TYPE_LONG: i32 = 3;
+const TAG_TYPE_BINARY: i32 = 4;
+
+// jaeger thrift SpanRefType
+const REF_TYPE_CHILD_OF: i32 = 0;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Error {
+    #[error("unexpected end of data")]
+    Eof,
+    #[error("invalid thrift data: {0}")]
+    InvalidThrift(String),
+    #[error("invalid grpc frame: {0}")]
+    InvalidGrpcFrame(&'static str),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+// thrift compact protocol types
+const CT_BOOLEAN_TRUE: u8 = 1;
+const CT_BOOLEAN_FALSE: u8 = 2;
+const CT_BYTE: u8 = 3;
+const CT_I16: u8 = 4;
+const CT_I32: u8 = 5;
+const CT_I64: u8 = 6;
+const CT_DOUBLE: u8 = 7;
+const CT_BINARY: u8 = 8;
+const CT_LIST: u8 = 9;
+const CT_SET: u8 = 10;
+const CT_MAP: u8 = 11;
+const CT_STRUCT: u8 = 12;
+
+// nesting limit of skipped values, avoids stack overflow on malicious packets
+const MAX_DEPTH: usize = 32;
+
+struct CompactReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> CompactReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        let b = *self.buf.get(self.pos).ok_or(Error::Eof)?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn read_varint(&mut self) -> Result<u64> {
+        let mut result = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.read_u8()?;
+            result |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+        Err(Error::InvalidThrift("varint too long".to_owned()))
+    }
+
+    fn read_i64(&mut self) -> Result<i64> {
+        let n = self.read_varint()?;
+        Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+    }
+
+    fn read_i32(&mut self) -> Result<i32> {
+        Ok(self.read_i64()? as i32)
+    }
+
+    fn read_double(&mut self) -> Result<f64> {
+        let end = self.pos + 8;
+        let bytes = self.buf.get(self.pos..end).ok_or(Error::Eof)?;
+        self.pos = end;
+        Ok(f64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_binary(&mut self) -> Result<&'a [u8]> {
+        let len = self.read_varint()? as usize;
+        let end = self.pos.checked_add(len).ok_or(Error::Eof)?;
+        let bytes = self.buf.get(self.pos..end).ok_or(Error::Eof)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn read_string(&mut self) -> Result<String> {
+        Ok(String::from_utf8_lossy(self.read_binary()?).into_owned())
+    }
+
+    // returns (element type, size)
+    fn read_list_header(&mut self) -> Result<(u8, usize)> {
+        let b = self.read_u8()?;
+        let size = match b >> 4 {
+            15 => self.read_varint()? as usize,
+            n => n as usize,
+        };
+        // each element takes at least one byte
+        if size > self.buf.len() - self.pos {
+            return Err(Error::Eof);
+        }
+        Ok((b & 0x0f, size))
+    }
+
+    // calls f with (field id, field type) for each field until stop
+    fn read_struct<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&mut Self, i16, u8) -> Result<()>,
+    {
+        let mut last_id = 0i16;
+        loop {
+            let b = self.read_u8()?;
+            if b == 0 {
+                return Ok(());
+            }
+            let field_type = b & 0x0f;
+            let id = match b >> 4 {
+                0 => self.read_i32()? as i16,
+                delta => last_id.wrapping_add(delta as i16),
+            };
+            last_id = id;
+            f(self, id, field_type)?;
+        }
+    }
+
+    fn read_list<T, F>(&mut self, mut f: F) -> Result<Vec<T>>
+    where
+        F: FnMut(&mut Self) -> Result<T>,
+    {
+        let (elem_type, size) = self.read_list_header()?;
+        if elem_type != CT_STRUCT {
+            return Err(Error::InvalidThrift(format!(
+                "expect list of struct, got element type {}",
+                elem_type
+            )));
+        }
+        let mut v = Vec::with_capacity(size);
+        for _ in 0..size {
+            v.push(f(self)?);
+        }
+        Ok(v)
+    }
+
+    fn skip(&mut self, field_type: u8) -> Result<()> {
+        self.skip_with_depth(field_type, 0)
+    }
+
+    fn skip_with_depth(&mut self, field_type: u8, depth: usize) -> Result<()> {
+        if depth > MAX_DEPTH {
+            return Err(Error::InvalidThrift("nesting too deep".to_owned()));
+        }
+        match field_type {
+            CT_BOOLEAN_TRUE | CT_BOOLEAN_FALSE => (),
+            CT_BYTE => {
+                self.read_u8()?;
+            }
+            CT_I16 | CT_I32 | CT_I64 => {
+                self.read_varint()?;
+            }
+            CT_DOUBLE => {
+                self.read_double()?;
+            }
+            CT_BINARY => {
+                self.read_binary()?;
+            }
+            CT_LIST | CT_SET => {
+                let (elem_type, size) = self.read_list_header()?;
+                for _ in 0..size {
+                    self.skip_collection_elem(elem_type, depth + 1)?;
+                }
+            }
+            CT_MAP => {
+                let size = self.read_varint()? as usize;
+                if size > 0 {
+                    let types = self.read_u8()?;
+                    for _ in 0..size {
+                        self.skip_collection_elem(types >> 4, depth + 1)?;
+                        self.skip_collection_elem(types & 0x0f, depth + 1)?;
+                    }
+                }
+            }
+            CT_STRUCT => self.read_struct(|r, _, t| r.skip_with_depth(t, depth + 1))?,
+            t => return Err(Error::InvalidThrift(format!("unknown type {}", t))),
+        }
+        Ok(())
+    }
+
+    fn skip_collection_elem(&mut self, elem_type: u8, depth: usize) -> Result<()> {
+        match elem_type {
+            // bool elements in collections take one byte
+            CT_BOOLEAN_TRUE | CT_BOOLEAN_FALSE => self.read_u8().map(|_| ()),
+            t => self.skip_with_depth(t, depth),
+        }
+    }
+}
+
+fn expect_type(field_type: u8, expected: u8) -> Result<()> {
+    if field_type == expected {
+        Ok(())
+    } else {
+        Err(Error::InvalidThrift(format!(
+            "expect type {}, got {}",
+            expected, field_type
+        )))
+    }
+}
+
+fn string_kv(key: &str, value: String) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue {
+            value: Some(Value::StringValue(value)),
+        }),
+    }
+}
+
+fn read_tag(r: &mut CompactReader) -> Result<KeyValue> {
+    let mut key = String::new();
+    let mut tag_type = TAG_TYPE_STRING;
+    let (mut v_str, mut v_double, mut v_bool, mut v_long, mut v_binary) =
+        (None, None, None, None, None);
+    r.read_struct(|r, id, t| {
+        match (id, t) {
+            (1, CT_BINARY) => key = r.read_string()?,
+            (2, CT_I32) => tag_type = r.read_i32()?,
+            (3, CT_BINARY) => v_str = Some(r.read_string()?),
+            (4, CT_DOUBLE) => v_double = Some(r.read_double()?),
+            (5, CT_BOOLEAN_TRUE) => v_bool = Some(true),
+            (5, CT_BOOLEAN_FALSE) => v_bool = Some(false),
+            (6, CT_I64) => v_long = Some(r.read_i64()?),
+            (7, CT_BINARY) => v_binary = Some(r.read_binary()?.to_vec()),
+            _ => r.skip(t)?,
+        }
+        Ok(())
+    })?;
+    let value = match tag_type {
+        TAG_TYPE_DOUBLE => v_double.map(Value::DoubleValue),
+        TAG_TYPE_BOOL => v_bool.map(Value::BoolValue),
+        TAG_TYPE_LONG => v_long.map(Value::IntValue),
+        TAG_TYPE_BINARY => v_binary.map(Value::BytesValue),
+        _ => v_str.map(Value::StringValue),
+    };
+    Ok(KeyValue {
+        key,
+        value: Some(AnyValue { value }),
+    })
+}
+
+fn read_tags(r: &mut CompactReader, field_type: u8) -> Result<Vec<KeyValue>> {
+    expect_type(field_type, CT_LIST)?;
+    r.read_list(read_tag)
+}
+
+fn read_process(r: &mut CompactReader) -> Result<Resource> {
+    let mut resource = Resource::default();
+    r.read_struct(|r, id, t| {
+        match (id, t) {
+            (1, CT_BINARY) => resource
+                .attributes
+                .push(string_kv("service.name", r.read_string()?)),
+            (2, _) => resource.attributes.extend(read_tags(r, t)?),
+            _ => r.skip(t)?,
+        }
+        Ok(())
+    })?;
+    Ok(resource)
+}
+
+fn read_log(r: &mut CompactReader) -> Result<Event> {
+    let mut event = Event::default();
+    r.read_struct(|r, id, t| {
+        match (id, t) {
+            (1, CT_I64) => event.time_unix_nano = r.read_i64()? as u64 * 1000,
+            (2, _) => event.attributes = read_tags(r, t)?,
+            _ => r.skip(t)?,
+        }
+        Ok(())
+    })?;
+    event.name = event_name(&event.attributes);
+    Ok(event)
+}
+
+fn read_span_ref(r: &mut CompactReader) -> Result<(i32, i64)> {
+    let (mut ref_type, mut span_id) = (REF_TYPE_CHILD_OF, 0);
+    r.read_struct(|r, id, t| {
+        match (id, t) {
+            (1, CT_I32) => ref_type = r.read_i32()?,
+            (4, CT_I64) => span_id = r.read_i64()?,
+            _ => r.skip(t)?,
+        }
+        Ok(())
+    })?;
+    Ok((ref_type, span_id))
+}
+
+fn read_span(r: &mut CompactReader) -> Result<Span> {
+    let (mut trace_id_low, mut trace_id_high, mut span_id, mut parent_span_id) = (0, 0, 0, 0);
+    let (mut start_time, mut duration) = (0, 0);
+    let mut span = Span::default();
+    r.read_struct(|r, id, t| {
+        match (id, t) {
+            (1, CT_I64) => trace_id_low = r.read_i64()?,
+            (2, CT_I64) => trace_id_high = r.read_i64()?,
+            (3, CT_I64) => span_id = r.read_i64()?,
+            (4, CT_I64) => parent_span_id = r.read_i64()?,
+            (5, CT_BINARY) => span.name = r.read_string()?,
+            (6, CT_LIST) => {
+                // parentSpanId is 0 if the parent is only in references
+                for (ref_type, id) in r.read_list(read_span_ref)? {
+                    if parent_span_id == 0 && ref_type == REF_TYPE_CHILD_OF {
+                        parent_span_id = id;
+                    }
+                }
+            }
+            (8, CT_I64) => start_time = r.read_i64()?,
+            (9, CT_I64) => duration = r.read_i64()?,
+            (10, _) => span.attributes = read_tags(r, t)?,
+            (11, CT_LIST) => span.events = r.read_list(read_log)?,
+            _ => r.skip(t)?,
+        }
+        Ok(())
+    })?;
+
+    let mut trace_id = Vec::with_capacity(16);
+    trace_id.extend_from_slice(&trace_id_high.to_be_bytes());
+    trace_id.extend_from_slice(&trace_id_low.to_be_bytes());
+    span.trace_id = trace_id;
+    span.span_id = span_id.to_be_bytes().to_vec();
+    if parent_span_id != 0 {
+        span.parent_span_id = parent_span_id.to_be_bytes().to_vec();
+    }
+    // jaeger timestamps are in microseconds
+    span.start_time_unix_nano = start_time as u64 * 1000;
+    span.end_time_unix_nano = (start_time + duration) as u64 * 1000;
+    fill_kind_and_status(&mut span);
+    Ok(span)
+}
+
+fn read_batch(r: &mut CompactReader) -> Result<ResourceSpans> {
+    let mut resource = None;
+    let mut spans = vec![];
+    r.read_struct(|r, id, t| {
+        match (id, t) {
+            (1, CT_STRUCT) => resource = Some(read_process(r)?),
+            (2, CT_LIST) => spans = r.read_list(read_span)?,
+            _ => r.skip(t)?,
+        }
+        Ok(())
+    })?;
+    Ok(ResourceSpans {
+        resource,
+        scope_spans: vec![ScopeSpans {
+            spans,
+            ..Default::default()
+        }],
+        ..Default::default()
+    })
+}
+
+// Decodes a UDP packet of `Agent::emitBatch` in thrift compact protocol
+pub fn decode_compact_thrift(data: &[u8]) -> Result<TracesData> {
+    let mut r = CompactReader::new(data);
+    if r.read_u8()? != COMPACT_PROTOCOL_ID {
+        return Err(Error::InvalidThrift("not compact protocol".to_owned()));
+    }
+    if r.read_u8()? & 0x1f != COMPACT_VERSION {
+        return Err(Error::InvalidThrift("unsupported version".to_owned()));
+    }
+    let _seq_id = r.read_varint()?;
+    let name = r.read_string()?;
+    if name != EMIT_BATCH {
+        return Err(Error::InvalidThrift(format!("unsupported method {}", name)));
+    }
+    let mut resource_spans = vec![];
+    r.read_struct(|r, id, t| {
+        match (id, t) {
+            (1, CT_STRUCT) => resource_spans.push(read_batch(r)?),
+            _ => r.skip(t)?,
+        }
+        Ok(())
+    })?;
+    Ok(TracesData { resource_spans })
+}
+
+// Strips the 5 bytes length-prefixed message header of gRPC
+pub fn decode_grpc_frame(data: &[u8]) -> Result<&[u8]> {
+    if data.len() < 5 {
+        return Err(Error::InvalidGrpcFrame("too short"));
+    }
+    if data[0] != 0 {
+        return Err(Error::InvalidGrpcFrame("compressed message not supported"));
+    }
+    let len = u32::from_be_bytes(data[1..5].try_into().unwrap()) as usize;
+    data.get(5..5 + len)
+        .ok_or(Error::InvalidGrpcFrame("length mismatch"))
+}
+
+pub fn encode_grpc_frame(message: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(5 + message.len());
+    frame.push(0);
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message);
+    frame
+}
+
+fn convert_key_value(kv: jaeger::KeyValue) -> KeyValue {
+    let value = match jaeger::ValueType::from_i32(kv.v_type) {
+        Some(jaeger::ValueType::Bool) => Value::BoolValue(kv.v_bool),
+        Some(jaeger::ValueType::Int64) => Value::IntValue(kv.v_int64),
+        Some(jaeger::ValueType::Float64) => Value::DoubleValue(kv.v_float64),
+        Some(jaeger::ValueType::Binary) => Value::BytesValue(kv.v_binary),
+        _ => Value::StringValue(kv.v_str),
+    };
+    KeyValue {
+        key: kv.key,
+        value: Some(AnyValue { value: Some(value) }),
+    }
+}
+
+fn convert_process(process: jaeger::Process) -> Resource {
+    let mut resource = Resource::default();
+    resource
+        .attributes
+        .push(string_kv("service.name", process.service_name));
+    resource
+        .attributes
+        .extend(process.tags.into_iter().map(convert_key_value));
+    resource
+}
+
+fn timestamp_nanos(ts: Option<prost_types::Timestamp>) -> u64 {
+    ts.map(|t| t.seconds as u64 * 1_000_000_000 + t.nanos as u64)
+        .unwrap_or_default()
+}
+
+fn convert_span(s: jaeger::Span) -> Span {
+    let start = timestamp_nanos(s.start_time);
+    let duration = s
+        .duration
+        .map(|d| d.seconds as u64 * 1_000_000_000 + d.nanos as u64)
+        .unwrap_or_default();
+    let parent_span_id = s
+        .references
+        .iter()
+        .find(|r| r.ref_type == jaeger::SpanRefType::ChildOf as i32)
+        .map(|r| r.span_id.clone())
+        .unwrap_or_default();
+    let mut span = Span {
+        trace_id: s.trace_id,
+        span_id: s.span_id,
+        parent_span_id,
+        name: s.operation_name,
+        start_time_unix_nano: start,
+        end_time_unix_nano: start + duration,
+        attributes: s.tags.into_iter().map(convert_key_value).collect(),
+        events: s
+            .logs
+            .into_iter()
+            .map(|log| {
+                let attributes = log
+                    .fields
+                    .into_iter()
+                    .map(convert_key_value)
+                    .collect::<Vec<_>>();
+                Event {
+                    time_unix_nano: timestamp_nanos(log.timestamp),
+                    name: event_name(&attributes),
+                    attributes,
+                    ..Default::default()
+                }
+            })
+            .collect(),
+        ..Default::default()
+    };
+    fill_kind_and_status(&mut span);
+    span
+}
+
+// Converts gRPC PostSpansRequest, spans with their own process are put into separated resources
+pub fn convert_post_spans(request: jaeger::PostSpansRequest) -> TracesData {
+    let Some(batch) = request.batch else {
+        return TracesData::default();
+    };
+    let mut batch_spans = vec![];
+    let mut resource_spans = vec![];
+    for mut span in batch.spans {
+        match span.process.take() {
+            Some(process) => resource_spans.push(ResourceSpans {
+                resource: Some(convert_process(process)),
+                scope_spans: vec![ScopeSpans {
+                    spans: vec![convert_span(span)],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            None => batch_spans.push(convert_span(span)),
+        }
+    }
+    if !batch_spans.is_empty() {
+        resource_spans.push(ResourceSpans {
+            resource: batch.process.map(convert_process),
+            scope_spans: vec![ScopeSpans {
+                spans: batch_spans,
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+    }
+    TracesData { resource_spans }
+}
+
+// opentracing logs use `event` field as the name
+fn event_name(attributes: &[KeyValue]) -> String {
+    attributes
+        .iter()
+        .find(|kv| kv.key == "event")
+        .and_then(
+            |kv| match kv.value.as_ref().and_then(|v| v.value.as_ref()) {
+                Some(Value::StringValue(s)) => Some(s.clone()),
+                _ => None,
+            },
+        )
+        .unwrap_or_else(|| "log".to_owned())
+}
+
+// Span kind and error are tags in jaeger
+fn fill_kind_and_status(span: &mut Span) {
+    let mut kind = SpanKind::Internal;
+    let mut error = false;
+    for kv in span.attributes.iter() {
+        match (
+            kv.key.as_str(),
+            kv.value.as_ref().and_then(|v| v.value.as_ref()),
+        ) {
+            ("span.kind", Some(Value::StringValue(s))) => {
+                kind = match s.as_str() {
+                    "server" => SpanKind::Server,
+                    "client" => SpanKind::Client,
+                    "producer" => SpanKind::Producer,
+                    "consumer" => SpanKind::Consumer,
+                    _ => SpanKind::Internal,
+                }
+            }
+            ("error", Some(Value::BoolValue(b))) => error = *b,
+            ("error", Some(Value::StringValue(s))) => error = s == "true",
+            _ => (),
+        }
+    }
+    span.kind = kind as i32;
+    if error {
+        span.status = Some(Status {
+            code: StatusCode::Error as i32,
+            ..Default::default()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emit_batch_packet() -> Vec<u8> {
+        let mut p = vec![COMPACT_PROTOCOL_ID, 0x81, 0x00, 0x09];
+        p.extend_from_slice(b"emitBatch");
+        // args.batch, batch.process
+        p.extend_from_slice(&[0x1c, 0x1c]);
+        // process.serviceName
+        p.extend_from_slice(&[0x18, 0x03]);
+        p.extend_from_slice(b"svc");
+        // process.tags with one string tag
+        p.extend_from_slice(&[0x19, 0x1c, 0x18, 0x02]);
+        p.extend_from_slice(b"ip");
+        p.extend_from_slice(&[0x15, 0x00, 0x18, 0x08]);
+        p.extend_from_slice(b"10.0.0.1");
+        p.extend_from_slice(&[0x00, 0x00]);
+        // batch.spans with one span
+        p.extend_from_slice(&[0x19, 0x1c]);
+        // traceIdLow 1, traceIdHigh 2, spanId 3, parentSpanId 0
+        p.extend_from_slice(&[0x16, 0x02, 0x16, 0x04, 0x16, 0x06, 0x16, 0x00]);
+        // operationName
+        p.extend_from_slice(&[0x18, 0x03]);
+        p.extend_from_slice(b"GET");
+        // flags 1, startTime 1000, duration 50
+        p.extend_from_slice(&[0x25, 0x02, 0x16, 0xd0, 0x0f, 0x16, 0x64]);
+        // tags: span.kind = server, error = true
+        p.extend_from_slice(&[0x19, 0x2c, 0x18, 0x09]);
+        p.extend_from_slice(b"span.kind");
+        p.extend_from_slice(&[0x15, 0x00, 0x18, 0x06]);
+        p.extend_from_slice(b"server");
+        p.extend_from_slice(&[0x00, 0x18, 0x05]);
+        p.extend_from_slice(b"error");
+        p.extend_from_slice(&[0x15, 0x04, 0x31, 0x00]);
+        // end of span, batch and args
+        p.extend_from_slice(&[0x00, 0x00, 0x00]);
+        p
+    }
+
+    #[test]
+    fn compact_thrift() {
+        let data = decode_compact_thrift(&emit_batch_packet()).unwrap();
+        assert_eq!(data.resource_spans.len(), 1);
+        let rs = &data.resource_spans[0];
+        let attrs = &rs.resource.as_ref().unwrap().attributes;
+        assert_eq!(attrs[0], string_kv("service.name", "svc".to_owned()));
+        assert_eq!(attrs[1], string_kv("ip", "10.0.0.1".to_owned()));
+
+        let span = &rs.scope_spans[0].spans[0];
+        assert_eq!(
+            span.trace_id,
+            [0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 1]
+        );
+        assert_eq!(span.span_id, [0, 0, 0, 0, 0, 0, 0, 3]);
+        assert!(span.parent_span_id.is_empty());
+        assert_eq!(span.name, "GET");
+        assert_eq!(span.start_time_unix_nano, 1_000_000);
+        assert_eq!(span.end_time_unix_nano, 1_050_000);
+        assert_eq!(span.kind, SpanKind::Server as i32);
+        assert_eq!(span.status.as_ref().unwrap().code, StatusCode::Error as i32);
+        assert_eq!(span.attributes.len(), 2);
+    }
+
+    #[test]
+    fn compact_thrift_truncated() {
+        let packet = emit_batch_packet();
+        for len in 0..packet.len() {
+            assert!(decode_compact_thrift(&packet[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn grpc_frame() {
+        let frame = encode_grpc_frame(b"abc");
+        assert_eq!(frame, [0, 0, 0, 0, 3, b'a', b'b', b'c']);
+        assert_eq!(decode_grpc_frame(&frame).unwrap(), b"abc");
+        assert!(decode_grpc_frame(&frame[..6]).is_err());
+        assert!(decode_grpc_frame(&[1, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn post_spans() {
+        let request = jaeger::PostSpansRequest {
+            batch: Some(jaeger::Batch {
+                spans: vec![jaeger::Span {
+                    trace_id: vec![1; 16],
+                    span_id: vec![2; 8],
+                    operation_name: "query".to_owned(),
+                    references: vec![jaeger::SpanRef {
+                        trace_id: vec![1; 16],
+                        span_id: vec![3; 8],
+                        ref_type: jaeger::SpanRefType::ChildOf as i32,
+                    }],
+                    start_time: Some(prost_types::Timestamp {
+                        seconds: 1,
+                        nanos: 0,
+                    }),
+                    duration: Some(prost_types::Duration {
+                        seconds: 0,
+                        nanos: 5000,
+                    }),
+                    tags: vec![jaeger::KeyValue {
+                        key: "span.kind".to_owned(),
+                        v_str: "client".to_owned(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                process: Some(jaeger::Process {
+                    service_name: "svc".to_owned(),
+                    tags: vec![],
+                }),
+            }),
+        };
+        let data = convert_post_spans(request);
+        assert_eq!(data.resource_spans.len(), 1);
+        let span = &data.resource_spans[0].scope_spans[0].spans[0];
+        assert_eq!(span.parent_span_id, vec![3; 8]);
+        assert_eq!(span.start_time_unix_nano, 1_000_000_000);
+        assert_eq!(span.end_time_unix_nano, 1_000_005_000);
+        assert_eq!(span.kind, SpanKind::Client as i32);
+        assert!(span.status.is_none());
+    }
+}
@@ -25,6 +25,11 @@ use signal_hook::{consts::TERM_SIGNALS, iterator::Signals};
 
 use ::deepflow_agent::*;
 
+// allocations are sampled only when the heap self profile is running
+#[cfg(target_os = "linux")]
+#[global_allocator]
+static ALLOCATOR: utils::self_profiler::SamplingAllocator = utils::self_profiler::SamplingAllocator;
+
 #[derive(Parser)]
 struct Opts {
     /// Specify config file location
@@ -70,6 +75,16 @@ struct Opts {
     /// optionally `K8S_POD_IP_FOR_DEEPFLOW` can be set to override ip address.
     #[clap(long)]
     sidecar: bool,
+
+    /// Check types, ranges and constraints of the specified config file and exit.
+    /// Use with '--standalone' to validate standalone config files
+    #[clap(long, value_name = "FILE")]
+    validate_config: Option<String>,
+
+    /// Print effective static configuration merged from local config file and defaults in YAML,
+    /// then exit. Configuration from controller is not included
+    #[clap(long)]
+    dump_effective_config: bool,
 }
 
 #[cfg(unix)]
@@ -108,14 +123,27 @@ fn main() -> Result<()> {
         println!("{}", VERSION_INFO);
         return Ok(());
     }
+    let agent_mode = if opts.standalone {
+        trident::RunningMode::Standalone
+    } else {
+        trident::RunningMode::Managed
+    };
+    if let Some(path) = opts.validate_config.as_ref() {
+        trident::validate_config(Path::new(path), agent_mode)?;
+        println!("config file {} is valid", path);
+        return Ok(());
+    }
+    if opts.dump_effective_config {
+        print!(
+            "{}",
+            trident::dump_effective_config(Path::new(&opts.config_file), agent_mode)?
+        );
+        return Ok(());
+    }
     let mut t = trident::Trident::start(
         &Path::new(&opts.config_file),
         VERSION_INFO,
-        if opts.standalone {
-            trident::RunningMode::Standalone
-        } else {
-            trident::RunningMode::Managed
-        },
+        agent_mode,
         opts.sidecar,
     )?;
     wait_on_signals();
@@ -18,6 +18,8 @@ use std::mem::swap;
 
 use public::proto::metric;
 
+use crate::utils::sketch::Sketch;
+
 const FLOW_ID: u32 = 1;
 const USAGE_ID: u32 = 4;
 const APP_ID: u32 = 5;
@@ -356,6 +358,13 @@ pub struct Anomaly {
     pub l7_client_error: u32,
     pub l7_server_error: u32,
     pub l7_timeout: u32,
+
+    pub icmp_unreachable: u64,
+    pub icmp_port_unreachable: u64,
+    pub icmp_fragmentation_needed: u64,
+    pub icmp_admin_prohibited: u64,
+    pub icmp_time_exceeded: u64,
+    pub icmp_redirect: u64,
 }
 
 impl Anomaly {
@@ -377,6 +386,13 @@ impl Anomaly {
         self.l7_client_error += other.l7_client_error;
         self.l7_server_error += other.l7_server_error;
         self.l7_timeout += other.l7_timeout;
+
+        self.icmp_unreachable += other.icmp_unreachable;
+        self.icmp_port_unreachable += other.icmp_port_unreachable;
+        self.icmp_fragmentation_needed += other.icmp_fragmentation_needed;
+        self.icmp_admin_prohibited += other.icmp_admin_prohibited;
+        self.icmp_time_exceeded += other.icmp_time_exceeded;
+        self.icmp_redirect += other.icmp_redirect;
     }
 }
 
@@ -400,6 +416,13 @@ impl From<Anomaly> for metric::Anomaly {
             l7_client_error: m.l7_client_error,
             l7_server_error: m.l7_server_error,
             l7_timeout: m.l7_timeout,
+
+            icmp_unreachable: m.icmp_unreachable,
+            icmp_port_unreachable: m.icmp_port_unreachable,
+            icmp_fragmentation_needed: m.icmp_fragmentation_needed,
+            icmp_admin_prohibited: m.icmp_admin_prohibited,
+            icmp_time_exceeded: m.icmp_time_exceeded,
+            icmp_redirect: m.icmp_redirect,
         }
     }
 }
@@ -489,6 +512,8 @@ pub struct AppLatency {
     pub rrt_max: u32,
     pub rrt_sum: u64,
     pub rrt_count: u32,
+    // mergeable across flows, so that percentiles are not derived from averages
+    pub rrt_sketch: Option<Sketch>,
 }
 
 impl AppLatency {
@@ -498,6 +523,11 @@ impl AppLatency {
         }
         self.rrt_sum += other.rrt_sum;
         self.rrt_count += other.rrt_count;
+        match (self.rrt_sketch.as_mut(), other.rrt_sketch.as_ref()) {
+            (Some(s), Some(o)) => s.merge(o),
+            (None, Some(o)) => self.rrt_sketch = Some(*o),
+            _ => (),
+        }
     }
 }
 
@@ -507,6 +537,15 @@ impl From<AppLatency> for metric::AppLatency {
             rrt_max: m.rrt_max,
             rrt_sum: m.rrt_sum,
             rrt_count: m.rrt_count,
+            rrt_sketch: m.rrt_sketch.filter(|s| !s.is_empty()).map(|s| {
+                let (offset, bins) = s.bins();
+                metric::Sketch {
+                    relative_accuracy: s.relative_accuracy(),
+                    offset,
+                    zero_count: s.zero_count(),
+                    bins: bins.to_vec(),
+                }
+            }),
         }
     }
 }
@@ -0,0 +1,262 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use arc_swap::ArcSwap;
+use log::{debug, info, warn};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use public::utils::net::conntrack_list;
+pub use public::utils::net::{ConntrackEntry, ConntrackTuple};
+
+use crate::common::flow::FlowKey;
+
+const MIN_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+// Real addresses of the peers of a connection observed in client to server direction
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NatMapping {
+    pub src: Option<(IpAddr, u16)>,
+    pub dst: Option<(IpAddr, u16)>,
+}
+
+impl NatMapping {
+    fn reversed(self) -> Self {
+        Self {
+            src: self.dst,
+            dst: self.src,
+        }
+    }
+}
+
+type Mappings = HashMap<ConntrackTuple, NatMapping>;
+
+// Both tuples of NATed connections are indexed, so that flows captured before
+// NAT (e.g. on pod veth) get the real server address after DNAT, and flows captured
+// after NAT (e.g. on node uplink or backend pod) get the real client address before SNAT.
+fn build_mappings(entries: &[ConntrackEntry]) -> Mappings {
+    let mut mappings = HashMap::new();
+    for e in entries.iter().filter(|e| e.is_nat()) {
+        let (orig, reply) = (&e.original, &e.reply);
+        if (reply.src, reply.src_port) != (orig.dst, orig.dst_port) {
+            mappings.insert(
+                *orig,
+                NatMapping {
+                    src: None,
+                    dst: Some((reply.src, reply.src_port)),
+                },
+            );
+        }
+        if (reply.dst, reply.dst_port) != (orig.src, orig.src_port) {
+            mappings.insert(
+                reply.reversed(),
+                NatMapping {
+                    src: Some((orig.src, orig.src_port)),
+                    dst: None,
+                },
+            );
+        }
+    }
+    mappings
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn list_entries() -> public::utils::net::Result<Vec<ConntrackEntry>> {
+    conntrack_list()
+}
+
+#[cfg(target_os = "windows")]
+fn list_entries() -> public::utils::net::Result<Vec<ConntrackEntry>> {
+    Ok(vec![])
+}
+
+// Periodically dumps kernel conntrack table to correlate pre-NAT and post-NAT
+// tuples of connections through kube-proxy, IPVS or SNAT gateways
+pub struct ConntrackTable {
+    interval: Duration,
+    mappings: Arc<ArcSwap<Mappings>>,
+    running: Arc<Mutex<bool>>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    timer: Arc<Condvar>,
+}
+
+impl ConntrackTable {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval: interval.max(MIN_SYNC_INTERVAL),
+            mappings: Default::default(),
+            running: Arc::new(Mutex::new(false)),
+            thread: Mutex::new(None),
+            timer: Arc::new(Condvar::new()),
+        }
+    }
+
+    fn refresh(mappings: &ArcSwap<Mappings>) {
+        match list_entries() {
+            Ok(entries) => {
+                let m = build_mappings(&entries);
+                debug!(
+                    "conntrack table refreshed with {} entries, {} nat mappings",
+                    entries.len(),
+                    m.len()
+                );
+                mappings.store(Arc::new(m));
+            }
+            Err(e) => warn!("dump conntrack table failed: {}", e),
+        }
+    }
+
+    pub fn start(&self) {
+        let mut running_lock = self.running.lock().unwrap();
+        if *running_lock {
+            return;
+        }
+        *running_lock = true;
+        drop(running_lock);
+
+        let interval = self.interval;
+        let mappings = self.mappings.clone();
+        let running = self.running.clone();
+        let timer = self.timer.clone();
+        *self.thread.lock().unwrap() = Some(
+            thread::Builder::new()
+                .name("conntrack-table".to_owned())
+                .spawn(move || loop {
+                    Self::refresh(&mappings);
+
+                    let guard = running.lock().unwrap();
+                    if !*guard {
+                        break;
+                    }
+                    let (guard, _) = timer.wait_timeout(guard, interval).unwrap();
+                    if !*guard {
+                        break;
+                    }
+                })
+                .unwrap(),
+        );
+        info!("conntrack table started");
+    }
+
+    pub fn stop(&self) {
+        let mut running_lock = self.running.lock().unwrap();
+        if !*running_lock {
+            return;
+        }
+        *running_lock = false;
+        drop(running_lock);
+        self.timer.notify_one();
+
+        if let Some(handle) = self.thread.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+        self.mappings.store(Default::default());
+        info!("conntrack table stopped");
+    }
+
+    // Returns real addresses of flow peers in the same order as the flow key
+    pub fn lookup(&self, key: &FlowKey) -> Option<NatMapping> {
+        let mappings = self.mappings.load();
+        if mappings.is_empty() {
+            return None;
+        }
+        let tuple = ConntrackTuple {
+            src: key.ip_src,
+            dst: key.ip_dst,
+            src_port: key.port_src,
+            dst_port: key.port_dst,
+            proto: key.proto.into(),
+        };
+        if let Some(m) = mappings.get(&tuple) {
+            return Some(*m);
+        }
+        mappings.get(&tuple.reversed()).map(|m| m.reversed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use public::enums::IpProtocol;
+
+    fn tuple(src: [u8; 4], src_port: u16, dst: [u8; 4], dst_port: u16) -> ConntrackTuple {
+        ConntrackTuple {
+            src: IpAddr::from(src),
+            dst: IpAddr::from(dst),
+            src_port,
+            dst_port,
+            proto: IpProtocol::TCP.into(),
+        }
+    }
+
+    fn flow_key(t: &ConntrackTuple) -> FlowKey {
+        FlowKey {
+            ip_src: t.src,
+            ip_dst: t.dst,
+            port_src: t.src_port,
+            port_dst: t.dst_port,
+            proto: IpProtocol::TCP,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn nat_mappings() {
+        // pod 10.0.0.1 -> service 10.96.0.10:80, DNATed to 10.244.1.5:8080 and SNATed to 192.168.0.1
+        let original = tuple([10, 0, 0, 1], 40000, [10, 96, 0, 10], 80);
+        let reply = tuple([10, 244, 1, 5], 8080, [192, 168, 0, 1], 50000);
+        let no_nat = tuple([10, 0, 0, 1], 40001, [10, 0, 0, 2], 80);
+        let entries = [
+            ConntrackEntry { original, reply },
+            ConntrackEntry {
+                original: no_nat,
+                reply: no_nat.reversed(),
+            },
+        ];
+        let table = ConntrackTable::new(Duration::from_secs(5));
+        assert_eq!(table.lookup(&flow_key(&original)), None);
+        table.mappings.store(Arc::new(build_mappings(&entries)));
+        assert_eq!(table.mappings.load().len(), 2);
+
+        // captured on pod veth before NAT
+        let m = table.lookup(&flow_key(&original)).unwrap();
+        assert_eq!(m.src, None);
+        assert_eq!(m.dst, Some((IpAddr::from([10, 244, 1, 5]), 8080)));
+        // reversed flow key
+        let m = table.lookup(&flow_key(&original.reversed())).unwrap();
+        assert_eq!(m.src, Some((IpAddr::from([10, 244, 1, 5]), 8080)));
+        assert_eq!(m.dst, None);
+
+        // captured on node uplink after NAT
+        let m = table.lookup(&flow_key(&reply.reversed())).unwrap();
+        assert_eq!(m.src, Some((IpAddr::from([10, 0, 0, 1]), 40000)));
+        assert_eq!(m.dst, None);
+
+        assert_eq!(table.lookup(&flow_key(&no_nat)), None);
+        let unknown = tuple([10, 0, 0, 3], 1, Ipv4Addr::LOCALHOST.octets(), 2);
+        assert_eq!(table.lookup(&flow_key(&unknown)), None);
+    }
+}
@@ -0,0 +1,275 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::hash::Hasher;
+use std::sync::Arc;
+
+use ahash::AHasher;
+use bollard::{
+    container::ListContainersOptions,
+    models::{ContainerSummary, LocalNodeState},
+    network::ListNetworksOptions,
+    service::ListServicesOptions,
+    Docker, API_DEFAULT_VERSION,
+};
+use log::{debug, info, warn};
+use tokio::runtime::Runtime;
+
+use public::utils::net::MacAddr;
+
+const DOCKER_API_TIMEOUT: u64 = 10; // s
+
+pub const IF_TYPE_DOCKER: &str = "docker";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DockerInterface {
+    pub mac: MacAddr,
+    // ip/masklen
+    pub ips: Vec<String>,
+    pub network: String,
+    pub container_id: String,
+    pub container_name: String,
+}
+
+#[derive(Debug, Default)]
+pub struct DockerData {
+    // json of docker api responses
+    pub raw_containers: String,
+    pub raw_networks: String,
+    // only available on swarm managers
+    pub raw_services: Option<String>,
+
+    pub interfaces: Vec<DockerInterface>,
+}
+
+// Discovers containers, networks and swarm services from docker api on hosts
+// without kubernetes, so that containers can be tagged by controller
+pub struct DockerDiscovery {
+    runtime: Arc<Runtime>,
+    socket_path: String,
+    docker: Option<Docker>,
+}
+
+impl DockerDiscovery {
+    pub fn new(runtime: Arc<Runtime>) -> Self {
+        Self {
+            runtime,
+            socket_path: String::new(),
+            docker: None,
+        }
+    }
+
+    fn connect(&mut self, socket_path: &str) -> Option<&Docker> {
+        if self.socket_path != socket_path {
+            self.docker = None;
+            self.socket_path = socket_path.to_owned();
+        }
+        if self.docker.is_none() {
+            match Docker::connect_with_socket(socket_path, DOCKER_API_TIMEOUT, API_DEFAULT_VERSION)
+            {
+                Ok(docker) => {
+                    info!("connected to docker api {}", socket_path);
+                    self.docker = Some(docker);
+                }
+                Err(e) => {
+                    warn!("connect to docker api {} failed: {}", socket_path, e);
+                    return None;
+                }
+            }
+        }
+        self.docker.as_ref()
+    }
+
+    // returns None if docker api is unavailable
+    pub fn query(&mut self, socket_path: &str) -> Option<DockerData> {
+        let runtime = self.runtime.clone();
+        let docker = self.connect(socket_path)?.clone();
+        let result = runtime.block_on(async {
+            let containers = docker
+                .list_containers(Some(ListContainersOptions::<String> {
+                    all: true,
+                    ..Default::default()
+                }))
+                .await?;
+            let networks = docker
+                .list_networks(None::<ListNetworksOptions<String>>)
+                .await?;
+            let info = docker.info().await?;
+            let is_swarm_manager = info
+                .swarm
+                .as_ref()
+                .map(|s| {
+                    s.local_node_state == Some(LocalNodeState::ACTIVE)
+                        && s.control_available == Some(true)
+                })
+                .unwrap_or(false);
+            let services = if is_swarm_manager {
+                Some(
+                    docker
+                        .list_services(None::<ListServicesOptions<String>>)
+                        .await?,
+                )
+            } else {
+                None
+            };
+            Ok::<_, bollard::errors::Error>((containers, networks, services))
+        });
+        let (mut containers, mut networks, services) = match result {
+            Ok(r) => r,
+            Err(e) => {
+                warn!("query docker api {} failed: {}", socket_path, e);
+                // reconnect on next query
+                self.docker = None;
+                return None;
+            }
+        };
+        containers.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+        networks.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+        debug!(
+            "docker api returned {} containers {} networks {} services",
+            containers.len(),
+            networks.len(),
+            services.as_ref().map(|s| s.len()).unwrap_or_default()
+        );
+
+        Some(DockerData {
+            raw_containers: serde_json::to_string(&containers).unwrap_or_default(),
+            raw_networks: serde_json::to_string(&networks).unwrap_or_default(),
+            raw_services: services.map(|s| serde_json::to_string(&s).unwrap_or_default()),
+            interfaces: containers.iter().flat_map(container_interfaces).collect(),
+        })
+    }
+}
+
+impl DockerData {
+    // Container status like `Up 5 minutes` changes every query, only stable
+    // fields are used for change detection
+    pub fn hash(&self, hasher: &mut AHasher) {
+        for i in self.interfaces.iter() {
+            hasher.write(i.container_id.as_bytes());
+            hasher.write(i.container_name.as_bytes());
+            hasher.write(i.network.as_bytes());
+            hasher.write_u64(u64::from(i.mac));
+            for ip in i.ips.iter() {
+                hasher.write(ip.as_bytes());
+            }
+        }
+        hasher.write(self.raw_networks.as_bytes());
+        if let Some(s) = self.raw_services.as_ref() {
+            hasher.write(s.as_bytes());
+        }
+    }
+}
+
+fn container_interfaces(container: &ContainerSummary) -> Vec<DockerInterface> {
+    let Some(networks) = container
+        .network_settings
+        .as_ref()
+        .and_then(|s| s.networks.as_ref())
+    else {
+        return vec![];
+    };
+    let container_id = container.id.clone().unwrap_or_default();
+    // names are prefixed with `/`
+    let container_name = container
+        .names
+        .as_ref()
+        .and_then(|n| n.first())
+        .map(|n| n.trim_start_matches('/').to_owned())
+        .unwrap_or_default();
+    let mut interfaces = networks
+        .iter()
+        .filter_map(|(network, endpoint)| {
+            let mac = endpoint.mac_address.as_ref()?.parse::<MacAddr>().ok()?;
+            let mut ips = vec![];
+            if let Some(ip) = endpoint.ip_address.as_ref().filter(|ip| !ip.is_empty()) {
+                ips.push(format!("{}/{}", ip, endpoint.ip_prefix_len.unwrap_or(32)));
+            }
+            if let Some(ip) = endpoint
+                .global_ipv6_address
+                .as_ref()
+                .filter(|ip| !ip.is_empty())
+            {
+                ips.push(format!(
+                    "{}/{}",
+                    ip,
+                    endpoint.global_ipv6_prefix_len.unwrap_or(128)
+                ));
+            }
+            Some(DockerInterface {
+                mac,
+                ips,
+                network: network.clone(),
+                container_id: container_id.clone(),
+                container_name: container_name.clone(),
+            })
+        })
+        .collect::<Vec<_>>();
+    interfaces.sort_unstable_by_key(|i| i.mac);
+    interfaces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use bollard::models::{ContainerSummaryNetworkSettings, EndpointSettings};
+
+    #[test]
+    fn interfaces_from_container() {
+        let container = ContainerSummary {
+            id: Some("0123456789ab".to_owned()),
+            names: Some(vec!["/web.1.abcdef".to_owned()]),
+            labels: Some(HashMap::from([(
+                "com.docker.swarm.service.name".to_owned(),
+                "web".to_owned(),
+            )])),
+            network_settings: Some(ContainerSummaryNetworkSettings {
+                networks: Some(HashMap::from([
+                    (
+                        "bridge".to_owned(),
+                        EndpointSettings {
+                            mac_address: Some("02:42:ac:11:00:02".to_owned()),
+                            ip_address: Some("172.17.0.2".to_owned()),
+                            ip_prefix_len: Some(16),
+                            global_ipv6_address: Some("".to_owned()),
+                            ..Default::default()
+                        },
+                    ),
+                    (
+                        "none".to_owned(),
+                        EndpointSettings {
+                            mac_address: Some("".to_owned()),
+                            ..Default::default()
+                        },
+                    ),
+                ])),
+            }),
+            ..Default::default()
+        };
+        let interfaces = container_interfaces(&container);
+        assert_eq!(interfaces.len(), 1);
+        assert_eq!(
+            interfaces[0].mac,
+            "02:42:ac:11:00:02".parse::<MacAddr>().unwrap()
+        );
+        assert_eq!(interfaces[0].ips, vec!["172.17.0.2/16"]);
+        assert_eq!(interfaces[0].network, "bridge");
+        assert_eq!(interfaces[0].container_name, "web.1.abcdef");
+    }
+}
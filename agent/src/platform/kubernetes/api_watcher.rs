@@ -15,7 +15,7 @@
  */
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt,
     io::prelude::*,
     mem,
@@ -29,9 +29,9 @@ use std::{
 };
 
 use arc_swap::access::Access;
-use flate2::{write::ZlibEncoder, Compression};
-use k8s_openapi::apimachinery::pkg::version::Info;
-use kube::{Client, Config};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use k8s_openapi::{api::core::v1::Pod, apimachinery::pkg::version::Info};
+use kube::Client;
 use log::{debug, error, info, log_enabled, warn, Level};
 use parking_lot::RwLock;
 use tokio::{runtime::Runtime, task::JoinHandle};
@@ -155,6 +155,29 @@ impl ApiWatcher {
         }
     }
 
+    // Uids and labels of pods, None if pods are not watched by this agent
+    pub fn get_pod_labels(&self) -> Option<Vec<(String, BTreeMap<String, String>)>> {
+        let entries = self.get_watcher_entries("pods")?;
+        let mut pods = Vec::with_capacity(entries.len());
+        let mut buf = vec![];
+        for entry in entries {
+            buf.clear();
+            if let Err(e) = ZlibDecoder::new(&entry[..]).read_to_end(&mut buf) {
+                warn!("decode pod entry failed: {}", e);
+                continue;
+            }
+            match serde_json::from_slice::<Pod>(&buf) {
+                Ok(pod) => {
+                    if let Some(uid) = pod.metadata.uid {
+                        pods.push((uid, pod.metadata.labels.unwrap_or_default()));
+                    }
+                }
+                Err(e) => warn!("deserialize pod entry failed: {}", e),
+            }
+        }
+        Some(pods)
+    }
+
     pub fn get_server_version(&self) -> Option<String> {
         let info = self.apiserver_version.lock().unwrap();
         serde_json::to_string(info.deref()).ok()
@@ -503,10 +526,9 @@ impl ApiWatcher {
         HashMap<WatcherKey, GenericResourceWatcher>,
         Vec<JoinHandle<()>>,
     )> {
-        let mut config = Config::infer().await.map_err(|e| {
+        let config = super::infer_config().await.map_err(|e| {
             Error::KubernetesApiWatcher(format!("failed to infer kubernetes config: {}", e))
         })?;
-        config.accept_invalid_certs = true;
         info!("api server url is: {}", config.cluster_url);
         let client = match Client::try_from(config) {
             Ok(c) => c,
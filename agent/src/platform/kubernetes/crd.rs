@@ -171,3 +171,201 @@ pub mod calico {
         }
     }
 }
+
+// discovery.k8s.io/v1 is not available in k8s-openapi built for kubernetes 1.19
+pub mod discovery {
+    use super::*;
+
+    use std::borrow::Cow;
+
+    use k8s_openapi::api::{core::v1::ObjectReference, discovery::v1beta1::EndpointPort};
+
+    pub const SERVICE_NAME_LABEL: &str = "kubernetes.io/service-name";
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct EndpointSlice {
+        #[serde(default)]
+        pub api_version: String,
+        #[serde(default)]
+        pub kind: String,
+        #[serde(default)]
+        pub metadata: ObjectMeta,
+        pub address_type: String,
+        #[serde(default)]
+        pub endpoints: Vec<Endpoint>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub ports: Option<Vec<EndpointPort>>,
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Endpoint {
+        pub addresses: Vec<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub conditions: Option<EndpointConditions>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub hostname: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub node_name: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub target_ref: Option<ObjectReference>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub zone: Option<String>,
+    }
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct EndpointConditions {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub ready: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub serving: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub terminating: Option<bool>,
+    }
+
+    impl kube::Resource for EndpointSlice {
+        type DynamicType = ();
+
+        fn kind(_: &()) -> Cow<'_, str> {
+            "EndpointSlice".into()
+        }
+
+        fn group(_: &()) -> Cow<'_, str> {
+            "discovery.k8s.io".into()
+        }
+
+        fn version(_: &()) -> Cow<'_, str> {
+            "v1".into()
+        }
+
+        fn api_version(_: &()) -> Cow<'_, str> {
+            "discovery.k8s.io/v1".into()
+        }
+
+        fn plural(_: &()) -> Cow<'_, str> {
+            "endpointslices".into()
+        }
+
+        fn meta(&self) -> &ObjectMeta {
+            &self.metadata
+        }
+
+        fn meta_mut(&mut self) -> &mut ObjectMeta {
+            &mut self.metadata
+        }
+    }
+
+    // Only keeps the owner service of the slice, labels of the service are synchronized with it
+    pub(crate) fn trim_slice_meta(meta: &mut ObjectMeta) -> ObjectMeta {
+        ObjectMeta {
+            uid: meta.uid.take(),
+            name: meta.name.take(),
+            namespace: meta.namespace.take(),
+            labels: meta.labels.take().map(|mut labels| {
+                labels.retain(|k, _| k == SERVICE_NAME_LABEL);
+                labels
+            }),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn trim_target_ref(target: Option<ObjectReference>) -> Option<ObjectReference> {
+        target.map(|t| ObjectReference {
+            kind: t.kind,
+            name: t.name,
+            namespace: t.namespace,
+            uid: t.uid,
+            ..Default::default()
+        })
+    }
+
+    impl Trimmable for EndpointSlice {
+        fn trim(mut self) -> Self {
+            Self {
+                api_version: <Self as kube::Resource>::api_version(&()).into_owned(),
+                kind: <Self as kube::Resource>::kind(&()).into_owned(),
+                metadata: trim_slice_meta(&mut self.metadata),
+                address_type: self.address_type,
+                endpoints: self
+                    .endpoints
+                    .into_iter()
+                    .map(|e| Endpoint {
+                        addresses: e.addresses,
+                        conditions: e.conditions,
+                        node_name: e.node_name,
+                        target_ref: trim_target_ref(e.target_ref),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ports: self.ports,
+            }
+        }
+    }
+
+    impl Trimmable for k8s_openapi::api::discovery::v1beta1::EndpointSlice {
+        fn trim(mut self) -> Self {
+            self.metadata = trim_slice_meta(&mut self.metadata);
+            for e in self.endpoints.iter_mut() {
+                e.hostname = None;
+                e.topology = None;
+                e.target_ref = trim_target_ref(e.target_ref.take());
+            }
+            self
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        use kube::Resource;
+
+        #[test]
+        fn trim_endpoint_slice() {
+            let slice: EndpointSlice = serde_json::from_str(
+                r#"{
+                    "metadata": {
+                        "uid": "u1", "name": "svc-abcde", "namespace": "default",
+                        "resourceVersion": "42",
+                        "labels": {"kubernetes.io/service-name": "svc", "app": "web"}
+                    },
+                    "addressType": "IPv4",
+                    "endpoints": [{
+                        "addresses": ["10.1.1.1"],
+                        "conditions": {"ready": true},
+                        "hostname": "web-0",
+                        "nodeName": "node-1",
+                        "zone": "z1",
+                        "targetRef": {"kind": "Pod", "name": "web-0", "namespace": "default",
+                                      "uid": "p1", "resourceVersion": "7"}
+                    }],
+                    "ports": [{"name": "http", "port": 80, "protocol": "TCP"}]
+                }"#,
+            )
+            .unwrap();
+            let slice = slice.trim();
+            assert_eq!(slice.kind, "EndpointSlice");
+            assert_eq!(slice.meta().resource_version, None);
+            assert_eq!(
+                slice
+                    .meta()
+                    .labels
+                    .as_ref()
+                    .unwrap()
+                    .keys()
+                    .collect::<Vec<_>>(),
+                vec![SERVICE_NAME_LABEL]
+            );
+            let e = &slice.endpoints[0];
+            assert_eq!(e.addresses, vec!["10.1.1.1"]);
+            assert_eq!(e.node_name.as_deref(), Some("node-1"));
+            assert!(e.hostname.is_none() && e.zone.is_none());
+            let target = e.target_ref.as_ref().unwrap();
+            assert_eq!(target.uid.as_deref(), Some("p1"));
+            assert!(target.resource_version.is_none());
+            assert_eq!(slice.ports.unwrap()[0].port, Some(80));
+        }
+    }
+}
@@ -18,6 +18,7 @@ use std::{fs, os::unix::io::AsRawFd};
 
 use arc_swap::access::Access;
 use enum_dispatch::enum_dispatch;
+use kube::Config;
 use log::{info, warn};
 use nix::sched::{setns, CloneFlags};
 use regex::Regex;
@@ -67,6 +68,19 @@ pub fn check_read_link_ns() -> bool {
     netns::supported() && fs::read_link("/proc/1/ns/net").is_ok()
 }
 
+// In cluster, the api server is verified with the CA of the service account, and accessed by
+// its service DNS name, as certificates issued for IP addresses are not accepted by rustls.
+// Out of cluster, the kubeconfig is used as is.
+pub async fn infer_config() -> Result<Config, String> {
+    if std::env::var_os("KUBERNETES_SERVICE_HOST").is_some() {
+        match Config::incluster_dns() {
+            Ok(config) => return Ok(config),
+            Err(e) => warn!("failed to load in-cluster kubernetes config: {}", e),
+        }
+    }
+    Config::infer().await.map_err(|e| e.to_string())
+}
+
 impl GenericPoller {
     pub fn new(config: PlatformAccess, extra_netns_regex: String) -> Self {
         let (can_set_ns, can_read_link_ns) = (check_set_ns(), check_read_link_ns());
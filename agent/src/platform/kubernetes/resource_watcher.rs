@@ -38,6 +38,7 @@ use k8s_openapi::{
             Container, ContainerStatus, Namespace, Node, NodeSpec, NodeStatus, Pod, PodSpec,
             PodStatus, ReplicationController, ReplicationControllerSpec, Service, ServiceSpec,
         },
+        discovery::v1beta1 as discovery_v1beta1,
         extensions, networking,
     },
     apimachinery::pkg::apis::meta::v1::ObjectMeta,
@@ -55,6 +56,7 @@ use tokio::{runtime::Handle, sync::Mutex, task::JoinHandle, time};
 
 use super::crd::{
     calico::IpPool,
+    discovery::EndpointSlice,
     kruise::{CloneSet, StatefulSet as KruiseStatefulSet},
     pingan::ServiceRule,
 };
@@ -92,6 +94,10 @@ pub enum GenericResourceWatcher {
     V1beta1Ingress(ResourceWatcher<networking::v1beta1::Ingress>),
     ExtV1beta1Ingress(ResourceWatcher<extensions::v1beta1::Ingress>),
     Route(ResourceWatcher<Route>),
+    // slices are keyed by uid, so that a pod change only updates the slice containing it
+    // instead of the whole endpoints of a service
+    V1EndpointSlice(ResourceWatcher<EndpointSlice>),
+    V1beta1EndpointSlice(ResourceWatcher<discovery_v1beta1::EndpointSlice>),
 
     // CRDs
     ServiceRule(ResourceWatcher<ServiceRule>),
@@ -344,6 +350,21 @@ pub fn supported_resources() -> Vec<Resource> {
             ],
             selected_gv: None,
         },
+        Resource {
+            name: "endpointslices",
+            pb_name: "*v1.EndpointSlice",
+            group_versions: vec![
+                GroupVersion {
+                    group: "discovery.k8s.io",
+                    version: "v1",
+                },
+                GroupVersion {
+                    group: "discovery.k8s.io",
+                    version: "v1beta1",
+                },
+            ],
+            selected_gv: None,
+        },
         Resource {
             name: "routes",
             pb_name: "*v1.Ingress",
@@ -1324,6 +1345,34 @@ impl ResourceWatcherFactory {
                     return None;
                 }
             },
+            "endpointslices" => match resource.selected_gv.as_ref().unwrap() {
+                GroupVersion {
+                    group: "discovery.k8s.io",
+                    version: "v1",
+                } => GenericResourceWatcher::V1EndpointSlice(self.new_watcher_inner(
+                    resource,
+                    stats_collector,
+                    namespace,
+                    config,
+                )),
+                GroupVersion {
+                    group: "discovery.k8s.io",
+                    version: "v1beta1",
+                } => GenericResourceWatcher::V1beta1EndpointSlice(self.new_watcher_inner(
+                    resource,
+                    stats_collector,
+                    namespace,
+                    config,
+                )),
+                _ => {
+                    warn!(
+                        "unsupported resource {} group version {}",
+                        resource.name,
+                        resource.selected_gv.as_ref().unwrap()
+                    );
+                    return None;
+                }
+            },
             "routes" => GenericResourceWatcher::Route(self.new_watcher_inner(
                 resource,
                 stats_collector,
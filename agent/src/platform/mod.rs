@@ -16,6 +16,7 @@
 
 cfg_if::cfg_if! {
     if #[cfg(target_os = "linux")] {
+        mod docker;
         mod libvirt_xml_extractor;
         pub mod kubernetes;
 
@@ -25,15 +26,18 @@ cfg_if::cfg_if! {
 }
 
 #[cfg(any(target_os = "linux", target_os = "android"))]
-pub use platform_synchronizer::{ProcRegRewrite, SocketSynchronizer};
+pub use platform_synchronizer::{ProcRegRewrite, ProcServiceRule, SocketSynchronizer};
 
 mod platform_synchronizer;
 
 pub use platform_synchronizer::process_info_enabled;
 
+mod conntrack;
 mod querier;
 pub mod synchronizer;
 
+pub use conntrack::ConntrackTable;
+
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct InterfaceEntry {
     pub name: String,
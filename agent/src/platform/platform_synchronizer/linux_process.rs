@@ -40,9 +40,10 @@ use super::proc_scan_hook::proc_scan_hook;
 
 use crate::config::handler::OsProcScanConfig;
 use crate::config::{
-    OsProcRegexp, OS_PROC_REGEXP_MATCH_ACTION_ACCEPT, OS_PROC_REGEXP_MATCH_ACTION_DROP,
-    OS_PROC_REGEXP_MATCH_TYPE_CMD, OS_PROC_REGEXP_MATCH_TYPE_PARENT_PROC_NAME,
-    OS_PROC_REGEXP_MATCH_TYPE_PROC_NAME, OS_PROC_REGEXP_MATCH_TYPE_TAG,
+    OsProcRegexp, OsProcServiceRule, OS_PROC_REGEXP_MATCH_ACTION_ACCEPT,
+    OS_PROC_REGEXP_MATCH_ACTION_DROP, OS_PROC_REGEXP_MATCH_TYPE_CMD,
+    OS_PROC_REGEXP_MATCH_TYPE_PARENT_PROC_NAME, OS_PROC_REGEXP_MATCH_TYPE_PROC_NAME,
+    OS_PROC_REGEXP_MATCH_TYPE_TAG,
 };
 
 const CONTAINER_ID_LEN: usize = 64;
@@ -353,6 +354,155 @@ impl ProcRegRewrite {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct ProcServiceRule {
+    exe: Option<Regex>,
+    cmdline: Option<Regex>,
+    container_labels: Vec<(String, String)>,
+    service_name: String,
+    tags: Vec<OsAppTagKV>,
+}
+
+impl PartialEq for ProcServiceRule {
+    fn eq(&self, other: &Self) -> bool {
+        fn regex_eq(l: &Option<Regex>, r: &Option<Regex>) -> bool {
+            l.as_ref().map(|r| r.as_str()) == r.as_ref().map(|r| r.as_str())
+        }
+        regex_eq(&self.exe, &other.exe)
+            && regex_eq(&self.cmdline, &other.cmdline)
+            && self.container_labels == other.container_labels
+            && self.service_name == other.service_name
+            && self.tags.len() == other.tags.len()
+            && self
+                .tags
+                .iter()
+                .zip(other.tags.iter())
+                .all(|(l, r)| l.key == r.key && l.value == r.value)
+    }
+}
+
+impl Eq for ProcServiceRule {}
+
+impl TryFrom<&OsProcServiceRule> for ProcServiceRule {
+    type Error = regex::Error;
+
+    fn try_from(value: &OsProcServiceRule) -> Result<Self, Self::Error> {
+        let regex = |r: &str| {
+            if r.is_empty() {
+                Ok(None)
+            } else {
+                Regex::new(r).map(Some)
+            }
+        };
+        Ok(Self {
+            exe: regex(&value.exe_regex)?,
+            cmdline: regex(&value.cmdline_regex)?,
+            container_labels: value
+                .container_labels
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            service_name: value.service_name.clone(),
+            tags: value
+                .tags
+                .iter()
+                .map(|(k, v)| OsAppTagKV {
+                    key: k.clone(),
+                    value: v.clone(),
+                })
+                .collect(),
+        })
+    }
+}
+
+// container id -> labels
+type ContainerLabels = HashMap<String, HashMap<String, String>>;
+
+// Rewrite the process name to service name and add the rule tags on the first matched rule,
+// tags already on the process are preserved. Returns whether any rule matched.
+pub(super) fn apply_service_rules(
+    rules: &[ProcServiceRule],
+    proc: &mut ProcessData,
+    proc_root: &str,
+    labels_cache: &mut ContainerLabels,
+) -> bool {
+    let mut exe = None;
+    for rule in rules {
+        if let Some(reg) = rule.exe.as_ref() {
+            let exe = exe.get_or_insert_with(|| get_proc_exe(proc_root, proc.pid));
+            if !reg.is_match(exe) {
+                continue;
+            }
+        }
+        if let Some(reg) = rule.cmdline.as_ref() {
+            if !reg.is_match(&proc.cmd.join(" ")) {
+                continue;
+            }
+        }
+        if !rule.container_labels.is_empty() {
+            if proc.container_id.is_empty() {
+                continue;
+            }
+            let labels = labels_cache
+                .entry(proc.container_id.clone())
+                .or_insert_with(|| get_docker_container_labels(proc_root, &proc.container_id));
+            if !rule
+                .container_labels
+                .iter()
+                .all(|(k, v)| labels.get(k) == Some(v))
+            {
+                continue;
+            }
+        }
+
+        if !rule.service_name.is_empty() {
+            proc.name = rule.service_name.clone();
+        }
+        merge_tag(&mut proc.os_app_tags, &rule.tags);
+        return true;
+    }
+    false
+}
+
+fn get_proc_exe(proc_root: &str, pid: u64) -> String {
+    let p = PathBuf::from_iter([proc_root, pid.to_string().as_str(), "exe"]);
+    std::fs::read_link(p)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[derive(Default, Deserialize)]
+struct DockerContainer {
+    #[serde(rename = "Config", default)]
+    config: DockerContainerConfig,
+}
+
+#[derive(Default, Deserialize)]
+struct DockerContainerConfig {
+    #[serde(rename = "Labels", default)]
+    labels: Option<HashMap<String, String>>,
+}
+
+// only docker keeps container labels on disk, read from the host mount namespace
+fn get_docker_container_labels(proc_root: &str, container_id: &str) -> HashMap<String, String> {
+    let p = PathBuf::from_iter([
+        proc_root,
+        "1/root/var/lib/docker/containers",
+        container_id,
+        "config.v2.json",
+    ]);
+    let Ok(content) = std::fs::read(&p) else {
+        return HashMap::new();
+    };
+    match serde_json::from_slice::<DockerContainer>(&content) {
+        Ok(c) => c.config.labels.unwrap_or_default(),
+        Err(e) => {
+            debug!("parse {} fail: {}", p.display(), e);
+            HashMap::new()
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, Deserialize)]
 pub struct OsAppTagKV {
     pub key: String,
@@ -398,11 +548,13 @@ pub(crate) fn get_all_process(conf: &OsProcScanConfig) -> Vec<ProcessData> {
 pub(crate) fn get_all_process_in(conf: &OsProcScanConfig, ret: &mut Vec<ProcessData>) {
     // Hashmap<root_inode, PasswordInfo>
     let mut pwd_info = HashMap::new();
-    let (user, cmd, proc_root, proc_regexp, tagged_only, now_sec) = (
+    let mut container_labels = HashMap::new();
+    let (user, cmd, proc_root, proc_regexp, service_rules, tagged_only, now_sec) = (
         conf.os_app_tag_exec_user.as_str(),
         conf.os_app_tag_exec.as_slice(),
         conf.os_proc_root.as_str(),
         conf.os_proc_regex.as_slice(),
+        conf.os_proc_service_rules.as_slice(),
         conf.os_proc_sync_tagged_only,
         SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -475,9 +627,19 @@ pub(crate) fn get_all_process_in(conf: &OsProcScanConfig, ret: &mut Vec<ProcessD
                     }
 
                     // fill tags
-                    if let Some(tags) = tags_map.remove(&proc_data.pid) {
-                        proc_data.os_app_tags = tags.tags
-                    } else if tagged_only {
+                    let tagged = if let Some(tags) = tags_map.remove(&proc_data.pid) {
+                        proc_data.os_app_tags = tags.tags;
+                        true
+                    } else {
+                        false
+                    };
+                    let serviced = apply_service_rules(
+                        service_rules,
+                        &mut proc_data,
+                        proc_root,
+                        &mut container_labels,
+                    );
+                    if tagged_only && !tagged && !serviced {
                         break;
                     }
 
@@ -665,9 +827,12 @@ mod test {
 
     use rand::{seq::SliceRandom, thread_rng};
 
+    use std::collections::{BTreeMap, HashMap};
+
+    use crate::config::OsProcServiceRule;
     use crate::platform::platform_synchronizer::linux_process::fill_child_proc_tag_by_parent;
 
-    use super::{OsAppTagKV, ProcessData};
+    use super::{apply_service_rules, OsAppTagKV, ProcServiceRule, ProcessData};
 
     #[test]
     fn test_tag_spread() {
@@ -772,4 +937,81 @@ mod test {
             assert_eq!(child.os_app_tags[2].value.to_string(), "root_val");
         }
     }
+
+    #[test]
+    fn test_service_rules() {
+        let rules = [
+            OsProcServiceRule {
+                exe_regex: "^/usr/bin/java$".into(),
+                service_name: "never".into(),
+                ..Default::default()
+            },
+            OsProcServiceRule {
+                cmdline_regex: "order-service\\.jar".into(),
+                service_name: "order".into(),
+                tags: BTreeMap::from([("team".into(), "trade".into())]),
+                ..Default::default()
+            },
+            OsProcServiceRule {
+                container_labels: BTreeMap::from([("app".into(), "cart".into())]),
+                service_name: "cart".into(),
+                ..Default::default()
+            },
+        ]
+        .iter()
+        .map(|r| ProcServiceRule::try_from(r).unwrap())
+        .collect::<Vec<_>>();
+        let mut labels = HashMap::from([(
+            "c1".to_string(),
+            HashMap::from([("app".to_string(), "cart".to_string())]),
+        )]);
+
+        let mut proc = ProcessData {
+            name: "java".into(),
+            pid: 1,
+            ppid: 0,
+            process_name: "java".into(),
+            cmd: vec!["java".into(), "-jar".into(), "order-service.jar".into()],
+            user_id: 0,
+            user: "u".into(),
+            start_time: Duration::ZERO,
+            os_app_tags: vec![OsAppTagKV {
+                key: "team".into(),
+                value: "from-exec".into(),
+            }],
+            netns_id: 1,
+            container_id: "".into(),
+        };
+        // exe of pid 1 can not be read under a nonexistent proc root
+        assert!(apply_service_rules(
+            &rules,
+            &mut proc,
+            "/nonexistent",
+            &mut labels
+        ));
+        assert_eq!(proc.name, "order");
+        assert_eq!(proc.os_app_tags.len(), 1);
+        assert_eq!(proc.os_app_tags[0].value, "from-exec");
+
+        proc.name = "python".into();
+        proc.cmd = vec!["python".into(), "cart.py".into()];
+        proc.container_id = "c1".into();
+        assert!(apply_service_rules(
+            &rules,
+            &mut proc,
+            "/nonexistent",
+            &mut labels
+        ));
+        assert_eq!(proc.name, "cart");
+
+        proc.name = "python".into();
+        proc.container_id = "c2".into();
+        assert!(!apply_service_rules(
+            &rules,
+            &mut proc,
+            "/nonexistent",
+            &mut labels
+        ));
+        assert_eq!(proc.name, "python");
+    }
 }
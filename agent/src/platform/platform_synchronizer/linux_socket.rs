@@ -40,7 +40,9 @@ use public::{
     proto::trident::{GpidSyncEntry, RoleType, ServiceProtocol},
 };
 
-use super::linux_process::{get_all_pid_process_map, get_os_app_tag_by_exec, RegExpAction};
+use super::linux_process::{
+    apply_service_rules, get_all_pid_process_map, get_os_app_tag_by_exec, RegExpAction,
+};
 
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub enum Role {
@@ -211,6 +213,7 @@ pub(super) fn get_all_socket(
 
     // netns idx increase every time get the new netns id
     let mut netns_idx = 0u16;
+    let mut container_labels = HashMap::new();
 
     let mut pid_proc_map = get_all_pid_process_map(conf.os_proc_root.as_str());
 
@@ -250,7 +253,15 @@ pub(super) fn get_all_socket(
                     break;
                 }
 
-                if tags_map.get(&(proc.pid as u64)).is_none() && tagged_only {
+                if tagged_only
+                    && tags_map.get(&(proc.pid as u64)).is_none()
+                    && !apply_service_rules(
+                        &conf.os_proc_service_rules,
+                        &mut proc_data,
+                        proc_root,
+                        &mut container_labels,
+                    )
+                {
                     break;
                 }
 
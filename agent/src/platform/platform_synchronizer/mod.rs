@@ -23,7 +23,7 @@ cfg_if::cfg_if! {
         mod linux_socket;
 
         pub use linux::SocketSynchronizer;
-        pub use linux_process::{ProcessData, ProcRegRewrite};
+        pub use linux_process::{ProcessData, ProcRegRewrite, ProcServiceRule};
     } else if #[cfg(target_os = "windows")] {
         pub struct ProcessData {}
     }
@@ -20,10 +20,12 @@ use std::sync::Arc;
 use ahash::AHasher;
 use log::{debug, info, trace, warn};
 use regex::Regex;
+use tokio::runtime::Runtime;
 
 use crate::{
     config::handler::PlatformConfig,
     platform::{
+        docker::{DockerData, DockerDiscovery, IF_TYPE_DOCKER},
         kubernetes::{InterfaceInfoStore, Poller},
         platform_synchronizer::{
             linux_process::get_all_process_in, process_info_enabled, ProcessData,
@@ -48,6 +50,7 @@ pub struct Querier {
     libvirt_xml_extractor: Arc<LibvirtXmlExtractor>,
 
     netns_regex: Option<Regex>,
+    exclude_interface_regex: Option<Regex>,
 
     digest: u64,
 
@@ -68,12 +71,16 @@ pub struct Querier {
     kubeif_store: InterfaceInfoStore,
 
     xml_interfaces: Vec<InterfaceEntry>,
+
+    docker_discovery: DockerDiscovery,
+    docker_data: Option<DockerData>,
 }
 
 impl Querier {
     pub fn new(
         override_os_hostname: Option<String>,
         libvirt_xml_extractor: Arc<LibvirtXmlExtractor>,
+        runtime: Arc<Runtime>,
     ) -> Self {
         Self {
             override_os_hostname,
@@ -81,6 +88,7 @@ impl Querier {
 
             kubernetes_poller: None,
             netns_regex: Default::default(),
+            exclude_interface_regex: Default::default(),
 
             digest: Default::default(),
 
@@ -101,6 +109,9 @@ impl Querier {
             kubeif_store: Default::default(),
 
             xml_interfaces: Default::default(),
+
+            docker_discovery: DockerDiscovery::new(runtime),
+            docker_data: None,
         }
     }
 
@@ -110,26 +121,16 @@ impl Querier {
 
     // returns digest
     pub fn update(&mut self, config: &PlatformConfig) -> u64 {
-        // reconstruct regex if changed
-        match self.netns_regex.as_ref() {
-            Some(re) if re.as_str() != config.extra_netns_regex.as_str() => self.netns_regex = None,
-            _ => (),
-        }
-        if self.netns_regex.is_none() && config.extra_netns_regex != "" {
-            match Regex::new(config.extra_netns_regex.as_str()) {
-                Ok(new_re) => {
-                    info!("extra_netns_regex updated to /{}/", new_re.as_str());
-                    self.netns_regex = Some(new_re);
-                }
-                Err(e) => {
-                    warn!(
-                        "extra_netns_regex /{}/ is invalid: {}",
-                        config.extra_netns_regex.as_str(),
-                        e
-                    )
-                }
-            }
-        }
+        Self::update_regex(
+            &mut self.netns_regex,
+            &config.extra_netns_regex,
+            "extra_netns_regex",
+        );
+        Self::update_regex(
+            &mut self.exclude_interface_regex,
+            &config.exclude_interface_regex,
+            "exclude_interface_regex",
+        );
 
         let mut netns = vec![NsFile::Root];
         if let Some(re) = self.netns_regex.as_ref() {
@@ -186,11 +187,36 @@ impl Querier {
         self.update_process_data(config, &mut hasher);
         self.update_kubernetes_interfaces(&netns, &mut hasher);
         self.update_xml_interfaces(&mut hasher);
+        self.update_docker_data(config, &mut hasher);
 
         self.digest = hasher.finish();
         self.digest()
     }
 
+    // reconstruct regex if changed
+    fn update_regex(current: &mut Option<Regex>, new: &str, label: &str) {
+        match current.as_ref() {
+            Some(re) if re.as_str() != new => *current = None,
+            _ => (),
+        }
+        if current.is_none() && new != "" {
+            match Regex::new(new) {
+                Ok(new_re) => {
+                    info!("{} updated to /{}/", label, new_re.as_str());
+                    *current = Some(new_re);
+                }
+                Err(e) => warn!("{} /{}/ is invalid: {}", label, new, e),
+            }
+        }
+    }
+
+    fn is_excluded_interface(&self, name: &str) -> bool {
+        self.exclude_interface_regex
+            .as_ref()
+            .map(|re| re.is_match(name))
+            .unwrap_or(false)
+    }
+
     pub fn generate_message(&self, config: &PlatformConfig) -> pb::GenesisSyncRequest {
         let mut interfaces: Vec<_> = self
             .kubernetes_poller
@@ -213,23 +239,41 @@ impl Querier {
                 if matches!(poller.as_ref(), &GenericPoller::ActivePoller(_)) {
                     self.kubeif_store
                         .iter()
+                        .filter(|info| !self.is_excluded_interface(&info.name))
                         .map(|info| info_to_pb(info))
                         .collect()
                 } else {
                     self.kubernetes_interfaces
                         .iter()
+                        .filter(|info| !self.is_excluded_interface(&info.name))
                         .map(|info| info_to_pb(info))
                         .collect()
                 }
             })
             .unwrap_or_default();
-        interfaces.extend(self.xml_interfaces.iter().map(|info| pb::InterfaceInfo {
-            name: Some(info.name.clone()),
-            mac: Some(info.mac.into()),
-            device_id: Some(info.domain_uuid.clone()),
-            device_name: Some(info.domain_name.clone()),
-            ..Default::default()
-        }));
+        interfaces.extend(
+            self.xml_interfaces
+                .iter()
+                .filter(|info| !self.is_excluded_interface(&info.name))
+                .map(|info| pb::InterfaceInfo {
+                    name: Some(info.name.clone()),
+                    mac: Some(info.mac.into()),
+                    device_id: Some(info.domain_uuid.clone()),
+                    device_name: Some(info.domain_name.clone()),
+                    ..Default::default()
+                }),
+        );
+        if let Some(docker_data) = self.docker_data.as_ref() {
+            interfaces.extend(docker_data.interfaces.iter().map(|info| pb::InterfaceInfo {
+                mac: Some(info.mac.into()),
+                ip: info.ips.clone(),
+                name: Some(info.network.clone()),
+                device_id: Some(info.container_id.clone()),
+                device_name: Some(info.container_name.clone()),
+                if_type: Some(IF_TYPE_DOCKER.to_owned()),
+                ..Default::default()
+            }));
+        }
 
         let mut platform_data = pb::GenesisPlatformData {
             platform_enabled: Some(config.enabled),
@@ -239,6 +283,11 @@ impl Querier {
             interfaces,
             ..Default::default()
         };
+        if let Some(docker_data) = self.docker_data.as_ref() {
+            platform_data.raw_docker_containers = Some(docker_data.raw_containers.clone());
+            platform_data.raw_docker_networks = Some(docker_data.raw_networks.clone());
+            platform_data.raw_docker_services = docker_data.raw_services.clone();
+        }
         if config.enabled {
             platform_data.raw_all_vm_xml = self.raw_all_vm_xml.clone();
             platform_data.raw_vm_states = self.raw_vm_states.clone();
@@ -293,7 +342,11 @@ impl Querier {
             }
             let raw_host_ip_addr = get_ip_address()
                 .map_err(|err| debug!("get_ip_address error:{}", err))
-                .ok();
+                .ok()
+                .map(|s| match self.exclude_interface_regex.as_ref() {
+                    Some(re) => exclude_interfaces(&s, re),
+                    None => s,
+                });
             if let Some(ip_addr) = raw_host_ip_addr.as_ref() {
                 for line in ip_addr.lines() {
                     // 忽略可能变化的行避免version频繁更新
@@ -413,4 +466,75 @@ impl Querier {
         );
         trace!("digest={:016x}", hasher.finish());
     }
+
+    fn update_docker_data(&mut self, config: &PlatformConfig, hasher: &mut AHasher) {
+        // containers on kubernetes nodes are reported by kubernetes poller
+        if !config.docker_discovery_enabled || self.kubernetes_poller.is_some() {
+            self.docker_data = None;
+            return;
+        }
+        trace!("get docker data");
+        let Some(docker_data) = self.docker_discovery.query(&config.docker_socket_path) else {
+            return;
+        };
+        docker_data.hash(hasher);
+        debug!(
+            "updated docker data returned {} container interfaces",
+            docker_data.interfaces.len()
+        );
+        self.docker_data = Some(docker_data);
+        trace!("digest={:016x}", hasher.finish());
+    }
+}
+
+// Remove blocks of interfaces matching the regex from `ip addr` output, e.g.
+// 4: kube-ipvs0: <BROADCAST,NOARP> mtu 1500 qdisc noop state DOWN group default
+//     link/ether 02:f1:4b:1e:2c:0a brd ff:ff:ff:ff:ff:ff
+//     inet 10.96.0.1/32 scope global kube-ipvs0
+fn exclude_interfaces(ip_addr: &str, re: &Regex) -> String {
+    let mut result = String::with_capacity(ip_addr.len());
+    let mut excluded = false;
+    for line in ip_addr.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            // name is the second field, with peer suffix like `veth0@if3`
+            excluded = line
+                .split(": ")
+                .nth(1)
+                .map(|name| re.is_match(name.split('@').next().unwrap()))
+                .unwrap_or(false);
+        }
+        if !excluded {
+            result.push_str(line);
+            result.push('\n');
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exclude_ip_addr_interfaces() {
+        let ip_addr = "1: lo: <LOOPBACK,UP,LOWER_UP> mtu 65536 qdisc noqueue state UNKNOWN group default qlen 1000
+    link/loopback 00:00:00:00:00:00 brd 00:00:00:00:00:00
+    inet 127.0.0.1/8 scope host lo
+2: eth0: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500 qdisc mq state UP group default qlen 1000
+    link/ether 52:54:00:12:34:56 brd ff:ff:ff:ff:ff:ff
+    inet 10.1.1.2/24 brd 10.1.1.255 scope global eth0
+3: dummy0: <BROADCAST,NOARP> mtu 1500 qdisc noop state DOWN group default qlen 1000
+    link/ether 06:9e:1c:5d:25:39 brd ff:ff:ff:ff:ff:ff
+4: kube-ipvs0@NONE: <BROADCAST,NOARP> mtu 1500 qdisc noop state DOWN group default
+    link/ether 02:f1:4b:1e:2c:0a brd ff:ff:ff:ff:ff:ff
+    inet 10.96.0.1/32 scope global kube-ipvs0
+";
+        let re = Regex::new("^(dummy.*|kube-ipvs0)$").unwrap();
+        let result = exclude_interfaces(ip_addr, &re);
+        assert!(result.contains("eth0"));
+        assert!(result.contains("127.0.0.1/8"));
+        assert!(!result.contains("dummy0"));
+        assert!(!result.contains("10.96.0.1"));
+        assert_eq!(result.lines().count(), 6);
+    }
 }
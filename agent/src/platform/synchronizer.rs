@@ -208,6 +208,8 @@ impl Synchronizer {
             args.override_os_hostname.clone(),
             #[cfg(target_os = "linux")]
             args.xml_extractor.clone(),
+            #[cfg(target_os = "linux")]
+            args.runtime.clone(),
         );
 
         'outer: loop {
@@ -0,0 +1,146 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, warn};
+use md5::{Digest, Md5};
+
+const TEMP_SUFFIX: &str = ".tmp";
+
+// On disk cache of plugin artifacts keyed by md5 digest of the content,
+// so that unchanged plugins are not pulled from server again after restart
+// or config change.
+pub struct PluginCache {
+    dir: PathBuf,
+}
+
+impl PluginCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn digest(data: &[u8]) -> String {
+        format!("{:x}", Md5::digest(data))
+    }
+
+    fn is_valid_key(key: &str) -> bool {
+        key.len() == 32 && key.bytes().all(|b| b.is_ascii_hexdigit())
+    }
+
+    // Returns cached content only if it matches the digest
+    pub fn get(&self, md5: &str) -> Option<Vec<u8>> {
+        let key = md5.to_ascii_lowercase();
+        if !Self::is_valid_key(&key) {
+            return None;
+        }
+        let path = self.dir.join(&key);
+        let data = fs::read(&path).ok()?;
+        if Self::digest(&data) != key {
+            warn!("plugin cache {} corrupted, removed", path.display());
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+        debug!("plugin {} loaded from cache", key);
+        Some(data)
+    }
+
+    // Whether content matches the md5 digest sent by server
+    pub fn verify(md5: &str, data: &[u8]) -> bool {
+        Self::digest(data).eq_ignore_ascii_case(md5)
+    }
+
+    // Writes content to a temporary file and renames it to avoid partial entries,
+    // content not matching the digest is rejected
+    pub fn put(&self, md5: &str, data: &[u8]) -> io::Result<String> {
+        if !Self::verify(md5, data) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("md5 mismatch, expected {}", md5),
+            ));
+        }
+        let key = md5.to_ascii_lowercase();
+        let path = self.dir.join(&key);
+        if path.exists() {
+            return Ok(key);
+        }
+        fs::create_dir_all(&self.dir)?;
+        let mut temp = path.clone().into_os_string();
+        temp.push(TEMP_SUFFIX);
+        fs::write(&temp, data)?;
+        fs::rename(&temp, &path)?;
+        Ok(key)
+    }
+
+    // Removes entries not in use
+    pub fn retain(&self, keys: &HashSet<String>) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            let stale = if Self::is_valid_key(name) {
+                !keys.contains(name)
+            } else {
+                name.ends_with(TEMP_SUFFIX)
+            };
+            if stale {
+                debug!("plugin cache {} removed", name);
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache() {
+        let dir = std::env::temp_dir().join(format!("plugin-cache-{}", std::process::id()));
+        let cache = PluginCache::new(&dir);
+        assert!(cache.get("not a digest").is_none());
+
+        let digest = PluginCache::digest(b"plugin");
+        assert!(cache.put(&digest, b"tampered plugin").is_err());
+        assert!(cache.get(&digest).is_none());
+        let key = cache.put(&digest.to_ascii_uppercase(), b"plugin").unwrap();
+        assert_eq!(key, digest);
+        assert_eq!(cache.get(&key.to_ascii_uppercase()).unwrap(), b"plugin");
+
+        let other = cache
+            .put(&PluginCache::digest(b"other plugin"), b"other plugin")
+            .unwrap();
+        fs::write(dir.join(&other), "corrupted").unwrap();
+        assert!(cache.get(&other).is_none());
+        assert!(!dir.join(&other).exists());
+
+        cache.retain(&HashSet::new());
+        assert!(cache.get(&key).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+pub mod cache;
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub mod c_ffi;
 #[cfg(any(target_os = "linux", target_os = "android"))]
@@ -472,6 +473,10 @@ impl L7ProtocolInfoInterface for CustomInfo {
     fn get_biz_type(&self) -> u8 {
         self.biz_type
     }
+
+    fn get_response_status(&self) -> L7ResponseStatus {
+        self.resp.status
+    }
 }
 
 impl From<CustomInfo> for L7ProtocolSendLog {
@@ -20,10 +20,11 @@ use std::sync::{
     Arc, RwLock,
 };
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use log::{info, warn};
 
 use super::fast_path::FastPath;
+use super::interval::IntervalIndex;
 use super::{Error as PError, Result as PResult};
 use crate::common::endpoint::{EndpointData, FeatureFlags};
 use crate::common::lookup_key::LookupKey;
@@ -234,13 +235,33 @@ struct Table6Item {
     policy: Arc<PolicyData>,
 }
 
+struct TableBucket<T> {
+    items: Vec<T>,
+    // 表项较多时按字段建立区间位图索引，避免逐条比较
+    index: Option<IntervalIndex>,
+}
+
+impl<T> Default for TableBucket<T> {
+    fn default() -> Self {
+        Self {
+            items: vec![],
+            index: None,
+        }
+    }
+}
+
 pub struct FirstPath {
     group_ip_map: Option<AHashMap<u16, Vec<IpSegment>>>,
 
     vector_4: Vector4,
-    table_4: RwLock<Vec<Vec<Table4Item>>>,
+    table_4: RwLock<Vec<TableBucket<Table4Item>>>,
     vector_6: Vector6,
-    table_6: RwLock<Vec<Vec<Table6Item>>>,
+    table_6: RwLock<Vec<TableBucket<Table6Item>>>,
+
+    // 上次生成的acl，匹配条件和资源组都未变化的acl复用其match_field
+    compiled_acls: AHashMap<u32, Acl>,
+    // 上次生成acl后内容发生变化的资源组
+    changed_groups: AHashSet<u16>,
 
     level: usize,
     current_level: usize,
@@ -260,22 +281,17 @@ impl FirstPath {
     const TABLE_SIZE: usize = 1 << Self::VECTOR_MASK_SIZE_MAX;
     const POLICY_LIMIT: u64 = 500000;
     const MEMORY_LIMIT: u64 = 1 << 20;
+    const INDEX_MEMORY_LIMIT: usize = 64 << 20;
 
     pub fn new(queue_count: usize, level: usize, map_size: usize, fast_disable: bool) -> FirstPath {
         FirstPath {
             group_ip_map: Some(AHashMap::new()),
             vector_4: Vector4::default(),
-            table_4: RwLock::new(
-                std::iter::repeat(Vec::new())
-                    .take(Self::TABLE_SIZE)
-                    .collect::<Vec<Vec<Table4Item>>>(),
-            ),
+            table_4: RwLock::new(Self::new_table()),
             vector_6: Vector6::default(),
-            table_6: RwLock::new(
-                std::iter::repeat(Vec::new())
-                    .take(Self::TABLE_SIZE)
-                    .collect::<Vec<Vec<Table6Item>>>(),
-            ),
+            table_6: RwLock::new(Self::new_table()),
+            compiled_acls: AHashMap::new(),
+            changed_groups: AHashSet::new(),
             level,
             current_level: level,
 
@@ -285,6 +301,12 @@ impl FirstPath {
         }
     }
 
+    fn new_table<T>() -> Vec<TableBucket<T>> {
+        (0..Self::TABLE_SIZE)
+            .map(|_| TableBucket::default())
+            .collect()
+    }
+
     pub fn update_interfaces(&mut self, ifaces: &Vec<Arc<PlatformData>>) {
         self.fast.generate_mask_from_interface(ifaces);
         self.fast.generate_mask_table();
@@ -309,6 +331,19 @@ impl FirstPath {
             }
         }
 
+        if let Some(last) = self.group_ip_map.as_ref() {
+            for (id, segments) in group_ip_map.iter() {
+                if last.get(id) != Some(segments) {
+                    self.changed_groups.insert(*id);
+                }
+            }
+            for id in last.keys() {
+                if !group_ip_map.contains_key(id) {
+                    self.changed_groups.insert(*id);
+                }
+            }
+        }
+
         self.group_ip_map.replace(group_ip_map);
     }
 
@@ -384,6 +419,13 @@ impl FirstPath {
     fn generate_acl_bits(&mut self, acls: &mut Vec<Acl>) -> PResult<u64> {
         let mut memory = 0;
         for acl in acls {
+            // 复用上次生成的结果
+            if !acl.match_field.is_empty() || !acl.match_field6.is_empty() {
+                memory += (Fieldv4::SIZE * acl.match_field.len()
+                    + Fieldv6::SIZE * acl.match_field6.len()) as u64;
+                continue;
+            }
+
             let mut src_ips = Vec::new();
             let mut dst_ips = Vec::new();
 
@@ -488,9 +530,7 @@ impl FirstPath {
     }
 
     fn generate_table4(&mut self, acls: &mut Vec<Acl>) -> PResult<()> {
-        let mut table_4 = std::iter::repeat(Vec::new())
-            .take(Self::TABLE_SIZE)
-            .collect::<Vec<Vec<Table4Item>>>();
+        let mut table_4 = Self::new_table::<Table4Item>();
 
         for acl in acls {
             for v4 in &acl.match_field {
@@ -500,7 +540,7 @@ impl FirstPath {
                     self.vector_4.max_bit,
                     &self.vector_4.vector_bits,
                 ) {
-                    table_4[index as usize].push(Table4Item {
+                    table_4[index as usize].items.push(Table4Item {
                         field: v4.clone(),
                         policy: acl.policy.clone(),
                     });
@@ -508,15 +548,32 @@ impl FirstPath {
             }
         }
 
+        let mut index_memory = 0;
+        for bucket in table_4.iter_mut() {
+            if bucket.items.len() < IntervalIndex::ITEMS_MIN {
+                continue;
+            }
+            // 超出内存限制的桶仍逐条比较
+            if index_memory >= Self::INDEX_MEMORY_LIMIT {
+                break;
+            }
+            let items = bucket
+                .items
+                .iter()
+                .map(|x| (&x.field.field, &x.field.mask))
+                .collect::<Vec<_>>();
+            bucket.index = IntervalIndex::new(&items);
+            index_memory += bucket.index.as_ref().map(|x| x.memory()).unwrap_or(0);
+        }
+        info!("Policy table4 interval index memory {}B.", index_memory);
+
         *self.table_4.write().unwrap() = table_4;
 
         Ok(())
     }
 
     fn generate_table6(&mut self, acls: &mut Vec<Acl>) -> PResult<()> {
-        let mut table_6 = std::iter::repeat(Vec::new())
-            .take(Self::TABLE_SIZE)
-            .collect::<Vec<Vec<Table6Item>>>();
+        let mut table_6 = Self::new_table::<Table6Item>();
 
         for acl in acls {
             for v6 in &acl.match_field6 {
@@ -526,7 +583,7 @@ impl FirstPath {
                     self.vector_6.max_bit,
                     &self.vector_6.vector_bits,
                 ) {
-                    table_6[index as usize].push(Table6Item {
+                    table_6[index as usize].items.push(Table6Item {
                         field: v6.clone(),
                         policy: acl.policy.clone(),
                     });
@@ -534,6 +591,25 @@ impl FirstPath {
             }
         }
 
+        let mut index_memory = 0;
+        for bucket in table_6.iter_mut() {
+            if bucket.items.len() < IntervalIndex::ITEMS_MIN {
+                continue;
+            }
+            // 超出内存限制的桶仍逐条比较
+            if index_memory >= Self::INDEX_MEMORY_LIMIT {
+                break;
+            }
+            let items = bucket
+                .items
+                .iter()
+                .map(|x| (&x.field.field, &x.field.mask))
+                .collect::<Vec<_>>();
+            bucket.index = IntervalIndex::new(&items);
+            index_memory += bucket.index.as_ref().map(|x| x.memory()).unwrap_or(0);
+        }
+        info!("Policy table6 interval index memory {}B.", index_memory);
+
         *self.table_6.write().unwrap() = table_6;
 
         Ok(())
@@ -581,22 +657,62 @@ impl FirstPath {
         Ok(())
     }
 
-    pub fn update_acl(&mut self, acls: &Vec<Arc<Acl>>, check: bool) -> PResult<()> {
-        if !NOT_SUPPORT {
-            let mut valid_acls = Vec::new();
+    fn compile_acls(&mut self, acls: &Vec<Arc<Acl>>, check: bool) -> PResult<()> {
+        let mut valid_acls = Vec::new();
+        let mut reused = 0;
 
-            for acl in acls {
-                if self.is_invalid_acl(acl, check) {
-                    continue;
+        for acl in acls {
+            if self.is_invalid_acl(acl, check) {
+                continue;
+            }
+            let valid_acl = match self.compiled_acls.get(&acl.id) {
+                Some(last)
+                    if last.is_same_rule(acl)
+                        && !last
+                            .src_groups
+                            .iter()
+                            .chain(last.dst_groups.iter())
+                            .any(|x| self.changed_groups.contains(&(*x as u16))) =>
+                {
+                    reused += 1;
+                    let mut valid_acl = (**acl).clone();
+                    valid_acl.match_field = last.match_field.clone();
+                    valid_acl.match_field6 = last.match_field6.clone();
+                    valid_acl
                 }
-                let mut valid_acl = (**acl).clone();
+                _ => {
+                    let mut valid_acl = (**acl).clone();
+                    valid_acl.reset();
+                    valid_acl
+                }
+            };
+            valid_acls.push(valid_acl);
+        }
+        info!(
+            "Policy update {} acls, {} reused, {} recompiled.",
+            valid_acls.len(),
+            reused,
+            valid_acls.len() - reused
+        );
 
-                valid_acl.reset();
-                valid_acls.push(valid_acl);
-            }
+        if reused == valid_acls.len() && reused == self.compiled_acls.len() && reused > 0 {
+            // 匹配条件均未变化，只需要更新表项中的策略
+            self.generate_table4(&mut valid_acls)?;
+            self.generate_table6(&mut valid_acls)?;
+        } else {
             self.generate_first_table(&mut valid_acls)?;
         }
 
+        self.compiled_acls = valid_acls.into_iter().map(|x| (x.id, x)).collect();
+        self.changed_groups.clear();
+        Ok(())
+    }
+
+    pub fn update_acl(&mut self, acls: &Vec<Arc<Acl>>, check: bool) -> PResult<()> {
+        if !NOT_SUPPORT {
+            self.compile_acls(acls, check)?;
+        }
+
         // fast
         self.fast.generate_interest_table(acls);
 
@@ -618,10 +734,16 @@ impl FirstPath {
             self.vector_4.min_bit,
             self.vector_4.max_bit,
         ) as usize;
-        for item in &self.table_4.read().unwrap()[index] {
+        let table = self.table_4.read().unwrap();
+        let bucket = &table[index];
+        let mut matched = |item: &Table4Item| {
             if field & &item.field.mask == item.field.field {
                 policy.merge_npb_actions(&item.policy.npb_actions, item.policy.acl_id, direction);
             }
+        };
+        match bucket.index.as_ref() {
+            Some(interval) => interval.for_each_candidate(field, |i| matched(&bucket.items[i])),
+            None => bucket.items.iter().for_each(matched),
         }
     }
 
@@ -636,10 +758,16 @@ impl FirstPath {
             self.vector_6.min_bit,
             self.vector_6.max_bit,
         ) as usize;
-        for item in &self.table_6.read().unwrap()[index] {
+        let table = self.table_6.read().unwrap();
+        let bucket = &table[index];
+        let mut matched = |item: &Table6Item| {
             if field & &item.field.mask == item.field.field {
                 policy.merge_npb_actions(&item.policy.npb_actions, item.policy.acl_id, direction);
             }
+        };
+        match bucket.index.as_ref() {
+            Some(interval) => interval.for_each_candidate(field, |i| matched(&bucket.items[i])),
+            None => bucket.items.iter().for_each(matched),
         }
     }
 
@@ -923,4 +1051,121 @@ mod tests {
         assert_eq!(policy.npb_actions.len(), 1);
         assert_eq!(policy.acl_id, 1);
     }
+
+    fn new_acl(id: u32, src_group: u32, dst_group: u32, dst_port: u16) -> Arc<Acl> {
+        Arc::new(Acl::new(
+            id,
+            vec![src_group],
+            vec![dst_group],
+            vec![],
+            vec![PortRange::new(dst_port, dst_port)],
+            NpbAction::new(
+                0,
+                100,
+                "192.168.1.100".parse::<IpAddr>().unwrap(),
+                1,
+                NpbTunnelType::VxLan,
+                TapSide::SRC,
+                DirectionType::ALL,
+                0,
+            ),
+        ))
+    }
+
+    fn lookup(first: &mut FirstPath, dst_ip: &str, dst_port: u16) -> PolicyData {
+        let endpoints = EndpointData {
+            src_info: EndpointInfo {
+                l3_epc_id: 2,
+                ..Default::default()
+            },
+            dst_info: EndpointInfo {
+                l3_epc_id: 20,
+                ..Default::default()
+            },
+        };
+        let mut key = LookupKey {
+            src_ip: "192.168.2.1".parse::<IpAddr>().unwrap(),
+            dst_ip: dst_ip.parse::<IpAddr>().unwrap(),
+            src_port: 1234,
+            dst_port,
+            tap_type: TapType::Cloud,
+            ..Default::default()
+        };
+        let mut policy = PolicyData::default();
+        first.get_policy_from_table(&mut key, &endpoints, &mut policy);
+        policy
+    }
+
+    #[test]
+    fn test_interval_index() {
+        let mut first = FirstPath::new(1, 8, 1 << 16, false);
+        update_ip_group(
+            &mut first,
+            &vec![
+                Arc::new(IpGroupData::new(10, 2, "192.168.2.1/32")),
+                Arc::new(IpGroupData::new(20, 20, "192.168.2.5/32")),
+            ],
+        );
+        let acls = (1..=1000).map(|i| new_acl(i, 10, 20, i as u16)).collect();
+        first.compile_acls(&acls, true).unwrap();
+        assert!(first
+            .table_4
+            .read()
+            .unwrap()
+            .iter()
+            .any(|x| x.index.is_some()));
+
+        for port in [1, 57, 512, 1000] {
+            let policy = lookup(&mut first, "192.168.2.5", port);
+            assert_eq!(policy.acl_id, port as u32);
+            assert_eq!(policy.npb_actions.len(), 1);
+        }
+        assert_eq!(lookup(&mut first, "192.168.2.5", 1001).npb_actions.len(), 0);
+        assert_eq!(lookup(&mut first, "192.168.2.6", 57).npb_actions.len(), 0);
+    }
+
+    #[test]
+    fn test_incremental_compile() {
+        let mut first = FirstPath::new(1, 8, 1 << 16, false);
+        let mut groups = vec![
+            Arc::new(IpGroupData::new(10, 2, "192.168.2.1/32")),
+            Arc::new(IpGroupData::new(20, 20, "192.168.2.5/32")),
+            Arc::new(IpGroupData::new(30, 20, "192.168.3.5/32")),
+        ];
+        update_ip_group(&mut first, &groups);
+        first
+            .compile_acls(&vec![new_acl(1, 10, 20, 80), new_acl(2, 10, 30, 80)], true)
+            .unwrap();
+        let field_ptr =
+            |first: &FirstPath, id: u32| Arc::as_ptr(&first.compiled_acls[&id].match_field[0]);
+        let (acl_1, acl_2) = (field_ptr(&first, 1), field_ptr(&first, 2));
+
+        // 内容相同的acl复用上次的结果
+        update_ip_group(&mut first, &groups);
+        first
+            .compile_acls(&vec![new_acl(1, 10, 20, 80), new_acl(2, 10, 30, 80)], true)
+            .unwrap();
+        assert_eq!(field_ptr(&first, 1), acl_1);
+        assert_eq!(field_ptr(&first, 2), acl_2);
+        assert_eq!(lookup(&mut first, "192.168.2.5", 80).acl_id, 1);
+
+        // 资源组变化的acl重新生成
+        groups[1] = Arc::new(IpGroupData::new(20, 20, "192.168.2.6/32"));
+        update_ip_group(&mut first, &groups);
+        first
+            .compile_acls(&vec![new_acl(1, 10, 20, 80), new_acl(2, 10, 30, 80)], true)
+            .unwrap();
+        assert_ne!(field_ptr(&first, 1), acl_1);
+        assert_eq!(field_ptr(&first, 2), acl_2);
+        assert_eq!(lookup(&mut first, "192.168.2.5", 80).npb_actions.len(), 0);
+        assert_eq!(lookup(&mut first, "192.168.2.6", 80).acl_id, 1);
+
+        // 匹配条件变化的acl重新生成
+        first
+            .compile_acls(&vec![new_acl(1, 10, 20, 80), new_acl(2, 10, 30, 81)], true)
+            .unwrap();
+        assert_ne!(field_ptr(&first, 2), acl_2);
+        assert_eq!(lookup(&mut first, "192.168.3.5", 80).npb_actions.len(), 0);
+        assert_eq!(lookup(&mut first, "192.168.3.5", 81).acl_id, 2);
+    }
 }
@@ -0,0 +1,283 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::common::matched_field::{MatchedFieldN, MatchedFlag};
+
+const DIMENSIONS: [MatchedFlag; 8] = [
+    MatchedFlag::SrcIp,
+    MatchedFlag::DstIp,
+    MatchedFlag::SrcEpc,
+    MatchedFlag::DstEpc,
+    MatchedFlag::SrcPort,
+    MatchedFlag::DstPort,
+    MatchedFlag::Proto,
+    MatchedFlag::TapType,
+];
+
+fn field_width<const N: usize>(flag: MatchedFlag) -> u32 {
+    match flag {
+        MatchedFlag::SrcIp | MatchedFlag::DstIp => N as u32 * u8::BITS,
+        MatchedFlag::SrcEpc | MatchedFlag::DstEpc | MatchedFlag::SrcPort | MatchedFlag::DstPort => {
+            u16::BITS
+        }
+        MatchedFlag::Proto | MatchedFlag::TapType => u8::BITS,
+    }
+}
+
+fn field_value<const N: usize>(field: &MatchedFieldN<N>, flag: MatchedFlag) -> u128 {
+    match flag {
+        MatchedFlag::SrcIp | MatchedFlag::DstIp => field.get_ip_bits(flag),
+        _ => field.get(flag) as u128,
+    }
+}
+
+// 返回field/mask匹配的取值区间[min, max]
+//
+// 端口和IP网段生成的掩码都是前缀掩码，匹配一个连续区间；非前缀掩码按全范围处理，
+// 由查找后的精确比较过滤
+fn field_range(field: u128, mask: u128, width: u32) -> (u128, u128) {
+    let full = if width >= u128::BITS {
+        u128::MAX
+    } else {
+        (1 << width) - 1
+    };
+    let host = !mask & full;
+    if host & host.wrapping_add(1) != 0 {
+        return (0, full);
+    }
+    let min = field & mask & full;
+    (min, min | host)
+}
+
+struct Dimension {
+    flag: MatchedFlag,
+    // 各基本区间的起始值，bounds[0]恒为0
+    bounds: Vec<u128>,
+    // 各基本区间内可能匹配的表项
+    bitmaps: Vec<Vec<u64>>,
+}
+
+impl Dimension {
+    fn build<const N: usize>(
+        flag: MatchedFlag,
+        items: &[(&MatchedFieldN<N>, &MatchedFieldN<N>)],
+    ) -> Self {
+        let width = field_width::<N>(flag);
+        let ranges = items
+            .iter()
+            .map(|(field, mask)| {
+                field_range(field_value(field, flag), field_value(mask, flag), width)
+            })
+            .collect::<Vec<_>>();
+
+        let mut bounds = vec![0];
+        for (min, max) in ranges.iter() {
+            bounds.push(*min);
+            if let Some(next) = max.checked_add(1) {
+                bounds.push(next);
+            }
+        }
+        let full = field_range(0, 0, width).1;
+        bounds.retain(|b| *b <= full);
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let words = items.len().div_ceil(u64::BITS as usize);
+        let mut bitmaps = vec![vec![0u64; words]; bounds.len()];
+        for (i, (min, max)) in ranges.iter().enumerate() {
+            let start = bounds.partition_point(|b| *b < *min);
+            let end = bounds.partition_point(|b| *b <= *max);
+            for bitmap in bitmaps[start..end].iter_mut() {
+                bitmap[i >> 6] |= 1 << (i & 63);
+            }
+        }
+
+        Self {
+            flag,
+            bounds,
+            bitmaps,
+        }
+    }
+
+    fn lookup<const N: usize>(&self, key: &MatchedFieldN<N>) -> &[u64] {
+        let value = field_value(key, self.flag);
+        // bounds[0]为0，partition_point至少为1
+        &self.bitmaps[self.bounds.partition_point(|b| *b <= value) - 1]
+    }
+
+    fn memory(&self) -> usize {
+        self.bounds.len() * (16 + self.bitmaps.first().map(|b| b.len() * 8).unwrap_or(0))
+    }
+}
+
+// 按字段分类的区间位图索引
+//
+// 每个字段将表项的取值区间切分为互不重叠的基本区间，每个基本区间对应一个表项位图，
+// 查找时各字段二分定位基本区间后将位图按位与，得到候选表项。候选表项仍需精确比较，
+// 索引只用于减少一个桶内需要比较的表项数量。
+pub struct IntervalIndex {
+    words: usize,
+    dimensions: Vec<Dimension>,
+}
+
+impl IntervalIndex {
+    // 表项少于该值时直接遍历
+    pub const ITEMS_MIN: usize = 32;
+    const MEMORY_MAX: usize = 4 << 20;
+
+    pub fn new<const N: usize>(items: &[(&MatchedFieldN<N>, &MatchedFieldN<N>)]) -> Option<Self> {
+        if items.len() < Self::ITEMS_MIN {
+            return None;
+        }
+
+        let mut memory = 0;
+        let mut dimensions = vec![];
+        for flag in DIMENSIONS {
+            let dimension = Dimension::build(flag, items);
+            // 所有表项都是全匹配的字段没有区分度
+            if dimension.bounds.len() <= 1 {
+                continue;
+            }
+            if memory + dimension.memory() > Self::MEMORY_MAX {
+                continue;
+            }
+            memory += dimension.memory();
+            dimensions.push(dimension);
+        }
+        if dimensions.is_empty() {
+            return None;
+        }
+        // 区间多的字段通常区分度高，放在前面以便尽早得到全0
+        dimensions.sort_by(|a, b| b.bounds.len().cmp(&a.bounds.len()));
+
+        Some(Self {
+            words: items.len().div_ceil(u64::BITS as usize),
+            dimensions,
+        })
+    }
+
+    pub fn memory(&self) -> usize {
+        self.dimensions.iter().map(|d| d.memory()).sum()
+    }
+
+    // 按表项下标升序回调候选表项
+    pub fn for_each_candidate<const N: usize, F: FnMut(usize)>(
+        &self,
+        key: &MatchedFieldN<N>,
+        mut f: F,
+    ) {
+        let mut bitmaps: [&[u64]; DIMENSIONS.len()] = [&[]; DIMENSIONS.len()];
+        for (i, dimension) in self.dimensions.iter().enumerate() {
+            bitmaps[i] = dimension.lookup(key);
+        }
+        let bitmaps = &bitmaps[..self.dimensions.len()];
+
+        for w in 0..self.words {
+            let mut word = u64::MAX;
+            for bitmap in bitmaps {
+                word &= bitmap[w];
+                if word == 0 {
+                    break;
+                }
+            }
+            while word != 0 {
+                f(w * u64::BITS as usize + word.trailing_zeros() as usize);
+                word &= word - 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::common::matched_field::MatchedFieldv4;
+
+    #[test]
+    fn range() {
+        assert_eq!(field_range(80, 0xFFFF, 16), (80, 80));
+        assert_eq!(field_range(1024, 0xFC00, 16), (1024, 2047));
+        assert_eq!(field_range(12345, 0, 16), (0, 0xFFFF));
+        // 非前缀掩码
+        assert_eq!(field_range(1, 0x00FF, 16), (0, 0xFFFF));
+        assert_eq!(field_range(1, 0, 128), (0, u128::MAX));
+    }
+
+    fn new_item(
+        dst_ip: Ipv4Addr,
+        dst_mask: Ipv4Addr,
+        port: u16,
+    ) -> (MatchedFieldv4, MatchedFieldv4) {
+        let (mut field, mut mask) = (MatchedFieldv4::default(), MatchedFieldv4::default());
+        field.set_ip(MatchedFlag::DstIp, dst_ip);
+        mask.set_ip(MatchedFlag::DstIp, dst_mask);
+        field.set(MatchedFlag::DstPort, port);
+        mask.set_mask(MatchedFlag::DstPort, true);
+        (field, mask)
+    }
+
+    #[test]
+    fn candidates() {
+        let mut items = vec![];
+        for i in 0..100u16 {
+            items.push(new_item(
+                Ipv4Addr::new(10, 0, i as u8, 0),
+                Ipv4Addr::new(255, 255, 255, 0),
+                i,
+            ));
+        }
+        // 匹配所有端口
+        let (field, mut mask) =
+            new_item(Ipv4Addr::new(10, 0, 0, 0), Ipv4Addr::new(255, 0, 0, 0), 0);
+        mask.set_mask(MatchedFlag::DstPort, false);
+        items.push((field, mask));
+
+        let refs = items.iter().map(|(f, m)| (f, m)).collect::<Vec<_>>();
+        let index = IntervalIndex::new(&refs).unwrap();
+
+        let mut key = MatchedFieldv4::default();
+        key.set_ip(MatchedFlag::DstIp, Ipv4Addr::new(10, 0, 7, 1));
+        key.set(MatchedFlag::DstPort, 7);
+        let mut candidates = vec![];
+        index.for_each_candidate(&key, |i| candidates.push(i));
+        assert_eq!(candidates, vec![7, 100]);
+
+        key.set(MatchedFlag::DstPort, 8);
+        candidates.clear();
+        index.for_each_candidate(&key, |i| candidates.push(i));
+        assert_eq!(candidates, vec![100]);
+
+        key.set_ip(MatchedFlag::DstIp, Ipv4Addr::new(11, 0, 7, 1));
+        candidates.clear();
+        index.for_each_candidate(&key, |i| candidates.push(i));
+        assert!(candidates.is_empty());
+
+        // 候选结果须包含所有精确匹配的表项
+        for i in 0..200u16 {
+            key.set_ip(MatchedFlag::DstIp, Ipv4Addr::new(10, 0, (i % 120) as u8, 3));
+            key.set(MatchedFlag::DstPort, i % 110);
+            let mut candidates = vec![];
+            index.for_each_candidate(&key, |i| candidates.push(i));
+            for (j, (field, mask)) in items.iter().enumerate() {
+                if &key & mask == *field {
+                    assert!(candidates.contains(&j));
+                }
+            }
+        }
+    }
+}
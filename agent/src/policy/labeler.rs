@@ -92,6 +92,9 @@ pub struct Labeler {
     tunnel_cidr_table: RwLock<AHashMap<u32, Vec<Arc<Cidr>>>>,
     // Container
     container_table: RwLock<AHashMap<String, u32>>,
+    // EPC所属租户表，分别来自Interface和CIDR，优先使用Interface
+    interface_tenant_table: RwLock<AHashMap<i32, u32>>,
+    cidr_tenant_table: RwLock<AHashMap<i32, u32>>,
 }
 
 impl Default for Labeler {
@@ -107,6 +110,8 @@ impl Default for Labeler {
             epc_cidr_table: RwLock::new(AHashMap::new()),
             tunnel_cidr_table: RwLock::new(AHashMap::new()),
             container_table: RwLock::new(AHashMap::new()),
+            interface_tenant_table: RwLock::new(AHashMap::new()),
+            cidr_tenant_table: RwLock::new(AHashMap::new()),
         }
     }
 }
@@ -303,6 +308,47 @@ impl Labeler {
         *self.tunnel_cidr_table.write().unwrap() = tunnel_table;
         *self.epc_cidr_masklen_table.write().unwrap() = masklen_table;
         *self.epc_cidr_table.write().unwrap() = epc_table;
+        *self.cidr_tenant_table.write().unwrap() =
+            Self::build_tenant_table(cidrs.iter().map(|c| (c.epc_id, c.tenant_id)));
+    }
+
+    fn build_tenant_table<I: Iterator<Item = (i32, u32)>>(items: I) -> AHashMap<i32, u32> {
+        let mut table = AHashMap::new();
+        for (epc_id, tenant_id) in items {
+            if epc_id <= 0 || tenant_id == 0 {
+                continue;
+            }
+            if let Some(old) = table.insert(epc_id, tenant_id) {
+                if old != tenant_id {
+                    warn!(
+                        "Found epc {} in tenant {} and {}, please check.",
+                        epc_id, old, tenant_id
+                    );
+                }
+            }
+        }
+        table
+    }
+
+    fn get_tenant_by_epc(&self, epc_id: i32) -> u32 {
+        if epc_id <= 0 {
+            return 0;
+        }
+        if let Some(tenant_id) = self.interface_tenant_table.read().unwrap().get(&epc_id) {
+            return *tenant_id;
+        }
+        self.cidr_tenant_table
+            .read()
+            .unwrap()
+            .get(&epc_id)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // 租户由最终的l3_epc_id决定，须在所有EPC修正之后调用
+    fn set_tenant(&self, endpoint: &mut EndpointData) {
+        endpoint.src_info.tenant_id = self.get_tenant_by_epc(endpoint.src_info.l3_epc_id);
+        endpoint.dst_info.tenant_id = self.get_tenant_by_epc(endpoint.dst_info.l3_epc_id);
     }
 
     pub fn update_container(&mut self, containers: &Vec<Arc<Container>>) {
@@ -471,6 +517,8 @@ impl Labeler {
         self.update_mac_table(interfaces);
         self.update_epc_ip_table(interfaces);
         self.update_ip_table(interfaces);
+        *self.interface_tenant_table.write().unwrap() =
+            Self::build_tenant_table(interfaces.iter().map(|i| (i.epc_id, i.tenant_id)));
     }
 
     fn get_endpoint_info(
@@ -719,6 +767,7 @@ impl Labeler {
         // 1. EPC通过另一端EPC查询时统一按照LAN处理
         self.get_vip(key, is_src_wan, is_dst_wan, &mut endpoint);
         self.modify_internet_epc(&key.src_ip, &key.dst_ip, &mut endpoint);
+        self.set_tenant(&mut endpoint);
         return endpoint;
     }
 
@@ -766,6 +815,7 @@ impl Labeler {
             self.get_l3_by_peer(key.src_ip, key.dst_ip, &mut endpoint);
         }
         self.modify_internet_epc(&src, &dst, &mut endpoint);
+        self.set_tenant(&mut endpoint);
         return endpoint;
     }
 }
@@ -1252,4 +1302,46 @@ mod tests {
         labeler.get_vip(&key, false, false, &mut endpoints);
         assert_eq!(endpoints.dst_info.is_vip, true);
     }
+
+    #[test]
+    fn test_tenant() {
+        let mut labeler: Labeler = Default::default();
+        let interface = PlatformData {
+            mac: 0x112233445566,
+            ips: vec![IpSubnet {
+                raw_ip: "192.168.10.100".parse().unwrap(),
+                ..Default::default()
+            }],
+            epc_id: 1,
+            tenant_id: 100,
+            ..Default::default()
+        };
+        let cidr = Cidr {
+            ip: IpNet::from_str("172.29.0.0/16").unwrap(),
+            epc_id: 2,
+            tenant_id: 200,
+            ..Default::default()
+        };
+        labeler.update_interface_table(&vec![Arc::new(interface)]);
+        labeler.update_cidr_table(&vec![Arc::new(cidr)]);
+
+        let endpoints = labeler.get_endpoint_data_by_epc(
+            "192.168.10.100".parse().unwrap(),
+            "172.29.20.200".parse().unwrap(),
+            1,
+            2,
+        );
+        assert_eq!(endpoints.src_info.tenant_id, 100);
+        assert_eq!(endpoints.dst_info.tenant_id, 200);
+
+        // 公网没有租户
+        let endpoints = labeler.get_endpoint_data_by_epc(
+            "192.168.10.100".parse().unwrap(),
+            "8.8.8.8".parse().unwrap(),
+            1,
+            0,
+        );
+        assert_eq!(endpoints.src_info.tenant_id, 100);
+        assert_eq!(endpoints.dst_info.tenant_id, 0);
+    }
 }
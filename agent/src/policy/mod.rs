@@ -18,6 +18,7 @@ mod bit;
 mod fast_path;
 pub mod first_path;
 mod forward;
+mod interval;
 pub mod labeler;
 pub mod policy;
 
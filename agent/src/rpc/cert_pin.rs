@@ -0,0 +1,225 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs;
+use std::sync::Arc;
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use grpc::CertVerifier;
+use log::error;
+use ring::digest;
+
+const SPKI_PIN_PREFIX: &str = "sha256//";
+const PEM_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const PEM_END: &str = "-----END CERTIFICATE-----";
+
+const DER_SEQUENCE: u8 = 0x30;
+const DER_EXPLICIT_0: u8 = 0xa0;
+
+// Pinned controller certificates, the certificate presented by controller must match
+// any of them in addition to chaining to a trusted CA
+//
+// A pin is either
+// - `sha256//<base64>`: SHA-256 of the DER encoded SubjectPublicKeyInfo, same as
+//   `curl --pinnedpubkey`, survives certificate renewal with the same key
+// - path to a PEM or DER encoded certificate file, matches the exact certificate
+#[derive(Debug, Default)]
+pub struct CertPins {
+    spki_sha256: Vec<Vec<u8>>,
+    certs: Vec<Vec<u8>>,
+}
+
+impl CertPins {
+    pub fn parse(pins: &[String]) -> Result<Self, String> {
+        let mut result = Self::default();
+        for pin in pins.iter() {
+            if let Some(hash) = pin.strip_prefix(SPKI_PIN_PREFIX) {
+                match BASE64_STANDARD.decode(hash) {
+                    Ok(h) if h.len() == digest::SHA256_OUTPUT_LEN => result.spki_sha256.push(h),
+                    _ => return Err(format!("invalid sha256 pin \"{}\"", pin)),
+                }
+                continue;
+            }
+            let content =
+                fs::read(pin).map_err(|e| format!("read pinned cert {} failed: {}", pin, e))?;
+            let der = match pem_to_der(&content) {
+                Some(der) => der,
+                None => content,
+            };
+            if spki(&der).is_none() {
+                return Err(format!("pinned cert {} is not a valid certificate", pin));
+            }
+            result.certs.push(der);
+        }
+        Ok(result)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spki_sha256.is_empty() && self.certs.is_empty()
+    }
+
+    // `cert` is the DER encoded end-entity certificate presented by controller
+    pub fn verify(&self, cert: &[u8]) -> Result<(), String> {
+        if self.certs.iter().any(|c| c == cert) {
+            return Ok(());
+        }
+        if !self.spki_sha256.is_empty() {
+            let Some(spki) = spki(cert) else {
+                return Err("malformed controller certificate".to_owned());
+            };
+            let hash = digest::digest(&digest::SHA256, spki);
+            if self.spki_sha256.iter().any(|h| h == hash.as_ref()) {
+                return Ok(());
+            }
+        }
+        Err("controller certificate does not match any pin".to_owned())
+    }
+}
+
+// Pins are checked in config validation, an invalid pin still rejects every certificate
+// instead of disabling pinning
+pub fn cert_verifier(pins: &[String]) -> Option<CertVerifier> {
+    match CertPins::parse(pins) {
+        Ok(pins) if pins.is_empty() => None,
+        Ok(pins) => Some(Arc::new(move |cert: &[u8]| pins.verify(cert))),
+        Err(e) => {
+            error!("{}", e);
+            Some(Arc::new(move |_: &[u8]| Err(e.clone())))
+        }
+    }
+}
+
+fn pem_to_der(content: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(content).ok()?;
+    let (_, rest) = text.split_once(PEM_BEGIN)?;
+    let (body, _) = rest.split_once(PEM_END)?;
+    BASE64_STANDARD
+        .decode(body.split_whitespace().collect::<String>())
+        .ok()
+}
+
+// returns (tag, content, remaining)
+fn der_next(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&len, data) = data.split_first()?;
+    let (len, data) = if len & 0x80 == 0 {
+        (len as usize, data)
+    } else {
+        let n = (len & 0x7f) as usize;
+        if n == 0 || n > 4 || data.len() < n {
+            return None;
+        }
+        let len = data[..n]
+            .iter()
+            .fold(0usize, |acc, b| acc << 8 | *b as usize);
+        (len, &data[n..])
+    };
+    if data.len() < len {
+        return None;
+    }
+    Some((tag, &data[..len], &data[len..]))
+}
+
+// DER encoded SubjectPublicKeyInfo of a X.509 certificate, including tag and length
+fn spki(cert: &[u8]) -> Option<&[u8]> {
+    let (tag, cert, _) = der_next(cert)?;
+    if tag != DER_SEQUENCE {
+        return None;
+    }
+    let (tag, mut tbs, _) = der_next(cert)?;
+    if tag != DER_SEQUENCE {
+        return None;
+    }
+    if tbs.first() == Some(&DER_EXPLICIT_0) {
+        // version
+        tbs = der_next(tbs)?.2;
+    }
+    // serialNumber, signature, issuer, validity, subject
+    for _ in 0..5 {
+        tbs = der_next(tbs)?.2;
+    }
+    let (tag, _, rest) = der_next(tbs)?;
+    if tag != DER_SEQUENCE {
+        return None;
+    }
+    Some(&tbs[..tbs.len() - rest.len()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    const CERT: &str = "-----BEGIN CERTIFICATE-----
+MIIBizCCATGgAwIBAgIUP9Ghrllb5VwlHHVVfAtRMp+F4icwCgYIKoZIzj0EAwIw
+GjEYMBYGA1UEAwwPZGVlcGZsb3ctc2VydmVyMCAXDTI2MTAxNjE3MjIwN1oYDzIx
+MjYwOTIyMTcyMjA3WjAaMRgwFgYDVQQDDA9kZWVwZmxvdy1zZXJ2ZXIwWTATBgcq
+hkjOPQIBBggqhkjOPQMBBwNCAAR5egSRTmoc8yzrneqXbTZyw4VmAyTg07HH8Smm
++hWr6PaCpYNfbAlVzjhfi6Bs+jq25SjLAdsyS6GllsPjEB+bo1MwUTAdBgNVHQ4E
+FgQUlhM9Ko4NefOz6Lgb5i8TxAQoyD0wHwYDVR0jBBgwFoAUlhM9Ko4NefOz6Lgb
+5i8TxAQoyD0wDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNIADBFAiEA70zs
+fXvThixeka1qwXEUMIJX0ymvF8aM7468+ZxEwIICIHsAPfv59RO99t0XAneObq2l
+g/bAlNYZ4L71vAUInUiY
+-----END CERTIFICATE-----
+";
+    // openssl x509 -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64
+    const SPKI_PIN: &str = "sha256//wIbOxz5qYuHsxuJs2efxqHlpWsdAKjkdjveNyGz32wc=";
+    const OTHER_PIN: &str = "sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+
+    #[test]
+    fn spki_pin() {
+        let cert = pem_to_der(CERT.as_bytes()).unwrap();
+
+        let pins = CertPins::parse(&[SPKI_PIN.to_owned()]).unwrap();
+        assert!(pins.verify(&cert).is_ok());
+        let pins = CertPins::parse(&[OTHER_PIN.to_owned()]).unwrap();
+        assert!(pins.verify(&cert).is_err());
+        assert!(pins.verify(&cert[..cert.len() / 2]).is_err());
+
+        assert!(CertPins::parse(&["sha256//AAAA".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn cert_file_pin() {
+        let cert = pem_to_der(CERT.as_bytes()).unwrap();
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(CERT.as_bytes()).unwrap();
+        let pins = CertPins::parse(&[
+            OTHER_PIN.to_owned(),
+            file.path().to_str().unwrap().to_owned(),
+        ])
+        .unwrap();
+        assert!(pins.verify(&cert).is_ok());
+        let mut other = cert.clone();
+        *other.last_mut().unwrap() ^= 1;
+        assert!(pins.verify(&other).is_err());
+
+        assert!(CertPins::parse(&["/nonexistent/cert.pem".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn invalid_pin_fails_closed() {
+        let cert = pem_to_der(CERT.as_bytes()).unwrap();
+
+        assert!(cert_verifier(&[]).is_none());
+        let verifier = cert_verifier(&[SPKI_PIN.to_owned()]).unwrap();
+        assert!(verifier(&cert).is_ok());
+        let verifier = cert_verifier(&[SPKI_PIN.to_owned(), "sha256//AAAA".to_owned()]).unwrap();
+        assert!(verifier(&cert).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#[path = "runtime.v1.rs"]
+mod runtime;
+
+use std::{
+    collections::VecDeque,
+    io::{self, SeekFrom},
+    path::Path,
+    time::Duration,
+};
+
+use log::debug;
+use thiserror::Error;
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+    net::UnixStream,
+};
+use tonic::transport::{Endpoint, Uri};
+use tower::service_fn;
+
+use runtime::{runtime_service_client::RuntimeServiceClient, ContainerStatusRequest};
+
+// tried in order if socket path is not configured
+const DEFAULT_SOCKETS: [&str; 2] = ["/run/containerd/containerd.sock", "/var/run/crio/crio.sock"];
+const CRI_API_TIMEOUT: Duration = Duration::from_secs(10);
+// only the tail of large log files is read
+const MAX_LOG_READ: u64 = 16 << 20;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("no cri socket found in {0:?}")]
+    SocketNotFound(Vec<String>),
+    #[error("connect to {0} failed: {1}")]
+    Transport(String, tonic::transport::Error),
+    #[error("{0}")]
+    Status(#[from] tonic::Status),
+    #[error("log path of container {0} not found")]
+    LogPathNotFound(String),
+    #[error("read log {0} failed: {1}")]
+    Io(String, io::Error),
+}
+
+fn socket_path(configured: &str) -> Result<String, Error> {
+    if !configured.is_empty() {
+        return Ok(configured.to_owned());
+    }
+    DEFAULT_SOCKETS
+        .iter()
+        .find(|p| Path::new(p).exists())
+        .map(|p| p.to_string())
+        .ok_or_else(|| {
+            Error::SocketNotFound(DEFAULT_SOCKETS.iter().map(|p| p.to_string()).collect())
+        })
+}
+
+// Log file of the container from container runtime status
+pub async fn container_log_path(socket_path: &str, container_id: &str) -> Result<String, Error> {
+    let path = self::socket_path(socket_path)?;
+    debug!("query container {} from cri {}", container_id, path);
+    let connect_path = path.clone();
+    // the uri is required by tonic but not used for unix socket
+    let channel = Endpoint::from_static("http://[::]:50051")
+        .timeout(CRI_API_TIMEOUT)
+        .connect_with_connector(service_fn(move |_: Uri| {
+            UnixStream::connect(connect_path.clone())
+        }))
+        .await
+        .map_err(|e| Error::Transport(path, e))?;
+    let status = RuntimeServiceClient::new(channel)
+        .container_status(ContainerStatusRequest {
+            container_id: container_id.to_owned(),
+            verbose: false,
+        })
+        .await?
+        .into_inner()
+        .status;
+    match status {
+        Some(s) if !s.log_path.is_empty() => Ok(s.log_path),
+        _ => Err(Error::LogPathNotFound(container_id.to_owned())),
+    }
+}
+
+// Last lines of the log file in CRI logging format
+pub async fn read_log(path: &str, lines: usize) -> Result<Vec<u8>, Error> {
+    let io_error = |e| Error::Io(path.to_owned(), e);
+    let mut file = File::open(path).await.map_err(io_error)?;
+    let len = file.metadata().await.map_err(io_error)?.len();
+    let start = len.saturating_sub(MAX_LOG_READ);
+    file.seek(SeekFrom::Start(start)).await.map_err(io_error)?;
+    let mut data = Vec::with_capacity((len - start) as usize);
+    file.read_to_end(&mut data).await.map_err(io_error)?;
+    if start > 0 {
+        // the first line is likely truncated
+        let first = data.iter().position(|c| *c == b'\n').map(|p| p + 1);
+        data.drain(..first.unwrap_or(data.len()));
+    }
+    Ok(parse_log(&data, lines))
+}
+
+// Each line is `<RFC3339Nano timestamp> <stdout|stderr> <P|F> <content>`, a long line is split
+// into partial (P) lines followed by a full (F) one
+fn parse_log(data: &[u8], lines: usize) -> Vec<u8> {
+    let mut output = VecDeque::new();
+    let mut current = vec![];
+    for line in data.split(|c| *c == b'\n') {
+        let mut fields = line.splitn(4, |c| *c == b' ');
+        let (Some(_), Some(_), Some(tag), content) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        current.extend_from_slice(content.unwrap_or_default());
+        if tag != b"P" {
+            current.push(b'\n');
+            if output.len() >= lines {
+                output.pop_front();
+            }
+            output.push_back(std::mem::take(&mut current));
+        }
+    }
+    output.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cri_log_format() {
+        let data = b"2024-01-01T00:00:00.000000001Z stdout F hello\n\
+            2024-01-01T00:00:01.000000001Z stderr P partial \n\
+            2024-01-01T00:00:01.000000002Z stderr F line\n\
+            malformed\n\
+            2024-01-01T00:00:02.000000001Z stdout F \n\
+            2024-01-01T00:00:03.000000001Z stdout F world";
+        assert_eq!(
+            parse_log(data, 10),
+            b"hello\npartial line\n\nworld\n".to_vec()
+        );
+        assert_eq!(parse_log(data, 2), b"\nworld\n".to_vec());
+    }
+}
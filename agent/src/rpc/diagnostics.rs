@@ -0,0 +1,521 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+    process::Output,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use arc_swap::ArcSwap;
+use flate2::{write::GzEncoder, Compression};
+use log::{debug, info};
+use nix::sys::utsname::uname;
+use parking_lot::RwLock;
+
+use public::{
+    proto::trident::{self as pb, Exception},
+    utils::net::{self, addr_list, link_list},
+};
+
+use super::synchronizer::{StaticConfig, Status};
+use crate::{
+    common::active_feature_flags, config::ModuleConfig, exception::ExceptionHandler,
+    trident::AgentId, utils::stats,
+};
+
+const BUNDLE_DIR: &str = "deepflow-agent-diagnostics";
+const LOG_TAIL_SIZE: u64 = 4 << 20;
+
+const TAR_BLOCK_SIZE: usize = 512;
+
+// Components in the state report and exceptions indicating they are unhealthy
+const COMPONENT_EXCEPTIONS: [(&str, &[Exception]); 7] = [
+    ("controller", &[Exception::ControllerSocketError]),
+    (
+        "analyzer",
+        &[Exception::AnalyzerSocketError, Exception::AnalyzerNoGwArp],
+    ),
+    ("integration", &[Exception::IntegrationSocketError]),
+    (
+        "npb",
+        &[
+            Exception::NpbFuse,
+            Exception::NpbBpsThresholdExceeded,
+            Exception::NpbNoGwArp,
+            Exception::NpbSocketError,
+            Exception::NpbEndpointDown,
+        ],
+    ),
+    (
+        "capture",
+        &[
+            Exception::RxPpsThresholdExceeded,
+            Exception::TooManyPolicies,
+        ],
+    ),
+    ("ebpf", &[]),
+    (
+        "resource",
+        &[
+            Exception::DiskNotEnough,
+            Exception::MemNotEnough,
+            Exception::CorefileTooMany,
+            Exception::ThreadThresholdExceeded,
+            Exception::ProcessThresholdExceeded,
+            Exception::FreeMemExceeded,
+            Exception::LogFileExceeded,
+            Exception::CgroupsConfigError,
+            Exception::SystemLoadCircuitBreaker,
+            Exception::InvalidConfiguration,
+        ],
+    ),
+];
+
+// Minimal ustar writer for regular files
+struct TarBuilder<W: Write> {
+    inner: W,
+    mtime: u64,
+}
+
+impl<W: Write> TarBuilder<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            mtime: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn write_octal(field: &mut [u8], value: u64) {
+        // zero padded with trailing nul
+        let s = format!("{:0width$o}", value, width = field.len() - 1);
+        field[..field.len() - 1].copy_from_slice(&s.as_bytes()[s.len() - (field.len() - 1)..]);
+        field[field.len() - 1] = 0;
+    }
+
+    fn append(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        if name.len() >= 100 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("tar entry name {} too long", name),
+            ));
+        }
+        let mut header = [0u8; TAR_BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        Self::write_octal(&mut header[100..108], 0o644);
+        Self::write_octal(&mut header[108..116], 0);
+        Self::write_octal(&mut header[116..124], 0);
+        Self::write_octal(&mut header[124..136], data.len() as u64);
+        Self::write_octal(&mut header[136..148], self.mtime);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        // checksum is calculated with the checksum field filled by spaces
+        header[148..156].fill(b' ');
+        let checksum: u32 = header.iter().map(|b| *b as u32).sum();
+        Self::write_octal(&mut header[148..155], checksum as u64);
+
+        self.inner.write_all(&header)?;
+        self.inner.write_all(data)?;
+        let padding = (TAR_BLOCK_SIZE - data.len() % TAR_BLOCK_SIZE) % TAR_BLOCK_SIZE;
+        self.inner.write_all(&[0u8; TAR_BLOCK_SIZE][..padding])
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(&[0u8; TAR_BLOCK_SIZE * 2])?;
+        Ok(self.inner)
+    }
+}
+
+// Sources of the agent self-diagnostics bundle and state report
+pub struct Diagnostics {
+    current_config: Arc<ArcSwap<ModuleConfig>>,
+    log_file: String,
+    stats_collector: Arc<stats::Collector>,
+    static_config: Arc<StaticConfig>,
+    sync_status: Arc<RwLock<Status>>,
+    exception_handler: ExceptionHandler,
+}
+
+impl Diagnostics {
+    pub fn new(
+        current_config: Arc<ArcSwap<ModuleConfig>>,
+        log_file: String,
+        stats_collector: Arc<stats::Collector>,
+        static_config: Arc<StaticConfig>,
+        sync_status: Arc<RwLock<Status>>,
+        exception_handler: ExceptionHandler,
+    ) -> Self {
+        Self {
+            current_config,
+            log_file,
+            stats_collector,
+            static_config,
+            sync_status,
+            exception_handler,
+        }
+    }
+
+    // Effective config of all modules
+    pub fn config(&self) -> String {
+        format!("{:#?}\n", self.current_config.load_full())
+    }
+
+    // Last reported values of agent counters in prometheus text format
+    pub fn metrics(&self) -> io::Result<Vec<u8>> {
+        let mut buffer = vec![];
+        self.stats_collector.write_prometheus(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    // Assembles a tar.gz bundle consisting of:
+    // - version.txt: version, revision and boot time of the agent
+    // - config.txt: effective config of all modules
+    // - agent.log: tail of the agent log file, last `log_lines` lines if not 0
+    // - stats.txt: last reported values of agent counters
+    // - ebpf.txt: kernel capabilities related to eBPF
+    // - interfaces.txt: interfaces and addresses in agent namespace
+    pub fn bundle(&self, log_lines: usize) -> io::Result<Vec<u8>> {
+        let mut tar = TarBuilder::new(GzEncoder::new(vec![], Compression::default()));
+
+        let mut buffer = vec![];
+        self.write_version(&mut buffer)?;
+        tar.append(&format!("{}/version.txt", BUNDLE_DIR), &buffer)?;
+
+        let config = self.current_config.load_full();
+        tar.append(
+            &format!("{}/config.txt", BUNDLE_DIR),
+            format!("{:#?}\n", config).as_bytes(),
+        )?;
+
+        let log = match tail_file(&self.log_file, LOG_TAIL_SIZE) {
+            Ok(log) => last_lines(&log, log_lines).to_vec(),
+            Err(e) => format!("read log file {} failed: {}\n", self.log_file, e).into_bytes(),
+        };
+        tar.append(&format!("{}/agent.log", BUNDLE_DIR), &log)?;
+
+        buffer.clear();
+        self.stats_collector.write_snapshot(&mut buffer)?;
+        tar.append(&format!("{}/stats.txt", BUNDLE_DIR), &buffer)?;
+
+        buffer.clear();
+        write_ebpf_capabilities(&mut buffer, &config)?;
+        tar.append(&format!("{}/ebpf.txt", BUNDLE_DIR), &buffer)?;
+
+        buffer.clear();
+        if let Err(e) = write_interfaces(&mut buffer) {
+            writeln!(&mut buffer, "list interfaces failed: {}", e)?;
+        }
+        tar.append(&format!("{}/interfaces.txt", BUNDLE_DIR), &buffer)?;
+
+        let bundle = tar.finish()?.finish()?;
+        info!("diagnostics bundle assembled with {} bytes", bundle.len());
+        Ok(bundle)
+    }
+
+    fn write_version<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "{}", self.static_config.version_info)?;
+        let boot_time = self
+            .static_config
+            .boot_time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        writeln!(w, "BootTime: {}", boot_time)?;
+        let uptime = self
+            .static_config
+            .boot_time
+            .elapsed()
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        writeln!(w, "Uptime: {}s", uptime)
+    }
+
+    // Effective config, enabled features and component health in one message,
+    // requested by the controller for fleet audits
+    pub fn state_report(&self, agent_id: &AgentId) -> pb::AgentStateReport {
+        let config = self.current_config.load_full();
+        let status = self.sync_status.read();
+        let exceptions = self.exception_handler.get();
+        let mut components = component_states(exceptions);
+        for c in components.iter_mut() {
+            c.detail = match c.name() {
+                "capture" if status.capture_paused => Some("paused".to_owned()),
+                "ebpf" if config.ebpf.ebpf.disabled => Some("disabled".to_owned()),
+                "ebpf" if status.ebpf_paused => Some("paused".to_owned()),
+                _ => None,
+            };
+        }
+        pb::AgentStateReport {
+            version: Some(self.static_config.version_info.to_string()),
+            revision: Some(self.static_config.version_info.revision.to_owned()),
+            boot_time: self
+                .static_config
+                .boot_time
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs() as u32),
+            config_accepted: Some(status.config_accepted),
+            effective_config: Some(format!("{:#?}", config)),
+            active_feature_flags: active_feature_flags(
+                &status.feature_flags,
+                &status.feature_flag_rollouts,
+                &agent_id.to_string(),
+                &self.static_config.vtap_group_id_request,
+            ),
+            active_l7_protocols: status.active_l7_protocols.clone(),
+            components,
+        }
+    }
+
+    pub async fn bundle_command(
+        self: Arc<Self>,
+        log_lines: usize,
+    ) -> super::remote_exec::Result<Output> {
+        let bundle = tokio::task::spawn_blocking(move || self.bundle(log_lines))
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+        Ok(Output {
+            status: Default::default(),
+            stdout: bundle,
+            stderr: vec![],
+        })
+    }
+}
+
+// Reads at most `size` bytes from the end of the file, starting from a new line
+fn tail_file<P: AsRef<Path>>(path: P, size: u64) -> io::Result<Vec<u8>> {
+    let mut fp = File::open(path)?;
+    let len = fp.metadata()?.len();
+    if len <= size {
+        let mut content = Vec::with_capacity(len as usize);
+        fp.read_to_end(&mut content)?;
+        return Ok(content);
+    }
+    fp.seek(SeekFrom::Start(len - size))?;
+    let mut content = Vec::with_capacity(size as usize);
+    fp.take(size).read_to_end(&mut content)?;
+    match content.iter().position(|c| *c == b'\n') {
+        Some(pos) => Ok(content.split_off(pos + 1)),
+        None => Ok(content),
+    }
+}
+
+// Last `n` lines of the content, all if n is 0
+fn last_lines(content: &[u8], n: usize) -> &[u8] {
+    if n == 0 {
+        return content;
+    }
+    // the trailing new line does not start a line
+    let end = content
+        .strip_suffix(b"\n")
+        .map(|c| c.len())
+        .unwrap_or(content.len());
+    match content[..end]
+        .iter()
+        .rev()
+        .enumerate()
+        .filter(|(_, c)| **c == b'\n')
+        .nth(n - 1)
+    {
+        Some((pos, _)) => &content[end - pos..],
+        None => content,
+    }
+}
+
+fn read_trimmed<P: AsRef<Path>>(path: P) -> String {
+    match fs::read_to_string(path.as_ref()) {
+        Ok(s) => s.trim().to_owned(),
+        Err(e) => {
+            debug!("read {} failed: {}", path.as_ref().display(), e);
+            "unknown".to_owned()
+        }
+    }
+}
+
+fn effective_capabilities() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|l| l.strip_prefix("CapEff:"))
+        .and_then(|v| u64::from_str_radix(v.trim(), 16).ok())
+}
+
+fn write_ebpf_capabilities<W: Write>(mut w: W, config: &ModuleConfig) -> io::Result<()> {
+    const CAPABILITIES: [(&str, u32); 6] = [
+        ("CAP_NET_ADMIN", 12),
+        ("CAP_NET_RAW", 13),
+        ("CAP_SYS_PTRACE", 19),
+        ("CAP_SYS_ADMIN", 21),
+        ("CAP_PERFMON", 38),
+        ("CAP_BPF", 39),
+    ];
+    let yes_or_no = |b: bool| if b { "yes" } else { "no" };
+
+    writeln!(w, "{:<32} {}", "kernel release", uname().release())?;
+    writeln!(w, "{:<32} {}", "machine", uname().machine())?;
+    writeln!(
+        w,
+        "{:<32} {}",
+        "ebpf disabled by config", config.ebpf.ebpf.disabled
+    )?;
+    writeln!(
+        w,
+        "{:<32} {}",
+        "btf vmlinux",
+        yes_or_no(Path::new("/sys/kernel/btf/vmlinux").exists())
+    )?;
+    let tracefs = ["/sys/kernel/tracing", "/sys/kernel/debug/tracing"]
+        .into_iter()
+        .find(|p| Path::new(p).join("kprobe_events").exists());
+    writeln!(w, "{:<32} {}", "tracefs", tracefs.unwrap_or("not mounted"))?;
+    for events in ["kprobe_events", "uprobe_events"] {
+        writeln!(
+            w,
+            "{:<32} {}",
+            events,
+            yes_or_no(tracefs.map(|p| Path::new(p).join(events).exists()) == Some(true))
+        )?;
+    }
+    for (name, path) in [
+        ("bpf_jit_enable", "/proc/sys/net/core/bpf_jit_enable"),
+        (
+            "unprivileged_bpf_disabled",
+            "/proc/sys/kernel/unprivileged_bpf_disabled",
+        ),
+        (
+            "perf_event_paranoid",
+            "/proc/sys/kernel/perf_event_paranoid",
+        ),
+        ("kptr_restrict", "/proc/sys/kernel/kptr_restrict"),
+    ] {
+        writeln!(w, "{:<32} {}", name, read_trimmed(path))?;
+    }
+    match effective_capabilities() {
+        Some(caps) => {
+            for (name, bit) in CAPABILITIES {
+                writeln!(w, "{:<32} {}", name, yes_or_no(caps & (1 << bit) != 0))?;
+            }
+        }
+        None => writeln!(w, "{:<32} unknown", "capabilities")?,
+    }
+    Ok(())
+}
+
+fn component_states(exceptions: u64) -> Vec<pb::ComponentState> {
+    COMPONENT_EXCEPTIONS
+        .iter()
+        .map(|(name, related)| {
+            let raised: Vec<String> = related
+                .iter()
+                .filter(|e| exceptions & **e as u64 != 0)
+                .map(|e| e.as_str_name().to_owned())
+                .collect();
+            pb::ComponentState {
+                name: Some(name.to_string()),
+                healthy: Some(raised.is_empty()),
+                exceptions: raised,
+                detail: None,
+            }
+        })
+        .collect()
+}
+
+fn write_interfaces<W: Write>(mut w: W) -> net::Result<()> {
+    let addrs = addr_list()?;
+    for link in link_list()? {
+        writeln!(
+            w,
+            "{}: {} mac {} flags {:?} type {} peer {:?} netnsid {:?}",
+            link.if_index,
+            link.name,
+            link.mac_addr,
+            link.flags,
+            link.if_type.as_deref().unwrap_or("unknown"),
+            link.peer_index,
+            link.link_netnsid
+        )?;
+        for addr in addrs.iter().filter(|a| a.if_index == link.if_index) {
+            writeln!(
+                w,
+                "    {}/{} scope {}",
+                addr.ip_addr, addr.prefix_len, addr.scope
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tar_format() {
+        let mut tar = TarBuilder::new(vec![]);
+        tar.append("a/b.txt", b"hello").unwrap();
+        let data = tar.finish().unwrap();
+        assert_eq!(data.len(), TAR_BLOCK_SIZE * 4);
+        assert_eq!(&data[..7], b"a/b.txt");
+        assert_eq!(&data[124..136], b"00000000005\0");
+        assert_eq!(&data[257..262], b"ustar");
+        let checksum = std::str::from_utf8(&data[148..154]).unwrap();
+        let mut header = data[..TAR_BLOCK_SIZE].to_vec();
+        header[148..156].fill(b' ');
+        assert_eq!(
+            u32::from_str_radix(checksum, 8).unwrap(),
+            header.iter().map(|b| *b as u32).sum::<u32>()
+        );
+        assert_eq!(&data[TAR_BLOCK_SIZE..TAR_BLOCK_SIZE + 5], b"hello");
+        assert!(data[TAR_BLOCK_SIZE + 5..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn log_lines() {
+        let log = b"a\nb\nc\n";
+        assert_eq!(last_lines(log, 0), log);
+        assert_eq!(last_lines(log, 1), b"c\n");
+        assert_eq!(last_lines(log, 2), b"b\nc\n");
+        assert_eq!(last_lines(log, 3), log);
+        assert_eq!(last_lines(log, 10), log);
+        assert_eq!(last_lines(b"a\nb", 1), b"b");
+    }
+
+    #[test]
+    fn component_health() {
+        let states = component_states(
+            Exception::ControllerSocketError as u64
+                | Exception::NpbFuse as u64
+                | Exception::NpbEndpointDown as u64,
+        );
+        assert_eq!(states.len(), COMPONENT_EXCEPTIONS.len());
+        let npb = states.iter().find(|s| s.name() == "npb").unwrap();
+        assert!(!npb.healthy());
+        assert_eq!(npb.exceptions, ["NPB_FUSE", "NPB_ENDPOINT_DOWN"]);
+        let unhealthy: Vec<_> = states
+            .iter()
+            .filter(|s| !s.healthy())
+            .map(|s| s.name())
+            .collect();
+        assert_eq!(unhealthy, ["controller", "npb"]);
+    }
+}
@@ -0,0 +1,301 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use chrono::{SecondsFormat, Utc};
+use log::{info, warn};
+use parking_lot::Mutex;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Succeeded,
+    Failed,
+    TimedOut,
+    Rejected,
+    // cancelled, replaced by another command or the stream is closed
+    #[default]
+    Aborted,
+}
+
+impl Outcome {
+    pub const ALL: [Self; 5] = [
+        Self::Succeeded,
+        Self::Failed,
+        Self::TimedOut,
+        Self::Rejected,
+        Self::Aborted,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+            Self::TimedOut => "timed-out",
+            Self::Rejected => "rejected",
+            Self::Aborted => "aborted",
+        }
+    }
+}
+
+// Written as one line of json for each command
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct AuditRecord {
+    pub request_id: Option<u64>,
+    pub batch_index: Option<u32>,
+    pub command_id: Option<u32>,
+    // with params replaced
+    pub cmdline: String,
+    pub params: BTreeMap<String, String>,
+    pub ns_pid: Option<u32>,
+    pub ns_types: Vec<&'static str>,
+    pub start_time: String,
+    pub end_time: String,
+    pub outcome: Outcome,
+    pub exit_code: Option<i32>,
+    // stdout bytes returned
+    pub bytes: u64,
+    pub error: Option<String>,
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+// Append-only log of remote commands, disabled if path is empty
+#[derive(Default)]
+pub struct AuditLog {
+    file: Mutex<Option<(String, File)>>,
+    // recent records, oldest first
+    history: Mutex<VecDeque<AuditRecord>>,
+    history_len: AtomicUsize,
+    // commands ended with each outcome since last taken, indexed by outcome
+    outcomes: [AtomicU64; Outcome::ALL.len()],
+}
+
+impl AuditLog {
+    // Reopens the file if path changed
+    pub fn set_path(&self, path: &str) {
+        let mut file = self.file.lock();
+        if file.as_ref().map(|(p, _)| p.as_str()).unwrap_or_default() == path {
+            return;
+        }
+        *file = None;
+        if path.is_empty() {
+            info!("remote exec audit log disabled");
+            return;
+        }
+        if let Some(parent) = Path::new(path).parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        match options.open(path) {
+            Ok(f) => {
+                info!("remote exec audit log set to {}", path);
+                *file = Some((path.to_owned(), f));
+            }
+            Err(e) => warn!("open remote exec audit log {} failed: {}", path, e),
+        }
+    }
+
+    // Drops oldest records beyond len, 0 to disable history
+    pub fn set_history_len(&self, len: usize) {
+        if self.history_len.swap(len, Ordering::Relaxed) == len {
+            return;
+        }
+        let mut history = self.history.lock();
+        while history.len() > len {
+            history.pop_front();
+        }
+    }
+
+    pub fn history(&self) -> Vec<AuditRecord> {
+        self.history.lock().iter().cloned().collect()
+    }
+
+    // Counts of commands by outcome since last call
+    pub fn take_outcomes(&self) -> [(Outcome, u64); Outcome::ALL.len()] {
+        Outcome::ALL.map(|o| (o, self.outcomes[o as usize].swap(0, Ordering::Relaxed)))
+    }
+
+    fn record(&self, record: &AuditRecord) {
+        self.outcomes[record.outcome as usize].fetch_add(1, Ordering::Relaxed);
+        let len = self.history_len.load(Ordering::Relaxed);
+        if len > 0 {
+            let mut history = self.history.lock();
+            while history.len() >= len {
+                history.pop_front();
+            }
+            history.push_back(record.clone());
+        }
+        self.write(record);
+    }
+
+    fn write(&self, record: &AuditRecord) {
+        let mut file = self.file.lock();
+        let Some((path, f)) = file.as_mut() else {
+            return;
+        };
+        let mut line = match serde_json::to_vec(record) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("serialize remote exec audit record failed: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+        // a single write keeps lines from interleaving
+        if let Err(e) = f.write_all(&line) {
+            warn!("write remote exec audit log {} failed: {}", path, e);
+        }
+    }
+}
+
+// Writes the record with end time on drop
+pub struct AuditGuard {
+    log: Arc<AuditLog>,
+    pub record: AuditRecord,
+}
+
+impl AuditGuard {
+    pub fn new(log: Arc<AuditLog>, mut record: AuditRecord) -> Self {
+        record.start_time = now();
+        Self { log, record }
+    }
+
+    pub fn finish(&mut self, outcome: Outcome, exit_code: Option<i32>, bytes: u64) {
+        self.record.outcome = outcome;
+        self.record.exit_code = exit_code;
+        self.record.bytes = bytes;
+    }
+
+    pub fn fail<S: Into<String>>(&mut self, outcome: Outcome, error: S) {
+        self.record.outcome = outcome;
+        self.record.error = Some(error.into());
+    }
+}
+
+impl Drop for AuditGuard {
+    fn drop(&mut self) {
+        self.record.end_time = now();
+        self.log.record(&self.record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audit_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let log = Arc::new(AuditLog::default());
+        log.set_path(path.to_str().unwrap());
+
+        let mut guard = AuditGuard::new(
+            log.clone(),
+            AuditRecord {
+                request_id: Some(1),
+                command_id: Some(12),
+                cmdline: "ping -n -c 4 -W 2 10.0.0.1".to_owned(),
+                params: [("host".to_owned(), "10.0.0.1".to_owned())].into(),
+                ns_pid: Some(100),
+                ns_types: vec!["net"],
+                ..Default::default()
+            },
+        );
+        guard.finish(Outcome::Succeeded, Some(0), 256);
+        drop(guard);
+        let mut guard = AuditGuard::new(log.clone(), Default::default());
+        guard.fail(Outcome::Rejected, "command_id not specified");
+        drop(guard);
+        drop(AuditGuard::new(log.clone(), Default::default()));
+
+        // disabled
+        log.set_path("");
+        drop(AuditGuard::new(log.clone(), Default::default()));
+
+        let lines = fs::read_to_string(&path).unwrap();
+        let records = lines
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0]["cmdline"], "ping -n -c 4 -W 2 10.0.0.1");
+        assert_eq!(records[0]["params"]["host"], "10.0.0.1");
+        assert_eq!(records[0]["outcome"], "succeeded");
+        assert_eq!(records[0]["bytes"], 256);
+        assert_eq!(records[1]["outcome"], "rejected");
+        assert_eq!(records[1]["error"], "command_id not specified");
+        assert_eq!(records[2]["outcome"], "aborted");
+        assert!(
+            records[2]["end_time"].as_str().unwrap() >= records[2]["start_time"].as_str().unwrap()
+        );
+    }
+
+    #[test]
+    fn history() {
+        let log = Arc::new(AuditLog::default());
+        drop(AuditGuard::new(log.clone(), Default::default()));
+        assert!(log.history().is_empty());
+
+        log.set_history_len(2);
+        for id in 1..=3 {
+            let mut guard = AuditGuard::new(
+                log.clone(),
+                AuditRecord {
+                    request_id: Some(id),
+                    ..Default::default()
+                },
+            );
+            guard.finish(Outcome::Succeeded, Some(0), 0);
+        }
+        let history = log.history();
+        assert_eq!(
+            history.iter().map(|r| r.request_id).collect::<Vec<_>>(),
+            vec![Some(2), Some(3)]
+        );
+        assert_eq!(history[1].outcome.as_str(), "succeeded");
+
+        log.set_history_len(1);
+        assert_eq!(log.history()[0].request_id, Some(3));
+
+        let outcomes = log.take_outcomes();
+        assert_eq!(
+            outcomes[Outcome::Succeeded as usize],
+            (Outcome::Succeeded, 3)
+        );
+        assert_eq!(outcomes[Outcome::Aborted as usize], (Outcome::Aborted, 1));
+        assert!(log.take_outcomes().iter().all(|(_, n)| *n == 0));
+    }
+}
@@ -0,0 +1,313 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    net::{Ipv4Addr, SocketAddr},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use hyper::{
+    header::AUTHORIZATION,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use log::{debug, error, info, warn};
+use parking_lot::Mutex;
+use rand::RngCore;
+use ring::constant_time::verify_slices_are_equal;
+use tokio::{
+    runtime::Runtime,
+    sync::{oneshot, Notify},
+    task::JoinHandle,
+};
+
+use super::Diagnostics;
+
+type GenericError = Box<dyn std::error::Error + Send + Sync>;
+
+// Local debug API for node operators, only listening on loopback address.
+// Requests must carry `Authorization: Bearer <token>` with the token generated on start
+// and written to a file readable by root only, so that other local users are refused.
+// Supported requests:
+// - POST /v1/sync: trigger a sync with controller immediately
+// - GET /v1/config: dump config in effect
+// - GET /v1/diagnostics: download the same diagnostics bundle as remote exec
+// - GET /metrics: agent counters and histograms for prometheus scraping
+pub struct LocalApi {
+    port: u16,
+    token_file: PathBuf,
+    runtime: Arc<Runtime>,
+    sync_trigger: Arc<Notify>,
+    diagnostics: Arc<Diagnostics>,
+
+    running: AtomicBool,
+    shutdown_tx: Mutex<Option<oneshot::Sender<()>>>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl LocalApi {
+    pub fn new(
+        port: u16,
+        token_file: PathBuf,
+        runtime: Arc<Runtime>,
+        sync_trigger: Arc<Notify>,
+        diagnostics: Arc<Diagnostics>,
+    ) -> Self {
+        Self {
+            port,
+            token_file,
+            runtime,
+            sync_trigger,
+            diagnostics,
+            running: AtomicBool::new(false),
+            shutdown_tx: Default::default(),
+            thread: Default::default(),
+        }
+    }
+
+    pub fn start(&self) {
+        if self.port == 0 {
+            debug!("local api disabled");
+            return;
+        }
+        if self.running.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let token = match write_token(&self.token_file) {
+            Ok(token) => Arc::new(token),
+            Err(e) => {
+                error!(
+                    "local api write token file {} failed: {}",
+                    self.token_file.display(),
+                    e
+                );
+                self.running.store(false, Ordering::Relaxed);
+                return;
+            }
+        };
+
+        let addr: SocketAddr = (Ipv4Addr::LOCALHOST, self.port).into();
+        // binding requires a runtime context to register the listener
+        let server = match {
+            let _guard = self.runtime.enter();
+            Server::try_bind(&addr)
+        } {
+            Ok(s) => s,
+            Err(e) => {
+                error!("local api bind {} failed: {}", addr, e);
+                self.running.store(false, Ordering::Relaxed);
+                return;
+            }
+        };
+        let (tx, rx) = oneshot::channel();
+        self.shutdown_tx.lock().replace(tx);
+
+        let sync_trigger = self.sync_trigger.clone();
+        let diagnostics = self.diagnostics.clone();
+        let service = make_service_fn(move |_| {
+            let token = token.clone();
+            let sync_trigger = sync_trigger.clone();
+            let diagnostics = diagnostics.clone();
+            async move {
+                Ok::<_, GenericError>(service_fn(move |req| {
+                    handler(
+                        req,
+                        token.clone(),
+                        sync_trigger.clone(),
+                        diagnostics.clone(),
+                    )
+                }))
+            }
+        });
+        self.thread.lock().replace(self.runtime.spawn(async move {
+            info!("local api listening on http://{}", addr);
+            let server = server.serve(service).with_graceful_shutdown(async {
+                let _ = rx.await;
+            });
+            if let Err(e) = server.await {
+                error!("local api error: {}", e);
+            }
+        }));
+    }
+
+    pub fn stop(&self) {
+        if !self.running.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        if let Some(tx) = self.shutdown_tx.lock().take() {
+            let _ = tx.send(());
+        }
+        if let Some(t) = self.thread.lock().take() {
+            let _ = self.runtime.block_on(t);
+        }
+        info!("local api stopped");
+    }
+}
+
+const TOKEN_LEN: usize = 32;
+
+// Writes a new random token to the file, replacing the old one so that its mode is not kept
+fn write_token(path: &Path) -> io::Result<String> {
+    let mut bytes = [0u8; TOKEN_LEN];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => (),
+    }
+    let mut fp = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    fp.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
+fn authorized(req: &Request<Body>, token: &str) -> bool {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|t| verify_slices_are_equal(t.trim().as_bytes(), token.as_bytes()).is_ok())
+        .unwrap_or_default()
+}
+
+fn text_response(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(body.into())
+        .unwrap()
+}
+
+async fn handler(
+    req: Request<Body>,
+    token: Arc<String>,
+    sync_trigger: Arc<Notify>,
+    diagnostics: Arc<Diagnostics>,
+) -> Result<Response<Body>, GenericError> {
+    debug!("local api request {} {}", req.method(), req.uri().path());
+    if !authorized(&req, &token) {
+        warn!(
+            "local api request {} {} unauthorized",
+            req.method(),
+            req.uri().path()
+        );
+        return Ok(Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header("WWW-Authenticate", "Bearer")
+            .body("unauthorized\n".into())
+            .unwrap());
+    }
+    match (req.method(), req.uri().path()) {
+        (&Method::POST, "/v1/sync") => {
+            info!("sync triggered by local api");
+            sync_trigger.notify_one();
+            Ok(text_response(
+                StatusCode::ACCEPTED,
+                "sync triggered\n".to_owned(),
+            ))
+        }
+        (&Method::GET, "/v1/config") => Ok(text_response(StatusCode::OK, diagnostics.config())),
+        (&Method::GET, "/v1/diagnostics") => {
+            match tokio::task::spawn_blocking(move || diagnostics.bundle(0)).await? {
+                Ok(bundle) => Ok(Response::builder()
+                    .header("Content-Type", "application/gzip")
+                    .header(
+                        "Content-Disposition",
+                        "attachment; filename=\"deepflow-agent-diagnostics.tar.gz\"",
+                    )
+                    .body(bundle.into())
+                    .unwrap()),
+                Err(e) => {
+                    warn!("local api assemble diagnostics bundle failed: {}", e);
+                    Ok(text_response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("assemble diagnostics bundle failed: {}\n", e),
+                    ))
+                }
+            }
+        }
+        (&Method::GET, "/metrics") => match diagnostics.metrics() {
+            Ok(metrics) => Ok(Response::builder()
+                .header("Content-Type", "text/plain; version=0.0.4; charset=utf-8")
+                .body(metrics.into())
+                .unwrap()),
+            Err(e) => Ok(text_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("collect metrics failed: {}\n", e),
+            )),
+        },
+        (_, "/v1/sync") | (_, "/v1/config") | (_, "/v1/diagnostics") | (_, "/metrics") => {
+            Ok(text_response(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "method not allowed\n".to_owned(),
+            ))
+        }
+        _ => Ok(text_response(
+            StatusCode::NOT_FOUND,
+            "not found\n".to_owned(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token() {
+        let path = std::env::temp_dir().join(format!("local-api-{}.token", std::process::id()));
+        let token = write_token(&path).unwrap();
+        assert_eq!(token.len(), TOKEN_LEN * 2);
+        assert_eq!(fs::read_to_string(&path).unwrap(), token);
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // replaced on restart
+        assert_ne!(write_token(&path).unwrap(), token);
+        fs::remove_file(&path).unwrap();
+
+        let request = |auth: Option<&str>| {
+            let mut builder = Request::builder().uri("/v1/config");
+            if let Some(auth) = auth {
+                builder = builder.header(AUTHORIZATION, auth);
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+        assert!(authorized(&request(Some("Bearer abc")), "abc"));
+        assert!(!authorized(&request(Some("Bearer abd")), "abc"));
+        assert!(!authorized(&request(Some("Basic abc")), "abc"));
+        assert!(!authorized(&request(None), "abc"));
+    }
+}
@@ -14,17 +14,27 @@
  * limitations under the License.
  */
 
+mod cert_pin;
+mod exec_audit;
 mod ntp;
+pub mod remote_exec;
+mod result_cipher;
 mod session;
 mod synchronizer;
+mod upgrade;
 
+pub use cert_pin::CertPins;
+pub use remote_exec::Executor;
 pub use session::{Session, DEFAULT_TIMEOUT};
 pub(crate) use synchronizer::{StaticConfig, Status, Synchronizer};
 
 cfg_if::cfg_if! {
     if #[cfg(any(target_os = "linux", target_os = "android"))] {
-        pub mod remote_exec;
-        pub use remote_exec::Executor;
+        mod cri;
+        mod diagnostics;
+        mod local_api;
+        pub use diagnostics::Diagnostics;
+        pub use local_api::LocalApi;
     }
 }
 
@@ -0,0 +1,2725 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    ffi::CString,
+    fmt::{self, Write as _},
+    fs::{File, OpenOptions},
+    io::Write,
+    iter,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{MetadataExt, OpenOptionsExt},
+        io::AsRawFd,
+        process::ExitStatusExt,
+    },
+    path::{Path, PathBuf},
+    process::{self, ExitStatus, Output},
+    ptr,
+    sync::Arc,
+    time::Duration,
+};
+
+use bollard::{container::LogsOptions, Docker, API_DEFAULT_VERSION};
+use futures::{
+    future::{self, BoxFuture, Future},
+    stream::StreamExt,
+    TryFutureExt,
+};
+use k8s_openapi::{
+    api::{
+        apps::v1::Deployment,
+        authorization::v1::{
+            ResourceAttributes, SelfSubjectAccessReview, SelfSubjectAccessReviewSpec,
+        },
+        core::v1::{Endpoints, Event, Node, Pod, Service},
+    },
+    apimachinery::pkg::apis::meta::v1::{ObjectMeta, Status},
+};
+use kube::{
+    api::{AttachParams, ListParams, LogParams, PostParams},
+    config::{KubeConfigOptions, Kubeconfig},
+    Api, Client, Config,
+};
+use log::{debug, info, warn};
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, ser::SerializeMap};
+use tokio::process::Command as TokioCommand;
+
+use super::*;
+use crate::{
+    config::{RemoteExecKubeConfig, RemoteExecLimits},
+    platform::kubernetes::infer_config,
+    utils::self_profiler::{self, ProfileFormat, ProfileType},
+};
+
+use public::netns::{reset_netns, set_netns};
+
+// $type and $pid can be empty
+const LSNS_CMDLINE: &str = "lsns -t $type -p $pid";
+// $log_lines can be empty for the log file tail of 4 MiB
+const DIAGNOSE_CMDLINE: &str = "deepflow-agent diagnose --log-lines $log_lines";
+const SELF_PROFILE_CPU_CMDLINE: &str = "deepflow-agent profile cpu $seconds $format";
+const SELF_PROFILE_HEAP_CMDLINE: &str = "deepflow-agent profile heap $seconds $format";
+// files in pcap local storage directory, fetched with their paths
+const PCAP_LIST_CMDLINE: &str = "deepflow-agent pcap list";
+const MAX_LOG_LINES: u64 = 100000;
+// state filters of ss
+const SS_STATES: &[&str] = &[
+    "all",
+    "connected",
+    "synchronized",
+    "bucket",
+    "big",
+    "established",
+    "syn-sent",
+    "syn-recv",
+    "fin-wait-1",
+    "fin-wait-2",
+    "time-wait",
+    "closed",
+    "close-wait",
+    "last-ack",
+    "listening",
+    "closing",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum KubeResource {
+    Deployment,
+    Service,
+    Endpoints,
+    Node,
+}
+
+impl KubeResource {
+    fn namespaced(&self) -> bool {
+        *self != Self::Node
+    }
+
+    fn plural(&self) -> &'static str {
+        match self {
+            Self::Deployment => "deployments",
+            Self::Service => "services",
+            Self::Endpoints => "endpoints",
+            Self::Node => "nodes",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum KubeCmd {
+    DescribePod,
+    DescribeDeployment,
+    DescribeService,
+    DescribeNode,
+    Log,
+    LogPrevious,
+    Get(KubeResource),
+    // run a command in the container
+    Exec,
+}
+
+impl KubeCmd {
+    // group, verb, resource and subresource of the main request
+    fn access(
+        &self,
+    ) -> (
+        &'static str,
+        &'static str,
+        &'static str,
+        Option<&'static str>,
+    ) {
+        match self {
+            Self::DescribePod => ("", "get", "pods", None),
+            Self::DescribeDeployment => ("apps", "get", "deployments", None),
+            Self::DescribeService => ("", "get", "services", None),
+            Self::DescribeNode => ("", "get", "nodes", None),
+            Self::Log | Self::LogPrevious => ("", "get", "pods", Some("log")),
+            Self::Get(KubeResource::Deployment) => ("apps", "list", "deployments", None),
+            Self::Get(r) => ("", "list", r.plural(), None),
+            Self::Exec => ("", "create", "pods", Some("exec")),
+        }
+    }
+
+    fn namespaced(&self) -> bool {
+        match self {
+            Self::DescribeNode => false,
+            Self::Get(r) => r.namespaced(),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ContainerCmd {
+    // read the log file found by CRI runtime service
+    CriLog,
+    DockerLog,
+}
+
+pub(super) fn all_supported_commands() -> Vec<Command> {
+    vec![
+        Command {
+            cmdline: LSNS_CMDLINE.into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Linux,
+            cacheable: true,
+            full_ns: false,
+            // all namespaces of all processes are listed if empty
+            param_rules: vec![
+                (
+                    "type".into(),
+                    ParamRule::Optional(Box::new(ParamRule::Identifier)),
+                ),
+                (
+                    "pid".into(),
+                    ParamRule::Optional(Box::new(ParamRule::Integer(1, u32::MAX as u64))),
+                ),
+            ],
+        },
+        Command {
+            cmdline: "top -b -n 1 -c -w 512".into(),
+            output_format: OutputFormat::Text,
+            desc: "top".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: true,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "ps auxf".into(),
+            output_format: OutputFormat::Text,
+            desc: "ps".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: true,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "ip address".into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Linux,
+            cacheable: true,
+            full_ns: true,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "kubectl -n $ns describe pod $pod".into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Kubernetes(KubeCmd::DescribePod),
+            cacheable: false,
+            full_ns: false,
+            // pod names are dns subdomains
+            param_rules: vec![("pod".into(), ParamRule::Host)],
+        },
+        // $container, $since_seconds and $timestamps can be empty
+        Command {
+            cmdline: concat!(
+                "kubectl -n $ns logs --tail=10000 $pod -c $container",
+                " --since $since_seconds --timestamps $timestamps"
+            )
+            .into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Kubernetes(KubeCmd::Log),
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![
+                ("pod".into(), ParamRule::Host),
+                (
+                    "container".into(),
+                    ParamRule::Optional(Box::new(ParamRule::Identifier)),
+                ),
+            ],
+        },
+        Command {
+            cmdline: concat!(
+                "kubectl -n $ns logs --tail=10000 -p $pod -c $container",
+                " --since $since_seconds --timestamps $timestamps"
+            )
+            .into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Kubernetes(KubeCmd::LogPrevious),
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![
+                ("pod".into(), ParamRule::Host),
+                (
+                    "container".into(),
+                    ParamRule::Optional(Box::new(ParamRule::Identifier)),
+                ),
+            ],
+        },
+        Command {
+            cmdline: DIAGNOSE_CMDLINE.into(),
+            output_format: OutputFormat::Binary,
+            desc: "".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![(
+                "log_lines".into(),
+                ParamRule::Optional(Box::new(ParamRule::Integer(1, MAX_LOG_LINES))),
+            )],
+        },
+        Command {
+            cmdline: "ping -n -c 4 -W 2 $host".into(),
+            output_format: OutputFormat::Text,
+            desc: "ping".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "traceroute -n -q 1 -w 2 -m 30 $host".into(),
+            output_format: OutputFormat::Text,
+            desc: "traceroute".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "curl -sS -g -I -m 10 --max-redirs 0 $url".into(),
+            output_format: OutputFormat::Text,
+            desc: "curl".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "hostname".into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: true,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "df -h".into(),
+            output_format: OutputFormat::Text,
+            desc: "df".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: true,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "netstat -tunlp".into(),
+            output_format: OutputFormat::Text,
+            desc: "netstat".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: true,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: SELF_PROFILE_CPU_CMDLINE.into(),
+            output_format: OutputFormat::Binary,
+            desc: "".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: SELF_PROFILE_HEAP_CMDLINE.into(),
+            output_format: OutputFormat::Binary,
+            desc: "".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        // $cmd_id is the id of a command with full_ns and no params
+        Command {
+            cmdline: "kubectl -n $ns exec $pod -c $container -- $cmd_id".into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Kubernetes(KubeCmd::Exec),
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![("pod".into(), ParamRule::Host)],
+        },
+        // pcap is streamed to the controller as it is captured
+        Command {
+            cmdline: "tcpdump -i $ifname -c $count -w -".into(),
+            output_format: OutputFormat::Binary,
+            desc: "tcpdump".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "crictl logs --tail=10000 $container_id".into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Container(ContainerCmd::CriLog),
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "docker logs --tail=10000 $container_id".into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Container(ContainerCmd::DockerLog),
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "kubectl -n $ns describe deployment $deployment".into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Kubernetes(KubeCmd::DescribeDeployment),
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "kubectl -n $ns describe service $service".into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Kubernetes(KubeCmd::DescribeService),
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "kubectl describe node $node".into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Kubernetes(KubeCmd::DescribeNode),
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "ss -tnp state $state".into(),
+            output_format: OutputFormat::Text,
+            desc: "ss".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: true,
+            param_rules: vec![("state".into(), ParamRule::OneOf(SS_STATES))],
+        },
+        Command {
+            cmdline: "conntrack -L".into(),
+            output_format: OutputFormat::Text,
+            desc: "conntrack".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "ethtool -S $dev".into(),
+            output_format: OutputFormat::Text,
+            desc: "ethtool".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![("dev".into(), ParamRule::Ifname)],
+        },
+        Command {
+            cmdline: "ip neigh".into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: true,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "kubectl -n $ns get deployment -o wide".into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Kubernetes(KubeCmd::Get(KubeResource::Deployment)),
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "kubectl -n $ns get service -o wide".into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Kubernetes(KubeCmd::Get(KubeResource::Service)),
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "kubectl -n $ns get endpoints -o wide".into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Kubernetes(KubeCmd::Get(KubeResource::Endpoints)),
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: "kubectl get node -o wide".into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Kubernetes(KubeCmd::Get(KubeResource::Node)),
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+        Command {
+            cmdline: PCAP_LIST_CMDLINE.into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        },
+    ]
+}
+
+impl ExecNs {
+    fn open(pid: u32, ns_type: &str) -> std::result::Result<File, String> {
+        let path: PathBuf = ["/proc", &pid.to_string(), "ns", ns_type].iter().collect();
+        File::open(&path)
+            .map_err(|e| format!("open namespace file {} failed: {}", path.display(), e))
+    }
+
+    // Cgroup and user namespaces entered together with the others, so that commands are
+    // confined like processes of the container. The user namespace is the last one as
+    // capabilities in the parent user namespace are lost after entering it.
+    fn confinement(pid: u32) -> std::result::Result<Vec<(File, libc::c_int)>, String> {
+        let mut files = vec![(Self::open(pid, "cgroup")?, libc::CLONE_NEWCGROUP)];
+        let user = Self::open(pid, "user")?;
+        let own = Self::open(process::id(), "user")?;
+        let inode = |fp: &File| {
+            fp.metadata()
+                .map(|m| m.ino())
+                .map_err(|e| format!("stat user namespace failed: {}", e))
+        };
+        // entering the user namespace it is already in fails with EINVAL
+        if inode(&user)? != inode(&own)? {
+            files.push((user, libc::CLONE_NEWUSER));
+        }
+        Ok(files)
+    }
+}
+
+fn ns_flag(ns_type: &str) -> libc::c_int {
+    match ns_type {
+        "net" => libc::CLONE_NEWNET,
+        "uts" => libc::CLONE_NEWUTS,
+        "pid" => libc::CLONE_NEWPID,
+        "mnt" => libc::CLONE_NEWNS,
+        _ => 0,
+    }
+}
+
+// Namespace files opened before the command is spawned
+pub(super) struct NsFiles {
+    // network namespace entered by the agent thread spawning the command
+    net: Option<File>,
+    // namespaces entered in the forked child
+    full: Option<Vec<(File, libc::c_int)>>,
+}
+
+impl NsFiles {
+    pub(super) fn open(cmd: &Command, exec_ns: ExecNs) -> std::result::Result<Self, String> {
+        let net = match exec_ns {
+            ExecNs::Net(pid) => Some(ExecNs::open(pid, "net")?),
+            _ => None,
+        };
+        let full = match exec_ns {
+            ExecNs::Full(_) | ExecNs::Selected(..) if !cmd.full_ns => {
+                return Err(format!(
+                    "rejected run command '{}' in namespaces other than net",
+                    cmd.cmdline
+                ));
+            }
+            ExecNs::Full(pid) | ExecNs::Selected(pid, _) => {
+                let mut files = Vec::with_capacity(ExecNs::FULL_NS_TYPES.len() + 2);
+                for ns_type in exec_ns.ns_types() {
+                    files.push((ExecNs::open(pid, ns_type)?, ns_flag(ns_type)));
+                }
+                files.extend(ExecNs::confinement(pid)?);
+                Some(files)
+            }
+            _ => None,
+        };
+        Ok(Self { net, full })
+    }
+
+    // Network namespace in cache keys, results in a full namespace set depend on more
+    // than the network namespace and are not cached
+    pub(super) fn cache_netns(&self) -> Option<u64> {
+        if self.full.is_some() {
+            return None;
+        }
+        match self.net.as_ref() {
+            Some(fp) => fp.metadata().ok().map(|m| m.ino()),
+            None => Some(0),
+        }
+    }
+}
+
+// O_NONBLOCK keeps opening fifos from blocking, symbolic links are not followed
+pub(super) fn open_fetch_file(path: &str) -> std::io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)
+}
+
+impl Responser {
+    // Spawns the command in namespaces of ns_files, builtin, kubernetes and container
+    // commands are run by the agent itself
+    pub(super) fn spawn_command(
+        &self,
+        cmd: &Command,
+        params: &Params,
+        ns_files: NsFiles,
+        limits: &RemoteExecLimits,
+        progress: &Arc<Progress>,
+        pending: impl FnOnce(BoxFuture<'static, Result<Output>>) -> PendingCommand,
+    ) -> std::result::Result<PendingCommand, String> {
+        let cmdline: &str = &cmd.cmdline;
+        if cmdline == LSNS_CMDLINE {
+            let optional = |name: &str| params.get(name).ok().filter(|v| !v.is_empty());
+            let ns_type = match optional("type") {
+                Some(t) => match NsType::from(t.as_str()) {
+                    NsType::Unknown => {
+                        return Err(format!(
+                            "rejected run command '{}' with unknown namespace type {}",
+                            cmdline, t
+                        ))
+                    }
+                    ns_type => Some(ns_type),
+                },
+                None => None,
+            };
+            let pid = match optional("pid") {
+                Some(p) => Some(p.parse::<u32>().map_err(|_| {
+                    format!("rejected run command '{}' with invalid pid {}", cmdline, p)
+                })?),
+                None => None,
+            };
+            return Ok(pending(Box::pin(lsns_command(ns_type, pid))));
+        }
+        if cmdline == DIAGNOSE_CMDLINE {
+            let log_lines = match params.get("log_lines").ok().filter(|v| !v.is_empty()) {
+                Some(n) => n.parse::<usize>().map_err(|_| {
+                    format!(
+                        "rejected run command '{}' with invalid log_lines {}",
+                        cmdline, n
+                    )
+                })?,
+                None => 0,
+            };
+            return Ok(pending(Box::pin(
+                self.diagnostics.clone().bundle_command(log_lines),
+            )));
+        }
+        if cmdline == PCAP_LIST_CMDLINE {
+            let dir = self
+                .current_config
+                .load()
+                .yaml_config
+                .pcap
+                .local_storage_path
+                .clone();
+            return Ok(pending(Box::pin(pcap_list_command(dir))));
+        }
+        let self_profile = match cmdline {
+            SELF_PROFILE_CPU_CMDLINE => Some(ProfileType::Cpu),
+            SELF_PROFILE_HEAP_CMDLINE => Some(ProfileType::Heap),
+            _ => None,
+        };
+        if let Some(profile_type) = self_profile {
+            return self_profile_execute(profile_type, params)
+                .map(pending)
+                .map_err(|e| e.to_string());
+        }
+
+        match cmd.command_type {
+            CommandType::Kubernetes(kcmd) => {
+                return kubectl_execute(kcmd, params, self.kube_client.clone(), progress.clone())
+                    .map(|(future, stdout)| match stdout {
+                        Some(stdout) => pending(future).with_stdout(stdout),
+                        None => pending(future),
+                    })
+                    .map_err(|e| e.to_string());
+            }
+            CommandType::Container(ccmd) => {
+                let config = self.current_config.load();
+                return container_execute(
+                    ccmd,
+                    params,
+                    config.yaml_config.cri_socket_path.clone(),
+                    config.yaml_config.docker_socket_path.clone(),
+                    progress.clone(),
+                )
+                .map(pending)
+                .map_err(|e| e.to_string());
+            }
+            _ => (),
+        }
+
+        let (program, cmd_args) = params.substitute(cmdline)?;
+        // PATH of the container is never searched
+        let host_program = match ns_files.full.as_ref() {
+            Some(_) => {
+                let Some(path) = find_program(program) else {
+                    return Err(format!(
+                        "rejected run command '{}': program {} not found on host",
+                        cmdline, program
+                    ));
+                };
+                Some(HostProgram::new(&path, &cmd_args)?)
+            }
+            None => None,
+        };
+        let mut cmd = TokioCommand::new(program);
+        cmd.args(&cmd_args);
+        let (cpu_time, memory) = (limits.cpu_time.as_secs(), limits.memory << 20);
+        let nice = limits.nice as libc::c_int;
+        // SAFETY: set_limits only makes async-signal-safe calls
+        unsafe {
+            cmd.pre_exec(move || set_limits(cpu_time, memory, nice));
+        }
+        if let (Some(files), Some(program)) = (ns_files.full, host_program) {
+            // SAFETY: enter_namespaces, drop_capabilities and HostProgram::exec only make
+            // async-signal-safe calls
+            unsafe {
+                cmd.pre_exec(move || enter_namespaces(&files));
+                cmd.pre_exec(drop_capabilities);
+                cmd.pre_exec(move || program.exec());
+            }
+        }
+        if let Some(f) = ns_files.net.as_ref() {
+            if let Err(e) = set_netns(f) {
+                warn!("set_netns failed when executing {}: {}", cmdline, e);
+            }
+        }
+        // the child is spawned immediately in the namespace
+        let child = cmd
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped())
+            // killed when the pending command is cancelled or replaced
+            .kill_on_drop(true)
+            .spawn();
+        if ns_files.net.is_some() {
+            if let Err(e) = reset_netns() {
+                warn!("reset_netns failed when executing {}: {}", cmdline, e);
+            }
+        }
+        match child {
+            Ok(mut child) => {
+                let stdout = child.stdout.take().unwrap();
+                Ok(pending(Box::pin(wait_child(child))).with_stdout(stdout))
+            }
+            Err(e) => Err(format!("command '{}' execute failed: {}", cmdline, e)),
+        }
+    }
+
+    // Checks namespace files, binary and kubernetes access of the command
+    pub(super) fn check_exec(
+        &self,
+        cmd: &Command,
+        params: &Params,
+        exec_ns: ExecNs,
+        mut items: Vec<pb::CommandCheckItem>,
+    ) -> BoxFuture<'static, pb::CommandCheck> {
+        let ns_types = match exec_ns {
+            ExecNs::Net(pid) => vec![(pid, "net")],
+            ExecNs::Full(pid) | ExecNs::Selected(pid, _) => {
+                if !cmd.full_ns {
+                    items.push(check_item(
+                        "namespace",
+                        false,
+                        "command not allowed in namespaces other than net",
+                    ));
+                }
+                exec_ns.ns_types().map(|t| (pid, t)).collect()
+            }
+            ExecNs::Agent => vec![],
+        };
+        for (pid, ns_type) in ns_types {
+            let name = format!("namespace {}", ns_type);
+            items.push(match ExecNs::open(pid, ns_type) {
+                Ok(_) => check_item(&name, true, format!("/proc/{}/ns/{}", pid, ns_type)),
+                Err(e) => check_item(&name, false, e),
+            });
+        }
+
+        let builtin = [
+            LSNS_CMDLINE,
+            DIAGNOSE_CMDLINE,
+            SELF_PROFILE_CPU_CMDLINE,
+            SELF_PROFILE_HEAP_CMDLINE,
+            PCAP_LIST_CMDLINE,
+        ];
+        if cmd.command_type == CommandType::Linux && !builtin.contains(&cmd.cmdline.as_ref()) {
+            // binaries are looked up in the container if its mount namespace is entered
+            let root = match exec_ns {
+                ExecNs::Full(pid) | ExecNs::Selected(pid, _)
+                    if exec_ns.ns_types().any(|t| t == "mnt") =>
+                {
+                    PathBuf::from(format!("/proc/{}/root", pid))
+                }
+                _ => PathBuf::from("/"),
+            };
+            let name = cmd.cmdline.split_whitespace().next().unwrap_or_default();
+            items.push(match find_executable(name, &root) {
+                Some(path) => check_item("binary", true, path.to_string_lossy()),
+                None => check_item(
+                    "binary",
+                    false,
+                    format!("{} not found in {}", name, root.display()),
+                ),
+            });
+        }
+
+        let CommandType::Kubernetes(kcmd) = cmd.command_type else {
+            return Box::pin(future::ready(command_check(items)));
+        };
+        let (group, verb, resource, subresource) = kcmd.access();
+        let attributes = ResourceAttributes {
+            group: Some(group.to_owned()),
+            verb: Some(verb.to_owned()),
+            resource: Some(resource.to_owned()),
+            subresource: subresource.map(|s| s.to_owned()),
+            namespace: if kcmd.namespaced() {
+                params.get("ns").ok()
+            } else {
+                None
+            },
+            ..Default::default()
+        };
+        let client = self.kube_client.clone();
+        Box::pin(async move {
+            items.push(kubectl_access(client, attributes).await);
+            command_check(items)
+        })
+    }
+}
+
+// Runs in the forked child before exec, so only async-signal-safe calls are allowed
+fn enter_namespaces(files: &[(File, libc::c_int)]) -> std::io::Result<()> {
+    for (fp, ns_type) in files {
+        // SAFETY: fp is a valid namespace file
+        if unsafe { libc::setns(fp.as_raw_fd(), *ns_type) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if *ns_type == libc::CLONE_NEWUSER {
+            // like nsenter, become root of the user namespace, which fails if it is not
+            // mapped and the command is refused
+            // SAFETY: setgroups, setresgid and setresuid with integer arguments
+            unsafe {
+                // denied if setgroups is disabled in the namespace, groups are kept then
+                libc::setgroups(0, ptr::null());
+                if libc::setresgid(0, 0, 0) < 0 || libc::setresuid(0, 0, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+        }
+    }
+    // like nsenter, fork once more as the pid namespace only applies to children,
+    // the intermediate process waits and exits with the status of the command
+    // SAFETY: the child is single threaded
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error()),
+        0 => {
+            // SAFETY: prctl with integer arguments
+            unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) };
+            Ok(())
+        }
+        child => {
+            let mut status = 0;
+            // SAFETY: close_range, waitpid and _exit are async-signal-safe
+            unsafe {
+                // release the exec error pipe of std::process so that spawn() returns
+                // without waiting for the command, stdio is kept as fd 0-2
+                if libc::syscall(libc::SYS_close_range, 3, libc::c_uint::MAX, 0) < 0 {
+                    for fd in 3..1024 {
+                        libc::close(fd);
+                    }
+                }
+                while libc::waitpid(child, &mut status, 0) < 0 {
+                    if std::io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+                        libc::_exit(1);
+                    }
+                }
+                if libc::WIFEXITED(status) {
+                    libc::_exit(libc::WEXITSTATUS(status));
+                }
+                libc::_exit(128 + libc::WTERMSIG(status));
+            }
+        }
+    }
+}
+
+#[repr(C)]
+struct CapUserHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+// capability numbers are checked until PR_CAPBSET_DROP reports an invalid one
+const MAX_CAPABILITY: libc::c_int = 63;
+
+// Runs in the forked child before exec, so only async-signal-safe calls are allowed
+//
+// The command gets no capabilities after exec though it runs as root: the bounding set
+// limits permitted capabilities of root, and the inheritable and ambient sets are
+// cleared as they are added to it.
+fn drop_capabilities() -> std::io::Result<()> {
+    // SAFETY: prctl and capget/capset with valid pointers
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        for cap in 0..=MAX_CAPABILITY {
+            if libc::prctl(libc::PR_CAPBSET_DROP, cap, 0, 0, 0) < 0 {
+                let e = std::io::Error::last_os_error();
+                if e.raw_os_error() == Some(libc::EINVAL) {
+                    break;
+                }
+                return Err(e);
+            }
+        }
+        if libc::prctl(
+            libc::PR_CAP_AMBIENT,
+            libc::PR_CAP_AMBIENT_CLEAR_ALL,
+            0,
+            0,
+            0,
+        ) < 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut header = CapUserHeader {
+            version: LINUX_CAPABILITY_VERSION_3,
+            pid: 0,
+        };
+        let mut data = [CapUserData::default(); 2];
+        if libc::syscall(
+            libc::SYS_capget,
+            &mut header as *mut CapUserHeader,
+            data.as_mut_ptr(),
+        ) < 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+        for d in data.iter_mut() {
+            d.inheritable = 0;
+        }
+        if libc::syscall(
+            libc::SYS_capset,
+            &mut header as *mut CapUserHeader,
+            data.as_ptr(),
+        ) < 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+// Program of the host executed in namespaces of a container
+//
+// The program is opened before entering the mount namespace and executed by fd, so
+// that files in the container are never executed with privileges of the agent.
+struct HostProgram {
+    fp: File,
+    // own the strings pointed to by argv_ptrs and envp_ptrs
+    _argv: Vec<CString>,
+    _envp: Vec<CString>,
+    argv_ptrs: Vec<*const libc::c_char>,
+    envp_ptrs: Vec<*const libc::c_char>,
+}
+
+// SAFETY: pointers refer to heap buffers of the CStrings owned by the struct
+unsafe impl Send for HostProgram {}
+unsafe impl Sync for HostProgram {}
+
+impl HostProgram {
+    fn new(program: &Path, args: &[String]) -> std::result::Result<Self, String> {
+        let fp = File::open(program)
+            .map_err(|e| format!("open program {} failed: {}", program.display(), e))?;
+        let cstring = |s: &[u8]| CString::new(s).map_err(|e| e.to_string());
+        let mut argv = vec![cstring(program.as_os_str().as_bytes())?];
+        for arg in args {
+            argv.push(cstring(arg.as_bytes())?);
+        }
+        let mut envp = vec![];
+        for (k, v) in std::env::vars_os() {
+            envp.push(cstring(&[k.as_bytes(), b"=", v.as_bytes()].concat())?);
+        }
+        let pointers = |v: &[CString]| {
+            v.iter()
+                .map(|s| s.as_ptr())
+                .chain(iter::once(ptr::null()))
+                .collect::<Vec<_>>()
+        };
+        Ok(Self {
+            fp,
+            argv_ptrs: pointers(&argv),
+            envp_ptrs: pointers(&envp),
+            _argv: argv,
+            _envp: envp,
+        })
+    }
+
+    // Runs in the forked child and returns only on failure
+    fn exec(&self) -> std::io::Result<()> {
+        // SAFETY: fexecve is async-signal-safe, pointer arrays are null terminated
+        unsafe {
+            libc::fexecve(
+                self.fp.as_raw_fd(),
+                self.argv_ptrs.as_ptr(),
+                self.envp_ptrs.as_ptr(),
+            )
+        };
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+// Looks up the program in PATH of the agent
+fn find_program(name: &str) -> Option<PathBuf> {
+    if name.contains('/') {
+        return Some(PathBuf::from(name));
+    }
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(name))
+            .find(|path| {
+                path.metadata()
+                    .map(|m| m.is_file() && m.mode() & 0o111 != 0)
+                    .unwrap_or_default()
+            })
+    })
+}
+
+// Runs in the forked child before exec, so only async-signal-safe calls are allowed
+fn set_limits(cpu_time: u64, memory: u64, nice: libc::c_int) -> std::io::Result<()> {
+    for (resource, limit) in [(libc::RLIMIT_CPU, cpu_time), (libc::RLIMIT_DATA, memory)] {
+        if limit == 0 {
+            continue;
+        }
+        let mut rlim = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: getrlimit and setrlimit with a valid pointer
+        unsafe {
+            if libc::getrlimit(resource, &mut rlim) < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            // only lowered as raising the hard limit requires CAP_SYS_RESOURCE
+            rlim.rlim_max = rlim.rlim_max.min(limit);
+            rlim.rlim_cur = rlim.rlim_cur.min(limit);
+            if libc::setrlimit(resource, &rlim) < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+    }
+    if nice > 0 {
+        // SAFETY: nice with integer argument, -1 is a valid return so errors are ignored
+        unsafe { libc::nice(nice) };
+    }
+    Ok(())
+}
+
+const MIN_BUF_SIZE: usize = 1024;
+
+fn username_by_uid(uid: u32) -> Result<String> {
+    // SAFTY: sysconf() is unlikely to go wrong
+    let conf = unsafe { libc::sysconf(libc::_SC_GETPW_R_SIZE_MAX) };
+    let buf_size = if conf < 0 {
+        MIN_BUF_SIZE
+    } else {
+        conf as usize
+    };
+    let mut buffer: Vec<libc::c_char> = Vec::with_capacity(buf_size);
+    let mut passwd = libc::passwd {
+        pw_name: ptr::null_mut(),
+        pw_passwd: ptr::null_mut(),
+        pw_uid: 0,
+        pw_gid: 0,
+        pw_gecos: ptr::null_mut(),
+        pw_dir: ptr::null_mut(),
+        pw_shell: ptr::null_mut(),
+    };
+    let mut p_passwd: *mut libc::passwd = ptr::null_mut();
+    unsafe {
+        // SAFTY: `buffer` is pre-allocated with buf_size for syscall
+        //        and will not `Drop` before the end of this function.
+        //        The contents in the buffer is `Copy`.
+        let r = libc::getpwuid_r(
+            uid,
+            &mut passwd as *mut libc::passwd,
+            buffer.as_mut_ptr(),
+            buf_size,
+            &mut p_passwd as *mut *mut libc::passwd,
+        );
+        if r != 0 {
+            return Err(Error::SyscallFailed(format!("getpwuid_r failed with {r}")));
+        } else if p_passwd.is_null() {
+            return Err(Error::SyscallFailed(format!(
+                "username with uid {uid} not found"
+            )));
+        }
+        // SAFTY:
+        // - p_passwd.pw_name points to nul terminated string in a single allocated `Vec<c_char>` object.
+        // - The memory referenced will not be mutated.
+        Ok(std::ffi::CStr::from_ptr(p_passwd.read().pw_name)
+            .to_string_lossy()
+            .to_string())
+    }
+}
+
+async fn get_proc_cmdline<P: AsRef<Path>>(pid_path: P) -> std::io::Result<String> {
+    let mut pid_path = pid_path.as_ref().to_path_buf();
+    pid_path.push("cmdline");
+    let mut cmdline = match tokio::fs::read(&pid_path).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            pid_path.pop();
+            pid_path.push("comm");
+            match tokio::fs::read(&pid_path).await {
+                Ok(bytes) => bytes,
+                Err(_) => {
+                    pid_path.pop();
+                    return Err(e);
+                }
+            }
+        }
+    };
+
+    // remove trailling \0
+    while let Some(c) = cmdline.pop() {
+        if c != b'\0' {
+            cmdline.push(c);
+            break;
+        }
+    }
+    // replace all \0 with space
+    for c in cmdline.iter_mut() {
+        if *c == b'\0' {
+            *c = b' ';
+        }
+    }
+    Ok(String::from_utf8(cmdline).unwrap_or_default())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NsType {
+    Unknown,
+    Mnt,
+    Net,
+    Pid,
+    Uts,
+    Ipc,
+    User,
+    Cgroup,
+    Time,
+}
+
+impl NsType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Unknown => "unknown",
+            Self::Mnt => "mnt",
+            Self::Net => "net",
+            Self::Pid => "pid",
+            Self::Uts => "uts",
+            Self::Ipc => "ipc",
+            Self::User => "user",
+            Self::Cgroup => "cgroup",
+            Self::Time => "time",
+        }
+    }
+}
+
+impl fmt::Display for NsType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&str> for NsType {
+    fn from(s: &str) -> Self {
+        match s {
+            "mnt" => Self::Mnt,
+            "net" => Self::Net,
+            "pid" => Self::Pid,
+            "uts" => Self::Uts,
+            "ipc" => Self::Ipc,
+            "user" => Self::User,
+            "cgroup" => Self::Cgroup,
+            "time" => Self::Time,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Namespace {
+    pub id: u64,
+    pub ty: NsType,
+    pub nprocs: usize,
+    pub pid: u32,
+    pub user: String,
+    pub command: String,
+}
+
+impl Namespace {
+    pub fn merge(&mut self, mut rhs: Namespace) {
+        if self.pid < rhs.pid {
+            self.nprocs += 1;
+            return;
+        }
+        rhs.nprocs += 1;
+        *self = rhs;
+    }
+}
+
+impl From<Namespace> for pb::LinuxNamespace {
+    fn from(ns: Namespace) -> Self {
+        Self {
+            id: Some(ns.id),
+            pid: Some(ns.pid),
+            user: Some(ns.user),
+            cmd: Some(ns.command),
+            ns_type: Some(ns.ty.to_string()),
+        }
+    }
+}
+
+pub async fn lsns() -> Result<Vec<Namespace>> {
+    lsns_filtered(None, None).await
+}
+
+// Namespaces of the type if set, and only those of the process if pid is set. Like `lsns -p`,
+// NPROCS and PID are still counted on all processes.
+pub async fn lsns_filtered(
+    filter_type: Option<NsType>,
+    pid: Option<u32>,
+) -> Result<Vec<Namespace>> {
+    let ids = match pid {
+        Some(pid) => {
+            let mut ids = vec![];
+            let path: PathBuf = [public::netns::PROC_PATH, &pid.to_string(), "ns"]
+                .iter()
+                .collect();
+            let mut iter = tokio::fs::read_dir(&path).await?;
+            while let Some(ns_file) = iter.next_entry().await? {
+                if let Ok(fp) = tokio::fs::metadata(ns_file.path()).await {
+                    ids.push(fp.ino());
+                }
+            }
+            Some(ids)
+        }
+        None => None,
+    };
+    let mut ns_by_id: HashMap<u64, Namespace> = HashMap::new();
+    let mut iter = tokio::fs::read_dir(public::netns::PROC_PATH).await?;
+    while let Some(proc) = iter.next_entry().await? {
+        match proc.file_type().await {
+            Ok(t) if t.is_dir() => (),
+            _ => {
+                debug!("skipped {}", proc.path().display());
+                continue;
+            }
+        }
+        let Some(pid) = proc
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let mut path = proc.path();
+
+        let user = match tokio::fs::metadata(&path).await {
+            Ok(fp) => match username_by_uid(fp.uid()) {
+                Ok(name) => name,
+                Err(e) => {
+                    debug!("get username for uid {} failed: {}", fp.uid(), e);
+                    fp.uid().to_string()
+                }
+            },
+            Err(e) => {
+                debug!("get uid for process {} failed: {}", pid, e);
+                continue;
+            }
+        };
+
+        let cmdline = match get_proc_cmdline(&path).await {
+            Ok(cmdline) => cmdline,
+            Err(e) => {
+                debug!("get_proc_cmdline for process {} failed: {}", pid, e);
+                continue;
+            }
+        };
+
+        path.push("ns");
+        // the process may have exited
+        let mut ns_iter = match tokio::fs::read_dir(&path).await {
+            Ok(iter) => iter,
+            Err(e) => {
+                debug!("read namespaces of process {} failed: {}", pid, e);
+                continue;
+            }
+        };
+        while let Some(ns_file) = ns_iter.next_entry().await? {
+            let Some(ns_type) = ns_file.file_name().as_os_str().to_str().map(NsType::from) else {
+                continue;
+            };
+            let ns_path = ns_file.path();
+            if ns_type == NsType::Unknown {
+                debug!("ignored path {} with unknown ns type", ns_path.display());
+                continue;
+            }
+            if matches!(filter_type, Some(t) if t != ns_type) {
+                continue;
+            }
+
+            let Ok(fp) = tokio::fs::metadata(&ns_path).await else {
+                continue;
+            };
+
+            let nsid = fp.ino();
+            let ns = Namespace {
+                id: nsid,
+                ty: ns_type,
+                nprocs: 1,
+                pid,
+                user: user.clone(),
+                command: cmdline.clone(),
+            };
+            match ns_by_id.entry(nsid) {
+                Entry::Occupied(mut o) => o.get_mut().merge(ns),
+                Entry::Vacant(v) => {
+                    v.insert(ns);
+                }
+            }
+        }
+    }
+    if let Some(ids) = ids {
+        ns_by_id.retain(|id, _| ids.contains(id));
+    }
+    Ok(ns_by_id.into_values().collect())
+}
+
+pub fn write_namespace_table<W: Write>(mut w: W, table: &[Namespace]) -> Result<()> {
+    let name_width = table
+        .iter()
+        .map(|n| n.user.len())
+        .max()
+        .unwrap_or_default()
+        .max("USER".len());
+    write!(
+        w,
+        "        NS TYPE   NPROCS   PID {:<name_width$} COMMAND\n",
+        "USER"
+    )?;
+    for ns in table.iter() {
+        write!(
+            w,
+            "{:>10} {:<6} {:>6} {:>5} {:<name_width$} {}\n",
+            ns.id,
+            ns.ty.as_str(),
+            ns.nprocs,
+            ns.pid,
+            ns.user,
+            ns.command,
+        )?;
+    }
+    Ok(())
+}
+
+pub(super) async fn ls_netns() -> Result<Vec<pb::LinuxNamespace>> {
+    Ok(lsns_filtered(Some(NsType::Net), None)
+        .await?
+        .into_iter()
+        .map(pb::LinuxNamespace::from)
+        .collect())
+}
+
+async fn lsns_command(ns_type: Option<NsType>, pid: Option<u32>) -> Result<Output> {
+    let mut output = vec![];
+    write_namespace_table(&mut output, &lsns_filtered(ns_type, pid).await?)?;
+    Ok(Output {
+        status: Default::default(),
+        stdout: output,
+        stderr: vec![],
+    })
+}
+
+async fn pcap_list_command(dir: String) -> Result<Output> {
+    let mut files = vec![];
+    let mut iter = tokio::fs::read_dir(&dir).await?;
+    while let Some(entry) = iter.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if metadata.is_file() {
+            files.push((entry.path(), metadata.len(), metadata.mtime()));
+        }
+    }
+    files.sort();
+    let mut output = vec![];
+    write!(output, "{:>10} {:>10} PATH\n", "SIZE", "MTIME")?;
+    for (path, size, mtime) in files.iter() {
+        write!(output, "{:>10} {:>10} {}\n", size, mtime, path.display())?;
+    }
+    Ok(Output {
+        status: Default::default(),
+        stdout: output,
+        stderr: vec![],
+    })
+}
+
+// Finds the executable like PATH lookup of exec, with `root` as the root directory
+fn find_executable(name: &str, root: &Path) -> Option<PathBuf> {
+    let is_executable = |path: &Path| {
+        path.metadata()
+            .map(|m| m.is_file() && m.mode() & 0o111 != 0)
+            .unwrap_or_default()
+    };
+    if name.contains('/') {
+        let path = root.join(name.trim_start_matches('/'));
+        return is_executable(&path).then_some(path);
+    }
+    std::env::var("PATH")
+        .ok()?
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| root.join(dir.trim_start_matches('/')).join(name))
+        .find(|path| is_executable(path))
+}
+
+// Reviews access of the agent with SelfSubjectAccessReview
+async fn kubectl_access(
+    client: Arc<KubeClient>,
+    attributes: ResourceAttributes,
+) -> pb::CommandCheckItem {
+    let name = format!(
+        "kubernetes {} {}{}",
+        attributes.verb.as_deref().unwrap_or_default(),
+        attributes.resource.as_deref().unwrap_or_default(),
+        attributes
+            .subresource
+            .as_deref()
+            .map(|s| format!("/{}", s))
+            .unwrap_or_default()
+    );
+    let review = SelfSubjectAccessReview {
+        spec: SelfSubjectAccessReviewSpec {
+            resource_attributes: Some(attributes),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let reviewed = async {
+        let client = client.get().await?;
+        Ok::<_, Error>(
+            Api::<SelfSubjectAccessReview>::all(client)
+                .create(&PostParams::default(), &review)
+                .await?,
+        )
+    }
+    .await;
+    match reviewed.map(|r| r.status.unwrap_or_default()) {
+        Ok(status) => check_item(&name, status.allowed, status.reason.unwrap_or_default()),
+        Err(e) => check_item(&name, false, e.to_string()),
+    }
+}
+
+// Kubernetes client shared by kubectl commands to save discovery and TLS handshakes,
+// rebuilt on the next command after an authentication failure
+#[derive(Default)]
+struct KubeClient {
+    config: Mutex<RemoteExecKubeConfig>,
+    client: Mutex<Option<Client>>,
+}
+
+impl KubeClient {
+    // The client is rebuilt if config changed
+    fn set_config(&self, config: &RemoteExecKubeConfig) {
+        let mut current = self.config.lock();
+        if *current == *config {
+            return;
+        }
+        info!("kubernetes config of remote exec updated to {:?}", config);
+        *current = config.clone();
+        self.client.lock().take();
+    }
+
+    async fn load_config(conf: &RemoteExecKubeConfig) -> Result<Config> {
+        let api_server = if conf.api_server.is_empty() {
+            None
+        } else {
+            Some(
+                conf.api_server
+                    .parse::<http::Uri>()
+                    .map_err(|e| Error::KubeConfigError(format!("invalid api server: {}", e)))?,
+            )
+        };
+        let mut config = if !conf.kubeconfig.is_empty() {
+            let kubeconfig = Kubeconfig::read_from(&conf.kubeconfig)
+                .map_err(|e| Error::KubeConfigError(e.to_string()))?;
+            Config::from_custom_kubeconfig(kubeconfig, &KubeConfigOptions::default())
+                .await
+                .map_err(|e| Error::KubeConfigError(e.to_string()))?
+        } else if let Some(uri) = api_server.clone() {
+            Config::new(uri)
+        } else {
+            infer_config().await.map_err(Error::KubeConfigError)?
+        };
+        if let Some(uri) = api_server {
+            config.cluster_url = uri;
+        }
+        if !conf.token_file.is_empty() {
+            config.auth_info.token = None;
+            config.auth_info.token_file = Some(conf.token_file.clone());
+        }
+        Ok(config)
+    }
+
+    async fn get(&self) -> Result<Client> {
+        if let Some(client) = self.client.lock().as_ref() {
+            return Ok(client.clone());
+        }
+        let conf = self.config.lock().clone();
+        let mut config = Self::load_config(&conf).await?;
+        // The api server is verified with the CA of kubeconfig or service account, unless
+        // skipped explicitly
+        if conf.overridden() {
+            config.accept_invalid_certs = conf.insecure_skip_tls_verify;
+        }
+        info!("api server url is: {}", config.cluster_url);
+        let client = Client::try_from(config)?;
+        *self.client.lock() = Some(client.clone());
+        Ok(client)
+    }
+
+    // Drops the cached client if the command failed on authentication, e.g. expired token
+    async fn run<F: Future<Output = Result<Output>>>(self: Arc<Self>, future: F) -> Result<Output> {
+        let result = future.await;
+        if let Err(Error::KubeError(e)) = result.as_ref() {
+            if is_kube_auth_error(e) && self.client.lock().take().is_some() {
+                info!("kubernetes client dropped on {}", e);
+            }
+        }
+        result
+    }
+}
+
+fn is_kube_auth_error(e: &kube::Error) -> bool {
+    match e {
+        kube::Error::Auth(_) => true,
+        kube::Error::Api(ae) => ae.code == 401,
+        _ => false,
+    }
+}
+
+// Returns stdout to be streamed along with the future if any
+fn kubectl_execute<'a>(
+    cmd: KubeCmd,
+    params: &Params<'a>,
+    client: Arc<KubeClient>,
+    progress: Arc<Progress>,
+) -> Result<(BoxFuture<'static, Result<Output>>, Option<DuplexStream>)> {
+    let param = |name: &str| params.get(name);
+    let c = client.clone();
+    let future: BoxFuture<'static, Result<Output>> = match cmd {
+        KubeCmd::DescribePod => Box::pin(kubectl_describe::<Pod>(
+            c,
+            "pod",
+            Some(param("ns")?),
+            param("pod")?,
+            progress,
+        )),
+        KubeCmd::DescribeDeployment => Box::pin(kubectl_describe::<Deployment>(
+            c,
+            "deployment",
+            Some(param("ns")?),
+            param("deployment")?,
+            progress,
+        )),
+        KubeCmd::DescribeService => Box::pin(kubectl_describe::<Service>(
+            c,
+            "service",
+            Some(param("ns")?),
+            param("service")?,
+            progress,
+        )),
+        KubeCmd::DescribeNode => Box::pin(kubectl_describe::<Node>(
+            c,
+            "node",
+            None,
+            param("node")?,
+            progress,
+        )),
+        KubeCmd::Log | KubeCmd::LogPrevious => {
+            let optional = |name: &str| params.get(name).ok().filter(|v| !v.is_empty());
+            let log_params = LogParams {
+                container: optional("container"),
+                previous: cmd == KubeCmd::LogPrevious,
+                since_seconds: optional("since_seconds").and_then(|s| s.parse().ok()),
+                tail_lines: Some(LOG_LINES as i64),
+                timestamps: optional("timestamps").as_deref() == Some("true"),
+                ..Default::default()
+            };
+            Box::pin(kubectl_log(
+                c,
+                param("ns")?,
+                param("pod")?,
+                log_params,
+                progress,
+            ))
+        }
+        KubeCmd::Get(resource) => {
+            let ns = if resource.namespaced() {
+                Some(param("ns")?)
+            } else {
+                None
+            };
+            Box::pin(kubectl_get(c, resource, ns, progress))
+        }
+        KubeCmd::Exec => {
+            let cmd_id = param("cmd_id")?;
+            let args = match cmd_id.parse().ok().and_then(get_cmd) {
+                Some(c) if c.command_type == CommandType::Linux && c.full_ns => c
+                    .cmdline
+                    .split_whitespace()
+                    .map(|arg| arg.to_owned())
+                    .collect::<Vec<_>>(),
+                _ => return Err(Error::ExecNotAllowed(cmd_id)),
+            };
+            if args.iter().any(|arg| arg.starts_with('$')) {
+                return Err(Error::ExecNotAllowed(cmd_id));
+            }
+            let (stdout, writer) = tokio::io::duplex(READ_BUFFER_SIZE);
+            let future = client.run(kubectl_exec(
+                c,
+                param("ns")?,
+                param("pod")?,
+                param("container")?,
+                args,
+                writer,
+                progress,
+            ));
+            return Ok((Box::pin(future), Some(stdout)));
+        }
+    };
+    Ok((Box::pin(client.run(future)), None))
+}
+
+fn self_profile_execute<'a>(
+    profile_type: ProfileType,
+    params: &Params<'a>,
+) -> Result<BoxFuture<'static, Result<Output>>> {
+    let param = |name: &str| {
+        params
+            .0
+            .iter()
+            .find(|p| p.key.as_deref() == Some(name))
+            .and_then(|p| p.value.as_deref())
+            .ok_or_else(|| Error::ParamNotFound(name.to_owned()))
+    };
+    let seconds = param("seconds")?;
+    let duration = seconds
+        .parse()
+        .map(Duration::from_secs)
+        .map_err(|_| Error::SelfProfileFailed(format!("invalid seconds {}", seconds)))?;
+    let format = ProfileFormat::try_from(param("format")?).map_err(Error::SelfProfileFailed)?;
+    Ok(Box::pin(async move {
+        // profiling blocks for the whole duration
+        let stdout = tokio::task::spawn_blocking(move || {
+            self_profiler::profile(
+                profile_type,
+                duration,
+                self_profiler::DEFAULT_CPU_FREQUENCY,
+                format,
+            )
+        })
+        .await
+        .map_err(|e| Error::SelfProfileFailed(e.to_string()))?
+        .map_err(Error::SelfProfileFailed)?;
+        Ok(Output {
+            status: Default::default(),
+            stdout,
+            stderr: vec![],
+        })
+    }))
+}
+
+// Object and related events, both are omitted if not found
+struct Describe<K> {
+    // field name of the object
+    key: &'static str,
+    object: Option<K>,
+    events: Vec<Event>,
+}
+
+impl<K: serde::Serialize> serde::Serialize for Describe<K> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(object) = self.object.as_ref() {
+            map.serialize_entry(self.key, object)?;
+        }
+        if !self.events.is_empty() {
+            map.serialize_entry("events", &self.events)?;
+        }
+        map.end()
+    }
+}
+
+// Cluster scoped objects are described if namespace is None
+async fn kubectl_describe<K>(
+    client: Arc<KubeClient>,
+    key: &'static str,
+    namespace: Option<String>,
+    name: String,
+    progress: Arc<Progress>,
+) -> Result<Output>
+where
+    K: kube::Resource<DynamicType = ()> + Clone + DeserializeOwned + fmt::Debug + serde::Serialize,
+{
+    progress.set_phase(Phase::Connecting);
+    let client = client.get().await?;
+
+    progress.set_phase(Phase::Fetching);
+    let object = match namespace.as_deref() {
+        Some(ns) => Api::<K>::namespaced(client.clone(), ns),
+        None => Api::<K>::all(client.clone()),
+    }
+    .get(&name)
+    .await;
+
+    let mut field_selector = format!(
+        "involvedObject.kind={},involvedObject.name={name}",
+        K::kind(&())
+    );
+    // events of nodes are recorded with node name as uid, so uid is only matched for
+    // namespaced objects
+    let events = match namespace.as_deref() {
+        Some(ns) => {
+            let _ = write!(&mut field_selector, ",involvedObject.namespace={ns}");
+            if let Some(uid) = object.as_ref().ok().and_then(|o| o.meta().uid.as_ref()) {
+                let _ = write!(&mut field_selector, ",involvedObject.uid={uid}");
+            }
+            Api::<Event>::namespaced(client, ns)
+        }
+        None => Api::<Event>::all(client),
+    }
+    .list(&ListParams::default().fields(&field_selector))
+    .await;
+
+    let describe = match object {
+        Ok(object) => Describe {
+            key,
+            object: Some(object),
+            events: events.ok().map(|e| e.items).unwrap_or_default(),
+        },
+        Err(e) => match events {
+            Ok(events) => Describe {
+                key,
+                object: None,
+                events: events.items,
+            },
+            Err(_) => {
+                return Err(e.into());
+            }
+        },
+    };
+
+    let stdout = serde_json::to_vec_pretty(&describe)?;
+    progress.add_bytes(stdout.len());
+    Ok(Output {
+        status: Default::default(),
+        stdout,
+        stderr: vec![],
+    })
+}
+
+const LOG_LINES: usize = 10000;
+const DOCKER_API_TIMEOUT: u64 = 10; // s
+
+async fn kubectl_log(
+    client: Arc<KubeClient>,
+    namespace: String,
+    pod: String,
+    log_params: LogParams,
+    progress: Arc<Progress>,
+) -> Result<Output> {
+    progress.set_phase(Phase::Connecting);
+    let client = client.get().await?;
+
+    progress.set_phase(Phase::Fetching);
+    let logs = Api::<Pod>::namespaced(client, &namespace)
+        .logs(&pod, &log_params)
+        .await?;
+    progress.add_bytes(logs.len());
+    Ok(Output {
+        status: Default::default(),
+        stdout: logs.into_bytes(),
+        stderr: vec![],
+    })
+}
+
+fn container_execute<'a>(
+    cmd: ContainerCmd,
+    params: &Params<'a>,
+    cri_socket_path: String,
+    docker_socket_path: String,
+    progress: Arc<Progress>,
+) -> Result<BoxFuture<'static, Result<Output>>> {
+    let container_id = params.get("container_id")?;
+    Ok(match cmd {
+        ContainerCmd::CriLog => Box::pin(crictl_log(cri_socket_path, container_id, progress)),
+        ContainerCmd::DockerLog => Box::pin(docker_log(docker_socket_path, container_id, progress)),
+    })
+}
+
+async fn crictl_log(
+    socket_path: String,
+    container_id: String,
+    progress: Arc<Progress>,
+) -> Result<Output> {
+    progress.set_phase(Phase::Connecting);
+    let log_path = cri::container_log_path(&socket_path, &container_id).await?;
+    progress.set_phase(Phase::Fetching);
+    let logs = cri::read_log(&log_path, LOG_LINES).await?;
+    progress.add_bytes(logs.len());
+    Ok(Output {
+        status: Default::default(),
+        stdout: logs,
+        stderr: vec![],
+    })
+}
+
+async fn docker_log(
+    socket_path: String,
+    container_id: String,
+    progress: Arc<Progress>,
+) -> Result<Output> {
+    progress.set_phase(Phase::Connecting);
+    let docker =
+        Docker::connect_with_socket(&socket_path, DOCKER_API_TIMEOUT, API_DEFAULT_VERSION)?;
+    progress.set_phase(Phase::Fetching);
+    // stdout and stderr are interleaved like `docker logs` on terminal
+    let mut logs = docker.logs(
+        &container_id,
+        Some(LogsOptions::<String> {
+            stdout: true,
+            stderr: true,
+            tail: LOG_LINES.to_string(),
+            ..Default::default()
+        }),
+    );
+    let mut stdout = vec![];
+    while let Some(log) = logs.next().await {
+        let log = log?.into_bytes();
+        progress.add_bytes(log.len());
+        stdout.extend_from_slice(&log);
+    }
+    Ok(Output {
+        status: Default::default(),
+        stdout,
+        stderr: vec![],
+    })
+}
+
+const NONE_COLUMN: &str = "<none>";
+
+// Exit code of exec is reported in causes of the status like kubectl
+fn exec_exit_code(status: &Status) -> Option<i32> {
+    if status.status.as_deref() == Some("Success") {
+        return Some(0);
+    }
+    status
+        .details
+        .as_ref()?
+        .causes
+        .as_ref()?
+        .iter()
+        .find(|c| c.reason.as_deref() == Some("ExitCode"))?
+        .message
+        .as_ref()?
+        .parse()
+        .ok()
+}
+
+// Stdout is copied to `writer` and streamed by the pending command
+async fn kubectl_exec(
+    client: Arc<KubeClient>,
+    namespace: String,
+    pod: String,
+    container: String,
+    args: Vec<String>,
+    mut writer: DuplexStream,
+    progress: Arc<Progress>,
+) -> Result<Output> {
+    progress.set_phase(Phase::Connecting);
+    let client = client.get().await?;
+
+    let cmdline = args.join(" ");
+    let mut attached = Api::<Pod>::namespaced(client, &namespace)
+        .exec(
+            &pod,
+            args,
+            &AttachParams::default().container(container).stderr(false),
+        )
+        .await?;
+    progress.set_phase(Phase::Fetching);
+    if let Some(mut stdout) = attached.stdout() {
+        tokio::io::copy(&mut stdout, &mut writer).await?;
+    }
+    // closes stdout of the pending command
+    drop(writer);
+    let status = match attached.take_status() {
+        Some(status) => status.await,
+        None => None,
+    };
+    match status.as_ref().and_then(exec_exit_code) {
+        Some(code) => Ok(Output {
+            status: ExitStatus::from_raw((code & 0xff) << 8),
+            stdout: vec![],
+            stderr: vec![],
+        }),
+        None => Err(Error::CmdFailed(cmdline, None)),
+    }
+}
+
+// Same as the AGE column of kubectl
+fn human_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    let (mins, hours, days) = (secs / 60, secs / 3600, secs / 86400);
+    if secs < 120 {
+        format!("{}s", secs)
+    } else if mins < 10 {
+        match secs % 60 {
+            0 => format!("{}m", mins),
+            s => format!("{}m{}s", mins, s),
+        }
+    } else if hours < 3 {
+        format!("{}m", mins)
+    } else if hours < 8 {
+        match mins % 60 {
+            0 => format!("{}h", hours),
+            m => format!("{}h{}m", hours, m),
+        }
+    } else if hours < 48 {
+        format!("{}h", hours)
+    } else if hours < 24 * 8 {
+        match hours % 24 {
+            0 => format!("{}d", days),
+            h => format!("{}d{}h", days, h),
+        }
+    } else if days < 365 * 2 {
+        format!("{}d", days)
+    } else if days < 365 * 8 {
+        match days % 365 {
+            0 => format!("{}y", days / 365),
+            d => format!("{}y{}d", days / 365, d),
+        }
+    } else {
+        format!("{}y", days / 365)
+    }
+}
+
+fn age(meta: &ObjectMeta) -> String {
+    match meta.creation_timestamp.as_ref() {
+        Some(t) => human_duration(
+            chrono::Utc::now()
+                .signed_duration_since(t.0)
+                .to_std()
+                .unwrap_or_default(),
+        ),
+        None => "<unknown>".to_owned(),
+    }
+}
+
+fn or_none(s: String) -> String {
+    if s.is_empty() {
+        NONE_COLUMN.to_owned()
+    } else {
+        s
+    }
+}
+
+fn join_labels(labels: Option<&BTreeMap<String, String>>) -> String {
+    or_none(
+        labels
+            .into_iter()
+            .flatten()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join(","),
+    )
+}
+
+// Columns are left aligned and separated by 3 spaces like kubectl
+fn write_table<W: Write>(mut w: W, header: &[&str], rows: &[Vec<String>]) -> Result<()> {
+    let mut widths = header.iter().map(|h| h.len()).collect::<Vec<_>>();
+    for row in rows.iter() {
+        for (w, col) in widths.iter_mut().zip(row.iter()) {
+            *w = (*w).max(col.len());
+        }
+    }
+    let mut write_row = |cols: &mut dyn Iterator<Item = &str>| -> std::io::Result<()> {
+        let mut line = String::new();
+        for (i, col) in cols.enumerate() {
+            if i > 0 {
+                line.push_str("   ");
+            }
+            let _ = write!(&mut line, "{:<width$}", col, width = widths[i]);
+        }
+        writeln!(w, "{}", line.trim_end())
+    };
+    write_row(&mut header.iter().copied())?;
+    for row in rows.iter() {
+        write_row(&mut row.iter().map(|c| c.as_str()))?;
+    }
+    Ok(())
+}
+
+fn deployment_rows(deployments: Vec<Deployment>) -> Vec<Vec<String>> {
+    deployments
+        .into_iter()
+        .map(|d| {
+            let spec = d.spec.unwrap_or_default();
+            let status = d.status.unwrap_or_default();
+            let containers = spec.template.spec.map(|s| s.containers).unwrap_or_default();
+            vec![
+                d.metadata.name.clone().unwrap_or_default(),
+                format!(
+                    "{}/{}",
+                    status.ready_replicas.unwrap_or_default(),
+                    spec.replicas.unwrap_or(1)
+                ),
+                status.updated_replicas.unwrap_or_default().to_string(),
+                status.available_replicas.unwrap_or_default().to_string(),
+                age(&d.metadata),
+                or_none(
+                    containers
+                        .iter()
+                        .map(|c| c.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                ),
+                or_none(
+                    containers
+                        .iter()
+                        .filter_map(|c| c.image.as_deref())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                ),
+                join_labels(spec.selector.match_labels.as_ref()),
+            ]
+        })
+        .collect()
+}
+
+fn service_rows(services: Vec<Service>) -> Vec<Vec<String>> {
+    services
+        .into_iter()
+        .map(|s| {
+            let spec = s.spec.unwrap_or_default();
+            let mut external_ips = spec.external_ips.clone().unwrap_or_default();
+            if let Some(ingress) = s
+                .status
+                .and_then(|s| s.load_balancer)
+                .and_then(|lb| lb.ingress)
+            {
+                external_ips.extend(ingress.into_iter().filter_map(|i| i.ip.or(i.hostname)));
+            }
+            let ports = spec
+                .ports
+                .iter()
+                .flatten()
+                .map(|p| {
+                    let protocol = p.protocol.as_deref().unwrap_or("TCP");
+                    match p.node_port {
+                        Some(node_port) => format!("{}:{}/{}", p.port, node_port, protocol),
+                        None => format!("{}/{}", p.port, protocol),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            vec![
+                s.metadata.name.clone().unwrap_or_default(),
+                spec.type_.clone().unwrap_or_else(|| "ClusterIP".to_owned()),
+                or_none(spec.cluster_ip.clone().unwrap_or_default()),
+                or_none(external_ips.join(",")),
+                or_none(ports),
+                age(&s.metadata),
+                join_labels(spec.selector.as_ref()),
+            ]
+        })
+        .collect()
+}
+
+const MAX_ENDPOINTS_SHOWN: usize = 3;
+
+fn endpoints_rows(endpoints: Vec<Endpoints>) -> Vec<Vec<String>> {
+    endpoints
+        .into_iter()
+        .map(|e| {
+            let mut addrs = vec![];
+            for subset in e.subsets.iter().flatten() {
+                for addr in subset.addresses.iter().flatten() {
+                    match subset.ports.as_ref() {
+                        Some(ports) if !ports.is_empty() => {
+                            for port in ports.iter() {
+                                addrs.push(format!("{}:{}", addr.ip, port.port));
+                            }
+                        }
+                        _ => addrs.push(addr.ip.clone()),
+                    }
+                }
+            }
+            let shown = if addrs.len() > MAX_ENDPOINTS_SHOWN {
+                format!(
+                    "{} + {} more...",
+                    addrs[..MAX_ENDPOINTS_SHOWN].join(","),
+                    addrs.len() - MAX_ENDPOINTS_SHOWN
+                )
+            } else {
+                or_none(addrs.join(","))
+            };
+            vec![
+                e.metadata.name.clone().unwrap_or_default(),
+                shown,
+                age(&e.metadata),
+            ]
+        })
+        .collect()
+}
+
+const NODE_ROLE_LABEL_PREFIX: &str = "node-role.kubernetes.io/";
+
+fn node_rows(nodes: Vec<Node>) -> Vec<Vec<String>> {
+    nodes
+        .into_iter()
+        .map(|n| {
+            let status = n.status.clone().unwrap_or_default();
+            let ready = status
+                .conditions
+                .iter()
+                .flatten()
+                .any(|c| c.type_ == "Ready" && c.status == "True");
+            let mut node_status = if ready { "Ready" } else { "NotReady" }.to_owned();
+            if n.spec.as_ref().and_then(|s| s.unschedulable) == Some(true) {
+                node_status.push_str(",SchedulingDisabled");
+            }
+            let roles = n
+                .metadata
+                .labels
+                .iter()
+                .flatten()
+                .filter_map(|(k, _)| k.strip_prefix(NODE_ROLE_LABEL_PREFIX))
+                .collect::<Vec<_>>()
+                .join(",");
+            let address = |ty: &str| {
+                or_none(
+                    status
+                        .addresses
+                        .iter()
+                        .flatten()
+                        .filter(|a| a.type_ == ty)
+                        .map(|a| a.address.as_str())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                )
+            };
+            let info = status.node_info.clone().unwrap_or_default();
+            vec![
+                n.metadata.name.clone().unwrap_or_default(),
+                node_status,
+                or_none(roles),
+                age(&n.metadata),
+                info.kubelet_version,
+                address("InternalIP"),
+                address("ExternalIP"),
+                info.os_image,
+                info.kernel_version,
+                info.container_runtime_version,
+            ]
+        })
+        .collect()
+}
+
+async fn kubectl_get(
+    client: Arc<KubeClient>,
+    resource: KubeResource,
+    namespace: Option<String>,
+    progress: Arc<Progress>,
+) -> Result<Output> {
+    progress.set_phase(Phase::Connecting);
+    let client = client.get().await?;
+
+    progress.set_phase(Phase::Fetching);
+    let lp = ListParams::default();
+    let ns = namespace.as_deref().unwrap_or_default();
+    let mut stdout = vec![];
+    match resource {
+        KubeResource::Deployment => write_table(
+            &mut stdout,
+            &[
+                "NAME",
+                "READY",
+                "UP-TO-DATE",
+                "AVAILABLE",
+                "AGE",
+                "CONTAINERS",
+                "IMAGES",
+                "SELECTOR",
+            ],
+            &deployment_rows(
+                Api::<Deployment>::namespaced(client, ns)
+                    .list(&lp)
+                    .await?
+                    .items,
+            ),
+        )?,
+        KubeResource::Service => write_table(
+            &mut stdout,
+            &[
+                "NAME",
+                "TYPE",
+                "CLUSTER-IP",
+                "EXTERNAL-IP",
+                "PORT(S)",
+                "AGE",
+                "SELECTOR",
+            ],
+            &service_rows(
+                Api::<Service>::namespaced(client, ns)
+                    .list(&lp)
+                    .await?
+                    .items,
+            ),
+        )?,
+        KubeResource::Endpoints => write_table(
+            &mut stdout,
+            &["NAME", "ENDPOINTS", "AGE"],
+            &endpoints_rows(
+                Api::<Endpoints>::namespaced(client, ns)
+                    .list(&lp)
+                    .await?
+                    .items,
+            ),
+        )?,
+        KubeResource::Node => write_table(
+            &mut stdout,
+            &[
+                "NAME",
+                "STATUS",
+                "ROLES",
+                "AGE",
+                "VERSION",
+                "INTERNAL-IP",
+                "EXTERNAL-IP",
+                "OS-IMAGE",
+                "KERNEL-VERSION",
+                "CONTAINER-RUNTIME",
+            ],
+            &node_rows(Api::<Node>::all(client).list(&lp).await?.items),
+        )?,
+    }
+    progress.add_bytes(stdout.len());
+    Ok(Output {
+        status: Default::default(),
+        stdout,
+        stderr: vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(kvs: &[(&str, &str)]) -> Vec<pb::Parameter> {
+        kvs.iter()
+            .map(|(k, v)| pb::Parameter {
+                key: Some(k.to_string()),
+                value: Some(v.to_string()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn param_validation() {
+        // params of commands without rules are validated by name
+        let plain = Command::from(&RemoteExecCommand::default());
+        for (kvs, valid) in [
+            (vec![("ns", "deepflow"), ("pod", "agent-x1_2")], true),
+            (vec![("pod", "agent.x")], false),
+            (vec![("host", "10.1.2.3")], true),
+            (vec![("host", "fe80::1")], true),
+            (vec![("host", "kube-dns.kube-system.svc")], true),
+            (vec![("host", "-c100")], false),
+            (vec![("host", "a..b")], false),
+            (vec![("host", "a b")], false),
+            (vec![("url", "http://10.0.0.1:8080/health?x=1")], true),
+            (vec![("url", "https://[fe80::1]:443/")], true),
+            (vec![("url", "https://example.com")], true),
+            (vec![("url", "file:///etc/passwd")], false),
+            (vec![("url", "http://user@example.com/")], false),
+            (vec![("url", "http://example.com:0/")], false),
+            (vec![("url", "http://example.com/a b")], false),
+            (vec![("ifname", "eth0.100"), ("count", "1000")], true),
+            (vec![("ifname", "-eth0")], false),
+            (vec![("ifname", "a-very-long-ifname")], false),
+            (vec![("node", "ip-10-0-0-1.ec2.internal")], true),
+            (
+                vec![
+                    ("container", ""),
+                    ("since_seconds", "3600"),
+                    ("timestamps", "true"),
+                ],
+                true,
+            ),
+            (vec![("since_seconds", "0")], false),
+            (vec![("since_seconds", "-1")], false),
+            (vec![("timestamps", "yes")], false),
+            (vec![("ns", "default"), ("service", "-kube-dns")], false),
+            (vec![("count", "0")], false),
+            (vec![("count", "1000000")], false),
+        ] {
+            let ps = params(&kvs);
+            assert_eq!(Params(&ps).is_valid(&plain), valid, "{:?}", kvs);
+        }
+
+        let describe_pod = all_supported_commands()
+            .into_iter()
+            .find(|c| c.command_type == CommandType::Kubernetes(KubeCmd::DescribePod))
+            .unwrap();
+        let ps = params(&[("ns", "default"), ("pod", "agent.x")]);
+        assert!(Params(&ps).is_valid(&describe_pod));
+
+        let tail = Command::from(&RemoteExecCommand {
+            cmdline: "tail -n $lines $file".to_owned(),
+            params: vec!["lines".to_owned(), "file".to_owned()],
+            param_rules: [
+                ("lines".to_owned(), RemoteExecParamRule::Integer),
+                (
+                    "file".to_owned(),
+                    RemoteExecParamRule::Regex("/var/log/[a-z.-]+".to_owned()),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        });
+        for (kvs, valid) in [
+            (vec![("lines", "100"), ("file", "/var/log/messages")], true),
+            (vec![("lines", "-1")], false),
+            (vec![("lines", "+1")], false),
+            (vec![("file", "/var/log/../../etc/shadow")], false),
+            (vec![("file", "/etc/shadow")], false),
+            (vec![("file", "x/var/log/messages")], false),
+        ] {
+            let ps = params(&kvs);
+            assert_eq!(Params(&ps).is_valid(&tail), valid, "{:?}", kvs);
+        }
+
+        for (path, valid) in [
+            ("/etc/resolv.conf", true),
+            ("etc/resolv.conf", false),
+            ("/proc/../etc/shadow", false),
+            ("/tmp/a b", false),
+        ] {
+            assert_eq!(ParamRule::Path.is_valid(path), valid, "{}", path);
+        }
+        let ss = all_supported_commands()
+            .into_iter()
+            .find(|c| c.cmdline.starts_with("ss "))
+            .unwrap();
+        for (state, valid) in [("established", true), ("time-wait", true), ("-a", false)] {
+            let ps = params(&[("state", state)]);
+            assert_eq!(Params(&ps).is_valid(&ss), valid, "{}", state);
+        }
+        assert!(ParamRule::Ip.is_valid("fe80::1"));
+        assert!(!ParamRule::Ip.is_valid("example.com"));
+
+        // params the executor falls back to default for are optional to check_command
+        let commands = all_supported_commands();
+        for (cmdline, name) in [
+            (LSNS_CMDLINE, "type"),
+            (LSNS_CMDLINE, "pid"),
+            (
+                "kubectl -n $ns logs --tail=10000 $pod -c $container",
+                "container",
+            ),
+            ("kubectl -n $ns logs --tail=10000 -p $pod", "container"),
+        ] {
+            let cmd = commands
+                .iter()
+                .find(|c| c.cmdline.starts_with(cmdline))
+                .unwrap();
+            assert!(
+                matches!(cmd.param_rule(name), ParamRule::Optional(_)),
+                "{} {}",
+                cmdline,
+                name
+            );
+        }
+        let lsns = &commands[0];
+        assert!(Params(&params(&[("pid", "")])).is_valid(lsns));
+        assert!(!Params(&params(&[("pid", "abc")])).is_valid(lsns));
+    }
+
+    #[test]
+    fn executable_lookup() {
+        let root = Path::new("/");
+        assert!(find_executable("sh", root).is_some());
+        assert_eq!(
+            find_executable("/bin/sh", root),
+            Some(PathBuf::from("/bin/sh"))
+        );
+        assert!(find_executable("deepflow-no-such-binary", root).is_none());
+        assert!(find_executable("/etc/hosts", root).is_none());
+    }
+
+    #[test]
+    fn kubectl_age() {
+        for (secs, age) in [
+            (59, "59s"),
+            (125, "2m5s"),
+            (600, "10m"),
+            (3 * 3600 + 60, "3h1m"),
+            (30 * 3600, "30h"),
+            (3 * 86400 + 3600, "3d1h"),
+            (100 * 86400, "100d"),
+            (3 * 365 * 86400 + 86400, "3y1d"),
+            (10 * 365 * 86400, "10y"),
+        ] {
+            assert_eq!(human_duration(Duration::from_secs(secs)), age);
+        }
+    }
+
+    #[test]
+    fn kubectl_table() {
+        let mut output = vec![];
+        write_table(
+            &mut output,
+            &["NAME", "ENDPOINTS", "AGE"],
+            &[
+                vec![
+                    "kube-dns".to_owned(),
+                    "10.0.0.10:53".to_owned(),
+                    "3d".to_owned(),
+                ],
+                vec!["x".to_owned(), NONE_COLUMN.to_owned(), "5m".to_owned()],
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "NAME       ENDPOINTS      AGE\nkube-dns   10.0.0.10:53   3d\nx          <none>         5m\n"
+        );
+    }
+
+    #[test]
+    fn lsns_filter() {
+        let runtime = Runtime::new().unwrap();
+        let pid = std::process::id();
+        let namespaces = runtime
+            .block_on(lsns_filtered(Some(NsType::Net), Some(pid)))
+            .unwrap();
+        assert_eq!(namespaces.len(), 1);
+        assert_eq!(namespaces[0].ty, NsType::Net);
+        assert_eq!(
+            namespaces[0].id,
+            std::fs::metadata(format!("/proc/{}/ns/net", pid))
+                .unwrap()
+                .ino()
+        );
+        let namespaces = runtime.block_on(lsns_filtered(None, Some(pid))).unwrap();
+        assert!(namespaces.iter().any(|ns| ns.ty == NsType::Mnt));
+    }
+
+    #[test]
+    fn describe_json() {
+        let mut describe = Describe::<Node> {
+            key: "node",
+            object: None,
+            events: vec![],
+        };
+        assert_eq!(serde_json::to_string(&describe).unwrap(), "{}");
+        describe.object = Some(Node {
+            metadata: ObjectMeta {
+                name: Some("node-1".to_owned()),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let value = serde_json::to_value(&describe).unwrap();
+        assert_eq!(value["node"]["metadata"]["name"], "node-1");
+        assert!(value.get("events").is_none());
+    }
+
+    #[test]
+    fn config_commands() {
+        use ring::signature::{Ed25519KeyPair, KeyPair};
+
+        let builtin = all_supported_commands().len();
+        let command = |cmdline: &str, params: &[&str]| RemoteExecCommand {
+            cmdline: cmdline.to_owned(),
+            params: params.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        };
+        update_config_commands(&[
+            command("ethtool -k $dev", &["dev"]),
+            command("hostname", &[]),
+            command(
+                "ip -s link show dev $dev type $type $extra",
+                &["dev", "type", "extra"],
+            ),
+        ]);
+        // hostname is ignored as a builtin command
+        let (commands, digest) = remote_commands();
+        assert_eq!(commands.len(), builtin + 2);
+        assert_eq!(check_registry_digest(Some(digest)), Ok(()));
+        assert_eq!(check_registry_digest(None), Ok(()));
+        assert_eq!(get_cmd(builtin).unwrap().cmdline, "ethtool -k $dev");
+        assert_eq!(
+            get_cmd(builtin + 1).unwrap().cmdline,
+            "ip -s link show dev $dev type $type $extra"
+        );
+        assert!(get_cmd(builtin + 2).is_none());
+        let builtin_params = all_supported_commands()
+            .iter()
+            .map(|c| param_nums(&c.cmdline))
+            .max()
+            .unwrap();
+        assert_eq!(builtin_params, 5);
+        assert_eq!(max_param_nums(), builtin_params);
+        update_config_commands(&[command(
+            "ip -s link show dev $dev type $type $a $b $c $d",
+            &["dev", "type", "a", "b", "c", "d"],
+        )]);
+        assert_eq!(max_param_nums(), 6);
+        // ids listed before config reload are rejected
+        assert!(check_registry_digest(Some(digest)).is_err());
+
+        update_config_commands(&[]);
+        assert!(get_cmd(builtin).is_none());
+
+        // controller commands follow config commands
+        update_config_commands(&[command("ethtool -k $dev", &["dev"])]);
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let public_key = key_pair.public_key().as_ref();
+        let definition = |cmdline: &str, params: &[&str]| pb::CommandDefinition {
+            cmdline: Some(cmdline.to_owned()),
+            param_names: params.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        };
+        let sign = |version: u64, definitions: &[pb::CommandDefinition]| {
+            key_pair
+                .sign(&commands_signed_message(
+                    version,
+                    &commands_digest(definitions),
+                ))
+                .as_ref()
+                .to_vec()
+        };
+        let update = |version: u64, definitions: &[pb::CommandDefinition]| {
+            let signature = sign(version, definitions);
+            update_controller_commands(version, definitions, &signature, public_key)
+        };
+        let mut definitions = vec![
+            definition("ethtool -i $dev", &["dev"]),
+            definition("uptime", &[]),
+        ];
+        definitions[0]
+            .param_rules
+            .insert("dev".to_owned(), "regex:eth[0-9]+".to_owned());
+        let signature = sign(1, &definitions);
+        // modified definitions, rules or versions are rejected
+        let mut modified = definitions.clone();
+        modified[1].desc = Some("modified".to_owned());
+        assert!(update_controller_commands(1, &modified, &signature, public_key).is_err());
+        let mut modified = definitions.clone();
+        modified[0]
+            .param_rules
+            .insert("dev".to_owned(), "path".to_owned());
+        assert!(update_controller_commands(1, &modified, &signature, public_key).is_err());
+        assert!(update_controller_commands(2, &definitions, &signature, public_key).is_err());
+        assert!(update_controller_commands(1, &definitions, &[], public_key).is_err());
+        assert!(update_controller_commands(1, &definitions, &signature, &[0; 32]).is_err());
+        // moving a definition changes the signed set
+        let reordered = vec![definitions[1].clone(), definitions[0].clone()];
+        assert!(update_controller_commands(1, &reordered, &signature, public_key).is_err());
+        let mut unknown = definitions.clone();
+        unknown[0]
+            .param_rules
+            .insert("dev".to_owned(), "any".to_owned());
+        assert!(update(1, &unknown).is_err());
+        assert!(get_cmd(builtin + 1).is_none());
+        let (_, digest) = remote_commands();
+        assert_eq!(
+            update_controller_commands(1, &definitions, &signature, public_key),
+            Ok(true)
+        );
+        assert!(check_registry_digest(Some(digest)).is_err());
+        let ethtool = get_cmd(builtin + 1).unwrap();
+        assert_eq!(ethtool.cmdline, "ethtool -i $dev");
+        assert!(ethtool.param_rule("dev").is_valid("eth0"));
+        assert!(!ethtool.param_rule("dev").is_valid("lo"));
+        assert_eq!(get_cmd(builtin + 2).unwrap().cmdline, "uptime");
+        // resending the applied set is ignored, older or conflicting sets are rejected
+        assert_eq!(
+            update_controller_commands(1, &definitions, &signature, public_key),
+            Ok(false)
+        );
+        assert!(update(1, &[]).is_err());
+        assert!(update(0, &[]).is_err());
+        assert_eq!(remote_commands().0.len(), builtin + 3);
+        for (cmdline, params) in [
+            ("hostname", &[][..]),
+            ("ethtool -k $dev", &["dev"][..]),
+            ("ethtool -i $dev", &[][..]),
+            ("$exe", &["exe"][..]),
+        ] {
+            assert!(
+                update(2, &[definition(cmdline, params)]).is_err(),
+                "{}",
+                cmdline
+            );
+        }
+        assert_eq!(update(2, &[]), Ok(true));
+        assert!(get_cmd(builtin + 1).is_none());
+        update_config_commands(&[]);
+    }
+
+    #[test]
+    fn exec_status() {
+        use k8s_openapi::apimachinery::pkg::apis::meta::v1::{StatusCause, StatusDetails};
+
+        let status = |status: &str, causes: Vec<(&str, &str)>| Status {
+            status: Some(status.to_owned()),
+            details: Some(StatusDetails {
+                causes: Some(
+                    causes
+                        .into_iter()
+                        .map(|(reason, message)| StatusCause {
+                            reason: Some(reason.to_owned()),
+                            message: Some(message.to_owned()),
+                            ..Default::default()
+                        })
+                        .collect(),
+                ),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(exec_exit_code(&status("Success", vec![])), Some(0));
+        assert_eq!(
+            exec_exit_code(&status("Failure", vec![("ExitCode", "127")])),
+            Some(127)
+        );
+        assert_eq!(
+            exec_exit_code(&status("Failure", vec![("InternalError", "1")])),
+            None
+        );
+    }
+
+    #[test]
+    fn spawn_limits() {
+        use std::os::unix::process::CommandExt;
+
+        let mut cmd = process::Command::new("sh");
+        cmd.args(["-c", "ulimit -t; ulimit -d"]);
+        // SAFETY: set_limits only makes async-signal-safe calls
+        unsafe {
+            cmd.pre_exec(|| set_limits(30, 64 << 20, 0));
+        }
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+        // ulimit -d reports in KiB
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "30\n65536\n");
+    }
+
+    #[test]
+    fn host_program() {
+        use std::os::unix::process::CommandExt;
+
+        assert!(find_program("no-such-program").is_none());
+        let program = HostProgram::new(
+            &find_program("sh").unwrap(),
+            &["-c".to_owned(), "grep CapEff /proc/self/status".to_owned()],
+        )
+        .unwrap();
+        // the program is executed by fd instead of the one of Command
+        let mut cmd = process::Command::new("false");
+        // SAFETY: drop_capabilities and HostProgram::exec only make async-signal-safe calls
+        unsafe {
+            if libc::geteuid() == 0 {
+                cmd.pre_exec(drop_capabilities);
+            }
+            cmd.pre_exec(move || program.exec());
+        }
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "CapEff:\t0000000000000000\n"
+        );
+    }
+
+    #[test]
+    fn full_ns_commands() {
+        for c in all_supported_commands() {
+            if c.full_ns {
+                assert!(
+                    matches!(c.command_type, CommandType::Linux) && c.cmdline != LSNS_CMDLINE,
+                    "{} can not run in full namespace set",
+                    c.cmdline
+                );
+            }
+        }
+    }
+}
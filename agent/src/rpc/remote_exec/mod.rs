@@ -0,0 +1,3810 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod linux;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use linux::*;
+
+#[cfg(target_os = "windows")]
+mod windows;
+#[cfg(target_os = "windows")]
+use self::windows::*;
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt,
+    ops::Deref,
+    pin::Pin,
+    process::{self, Output},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering},
+        Arc, Weak,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use arc_swap::ArcSwap;
+use futures::{
+    future::{self, BoxFuture, Future},
+    stream::Stream,
+};
+use log::{debug, info, trace, warn};
+use md5::{Digest, Md5};
+use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use rand::Rng;
+use regex::Regex;
+use ring::{
+    digest,
+    signature::{UnparsedPublicKey, ED25519},
+};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt, DuplexStream, ReadBuf},
+    process::Child,
+    runtime::Runtime,
+    sync::{
+        mpsc::{self, Receiver},
+        watch,
+    },
+    task::JoinHandle,
+    time::{self, Interval, Sleep},
+};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use super::{cri, Diagnostics};
+use super::{
+    exec_audit::{AuditGuard, AuditLog, AuditRecord, Outcome},
+    result_cipher::ResultCipher,
+    Session, RPC_RETRY_INTERVAL,
+};
+use crate::{
+    config::{ModuleConfig, RemoteExecCommand, RemoteExecOutputFormat, RemoteExecParamRule},
+    exception::ExceptionHandler,
+    handler::{pcap_header, PacketCapture},
+    trident::AgentId,
+    utils::stats,
+};
+
+use public::{
+    counter::{Countable, Counter, CounterType, CounterValue, RefCountable},
+    proto::trident as pb,
+    utils::net::link_by_name,
+};
+
+const MIN_BATCH_LEN: usize = 1024;
+const MAX_PENDING_LSNS: usize = 8;
+const MAX_PENDING_CHECKS: usize = 8;
+const MAX_BATCH_COMMANDS: usize = 16;
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+const READ_BUFFER_SIZE: usize = 8192;
+const MAX_CACHED_RESULTS: usize = 16;
+const MAX_RESULT_CACHE_TTL: Duration = Duration::from_secs(300);
+// tail of stderr returned for failed commands
+const MAX_STDERR_LEN: usize = 4096;
+const RESUME_RESULT_TTL: Duration = Duration::from_secs(300);
+// larger results are not kept for resuming
+const MAX_RESUME_RESULT_LEN: usize = 16 << 20;
+const MAX_RESUME_BUFFER_SIZE: usize = 64 << 20;
+// appended to output truncated at max output
+const TRUNCATED_MARKER: &[u8] = b"\n[truncated]\n";
+// fetched files are truncated at this size
+const MAX_FETCH_FILE_SIZE: u64 = 16 << 20;
+const MAX_CAPTURE_DURATION: u32 = 300;
+const MAX_CAPTURE_SNAP_LEN: u32 = 65535;
+
+#[derive(Clone, Copy)]
+enum OutputFormat {
+    Text,
+    Binary,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CommandType {
+    // plain commands run on the host, also on windows
+    Linux,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Kubernetes(KubeCmd),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Container(ContainerCmd),
+}
+
+#[derive(Clone)]
+struct Command {
+    cmdline: Cow<'static, str>,
+    output_format: OutputFormat,
+    desc: Cow<'static, str>,
+    command_type: CommandType,
+    // idempotent commands whose results can be cached
+    cacheable: bool,
+    // commands allowed to run in the full namespace set of a container
+    full_ns: bool,
+    // rules of params differing from ParamRule::by_name
+    param_rules: Vec<(Cow<'static, str>, ParamRule)>,
+}
+
+impl Command {
+    fn param_rule(&self, name: &str) -> ParamRule {
+        self.param_rules
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, r)| r.clone())
+            .unwrap_or_else(|| ParamRule::by_name(name))
+    }
+}
+
+// Values allowed for a param
+#[derive(Clone, Debug)]
+enum ParamRule {
+    // letters, digits, `-` and `_`
+    Identifier,
+    // hostname or ip address
+    Host,
+    Ip,
+    Path,
+    Url,
+    Ifname,
+    Integer(u64, u64),
+    Bool,
+    OneOf(&'static [&'static str]),
+    // anchored to match the whole value
+    Regex(Regex),
+    // empty or following the rule
+    Optional(Box<ParamRule>),
+}
+
+impl ParamRule {
+    // Rules of builtin params
+    fn by_name(name: &str) -> Self {
+        match name {
+            // names of kubernetes objects are dns subdomains like hosts
+            "host" | "deployment" | "service" | "node" => Self::Host,
+            "url" => Self::Url,
+            "ifname" => Self::Ifname,
+            "count" => Self::Integer(1, MAX_CAPTURE_COUNT as u64),
+            "since_seconds" => Self::Optional(Box::new(Self::Integer(1, u32::MAX as u64))),
+            "timestamps" => Self::Optional(Box::new(Self::Bool)),
+            _ => Self::Identifier,
+        }
+    }
+
+    fn is_valid(&self, value: &str) -> bool {
+        match self {
+            Self::Identifier => value.bytes().all(|c| match c {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' => true,
+                _ => false,
+            }),
+            Self::Host => is_valid_host(value),
+            Self::Ip => value.parse::<std::net::IpAddr>().is_ok(),
+            Self::Path => is_valid_path(value),
+            Self::Url => is_valid_url(value),
+            Self::Ifname => is_valid_ifname(value),
+            Self::Integer(min, max) => {
+                value.bytes().all(|c| c.is_ascii_digit())
+                    && value
+                        .parse::<u64>()
+                        .map(|v| v >= *min && v <= *max)
+                        .unwrap_or(false)
+            }
+            Self::Bool => matches!(value, "true" | "false"),
+            Self::OneOf(values) => values.contains(&value),
+            Self::Regex(re) => re.is_match(value),
+            Self::Optional(rule) => value.is_empty() || rule.is_valid(value),
+        }
+    }
+}
+
+impl From<&RemoteExecParamRule> for ParamRule {
+    // regex already validated on config loading
+    fn from(rule: &RemoteExecParamRule) -> Self {
+        match rule {
+            RemoteExecParamRule::Identifier => Self::Identifier,
+            RemoteExecParamRule::Host => Self::Host,
+            RemoteExecParamRule::Ip => Self::Ip,
+            RemoteExecParamRule::Path => Self::Path,
+            RemoteExecParamRule::Integer => Self::Integer(0, u64::MAX),
+            RemoteExecParamRule::Regex(re) => match Regex::new(&format!("^(?:{})$", re)) {
+                Ok(re) => Self::Regex(re),
+                Err(e) => {
+                    warn!("malformed param regex \"{}\": {}", re, e);
+                    // matches nothing
+                    Self::Regex(Regex::new(r"[^\s\S]").unwrap())
+                }
+            },
+        }
+    }
+}
+
+impl From<&RemoteExecCommand> for Command {
+    fn from(c: &RemoteExecCommand) -> Self {
+        Self {
+            cmdline: c.cmdline.clone().into(),
+            output_format: match c.output_format {
+                RemoteExecOutputFormat::Text => OutputFormat::Text,
+                RemoteExecOutputFormat::Binary => OutputFormat::Binary,
+            },
+            desc: c.desc.clone().into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: c.full_ns,
+            param_rules: c
+                .param_rules
+                .iter()
+                .map(|(name, rule)| (name.clone().into(), rule.into()))
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<&pb::CommandDefinition> for RemoteExecCommand {
+    type Error = String;
+
+    fn try_from(c: &pb::CommandDefinition) -> std::result::Result<Self, Self::Error> {
+        let mut param_rules = BTreeMap::new();
+        for (name, rule) in c.param_rules.iter() {
+            let rule = match rule.as_str() {
+                "identifier" => RemoteExecParamRule::Identifier,
+                "host" => RemoteExecParamRule::Host,
+                "ip" => RemoteExecParamRule::Ip,
+                "path" => RemoteExecParamRule::Path,
+                "integer" => RemoteExecParamRule::Integer,
+                r => match r.strip_prefix("regex:") {
+                    Some(re) => RemoteExecParamRule::Regex(re.to_owned()),
+                    None => return Err(format!("unknown rule \"{}\" of \"{}\"", r, name)),
+                },
+            };
+            param_rules.insert(name.clone(), rule);
+        }
+        Ok(Self {
+            cmdline: c.cmdline().to_owned(),
+            params: c.param_names.clone(),
+            output_format: match c.output_format() {
+                pb::OutputFormat::Text => RemoteExecOutputFormat::Text,
+                pb::OutputFormat::Binary => RemoteExecOutputFormat::Binary,
+            },
+            desc: c.desc().to_owned(),
+            full_ns: c.full_ns_supported(),
+            param_rules,
+        })
+    }
+}
+
+// Builtin commands, followed by commands declared in agent config and those pushed by the
+// controller, with ids in this order
+struct CommandRegistry {
+    // initialized on first access
+    builtin: Vec<Command>,
+    builtin_max_param_nums: usize,
+    // config commands for change detection
+    config_source: Vec<RemoteExecCommand>,
+    config: Vec<Command>,
+    config_max_param_nums: usize,
+    controller_version: u64,
+    // SHA-256 of the applied controller commands
+    controller_digest: [u8; digest::SHA256_OUTPUT_LEN],
+    controller: Vec<Command>,
+    controller_max_param_nums: usize,
+    // changed whenever command ids change
+    digest: u64,
+}
+
+impl CommandRegistry {
+    fn get(&self, id: usize) -> Option<&Command> {
+        self.builtin
+            .iter()
+            .chain(self.config.iter())
+            .chain(self.controller.iter())
+            .nth(id)
+    }
+
+    fn conflicted(&self, cmdline: &str) -> bool {
+        self.builtin.iter().any(|b| b.cmdline == cmdline)
+    }
+
+    // Digest of cmdlines in the order of ids
+    fn update_digest(&mut self) {
+        let mut ctx = digest::Context::new(&digest::SHA256);
+        for c in self
+            .builtin
+            .iter()
+            .chain(self.config.iter())
+            .chain(self.controller.iter())
+        {
+            ctx.update(c.cmdline.as_bytes());
+            ctx.update(b"\n");
+        }
+        let mut digest = [0; 8];
+        digest.copy_from_slice(&ctx.finish().as_ref()[..8]);
+        self.digest = u64::from_be_bytes(digest);
+    }
+
+    // Some(false) if the update is the applied one, rejected if older or with the applied
+    // version but other commands, so that signed sets can not be replayed
+    fn applied_controller_version(
+        &self,
+        version: u64,
+        digest: &[u8],
+    ) -> std::result::Result<Option<bool>, String> {
+        if version > self.controller_version {
+            return Ok(None);
+        }
+        if version == self.controller_version && digest == self.controller_digest {
+            return Ok(Some(false));
+        }
+        Err(format!(
+            "rejected controller commands version {} older than or conflicting with applied version {}",
+            version, self.controller_version
+        ))
+    }
+}
+
+static COMMAND_REGISTRY: RwLock<CommandRegistry> = parking_lot::const_rwlock(CommandRegistry {
+    builtin: Vec::new(),
+    builtin_max_param_nums: 0,
+    config_source: Vec::new(),
+    config: Vec::new(),
+    config_max_param_nums: 0,
+    controller_version: 0,
+    controller_digest: [0; digest::SHA256_OUTPUT_LEN],
+    controller: Vec::new(),
+    controller_max_param_nums: 0,
+    digest: 0,
+});
+
+fn registry() -> RwLockReadGuard<'static, CommandRegistry> {
+    {
+        let r = COMMAND_REGISTRY.read();
+        if !r.builtin.is_empty() {
+            return r;
+        }
+    }
+    let mut w = COMMAND_REGISTRY.write();
+    if w.builtin.is_empty() {
+        w.builtin = all_supported_commands();
+        w.builtin_max_param_nums = max_param_nums_of(&w.builtin);
+        w.update_digest();
+    }
+    RwLockWriteGuard::downgrade(w)
+}
+
+fn param_nums(cmdline: &str) -> usize {
+    cmdline
+        .split_whitespace()
+        .filter(|seg| seg.starts_with('$'))
+        .count()
+}
+
+fn max_param_nums_of(commands: &[Command]) -> usize {
+    commands
+        .iter()
+        .map(|c| param_nums(&c.cmdline))
+        .max()
+        .unwrap_or_default()
+}
+
+// Commands already validated on config loading, those conflicting with builtin commands are ignored
+fn update_config_commands(source: &[RemoteExecCommand]) {
+    let commands = {
+        let r = registry();
+        if r.config_source == source {
+            return;
+        }
+        source
+            .iter()
+            .filter(|c| {
+                let conflicted = r.conflicted(&c.cmdline);
+                if conflicted {
+                    warn!(
+                        "ignored config command '{}' conflicting with builtin",
+                        c.cmdline
+                    );
+                }
+                !conflicted
+            })
+            .map(Command::from)
+            .collect::<Vec<_>>()
+    };
+    info!(
+        "config commands updated to {:?}",
+        commands.iter().map(|c| &c.cmdline).collect::<Vec<_>>()
+    );
+    let mut w = COMMAND_REGISTRY.write();
+    w.config_source = source.to_vec();
+    w.config_max_param_nums = max_param_nums_of(&commands);
+    w.config = commands;
+    w.update_digest();
+}
+
+const COMMANDS_SIGNATURE_CONTEXT: &[u8] = b"deepflow remote exec commands v1";
+
+// SHA-256 of the canonical encoding of controller commands, see commands_signature in
+// trident.proto
+fn commands_digest(definitions: &[pb::CommandDefinition]) -> [u8; digest::SHA256_OUTPUT_LEN] {
+    fn put_len(ctx: &mut digest::Context, len: usize) {
+        ctx.update(&(len as u32).to_be_bytes());
+    }
+    fn put_str(ctx: &mut digest::Context, s: &str) {
+        put_len(ctx, s.len());
+        ctx.update(s.as_bytes());
+    }
+
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    put_len(&mut ctx, definitions.len());
+    for d in definitions {
+        put_str(&mut ctx, d.cmdline());
+        put_len(&mut ctx, d.param_names.len());
+        for name in d.param_names.iter() {
+            put_str(&mut ctx, name);
+        }
+        ctx.update(&(d.output_format() as u32).to_be_bytes());
+        put_str(&mut ctx, d.desc());
+        ctx.update(&[d.full_ns_supported() as u8]);
+        put_len(&mut ctx, d.param_rules.len());
+        for (name, rule) in d.param_rules.iter().collect::<BTreeMap<_, _>>() {
+            put_str(&mut ctx, name);
+            put_str(&mut ctx, rule);
+        }
+    }
+    let mut result = [0; digest::SHA256_OUTPUT_LEN];
+    result.copy_from_slice(ctx.finish().as_ref());
+    result
+}
+
+// Message signed by the controller for a version of commands
+fn commands_signed_message(version: u64, digest: &[u8]) -> Vec<u8> {
+    let mut message = COMMANDS_SIGNATURE_CONTEXT.to_vec();
+    message.extend_from_slice(&version.to_be_bytes());
+    message.extend_from_slice(digest);
+    message
+}
+
+// Replaces commands pushed by the controller, returns false if the update is the applied one
+fn update_controller_commands(
+    version: u64,
+    definitions: &[pb::CommandDefinition],
+    signature: &[u8],
+    public_key: &[u8],
+) -> std::result::Result<bool, String> {
+    let digest = commands_digest(definitions);
+    if UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(&commands_signed_message(version, &digest), signature)
+        .is_err()
+    {
+        return Err(format!(
+            "rejected controller commands version {} with invalid signature",
+            version
+        ));
+    }
+    let mut source = Vec::with_capacity(definitions.len());
+    for (i, d) in definitions.iter().enumerate() {
+        source.push(
+            RemoteExecCommand::try_from(d).map_err(|e| {
+                format!("rejected controller command {} '{}': {}", i, d.cmdline(), e)
+            })?,
+        );
+    }
+    let r = registry();
+    if let Some(applied) = r.applied_controller_version(version, &digest)? {
+        return Ok(applied);
+    }
+    for (i, c) in source.iter().enumerate() {
+        if let Err(e) = c.validate() {
+            return Err(format!(
+                "rejected controller command {} '{}': {}",
+                i, c.cmdline, e
+            ));
+        }
+        if r.conflicted(&c.cmdline)
+            || r.config.iter().any(|p| p.cmdline == c.cmdline)
+            || source[..i].iter().any(|p| p.cmdline == c.cmdline)
+        {
+            return Err(format!(
+                "rejected controller command {} '{}' duplicated",
+                i, c.cmdline
+            ));
+        }
+    }
+    drop(r);
+    let commands = source.iter().map(Command::from).collect::<Vec<_>>();
+    info!(
+        "controller commands updated to version {}: {:?}",
+        version,
+        commands.iter().map(|c| &c.cmdline).collect::<Vec<_>>()
+    );
+    let mut w = COMMAND_REGISTRY.write();
+    // checked again in case of concurrent updates
+    if let Some(applied) = w.applied_controller_version(version, &digest)? {
+        return Ok(applied);
+    }
+    w.controller_version = version;
+    w.controller_digest = digest;
+    w.controller_max_param_nums = max_param_nums_of(&commands);
+    w.controller = commands;
+    w.update_digest();
+    Ok(true)
+}
+
+fn get_cmd(id: usize) -> Option<Command> {
+    registry().get(id).cloned()
+}
+
+// Requests with command ids listed before commands changed are rejected
+fn check_registry_digest(digest: Option<u64>) -> std::result::Result<(), String> {
+    let current = registry().digest;
+    match digest {
+        Some(digest) if digest != current => Err(format!(
+            "rejected request with registry digest {:016x} of changed commands, current {:016x}",
+            digest, current
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn max_param_nums() -> usize {
+    let r = registry();
+    r.builtin_max_param_nums
+        .max(r.config_max_param_nums)
+        .max(r.controller_max_param_nums)
+}
+
+// Commands listed with the registry digest of their ids
+fn remote_commands() -> (Vec<pb::RemoteCommand>, u64) {
+    let r = registry();
+    let commands = r
+        .builtin
+        .iter()
+        .chain(r.config.iter())
+        .chain(r.controller.iter())
+        .enumerate()
+        .map(|(id, c)| pb::RemoteCommand {
+            id: Some(id as u32),
+            cmd: if c.desc.is_empty() {
+                Some(c.cmdline.to_string())
+            } else {
+                Some(c.desc.to_string())
+            },
+            param_names: c
+                .cmdline
+                .split_whitespace()
+                .filter_map(|seg| seg.strip_prefix('$').map(|name| name.to_owned()))
+                .collect(),
+            output_format: match c.output_format {
+                OutputFormat::Text => Some(pb::OutputFormat::Text as i32),
+                OutputFormat::Binary => Some(pb::OutputFormat::Binary as i32),
+            },
+            cmd_type: match c.command_type {
+                CommandType::Linux => Some(pb::CommandType::Linux as i32),
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                CommandType::Kubernetes(_) => Some(pb::CommandType::Kubernetes as i32),
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                CommandType::Container(_) => Some(pb::CommandType::Container as i32),
+            },
+            full_ns_supported: Some(c.full_ns),
+        })
+        .collect();
+    (commands, r.digest)
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("command `{0}` execution failed")]
+    CmdExecFailed(#[from] std::io::Error),
+    #[error("command `{0}` failed with code {1:?}")]
+    CmdFailed(String, Option<i32>),
+    #[error("param `{0}` not found")]
+    ParamNotFound(String),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[error("kubernetes failed with {0}")]
+    KubeError(#[from] kube::Error),
+    #[error("serialize failed with {0}")]
+    SerializeError(#[from] serde_json::Error),
+    #[error("transparent")]
+    SyscallFailed(String),
+    #[error("self profile failed with {0}")]
+    SelfProfileFailed(String),
+    #[error("command `{0}` is not allowed in container")]
+    ExecNotAllowed(String),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[error("cri failed with {0}")]
+    CriError(#[from] cri::Error),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[error("docker failed with {0}")]
+    DockerError(#[from] bollard::errors::Error),
+    #[error("kubernetes config failed with {0}")]
+    KubeConfigError(String),
+}
+
+pub(super) type Result<T> = std::result::Result<T, Error>;
+
+// Exponential backoff with jitter between reconnections, so that agents don't reconnect to
+// the controller all at once after it restarts
+struct Backoff {
+    current: Duration,
+}
+
+impl Backoff {
+    const INITIAL: Duration = Duration::from_secs(1);
+    const MAX: Duration = RPC_RETRY_INTERVAL;
+
+    fn new() -> Self {
+        Self {
+            current: Self::INITIAL,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.current = Self::INITIAL;
+    }
+
+    // Random delay in [current / 2, current], with current doubled up to MAX
+    fn next_delay(&mut self) -> Duration {
+        let max = self.current.as_millis() as u64;
+        let delay = Duration::from_millis(rand::thread_rng().gen_range(max / 2..=max));
+        self.current = (self.current * 2).min(Self::MAX);
+        delay
+    }
+}
+
+// Remote exec activity, commands are counted by outcome when recorded in audit log
+pub struct ExecutorCounter {
+    audit_log: Arc<AuditLog>,
+    // bytes of command results sent
+    tx_bytes: AtomicU64,
+    active_streams: AtomicU64,
+    reconnects: AtomicU64,
+}
+
+impl RefCountable for ExecutorCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        let outcomes = self.audit_log.take_outcomes();
+        let mut counters = vec![(
+            "commands",
+            CounterType::Counted,
+            CounterValue::Unsigned(outcomes.iter().map(|(_, n)| n).sum()),
+        )];
+        for (outcome, n) in outcomes {
+            counters.push((
+                match outcome {
+                    Outcome::Succeeded => "succeeded",
+                    Outcome::Failed => "failed",
+                    Outcome::TimedOut => "timed_out",
+                    Outcome::Rejected => "rejected",
+                    Outcome::Aborted => "aborted",
+                },
+                CounterType::Counted,
+                CounterValue::Unsigned(n),
+            ));
+        }
+        counters.extend([
+            (
+                "tx_bytes",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.tx_bytes.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "active_streams",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.active_streams.load(Ordering::Relaxed)),
+            ),
+            (
+                "reconnects",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.reconnects.swap(0, Ordering::Relaxed)),
+            ),
+        ]);
+        counters
+    }
+}
+
+// Cancelled when the executor stops, to close the stream and kill running commands promptly
+#[derive(Clone)]
+struct CancelToken(watch::Receiver<bool>);
+
+impl CancelToken {
+    fn new() -> (watch::Sender<bool>, Self) {
+        let (sender, receiver) = watch::channel(false);
+        (sender, Self(receiver))
+    }
+
+    // Also cancelled if the sender is dropped
+    async fn cancelled(&self) {
+        let mut receiver = self.0.clone();
+        while !*receiver.borrow() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    // Returns false if cancelled before the duration elapsed
+    async fn sleep(&self, duration: Duration) -> bool {
+        tokio::select! {
+            _ = self.cancelled() => false,
+            _ = tokio::time::sleep(duration) => true,
+        }
+    }
+}
+
+struct Interior {
+    agent_id: Arc<RwLock<AgentId>>,
+    session: Arc<Session>,
+    exc: ExceptionHandler,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    diagnostics: Arc<Diagnostics>,
+    current_config: Arc<ArcSwap<ModuleConfig>>,
+    audit_log: Arc<AuditLog>,
+    resume_buffer: Arc<ResumeBuffer>,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    kube_client: Arc<KubeClient>,
+    packet_capture: Arc<PacketCapture>,
+    counter: Arc<ExecutorCounter>,
+    running: Arc<AtomicBool>,
+    cancel: CancelToken,
+}
+
+impl Interior {
+    async fn run(&mut self) {
+        let mut backoff = Backoff::new();
+        let mut first = true;
+        while self.running.load(Ordering::Relaxed) {
+            if !first {
+                self.counter.reconnects.fetch_add(1, Ordering::Relaxed);
+            }
+            first = false;
+            let (heartbeat, silence_timeout) = {
+                let config = self.current_config.load();
+                let interval = config.yaml_config.remote_exec_heartbeat_interval;
+                let misses = config.yaml_config.remote_exec_heartbeat_misses;
+                (interval, (misses > 0).then(|| interval * misses))
+            };
+            let (sender, receiver) = mpsc::channel(1);
+            let responser = Responser::new(self, heartbeat, receiver);
+
+            self.session.update_current_server().await;
+            let session_version = self.session.get_version();
+            let client = match self.session.get_client() {
+                Some(c) => c,
+                None => {
+                    self.session.set_request_failed(true);
+                    self.cancel.sleep(backoff.next_delay()).await;
+                    continue;
+                }
+            };
+            let mut client = pb::synchronizer_client::SynchronizerClient::new(client);
+
+            let now = Instant::now();
+            trace!("remote_execute call");
+
+            let result = tokio::select! {
+                _ = self.cancel.cancelled() => break,
+                r = client.remote_execute(responser) => r,
+            };
+            let mut stream = match result {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("remote_execute failed: {:?}", e);
+                    self.exc.set(pb::Exception::ControllerSocketError);
+                    self.cancel.sleep(backoff.next_delay()).await;
+                    continue;
+                }
+            }
+            .into_inner();
+            trace!("remote_execute initial receive");
+            debug!("remote_execute latency {:?}ms", now.elapsed().as_millis());
+            backoff.reset();
+            self.counter.active_streams.fetch_add(1, Ordering::Relaxed);
+
+            let silence = silence_timeout.unwrap_or_default();
+            while self.running.load(Ordering::Relaxed) {
+                let message = tokio::select! {
+                    _ = self.cancel.cancelled() => break,
+                    // the server replies to heartbeats, no message for a long time means the
+                    // connection is half-open and will not recover by itself
+                    _ = tokio::time::sleep(silence), if silence_timeout.is_some() => {
+                        warn!("remote_execute received nothing in {:?}, reconnecting", silence);
+                        self.exc.set(pb::Exception::ControllerSocketError);
+                        break;
+                    }
+                    m = stream.message() => m,
+                };
+                let message = match message {
+                    Ok(Some(message)) => message,
+                    Ok(None) => {
+                        debug!("server closed stream");
+                        break;
+                    }
+                    Err(e) => {
+                        warn!("remote_execute failed: {:?}", e);
+                        self.exc.set(pb::Exception::ControllerSocketError);
+                        break;
+                    }
+                };
+                if session_version != self.session.get_version() {
+                    info!("grpc server changed");
+                    break;
+                }
+                if message.exec_type.is_none() {
+                    continue;
+                }
+                match pb::ExecutionType::from_i32(message.exec_type.unwrap()) {
+                    Some(t) => debug!("received {:?} command from server", t),
+                    None => {
+                        warn!(
+                            "unsupported remote exec type id {}",
+                            message.exec_type.unwrap()
+                        );
+                        continue;
+                    }
+                }
+                if sender.send(message).await.is_err() {
+                    debug!("responser channel closed");
+                    break;
+                }
+            }
+            self.counter.active_streams.fetch_sub(1, Ordering::Relaxed);
+            // streams of all agents end together when the controller restarts, spread the
+            // reconnections however the stream ended
+            self.cancel.sleep(backoff.next_delay()).await;
+        }
+    }
+}
+
+pub struct Executor {
+    agent_id: Arc<RwLock<AgentId>>,
+    session: Arc<Session>,
+    runtime: Arc<Runtime>,
+    exc: ExceptionHandler,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    diagnostics: Arc<Diagnostics>,
+    current_config: Arc<ArcSwap<ModuleConfig>>,
+    audit_log: Arc<AuditLog>,
+    // kept across reconnections
+    resume_buffer: Arc<ResumeBuffer>,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    kube_client: Arc<KubeClient>,
+    packet_capture: Arc<PacketCapture>,
+    counter: Arc<ExecutorCounter>,
+
+    running: Arc<AtomicBool>,
+    cancel_tx: Mutex<Option<watch::Sender<bool>>>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Executor {
+    pub fn new(
+        agent_id: Arc<RwLock<AgentId>>,
+        session: Arc<Session>,
+        runtime: Arc<Runtime>,
+        exc: ExceptionHandler,
+        #[cfg(any(target_os = "linux", target_os = "android"))] diagnostics: Arc<Diagnostics>,
+        current_config: Arc<ArcSwap<ModuleConfig>>,
+        packet_capture: Arc<PacketCapture>,
+        stats_collector: &stats::Collector,
+    ) -> Self {
+        let audit_log = Arc::new(AuditLog::default());
+        let counter = Arc::new(ExecutorCounter {
+            audit_log: audit_log.clone(),
+            tx_bytes: Default::default(),
+            active_streams: Default::default(),
+            reconnects: Default::default(),
+        });
+        stats_collector.register_countable(
+            &stats::NoTagModule("remote_exec"),
+            Countable::Ref(Arc::downgrade(&counter) as Weak<dyn RefCountable>),
+        );
+        Self {
+            agent_id,
+            session,
+            runtime,
+            exc,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            diagnostics,
+            current_config,
+            audit_log,
+            resume_buffer: Default::default(),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            kube_client: Default::default(),
+            packet_capture,
+            counter,
+            running: Default::default(),
+            cancel_tx: Default::default(),
+            thread: Default::default(),
+        }
+    }
+
+    pub fn start(&self) {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let (cancel_tx, cancel) = CancelToken::new();
+        self.cancel_tx.lock().replace(cancel_tx);
+        let mut interior = Interior {
+            agent_id: self.agent_id.clone(),
+            session: self.session.clone(),
+            exc: self.exc.clone(),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            diagnostics: self.diagnostics.clone(),
+            current_config: self.current_config.clone(),
+            audit_log: self.audit_log.clone(),
+            resume_buffer: self.resume_buffer.clone(),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            kube_client: self.kube_client.clone(),
+            packet_capture: self.packet_capture.clone(),
+            counter: self.counter.clone(),
+            running: self.running.clone(),
+            cancel,
+        };
+        self.thread.lock().replace(self.runtime.spawn(async move {
+            interior.run().await;
+        }));
+        info!("Started remote executor");
+    }
+
+    // Closes the stream and kills running commands, returns after the executor task ends
+    pub fn stop(&self) {
+        if !self.running.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        if let Some(cancel_tx) = self.cancel_tx.lock().take() {
+            let _ = cancel_tx.send(true);
+        }
+        if let Some(t) = self.thread.lock().take() {
+            let _ = self.runtime.block_on(t);
+        }
+        info!("Stopped remote executor");
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+enum Phase {
+    Running = 0,
+    Connecting = 1,
+    Fetching = 2,
+}
+
+impl Phase {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::Connecting => "connecting",
+            Self::Fetching => "fetching",
+        }
+    }
+}
+
+impl From<u8> for Phase {
+    fn from(v: u8) -> Self {
+        match v {
+            1 => Self::Connecting,
+            2 => Self::Fetching,
+            _ => Self::Running,
+        }
+    }
+}
+
+// Progress of a pending command updated by the command future
+#[derive(Default)]
+struct Progress {
+    phase: AtomicU8,
+    bytes: AtomicU64,
+}
+
+impl Progress {
+    fn set_phase(&self, phase: Phase) {
+        self.phase.store(phase as u8, Ordering::Relaxed);
+    }
+
+    fn add_bytes(&self, n: usize) {
+        self.bytes.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+// Results of the same command in different network namespaces are cached separately
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct CacheKey {
+    id: usize,
+    params: Vec<(String, String)>,
+    // inode of the network namespace, 0 for the namespace of agent
+    netns: u64,
+}
+
+struct CachedResult {
+    stdout: Vec<u8>,
+    cached_at: Instant,
+}
+
+// Results of idempotent commands, so that diagnostics polled by dashboards every few seconds
+// do not fork processes repeatedly
+#[derive(Default)]
+struct ResultCache {
+    commands: HashMap<CacheKey, CachedResult>,
+    namespaces: Option<(Vec<pb::LinuxNamespace>, Instant)>,
+}
+
+impl ResultCache {
+    fn ttl(seconds: Option<u32>) -> Duration {
+        Duration::from_secs(seconds.unwrap_or_default() as u64).min(MAX_RESULT_CACHE_TTL)
+    }
+
+    fn get(&self, key: &CacheKey, ttl: Duration) -> Option<&[u8]> {
+        self.commands
+            .get(key)
+            .filter(|r| r.cached_at.elapsed() < ttl)
+            .map(|r| r.stdout.as_slice())
+    }
+
+    fn insert(&mut self, key: CacheKey, stdout: Vec<u8>) {
+        self.commands
+            .retain(|_, r| r.cached_at.elapsed() < MAX_RESULT_CACHE_TTL);
+        if self.commands.len() >= MAX_CACHED_RESULTS && !self.commands.contains_key(&key) {
+            let oldest = self
+                .commands
+                .iter()
+                .min_by_key(|(_, r)| r.cached_at)
+                .map(|(k, _)| k.clone());
+            if let Some(k) = oldest {
+                self.commands.remove(&k);
+            }
+        }
+        self.commands.insert(
+            key,
+            CachedResult {
+                stdout,
+                cached_at: Instant::now(),
+            },
+        );
+    }
+
+    fn get_namespaces(&self, ttl: Duration) -> Option<&Vec<pb::LinuxNamespace>> {
+        self.namespaces
+            .as_ref()
+            .filter(|(_, cached_at)| cached_at.elapsed() < ttl)
+            .map(|(ns, _)| ns)
+    }
+
+    fn insert_namespaces(&mut self, namespaces: Vec<pb::LinuxNamespace>) {
+        self.namespaces = Some((namespaces, Instant::now()));
+    }
+}
+
+// Output of completed commands keyed by request id and batch index, so that results broken by
+// reconnection can be resumed
+#[derive(Default)]
+struct ResumeBuffer {
+    results: Mutex<HashMap<(u64, Option<u32>), (Arc<[u8]>, Instant)>>,
+}
+
+impl ResumeBuffer {
+    fn insert(&self, request_id: u64, batch_index: Option<u32>, output: Vec<u8>) {
+        let mut results = self.results.lock();
+        results.retain(|_, (_, completed)| completed.elapsed() < RESUME_RESULT_TTL);
+        results.remove(&(request_id, batch_index));
+        let mut size = results.values().map(|(o, _)| o.len()).sum::<usize>();
+        while size + output.len() > MAX_RESUME_BUFFER_SIZE {
+            let Some(oldest) = results
+                .iter()
+                .min_by_key(|(_, (_, completed))| *completed)
+                .map(|(k, _)| *k)
+            else {
+                break;
+            };
+            if let Some((o, _)) = results.remove(&oldest) {
+                size -= o.len();
+            }
+        }
+        results.insert((request_id, batch_index), (output.into(), Instant::now()));
+    }
+
+    fn get(&self, request_id: u64, batch_index: Option<u32>) -> Option<Arc<[u8]>> {
+        self.results
+            .lock()
+            .get(&(request_id, batch_index))
+            .filter(|(_, completed)| completed.elapsed() < RESUME_RESULT_TTL)
+            .map(|(o, _)| o.clone())
+    }
+}
+
+struct PendingCommand {
+    request_id: Option<u64>,
+    batch_index: Option<u32>,
+    // kept as ids of config commands may change while running
+    cmdline: Cow<'static, str>,
+    started: Instant,
+    progress: Arc<Progress>,
+    // first tick after `PROGRESS_INTERVAL`
+    progress_ticker: Interval,
+    cipher: Option<ResultCipher>,
+    // cache output on success if set
+    cache_key: Option<CacheKey>,
+    future: BoxFuture<'static, Result<Output>>,
+    // stdout of the spawned child or exec in container, streamed as it arrives and set to None
+    // on EOF
+    stdout: Option<Pin<Box<dyn AsyncRead + Send>>>,
+    // result of the future, kept until stdout is drained
+    output: Option<Result<Output>>,
+    // streamed stdout kept for the result cache
+    cached_stdout: Vec<u8>,
+    timeout: Duration,
+    // the command is dropped and its child killed when fired
+    deadline: Option<Pin<Box<Sleep>>>,
+    // written to audit log when the command is dropped
+    audit: Option<AuditGuard>,
+    // stdout bytes allowed, 0 for unlimited
+    max_output: u64,
+    // truncate stdout at max_output instead of failing
+    truncate_output: bool,
+    // streamed stdout truncated and no longer read
+    truncated: bool,
+    compress: bool,
+}
+
+impl PendingCommand {
+    fn new(
+        request_id: Option<u64>,
+        batch_index: Option<u32>,
+        cmdline: Cow<'static, str>,
+        progress: Arc<Progress>,
+        cipher: Option<ResultCipher>,
+        cache_key: Option<CacheKey>,
+        future: BoxFuture<'static, Result<Output>>,
+    ) -> Self {
+        Self {
+            request_id,
+            batch_index,
+            cmdline,
+            started: Instant::now(),
+            progress,
+            progress_ticker: time::interval_at(
+                time::Instant::now() + PROGRESS_INTERVAL,
+                PROGRESS_INTERVAL,
+            ),
+            cipher,
+            cache_key,
+            future,
+            stdout: None,
+            output: None,
+            cached_stdout: vec![],
+            timeout: Duration::ZERO,
+            deadline: None,
+            audit: None,
+            max_output: 0,
+            truncate_output: false,
+            truncated: false,
+            compress: false,
+        }
+    }
+
+    fn with_stdout<R: AsyncRead + Send + 'static>(mut self, stdout: R) -> Self {
+        self.stdout = Some(Box::pin(stdout));
+        self
+    }
+
+    fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self.deadline = Some(Box::pin(time::sleep(timeout)));
+        self
+    }
+
+    fn with_audit(mut self, audit: AuditGuard) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    fn with_max_output(mut self, max_output: u64) -> Self {
+        self.max_output = max_output;
+        self
+    }
+
+    fn with_truncation(mut self, truncate_output: bool) -> Self {
+        self.truncate_output = truncate_output;
+        self
+    }
+
+    fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    fn output_exceeded(&self, bytes: u64) -> bool {
+        self.max_output > 0 && bytes > self.max_output
+    }
+
+    // Stdout bytes collected
+    fn bytes(&self, output: &Output) -> u64 {
+        self.progress
+            .bytes
+            .load(Ordering::Relaxed)
+            .max(output.stdout.len() as u64)
+    }
+
+    fn timed_out(&mut self, ctx: &mut Context<'_>) -> bool {
+        self.deadline
+            .as_mut()
+            .map(|d| d.as_mut().poll(ctx).is_ready())
+            .unwrap_or_default()
+    }
+
+    fn progress(&self) -> pb::CommandProgress {
+        pb::CommandProgress {
+            phase: Some(
+                Phase::from(self.progress.phase.load(Ordering::Relaxed))
+                    .as_str()
+                    .to_owned(),
+            ),
+            elapsed_ms: Some(self.started.elapsed().as_millis() as u64),
+            bytes_collected: Some(self.progress.bytes.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+// Namespaces a command is executed in
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ExecNs {
+    Agent,
+    // network namespace of the process
+    Net(u32),
+    // network, uts, pid and mount namespaces of the process
+    Full(u32),
+    // namespaces of the process selected by bit mask of FULL_NS_TYPES
+    Selected(u32, u8),
+}
+
+impl ExecNs {
+    // namespace types in the order they are entered, mount namespace is the last as
+    // entering it changes the root directory
+    const FULL_NS_TYPES: [&'static str; 4] = ["net", "uts", "pid", "mnt"];
+
+    fn new(pid: Option<u32>, full: bool) -> Self {
+        match pid {
+            Some(pid) if pid != process::id() && full => Self::Full(pid),
+            Some(pid) if pid != process::id() => Self::Net(pid),
+            _ => Self::Agent,
+        }
+    }
+
+    // Selects namespaces by name if not empty
+    fn select(
+        pid: Option<u32>,
+        full: bool,
+        ns_types: &[String],
+    ) -> std::result::Result<Self, String> {
+        let mut mask = 0u8;
+        for name in ns_types {
+            match Self::FULL_NS_TYPES.iter().position(|t| t == name) {
+                Some(i) => mask |= 1 << i,
+                None => {
+                    return Err(format!(
+                        "unsupported namespace type {}, expected one of {:?}",
+                        name,
+                        Self::FULL_NS_TYPES
+                    ))
+                }
+            }
+        }
+        let all = (1 << Self::FULL_NS_TYPES.len()) - 1;
+        Ok(match Self::new(pid, full) {
+            Self::Net(pid) | Self::Full(pid) if mask == 1 => Self::Net(pid),
+            Self::Net(pid) | Self::Full(pid) if mask == all => Self::Full(pid),
+            Self::Net(pid) | Self::Full(pid) if mask != 0 => Self::Selected(pid, mask),
+            ns => ns,
+        })
+    }
+
+    // Namespaces entered in the forked child
+    fn ns_types(&self) -> impl Iterator<Item = &'static str> {
+        let mask = match self {
+            Self::Full(_) => u8::MAX,
+            Self::Selected(_, mask) => *mask,
+            _ => 0,
+        };
+        Self::FULL_NS_TYPES
+            .into_iter()
+            .enumerate()
+            .filter(move |(i, _)| mask & 1 << i != 0)
+            .map(|(_, t)| t)
+    }
+}
+
+// Token bucket limiting commands started per minute, with one minute of burst
+struct CommandLimiter {
+    // 0 for unlimited
+    rate: u32,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl CommandLimiter {
+    fn new(rate: u32) -> Self {
+        Self {
+            rate,
+            tokens: rate as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn set_rate(&mut self, rate: u32) {
+        if self.rate != rate {
+            info!("remote exec command rate set to {} per minute", rate);
+            *self = Self::new(rate);
+        }
+    }
+
+    fn acquire(&mut self, n: u32, now: Instant) -> bool {
+        if self.rate == 0 {
+            return true;
+        }
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.last_refill = now;
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() * self.rate as f64 / 60.0).min(self.rate as f64);
+        if self.tokens < n as f64 {
+            return false;
+        }
+        self.tokens -= n as f64;
+        true
+    }
+}
+
+// Sub-commands of a RUN_BATCH request waiting to be executed
+struct PendingBatch {
+    request_id: Option<u64>,
+    stop_on_error: bool,
+    exec_ns: ExecNs,
+    result_public_key: Option<Vec<u8>>,
+    result_cache_ttl: Option<u32>,
+    // timeout of each sub-command
+    command_timeout: Duration,
+    compress_result: bool,
+    commands: VecDeque<pb::BatchCommand>,
+    next_index: u32,
+    // a sub-command failed
+    failed: bool,
+}
+
+// Digest of plain text output in the algorithm selected by the request
+#[derive(Clone)]
+enum ResultDigest {
+    Md5(Md5),
+    Sha256(digest::Context),
+}
+
+impl Default for ResultDigest {
+    fn default() -> Self {
+        Self::Md5(Md5::new())
+    }
+}
+
+impl ResultDigest {
+    fn new(algorithm: pb::DigestAlgorithm) -> Self {
+        match algorithm {
+            pb::DigestAlgorithm::Md5 => Self::Md5(Md5::new()),
+            pb::DigestAlgorithm::Sha256 => Self::Sha256(digest::Context::new(&digest::SHA256)),
+        }
+    }
+
+    fn algorithm(&self) -> pb::DigestAlgorithm {
+        match self {
+            Self::Md5(_) => pb::DigestAlgorithm::Md5,
+            Self::Sha256(_) => pb::DigestAlgorithm::Sha256,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Md5(d) => d.update(data),
+            Self::Sha256(d) => d.update(data),
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.algorithm());
+    }
+
+    // Sets digest of the last segment and resets
+    fn finish(&mut self, result: &mut pb::CommandResult) {
+        let digest = std::mem::replace(self, Self::new(self.algorithm()));
+        let (name, hex_digest) = match digest {
+            Self::Md5(d) => {
+                let hex_digest = format!("{:x}", d.finalize());
+                result.md5 = Some(hex_digest.clone());
+                ("md5", hex_digest)
+            }
+            Self::Sha256(d) => ("sha256", hex::encode(d.finish())),
+        };
+        result.digest = Some(hex_digest);
+        result.digest_algorithm = Some(name.to_owned());
+    }
+}
+
+#[derive(Default)]
+struct CommandResult {
+    request_id: Option<u64>,
+    batch_index: Option<u32>,
+
+    errno: i32,
+    output: VecDeque<u8>,
+    // length of buffered output, None if output is streamed
+    total_len: Option<usize>,
+    // output of a running command is being streamed
+    streaming: bool,
+    sent_len: usize,
+    pkt_count: u32,
+    // segments acked by the server with flow control
+    acked: u32,
+    // output truncated at max output
+    truncated: bool,
+    // kept across commands, changed by requests
+    digest: ResultDigest,
+    cipher: Option<ResultCipher>,
+    // zstd compress content of each segment
+    compress: bool,
+    // start of the output resumed, 0 if not resuming
+    offset: usize,
+    // plain text output sent, moved to resume buffer after the last segment
+    keep: bool,
+    kept: Vec<u8>,
+    resume_buffer: Arc<ResumeBuffer>,
+}
+
+impl CommandResult {
+    fn start(
+        &mut self,
+        request_id: Option<u64>,
+        batch_index: Option<u32>,
+        total_len: Option<usize>,
+        cipher: Option<ResultCipher>,
+        compress: bool,
+    ) {
+        self.request_id = request_id;
+        self.batch_index = batch_index;
+        self.errno = 0;
+        self.total_len = total_len;
+        self.streaming = total_len.is_none();
+        self.sent_len = 0;
+        self.pkt_count = 0;
+        self.acked = 0;
+        self.truncated = false;
+        self.digest.reset();
+        self.cipher = cipher;
+        self.compress = compress;
+        self.offset = 0;
+        self.keep = request_id.is_some();
+        self.kept.clear();
+    }
+
+    // Sends kept output of a completed command from offset
+    fn resume(
+        &mut self,
+        request_id: Option<u64>,
+        batch_index: Option<u32>,
+        output: &[u8],
+        offset: usize,
+        cipher: Option<ResultCipher>,
+        compress: bool,
+    ) {
+        self.start(
+            request_id,
+            batch_index,
+            Some(output.len()),
+            cipher,
+            compress,
+        );
+        self.offset = offset;
+        self.keep = false;
+        self.digest.update(&output[..offset]);
+        self.output = output[offset..].to_vec().into();
+    }
+
+    fn ack(&mut self, request_id: Option<u64>, batch_index: Option<u32>, pkt_count: u32) {
+        if request_id != self.request_id || batch_index != self.batch_index {
+            debug!(
+                "ignored ack of request {:?} batch {:?}, sending {:?} batch {:?}",
+                request_id, batch_index, self.request_id, self.batch_index
+            );
+            return;
+        }
+        // segments not sent can not be acked
+        self.acked = self.acked.max(pkt_count.min(self.pkt_count));
+    }
+
+    // Segments sent but not acked reach the window, 0 for unlimited
+    fn window_full(&self, window: u32) -> bool {
+        window > 0 && self.pkt_count - self.acked >= window
+    }
+
+    // Drops output not sent yet
+    fn abort(&mut self) {
+        self.output.clear();
+        self.streaming = false;
+        self.cipher = None;
+        self.keep = false;
+        self.kept = vec![];
+    }
+
+    fn next_batch(&mut self, batch_len: usize) -> Option<pb::CommandResult> {
+        // streamed output is sent in full batches with remaining bytes left for the last segment,
+        // which is sent after the command exits
+        if self.output.is_empty() || self.streaming && self.output.len() <= batch_len {
+            return None;
+        }
+
+        let mut pb_result = pb::CommandResult {
+            errno: Some(self.errno),
+            batch_index: self.batch_index,
+            pkt_index: Some(self.pkt_count),
+            ..Default::default()
+        };
+        let last = !self.streaming && self.output.len() <= batch_len;
+        let content = if last {
+            self.output.drain(..).collect::<Vec<_>>()
+        } else {
+            self.output.drain(..batch_len).collect::<Vec<_>>()
+        };
+        self.sent_len += content.len();
+        self.pkt_count += 1;
+        match self.total_len {
+            Some(total_len) => {
+                pb_result.total_len = Some(total_len as u64);
+                pb_result.pkt_count =
+                    Some(((total_len - self.offset).saturating_sub(1) / batch_len + 1) as u32);
+            }
+            // only known in the last segment of streamed output
+            None if last => {
+                pb_result.total_len = Some(self.sent_len as u64);
+                pb_result.pkt_count = Some(self.pkt_count);
+            }
+            None => (),
+        }
+        self.digest.update(&content[..]);
+        if self.keep {
+            if self.kept.len() + content.len() > MAX_RESUME_RESULT_LEN {
+                self.keep = false;
+                self.kept = vec![];
+            } else {
+                self.kept.extend_from_slice(&content);
+            }
+        }
+        if last {
+            self.digest.finish(&mut pb_result);
+            if self.truncated {
+                pb_result.truncated = Some(true);
+            }
+            if let (true, Some(request_id)) = (self.keep, self.request_id) {
+                self.keep = false;
+                let kept = std::mem::take(&mut self.kept);
+                self.resume_buffer
+                    .insert(request_id, self.batch_index, kept);
+            }
+        }
+        let content = if self.compress {
+            match zstd::bulk::compress(&content[..], zstd::DEFAULT_COMPRESSION_LEVEL) {
+                Ok(compressed) if compressed.len() < content.len() => {
+                    pb_result.compressed = Some(true);
+                    compressed
+                }
+                Ok(_) => content,
+                Err(e) => {
+                    debug!("compress command result failed: {}", e);
+                    content
+                }
+            }
+        } else {
+            content
+        };
+        match self.cipher.as_mut() {
+            Some(cipher) => match cipher.seal(content) {
+                Ok(sealed) => {
+                    pb_result.encryption_public_key = Some(cipher.public_key().to_vec());
+                    pb_result.content = Some(sealed);
+                }
+                Err(e) => {
+                    // never fallback to plain text
+                    warn!("{}, drop remaining output", e);
+                    self.abort();
+                    return Some(pb::CommandResult {
+                        errno: Some(-1),
+                        batch_index: self.batch_index,
+                        ..Default::default()
+                    });
+                }
+            },
+            None => pb_result.content = Some(content),
+        }
+        if last {
+            self.cipher = None;
+        }
+        Some(pb_result)
+    }
+}
+
+struct Responser {
+    agent_id: Arc<RwLock<AgentId>>,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    diagnostics: Arc<Diagnostics>,
+    current_config: Arc<ArcSwap<ModuleConfig>>,
+    audit_log: Arc<AuditLog>,
+    batch_len: usize,
+    // unacked segments of result allowed, 0 to disable flow control
+    result_window: u32,
+
+    heartbeat: Interval,
+    msg_recv: Receiver<pb::RemoteExecRequest>,
+
+    // request id -> future
+    pending_lsns: HashMap<Option<u64>, BoxFuture<'static, Result<Vec<pb::LinuxNamespace>>>>,
+    // request id -> future
+    pending_checks: HashMap<Option<u64>, BoxFuture<'static, pb::CommandCheck>>,
+
+    pending_command: Option<PendingCommand>,
+    pending_batch: Option<PendingBatch>,
+    result: CommandResult,
+    result_cache: ResultCache,
+    limiter: CommandLimiter,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    kube_client: Arc<KubeClient>,
+    packet_capture: Arc<PacketCapture>,
+    counter: Arc<ExecutorCounter>,
+    // ready when the executor stops
+    cancelled: BoxFuture<'static, ()>,
+}
+
+impl Responser {
+    fn new(
+        interior: &Interior,
+        heartbeat: Duration,
+        receiver: Receiver<pb::RemoteExecRequest>,
+    ) -> Self {
+        let cancel = interior.cancel.clone();
+        Responser {
+            agent_id: interior.agent_id.clone(),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            diagnostics: interior.diagnostics.clone(),
+            current_config: interior.current_config.clone(),
+            audit_log: interior.audit_log.clone(),
+            batch_len: pb::RemoteExecRequest::default().batch_len() as usize,
+            result_window: 0,
+            heartbeat: time::interval(heartbeat),
+            msg_recv: receiver,
+            pending_lsns: HashMap::new(),
+            pending_checks: HashMap::new(),
+            pending_command: None,
+            pending_batch: None,
+            result: CommandResult {
+                resume_buffer: interior.resume_buffer.clone(),
+                ..Default::default()
+            },
+            result_cache: ResultCache::default(),
+            limiter: CommandLimiter::new(0),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            kube_client: interior.kube_client.clone(),
+            packet_capture: interior.packet_capture.clone(),
+            counter: interior.counter.clone(),
+            cancelled: Box::pin(async move { cancel.cancelled().await }),
+        }
+    }
+
+    fn generate_result_batch(&mut self) -> Option<pb::CommandResult> {
+        if self.result.window_full(self.result_window) {
+            return None;
+        }
+        self.result.next_batch(self.batch_len)
+    }
+
+    fn audit(
+        &self,
+        request_id: Option<u64>,
+        batch_index: Option<u32>,
+        command_id: Option<u32>,
+        params: &[pb::Parameter],
+        exec_ns: ExecNs,
+    ) -> AuditGuard {
+        let params: BTreeMap<String, String> = params
+            .iter()
+            .map(|p| {
+                (
+                    p.key.clone().unwrap_or_default(),
+                    p.value.clone().unwrap_or_default(),
+                )
+            })
+            .collect();
+        let cmdline = command_id
+            .and_then(|id| get_cmd(id as usize))
+            .map(|c| {
+                c.cmdline
+                    .split_whitespace()
+                    .map(|arg| {
+                        arg.strip_prefix('$')
+                            .and_then(|name| params.get(name))
+                            .map(|v| v.as_str())
+                            .unwrap_or(arg)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .unwrap_or_default();
+        let (ns_pid, ns_types) = match exec_ns {
+            ExecNs::Agent => (None, vec![]),
+            ExecNs::Net(pid) => (Some(pid), vec!["net"]),
+            ExecNs::Full(pid) | ExecNs::Selected(pid, _) => {
+                (Some(pid), exec_ns.ns_types().collect())
+            }
+        };
+        AuditGuard::new(
+            self.audit_log.clone(),
+            AuditRecord {
+                request_id,
+                batch_index,
+                command_id,
+                cmdline,
+                params,
+                ns_pid,
+                ns_types,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn command_timeout(&self, requested: Option<u32>) -> Duration {
+        command_timeout(
+            requested,
+            self.current_config
+                .load()
+                .yaml_config
+                .remote_exec_command_timeout,
+        )
+    }
+
+    // Reads stdout of the pending command until more than a batch is buffered or no data
+    // is available
+    fn poll_stdout(&mut self, ctx: &mut Context<'_>) -> std::io::Result<()> {
+        let Responser {
+            pending_command,
+            result,
+            batch_len,
+            ..
+        } = self;
+        let Some(pending) = pending_command.as_mut() else {
+            return Ok(());
+        };
+        if pending.stdout.is_none() {
+            return Ok(());
+        }
+        if !result.streaming {
+            result.start(
+                pending.request_id,
+                pending.batch_index,
+                None,
+                pending.cipher.take(),
+                pending.compress,
+            );
+        }
+        let mut buffer = [0u8; READ_BUFFER_SIZE];
+        while result.output.len() <= *batch_len {
+            let Some(stdout) = pending.stdout.as_mut() else {
+                break;
+            };
+            let mut buf = ReadBuf::new(&mut buffer);
+            match stdout.as_mut().poll_read(ctx, &mut buf) {
+                Poll::Pending => break,
+                Poll::Ready(Err(e)) => {
+                    pending.stdout = None;
+                    return Err(e);
+                }
+                Poll::Ready(Ok(())) if buf.filled().is_empty() => pending.stdout = None,
+                Poll::Ready(Ok(())) => {
+                    let mut data = buf.filled();
+                    pending.progress.add_bytes(data.len());
+                    let bytes = pending.progress.bytes.load(Ordering::Relaxed);
+                    if pending.output_exceeded(bytes) {
+                        pending.stdout = None;
+                        if !pending.truncate_output {
+                            return Err(std::io::Error::new(
+                                std::io::ErrorKind::Other,
+                                format!("output exceeds {} bytes", pending.max_output),
+                            ));
+                        }
+                        // the child is stopped by SIGPIPE or broken pipe on further writes
+                        data = &data[..data.len() - (bytes - pending.max_output) as usize];
+                        pending.truncated = true;
+                    }
+                    if pending.cache_key.is_some() {
+                        pending.cached_stdout.extend_from_slice(data);
+                    }
+                    result.output.extend(data);
+                    if pending.truncated {
+                        result.output.extend(TRUNCATED_MARKER);
+                        result.truncated = true;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Drops the running command, output not sent yet is discarded
+    fn drop_pending_command(&mut self) {
+        let dropped = self.pending_command.take().is_some();
+        // output is left only if held back by flow control
+        if dropped && self.result.streaming || !self.result.output.is_empty() {
+            self.result.abort();
+        }
+    }
+
+    fn errmsg_helper<'a, S: Into<Cow<'a, str>>>(
+        &self,
+        request_id: Option<u64>,
+        msg: S,
+    ) -> Poll<Option<pb::RemoteExecResponse>> {
+        let msg: Cow<str> = msg.into();
+        warn!("{}", msg);
+        Poll::Ready(Some(pb::RemoteExecResponse {
+            agent_id: Some(self.agent_id.read().deref().into()),
+            request_id,
+            errmsg: Some(msg.into_owned()),
+            ..Default::default()
+        }))
+    }
+
+    fn command_failed_helper<'a, S: Into<Cow<'a, str>>>(
+        &mut self,
+        request_id: Option<u64>,
+        batch_index: Option<u32>,
+        code: Option<i32>,
+        msg: S,
+    ) -> Poll<Option<pb::RemoteExecResponse>> {
+        Poll::Ready(Some(self.command_failed_response(
+            request_id,
+            batch_index,
+            code,
+            msg,
+        )))
+    }
+
+    fn command_failed_response<'a, S: Into<Cow<'a, str>>>(
+        &mut self,
+        request_id: Option<u64>,
+        batch_index: Option<u32>,
+        code: Option<i32>,
+        msg: S,
+    ) -> pb::RemoteExecResponse {
+        if batch_index.is_some() {
+            if let Some(batch) = self.pending_batch.as_mut() {
+                batch.failed = true;
+            }
+        }
+        let msg: Cow<str> = msg.into();
+        warn!("{}", msg);
+        pb::RemoteExecResponse {
+            agent_id: Some(self.agent_id.read().deref().into()),
+            request_id,
+            errmsg: Some(msg.into_owned()),
+            command_result: Some(pb::CommandResult {
+                errno: code,
+                batch_index,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn start_command(
+        &self,
+        request_id: Option<u64>,
+        batch_index: Option<u32>,
+        command_id: Option<u32>,
+        params: &[pb::Parameter],
+        exec_ns: ExecNs,
+        result_public_key: Option<&Vec<u8>>,
+        result_cache_ttl: Option<u32>,
+    ) -> std::result::Result<PendingCommand, String> {
+        let Some(cmd_id) = command_id else {
+            return Err("command_id not specified".to_owned());
+        };
+        let Some(cmd) = get_cmd(cmd_id as usize) else {
+            return Err("command_id not specified or invalid in run command request".to_owned());
+        };
+        let cmdline: &str = &cmd.cmdline;
+        let limits = self
+            .current_config
+            .load()
+            .yaml_config
+            .remote_exec_limits
+            .get(cmdline)
+            .clone();
+        let params = Params(&params[..params.len().min(max_param_nums())]);
+        if !params.is_valid(&cmd) {
+            return Err(format!(
+                "rejected run command '{}' with invalid params: {:?}",
+                cmdline, params
+            ));
+        }
+
+        let ns_files = NsFiles::open(&cmd, exec_ns)?;
+
+        let cipher = match result_public_key {
+            Some(key) => match ResultCipher::new(key) {
+                Ok(cipher) => Some(cipher),
+                Err(e) => return Err(format!("rejected run command '{}': {}", cmdline, e)),
+            },
+            None => None,
+        };
+
+        let cache_ttl = ResultCache::ttl(result_cache_ttl);
+        let cache_key = match ns_files.cache_netns() {
+            Some(netns) if cmd.cacheable && !cache_ttl.is_zero() => Some(CacheKey {
+                id: cmd_id as usize,
+                params: params
+                    .0
+                    .iter()
+                    .map(|p| {
+                        (
+                            p.key.clone().unwrap_or_default(),
+                            p.value.clone().unwrap_or_default(),
+                        )
+                    })
+                    .collect(),
+                netns,
+            }),
+            _ => None,
+        };
+
+        let progress = Arc::new(Progress::default());
+        if let Some(stdout) = cache_key
+            .as_ref()
+            .and_then(|key| self.result_cache.get(key, cache_ttl))
+        {
+            debug!("run command '{}' returned from cache", cmdline);
+            let output = Output {
+                status: Default::default(),
+                stdout: stdout.to_vec(),
+                stderr: vec![],
+            };
+            return Ok(PendingCommand::new(
+                request_id,
+                batch_index,
+                cmd.cmdline.clone(),
+                progress,
+                cipher,
+                None,
+                Box::pin(future::ready(Ok(output))),
+            ));
+        }
+
+        trace!(
+            "pending run command '{}', ns: {:?}, params: {:?}",
+            cmdline,
+            exec_ns,
+            params
+        );
+
+        let pending = |future: BoxFuture<'static, Result<Output>>| {
+            PendingCommand::new(
+                request_id,
+                batch_index,
+                cmd.cmdline.clone(),
+                progress.clone(),
+                cipher,
+                cache_key,
+                future,
+            )
+            .with_max_output(limits.max_output << 20)
+            .with_truncation(limits.truncate_output)
+        };
+        self.spawn_command(&cmd, &params, ns_files, &limits, &progress, pending)
+    }
+
+    fn fetch_file(
+        &self,
+        request_id: Option<u64>,
+        path: &str,
+        result_public_key: Option<&Vec<u8>>,
+    ) -> std::result::Result<PendingCommand, String> {
+        let config = self.current_config.load();
+        // files in pcap local storage are always fetchable
+        let pcap_dir = format!(
+            "{}/*",
+            config
+                .yaml_config
+                .pcap
+                .local_storage_path
+                .trim_end_matches('/')
+        );
+        if !is_fetch_allowed(path, &config.yaml_config.remote_exec_fetch_paths)
+            && !is_fetch_allowed(path, &[pcap_dir])
+        {
+            return Err(format!("rejected fetch file '{}' not allowed", path));
+        }
+        let cipher = match result_public_key {
+            Some(key) => match ResultCipher::new(key) {
+                Ok(cipher) => Some(cipher),
+                Err(e) => return Err(format!("rejected fetch file '{}': {}", path, e)),
+            },
+            None => None,
+        };
+        let fp = open_fetch_file(path)
+            .map_err(|e| format!("fetch file '{}' open failed: {}", path, e))?;
+        match fp.metadata() {
+            Ok(m) if m.is_file() => (),
+            Ok(_) => return Err(format!("rejected fetch file '{}' not a regular file", path)),
+            Err(e) => return Err(format!("fetch file '{}' stat failed: {}", path, e)),
+        }
+        trace!("pending fetch file '{}'", path);
+        let output = Output {
+            status: Default::default(),
+            stdout: vec![],
+            stderr: vec![],
+        };
+        Ok(PendingCommand::new(
+            request_id,
+            None,
+            Cow::Owned(format!("fetch {}", path)),
+            Arc::new(Progress::default()),
+            cipher,
+            None,
+            Box::pin(future::ready(Ok(output))),
+        )
+        .with_stdout(tokio::fs::File::from_std(fp))
+        .with_max_output(MAX_FETCH_FILE_SIZE)
+        .with_truncation(true))
+    }
+
+    // Checks params, binary, namespace files and kubernetes access of the command without
+    // running it, kubernetes access is reviewed by the returned future
+    // Checks params, binary, namespace files and kubernetes access of the command without
+    // running it, kubernetes access is reviewed by the returned future
+    fn check_command(&self, msg: &pb::RemoteExecRequest) -> BoxFuture<'static, pb::CommandCheck> {
+        let mut items = vec![];
+        let Some(cmd) = msg.command_id.and_then(|id| get_cmd(id as usize)) else {
+            items.push(check_item(
+                "command",
+                false,
+                "command_id not specified or invalid",
+            ));
+            return Box::pin(future::ready(command_check(items)));
+        };
+        items.push(check_item("command", true, cmd.cmdline.as_ref()));
+
+        let params = Params(&msg.params[..msg.params.len().min(max_param_nums())]);
+        let missing = cmd
+            .cmdline
+            .split_whitespace()
+            .filter_map(|arg| arg.strip_prefix('$'))
+            .filter(|name| {
+                params.get(name).is_err() && !matches!(cmd.param_rule(name), ParamRule::Optional(_))
+            })
+            .collect::<Vec<_>>();
+        items.push(if !params.is_valid(&cmd) {
+            check_item("params", false, format!("invalid params: {:?}", params))
+        } else if !missing.is_empty() {
+            check_item(
+                "params",
+                false,
+                format!("params not found: {}", missing.join(", ")),
+            )
+        } else {
+            check_item("params", true, "")
+        });
+
+        let exec_ns =
+            match ExecNs::select(msg.linux_ns_pid, msg.linux_ns_full(), &msg.linux_ns_types) {
+                Ok(exec_ns) => exec_ns,
+                Err(e) => {
+                    items.push(check_item("namespace", false, e));
+                    ExecNs::Agent
+                }
+            };
+        self.check_exec(&cmd, &params, exec_ns, items)
+    }
+
+    fn start_capture(
+        &self,
+        msg: &pb::RemoteExecRequest,
+        filter: &pb::CaptureFilter,
+        expr: &str,
+        cmdline: String,
+    ) -> std::result::Result<PendingCommand, String> {
+        let Some(id) = msg.request_id else {
+            return Err("rejected capture without request_id".to_owned());
+        };
+        let if_index = match filter.interface() {
+            "" => None,
+            name if is_valid_ifname(name) => Some(
+                link_by_name(name)
+                    .map_err(|e| format!("capture interface {} not found: {}", name, e))?
+                    .if_index,
+            ),
+            name => return Err(format!("rejected capture on invalid interface {}", name)),
+        };
+        let cipher = match msg.result_public_key.as_ref() {
+            Some(key) => match ResultCipher::new(key) {
+                Ok(cipher) => Some(cipher),
+                Err(e) => return Err(format!("rejected '{}': {}", cmdline, e)),
+            },
+            None => None,
+        };
+        let duration = msg.capture_duration().clamp(1, MAX_CAPTURE_DURATION);
+        let snap_len = msg.capture_snap_len().clamp(1, MAX_CAPTURE_SNAP_LEN);
+        let packets = self.packet_capture.start(
+            id,
+            if_index,
+            expr,
+            snap_len,
+            msg.capture_max_packets().clamp(1, MAX_CAPTURE_COUNT),
+        )?;
+        trace!("pending '{}' for {}s", cmdline, duration);
+        let (stdout, writer) = tokio::io::duplex(READ_BUFFER_SIZE);
+        let future = capture_command(
+            CaptureGuard(self.packet_capture.clone(), id),
+            packets,
+            writer,
+            snap_len,
+            Duration::from_secs(duration as u64),
+        );
+        Ok(PendingCommand::new(
+            msg.request_id,
+            None,
+            Cow::Owned(cmdline),
+            Arc::new(Progress::default()),
+            cipher,
+            None,
+            Box::pin(future),
+        )
+        .with_stdout(stdout))
+    }
+}
+
+impl Stream for Responser {
+    type Item = pb::RemoteExecResponse;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        /*
+         * order of polling:
+         * 1. Send remaining buffered command output
+         * 2. Read stdout of pending command if any. If more than a batch is read, restart from top
+         *    to send it
+         * 3. Poll pending command if any. If command succeeded, restart from top. If command
+         *    timed out, kill it and send the failure, otherwise send progress of the command
+         *    periodically
+         * 4. Start the next command of pending batch if no command is running
+         * 5. Poll pending lsns functions and command checks if any
+         * 6. Poll message queue for command from server. On receiving a new command, restart from top
+         * 7. Poll ticker for heartbeat
+         *
+         * The stream ends when the executor stops, killing the pending command if any
+         */
+
+        if self.cancelled.as_mut().poll(ctx).is_ready() {
+            if let Some(p) = self.pending_command.take() {
+                info!("command '{}' killed as remote executor stopped", p.cmdline);
+            }
+            self.pending_batch = None;
+            self.pending_lsns.clear();
+            self.pending_checks.clear();
+            return Poll::Ready(None);
+        }
+
+        loop {
+            if let Some(batch) = self.as_mut().generate_result_batch() {
+                let len = batch.content.as_ref().map(|c| c.len()).unwrap_or_default();
+                trace!("send buffer {} bytes", len);
+                self.counter
+                    .tx_bytes
+                    .fetch_add(len as u64, Ordering::Relaxed);
+                return Poll::Ready(Some(pb::RemoteExecResponse {
+                    agent_id: Some(self.agent_id.read().deref().into()),
+                    request_id: self.result.request_id,
+                    command_result: Some(batch),
+                    ..Default::default()
+                }));
+            }
+
+            if self.pending_command.is_some() {
+                if let Err(e) = self.poll_stdout(ctx) {
+                    let PendingCommand {
+                        request_id,
+                        batch_index,
+                        cmdline,
+                        mut audit,
+                        ..
+                    } = self.pending_command.take().unwrap();
+                    self.result.abort();
+                    let msg = format!("command '{}' read output failed: {}", cmdline, e);
+                    if let Some(audit) = audit.as_mut() {
+                        audit.fail(Outcome::Failed, &msg);
+                    }
+                    return self.command_failed_helper(request_id, batch_index, None, msg);
+                }
+                if self.result.streaming && self.result.output.len() > self.batch_len {
+                    continue;
+                }
+            }
+
+            if let Some(pending) = self.pending_command.as_mut() {
+                trace!("poll pending command '{}'", pending.cmdline);
+                if pending.output.is_none() {
+                    if let Poll::Ready(res) = pending.future.as_mut().poll(ctx) {
+                        pending.output = Some(res);
+                    }
+                }
+                // streamed stdout is drained before the command finishes
+                let finished = pending.output.is_some() && pending.stdout.is_none();
+
+                if !finished && pending.timed_out(ctx) {
+                    let PendingCommand {
+                        request_id,
+                        batch_index,
+                        cmdline,
+                        timeout,
+                        mut audit,
+                        ..
+                    } = self.pending_command.take().unwrap();
+                    if self.result.streaming {
+                        self.result.abort();
+                    }
+                    let msg = format!(
+                        "command '{}' timed out after {:?}, killed",
+                        cmdline, timeout
+                    );
+                    if let Some(audit) = audit.as_mut() {
+                        audit.fail(Outcome::TimedOut, &msg);
+                    }
+                    return self.command_failed_helper(request_id, batch_index, None, msg);
+                }
+
+                if !finished && pending.progress_ticker.poll_tick(ctx).is_ready() {
+                    let request_id = pending.request_id;
+                    let progress = pending.progress();
+                    trace!("command progress {:?}", progress);
+                    return Poll::Ready(Some(pb::RemoteExecResponse {
+                        agent_id: Some(self.agent_id.read().deref().into()),
+                        request_id,
+                        progress: Some(progress),
+                        ..Default::default()
+                    }));
+                }
+
+                if finished {
+                    let output = pending.output.take().unwrap();
+                    let bytes = output
+                        .as_ref()
+                        .map(|o| pending.bytes(o))
+                        .unwrap_or_default();
+                    let exceeded = pending.output_exceeded(bytes);
+                    let PendingCommand {
+                        request_id,
+                        batch_index,
+                        cmdline,
+                        cipher,
+                        cache_key,
+                        cached_stdout,
+                        mut audit,
+                        max_output,
+                        truncate_output,
+                        truncated,
+                        compress,
+                        ..
+                    } = self.pending_command.take().unwrap();
+                    let streamed = self.result.streaming;
+                    let (output, truncated) = match output {
+                        // failure of the command stopped on truncation is ignored
+                        _ if truncated => (
+                            Ok(Output {
+                                status: Default::default(),
+                                stdout: vec![],
+                                stderr: vec![],
+                            }),
+                            true,
+                        ),
+                        Ok(mut output) if exceeded && truncate_output && !streamed => {
+                            output.stdout.truncate(max_output as usize);
+                            output.stdout.extend_from_slice(TRUNCATED_MARKER);
+                            (Ok(output), true)
+                        }
+                        output => (output, false),
+                    };
+                    let exceeded = exceeded && !truncated;
+                    if truncated {
+                        debug!(
+                            "command '{}' output truncated at {} bytes",
+                            cmdline, max_output
+                        );
+                    }
+                    match output {
+                        Ok(_) if exceeded => {
+                            if streamed {
+                                self.result.abort();
+                            }
+                            let msg = format!(
+                                "command '{}' output exceeds {} bytes",
+                                cmdline, max_output
+                            );
+                            if let Some(audit) = audit.as_mut() {
+                                audit.finish(Outcome::Failed, None, bytes);
+                                audit.fail(Outcome::Failed, &msg);
+                            }
+                            return self.command_failed_helper(request_id, batch_index, None, msg);
+                        }
+                        Ok(output) if output.status.success() => {
+                            debug!("command '{}' succeeded", cmdline);
+                            if let Some(audit) = audit.as_mut() {
+                                audit.finish(Outcome::Succeeded, Some(0), bytes);
+                            }
+                            if let Some(key) = cache_key.filter(|_| !truncated) {
+                                let stdout = if streamed {
+                                    cached_stdout
+                                } else {
+                                    output.stdout.clone()
+                                };
+                                self.result_cache.insert(key, stdout);
+                            }
+                            let empty = if streamed {
+                                self.result.sent_len == 0 && self.result.output.is_empty()
+                            } else {
+                                output.stdout.is_empty()
+                            };
+                            if empty {
+                                self.result.abort();
+                                return Poll::Ready(Some(pb::RemoteExecResponse {
+                                    agent_id: Some(self.agent_id.read().deref().into()),
+                                    request_id: request_id,
+                                    command_result: Some(pb::CommandResult {
+                                        batch_index,
+                                        ..Default::default()
+                                    }),
+                                    ..Default::default()
+                                }));
+                            }
+                            let r = &mut self.result;
+                            if streamed {
+                                // remaining output is sent as the last segment
+                                r.streaming = false;
+                            } else {
+                                r.start(
+                                    request_id,
+                                    batch_index,
+                                    Some(output.stdout.len()),
+                                    cipher,
+                                    compress,
+                                );
+                                r.truncated = truncated;
+                                r.output = output.stdout.into();
+                            }
+                            continue;
+                        }
+                        Ok(mut output) => {
+                            // stderr is not returned if output is encrypted
+                            let encrypted =
+                                cipher.is_some() || streamed && self.result.cipher.is_some();
+                            if streamed {
+                                self.result.abort();
+                            }
+                            let (code, msg) = match output.status.code() {
+                                Some(code) => (
+                                    Some(code),
+                                    format!("command '{}' failed with {}", cmdline, code),
+                                ),
+                                None => (
+                                    None,
+                                    format!(
+                                        "command '{}' execute terminated without errno",
+                                        cmdline
+                                    ),
+                                ),
+                            };
+                            if let Some(audit) = audit.as_mut() {
+                                audit.finish(Outcome::Failed, code, bytes);
+                                audit.fail(Outcome::Failed, &msg);
+                            }
+                            let mut response =
+                                self.command_failed_response(request_id, batch_index, code, msg);
+                            if !encrypted && !output.stderr.is_empty() {
+                                let start = output.stderr.len().saturating_sub(MAX_STDERR_LEN);
+                                output.stderr.drain(..start);
+                                debug!(
+                                    "command '{}' stderr: {}",
+                                    cmdline,
+                                    String::from_utf8_lossy(&output.stderr)
+                                );
+                                if let Some(result) = response.command_result.as_mut() {
+                                    result.stderr = Some(output.stderr);
+                                }
+                            }
+                            return Poll::Ready(Some(response));
+                        }
+                        Err(e) => {
+                            if streamed {
+                                self.result.abort();
+                            }
+                            let msg = format!("command '{}' execute failed: {}", cmdline, e);
+                            if let Some(audit) = audit.as_mut() {
+                                audit.fail(Outcome::Failed, &msg);
+                            }
+                            return self.command_failed_helper(request_id, batch_index, None, msg);
+                        }
+                    }
+                }
+            }
+
+            // result of the previous sub-command held back by flow control is sent first
+            if self.pending_command.is_none() && self.result.output.is_empty() {
+                if let Some(batch) = self.pending_batch.as_mut() {
+                    match batch.commands.pop_front() {
+                        Some(cmd) => {
+                            let request_id = batch.request_id;
+                            let index = batch.next_index;
+                            batch.next_index += 1;
+                            if batch.failed && batch.stop_on_error {
+                                return self.command_failed_helper(
+                                    request_id,
+                                    Some(index),
+                                    Some(-1),
+                                    format!(
+                                        "batch {:?} command {} skipped on previous failure",
+                                        request_id, index
+                                    ),
+                                );
+                            }
+                            let (exec_ns, result_public_key, result_cache_ttl, timeout, compress) = (
+                                batch.exec_ns,
+                                batch.result_public_key.clone(),
+                                batch.result_cache_ttl,
+                                batch.command_timeout,
+                                batch.compress_result,
+                            );
+                            let mut audit = self.audit(
+                                request_id,
+                                Some(index),
+                                cmd.command_id,
+                                &cmd.params,
+                                exec_ns,
+                            );
+                            match self.start_command(
+                                request_id,
+                                Some(index),
+                                cmd.command_id,
+                                &cmd.params,
+                                exec_ns,
+                                result_public_key.as_ref(),
+                                result_cache_ttl,
+                            ) {
+                                Ok(pending) => {
+                                    self.pending_command = Some(
+                                        pending
+                                            .with_timeout(timeout)
+                                            .with_audit(audit)
+                                            .with_compression(compress),
+                                    );
+                                    continue;
+                                }
+                                Err(e) => {
+                                    audit.fail(Outcome::Rejected, &e);
+                                    return self.command_failed_helper(
+                                        request_id,
+                                        Some(index),
+                                        None,
+                                        e,
+                                    );
+                                }
+                            }
+                        }
+                        None => {
+                            debug!("batch {:?} completed", batch.request_id);
+                            self.pending_batch = None;
+                        }
+                    }
+                }
+            }
+
+            let mut lsns_result = None;
+            for (request_id, future) in self.pending_lsns.iter_mut() {
+                trace!("poll pending lsns {:?}", request_id);
+                if let Poll::Ready(result) = future.as_mut().poll(ctx) {
+                    lsns_result = Some((*request_id, result));
+                    break;
+                }
+            }
+            if let Some((request_id, result)) = lsns_result {
+                self.pending_lsns.remove(&request_id);
+                match result {
+                    Ok(namespaces) => {
+                        debug!("list namespace completed with {} entries", namespaces.len());
+                        self.result_cache.insert_namespaces(namespaces.clone());
+                        return Poll::Ready(Some(pb::RemoteExecResponse {
+                            agent_id: Some(self.agent_id.read().deref().into()),
+                            request_id,
+                            linux_namespaces: namespaces,
+                            ..Default::default()
+                        }));
+                    }
+                    Err(e) => {
+                        warn!("list namespace failed: {}", e);
+                        return Poll::Ready(Some(pb::RemoteExecResponse {
+                            agent_id: Some(self.agent_id.read().deref().into()),
+                            request_id,
+                            errmsg: Some(e.to_string()),
+                            ..Default::default()
+                        }));
+                    }
+                }
+            }
+
+            let mut check_result = None;
+            for (request_id, future) in self.pending_checks.iter_mut() {
+                trace!("poll pending check {:?}", request_id);
+                if let Poll::Ready(check) = future.as_mut().poll(ctx) {
+                    check_result = Some((*request_id, check));
+                    break;
+                }
+            }
+            if let Some((request_id, check)) = check_result {
+                self.pending_checks.remove(&request_id);
+                debug!(
+                    "check command {:?} completed, passed: {}",
+                    request_id,
+                    check.passed()
+                );
+                return Poll::Ready(Some(pb::RemoteExecResponse {
+                    agent_id: Some(self.agent_id.read().deref().into()),
+                    request_id,
+                    command_check: Some(check),
+                    ..Default::default()
+                }));
+            }
+
+            match self.msg_recv.poll_recv(ctx) {
+                // sender closed, terminate the current stream
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Ready(Some(msg)) => {
+                    let config = self.current_config.load();
+                    update_config_commands(&config.yaml_config.remote_exec_commands);
+                    self.audit_log
+                        .set_path(&config.yaml_config.remote_exec_audit_log);
+                    self.audit_log
+                        .set_history_len(config.yaml_config.remote_exec_history_len);
+                    self.limiter
+                        .set_rate(config.yaml_config.remote_exec_command_rate);
+                    #[cfg(any(target_os = "linux", target_os = "android"))]
+                    self.kube_client
+                        .set_config(&config.yaml_config.remote_exec_kubernetes);
+                    let exec_type = pb::ExecutionType::from_i32(msg.exec_type.unwrap()).unwrap();
+                    if matches!(
+                        exec_type,
+                        pb::ExecutionType::RunCommand
+                            | pb::ExecutionType::RunBatch
+                            | pb::ExecutionType::CheckCommand
+                    ) {
+                        if let Err(e) = check_registry_digest(msg.registry_digest) {
+                            return self.errmsg_helper(msg.request_id, e);
+                        }
+                    }
+                    match exec_type {
+                        pb::ExecutionType::ListCommand => {
+                            let (commands, registry_digest) = remote_commands();
+                            debug!("list command returning {} entries", commands.len());
+                            return Poll::Ready(Some(pb::RemoteExecResponse {
+                                agent_id: Some(self.agent_id.read().deref().into()),
+                                request_id: msg.request_id,
+                                commands,
+                                registry_digest: Some(registry_digest),
+                                ..Default::default()
+                            }));
+                        }
+                        pb::ExecutionType::ListHistory => {
+                            let history = self
+                                .audit_log
+                                .history()
+                                .iter()
+                                .map(command_history)
+                                .collect::<Vec<_>>();
+                            debug!("list history returning {} entries", history.len());
+                            return Poll::Ready(Some(pb::RemoteExecResponse {
+                                agent_id: Some(self.agent_id.read().deref().into()),
+                                request_id: msg.request_id,
+                                history,
+                                ..Default::default()
+                            }));
+                        }
+                        #[cfg(any(target_os = "linux", target_os = "android"))]
+                        pb::ExecutionType::StateReport => {
+                            let state_report = self.diagnostics.state_report(&self.agent_id.read());
+                            debug!(
+                                "state report returning {} components",
+                                state_report.components.len()
+                            );
+                            return Poll::Ready(Some(pb::RemoteExecResponse {
+                                agent_id: Some(self.agent_id.read().deref().into()),
+                                request_id: msg.request_id,
+                                state_report: Some(state_report),
+                                ..Default::default()
+                            }));
+                        }
+                        #[cfg(target_os = "windows")]
+                        pb::ExecutionType::StateReport => {
+                            return self.errmsg_helper(
+                                msg.request_id,
+                                "state report is not supported on windows",
+                            );
+                        }
+                        pb::ExecutionType::UpdateCommands => {
+                            if !config.yaml_config.remote_exec_controller_commands {
+                                return self.errmsg_helper(
+                                    msg.request_id,
+                                    "rejected update commands, remote-exec-controller-commands disabled",
+                                );
+                            }
+                            let Some(public_key) =
+                                config.yaml_config.remote_exec_commands_public_key()
+                            else {
+                                return self.errmsg_helper(
+                                    msg.request_id,
+                                    "rejected update commands, remote-exec-commands-public-key not configured",
+                                );
+                            };
+                            let version = msg.commands_version();
+                            match update_controller_commands(
+                                version,
+                                &msg.update_commands,
+                                msg.commands_signature(),
+                                &public_key,
+                            ) {
+                                Ok(true) => (),
+                                Ok(false) => debug!(
+                                    "ignored controller commands version {} already applied",
+                                    version
+                                ),
+                                Err(e) => return self.errmsg_helper(msg.request_id, e),
+                            }
+                            let (commands, registry_digest) = remote_commands();
+                            return Poll::Ready(Some(pb::RemoteExecResponse {
+                                agent_id: Some(self.agent_id.read().deref().into()),
+                                request_id: msg.request_id,
+                                commands,
+                                registry_digest: Some(registry_digest),
+                                ..Default::default()
+                            }));
+                        }
+                        pb::ExecutionType::AckResult => {
+                            trace!(
+                                "ack {} segments of request {:?} batch {:?}",
+                                msg.ack_pkt_count(),
+                                msg.request_id,
+                                msg.ack_batch_index
+                            );
+                            self.result.ack(
+                                msg.request_id,
+                                msg.ack_batch_index,
+                                msg.ack_pkt_count(),
+                            );
+                            continue;
+                        }
+                        pb::ExecutionType::ListNamespace => {
+                            if self.pending_lsns.contains_key(&msg.request_id) {
+                                return self.errmsg_helper(
+                                    msg.request_id,
+                                    format!(
+                                        "list namespace request {:?} is already pending",
+                                        msg.request_id
+                                    ),
+                                );
+                            }
+                            if self.pending_lsns.len() >= MAX_PENDING_LSNS {
+                                return self.errmsg_helper(
+                                    msg.request_id,
+                                    format!(
+                                        "too many pending list namespace requests, max {}",
+                                        MAX_PENDING_LSNS
+                                    ),
+                                );
+                            }
+                            if let Some(namespaces) = self
+                                .result_cache
+                                .get_namespaces(ResultCache::ttl(msg.result_cache_ttl))
+                            {
+                                debug!(
+                                    "list namespace returned {} entries from cache",
+                                    namespaces.len()
+                                );
+                                return Poll::Ready(Some(pb::RemoteExecResponse {
+                                    agent_id: Some(self.agent_id.read().deref().into()),
+                                    request_id: msg.request_id,
+                                    linux_namespaces: namespaces.clone(),
+                                    ..Default::default()
+                                }));
+                            }
+                            trace!("pending list namespace {:?}", msg.request_id);
+                            self.pending_lsns
+                                .insert(msg.request_id, Box::pin(ls_netns()));
+                            continue;
+                        }
+                        pb::ExecutionType::CheckCommand => {
+                            if self.pending_checks.contains_key(&msg.request_id) {
+                                return self.errmsg_helper(
+                                    msg.request_id,
+                                    format!(
+                                        "check command request {:?} is already pending",
+                                        msg.request_id
+                                    ),
+                                );
+                            }
+                            if self.pending_checks.len() >= MAX_PENDING_CHECKS {
+                                return self.errmsg_helper(
+                                    msg.request_id,
+                                    format!(
+                                        "too many pending check command requests, max {}",
+                                        MAX_PENDING_CHECKS
+                                    ),
+                                );
+                            }
+                            trace!("pending check command {:?}", msg.request_id);
+                            let future = self.check_command(&msg);
+                            self.pending_checks.insert(msg.request_id, future);
+                            continue;
+                        }
+                        pb::ExecutionType::CancelCommand => {
+                            // the pending future is aborted and the child is killed on drop
+                            let command_cancelled = matches!(
+                                self.pending_command.as_ref(),
+                                Some(p) if p.request_id == msg.request_id
+                            );
+                            let batch_cancelled = matches!(
+                                self.pending_batch.as_ref(),
+                                Some(b) if b.request_id == msg.request_id
+                            );
+                            // output not sent yet is discarded
+                            let output_discarded = self.result.request_id == msg.request_id
+                                && (self.result.streaming || !self.result.output.is_empty());
+                            if command_cancelled || batch_cancelled || output_discarded {
+                                if command_cancelled {
+                                    self.pending_command = None;
+                                }
+                                if batch_cancelled {
+                                    self.pending_batch = None;
+                                }
+                                if output_discarded {
+                                    self.result.abort();
+                                }
+                                info!("command request {:?} cancelled", msg.request_id);
+                                return self.errmsg_helper(
+                                    msg.request_id,
+                                    format!("command request {:?} cancelled", msg.request_id),
+                                );
+                            }
+                            if self.pending_lsns.remove(&msg.request_id).is_some() {
+                                info!("list namespace request {:?} cancelled", msg.request_id);
+                                return self.errmsg_helper(
+                                    msg.request_id,
+                                    format!(
+                                        "list namespace request {:?} cancelled",
+                                        msg.request_id
+                                    ),
+                                );
+                            }
+                            if self.pending_checks.remove(&msg.request_id).is_some() {
+                                info!("check command request {:?} cancelled", msg.request_id);
+                                return self.errmsg_helper(
+                                    msg.request_id,
+                                    format!("check command request {:?} cancelled", msg.request_id),
+                                );
+                            }
+                            return self.errmsg_helper(
+                                msg.request_id,
+                                format!("no pending request {:?} to cancel", msg.request_id),
+                            );
+                        }
+                        pb::ExecutionType::ResumeResult => {
+                            let (request_id, batch_index) =
+                                (msg.request_id, msg.resume_batch_index);
+                            let Some(output) = request_id
+                                .and_then(|id| self.result.resume_buffer.get(id, batch_index))
+                            else {
+                                return self.command_failed_helper(
+                                    request_id,
+                                    batch_index,
+                                    None,
+                                    format!(
+                                        "result of request {:?} batch {:?} not found or expired",
+                                        request_id, batch_index
+                                    ),
+                                );
+                            };
+                            let offset = msg.resume_offset() as usize;
+                            if offset > output.len() {
+                                return self.command_failed_helper(
+                                    request_id,
+                                    batch_index,
+                                    None,
+                                    format!(
+                                        "resume offset {} beyond result length {}",
+                                        offset,
+                                        output.len()
+                                    ),
+                                );
+                            }
+                            let cipher = match msg.result_public_key.as_ref() {
+                                Some(key) => match ResultCipher::new(key) {
+                                    Ok(cipher) => Some(cipher),
+                                    Err(e) => {
+                                        return self.command_failed_helper(
+                                            request_id,
+                                            batch_index,
+                                            None,
+                                            format!("rejected resume result: {}", e),
+                                        );
+                                    }
+                                },
+                                None => None,
+                            };
+                            if let Some(batch_len) = msg.batch_len {
+                                self.batch_len = MIN_BATCH_LEN.max(batch_len as usize);
+                            }
+                            self.pending_batch = None;
+                            self.drop_pending_command();
+                            self.result.digest = ResultDigest::new(msg.result_digest());
+                            self.result_window = msg.result_window();
+                            debug!(
+                                "resume result of request {:?} from {}/{}",
+                                request_id,
+                                offset,
+                                output.len()
+                            );
+                            if offset == output.len() {
+                                // nothing left but the end of result
+                                self.result.abort();
+                                let mut result = pb::CommandResult {
+                                    errno: Some(0),
+                                    total_len: Some(output.len() as u64),
+                                    pkt_count: Some(0),
+                                    batch_index,
+                                    ..Default::default()
+                                };
+                                let mut digest = ResultDigest::new(msg.result_digest());
+                                digest.update(&output);
+                                digest.finish(&mut result);
+                                return Poll::Ready(Some(pb::RemoteExecResponse {
+                                    agent_id: Some(self.agent_id.read().deref().into()),
+                                    request_id,
+                                    command_result: Some(result),
+                                    ..Default::default()
+                                }));
+                            }
+                            self.result.resume(
+                                request_id,
+                                batch_index,
+                                &output,
+                                offset,
+                                cipher,
+                                msg.compress_result(),
+                            );
+                            continue;
+                        }
+                        pb::ExecutionType::RunCommand => {
+                            let full_ns = msg.linux_ns_full();
+                            if !self.limiter.acquire(1, Instant::now()) {
+                                let errmsg = format!(
+                                    "rejected run command request {:?} over rate limit {}/min",
+                                    msg.request_id, self.limiter.rate
+                                );
+                                let mut audit = self.audit(
+                                    msg.request_id,
+                                    None,
+                                    msg.command_id,
+                                    &msg.params,
+                                    ExecNs::new(msg.linux_ns_pid, full_ns),
+                                );
+                                audit.fail(Outcome::Rejected, &errmsg);
+                                return self.command_failed_helper(
+                                    msg.request_id,
+                                    None,
+                                    None,
+                                    errmsg,
+                                );
+                            }
+                            if let Some(batch_len) = msg.batch_len {
+                                self.batch_len = MIN_BATCH_LEN.max(batch_len as usize);
+                            }
+                            self.pending_batch = None;
+                            self.drop_pending_command();
+                            self.result.digest = ResultDigest::new(msg.result_digest());
+                            self.result_window = msg.result_window();
+                            let timeout = self.command_timeout(msg.command_timeout);
+                            let exec_ns =
+                                ExecNs::select(msg.linux_ns_pid, full_ns, &msg.linux_ns_types);
+                            let mut audit = self.audit(
+                                msg.request_id,
+                                None,
+                                msg.command_id,
+                                &msg.params,
+                                exec_ns
+                                    .as_ref()
+                                    .copied()
+                                    .unwrap_or_else(|_| ExecNs::new(msg.linux_ns_pid, full_ns)),
+                            );
+                            let started = exec_ns.and_then(|exec_ns| {
+                                self.start_command(
+                                    msg.request_id,
+                                    None,
+                                    msg.command_id,
+                                    &msg.params,
+                                    exec_ns,
+                                    msg.result_public_key.as_ref(),
+                                    msg.result_cache_ttl,
+                                )
+                            });
+                            match started {
+                                Ok(pending) => {
+                                    self.pending_command = Some(
+                                        pending
+                                            .with_timeout(timeout)
+                                            .with_audit(audit)
+                                            .with_compression(msg.compress_result()),
+                                    );
+                                    continue;
+                                }
+                                Err(e) => {
+                                    audit.fail(Outcome::Rejected, &e);
+                                    return self.command_failed_helper(
+                                        msg.request_id,
+                                        None,
+                                        None,
+                                        e,
+                                    );
+                                }
+                            }
+                        }
+                        pb::ExecutionType::FetchFile => {
+                            let path = msg.file_path.clone().unwrap_or_default();
+                            let mut audit = AuditGuard::new(
+                                self.audit_log.clone(),
+                                AuditRecord {
+                                    request_id: msg.request_id,
+                                    cmdline: format!("fetch {}", path),
+                                    ..Default::default()
+                                },
+                            );
+                            if !self.limiter.acquire(1, Instant::now()) {
+                                let errmsg = format!(
+                                    "rejected fetch file request {:?} over rate limit {}/min",
+                                    msg.request_id, self.limiter.rate
+                                );
+                                audit.fail(Outcome::Rejected, &errmsg);
+                                return self.command_failed_helper(
+                                    msg.request_id,
+                                    None,
+                                    None,
+                                    errmsg,
+                                );
+                            }
+                            if let Some(batch_len) = msg.batch_len {
+                                self.batch_len = MIN_BATCH_LEN.max(batch_len as usize);
+                            }
+                            self.pending_batch = None;
+                            self.drop_pending_command();
+                            self.result.digest = ResultDigest::new(msg.result_digest());
+                            self.result_window = msg.result_window();
+                            let timeout = self.command_timeout(msg.command_timeout);
+                            match self.fetch_file(
+                                msg.request_id,
+                                &path,
+                                msg.result_public_key.as_ref(),
+                            ) {
+                                Ok(pending) => {
+                                    self.pending_command = Some(
+                                        pending
+                                            .with_timeout(timeout)
+                                            .with_audit(audit)
+                                            .with_compression(msg.compress_result()),
+                                    );
+                                    continue;
+                                }
+                                Err(e) => {
+                                    audit.fail(Outcome::Rejected, &e);
+                                    return self.command_failed_helper(
+                                        msg.request_id,
+                                        None,
+                                        None,
+                                        e,
+                                    );
+                                }
+                            }
+                        }
+                        pb::ExecutionType::StartCapture => {
+                            let filter = msg.capture_filter.clone().unwrap_or_default();
+                            let expr = capture_filter_expr(&filter);
+                            let cmdline = format!(
+                                "capture -i {} {}",
+                                if filter.interface().is_empty() {
+                                    "any"
+                                } else {
+                                    filter.interface()
+                                },
+                                expr.as_deref().unwrap_or_default()
+                            );
+                            let mut audit = AuditGuard::new(
+                                self.audit_log.clone(),
+                                AuditRecord {
+                                    request_id: msg.request_id,
+                                    cmdline: cmdline.clone(),
+                                    ..Default::default()
+                                },
+                            );
+                            if !self.limiter.acquire(1, Instant::now()) {
+                                let errmsg = format!(
+                                    "rejected capture request {:?} over rate limit {}/min",
+                                    msg.request_id, self.limiter.rate
+                                );
+                                audit.fail(Outcome::Rejected, &errmsg);
+                                return self.command_failed_helper(
+                                    msg.request_id,
+                                    None,
+                                    None,
+                                    errmsg,
+                                );
+                            }
+                            if let Some(batch_len) = msg.batch_len {
+                                self.batch_len = MIN_BATCH_LEN.max(batch_len as usize);
+                            }
+                            self.pending_batch = None;
+                            self.drop_pending_command();
+                            self.result.digest = ResultDigest::new(msg.result_digest());
+                            self.result_window = msg.result_window();
+                            let started = expr
+                                .and_then(|expr| self.start_capture(&msg, &filter, &expr, cmdline));
+                            match started {
+                                Ok(pending) => {
+                                    self.pending_command = Some(
+                                        pending
+                                            .with_audit(audit)
+                                            .with_compression(msg.compress_result()),
+                                    );
+                                    continue;
+                                }
+                                Err(e) => {
+                                    audit.fail(Outcome::Rejected, &e);
+                                    return self.command_failed_helper(
+                                        msg.request_id,
+                                        None,
+                                        None,
+                                        e,
+                                    );
+                                }
+                            }
+                        }
+                        pb::ExecutionType::StopCapture => {
+                            // the capture result finishes after captured packets are sent
+                            let stopped = msg
+                                .request_id
+                                .map(|id| self.packet_capture.stop(id))
+                                .unwrap_or_default();
+                            if !stopped {
+                                return self.errmsg_helper(
+                                    msg.request_id,
+                                    format!("no running capture {:?} to stop", msg.request_id),
+                                );
+                            }
+                            continue;
+                        }
+                        pb::ExecutionType::RunBatch => {
+                            if let Some(batch_len) = msg.batch_len {
+                                self.batch_len = MIN_BATCH_LEN.max(batch_len as usize);
+                            }
+                            if msg.batch_commands.is_empty()
+                                || msg.batch_commands.len() > MAX_BATCH_COMMANDS
+                            {
+                                return self.errmsg_helper(
+                                    msg.request_id,
+                                    format!(
+                                        "rejected batch with {} commands, expected 1 to {}",
+                                        msg.batch_commands.len(),
+                                        MAX_BATCH_COMMANDS
+                                    ),
+                                );
+                            }
+                            // each sub-command counts
+                            if !self
+                                .limiter
+                                .acquire(msg.batch_commands.len() as u32, Instant::now())
+                            {
+                                let errmsg = format!(
+                                    "rejected batch {:?} with {} commands over rate limit {}/min",
+                                    msg.request_id,
+                                    msg.batch_commands.len(),
+                                    self.limiter.rate
+                                );
+                                // each sub-command is recorded like a rejected run command
+                                for (i, c) in msg.batch_commands.iter().enumerate() {
+                                    let mut audit = self.audit(
+                                        msg.request_id,
+                                        Some(i as u32),
+                                        c.command_id,
+                                        &c.params,
+                                        ExecNs::new(msg.linux_ns_pid, msg.linux_ns_full()),
+                                    );
+                                    audit.fail(Outcome::Rejected, &errmsg);
+                                }
+                                return self.errmsg_helper(msg.request_id, errmsg);
+                            }
+                            let exec_ns = match ExecNs::select(
+                                msg.linux_ns_pid,
+                                msg.linux_ns_full(),
+                                &msg.linux_ns_types,
+                            ) {
+                                Ok(exec_ns) => exec_ns,
+                                Err(e) => return self.errmsg_helper(msg.request_id, e),
+                            };
+                            trace!(
+                                "pending batch {:?} with {} commands",
+                                msg.request_id,
+                                msg.batch_commands.len()
+                            );
+                            self.drop_pending_command();
+                            self.result.digest = ResultDigest::new(msg.result_digest());
+                            self.result_window = msg.result_window();
+                            self.pending_batch = Some(PendingBatch {
+                                request_id: msg.request_id,
+                                stop_on_error: msg.stop_on_error(),
+                                exec_ns,
+                                result_public_key: msg.result_public_key,
+                                result_cache_ttl: msg.result_cache_ttl,
+                                command_timeout: self.command_timeout(msg.command_timeout),
+                                compress_result: msg.compress_result(),
+                                commands: msg.batch_commands.into_iter().collect(),
+                                next_index: 0,
+                                failed: false,
+                            });
+                            continue;
+                        }
+                    }
+                }
+                _ => (),
+            }
+
+            return match self.heartbeat.poll_tick(ctx) {
+                Poll::Pending => Poll::Pending,
+                Poll::Ready(_) => Poll::Ready(Some(pb::RemoteExecResponse {
+                    agent_id: Some(self.agent_id.read().deref().into()),
+                    ..Default::default()
+                })),
+            };
+        }
+    }
+}
+
+// Falls back to the configured default if not requested
+fn command_timeout(requested: Option<u32>, default: Duration) -> Duration {
+    match requested {
+        Some(seconds) if seconds > 0 => Duration::from_secs(seconds as u64),
+        _ => default,
+    }
+}
+
+// Collects stderr and waits for the child, stdout is streamed by the responser
+async fn wait_child(mut child: Child) -> Result<Output> {
+    let mut stderr = vec![];
+    child
+        .stderr
+        .take()
+        .unwrap()
+        .read_to_end(&mut stderr)
+        .await?;
+    let status = child.wait().await?;
+    Ok(Output {
+        status,
+        stdout: vec![],
+        stderr,
+    })
+}
+
+fn check_item<S: Into<String>>(name: &str, passed: bool, detail: S) -> pb::CommandCheckItem {
+    pb::CommandCheckItem {
+        name: Some(name.to_owned()),
+        passed: Some(passed),
+        detail: Some(detail.into()),
+    }
+}
+
+fn command_check(items: Vec<pb::CommandCheckItem>) -> pb::CommandCheck {
+    pb::CommandCheck {
+        passed: Some(items.iter().all(|i| i.passed())),
+        items,
+    }
+}
+
+fn command_history(record: &AuditRecord) -> pb::CommandHistory {
+    pb::CommandHistory {
+        request_id: record.request_id,
+        batch_index: record.batch_index,
+        command_id: record.command_id,
+        cmdline: Some(record.cmdline.clone()),
+        params: record
+            .params
+            .iter()
+            .map(|(k, v)| pb::Parameter {
+                key: Some(k.clone()),
+                value: Some(v.clone()),
+            })
+            .collect(),
+        linux_ns_pid: record.ns_pid,
+        linux_ns_types: record.ns_types.iter().map(|t| t.to_string()).collect(),
+        start_time: Some(record.start_time.clone()),
+        end_time: Some(record.end_time.clone()),
+        outcome: Some(record.outcome.as_str().to_owned()),
+        exit_code: record.exit_code,
+        bytes: Some(record.bytes),
+        error: record.error.clone(),
+    }
+}
+
+// Stops the capture when finished or the pending command is dropped
+struct CaptureGuard(Arc<PacketCapture>, u64);
+
+impl Drop for CaptureGuard {
+    fn drop(&mut self) {
+        self.0.stop(self.1);
+    }
+}
+
+// Writes captured packets in pcap format to `writer` streamed by the pending command
+async fn capture_command(
+    _guard: CaptureGuard,
+    mut packets: Receiver<Vec<u8>>,
+    mut writer: DuplexStream,
+    snap_len: u32,
+    duration: Duration,
+) -> Result<Output> {
+    writer.write_all(&pcap_header(snap_len)).await?;
+    let deadline = time::sleep(duration);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            record = packets.recv() => match record {
+                Some(record) => writer.write_all(&record).await?,
+                None => break,
+            },
+        }
+    }
+    Ok(Output {
+        status: Default::default(),
+        stdout: vec![],
+        stderr: vec![],
+    })
+}
+
+// Filter expression of packets in both directions of the 5-tuple and matching bpf
+fn capture_filter_expr(filter: &pb::CaptureFilter) -> std::result::Result<String, String> {
+    let mut exprs = vec![];
+    for ip in [filter.ip_0(), filter.ip_1()] {
+        if ip.is_empty() {
+            continue;
+        }
+        match ip.parse::<std::net::IpAddr>() {
+            Ok(ip) => exprs.push(format!("host {}", ip)),
+            Err(_) => return Err(format!("rejected capture with invalid ip {}", ip)),
+        }
+    }
+    for port in [filter.port_0, filter.port_1].into_iter().flatten() {
+        if port == 0 || port > u16::MAX as u32 {
+            return Err(format!("rejected capture with invalid port {}", port));
+        }
+        exprs.push(format!("port {}", port));
+    }
+    if let Some(protocol) = filter.protocol {
+        if protocol > u8::MAX as u32 {
+            return Err(format!(
+                "rejected capture with invalid protocol {}",
+                protocol
+            ));
+        }
+        exprs.push(format!("(ip proto {0} or ip6 proto {0})", protocol));
+    }
+    if !filter.bpf().trim().is_empty() {
+        exprs.push(format!("({})", filter.bpf().trim()));
+    }
+    Ok(exprs.join(" and "))
+}
+
+struct Params<'a>(&'a [pb::Parameter]);
+
+impl Params<'_> {
+    fn get(&self, name: &str) -> Result<String> {
+        self.0
+            .iter()
+            .find(|p| p.key.as_deref() == Some(name))
+            .and_then(|p| p.value.clone())
+            .ok_or_else(|| Error::ParamNotFound(name.to_owned()))
+    }
+
+    fn is_valid(&self, cmd: &Command) -> bool {
+        self.0
+            .iter()
+            .all(|p| match (p.key.as_ref(), p.value.as_ref()) {
+                (Some(key), Some(value)) => cmd.param_rule(key).is_valid(value),
+                _ => false,
+            })
+    }
+
+    // Splits the command line into program and args with params substituted, so that the
+    // program is looked up in PATH
+    fn substitute<'c>(
+        &self,
+        cmdline: &'c str,
+    ) -> std::result::Result<(&'c str, Vec<String>), String> {
+        let mut args = cmdline.split_whitespace();
+        let program = args.next().unwrap_or_default();
+        let mut cmd_args = vec![];
+        for arg in args {
+            match arg.strip_prefix('$') {
+                Some(name) => match self.get(name) {
+                    Ok(value) => cmd_args.push(value),
+                    Err(_) => {
+                        return Err(format!(
+                            "parameter {} not found in command '{}'",
+                            arg, cmdline
+                        ))
+                    }
+                },
+                None => cmd_args.push(arg.to_owned()),
+            }
+        }
+        Ok((program, cmd_args))
+    }
+}
+
+const MAX_HOST_LEN: usize = 253;
+const MAX_URL_LEN: usize = 2048;
+// IFNAMSIZ without the trailing null
+const MAX_IFNAME_LEN: usize = 15;
+const MAX_CAPTURE_COUNT: u32 = 100000;
+const MAX_PATH_LEN: usize = 4096;
+
+fn is_valid_ifname(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_IFNAME_LEN
+        && !name.starts_with('-')
+        && name
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_' || c == b'.')
+}
+
+// absolute path without `..`, whitespaces or control characters
+fn is_valid_path(path: &str) -> bool {
+    path.starts_with('/')
+        && path.len() <= MAX_PATH_LEN
+        && path.split('/').all(|seg| seg != "..")
+        && path.chars().all(|c| !c.is_whitespace() && !c.is_control())
+}
+
+// path in `remote-exec-fetch-paths`, or a file directly in a directory with `/*` suffix
+fn is_fetch_allowed(path: &str, allowed: &[String]) -> bool {
+    is_valid_path(path)
+        && allowed.iter().any(|a| match a.strip_suffix("/*") {
+            Some(dir) => path
+                .strip_prefix(dir)
+                .and_then(|name| name.strip_prefix('/'))
+                .map(|name| !name.is_empty() && name != "." && !name.contains('/'))
+                .unwrap_or_default(),
+            None => a == path,
+        })
+}
+
+// hostname, ipv4 or ipv6 address
+fn is_valid_host(host: &str) -> bool {
+    if host.is_empty() || host.len() > MAX_HOST_LEN || host.starts_with('-') {
+        return false;
+    }
+    if host.parse::<std::net::IpAddr>().is_ok() {
+        return true;
+    }
+    host.split('.').all(|label| {
+        !label.is_empty()
+            && !label.starts_with('-')
+            && label
+                .bytes()
+                .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_')
+    })
+}
+
+// http or https url without whitespaces and control characters
+fn is_valid_url(url: &str) -> bool {
+    if url.len() > MAX_URL_LEN {
+        return false;
+    }
+    let Some(rest) = url
+        .strip_prefix("http://")
+        .or_else(|| url.strip_prefix("https://"))
+    else {
+        return false;
+    };
+    let authority = rest
+        .split(|c| c == '/' || c == '?' || c == '#')
+        .next()
+        .unwrap();
+    // user info is not allowed
+    if authority.is_empty() || authority.contains('@') {
+        return false;
+    }
+    let host = match authority.strip_prefix('[') {
+        Some(v6) => match v6.split_once(']') {
+            Some((addr, port)) if addr.parse::<std::net::Ipv6Addr>().is_ok() => {
+                if !port.is_empty() && !is_valid_port(port) {
+                    return false;
+                }
+                return url.bytes().all(is_url_char);
+            }
+            _ => return false,
+        },
+        None => match authority.split_once(':') {
+            Some((host, port)) if is_valid_port(port) => host,
+            Some(_) => return false,
+            None => authority,
+        },
+    };
+    is_valid_host(host) && url.bytes().all(is_url_char)
+}
+
+fn is_valid_port(s: &str) -> bool {
+    s.strip_prefix(':')
+        .unwrap_or(s)
+        .parse::<u16>()
+        .map(|p| p > 0)
+        .unwrap_or(false)
+}
+
+fn is_url_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"-._~:/?#[]@!$&'()*+,;=%".contains(&c)
+}
+
+impl fmt::Debug for Params<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{")?;
+        let mut empty = true;
+        for p in self.0.iter() {
+            let Some(key) = p.key.as_ref() else {
+                continue;
+            };
+            if empty {
+                write!(f, " ")?;
+            } else {
+                write!(f, ", ")?;
+            }
+            if let Some(value) = p.value.as_ref() {
+                write!(f, "{}: \"{}\"", key, value)?;
+            } else {
+                write!(f, "{}: null", key)?;
+            }
+            empty = false;
+        }
+        if !empty {
+            write!(f, " ")?;
+        }
+        write!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_filter() {
+        let filter = pb::CaptureFilter {
+            ip_0: Some("10.0.0.1".to_owned()),
+            port_1: Some(80),
+            protocol: Some(6),
+            bpf: Some("tcp[tcpflags] & tcp-syn != 0".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            capture_filter_expr(&filter).unwrap(),
+            "host 10.0.0.1 and port 80 and (ip proto 6 or ip6 proto 6) and (tcp[tcpflags] & tcp-syn != 0)"
+        );
+        assert_eq!(
+            capture_filter_expr(&pb::CaptureFilter::default()).unwrap(),
+            ""
+        );
+        for filter in [
+            pb::CaptureFilter {
+                ip_1: Some("10.0.0.1 or port 22".to_owned()),
+                ..Default::default()
+            },
+            pb::CaptureFilter {
+                port_0: Some(65536),
+                ..Default::default()
+            },
+        ] {
+            assert!(capture_filter_expr(&filter).is_err());
+        }
+    }
+
+    #[test]
+    fn cancel_token() {
+        let runtime = Runtime::new().unwrap();
+        let (cancel_tx, cancel) = CancelToken::new();
+        assert!(runtime.block_on(cancel.sleep(Duration::from_millis(1))));
+        let sleeping = runtime.spawn({
+            let cancel = cancel.clone();
+            async move { cancel.sleep(Duration::from_secs(60)).await }
+        });
+        cancel_tx.send(true).unwrap();
+        assert!(!runtime.block_on(sleeping).unwrap());
+        // cancelled also when the sender is dropped
+        let (cancel_tx, cancel) = CancelToken::new();
+        drop(cancel_tx);
+        runtime.block_on(cancel.cancelled());
+    }
+
+    #[test]
+    fn reconnect_backoff() {
+        let mut backoff = Backoff::new();
+        let mut max = Backoff::INITIAL;
+        for _ in 0..10 {
+            let delay = backoff.next_delay();
+            assert!(delay >= max / 2 && delay <= max, "{:?} {:?}", delay, max);
+            max = (max * 2).min(Backoff::MAX);
+        }
+        assert_eq!(backoff.current, Backoff::MAX);
+        backoff.reset();
+        assert!(backoff.next_delay() <= Backoff::INITIAL);
+    }
+
+    #[test]
+    fn fetch_allowed() {
+        let allowed = vec!["/proc/net/*".to_owned(), "/etc/resolv.conf".to_owned()];
+        for (path, valid) in [
+            ("/proc/net/tcp", true),
+            ("/etc/resolv.conf", true),
+            ("/proc/net", false),
+            ("/proc/net/", false),
+            ("/proc/net/stat/nf_conntrack", false),
+            ("/proc/net/../../etc/shadow", false),
+            ("/etc/hosts", false),
+            ("/etc/resolv.conf/", false),
+        ] {
+            assert_eq!(is_fetch_allowed(path, &allowed), valid, "{}", path);
+        }
+    }
+
+    #[test]
+    fn streamed_result() {
+        let mut r = CommandResult::default();
+        r.start(Some(1), None, None, None, false);
+        r.output.extend(b"0123456789abc");
+        for expected in [&b"0123"[..], b"4567", b"89ab"] {
+            let batch = r.next_batch(4).unwrap();
+            assert_eq!(batch.content.as_deref(), Some(expected));
+            assert!(batch.total_len.is_none() && batch.md5.is_none());
+        }
+        // the last bytes are kept until the command exits
+        assert!(r.next_batch(4).is_none());
+
+        r.streaming = false;
+        let last = r.next_batch(4).unwrap();
+        assert_eq!(last.content.as_deref(), Some(&b"c"[..]));
+        assert_eq!(last.total_len, Some(13));
+        assert_eq!(last.pkt_count, Some(4));
+        assert_eq!(
+            last.md5,
+            Some(format!("{:x}", Md5::digest(b"0123456789abc")))
+        );
+        assert!(r.next_batch(4).is_none());
+    }
+
+    #[test]
+    fn windowed_result() {
+        let mut r = CommandResult::default();
+        r.start(Some(1), None, Some(10), None, false);
+        r.output.extend(b"0123456789");
+        let mut sent = vec![];
+        while !r.window_full(2) {
+            let Some(batch) = r.next_batch(2) else {
+                break;
+            };
+            sent.push(batch.pkt_index.unwrap());
+        }
+        assert_eq!(sent, vec![0, 1]);
+        // acks of other requests or beyond sent are ignored
+        r.ack(Some(2), None, 2);
+        assert!(r.window_full(2));
+        r.ack(Some(1), None, 5);
+        assert_eq!(r.acked, 2);
+        assert!(!r.window_full(2));
+        r.ack(Some(1), None, 1);
+        assert_eq!(r.acked, 2);
+        assert_eq!(r.next_batch(2).unwrap().pkt_index, Some(2));
+        assert!(!r.window_full(0));
+    }
+
+    #[test]
+    fn truncated_result() {
+        let mut r = CommandResult::default();
+        r.start(Some(1), None, None, None, false);
+        r.output.extend(b"0123");
+        r.output.extend(TRUNCATED_MARKER);
+        r.truncated = true;
+        r.streaming = false;
+        let last = r.next_batch(1024).unwrap();
+        assert_eq!(last.truncated, Some(true));
+        assert_eq!(last.total_len, Some(4 + TRUNCATED_MARKER.len() as u64));
+
+        r.start(Some(2), None, Some(4), None, false);
+        r.output.extend(b"0123");
+        assert!(r.next_batch(1024).unwrap().truncated.is_none());
+    }
+
+    #[test]
+    fn sha256_result() {
+        let mut r = CommandResult::default();
+        r.digest = ResultDigest::new(pb::DigestAlgorithm::Sha256);
+        r.start(Some(1), None, Some(3), None, false);
+        r.output.extend(b"abc");
+        let last = r.next_batch(4).unwrap();
+        assert!(last.md5.is_none());
+        assert_eq!(last.digest_algorithm.as_deref(), Some("sha256"));
+        assert_eq!(
+            last.digest.as_deref(),
+            Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+
+        // algorithm is kept for the next command
+        r.start(Some(2), None, Some(3), None, false);
+        r.output.extend(b"abc");
+        assert_eq!(r.next_batch(4).unwrap().digest, last.digest);
+
+        r.digest = ResultDigest::default();
+        r.start(Some(3), None, Some(3), None, false);
+        r.output.extend(b"abc");
+        let last = r.next_batch(4).unwrap();
+        assert_eq!(last.digest_algorithm.as_deref(), Some("md5"));
+        assert_eq!(last.md5, Some(format!("{:x}", Md5::digest(b"abc"))));
+        assert_eq!(last.digest, last.md5);
+    }
+
+    #[test]
+    fn compressed_result() {
+        let mut r = CommandResult::default();
+        let output = b"deepflow ".repeat(1000);
+        r.start(Some(1), None, Some(output.len() + 3), None, true);
+        r.output.extend(&output);
+        r.output.extend(b"end");
+        let first = r.next_batch(output.len()).unwrap();
+        assert_eq!(first.compressed, Some(true));
+        let content = first.content.unwrap();
+        assert!(content.len() < output.len());
+        assert_eq!(
+            zstd::bulk::decompress(&content, output.len()).unwrap(),
+            output
+        );
+        // not smaller when compressed
+        let last = r.next_batch(output.len()).unwrap();
+        assert!(last.compressed.is_none());
+        assert_eq!(last.content.as_deref(), Some(&b"end"[..]));
+        assert_eq!(last.total_len, Some(output.len() as u64 + 3));
+        let mut digest = Md5::new();
+        digest.update(&output);
+        digest.update(b"end");
+        assert_eq!(last.md5, Some(format!("{:x}", digest.finalize())));
+    }
+
+    #[test]
+    fn resumed_result() {
+        let mut r = CommandResult::default();
+        r.start(Some(1), Some(2), Some(10), None, false);
+        r.output.extend(b"0123456789");
+        while r.next_batch(4).is_some() {}
+        let output = r.resume_buffer.get(1, Some(2)).unwrap();
+        assert_eq!(&output[..], b"0123456789");
+        assert!(r.resume_buffer.get(1, None).is_none());
+
+        r.resume(Some(1), Some(2), &output, 5, None, false);
+        let first = r.next_batch(4).unwrap();
+        assert_eq!(first.content.as_deref(), Some(&b"5678"[..]));
+        assert_eq!(first.total_len, Some(10));
+        assert_eq!(first.pkt_count, Some(2));
+        let last = r.next_batch(4).unwrap();
+        assert_eq!(last.content.as_deref(), Some(&b"9"[..]));
+        assert_eq!(last.md5, Some(format!("{:x}", Md5::digest(b"0123456789"))));
+        assert!(r.next_batch(4).is_none());
+
+        // not kept if aborted
+        r.start(Some(3), None, None, None, false);
+        r.output.extend(b"0123456789");
+        r.next_batch(4).unwrap();
+        r.abort();
+        assert!(r.resume_buffer.get(3, None).is_none());
+    }
+
+    #[test]
+    fn result_cache() {
+        let key = |id: usize, netns: u64| CacheKey {
+            id,
+            params: vec![],
+            netns,
+        };
+        let ttl = Duration::from_secs(10);
+        let mut cache = ResultCache::default();
+        cache.insert(key(0, 0), b"lsns".to_vec());
+        assert_eq!(cache.get(&key(0, 0), ttl), Some(&b"lsns"[..]));
+        assert_eq!(cache.get(&key(0, 0), Duration::ZERO), None);
+        assert_eq!(cache.get(&key(0, 1), ttl), None);
+
+        for i in 0..MAX_CACHED_RESULTS * 2 {
+            cache.insert(key(3, i as u64), vec![]);
+        }
+        assert_eq!(cache.commands.len(), MAX_CACHED_RESULTS);
+        assert!(cache
+            .get(&key(3, (MAX_CACHED_RESULTS * 2 - 1) as u64), ttl)
+            .is_some());
+
+        assert_eq!(ResultCache::ttl(None), Duration::ZERO);
+        assert_eq!(ResultCache::ttl(Some(3600)), MAX_RESULT_CACHE_TTL);
+    }
+
+    #[test]
+    fn command_limiter() {
+        let mut limiter = CommandLimiter::new(0);
+        let now = Instant::now();
+        assert!(limiter.acquire(MAX_BATCH_COMMANDS as u32, now));
+
+        limiter.set_rate(10);
+        assert!(limiter.acquire(8, now));
+        assert!(!limiter.acquire(3, now));
+        assert!(limiter.acquire(2, now));
+        assert!(!limiter.acquire(1, now + Duration::from_secs(5)));
+        assert!(limiter.acquire(1, now + Duration::from_secs(7)));
+        // burst is limited to one minute
+        assert!(limiter.acquire(10, now + Duration::from_secs(600)));
+        assert!(!limiter.acquire(1, now + Duration::from_secs(600)));
+    }
+
+    #[test]
+    fn timeout_fallback() {
+        let default = Duration::from_secs(300);
+        assert_eq!(command_timeout(None, default), default);
+        assert_eq!(command_timeout(Some(0), default), default);
+        assert_eq!(command_timeout(Some(5), default), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn exec_ns() {
+        let own = process::id();
+        assert_eq!(ExecNs::new(None, true), ExecNs::Agent);
+        assert_eq!(ExecNs::new(Some(own), true), ExecNs::Agent);
+        assert_eq!(ExecNs::new(Some(own + 1), false), ExecNs::Net(own + 1));
+        assert_eq!(ExecNs::new(Some(own + 1), true), ExecNs::Full(own + 1));
+
+        let select = |full: bool, types: &[&str]| {
+            let types: Vec<String> = types.iter().map(|t| t.to_string()).collect();
+            ExecNs::select(Some(own + 1), full, &types)
+        };
+        assert_eq!(select(true, &[]), Ok(ExecNs::Full(own + 1)));
+        assert_eq!(select(true, &["net"]), Ok(ExecNs::Net(own + 1)));
+        assert_eq!(
+            select(false, &["mnt", "net", "pid", "uts"]),
+            Ok(ExecNs::Full(own + 1))
+        );
+        let ns = select(false, &["pid", "mnt"]).unwrap();
+        assert_eq!(ns, ExecNs::Selected(own + 1, 0b1100));
+        assert_eq!(ns.ns_types().collect::<Vec<_>>(), vec!["pid", "mnt"]);
+        assert!(select(false, &["ipc"]).is_err());
+        assert_eq!(
+            ExecNs::select(Some(own), false, &["mnt".to_owned()]),
+            Ok(ExecNs::Agent)
+        );
+    }
+}
@@ -0,0 +1,138 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Only a fixed set of commands without params is run on windows, namespace related requests
+// are rejected
+
+use std::{
+    fs::File,
+    path::Path,
+    process::{self, Output},
+    sync::Arc,
+};
+
+use futures::future::{self, BoxFuture};
+use tokio::process::Command as TokioCommand;
+
+use super::*;
+use crate::config::RemoteExecLimits;
+
+// CREATE_NO_WINDOW, do not allocate a console for commands
+const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+pub(super) fn all_supported_commands() -> Vec<Command> {
+    ["tasklist", "ipconfig /all", "netstat -ano"]
+        .into_iter()
+        .map(|cmdline| Command {
+            cmdline: cmdline.into(),
+            output_format: OutputFormat::Text,
+            desc: "".into(),
+            command_type: CommandType::Linux,
+            cacheable: false,
+            full_ns: false,
+            param_rules: vec![],
+        })
+        .collect()
+}
+
+pub(super) struct NsFiles;
+
+impl NsFiles {
+    pub(super) fn open(_: &Command, exec_ns: ExecNs) -> std::result::Result<Self, String> {
+        match exec_ns {
+            ExecNs::Agent => Ok(Self),
+            _ => Err("namespaces are not supported on windows".to_owned()),
+        }
+    }
+
+    pub(super) fn cache_netns(&self) -> Option<u64> {
+        Some(0)
+    }
+}
+
+impl Responser {
+    // Limits of cpu time, memory and nice are not applied on windows
+    pub(super) fn spawn_command(
+        &self,
+        cmd: &Command,
+        params: &Params,
+        _: NsFiles,
+        _: &RemoteExecLimits,
+        _: &Arc<Progress>,
+        pending: impl FnOnce(BoxFuture<'static, Result<Output>>) -> PendingCommand,
+    ) -> std::result::Result<PendingCommand, String> {
+        let cmdline: &str = &cmd.cmdline;
+        let (program, cmd_args) = params.substitute(cmdline)?;
+        let child = TokioCommand::new(program)
+            .args(&cmd_args)
+            .creation_flags(CREATE_NO_WINDOW)
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped())
+            // killed when the pending command is cancelled or replaced
+            .kill_on_drop(true)
+            .spawn();
+        match child {
+            Ok(mut child) => {
+                let stdout = child.stdout.take().unwrap();
+                Ok(pending(Box::pin(wait_child(child))).with_stdout(stdout))
+            }
+            Err(e) => Err(format!("command '{}' execute failed: {}", cmdline, e)),
+        }
+    }
+
+    pub(super) fn check_exec(
+        &self,
+        cmd: &Command,
+        _: &Params,
+        exec_ns: ExecNs,
+        mut items: Vec<pb::CommandCheckItem>,
+    ) -> BoxFuture<'static, pb::CommandCheck> {
+        if exec_ns != ExecNs::Agent {
+            items.push(check_item(
+                "namespace",
+                false,
+                "namespaces are not supported on windows",
+            ));
+        }
+        let name = cmd.cmdline.split_whitespace().next().unwrap_or_default();
+        let found = std::env::var_os("PATH").and_then(|paths| {
+            std::env::split_paths(&paths)
+                .flat_map(|dir| [dir.join(name), dir.join(name).with_extension("exe")])
+                .find(|path| path.is_file())
+        });
+        items.push(match found {
+            Some(path) => check_item("binary", true, path.to_string_lossy()),
+            None => check_item("binary", false, format!("{} not found in PATH", name)),
+        });
+        Box::pin(future::ready(command_check(items)))
+    }
+}
+
+// There are no linux namespaces on windows
+pub(super) async fn ls_netns() -> Result<Vec<pb::LinuxNamespace>> {
+    Ok(vec![])
+}
+
+// Symbolic links are not followed
+pub(super) fn open_fetch_file(path: &str) -> std::io::Result<File> {
+    if Path::new(path).symlink_metadata()?.file_type().is_symlink() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "symbolic link not followed",
+        ));
+    }
+    File::open(path)
+}
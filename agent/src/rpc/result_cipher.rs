@@ -0,0 +1,151 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, X25519},
+    error::Unspecified,
+    hkdf::{Salt, HKDF_SHA256},
+    rand::SystemRandom,
+};
+
+const KDF_INFO: &[u8] = b"deepflow remote exec result";
+
+// Encrypts command result batches for the server holding the private key of
+// `server_public_key`:
+// - An ephemeral X25519 key pair is generated for each request
+// - key = HKDF-SHA256(salt = agent public key | server public key,
+//                     ikm = X25519 shared secret, info = KDF_INFO)
+// - Each batch is sealed by AES-256-GCM with the big-endian batch index as nonce,
+//   the tag is appended to the content
+pub struct ResultCipher {
+    key: LessSafeKey,
+    public_key: Vec<u8>,
+    index: u64,
+}
+
+impl ResultCipher {
+    pub const TAG_LEN: usize = 16;
+
+    pub fn new(server_public_key: &[u8]) -> Result<Self, String> {
+        if server_public_key.len() != 32 {
+            return Err(format!(
+                "invalid result public key length {}",
+                server_public_key.len()
+            ));
+        }
+        let rng = SystemRandom::new();
+        let private_key = EphemeralPrivateKey::generate(&X25519, &rng)
+            .map_err(|_| "generate ephemeral key failed".to_owned())?;
+        let public_key = private_key
+            .compute_public_key()
+            .map_err(|_| "compute ephemeral public key failed".to_owned())?
+            .as_ref()
+            .to_vec();
+        let key = agreement::agree_ephemeral(
+            private_key,
+            &UnparsedPublicKey::new(&X25519, server_public_key),
+            Unspecified,
+            |secret| derive_key(secret, &public_key, server_public_key),
+        )
+        .map_err(|_| "invalid result public key".to_owned())?;
+        Ok(Self {
+            key,
+            public_key,
+            index: 0,
+        })
+    }
+
+    // Ephemeral public key sent to the server for key agreement
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    pub fn seal(&mut self, mut content: Vec<u8>) -> Result<Vec<u8>, String> {
+        let nonce = batch_nonce(self.index);
+        self.index += 1;
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut content,
+            )
+            .map_err(|_| "encrypt command result failed".to_owned())?;
+        Ok(content)
+    }
+}
+
+fn batch_nonce(index: u64) -> [u8; NONCE_LEN] {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[NONCE_LEN - 8..].copy_from_slice(&index.to_be_bytes());
+    nonce
+}
+
+fn derive_key(
+    secret: &[u8],
+    agent_public_key: &[u8],
+    server_public_key: &[u8],
+) -> Result<LessSafeKey, Unspecified> {
+    let mut salt = Vec::with_capacity(agent_public_key.len() + server_public_key.len());
+    salt.extend_from_slice(agent_public_key);
+    salt.extend_from_slice(server_public_key);
+    let okm = Salt::new(HKDF_SHA256, &salt)
+        .extract(secret)
+        .expand(&[KDF_INFO], &AES_256_GCM)?;
+    Ok(LessSafeKey::new(UnboundKey::from(okm)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_and_open() {
+        let rng = SystemRandom::new();
+        let server_private_key = EphemeralPrivateKey::generate(&X25519, &rng).unwrap();
+        let server_public_key = server_private_key.compute_public_key().unwrap();
+        let server_public_key = server_public_key.as_ref().to_vec();
+
+        let mut cipher = ResultCipher::new(&server_public_key).unwrap();
+        let first = cipher.seal(b"first batch".to_vec()).unwrap();
+        let second = cipher.seal(b"second batch".to_vec()).unwrap();
+        assert_eq!(first.len(), b"first batch".len() + ResultCipher::TAG_LEN);
+
+        let key = agreement::agree_ephemeral(
+            server_private_key,
+            &UnparsedPublicKey::new(&X25519, cipher.public_key()),
+            Unspecified,
+            |secret| derive_key(secret, cipher.public_key(), &server_public_key),
+        )
+        .unwrap();
+        for (i, (mut sealed, plain)) in
+            [(first, &b"first batch"[..]), (second, &b"second batch"[..])]
+                .into_iter()
+                .enumerate()
+        {
+            let opened = key
+                .open_in_place(
+                    Nonce::assume_unique_for_key(batch_nonce(i as u64)),
+                    Aad::empty(),
+                    &mut sealed,
+                )
+                .unwrap();
+            assert_eq!(&opened[..], plain);
+        }
+
+        assert!(ResultCipher::new(&[0u8; 16]).is_err());
+    }
+}
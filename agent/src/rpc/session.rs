@@ -26,16 +26,17 @@ use md5::{Digest, Md5};
 use parking_lot::RwLock;
 use tonic::transport::Channel;
 
+use super::cert_pin::cert_verifier;
 use crate::{
     common::{DEFAULT_CONTROLLER_PORT, DEFAULT_CONTROLLER_TLS_PORT},
     exception::ExceptionHandler,
     trident::AgentId,
     utils::stats::{self, AtomicTimeStats},
 };
-use grpc::dial as grpc_dial;
+use grpc::{dial as grpc_dial, CertVerifier};
 use public::proto::trident::{self, Exception, Status};
 use public::{
-    counter::{Countable, Counter, CounterType, CounterValue, RefCountable},
+    counter::{Countable, Counter, CounterType, CounterValue, Histogram, RefCountable},
     proto::trident::PluginType,
 };
 
@@ -113,6 +114,7 @@ impl Config {
 pub struct Session {
     config: Arc<RwLock<Config>>,
     controller_cert_file_prefix: String,
+    cert_verifier: Option<CertVerifier>,
 
     server_dispatcher: RwLock<ServerDispatcher>,
 
@@ -167,6 +169,9 @@ macro_rules! sync_grpc_call {
         log::trace!("{} receive response", prefix);
         let now_elapsed = now.elapsed();
         $self.counters[$enpoint].delay.update(now_elapsed);
+        $self.counters[$enpoint]
+            .delay_histogram
+            .observe(now_elapsed.as_micros() as u64);
         if log::log_enabled!(log::Level::Debug) {
             debug!(
                 "{} latency {:?}ms request {}B response {}",
@@ -186,10 +191,13 @@ impl Session {
         tls_port: u16,
         timeout: Duration,
         controller_cert_file_prefix: String,
+        controller_cert_pins: &[String],
         controller_ips: Vec<String>,
         exception_handler: ExceptionHandler,
         stats_collector: &stats::Collector,
     ) -> Session {
+        let cert_verifier = cert_verifier(controller_cert_pins);
+
         let counters = (0..GRPC_CALL_ENDPOINTS.len())
             .into_iter()
             .map(|_| Arc::new(GrpcCallCounter::default()))
@@ -219,6 +227,7 @@ impl Session {
             exception_handler,
             counters,
             controller_cert_file_prefix,
+            cert_verifier,
         }
     }
 
@@ -234,7 +243,14 @@ impl Session {
     }
 
     async fn dial(&self, remote: &str, remote_port: u16, controller_cert_file_prefix: String) {
-        match grpc_dial(remote, remote_port, controller_cert_file_prefix).await {
+        match grpc_dial(
+            remote,
+            remote_port,
+            controller_cert_file_prefix,
+            self.cert_verifier.clone(),
+        )
+        .await
+        {
             Ok(channel) => *self.client.write() = Some(channel),
             Err(e) => {
                 self.exception_handler.set(Exception::ControllerSocketError);
@@ -635,9 +651,23 @@ impl ServerDispatcher {
     }
 }
 
-#[derive(Default)]
+// 1ms to 30s in µs
+const GRPC_DELAY_BUCKETS: [u64; 10] = [
+    1000, 5000, 10000, 50000, 100000, 500000, 1000000, 5000000, 10000000, 30000000,
+];
+
 pub struct GrpcCallCounter {
     pub delay: AtomicTimeStats,
+    pub delay_histogram: Histogram,
+}
+
+impl Default for GrpcCallCounter {
+    fn default() -> Self {
+        Self {
+            delay: Default::default(),
+            delay_histogram: Histogram::new("delay", &GRPC_DELAY_BUCKETS),
+        }
+    }
 }
 
 impl RefCountable for GrpcCallCounter {
@@ -650,7 +680,7 @@ impl RefCountable for GrpcCallCounter {
         } else {
             sum / delay_count
         };
-        vec![
+        let mut counters = vec![
             (
                 "max_delay",
                 CounterType::Gauged,
@@ -666,6 +696,8 @@ impl RefCountable for GrpcCallCounter {
                 CounterType::Gauged,
                 CounterValue::Unsigned(delay_count),
             ),
-        ]
+        ];
+        counters.append(&mut self.delay_histogram.get_counters());
+        counters
     }
 }
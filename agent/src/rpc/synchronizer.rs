@@ -17,7 +17,6 @@
 use std::collections::HashSet;
 use std::fs::{self, File};
 use std::io::{BufWriter, Write};
-use std::mem;
 use std::net::IpAddr;
 use std::path::PathBuf;
 use std::process::Command;
@@ -31,6 +30,7 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 #[cfg(unix)]
 use std::{fs::Permissions, os::unix::fs::PermissionsExt};
 
+use ipnet::IpNet;
 #[cfg(any(target_os = "linux"))]
 use k8s_openapi::api::apps::v1::DaemonSet;
 #[cfg(any(target_os = "linux"))]
@@ -43,32 +43,36 @@ use md5::{Digest, Md5};
 use parking_lot::{Mutex, RwLock, RwLockUpgradableReadGuard};
 use prost::Message;
 use rand::RngCore;
+use regex::Regex;
 use sysinfo::{System, SystemExt};
 use tokio::runtime::Runtime;
 use tokio::sync::{
     broadcast,
     mpsc::{self, UnboundedSender},
+    Notify,
 };
 use tokio::task::JoinHandle;
 use tokio::time;
 
 use super::{
     ntp::{NtpMode, NtpPacket, NtpTime},
+    upgrade::{self, BootState, UpgradeGuard},
     RPC_RETRY_INTERVAL,
 };
 
 use crate::common::endpoint::EPC_INTERNET;
 use crate::common::policy::Acl;
 use crate::common::policy::{Cidr, Container, IpGroupData, PeerConnection};
-use crate::common::NORMAL_EXIT_WITH_RESTART;
+use crate::common::{active_feature_flags, NORMAL_EXIT_WITH_RESTART};
 use crate::common::{FlowAclListener, PlatformData as VInterface, DEFAULT_CONTROLLER_PORT};
-use crate::config::RuntimeConfig;
+use crate::config::{FeatureFlagRollout, RuntimeConfig, YamlConfig};
 use crate::exception::ExceptionHandler;
 use crate::rpc::session::Session;
 use crate::trident::{self, AgentId, ChangedConfig, RunningMode, TridentState, VersionInfo};
 #[cfg(any(target_os = "linux"))]
 use crate::utils::environment::{get_current_k8s_image, get_k8s_namespace};
 use crate::utils::{
+    cloud_metadata::{self, CloudMetadata},
     command::get_hostname,
     environment::{
         get_executable_path, is_tt_pod, running_in_container, running_in_k8s,
@@ -162,6 +166,23 @@ pub struct Status {
     pub cidrs: Vec<Arc<Cidr>>,
     pub ip_groups: Vec<Arc<IpGroupData>>,
     pub acls: Vec<Arc<Acl>>,
+
+    pub cloud_metadata: Option<CloudMetadata>,
+
+    // host ip selection in sync request
+    pub exclude_interface_regex: Option<Regex>,
+    pub host_ip_cidrs: Vec<IpNet>,
+
+    // feature flags reported in sync request
+    pub feature_flags: Vec<String>,
+    pub feature_flag_rollouts: Vec<FeatureFlagRollout>,
+
+    // capture pause state set by controller, reported in sync request
+    pub capture_paused: bool,
+    pub ebpf_paused: bool,
+
+    // l7 protocols enabled by l7-protocol-enabled and l7-protocol-disabled
+    pub active_l7_protocols: Vec<String>,
 }
 
 impl Default for Status {
@@ -191,11 +212,43 @@ impl Default for Status {
             cidrs: Default::default(),
             ip_groups: Default::default(),
             acls: Default::default(),
+
+            cloud_metadata: None,
+
+            exclude_interface_regex: None,
+            host_ip_cidrs: vec![],
+
+            feature_flags: vec![],
+            feature_flag_rollouts: vec![],
+
+            capture_paused: false,
+            ebpf_paused: false,
+            active_l7_protocols: vec![],
         }
     }
 }
 
 impl Status {
+    fn update_host_ip_selection(&mut self, config: &YamlConfig) {
+        let regex = config.platform_sync_exclude_interface_regex.as_str();
+        match self.exclude_interface_regex.as_ref() {
+            Some(re) if re.as_str() == regex => (),
+            None if regex.is_empty() => (),
+            _ if regex.is_empty() => self.exclude_interface_regex = None,
+            _ => self.exclude_interface_regex = Regex::new(regex).ok(),
+        }
+        self.host_ip_cidrs = config
+            .platform_sync_host_ip_cidrs
+            .iter()
+            .filter_map(|c| c.parse().ok())
+            .collect();
+    }
+
+    fn update_feature_flags(&mut self, config: &YamlConfig) {
+        self.feature_flags = config.feature_flags.clone();
+        self.feature_flag_rollouts = config.feature_flag_rollouts.clone();
+    }
+
     fn update_platform_data(
         &mut self,
         version: u64,
@@ -458,6 +511,12 @@ pub struct Synchronizer {
     agent_mode: RunningMode,
     standalone_runtime_config: Option<PathBuf>,
     agent_id_tx: Arc<broadcast::Sender<AgentId>>,
+    // wakes up the sync loop before sync interval elapses
+    sync_trigger: Arc<Notify>,
+    // notified on each successful sync, used for upgrade health check
+    sync_succeeded: Arc<Notify>,
+    upgrade_public_key: Option<Arc<Vec<u8>>>,
+    disable_cloud_metadata: bool,
 }
 
 impl Synchronizer {
@@ -480,6 +539,8 @@ impl Synchronizer {
         standalone_runtime_config: Option<PathBuf>,
         agent_id_tx: Arc<broadcast::Sender<AgentId>>,
         ntp_diff: Arc<AtomicI64>,
+        upgrade_public_key: Option<Vec<u8>>,
+        disable_cloud_metadata: bool,
     ) -> Synchronizer {
         Synchronizer {
             static_config: Arc::new(StaticConfig {
@@ -514,6 +575,10 @@ impl Synchronizer {
             agent_mode,
             standalone_runtime_config,
             agent_id_tx,
+            sync_trigger: Default::default(),
+            sync_succeeded: Default::default(),
+            upgrade_public_key: upgrade_public_key.map(Arc::new),
+            disable_cloud_metadata,
         }
     }
 
@@ -525,6 +590,10 @@ impl Synchronizer {
         info!("Reset version of acls, groups and platform_data.");
     }
 
+    pub fn sync_trigger(&self) -> Arc<Notify> {
+        self.sync_trigger.clone()
+    }
+
     pub fn add_flow_acl_listener(&self, module: Box<dyn FlowAclListener>) {
         let mut listeners = self.flow_acl_listener.lock().unwrap();
         for item in listeners.iter() {
@@ -598,10 +667,34 @@ impl Synchronizer {
                 #[cfg(any(target_os = "windows", target_os = "android"))]
                 let addrs = public::utils::net::addr_list();
 
+                let excluded_if_indices = match status.exclude_interface_regex.as_ref() {
+                    Some(re) => {
+                        #[cfg(target_os = "linux")]
+                        let links = public::netns::link_list_in_netns(&public::netns::NsFile::Root);
+                        #[cfg(any(target_os = "windows", target_os = "android"))]
+                        let links = public::utils::net::link_list();
+
+                        links.map_or(HashSet::new(), |xs| {
+                            xs.into_iter()
+                                .filter(|x| re.is_match(&x.name))
+                                .map(|x| x.if_index)
+                                .collect()
+                        })
+                    }
+                    None => HashSet::new(),
+                };
+                let out_of_cidrs = |ip_addr: &IpAddr| {
+                    !status.host_ip_cidrs.is_empty()
+                        && !status.host_ip_cidrs.iter().any(|c| c.contains(ip_addr))
+                };
+
                 addrs.map_or(vec![], |xs| {
                     xs.into_iter()
                         .filter_map(|x| {
-                            if is_excluded_ip_addr(x.ip_addr) {
+                            if is_excluded_ip_addr(x.ip_addr)
+                                || excluded_if_indices.contains(&x.if_index)
+                                || out_of_cidrs(&x.ip_addr)
+                            {
                                 None
                             } else {
                                 Some(x.ip_addr.to_string())
@@ -619,6 +712,16 @@ impl Synchronizer {
             kubernetes_cluster_id: Some(static_config.kubernetes_cluster_id.clone()),
             kubernetes_cluster_name: static_config.kubernetes_cluster_name.clone(),
             kubernetes_force_watch: Some(running_in_only_watch_k8s_mode()),
+            cloud_metadata: status.cloud_metadata.as_ref().map(|m| m.into()),
+            active_feature_flags: active_feature_flags(
+                &status.feature_flags,
+                &status.feature_flag_rollouts,
+                &agent_id.to_string(),
+                &static_config.vtap_group_id_request,
+            ),
+            capture_paused: Some(status.capture_paused),
+            ebpf_paused: Some(status.ebpf_paused),
+            active_l7_protocols: status.active_l7_protocols.clone(),
             agent_unique_identifier: Some(tp::AgentIdentifier::from(
                 static_config.agent_unique_identifier,
             ) as i32),
@@ -787,6 +890,11 @@ impl Synchronizer {
         status_guard.ntp_enabled = runtime_config.ntp_enabled;
         status_guard.ntp_max_interval = runtime_config.yaml_config.ntp_max_interval;
         status_guard.ntp_min_interval = runtime_config.yaml_config.ntp_min_interval;
+        status_guard.update_host_ip_selection(&runtime_config.yaml_config);
+        status_guard.update_feature_flags(&runtime_config.yaml_config);
+        status_guard.capture_paused = runtime_config.capture_paused;
+        status_guard.ebpf_paused = runtime_config.ebpf_paused;
+        status_guard.active_l7_protocols = runtime_config.yaml_config.get_active_l7_protocols();
         let updated_platform = status_guard.get_platform_data(&resp);
         if updated_platform {
             status_guard.modify_platform(&macs, &runtime_config);
@@ -1215,6 +1323,7 @@ impl Synchronizer {
         session: &Session,
         new_revision: &str,
         agent_id: &AgentId,
+        public_key: Option<&[u8]>,
     ) -> Result<(), String> {
         if running_in_container() {
             info!("running in a non-k8s containter, exit directly and try to recreate myself using a new version docker image...");
@@ -1239,11 +1348,12 @@ impl Synchronizer {
         temp_path.set_extension("test");
         #[cfg(windows)]
         temp_path.set_extension("test.exe");
-        let mut backup_path = binary_path.clone();
-        backup_path.set_extension("bak");
+        let guard = UpgradeGuard::new(binary_path.clone());
+        let backup_path = guard.backup_path().to_path_buf();
 
         let mut first_message = true;
         let mut md5_sum = String::new();
+        let mut signature = vec![];
         let mut bytes = 0;
         let mut total_bytes = 0;
         let mut count = 0usize;
@@ -1268,6 +1378,7 @@ impl Synchronizer {
             if first_message {
                 first_message = false;
                 md5_sum = message.md5().to_owned();
+                signature = message.signature().to_vec();
                 total_bytes = message.total_len() as usize;
                 total_count = message.pkt_count() as usize;
             }
@@ -1301,10 +1412,22 @@ impl Synchronizer {
             ));
         }
 
+        // make sure the binary is on disk before replacing the old one
         writer
-            .flush()
-            .map_err(|e| format!("Flush {} failed: {:?}", temp_path.display(), e))?;
-        mem::drop(writer);
+            .into_inner()
+            .map_err(|e| format!("Flush {} failed: {:?}", temp_path.display(), e.error()))?
+            .sync_all()
+            .map_err(|e| format!("Sync {} failed: {:?}", temp_path.display(), e))?;
+
+        if let Some(public_key) = public_key {
+            if signature.is_empty() {
+                return Err("Binary signature required but not found in server response".to_owned());
+            }
+            let binary = fs::read(&temp_path)
+                .map_err(|e| format!("Read {} failed: {:?}", temp_path.display(), e))?;
+            upgrade::verify_signature(public_key, &binary, &signature)?;
+            info!("Binary signature verified");
+        }
 
         #[cfg(unix)]
         if let Err(e) = fs::set_permissions(&temp_path, Permissions::from_mode(0o755)) {
@@ -1327,6 +1450,12 @@ impl Synchronizer {
         // ignore file not exist and other errors
         let _ = fs::remove_file(&backup_path);
 
+        // keep the old binary in place until the new one is renamed over it
+        #[cfg(unix)]
+        if let Err(e) = fs::hard_link(&binary_path, &backup_path) {
+            return Err(format!("Backup old binary failed: {:?}", e));
+        }
+        #[cfg(windows)]
         if let Err(e) = fs::rename(&binary_path, &backup_path) {
             return Err(format!("Backup old binary failed: {:?}", e));
         }
@@ -1342,12 +1471,82 @@ impl Synchronizer {
             return Err(err_string);
         }
 
-        // ignore failure as upgrade succeeded anyway
-        let _ = fs::remove_file(backup_path);
+        // the backup is removed after the new binary passes health check
+        if let Err(e) = guard.arm() {
+            warn!(
+                "Upgraded binary will not be rolled back on failure: {:?}",
+                e
+            );
+            let _ = fs::remove_file(backup_path);
+        }
 
         Ok(())
     }
 
+    fn run_cloud_metadata_query(&self) {
+        if self.disable_cloud_metadata {
+            return;
+        }
+        let status = self.status.clone();
+        let sync_trigger = self.sync_trigger.clone();
+        self.runtime.spawn(async move {
+            let Some(metadata) = cloud_metadata::query().await else {
+                info!("no cloud instance metadata service found");
+                return;
+            };
+            info!("cloud instance metadata: {:?}", metadata);
+            status.write().cloud_metadata = Some(metadata);
+            // report to controller without waiting for the next sync
+            sync_trigger.notify_one();
+        });
+    }
+
+    fn run_upgrade_health_check(&self) {
+        let guard = match get_executable_path() {
+            Ok(path) => UpgradeGuard::new(path),
+            Err(e) => {
+                warn!(
+                    "Cannot get deepflow-agent path for upgrade health check: {:?}",
+                    e
+                );
+                return;
+            }
+        };
+        match guard.on_boot() {
+            Ok(BootState::Normal) => return,
+            Ok(BootState::Pending) => (),
+            Ok(BootState::RolledBack) => {
+                warn!("agent rolled back to previous binary, deepflow-agent restart...");
+                crate::utils::notify_exit(NORMAL_EXIT_WITH_RESTART);
+                return;
+            }
+            Err(e) => {
+                warn!("upgrade health check failed: {:?}", e);
+                return;
+            }
+        }
+        let sync_succeeded = self.sync_succeeded.clone();
+        self.runtime.spawn(async move {
+            if time::timeout(upgrade::HEALTH_CHECK_WINDOW, sync_succeeded.notified())
+                .await
+                .is_ok()
+            {
+                guard.confirm();
+                return;
+            }
+            error!(
+                "upgraded binary failed to sync with controller in {:?}, rolling back",
+                upgrade::HEALTH_CHECK_WINDOW
+            );
+            if let Err(e) = guard.rollback() {
+                error!("rollback failed: {:?}", e);
+                return;
+            }
+            warn!("agent rolled back to previous binary, deepflow-agent restart...");
+            crate::utils::notify_exit(NORMAL_EXIT_WITH_RESTART);
+        });
+    }
+
     fn run_standalone(&self) {
         let running = self.running.clone();
         let trident_state = self.trident_state.clone();
@@ -1418,6 +1617,9 @@ impl Synchronizer {
         let exception_handler = self.exception_handler.clone();
         let ntp_diff = self.ntp_diff.clone();
         let ntp_state = self.ntp_state.clone();
+        let sync_trigger = self.sync_trigger.clone();
+        let sync_succeeded = self.sync_succeeded.clone();
+        let upgrade_public_key = self.upgrade_public_key.clone();
         self.threads.lock().push(self.runtime.spawn(async move {
             let mut grpc_failed_count = 0;
             while running.load(Ordering::SeqCst) {
@@ -1471,6 +1673,7 @@ impl Synchronizer {
                 }
                 session.set_request_failed(false);
                 grpc_failed_count = 0;
+                sync_succeeded.notify_one();
 
                 Self::on_response(
                     session.get_current_server(),
@@ -1510,7 +1713,7 @@ impl Synchronizer {
                         #[cfg(any(target_os = "windows", target_os = "android"))]
                         warn!("does not support upgrading environment");
                     } else {
-                        match Self::upgrade(&running, &session, &revision, &id).await {
+                        match Self::upgrade(&running, &session, &revision, &id, upgrade_public_key.as_deref().map(Vec::as_slice)).await {
                             Ok(_) => {
                                 let (ts, cvar) = &*trident_state;
                                 *ts.lock().unwrap() = trident::State::Terminated;
@@ -1538,7 +1741,10 @@ impl Synchronizer {
                     info!("sync interval set to {:?}", sync_interval);
                 }
 
-                time::sleep(sync_interval).await;
+                tokio::select! {
+                    _ = time::sleep(sync_interval) => (),
+                    _ = sync_trigger.notified() => info!("sync triggered before sync interval elapses"),
+                }
             }
         }));
     }
@@ -1567,6 +1773,8 @@ impl Synchronizer {
         });
         match self.agent_mode {
             RunningMode::Managed => {
+                self.run_upgrade_health_check();
+                self.run_cloud_metadata_query();
                 self.run_ntp_sync();
                 let esc_tx = self.run_escape_timer();
                 self.run_triggered_session(esc_tx.clone());
@@ -0,0 +1,156 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::{info, warn};
+use ring::signature::{UnparsedPublicKey, ED25519};
+
+// The new binary is confirmed by the first successful sync within the window
+pub const HEALTH_CHECK_WINDOW: Duration = Duration::from_secs(300);
+// Roll back if the new binary keeps failing before the health check completes
+const MAX_BOOT_ATTEMPTS: u32 = 3;
+
+pub fn verify_signature(public_key: &[u8], binary: &[u8], signature: &[u8]) -> Result<(), String> {
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(binary, signature)
+        .map_err(|_| "Binary signature verification failed".to_owned())
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BootState {
+    Normal,
+    // restarted with the upgraded binary, waiting for health check
+    Pending,
+    // previous binary restored, agent should restart
+    RolledBack,
+}
+
+// Keeps the previous binary as backup after upgrade, and tracks the boot attempts
+// of the new binary in a marker file until it's confirmed healthy.
+pub struct UpgradeGuard {
+    binary_path: PathBuf,
+    backup_path: PathBuf,
+    marker_path: PathBuf,
+}
+
+impl UpgradeGuard {
+    pub fn new(binary_path: PathBuf) -> Self {
+        Self {
+            backup_path: binary_path.with_extension("bak"),
+            marker_path: binary_path.with_extension("upgrading"),
+            binary_path,
+        }
+    }
+
+    pub fn backup_path(&self) -> &Path {
+        &self.backup_path
+    }
+
+    // Called after the new binary is in place and before restarting
+    pub fn arm(&self) -> io::Result<()> {
+        fs::write(&self.marker_path, "0")
+    }
+
+    pub fn on_boot(&self) -> io::Result<BootState> {
+        let attempts = match fs::read_to_string(&self.marker_path) {
+            Ok(s) => s.trim().parse::<u32>().unwrap_or(MAX_BOOT_ATTEMPTS) + 1,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BootState::Normal),
+            Err(e) => return Err(e),
+        };
+        if attempts > MAX_BOOT_ATTEMPTS {
+            warn!(
+                "upgraded binary failed to start {} times, rolling back",
+                MAX_BOOT_ATTEMPTS
+            );
+            self.rollback()?;
+            return Ok(BootState::RolledBack);
+        }
+        fs::write(&self.marker_path, attempts.to_string())?;
+        info!(
+            "upgraded binary boot attempt {}/{}, health check in {:?}",
+            attempts, MAX_BOOT_ATTEMPTS, HEALTH_CHECK_WINDOW
+        );
+        Ok(BootState::Pending)
+    }
+
+    pub fn confirm(&self) {
+        info!("upgraded binary passed health check");
+        // ignore failures as the new binary works anyway
+        let _ = fs::remove_file(&self.backup_path);
+        let _ = fs::remove_file(&self.marker_path);
+    }
+
+    pub fn rollback(&self) -> io::Result<()> {
+        if self.backup_path.exists() {
+            fs::rename(&self.backup_path, &self.binary_path)?;
+            info!("previous binary restored to {}", self.binary_path.display());
+        } else {
+            warn!(
+                "backup binary {} not found, rollback skipped",
+                self.backup_path.display()
+            );
+        }
+        fs::remove_file(&self.marker_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+
+    #[test]
+    fn signature() {
+        let rng = SystemRandom::new();
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let public_key = key_pair.public_key().as_ref();
+        assert_eq!(public_key.len(), 32);
+
+        let signature = key_pair.sign(b"binary");
+        assert!(verify_signature(public_key, b"binary", signature.as_ref()).is_ok());
+        assert!(verify_signature(public_key, b"binaries", signature.as_ref()).is_err());
+    }
+
+    #[test]
+    fn rollback_after_boot_attempts() {
+        let dir = std::env::temp_dir().join(format!("upgrade-guard-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let guard = UpgradeGuard::new(dir.join("deepflow-agent"));
+        fs::write(&guard.binary_path, "new").unwrap();
+        fs::write(guard.backup_path(), "old").unwrap();
+
+        assert_eq!(guard.on_boot().unwrap(), BootState::Normal);
+        guard.arm().unwrap();
+        for _ in 0..MAX_BOOT_ATTEMPTS {
+            assert_eq!(guard.on_boot().unwrap(), BootState::Pending);
+        }
+        assert_eq!(guard.on_boot().unwrap(), BootState::RolledBack);
+        assert_eq!(fs::read_to_string(&guard.binary_path).unwrap(), "old");
+        assert_eq!(guard.on_boot().unwrap(), BootState::Normal);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+use log::info;
+use public::{sender::SendMessageType, LeakyBucket};
+
+use crate::config::{SenderBandwidthQuota, SignalClass};
+use crate::utils::stats::{Counter, CounterType, CounterValue, RefCountable};
+
+// Each priority level above a signal class reserves 1/RESERVE_DIVISOR second of the
+// total bandwidth, so that lower priority classes are shed first when the uplink is busy
+const RESERVE_DIVISOR: u64 = 10;
+
+// Agent stats, logs and events are not in any class and never shed
+pub fn signal_class(msg_type: SendMessageType) -> Option<SignalClass> {
+    match msg_type {
+        SendMessageType::Metrics
+        | SendMessageType::Prometheus
+        | SendMessageType::Telegraf
+        | SendMessageType::Statsd => Some(SignalClass::Metrics),
+        SendMessageType::TaggedFlow => Some(SignalClass::FlowLog),
+        SendMessageType::ProtocolLog
+        | SendMessageType::OpenTelemetry
+        | SendMessageType::OpenTelemetryCompressed
+        | SendMessageType::ApplicationLog => Some(SignalClass::L7Log),
+        SendMessageType::Profile => Some(SignalClass::Profile),
+        SendMessageType::RawPcap | SendMessageType::PacketSequenceBlock => Some(SignalClass::Pcap),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ShedCounter {
+    pub shed: AtomicU64,
+    pub shed_bytes: AtomicU64,
+}
+
+impl RefCountable for ShedCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "shed",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.shed.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "shed-bytes",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.shed_bytes.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
+struct ClassQuota {
+    bucket: LeakyBucket,
+    // tokens of the total bucket left for classes with higher priorities, unit: bit
+    reserved: AtomicU64,
+    counter: Arc<ShedCounter>,
+}
+
+// Bandwidth quotas shared by all uniform senders
+pub struct BandwidthQuota {
+    total: LeakyBucket,
+    classes: Vec<ClassQuota>,
+    config: Mutex<SenderBandwidthQuota>,
+}
+
+impl BandwidthQuota {
+    pub fn new(config: &SenderBandwidthQuota) -> Self {
+        let quota = Self {
+            total: LeakyBucket::new(None),
+            classes: SignalClass::ALL
+                .iter()
+                .map(|_| ClassQuota {
+                    bucket: LeakyBucket::new(None),
+                    reserved: AtomicU64::new(0),
+                    counter: Default::default(),
+                })
+                .collect(),
+            config: Mutex::new(Default::default()),
+        };
+        quota.update(config);
+        quota
+    }
+
+    pub fn counters(&self) -> impl Iterator<Item = (SignalClass, &Arc<ShedCounter>)> {
+        SignalClass::ALL
+            .into_iter()
+            .zip(self.classes.iter().map(|c| &c.counter))
+    }
+
+    // Classes without quota have the lowest priority and are only limited by the total bandwidth
+    fn priority(config: &SenderBandwidthQuota, class: SignalClass) -> u16 {
+        config
+            .quotas
+            .iter()
+            .find(|q| q.signal == class)
+            .map(|q| q.priority as u16)
+            .unwrap_or(u8::MAX as u16 + 1)
+    }
+
+    pub fn update(&self, config: &SenderBandwidthQuota) {
+        let mut current = self.config.lock().unwrap();
+        if *current == *config {
+            return;
+        }
+        let total = config.total_mbps << 20;
+        self.total.set_rate(Some(total));
+        let mut priorities: Vec<u16> = SignalClass::ALL
+            .iter()
+            .map(|c| Self::priority(config, *c))
+            .collect();
+        priorities.sort_unstable();
+        priorities.dedup();
+        for (class, quota) in SignalClass::ALL.iter().zip(self.classes.iter()) {
+            let rate = config
+                .quotas
+                .iter()
+                .find(|q| q.signal == *class)
+                .map(|q| q.mbps << 20)
+                .unwrap_or_default();
+            quota.bucket.set_rate(Some(rate));
+            let priority = Self::priority(config, *class);
+            let rank = priorities.iter().filter(|p| **p < priority).count() as u64;
+            quota
+                .reserved
+                .store(rank * total / RESERVE_DIVISOR, Ordering::Relaxed);
+        }
+        info!("sender bandwidth quota set to {:?}", config);
+        *current = config.clone();
+    }
+
+    // Returns false if the data should be shed
+    pub fn acquire(&self, msg_type: SendMessageType, size: usize) -> bool {
+        let Some(class) = signal_class(msg_type) else {
+            return true;
+        };
+        let quota = &self.classes[class as usize];
+        let bits = size as u64 * 8;
+        // quota of the class is consumed even if the total bandwidth is not enough,
+        // as the data is shed anyway
+        if quota.bucket.acquire(bits)
+            && self
+                .total
+                .acquire_reserved(bits, quota.reserved.load(Ordering::Relaxed))
+        {
+            return true;
+        }
+        quota.counter.shed.fetch_add(1, Ordering::Relaxed);
+        quota
+            .counter
+            .shed_bytes
+            .fetch_add(size as u64, Ordering::Relaxed);
+        false
+    }
+}
+
+impl Default for BandwidthQuota {
+    fn default() -> Self {
+        Self::new(&Default::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{thread, time::Duration};
+
+    use crate::config::SignalQuota;
+
+    #[test]
+    fn shedding() {
+        let quota = BandwidthQuota::new(&SenderBandwidthQuota {
+            total_mbps: 1,
+            quotas: vec![
+                SignalQuota {
+                    signal: SignalClass::Metrics,
+                    priority: 0,
+                    mbps: 0,
+                },
+                SignalQuota {
+                    signal: SignalClass::Pcap,
+                    priority: 1,
+                    mbps: 0,
+                },
+                SignalQuota {
+                    signal: SignalClass::Profile,
+                    priority: 1,
+                    mbps: 1,
+                },
+            ],
+        });
+        thread::sleep(Duration::from_millis(10));
+        let reserved: Vec<u64> = quota
+            .classes
+            .iter()
+            .map(|c| c.reserved.load(Ordering::Relaxed))
+            .collect();
+        let reserve = |rank: u64| rank * (1 << 20) / RESERVE_DIVISOR;
+        // metrics, flow-log, l7-log, profile, pcap
+        assert_eq!(
+            reserved,
+            vec![0, reserve(2), reserve(2), reserve(1), reserve(1)]
+        );
+        let step = reserve(1);
+
+        // stats are never shed
+        assert!(quota.acquire(SendMessageType::DeepflowStats, 1 << 20));
+        // leaves 1.5 steps of the total bandwidth
+        assert!(quota.acquire(
+            SendMessageType::Metrics,
+            ((1 << 20) - step * 3 / 2) as usize / 8
+        ));
+        // l7 logs are shed before pcap and metrics
+        assert!(!quota.acquire(SendMessageType::ProtocolLog, 1));
+        assert!(quota.acquire(SendMessageType::RawPcap, 1));
+        assert!(quota.acquire(SendMessageType::Metrics, 1));
+        assert!(!quota.acquire(SendMessageType::RawPcap, step as usize / 8));
+        assert!(quota.acquire(SendMessageType::Metrics, step as usize / 8));
+        let counters: Vec<u64> = quota
+            .counters()
+            .map(|(_, c)| c.shed.load(Ordering::Relaxed))
+            .collect();
+        assert_eq!(counters, vec![0, 0, 1, 0, 1]);
+
+        quota.update(&Default::default());
+        assert!(quota.acquire(SendMessageType::ProtocolLog, 1 << 30));
+    }
+}
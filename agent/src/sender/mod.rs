@@ -17,7 +17,11 @@
 use std::sync::atomic::{AtomicU8, Ordering};
 
 // NpbBandwidthWatcher NewFragmenterBuilder NewCompressorBuilder NewPCapBuilder NewUniformCollectSender
+pub(crate) mod bandwidth_quota;
+pub(crate) mod npb_cipher;
+pub mod npb_health;
 pub mod npb_sender;
+mod npb_shaper;
 mod tcp_packet;
 pub(crate) mod uniform_sender;
 
@@ -0,0 +1,253 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io::{Error as IOError, ErrorKind, Result as IOResult};
+
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    hkdf::{Salt, HKDF_SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::common::{
+    enums::IpProtocol, vxlan, ETH_HEADER_SIZE, IPV4_PROTO_OFFSET, IPV4_TOTAL_LENGTH_OFFSET,
+    IPV6_PAYLOAD_LENGTH_OFFSET, IPV6_PROTO_OFFSET, UDP6_CHKSUM_OFFSET, UDP6_LENGTH_OFFSET,
+    UDP6_PACKET_SIZE, UDP_CHKSUM_OFFSET, UDP_HEADER_SIZE, UDP_LENGTH_OFFSET, UDP_PACKET_SIZE,
+    VXLAN_HEADER_SIZE,
+};
+
+const SESSION_SALT_LEN: usize = 16;
+const KDF_INFO: &[u8] = b"deepflow npb vxlan";
+
+// Encrypts the overlay packet carried by VXLAN with AES-256-GCM:
+// - A random session salt is generated on creation
+// - key = HKDF-SHA256(salt = session salt, ikm = pre-shared key, info = KDF_INFO)
+// - The nonce is the big-endian packet counter of the session
+//
+// Encrypted VXLAN payload:
+// +------------------+------------+-----------------------------+------------+
+// | session salt 16B | nonce 12B  | encrypted overlay packet    | tag 16B    |
+// +------------------+------------+-----------------------------+------------+
+//
+// Each session has its own key, so nonces never repeat under a key across agents
+// and restarts. A new session is started before the counter wraps. The VNI of the
+// VXLAN header is authenticated as additional data.
+pub struct NpbCipher {
+    pre_shared_key: Vec<u8>,
+    rng: SystemRandom,
+    key: LessSafeKey,
+    session_salt: [u8; SESSION_SALT_LEN],
+    counter: u64,
+}
+
+impl NpbCipher {
+    pub const OVERHEAD: usize = SESSION_SALT_LEN + NONCE_LEN + 16;
+
+    pub fn new(pre_shared_key: &str) -> Result<Self, String> {
+        if pre_shared_key.is_empty() {
+            return Err("empty npb encryption key".to_owned());
+        }
+        let rng = SystemRandom::new();
+        let (key, session_salt) = new_session(&rng, pre_shared_key.as_bytes())?;
+        Ok(Self {
+            pre_shared_key: pre_shared_key.as_bytes().to_vec(),
+            rng,
+            key,
+            session_salt,
+            counter: 0,
+        })
+    }
+
+    fn next_nonce(&mut self) -> Result<[u8; NONCE_LEN], String> {
+        if self.counter == u64::MAX {
+            (self.key, self.session_salt) = new_session(&self.rng, &self.pre_shared_key)?;
+            self.counter = 0;
+        }
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce[NONCE_LEN - 8..].copy_from_slice(&self.counter.to_be_bytes());
+        self.counter += 1;
+        Ok(nonce)
+    }
+
+    // Encrypt the VXLAN payload in place and fix up the underlay length fields,
+    // packets of other tunnel types are rejected to avoid sending them in plain text.
+    pub fn seal(
+        &mut self,
+        underlay_l2_opt_size: usize,
+        underlay_is_ipv6: bool,
+        packet: &mut Vec<u8>,
+    ) -> IOResult<()> {
+        let (protocol_offset, udp_size) = if underlay_is_ipv6 {
+            (IPV6_PROTO_OFFSET, UDP6_PACKET_SIZE)
+        } else {
+            (IPV4_PROTO_OFFSET, UDP_PACKET_SIZE)
+        };
+        let vxlan_offset = udp_size + underlay_l2_opt_size;
+        let payload_offset = vxlan_offset + VXLAN_HEADER_SIZE;
+        if packet.len() < payload_offset
+            || packet[protocol_offset + underlay_l2_opt_size] != IpProtocol::UDP
+        {
+            return Err(IOError::new(
+                ErrorKind::InvalidInput,
+                "npb encryption only supports vxlan tunnel",
+            ));
+        }
+
+        let mut aad = [0u8; 4];
+        aad.copy_from_slice(
+            &packet[vxlan_offset + vxlan::VNI_OFFSET..vxlan_offset + vxlan::VNI_OFFSET + 4],
+        );
+        let nonce = self
+            .next_nonce()
+            .map_err(|e| IOError::new(ErrorKind::Other, e))?;
+        let mut payload = packet.split_off(payload_offset);
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(aad),
+                &mut payload,
+            )
+            .map_err(|_| IOError::new(ErrorKind::Other, "npb encryption failed"))?;
+        packet.extend_from_slice(&self.session_salt);
+        packet.extend_from_slice(&nonce);
+        packet.extend_from_slice(&payload);
+
+        let ip_header_offset = ETH_HEADER_SIZE + underlay_l2_opt_size;
+        let udp_length =
+            (packet.len() - (udp_size - UDP_HEADER_SIZE + underlay_l2_opt_size)) as u16;
+        if underlay_is_ipv6 {
+            let offset = IPV6_PAYLOAD_LENGTH_OFFSET + underlay_l2_opt_size;
+            packet[offset..offset + 2].copy_from_slice(&udp_length.to_be_bytes());
+            let offset = UDP6_LENGTH_OFFSET + underlay_l2_opt_size;
+            packet[offset..offset + 2].copy_from_slice(&udp_length.to_be_bytes());
+            // recalculated by the sender
+            let offset = UDP6_CHKSUM_OFFSET + underlay_l2_opt_size;
+            packet[offset..offset + 2].copy_from_slice(&[0, 0]);
+        } else {
+            let offset = IPV4_TOTAL_LENGTH_OFFSET + underlay_l2_opt_size;
+            let total_length = (packet.len() - ip_header_offset) as u16;
+            packet[offset..offset + 2].copy_from_slice(&total_length.to_be_bytes());
+            let offset = UDP_LENGTH_OFFSET + underlay_l2_opt_size;
+            packet[offset..offset + 2].copy_from_slice(&udp_length.to_be_bytes());
+            // udp checksum is optional in ipv4
+            let offset = UDP_CHKSUM_OFFSET + underlay_l2_opt_size;
+            packet[offset..offset + 2].copy_from_slice(&[0, 0]);
+        }
+        Ok(())
+    }
+}
+
+fn derive_key(pre_shared_key: &[u8], session_salt: &[u8]) -> Option<LessSafeKey> {
+    let okm = Salt::new(HKDF_SHA256, session_salt)
+        .extract(pre_shared_key)
+        .expand(&[KDF_INFO], &AES_256_GCM)
+        .ok()?;
+    Some(LessSafeKey::new(UnboundKey::from(okm)))
+}
+
+fn new_session(
+    rng: &SystemRandom,
+    pre_shared_key: &[u8],
+) -> Result<(LessSafeKey, [u8; SESSION_SALT_LEN]), String> {
+    let mut session_salt = [0u8; SESSION_SALT_LEN];
+    rng.fill(&mut session_salt)
+        .map_err(|_| "generate npb encryption session salt failed".to_owned())?;
+    let key = derive_key(pre_shared_key, &session_salt)
+        .ok_or_else(|| "derive npb encryption key failed".to_owned())?;
+    Ok((key, session_salt))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Decrypt the payload sealed by `NpbCipher::seal`
+    fn open_vxlan_payload(
+        pre_shared_key: &str,
+        vni: [u8; 4],
+        payload: &mut [u8],
+    ) -> Option<&mut [u8]> {
+        if payload.len() < NpbCipher::OVERHEAD {
+            return None;
+        }
+        let (session_salt, payload) = payload.split_at_mut(SESSION_SALT_LEN);
+        let key = derive_key(pre_shared_key.as_bytes(), session_salt)?;
+        let (nonce, in_out) = payload.split_at_mut(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+        key.open_in_place(nonce, Aad::from(vni), in_out).ok()
+    }
+
+    #[test]
+    fn seal_and_open() {
+        let overlay = b"overlay packet".to_vec();
+        let mut packet = vec![0u8; UDP_PACKET_SIZE + VXLAN_HEADER_SIZE];
+        packet[IPV4_PROTO_OFFSET] = u8::from(IpProtocol::UDP);
+        packet[UDP_PACKET_SIZE + vxlan::VNI_OFFSET..UDP_PACKET_SIZE + vxlan::VNI_OFFSET + 4]
+            .copy_from_slice(&[0, 0, 1, 0]);
+        packet.extend_from_slice(&overlay);
+
+        let mut cipher = NpbCipher::new("secret").unwrap();
+        cipher.seal(0, false, &mut packet).unwrap();
+        let payload_offset = UDP_PACKET_SIZE + VXLAN_HEADER_SIZE;
+        assert_eq!(
+            packet.len(),
+            payload_offset + overlay.len() + NpbCipher::OVERHEAD
+        );
+        assert_eq!(
+            u16::from_be_bytes([packet[UDP_LENGTH_OFFSET], packet[UDP_LENGTH_OFFSET + 1]]) as usize,
+            packet.len() - UDP_PACKET_SIZE + UDP_HEADER_SIZE
+        );
+        assert_ne!(
+            &packet[payload_offset + SESSION_SALT_LEN + NONCE_LEN..][..overlay.len()],
+            &overlay[..]
+        );
+
+        let mut wrong_vni = packet[payload_offset..].to_vec();
+        assert!(open_vxlan_payload("secret", [0, 0, 2, 0], &mut wrong_vni).is_none());
+        let mut wrong_key = packet[payload_offset..].to_vec();
+        assert!(open_vxlan_payload("other", [0, 0, 1, 0], &mut wrong_key).is_none());
+        let sealed = packet[payload_offset..].to_vec();
+        let plain = open_vxlan_payload("secret", [0, 0, 1, 0], &mut packet[payload_offset..]);
+        assert_eq!(plain.unwrap(), &overlay[..]);
+
+        // another session of the same key never shares the key and nonce
+        let other = NpbCipher::new("secret").unwrap();
+        assert_ne!(other.session_salt, cipher.session_salt);
+        let mut other_salt = sealed.clone();
+        other_salt[..SESSION_SALT_LEN].copy_from_slice(&other.session_salt);
+        assert!(open_vxlan_payload("secret", [0, 0, 1, 0], &mut other_salt).is_none());
+    }
+
+    #[test]
+    fn new_session_before_counter_wraps() {
+        let mut cipher = NpbCipher::new("secret").unwrap();
+        let session_salt = cipher.session_salt;
+        assert_eq!(cipher.next_nonce().unwrap()[NONCE_LEN - 1], 0);
+        cipher.counter = u64::MAX;
+        assert_eq!(cipher.next_nonce().unwrap(), [0u8; NONCE_LEN]);
+        assert_ne!(cipher.session_salt, session_salt);
+        assert_eq!(cipher.counter, 1);
+    }
+
+    #[test]
+    fn reject_non_vxlan() {
+        let mut packet = vec![0u8; UDP_PACKET_SIZE + VXLAN_HEADER_SIZE];
+        packet[IPV4_PROTO_OFFSET] = u8::from(IpProtocol::GRE);
+        let mut cipher = NpbCipher::new("secret").unwrap();
+        assert!(cipher.seal(0, false, &mut packet).is_err());
+        assert!(NpbCipher::new("").is_err());
+    }
+}
@@ -0,0 +1,794 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::mem::MaybeUninit;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::process;
+use std::slice;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    Arc, Mutex, RwLock, Weak,
+};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+
+use crate::common::platform_data::PlatformData;
+use crate::common::policy::{Acl, Cidr, IpGroupData, PeerConnection};
+use crate::common::{FlowAclListener, FlowAclListenerId};
+use crate::config::{NpbHealthCheck, NpbProbeProtocol};
+use crate::exception::ExceptionHandler;
+use crate::utils::stats::{self, Countable, Counter, CounterType, CounterValue, RefCountable};
+use npb_handler::NOT_SUPPORT;
+use public::proto::{common::TridentType, trident::Exception};
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+const ICMP_ECHO_HEADER_SIZE: usize = 8;
+const ECHO_PAYLOAD: &[u8] = b"deepflow";
+
+// endpoints not used by any tunnel in this period are no longer probed
+const ENDPOINT_AGING_TIME: Duration = Duration::from_secs(300);
+const RECV_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+struct Endpoint {
+    // backups of this endpoint when used as tunnel ip
+    backups: Vec<IpAddr>,
+    alive: AtomicBool,
+    // consecutive lost probes
+    lost: AtomicU32,
+    used: AtomicBool,
+
+    probe_lost: AtomicU64,
+    failover: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Endpoint {
+    fn new(backups: Vec<IpAddr>) -> Self {
+        Self {
+            backups,
+            // alive until proved otherwise
+            alive: AtomicBool::new(true),
+            lost: AtomicU32::new(0),
+            used: AtomicBool::new(false),
+            probe_lost: AtomicU64::new(0),
+            failover: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+}
+
+impl RefCountable for Endpoint {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "alive",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.alive.load(Ordering::Relaxed) as u64),
+            ),
+            (
+                "probe_lost",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.probe_lost.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "failover",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.failover.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "dropped",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.dropped.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
+struct Config {
+    source: NpbHealthCheck,
+    backups: HashMap<IpAddr, Vec<IpAddr>>,
+    // backups defined in npb policy take precedence over those in config
+    policy_backups: HashMap<IpAddr, Vec<IpAddr>>,
+}
+
+impl Config {
+    fn backups(&self, tunnel_ip: &IpAddr) -> Vec<IpAddr> {
+        self.policy_backups
+            .get(tunnel_ip)
+            .or_else(|| self.backups.get(tunnel_ip))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+impl From<&NpbHealthCheck> for Config {
+    fn from(config: &NpbHealthCheck) -> Self {
+        let mut backups = HashMap::new();
+        for b in config.backups.iter() {
+            let Ok(tunnel_ip) = b.tunnel_ip.parse::<IpAddr>() else {
+                warn!(
+                    "Npb health check ignored invalid tunnel ip {}.",
+                    b.tunnel_ip
+                );
+                continue;
+            };
+            backups.insert(
+                tunnel_ip,
+                b.backup_ips
+                    .iter()
+                    .filter_map(|ip| ip.parse::<IpAddr>().ok())
+                    .filter(|ip| ip.is_ipv6() == tunnel_ip.is_ipv6())
+                    .collect(),
+            );
+        }
+        Self {
+            source: config.clone(),
+            backups,
+            policy_backups: HashMap::new(),
+        }
+    }
+}
+
+// Probes npb tunnel endpoints with ICMP echo or UDP heartbeat and fails over to backup
+// endpoints
+//
+// An endpoint is down after `threshold` consecutive lost probes and up again on the
+// first reply. Tunnel endpoints are probed once packets are sent to them, together
+// with their backups. Endpoints that can not be probed, e.g. ICMP without CAP_NET_RAW,
+// are always considered alive.
+pub struct NpbHealthChecker {
+    endpoints: Arc<RwLock<HashMap<IpAddr, Arc<Endpoint>>>>,
+    config: Arc<RwLock<Config>>,
+    enabled: Arc<AtomicBool>,
+    is_running: Arc<AtomicBool>,
+    exception_handler: ExceptionHandler,
+    stats_collector: Arc<stats::Collector>,
+
+    thread_handler: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl NpbHealthChecker {
+    pub fn new(
+        config: &NpbHealthCheck,
+        exception_handler: ExceptionHandler,
+        stats_collector: Arc<stats::Collector>,
+    ) -> Self {
+        Self {
+            endpoints: Default::default(),
+            config: Arc::new(RwLock::new(Config::from(config))),
+            enabled: Arc::new(AtomicBool::new(config.enabled)),
+            is_running: Arc::new(AtomicBool::new(false)),
+            exception_handler,
+            stats_collector,
+            thread_handler: Mutex::new(None),
+        }
+    }
+
+    // Returns the endpoint to send packets of the tunnel to, None if the tunnel
+    // endpoint and all its backups are down
+    pub fn select(&self, tunnel_ip: &IpAddr) -> Option<IpAddr> {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return Some(*tunnel_ip);
+        }
+        let endpoints = self.endpoints.read().unwrap();
+        let Some(endpoint) = endpoints.get(tunnel_ip) else {
+            drop(endpoints);
+            self.add(tunnel_ip);
+            return Some(*tunnel_ip);
+        };
+        if !endpoint.used.load(Ordering::Relaxed) {
+            endpoint.used.store(true, Ordering::Relaxed);
+        }
+        if endpoint.alive.load(Ordering::Relaxed) {
+            return Some(*tunnel_ip);
+        }
+
+        let backup = endpoint.backups.iter().find(|ip| {
+            endpoints
+                .get(ip)
+                .map(|e| e.alive.load(Ordering::Relaxed))
+                .unwrap_or_default()
+        });
+        match backup {
+            Some(ip) => {
+                endpoint.failover.fetch_add(1, Ordering::Relaxed);
+                Some(*ip)
+            }
+            None => {
+                endpoint.dropped.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn add(&self, tunnel_ip: &IpAddr) {
+        let config = self.config.read().unwrap();
+        let backups = config.backups(tunnel_ip);
+        let mut endpoints = self.endpoints.write().unwrap();
+        for ip in iter::once(tunnel_ip).chain(backups.iter()) {
+            if endpoints.contains_key(ip) {
+                continue;
+            }
+            let endpoint = Arc::new(Endpoint::new(config.backups(ip)));
+            self.stats_collector.register_countable(
+                &stats::SingleTagModule("npb_endpoint", "ip", ip),
+                Countable::Ref(Arc::downgrade(&endpoint) as Weak<dyn RefCountable>),
+            );
+            endpoints.insert(*ip, endpoint);
+        }
+        if let Some(e) = endpoints.get(tunnel_ip) {
+            e.used.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn on_config_change(&self, config: &NpbHealthCheck) {
+        {
+            let mut current = self.config.write().unwrap();
+            if current.source == *config {
+                return;
+            }
+            let policy_backups = std::mem::take(&mut current.policy_backups);
+            *current = Config::from(config);
+            current.policy_backups = policy_backups;
+        }
+        // endpoints are added again with new backups on sending
+        self.endpoints.write().unwrap().clear();
+        self.enabled.store(config.enabled, Ordering::Relaxed);
+        info!("Npb health check config changed to {:?}.", config);
+    }
+
+    fn aging(endpoints: &RwLock<HashMap<IpAddr, Arc<Endpoint>>>) {
+        let mut endpoints = endpoints.write().unwrap();
+        let used = endpoints
+            .iter()
+            .filter(|(_, e)| e.used.load(Ordering::Relaxed))
+            .flat_map(|(ip, e)| iter::once(*ip).chain(e.backups.iter().copied()))
+            .collect::<HashSet<_>>();
+        endpoints.retain(|ip, _| used.contains(ip));
+        for e in endpoints.values() {
+            e.used.store(false, Ordering::Relaxed);
+        }
+    }
+
+    fn run(
+        endpoints: Arc<RwLock<HashMap<IpAddr, Arc<Endpoint>>>>,
+        config: Arc<RwLock<Config>>,
+        enabled: Arc<AtomicBool>,
+        is_running: Arc<AtomicBool>,
+        exception_handler: ExceptionHandler,
+    ) {
+        let mut prober = Prober::new(&config.read().unwrap().source);
+        let mut seq = 0u16;
+        let mut last_aging = Instant::now();
+        while is_running.load(Ordering::Relaxed) {
+            let (interval, threshold) = {
+                let c = config.read().unwrap();
+                if !prober.matches(&c.source) {
+                    prober = Prober::new(&c.source);
+                }
+                (c.source.interval, c.source.threshold)
+            };
+            let start = Instant::now();
+            if !enabled.load(Ordering::Relaxed) {
+                thread::sleep(interval);
+                continue;
+            }
+            if last_aging.elapsed() >= ENDPOINT_AGING_TIME {
+                Self::aging(&endpoints);
+                last_aging = start;
+            }
+
+            let targets = endpoints
+                .read()
+                .unwrap()
+                .iter()
+                .map(|(ip, e)| (*ip, e.clone()))
+                .collect::<Vec<_>>();
+            let ips = targets.iter().map(|(ip, _)| *ip).collect::<Vec<_>>();
+            seq = seq.wrapping_add(1);
+            let replies = prober.probe(&ips, seq, interval);
+
+            let mut down = false;
+            for (ip, endpoint) in targets {
+                match replies.get(&ip) {
+                    Some(true) => {
+                        endpoint.lost.store(0, Ordering::Relaxed);
+                        if !endpoint.alive.swap(true, Ordering::Relaxed) {
+                            info!("Npb endpoint {} is up.", ip);
+                        }
+                    }
+                    Some(false) => {
+                        endpoint.probe_lost.fetch_add(1, Ordering::Relaxed);
+                        let lost = endpoint.lost.fetch_add(1, Ordering::Relaxed) + 1;
+                        if lost >= threshold && endpoint.alive.swap(false, Ordering::Relaxed) {
+                            warn!("Npb endpoint {} is down after {} lost probes.", ip, lost);
+                        }
+                    }
+                    None => (),
+                }
+                down |= !endpoint.alive.load(Ordering::Relaxed);
+            }
+            if down {
+                exception_handler.set(Exception::NpbEndpointDown);
+            }
+
+            if let Some(d) = interval.checked_sub(start.elapsed()) {
+                thread::sleep(d);
+            }
+        }
+    }
+
+    pub fn start(&self) {
+        if self.is_running.load(Ordering::Relaxed) || NOT_SUPPORT {
+            return;
+        }
+        info!("Npb health checker starting...");
+        self.is_running.store(true, Ordering::Relaxed);
+        let endpoints = self.endpoints.clone();
+        let config = self.config.clone();
+        let enabled = self.enabled.clone();
+        let is_running = self.is_running.clone();
+        let exception_handler = self.exception_handler.clone();
+        self.thread_handler.lock().unwrap().replace(
+            thread::Builder::new()
+                .name("npb-health".to_owned())
+                .spawn(move || {
+                    Self::run(endpoints, config, enabled, is_running, exception_handler);
+                })
+                .unwrap(),
+        );
+    }
+
+    pub fn notify_stop(&self) -> Option<JoinHandle<()>> {
+        if !self.is_running.load(Ordering::Relaxed) || NOT_SUPPORT {
+            return None;
+        }
+        info!("Npb health checker stopping...");
+        self.is_running.store(false, Ordering::Relaxed);
+        self.thread_handler.lock().unwrap().take()
+    }
+
+    pub fn stop(&self) {
+        if let Some(handler) = self.notify_stop() {
+            let _ = handler.join();
+        }
+    }
+}
+
+impl FlowAclListener for Arc<NpbHealthChecker> {
+    fn flow_acl_change(
+        &mut self,
+        _trident_type: TridentType,
+        _local_epc: i32,
+        _ip_groups: &Vec<Arc<IpGroupData>>,
+        _platform_data: &Vec<Arc<PlatformData>>,
+        _peers: &Vec<Arc<PeerConnection>>,
+        _cidrs: &Vec<Arc<Cidr>>,
+        acls: &Vec<Arc<Acl>>,
+    ) -> Result<(), String> {
+        let mut backups = HashMap::new();
+        for (tunnel_ip, backup_ips) in acls.iter().flat_map(|a| a.npb_tunnel_backups.iter()) {
+            backups
+                .entry(*tunnel_ip)
+                .or_insert_with(|| backup_ips.clone());
+        }
+        {
+            let mut config = self.config.write().unwrap();
+            if config.policy_backups == backups {
+                return Ok(());
+            }
+            config.policy_backups = backups;
+        }
+        // endpoints are added again with new backups on sending
+        self.endpoints.write().unwrap().clear();
+        info!("Npb health check backups in policy changed.");
+        Ok(())
+    }
+
+    fn id(&self) -> usize {
+        u16::from(FlowAclListenerId::NpbHealthChecker) as usize
+    }
+}
+
+enum Prober {
+    Icmp(IcmpProber),
+    Udp(UdpProber),
+}
+
+impl Prober {
+    fn new(config: &NpbHealthCheck) -> Self {
+        match config.probe {
+            NpbProbeProtocol::Icmp => Self::Icmp(IcmpProber::new()),
+            NpbProbeProtocol::Udp => Self::Udp(UdpProber::new(config.udp_port)),
+        }
+    }
+
+    fn matches(&self, config: &NpbHealthCheck) -> bool {
+        match self {
+            Self::Icmp(_) => config.probe == NpbProbeProtocol::Icmp,
+            Self::Udp(p) => config.probe == NpbProbeProtocol::Udp && config.udp_port == p.port,
+        }
+    }
+
+    // Returns whether each probed endpoint replied in time
+    fn probe(&mut self, targets: &[IpAddr], seq: u16, timeout: Duration) -> HashMap<IpAddr, bool> {
+        let mut replies = HashMap::new();
+        for ip in targets {
+            let sent = match self {
+                Self::Icmp(p) => p.send(ip, seq),
+                Self::Udp(p) => p.send(ip, seq),
+            };
+            if sent {
+                replies.insert(*ip, false);
+            }
+        }
+
+        let deadline = Instant::now() + timeout;
+        let mut pending = replies.len();
+        let mut replied = vec![];
+        while pending > 0 && Instant::now() < deadline {
+            let received = match self {
+                Self::Icmp(p) => p.recv(seq, &mut replied),
+                Self::Udp(p) => p.recv(seq, &mut replied),
+            };
+            for ip in replied.drain(..) {
+                if let Some(r) = replies.get_mut(&ip) {
+                    if !*r {
+                        *r = true;
+                        pending -= 1;
+                    }
+                }
+            }
+            if !received {
+                thread::sleep(RECV_POLL_INTERVAL);
+            }
+        }
+        replies
+    }
+}
+
+struct IcmpProber {
+    ident: u16,
+    v4: Option<Socket>,
+    v6: Option<Socket>,
+    buffer: [MaybeUninit<u8>; 1500],
+}
+
+impl IcmpProber {
+    fn new() -> Self {
+        Self {
+            ident: process::id() as u16,
+            v4: Self::open(Domain::IPV4, Protocol::ICMPV4),
+            v6: Self::open(Domain::IPV6, Protocol::ICMPV6),
+            buffer: [MaybeUninit::uninit(); 1500],
+        }
+    }
+
+    fn open(domain: Domain, protocol: Protocol) -> Option<Socket> {
+        let socket = Socket::new(domain, Type::RAW, Some(protocol))
+            .and_then(|s| s.set_nonblocking(true).map(|_| s));
+        match socket {
+            Ok(s) => Some(s),
+            Err(e) => {
+                warn!(
+                    "Npb health check open {:?} socket failed: {:?}, endpoints are not probed.",
+                    protocol, e
+                );
+                None
+            }
+        }
+    }
+
+    fn send(&self, ip: &IpAddr, seq: u16) -> bool {
+        let socket = if ip.is_ipv6() {
+            self.v6.as_ref()
+        } else {
+            self.v4.as_ref()
+        };
+        let Some(socket) = socket else {
+            return false;
+        };
+        let request = echo_request(ip.is_ipv6(), self.ident, seq);
+        match socket.send_to(&request, &SockAddr::from(SocketAddr::new(*ip, 0))) {
+            Ok(_) => true,
+            Err(e) => {
+                debug!("Npb health check probe {} failed: {:?}.", ip, e);
+                false
+            }
+        }
+    }
+
+    // Returns whether any packet is received
+    fn recv(&mut self, seq: u16, replied: &mut Vec<IpAddr>) -> bool {
+        let mut received = false;
+        for socket in [self.v4.as_ref(), self.v6.as_ref()].into_iter().flatten() {
+            while let Ok((n, addr)) = socket.recv_from(&mut self.buffer) {
+                received = true;
+                // SAFETY: the first n bytes are initialized by recv_from
+                let data = unsafe { slice::from_raw_parts(self.buffer.as_ptr() as *const u8, n) };
+                let Some(ip) = addr.as_socket().map(|a| a.ip()) else {
+                    continue;
+                };
+                if is_echo_reply(ip.is_ipv6(), data, self.ident, seq) {
+                    replied.push(ip);
+                }
+            }
+        }
+        received
+    }
+}
+
+// Sends heartbeat datagrams to `port` of endpoints, which are expected to be echoed back
+struct UdpProber {
+    port: u16,
+    v4: Option<UdpSocket>,
+    v6: Option<UdpSocket>,
+    buffer: [u8; 1500],
+}
+
+impl UdpProber {
+    fn new(port: u16) -> Self {
+        Self {
+            port,
+            v4: Self::open(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0)),
+            v6: Self::open(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0)),
+            buffer: [0; 1500],
+        }
+    }
+
+    fn open(addr: SocketAddr) -> Option<UdpSocket> {
+        let socket = UdpSocket::bind(addr).and_then(|s| s.set_nonblocking(true).map(|_| s));
+        match socket {
+            Ok(s) => Some(s),
+            Err(e) => {
+                warn!(
+                    "Npb health check bind udp socket {} failed: {:?}, endpoints are not probed.",
+                    addr, e
+                );
+                None
+            }
+        }
+    }
+
+    fn send(&self, ip: &IpAddr, seq: u16) -> bool {
+        let socket = if ip.is_ipv6() {
+            self.v6.as_ref()
+        } else {
+            self.v4.as_ref()
+        };
+        let Some(socket) = socket else {
+            return false;
+        };
+        match socket.send_to(&heartbeat(seq), SocketAddr::new(*ip, self.port)) {
+            Ok(_) => true,
+            Err(e) => {
+                debug!("Npb health check probe {} failed: {:?}.", ip, e);
+                false
+            }
+        }
+    }
+
+    // Returns whether any packet is received
+    fn recv(&mut self, seq: u16, replied: &mut Vec<IpAddr>) -> bool {
+        let mut received = false;
+        let expected = heartbeat(seq);
+        for socket in [self.v4.as_ref(), self.v6.as_ref()].into_iter().flatten() {
+            while let Ok((n, addr)) = socket.recv_from(&mut self.buffer) {
+                received = true;
+                if addr.port() == self.port && self.buffer[..n] == expected[..] {
+                    replied.push(addr.ip());
+                }
+            }
+        }
+        received
+    }
+}
+
+fn heartbeat(seq: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(ECHO_PAYLOAD.len() + 2);
+    packet.extend_from_slice(ECHO_PAYLOAD);
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet
+}
+
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum = data
+        .chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], c.get(1).copied().unwrap_or_default()]) as u32)
+        .sum::<u32>();
+    while sum > u16::MAX as u32 {
+        sum = (sum >> 16) + (sum & 0xffff);
+    }
+    !(sum as u16)
+}
+
+fn echo_request(ipv6: bool, ident: u16, seq: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(ICMP_ECHO_HEADER_SIZE + ECHO_PAYLOAD.len());
+    packet.extend_from_slice(&[
+        if ipv6 {
+            ICMPV6_ECHO_REQUEST
+        } else {
+            ICMP_ECHO_REQUEST
+        },
+        0,
+        0,
+        0,
+    ]);
+    packet.extend_from_slice(&ident.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(ECHO_PAYLOAD);
+    // checksum of icmpv6 covers the pseudo header and is filled by kernel
+    if !ipv6 {
+        let checksum = checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+    packet
+}
+
+fn is_echo_reply(ipv6: bool, data: &[u8], ident: u16, seq: u16) -> bool {
+    let icmp = if ipv6 {
+        data
+    } else {
+        // ipv4 raw sockets receive the ip header
+        let Some(header_len) = data.first().map(|b| ((b & 0xf) as usize) << 2) else {
+            return false;
+        };
+        match data.get(header_len..) {
+            Some(icmp) => icmp,
+            None => return false,
+        }
+    };
+    icmp.len() >= ICMP_ECHO_HEADER_SIZE
+        && icmp[0]
+            == if ipv6 {
+                ICMPV6_ECHO_REPLY
+            } else {
+                ICMP_ECHO_REPLY
+            }
+        && icmp[4..6] == ident.to_be_bytes()
+        && icmp[6..8] == seq.to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::NpbTunnelBackup;
+
+    #[test]
+    fn echo_request_and_reply() {
+        let request = echo_request(false, 0x1234, 7);
+        assert_eq!(request[0], ICMP_ECHO_REQUEST);
+        assert_eq!(checksum(&request), 0);
+
+        let mut reply = vec![0x45];
+        reply.resize(20, 0);
+        reply.extend_from_slice(&request);
+        reply[20] = ICMP_ECHO_REPLY;
+        assert!(is_echo_reply(false, &reply, 0x1234, 7));
+        assert!(!is_echo_reply(false, &reply, 0x1234, 8));
+        assert!(!is_echo_reply(false, &reply[..24], 0x1234, 7));
+
+        let mut reply = echo_request(true, 0x1234, 7);
+        reply[0] = ICMPV6_ECHO_REPLY;
+        assert!(is_echo_reply(true, &reply, 0x1234, 7));
+        assert!(!is_echo_reply(true, &reply, 0x4321, 7));
+    }
+
+    #[test]
+    fn failover() {
+        let config = NpbHealthCheck {
+            enabled: true,
+            backups: vec![NpbTunnelBackup {
+                tunnel_ip: "10.0.0.1".to_owned(),
+                backup_ips: vec!["10.0.0.2".to_owned(), "10.0.0.3".to_owned()],
+            }],
+            ..Default::default()
+        };
+        let checker = NpbHealthChecker::new(
+            &config,
+            ExceptionHandler::default(),
+            Arc::new(stats::Collector::new("", Default::default())),
+        );
+        let tunnel_ip = "10.0.0.1".parse::<IpAddr>().unwrap();
+        assert_eq!(checker.select(&tunnel_ip), Some(tunnel_ip));
+        assert_eq!(checker.endpoints.read().unwrap().len(), 3);
+
+        let set_alive = |ip: &str, alive: bool| {
+            checker.endpoints.read().unwrap()[&ip.parse::<IpAddr>().unwrap()]
+                .alive
+                .store(alive, Ordering::Relaxed)
+        };
+        set_alive("10.0.0.1", false);
+        set_alive("10.0.0.2", false);
+        assert_eq!(checker.select(&tunnel_ip), "10.0.0.3".parse().ok());
+        set_alive("10.0.0.3", false);
+        assert_eq!(checker.select(&tunnel_ip), None);
+        set_alive("10.0.0.1", true);
+        assert_eq!(checker.select(&tunnel_ip), Some(tunnel_ip));
+    }
+
+    #[test]
+    fn policy_backups() {
+        let config = NpbHealthCheck {
+            enabled: true,
+            backups: vec![NpbTunnelBackup {
+                tunnel_ip: "10.0.0.1".to_owned(),
+                backup_ips: vec!["10.0.0.2".to_owned()],
+            }],
+            ..Default::default()
+        };
+        let mut checker = Arc::new(NpbHealthChecker::new(
+            &config,
+            ExceptionHandler::default(),
+            Arc::new(stats::Collector::new("", Default::default())),
+        ));
+        let tunnel_ip = "10.0.0.1".parse::<IpAddr>().unwrap();
+        let backup_ip = "10.0.0.3".parse::<IpAddr>().unwrap();
+        let acls = vec![Arc::new(Acl {
+            npb_tunnel_backups: vec![(tunnel_ip, vec![backup_ip])],
+            ..Default::default()
+        })];
+        checker
+            .flow_acl_change(
+                TridentType::TtProcess,
+                0,
+                &vec![],
+                &vec![],
+                &vec![],
+                &vec![],
+                &acls,
+            )
+            .unwrap();
+        assert_eq!(checker.select(&tunnel_ip), Some(tunnel_ip));
+        let endpoints = checker.endpoints.read().unwrap();
+        assert_eq!(endpoints.len(), 2);
+        assert_eq!(endpoints[&tunnel_ip].backups, vec![backup_ip]);
+    }
+
+    #[test]
+    fn udp_heartbeat() {
+        let echo = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = echo.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let mut buffer = [0; 64];
+            let (n, addr) = echo.recv_from(&mut buffer).unwrap();
+            echo.send_to(&buffer[..n], addr).unwrap();
+        });
+
+        let mut prober = Prober::new(&NpbHealthCheck {
+            probe: NpbProbeProtocol::Udp,
+            udp_port: port,
+            ..Default::default()
+        });
+        let ip = "127.0.0.1".parse::<IpAddr>().unwrap();
+        let replies = prober.probe(&[ip], 1, Duration::from_secs(1));
+        assert_eq!(replies.get(&ip), Some(&true));
+        server.join().unwrap();
+
+        // nothing echoes the second heartbeat
+        let replies = prober.probe(&[ip], 2, Duration::from_millis(50));
+        assert_eq!(replies.get(&ip), Some(&false));
+    }
+}
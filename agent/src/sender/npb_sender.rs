@@ -36,19 +36,20 @@ use socket2::{Domain, SockAddr, Socket, Type};
 #[cfg(windows)]
 use windows::Win32::Networking::WinSock::socket;
 
-use super::QUEUE_BATCH_SIZE;
+use super::{
+    npb_cipher::NpbCipher, npb_health::NpbHealthChecker, npb_shaper::NpbShaper, QUEUE_BATCH_SIZE,
+};
 
 use crate::common::{
-    enums::IpProtocol, erspan, vxlan, IPV4_ADDR_LEN, IPV4_DST_OFFSET, IPV4_PACKET_SIZE,
-    IPV4_PROTO_OFFSET, IPV6_ADDR_LEN, IPV6_DST_OFFSET, IPV6_PACKET_SIZE, IPV6_PROTO_OFFSET,
-    TCP6_PACKET_SIZE, TCP_PACKET_SIZE, UDP6_PACKET_SIZE, UDP_PACKET_SIZE,
+    enums::IpProtocol, erspan, vxlan, ETH_HEADER_SIZE, IPV4_ADDR_LEN, IPV4_DST_OFFSET,
+    IPV4_PACKET_SIZE, IPV4_PROTO_OFFSET, IPV6_ADDR_LEN, IPV6_DST_OFFSET, IPV6_PACKET_SIZE,
+    IPV6_PROTO_OFFSET, TCP6_PACKET_SIZE, TCP_PACKET_SIZE, UDP6_PACKET_SIZE, UDP_PACKET_SIZE,
 };
 #[cfg(unix)]
 use crate::common::{
-    ETH_HEADER_SIZE, IPV4_CSUM_OFFSET, IPV4_HEADER_SIZE, IPV4_SRC_OFFSET, IPV6_SRC_OFFSET,
-    UDP6_CHKSUM_OFFSET,
+    IPV4_CSUM_OFFSET, IPV4_HEADER_SIZE, IPV4_SRC_OFFSET, IPV6_SRC_OFFSET, UDP6_CHKSUM_OFFSET,
 };
-use crate::config::NpbConfig;
+use crate::config::{NpbConfig, NpbTunnelShaping};
 #[cfg(unix)]
 use crate::dispatcher::af_packet::{Options, Tpacket};
 use crate::exception::ExceptionHandler;
@@ -333,6 +334,15 @@ impl IpSender {
             .send_to(&packet.as_slice()[header_size..], &self.remote)
     }
 
+    fn set_tos(&mut self, tos: u32) {
+        // the underlay ip header is built by kernel
+        if !self.underlay_is_ipv6 {
+            if let Err(e) = self.socket.set_tos(tos) {
+                warn!("Npb IpSender set tos {} failed: {:?}.", tos, e);
+            }
+        }
+    }
+
     fn close(&mut self) {}
 }
 
@@ -345,6 +355,7 @@ struct TcpSender {
 
     dst_ip: IpAddr,
     remote: SockAddr,
+    tos: Option<u32>,
 
     last_connect: u32, // time in second
 }
@@ -368,6 +379,7 @@ impl TcpSender {
                 IpAddr::V6(ip) => SockAddr::from(SocketAddrV6::new(ip.clone(), dst_port, 0, 0)),
             },
             dst_ip: dst_ip.clone(),
+            tos: None,
             last_connect: 0,
         }
     }
@@ -382,6 +394,9 @@ impl TcpSender {
         socket.connect_timeout(&self.remote, Duration::from_millis(Self::CONNECT_TIMEOUT))?;
         socket.set_nonblocking(true)?;
         socket.set_keepalive(true)?;
+        if let (Some(tos), false) = (self.tos, self.underlay_is_ipv6) {
+            socket.set_tos(tos)?;
+        }
         self.socket.replace(socket);
         info!("Npb TcpSender init with {}.", self.dst_ip);
         Ok(())
@@ -448,6 +463,14 @@ impl TcpSender {
         }
     }
 
+    fn set_tos(&mut self, tos: u32) {
+        self.tos = Some(tos);
+        // applied on next connection
+        if let Some(socket) = self.socket.take() {
+            let _ = socket.shutdown(Shutdown::Both);
+        }
+    }
+
     fn close(&mut self) {}
 }
 
@@ -475,6 +498,16 @@ impl NpbSender {
             Self::TcpSender(s) => s.send(underlay_l2_opt_size, packet, arp),
         }
     }
+
+    fn set_tos(&mut self, tos: u32) {
+        match self {
+            Self::IpSender(s) => s.set_tos(tos),
+            // the underlay ip header is marked before sending
+            #[cfg(unix)]
+            Self::RawSender(_) => {}
+            Self::TcpSender(s) => s.set_tos(tos),
+        }
+    }
 }
 
 #[derive(Default)]
@@ -482,6 +515,9 @@ pub struct NpbSenderCounter {
     pub tx: AtomicUsize,
     pub tx_bytes: AtomicUsize,
     pub tx_dropped: AtomicUsize,
+    pub shaping_passed_bytes: AtomicUsize,
+    pub shaping_dropped: AtomicUsize,
+    pub shaping_dropped_bytes: AtomicUsize,
 }
 
 pub struct StatsNpbSenderCounter(Weak<NpbSenderCounter>);
@@ -510,6 +546,25 @@ impl OwnedCountable for StatsNpbSenderCounter {
                         CounterType::Counted,
                         CounterValue::Unsigned(x.tx_dropped.swap(0, Ordering::Relaxed) as u64),
                     ),
+                    (
+                        "shaping_passed_bytes",
+                        CounterType::Counted,
+                        CounterValue::Unsigned(
+                            x.shaping_passed_bytes.swap(0, Ordering::Relaxed) as u64
+                        ),
+                    ),
+                    (
+                        "shaping_dropped",
+                        CounterType::Counted,
+                        CounterValue::Unsigned(x.shaping_dropped.swap(0, Ordering::Relaxed) as u64),
+                    ),
+                    (
+                        "shaping_dropped_bytes",
+                        CounterType::Counted,
+                        CounterValue::Unsigned(
+                            x.shaping_dropped_bytes.swap(0, Ordering::Relaxed) as u64
+                        ),
+                    ),
                 ]
             }
             None => vec![],
@@ -783,6 +838,10 @@ pub struct NpbConnectionPool {
     counter: Arc<NpbSenderCounter>,
 
     arp: Arc<NpbArpTable>,
+    health: Arc<NpbHealthChecker>,
+    encryption_required: bool,
+    cipher: Option<NpbCipher>,
+    shaper: NpbShaper,
 }
 
 impl NpbConnectionPool {
@@ -791,7 +850,10 @@ impl NpbConnectionPool {
         underlay_is_ipv6: bool,
         socket_type: SocketType,
         npb_port: u16,
+        encryption_key: &str,
+        tunnel_shaping: &[NpbTunnelShaping],
         arp: Arc<NpbArpTable>,
+        health: Arc<NpbHealthChecker>,
         stats_collector: Arc<stats::Collector>,
     ) -> Self {
         let counter = Arc::new(NpbSenderCounter::default());
@@ -810,6 +872,22 @@ impl NpbConnectionPool {
             }
         }
 
+        let cipher = if encryption_key.is_empty() {
+            None
+        } else {
+            match NpbCipher::new(encryption_key) {
+                Ok(c) => {
+                    info!("Npb tunnel encryption enabled.");
+                    Some(c)
+                }
+                Err(e) => {
+                    // Never fall back to plain text, all packets will be dropped
+                    warn!("Npb tunnel encryption init failed: {}.", e);
+                    None
+                }
+            }
+        };
+
         Self {
             connections: HashMap::new(),
             socket_type,
@@ -817,10 +895,22 @@ impl NpbConnectionPool {
             underlay_is_ipv6,
             counter,
             arp,
+            health,
+            encryption_required: !encryption_key.is_empty(),
+            cipher,
+            shaper: NpbShaper::new(tunnel_shaping),
         }
     }
 
     fn create_sender(&self, remote: &IpAddr, protocol: u8) -> Result<NpbSender, String> {
+        let mut sender = self.create_raw_sender(remote, protocol)?;
+        if let Some(dscp) = self.shaper.dscp(remote) {
+            sender.set_tos((dscp as u32) << 2);
+        }
+        Ok(sender)
+    }
+
+    fn create_raw_sender(&self, remote: &IpAddr, protocol: u8) -> Result<NpbSender, String> {
         // Trigger to create ARP table entry.
         self.arp.add(remote);
         match self.socket_type {
@@ -845,13 +935,8 @@ impl NpbConnectionPool {
         }
     }
 
-    fn send_to(
-        &mut self,
-        timestamp: u64,
-        underlay_l2_opt_size: usize,
-        packet: Vec<u8>,
-    ) -> IOResult<usize> {
-        let (remote, key) = if self.underlay_is_ipv6 {
+    fn parse_remote(&self, underlay_l2_opt_size: usize, packet: &[u8]) -> (IpAddr, (u128, u8)) {
+        if self.underlay_is_ipv6 {
             let offset = IPV6_DST_OFFSET + underlay_l2_opt_size;
             let ip = Ipv6Addr::from(
                 *<&[u8; 16]>::try_from(&packet[offset..offset + IPV6_ADDR_LEN]).unwrap(),
@@ -875,8 +960,43 @@ impl NpbConnectionPool {
                     packet[IPV4_PROTO_OFFSET + underlay_l2_opt_size],
                 ),
             )
-        };
+        }
+    }
+
+    // Redirect the packet to another tunnel endpoint, checksums are filled when sending
+    fn set_underlay_dst(&self, underlay_l2_opt_size: usize, packet: &mut [u8], remote: &IpAddr) {
+        match remote {
+            IpAddr::V4(ip) => {
+                let offset = IPV4_DST_OFFSET + underlay_l2_opt_size;
+                packet[offset..offset + IPV4_ADDR_LEN].copy_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                let offset = IPV6_DST_OFFSET + underlay_l2_opt_size;
+                packet[offset..offset + IPV6_ADDR_LEN].copy_from_slice(&ip.octets());
+            }
+        }
+    }
+
+    // Set DSCP of the underlay ip header, the ECN bits are left unchanged
+    fn mark_dscp(&self, underlay_l2_opt_size: usize, packet: &mut [u8], dscp: u8) {
+        let offset = ETH_HEADER_SIZE + underlay_l2_opt_size;
+        if self.underlay_is_ipv6 {
+            // version (4 bits) | traffic class (8 bits) | flow label (20 bits)
+            packet[offset] = (packet[offset] & 0xf0) | (dscp >> 2);
+            packet[offset + 1] = (packet[offset + 1] & 0x3f) | ((dscp & 0x3) << 6);
+        } else {
+            packet[offset + 1] = (packet[offset + 1] & 0x3) | (dscp << 2);
+        }
+    }
 
+    fn send_to(
+        &mut self,
+        timestamp: u64,
+        underlay_l2_opt_size: usize,
+        packet: Vec<u8>,
+        remote: IpAddr,
+        key: (u128, u8),
+    ) -> IOResult<usize> {
         let mut conn = self.connections.get_mut(&key);
         if conn.is_some() {
             return conn
@@ -899,10 +1019,62 @@ impl NpbConnectionPool {
         &mut self,
         timestamp: u64,
         underlay_l2_opt_size: usize,
-        packet: Vec<u8>,
+        mut packet: Vec<u8>,
     ) -> IOResult<usize> {
+        let (remote, key) = self.parse_remote(underlay_l2_opt_size, &packet);
+        if !self.shaper.is_empty() {
+            match self.shaper.acquire(&remote, timestamp, packet.len()) {
+                Some(true) => {
+                    self.counter
+                        .shaping_passed_bytes
+                        .fetch_add(packet.len(), Ordering::Relaxed);
+                }
+                Some(false) => {
+                    self.counter.shaping_dropped.fetch_add(1, Ordering::Relaxed);
+                    self.counter
+                        .shaping_dropped_bytes
+                        .fetch_add(packet.len(), Ordering::Relaxed);
+                    return Ok(0);
+                }
+                None => {}
+            }
+            if let Some(dscp) = self.shaper.dscp(&remote) {
+                self.mark_dscp(underlay_l2_opt_size, &mut packet, dscp);
+            }
+        }
+        let (remote, key) = match self.health.select(&remote) {
+            Some(endpoint) if endpoint != remote => {
+                self.set_underlay_dst(underlay_l2_opt_size, &mut packet, &endpoint);
+                let ip_key = match endpoint {
+                    IpAddr::V4(ip) => u32::from(ip) as u128,
+                    IpAddr::V6(ip) => u128::from(ip),
+                };
+                (endpoint, (ip_key, key.1))
+            }
+            Some(_) => (remote, key),
+            None => {
+                self.counter.tx_dropped.fetch_add(1, Ordering::Relaxed);
+                return Err(IOError::new(
+                    ErrorKind::NotConnected,
+                    format!("npb tunnel endpoint {} and its backups are down", remote),
+                ));
+            }
+        };
+        if self.encryption_required {
+            let ret = match self.cipher.as_mut() {
+                Some(c) => c.seal(underlay_l2_opt_size, self.underlay_is_ipv6, &mut packet),
+                None => Err(IOError::new(
+                    ErrorKind::Other,
+                    "npb encryption is required but not available",
+                )),
+            };
+            if let Err(e) = ret {
+                self.counter.tx_dropped.fetch_add(1, Ordering::Relaxed);
+                return Err(e);
+            }
+        }
         let bytes = packet.len();
-        let ret = self.send_to(timestamp, underlay_l2_opt_size, packet);
+        let ret = self.send_to(timestamp, underlay_l2_opt_size, packet, remote, key);
         if ret.is_err() {
             self.counter.tx_dropped.fetch_add(1, Ordering::Relaxed);
             return ret;
@@ -930,6 +1102,7 @@ impl NpbPacketSender {
         receiver: Receiver<(u64, usize, Vec<u8>)>,
         config: &NpbConfig,
         arp: Arc<NpbArpTable>,
+        health: Arc<NpbHealthChecker>,
         stats_collector: Arc<stats::Collector>,
     ) -> Self {
         NpbPacketSender {
@@ -938,7 +1111,10 @@ impl NpbPacketSender {
                 config.underlay_is_ipv6,
                 config.socket_type,
                 config.npb_port,
+                config.encryption_key.expose(),
+                &config.tunnel_shaping,
                 arp.clone(),
+                health,
                 stats_collector,
             )),
             receiver,
@@ -0,0 +1,166 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use log::warn;
+
+use crate::config::NpbTunnelShaping;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+// Token bucket without background thread, only used by one npb sender thread
+struct TokenBucket {
+    rate: u64, // unit: byte per second
+    burst: u64,
+    tokens: u64,
+    last_refill: u64, // unit: ns
+}
+
+impl TokenBucket {
+    fn new(rate: u64, burst: u64) -> Self {
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            last_refill: 0,
+        }
+    }
+
+    fn acquire(&mut self, now: u64, size: u64) -> bool {
+        if now > self.last_refill {
+            let refill = (now - self.last_refill) as u128 * self.rate as u128 / NANOS_PER_SECOND;
+            if refill > 0 {
+                self.tokens = (self.tokens as u128 + refill).min(self.burst as u128) as u64;
+                self.last_refill = now;
+            }
+        } else if now + NANOS_PER_SECOND as u64 <= self.last_refill {
+            // clock jumped backwards
+            self.last_refill = now;
+        }
+        if self.tokens < size {
+            return false;
+        }
+        self.tokens -= size;
+        true
+    }
+}
+
+struct TunnelShaper {
+    bucket: Option<TokenBucket>,
+    dscp: Option<u8>,
+}
+
+// Per npb tunnel rate limit and underlay DSCP marking
+#[derive(Default)]
+pub struct NpbShaper {
+    tunnels: HashMap<IpAddr, TunnelShaper>,
+}
+
+impl NpbShaper {
+    pub fn new(config: &[NpbTunnelShaping]) -> Self {
+        let mut tunnels = HashMap::new();
+        for c in config {
+            let Ok(ip) = c.tunnel_ip.parse::<IpAddr>() else {
+                warn!(
+                    "Npb tunnel shaping ignored invalid tunnel ip {}.",
+                    c.tunnel_ip
+                );
+                continue;
+            };
+            let bucket = if c.max_bps > 0 {
+                let rate = (c.max_bps >> 3).max(1);
+                // at least one mtu sized packet should pass
+                Some(TokenBucket::new(rate, c.burst_bytes.max(u16::MAX as u64)))
+            } else {
+                None
+            };
+            tunnels.insert(
+                ip,
+                TunnelShaper {
+                    bucket,
+                    dscp: c.priority.dscp(),
+                },
+            );
+        }
+        Self { tunnels }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tunnels.is_empty()
+    }
+
+    // Returns None if the tunnel is not rate limited, or Some(false) if
+    // the packet exceeds the rate limit of the tunnel
+    pub fn acquire(&mut self, remote: &IpAddr, timestamp: u64, size: usize) -> Option<bool> {
+        match self.tunnels.get_mut(remote) {
+            Some(TunnelShaper {
+                bucket: Some(b), ..
+            }) => Some(b.acquire(timestamp, size as u64)),
+            _ => None,
+        }
+    }
+
+    pub fn dscp(&self, remote: &IpAddr) -> Option<u8> {
+        self.tunnels.get(remote).and_then(|t| t.dscp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::NpbTunnelPriority;
+
+    #[test]
+    fn token_bucket() {
+        let mut b = TokenBucket::new(1000, 1500);
+        let mut now = 1_000_000_000;
+        assert!(b.acquire(now, 1500));
+        assert!(!b.acquire(now, 1));
+        now += 500_000_000;
+        assert!(b.acquire(now, 500));
+        assert!(!b.acquire(now, 1));
+        // never exceeds burst
+        now += 10 * 1_000_000_000;
+        assert!(!b.acquire(now, 1501));
+        assert!(b.acquire(now, 1500));
+    }
+
+    #[test]
+    fn shaper() {
+        let mut shaper = NpbShaper::new(&[
+            NpbTunnelShaping {
+                tunnel_ip: "10.0.0.1".to_owned(),
+                max_bps: 8 * 100000,
+                burst_bytes: 0,
+                priority: NpbTunnelPriority::Low,
+            },
+            NpbTunnelShaping {
+                tunnel_ip: "bad ip".to_owned(),
+                ..Default::default()
+            },
+        ]);
+        let limited: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        assert_eq!(shaper.dscp(&limited), Some(8));
+        assert_eq!(shaper.dscp(&other), None);
+        assert_eq!(shaper.acquire(&limited, 1, u16::MAX as usize), Some(true));
+        assert_eq!(shaper.acquire(&limited, 1, 1500), Some(false));
+        assert_eq!(shaper.acquire(&other, 1, 1500), None);
+    }
+}
@@ -31,9 +31,9 @@ use log::{debug, error, info, warn};
 use public::sender::{SendMessageType, Sendable};
 use rand::{thread_rng, RngCore};
 
-use super::{get_sender_id, QUEUE_BATCH_SIZE};
+use super::{bandwidth_quota::BandwidthQuota, get_sender_id, QUEUE_BATCH_SIZE};
 
-use crate::config::handler::SenderAccess;
+use crate::config::handler::{SenderAccess, SenderConfig};
 use crate::exception::ExceptionHandler;
 use crate::utils::stats::{
     self, Collector, Countable, Counter, CounterType, CounterValue, RefCountable,
@@ -206,6 +206,8 @@ pub struct UniformSenderThread<T> {
     // if true, cache message for batch sending
     // can be turned off if message already cached
     cached: bool,
+    // data is shed when exceeding the quota of its signal class
+    bandwidth_quota: Option<Arc<BandwidthQuota>>,
 }
 
 impl<T: Sendable> UniformSenderThread<T> {
@@ -216,6 +218,7 @@ impl<T: Sendable> UniformSenderThread<T> {
         stats: Arc<Collector>,
         exception_handler: ExceptionHandler,
         cached: bool,
+        bandwidth_quota: Option<Arc<BandwidthQuota>>,
     ) -> Self {
         let running = Arc::new(AtomicBool::new(false));
         Self {
@@ -228,6 +231,7 @@ impl<T: Sendable> UniformSenderThread<T> {
             stats,
             exception_handler,
             cached,
+            bandwidth_quota,
         }
     }
 
@@ -249,6 +253,7 @@ impl<T: Sendable> UniformSenderThread<T> {
             self.stats.clone(),
             self.exception_handler.clone(),
             self.cached,
+            self.bandwidth_quota.clone(),
         );
         self.thread_handle = Some(
             thread::Builder::new()
@@ -297,6 +302,26 @@ struct Connection {
     last_reconnect: Duration,
 }
 
+impl Connection {
+    fn new(dst_ip: String, dst_port: u16, reconnect_interval: u8) -> Self {
+        Self {
+            tcp_stream: None,
+            reconnect_interval,
+            dst_ip,
+            dst_port,
+            reconnect: false,
+            last_reconnect: Duration::ZERO,
+        }
+    }
+}
+
+// Data of a tenant with its own export destination is batched and sent separately
+struct TenantRoute<T> {
+    tenant_id: u32,
+    encoder: Encoder<T>,
+    conn: Connection,
+}
+
 pub struct UniformSender<T> {
     id: usize,
     name: &'static str,
@@ -306,6 +331,7 @@ pub struct UniformSender<T> {
 
     encoder: Encoder<T>,
     conn: Connection,
+    tenant_routes: Vec<TenantRoute<T>>,
 
     config: SenderAccess,
 
@@ -319,6 +345,7 @@ pub struct UniformSender<T> {
     written_size: u64,
 
     cached: bool,
+    bandwidth_quota: Option<Arc<BandwidthQuota>>,
 }
 
 impl<T: Sendable> UniformSender<T> {
@@ -335,6 +362,7 @@ impl<T: Sendable> UniformSender<T> {
         stats: Arc<Collector>,
         exception_handler: ExceptionHandler,
         cached: bool,
+        bandwidth_quota: Option<Arc<BandwidthQuota>>,
     ) -> Self {
         let cfg = config.load();
         Self {
@@ -343,15 +371,13 @@ impl<T: Sendable> UniformSender<T> {
             input,
             counter: Arc::new(SenderCounter::default()),
             encoder: Encoder::new(0, SendMessageType::TaggedFlow, config.load().vtap_id),
+            conn: Connection::new(
+                cfg.dest_ip.clone(),
+                cfg.dest_port,
+                Self::DEFAULT_RECONNECT_INTERVAL,
+            ),
+            tenant_routes: Self::new_tenant_routes(&cfg),
             config,
-            conn: Connection {
-                tcp_stream: None,
-                reconnect_interval: Self::DEFAULT_RECONNECT_INTERVAL,
-                dst_ip: cfg.dest_ip.clone(),
-                dst_port: cfg.dest_port,
-                reconnect: false,
-                last_reconnect: Duration::ZERO,
-            },
             running,
             stats,
             stats_registered: false,
@@ -361,6 +387,21 @@ impl<T: Sendable> UniformSender<T> {
             pre_file_path: String::new(),
             written_size: 0,
             cached,
+            bandwidth_quota,
+        }
+    }
+
+    fn update_bandwidth_quota(&self) {
+        if let Some(q) = self.bandwidth_quota.as_ref() {
+            q.update(&self.config.load().bandwidth_quota);
+        }
+    }
+
+    // Returns false if the buffer is shed for exceeding the bandwidth quota
+    fn acquire_bandwidth(&self, encoder: &Encoder<T>) -> bool {
+        match self.bandwidth_quota.as_ref() {
+            Some(q) => q.acquire(encoder.header.msg_type, encoder.buffer_len()),
+            None => true,
         }
     }
 
@@ -379,8 +420,78 @@ impl<T: Sendable> UniformSender<T> {
         }
     }
 
+    fn new_tenant_routes(config: &SenderConfig) -> Vec<TenantRoute<T>> {
+        config
+            .tenant_destinations
+            .iter()
+            .map(|d| TenantRoute {
+                tenant_id: d.tenant_id,
+                encoder: Encoder::new(0, SendMessageType::TaggedFlow, config.vtap_id),
+                conn: Connection::new(d.ip.clone(), d.port, Self::DEFAULT_RECONNECT_INTERVAL),
+            })
+            .collect()
+    }
+
+    fn update_tenant_routes(&mut self) {
+        let cfg = self.config.load();
+        let destinations = &cfg.tenant_destinations;
+        if self.tenant_routes.len() == destinations.len()
+            && self
+                .tenant_routes
+                .iter()
+                .zip(destinations.iter())
+                .all(|(r, d)| {
+                    r.tenant_id == d.tenant_id && r.conn.dst_ip == d.ip && r.conn.dst_port == d.port
+                })
+        {
+            return;
+        }
+        info!(
+            "{} sender update tenant destinations to {:?}",
+            self.name, destinations
+        );
+        // data cached for the old destinations is sent before they are replaced
+        self.flush_tenant_routes();
+        self.tenant_routes = Self::new_tenant_routes(&cfg);
+    }
+
+    fn flush_tenant_route(&mut self, index: usize) {
+        let route = &mut self.tenant_routes[index];
+        route
+            .encoder
+            .update_header(self.name, self.id, &self.config);
+        if route.encoder.buffer_len() == 0 {
+            return;
+        }
+        if !self.acquire_bandwidth(&self.tenant_routes[index].encoder) {
+            self.tenant_routes[index].encoder.reset_buffer();
+            return;
+        }
+        let route = &mut self.tenant_routes[index];
+        route.encoder.set_header_frame_size();
+        Self::send_buffer(
+            &self.running,
+            &self.name,
+            &self.counter,
+            &self.exception_handler,
+            &mut route.conn,
+            &route.encoder.get_buffer(),
+        );
+        route.encoder.reset_buffer();
+    }
+
+    fn flush_tenant_routes(&mut self) {
+        for i in 0..self.tenant_routes.len() {
+            self.flush_tenant_route(i);
+        }
+    }
+
     fn flush_encoder(&mut self) {
         if self.encoder.buffer_len() > 0 {
+            if !self.acquire_bandwidth(&self.encoder) {
+                self.encoder.reset_buffer();
+                return;
+            }
             self.encoder.set_header_frame_size();
             Self::send_buffer(
                 &self.running,
@@ -542,15 +653,21 @@ impl<T: Sendable> UniformSender<T> {
                 Err(Error::Timeout) => match socket_type {
                     SocketType::File => self.flush_writer(),
                     _ => {
+                        self.update_bandwidth_quota();
                         self.update_dst_ip_and_port();
                         self.encoder.update_header(self.name, self.id, &self.config);
                         self.flush_encoder();
+                        self.update_tenant_routes();
+                        self.flush_tenant_routes();
                     }
                 },
                 Err(Error::Terminated(..)) => {
                     match socket_type {
                         SocketType::File => self.flush_writer(),
-                        _ => self.flush_encoder(),
+                        _ => {
+                            self.flush_encoder();
+                            self.flush_tenant_routes();
+                        }
                     }
                     break;
                 }
@@ -611,9 +728,27 @@ impl<T: Sendable> UniformSender<T> {
     }
 
     pub fn handle_target_server(&mut self, send_item: T) -> std::io::Result<()> {
+        let tenant_id = send_item.tenant_id();
+        if tenant_id > 0 && !self.tenant_routes.is_empty() {
+            if let Some(index) = self
+                .tenant_routes
+                .iter()
+                .position(|r| r.tenant_id == tenant_id)
+            {
+                self.check_or_register_counterable(send_item.message_type());
+                let encoder = &mut self.tenant_routes[index].encoder;
+                encoder.cache_to_sender(send_item);
+                if !self.cached || encoder.buffer_len() > Encoder::<T>::BUFFER_LEN {
+                    self.flush_tenant_route(index);
+                }
+                return Ok(());
+            }
+        }
+
         self.encoder.cache_to_sender(send_item);
         if !self.cached || self.encoder.buffer_len() > Encoder::<T>::BUFFER_LEN {
             self.check_or_register_counterable(self.encoder.header.msg_type);
+            self.update_bandwidth_quota();
             self.update_dst_ip_and_port();
             self.encoder.update_header(self.name, self.id, &self.config);
             self.flush_encoder();
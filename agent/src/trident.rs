@@ -27,7 +27,7 @@ use std::sync::{
     Arc, Condvar, Mutex, Weak,
 };
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
 use arc_swap::access::Access;
@@ -36,6 +36,7 @@ use flexi_logger::{colored_opt_format, Age, Cleanup, Criterion, FileSpec, Logger
 use log::{debug, info, warn};
 use tokio::runtime::{Builder, Runtime};
 use tokio::sync::broadcast;
+use trust_dns_resolver::{system_conf::read_system_conf, TokioAsyncResolver};
 
 #[cfg(target_os = "linux")]
 use crate::platform::{
@@ -70,20 +71,26 @@ use crate::{
     },
     exception::ExceptionHandler,
     flow_generator::{
-        protocol_logs::BoxAppProtoLogsData, protocol_logs::SessionAggregator, PacketSequenceParser,
-        TIME_UNIT,
+        protocol_logs::BoxAppProtoLogsData, protocol_logs::SessionAggregator, CapturePointDedup,
+        PacketSequenceParser, TIME_UNIT,
+    },
+    handler::{
+        NpbBuilder, NpbDedup, PacketCapture, PacketHandlerBuilder, PcapPolicyWindows, PcapStorage,
+        PolicyHitStats,
     },
-    handler::{NpbBuilder, PacketHandlerBuilder},
     integration_collector::{
         ApplicationLog, BoxedPrometheusExtra, MetricServer, OpenTelemetry, OpenTelemetryCompressed,
         Profile, TelegrafMetric,
     },
     metric::document::BoxedDocument,
     monitor::Monitor,
-    platform::synchronizer::Synchronizer as PlatformSynchronizer,
+    platform::{synchronizer::Synchronizer as PlatformSynchronizer, ConntrackTable},
     policy::{Policy, PolicyGetter, PolicySetter},
     rpc::{Session, Synchronizer, DEFAULT_TIMEOUT},
-    sender::{npb_sender::NpbArpTable, uniform_sender::UniformSenderThread},
+    sender::{
+        bandwidth_quota::BandwidthQuota, npb_health::NpbHealthChecker, npb_sender::NpbArpTable,
+        uniform_sender::UniformSenderThread,
+    },
     utils::{
         cgroups::{is_kernel_available_for_cgroups, Cgroups},
         command::get_hostname,
@@ -100,6 +107,7 @@ use crate::{
 };
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use crate::{
+    dispatcher::af_packet::OptFanout,
     ebpf_dispatcher::EbpfCollector,
     platform::SocketSynchronizer,
     utils::{environment::core_file_check, lru::Lru},
@@ -110,6 +118,8 @@ use pcap_assembler::{BoxedPcapBatch, PcapAssembler};
 
 #[cfg(target_os = "linux")]
 use public::netns;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use public::utils::net::{remove_stacked_links, LinkFlags};
 use public::{
     buffer::BatchedBox,
     debug::QueueDebugger,
@@ -121,6 +131,8 @@ use public::{
 };
 
 const MINUTE: Duration = Duration::from_secs(60);
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const LOOPBACK_INTERFACE: &str = "^lo$";
 const COMMON_DELAY: u64 = 5; // Potential delay from other processing steps in flow_map
 const QG_PROCESS_MAX_DELAY: u64 = 5; // FIXME: Potential delay from processing steps in qg, it is an estimated value and is not accurate; the data processing capability of the quadruple_generator should be optimized.
 
@@ -238,9 +250,10 @@ impl Trident {
         agent_mode: RunningMode,
         sidecar_mode: bool,
     ) -> Result<Trident> {
+        let mut invalid_config = None;
         let config = match agent_mode {
             RunningMode::Managed => {
-                match Config::load_from_file(config_path.as_ref()) {
+                let conf = match Config::load_from_file(config_path.as_ref()) {
                     Ok(conf) => conf,
                     Err(e) => {
                         if let ConfigError::YamlConfigInvalid(_) = e {
@@ -255,7 +268,10 @@ impl Trident {
                             return Err(e.into());
                         }
                     }
-                }
+                };
+                // reported only, agents with these configs used to start
+                invalid_config = conf.validate().err();
+                conf
             }
             RunningMode::Standalone => {
                 let rc = RuntimeConfig::load_from_file(config_path.as_ref())?;
@@ -372,6 +388,9 @@ impl Trident {
         );
 
         info!("static_config {:#?}", config);
+        if let Some(e) = invalid_config {
+            warn!("{}, check with --validate-config", e);
+        }
         let state = Arc::new((Mutex::new(State::Running), Condvar::new()));
         let state_thread = state.clone();
         let config_path = match agent_mode {
@@ -475,6 +494,7 @@ impl Trident {
                 .static_config
                 .controller_cert_file_prefix
                 .clone(),
+            &config_handler.static_config.controller_cert_pins,
             config_handler.static_config.controller_ips.clone(),
             exception_handler.clone(),
             &stats_collector,
@@ -527,6 +547,8 @@ impl Trident {
             config_path,
             agent_id_tx.clone(),
             ntp_diff,
+            config_handler.static_config.upgrade_public_key(),
+            config_handler.static_config.disable_cloud_metadata,
         ));
         stats_collector.register_countable(
             &stats::NoTagModule("ntp"),
@@ -534,19 +556,53 @@ impl Trident {
         );
         synchronizer.start();
 
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let diagnostics = Arc::new(crate::rpc::Diagnostics::new(
+            config_handler.current_config.clone(),
+            config_handler.static_config.log_file.clone(),
+            stats_collector.clone(),
+            synchronizer.static_config.clone(),
+            synchronizer.status.clone(),
+            exception_handler.clone(),
+        ));
+        let packet_capture = Arc::new(PacketCapture::default());
         #[cfg(any(target_os = "linux", target_os = "android"))]
         let remote_executor = crate::rpc::Executor::new(
             synchronizer.agent_id.clone(),
             session.clone(),
             runtime.clone(),
             exception_handler.clone(),
+            diagnostics.clone(),
+            config_handler.current_config.clone(),
+            packet_capture.clone(),
+            &stats_collector,
+        );
+        #[cfg(target_os = "windows")]
+        let remote_executor = crate::rpc::Executor::new(
+            synchronizer.agent_id.clone(),
+            session.clone(),
+            runtime.clone(),
+            exception_handler.clone(),
+            config_handler.current_config.clone(),
+            packet_capture.clone(),
+            &stats_collector,
         );
-        #[cfg(any(target_os = "linux", target_os = "android"))]
         remote_executor.start();
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let local_api = crate::rpc::LocalApi::new(
+            config_handler.static_config.local_api_port,
+            config_handler.static_config.local_api_token_file(),
+            runtime.clone(),
+            synchronizer.sync_trigger(),
+            diagnostics,
+        );
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        local_api.start();
 
         let mut domain_name_listener = DomainNameListener::new(
             stats_collector.clone(),
             session.clone(),
+            runtime.clone(),
             config_handler.static_config.controller_domain_name.clone(),
             config_handler.static_config.controller_ips.clone(),
             config_handler.static_config.team_id.clone(),
@@ -687,6 +743,9 @@ impl Trident {
                         monitor.stop();
                         domain_name_listener.stop();
                         platform_synchronizer.stop();
+                        #[cfg(any(target_os = "linux", target_os = "android"))]
+                        local_api.stop();
+                        remote_executor.stop();
                         #[cfg(target_os = "linux")]
                         {
                             api_watcher.stop();
@@ -818,6 +877,7 @@ impl Trident {
                         gateway_vmac_addrs,
                         config_handler.static_config.agent_mode,
                         runtime.clone(),
+                        packet_capture.clone(),
                     )?;
 
                     comp.start();
@@ -923,12 +983,43 @@ impl Trident {
     }
 }
 
+pub fn validate_config<P: AsRef<Path>>(config_path: P, agent_mode: RunningMode) -> Result<()> {
+    match agent_mode {
+        RunningMode::Managed => Config::load_from_file(config_path.as_ref())?.validate()?,
+        // runtime config is validated when loading
+        RunningMode::Standalone => RuntimeConfig::load_from_file(config_path.as_ref())?
+            .yaml_config
+            .validate_l7_protocol_ports()?,
+    }
+    Ok(())
+}
+
+// Returns the static configuration merged from local config file and defaults in YAML,
+// configuration from controller is not fetched
+pub fn dump_effective_config<P: AsRef<Path>>(
+    config_path: P,
+    agent_mode: RunningMode,
+) -> Result<String> {
+    let config = match agent_mode {
+        RunningMode::Managed => Config::load_from_file(config_path.as_ref())?,
+        RunningMode::Standalone => {
+            let rc = RuntimeConfig::load_from_file(config_path.as_ref())?;
+            let mut conf = Config::default();
+            conf.controller_ips = vec!["127.0.0.1".into()];
+            conf.log_file = rc.yaml_config.log_file;
+            conf.agent_mode = agent_mode;
+            conf
+        }
+    };
+    Ok(serde_yaml::to_string(&config)?)
+}
+
 fn get_listener_links(
     conf: &DispatcherConfig,
     #[cfg(target_os = "linux")] netns: &netns::NsFile,
 ) -> Vec<Link> {
     #[cfg(target_os = "linux")]
-    match netns::links_by_name_regex_in_netns(&conf.tap_interface_regex, netns) {
+    let mut links = match netns::links_by_name_regex_in_netns(&conf.tap_interface_regex, netns) {
         Err(e) => {
             warn!("get interfaces by name regex in {:?} failed: {}", netns, e);
             vec![]
@@ -943,10 +1034,11 @@ fn get_listener_links(
             debug!("tap interfaces in namespace {:?}: {:?}", netns, links);
             links
         }
-    }
+    };
 
     #[cfg(any(target_os = "windows", target_os = "android"))]
-    match public::utils::net::links_by_name_regex(&conf.tap_interface_regex) {
+    #[cfg_attr(target_os = "windows", allow(unused_mut))]
+    let mut links = match public::utils::net::links_by_name_regex(&conf.tap_interface_regex) {
         Err(e) => {
             warn!("get interfaces by name regex failed: {}", e);
             vec![]
@@ -961,7 +1053,40 @@ fn get_listener_links(
             debug!("tap interfaces: {:?}", links);
             links
         }
+    };
+
+    // traffic of bond slaves and vlan sub-interfaces is also seen on the bond master or
+    // underlying interface, avoid counting it twice
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    for l in remove_stacked_links(&mut links) {
+        info!(
+            "tap interface {} ignored as its traffic is captured on another tap interface",
+            l.name
+        );
     }
+
+    // loopback traffic is captured in local mode only, it is attributed to the agent itself
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if conf.tap_mode == TapMode::Local
+        && conf.loopback_capture_enabled
+        && !links.iter().any(|l| l.flags.contains(LinkFlags::LOOPBACK))
+    {
+        #[cfg(target_os = "linux")]
+        let loopback = netns::links_by_name_regex_in_netns(LOOPBACK_INTERFACE, netns)
+            .map_err(|e| e.to_string());
+        #[cfg(target_os = "android")]
+        let loopback =
+            public::utils::net::links_by_name_regex(LOOPBACK_INTERFACE).map_err(|e| e.to_string());
+        match loopback {
+            Ok(loopback) => links.extend(
+                loopback
+                    .into_iter()
+                    .filter(|l| l.flags.contains(LinkFlags::LOOPBACK)),
+            ),
+            Err(e) => warn!("get loopback interface failed: {}", e),
+        }
+    }
+    links
 }
 
 fn component_on_config_change(
@@ -1023,6 +1148,14 @@ fn component_on_config_change(
                 retain
             });
 
+            let yaml_config = &config_handler.candidate_config.yaml_config;
+            let interfaces_to_build: Vec<_> = interfaces_to_build
+                .into_iter()
+                .flat_map(|i| {
+                    let count = af_packet_fanout_thread_count(yaml_config, &i);
+                    std::iter::repeat(i).take(count)
+                })
+                .collect();
             let mut id = components.last_dispatcher_component_id;
             components
                 .policy_setter
@@ -1039,9 +1172,16 @@ fn component_on_config_change(
                     components.is_ce_version,
                     synchronizer,
                     components.npb_bps_limit.clone(),
+                    components.npb_dedup.clone(),
+                    components.capture_point_dedup.clone(),
+                    components.conntrack_table.clone(),
                     components.npb_arp_table.clone(),
+                    components.npb_health_checker.clone(),
+                    components.pcap_policy_windows.clone(),
                     components.rx_leaky_bucket.clone(),
                     components.policy_getter,
+                    components.policy_hit_stats.clone(),
+                    components.packet_capture.clone(),
                     components.exception_handler.clone(),
                     0,
                     components.bpf_options.clone(),
@@ -1102,9 +1242,15 @@ fn parse_tap_type(components: &mut AgentComponents, tap_types: Vec<trident::TapT
     }
 }
 
+// Keeps controller ips in sync with controller domain names
+//
+// Domain names are re-resolved when the TTL of the last answer expires or when requests
+// to controller fail, so that agents follow controllers behind global load balancers
+// instead of pinning the ip resolved at startup.
 pub struct DomainNameListener {
     stats_collector: Arc<stats::Collector>,
     session: Arc<Session>,
+    runtime: Arc<Runtime>,
     ips: Vec<String>,
     domain_names: Vec<String>,
     team_id: String,
@@ -1118,10 +1264,14 @@ pub struct DomainNameListener {
 
 impl DomainNameListener {
     const INTERVAL: Duration = Duration::from_secs(5);
+    // upper bound of re-resolving interval for records with long TTL
+    const MAX_TTL: Duration = Duration::from_secs(300);
+    const RESOLVE_TIMEOUT: Duration = Duration::from_secs(5);
 
     fn new(
         stats_collector: Arc<stats::Collector>,
         session: Arc<Session>,
+        runtime: Arc<Runtime>,
         domain_names: Vec<String>,
         ips: Vec<String>,
         team_id: String,
@@ -1131,6 +1281,7 @@ impl DomainNameListener {
         Self {
             stats_collector,
             session,
+            runtime,
             domain_names,
             ips,
             team_id,
@@ -1170,6 +1321,7 @@ impl DomainNameListener {
         let stopped = self.stopped.clone();
         let agent_id_tx = self.agent_id_tx.clone();
         let session = self.session.clone();
+        let runtime = self.runtime.clone();
 
         #[cfg(target_os = "linux")]
         let sidecar_mode = self.sidecar_mode;
@@ -1183,25 +1335,33 @@ impl DomainNameListener {
             thread::Builder::new()
                 .name("domain-name-listener".to_owned())
                 .spawn(move || {
+                    let resolver = Self::resolver(&runtime);
+                    // all names are resolved on the first round
+                    let mut expires = vec![Instant::now(); domain_names.len()];
                     while !stopped.swap(false, Ordering::Relaxed) {
                         thread::sleep(Self::INTERVAL);
 
+                        let request_failed = session.get_request_failed();
                         let mut changed = false;
                         for i in 0..domain_names.len() {
-                            let current = lookup_host(domain_names[i].as_str());
-                            if current.is_err() {
+                            let now = Instant::now();
+                            if !request_failed && now < expires[i] {
                                 continue;
                             }
-                            let current = current.unwrap();
-
-                            changed = current.iter().find(|&&x| x.to_string() == ips[i]).is_none();
-                            if changed {
-                                info!(
-                                    "Domain name {} ip {} change to {}",
-                                    domain_names[i], ips[i], current[0]
-                                );
-                                ips[i] = current[0].to_string();
+                            let Some((current, valid_until)) = Self::resolve(&runtime, &resolver, &domain_names[i]) else {
+                                continue;
+                            };
+                            expires[i] = valid_until.clamp(now + Self::INTERVAL, now + Self::MAX_TTL);
+
+                            if current.is_empty() || current.iter().any(|x| x.to_string() == ips[i]) {
+                                continue;
                             }
+                            info!(
+                                "Domain name {} ip {} change to {}",
+                                domain_names[i], ips[i], current[0]
+                            );
+                            ips[i] = current[0].to_string();
+                            changed = true;
                         }
 
                         if changed {
@@ -1258,6 +1418,42 @@ impl DomainNameListener {
                 .unwrap(),
         );
     }
+
+    fn resolver(runtime: &Runtime) -> TokioAsyncResolver {
+        let (config, mut opts) = read_system_conf().unwrap_or_else(|e| {
+            warn!("read system dns config failed: {}, use default config", e);
+            Default::default()
+        });
+        // expiration is tracked by the listener, every query should reach name servers
+        opts.cache_size = 0;
+        let _guard = runtime.enter();
+        TokioAsyncResolver::tokio(config, opts)
+    }
+
+    // Returns resolved ips and the instant the answer expires. Falls back to system
+    // resolver without TTL if the name can not be resolved with DNS, e.g. names
+    // provided by nsswitch modules
+    fn resolve(
+        runtime: &Runtime,
+        resolver: &TokioAsyncResolver,
+        domain_name: &str,
+    ) -> Option<(Vec<IpAddr>, Instant)> {
+        let result = runtime.block_on(async {
+            tokio::time::timeout(Self::RESOLVE_TIMEOUT, resolver.lookup_ip(domain_name)).await
+        });
+        match result {
+            Ok(Ok(lookup)) => return Some((lookup.iter().collect(), lookup.valid_until())),
+            Ok(Err(e)) => debug!("resolve domain name {} failed: {}", domain_name, e),
+            Err(_) => debug!("resolve domain name {} timeout", domain_name),
+        }
+        match lookup_host(domain_name) {
+            Ok(ips) => Some((ips, Instant::now())),
+            Err(e) => {
+                warn!("resolve domain name {} failed: {}", domain_name, e);
+                None
+            }
+        }
+    }
 }
 
 pub enum Components {
@@ -1358,6 +1554,7 @@ pub struct DispatcherComponent {
     pub l7_collector: L7CollectorThread,
     pub packet_sequence_parser: PacketSequenceParser,
     pub pcap_assembler: PcapAssembler,
+    pub pcap_storage: Option<PcapStorage>,
     pub handler_builders: Arc<Mutex<Vec<PacketHandlerBuilder>>>,
     pub src_link: Link, // The original src_interface
 }
@@ -1370,6 +1567,9 @@ impl DispatcherComponent {
         self.l7_collector.start();
         self.packet_sequence_parser.start();
         self.pcap_assembler.start();
+        if let Some(s) = self.pcap_storage.as_ref() {
+            s.start();
+        }
         self.handler_builders
             .lock()
             .unwrap()
@@ -1385,6 +1585,9 @@ impl DispatcherComponent {
         self.l7_collector.stop();
         self.packet_sequence_parser.stop();
         self.pcap_assembler.stop();
+        if let Some(s) = self.pcap_storage.as_ref() {
+            s.stop();
+        }
         self.handler_builders
             .lock()
             .unwrap()
@@ -1435,8 +1638,15 @@ pub struct AgentComponents {
     pub pcap_batch_uniform_sender: UniformSenderThread<BoxedPcapBatch>,
     pub policy_setter: PolicySetter,
     pub policy_getter: PolicyGetter,
+    pub policy_hit_stats: Arc<PolicyHitStats>,
+    pub packet_capture: Arc<PacketCapture>,
     pub npb_bandwidth_watcher: Box<Arc<NpbBandwidthWatcher>>,
     pub npb_arp_table: Arc<NpbArpTable>,
+    pub npb_health_checker: Arc<NpbHealthChecker>,
+    pub pcap_policy_windows: Arc<PcapPolicyWindows>,
+    pub npb_dedup: Arc<NpbDedup>,
+    pub capture_point_dedup: Arc<CapturePointDedup>,
+    pub conntrack_table: Option<Arc<ConntrackTable>>,
     pub is_ce_version: bool, // Determine whether the current version is a ce version, CE-AGENT always set pcap-assembler disabled
     pub tap_interfaces: Vec<Link>,
     pub bpf_options: Arc<Mutex<BpfOptions>>,
@@ -1697,6 +1907,7 @@ impl AgentComponents {
         gateway_vmac_addrs: Vec<MacAddr>,
         agent_mode: RunningMode,
         runtime: Arc<Runtime>,
+        packet_capture: Arc<PacketCapture>,
     ) -> Result<Self> {
         let static_config = &config_handler.static_config;
         let candidate_config = &config_handler.candidate_config;
@@ -1720,6 +1931,7 @@ impl AgentComponents {
             stats_collector.clone(),
             exception_handler.clone(),
             true,
+            None,
         );
         stats_sender.start();
 
@@ -1778,10 +1990,12 @@ impl AgentComponents {
             );
             if candidate_config.tap_mode != TapMode::Local {
                 for l in links {
-                    #[cfg(target_os = "linux")]
-                    interfaces_and_ns.push((vec![l], netns::NsFile::Root));
-                    #[cfg(any(target_os = "windows", target_os = "android"))]
-                    interfaces_and_ns.push(vec![l]);
+                    for _ in 0..af_packet_fanout_thread_count(yaml_config, &l) {
+                        #[cfg(target_os = "linux")]
+                        interfaces_and_ns.push((vec![l.clone()], netns::NsFile::Root));
+                        #[cfg(any(target_os = "windows", target_os = "android"))]
+                        interfaces_and_ns.push(vec![l.clone()]);
+                    }
                 }
             } else {
                 for _ in 0..local_dispatcher_count {
@@ -1839,6 +2053,7 @@ impl AgentComponents {
         );
         synchronizer.add_flow_acl_listener(Box::new(policy_setter));
         policy_setter.set_memory_limit(max_memory);
+        let policy_hit_stats = PolicyHitStats::new(stats_collector.clone());
 
         // TODO: collector enabled
         // TODO: packet handler builders
@@ -1917,16 +2132,29 @@ impl AgentComponents {
         let mut dispatcher_components = vec![];
 
         // Sender/Collector
+        let bandwidth_quota = Arc::new(BandwidthQuota::new(
+            &candidate_config.sender.bandwidth_quota,
+        ));
+        for (signal, counter) in bandwidth_quota.counters() {
+            stats_collector.register_countable(
+                &stats::SingleTagModule("sender_bandwidth_quota", "signal", signal),
+                Countable::Ref(Arc::downgrade(counter) as Weak<dyn RefCountable>),
+            );
+        }
         info!(
             "static analyzer ip: '{}' actual analyzer ip '{}'",
             yaml_config.analyzer_ip, candidate_config.sender.dest_ip
         );
         let l4_flow_aggr_queue_name = "3-flowlog-to-collector-sender";
-        let (l4_flow_aggr_sender, l4_flow_aggr_receiver, counter) = queue::bounded_with_debug(
-            yaml_config.flow_sender_queue_size as usize,
-            l4_flow_aggr_queue_name,
-            &queue_debugger,
-        );
+        let (l4_flow_aggr_sender, l4_flow_aggr_receiver, counter) =
+            queue::bounded_with_debug_and_policy(
+                yaml_config.flow_sender_queue_size as usize,
+                l4_flow_aggr_queue_name,
+                &queue_debugger,
+                yaml_config
+                    .queue_overflow
+                    .drop_policy(yaml_config.queue_overflow.l4_flow_log),
+            );
         stats_collector.register_countable(
             &QueueStats {
                 module: l4_flow_aggr_queue_name,
@@ -1941,13 +2169,17 @@ impl AgentComponents {
             stats_collector.clone(),
             exception_handler.clone(),
             true,
+            Some(bandwidth_quota.clone()),
         );
 
         let metrics_queue_name = "3-doc-to-collector-sender";
-        let (metrics_sender, metrics_receiver, counter) = queue::bounded_with_debug(
+        let (metrics_sender, metrics_receiver, counter) = queue::bounded_with_debug_and_policy(
             yaml_config.collector_sender_queue_size,
             metrics_queue_name,
             &queue_debugger,
+            yaml_config
+                .queue_overflow
+                .drop_policy(yaml_config.queue_overflow.metrics),
         );
         stats_collector.register_countable(
             &QueueStats {
@@ -1963,13 +2195,17 @@ impl AgentComponents {
             stats_collector.clone(),
             exception_handler.clone(),
             true,
+            Some(bandwidth_quota.clone()),
         );
 
         let proto_log_queue_name = "2-protolog-to-collector-sender";
-        let (proto_log_sender, proto_log_receiver, counter) = queue::bounded_with_debug(
+        let (proto_log_sender, proto_log_receiver, counter) = queue::bounded_with_debug_and_policy(
             yaml_config.flow_sender_queue_size,
             proto_log_queue_name,
             &queue_debugger,
+            yaml_config
+                .queue_overflow
+                .drop_policy(yaml_config.queue_overflow.l7_flow_log),
         );
         stats_collector.register_countable(
             &QueueStats {
@@ -1985,6 +2221,7 @@ impl AgentComponents {
             stats_collector.clone(),
             exception_handler.clone(),
             true,
+            Some(bandwidth_quota.clone()),
         );
 
         let analyzer_ip = if candidate_config
@@ -2023,6 +2260,28 @@ impl AgentComponents {
             config_handler.candidate_config.npb.socket_type == SocketType::RawUdp,
             exception_handler.clone(),
         ));
+        let npb_health_checker = Arc::new(NpbHealthChecker::new(
+            &config_handler.candidate_config.npb.health_check,
+            exception_handler.clone(),
+            stats_collector.clone(),
+        ));
+        synchronizer.add_flow_acl_listener(Box::new(npb_health_checker.clone()));
+        let pcap_policy_windows = Arc::new(PcapPolicyWindows::default());
+        synchronizer.add_flow_acl_listener(Box::new(pcap_policy_windows.clone()));
+        let npb_dedup = Arc::new(NpbDedup::new(&config_handler.candidate_config.npb));
+        stats_collector.register_countable(
+            &stats::NoTagModule("npb_dedup"),
+            Countable::Ref(Arc::downgrade(&npb_dedup.counter) as Weak<dyn RefCountable>),
+        );
+        let capture_point_dedup =
+            Arc::new(CapturePointDedup::new(yaml_config.flow.capacity as usize));
+        let conntrack_table = if yaml_config.conntrack_nat_enabled {
+            Some(Arc::new(ConntrackTable::new(
+                yaml_config.conntrack_sync_interval,
+            )))
+        } else {
+            None
+        };
 
         let pcap_batch_queue = "2-pcap-batch-to-sender";
         let (pcap_batch_sender, pcap_batch_receiver, pcap_batch_counter) =
@@ -2045,6 +2304,7 @@ impl AgentComponents {
             stats_collector.clone(),
             exception_handler.clone(),
             false,
+            Some(bandwidth_quota.clone()),
         );
         // Enterprise Edition Feature: packet-sequence
         let packet_sequence_queue_name = "2-packet-sequence-block-to-sender";
@@ -2070,6 +2330,7 @@ impl AgentComponents {
             stats_collector.clone(),
             exception_handler.clone(),
             true,
+            Some(bandwidth_quota.clone()),
         );
 
         let bpf_builder = bpf::Builder {
@@ -2111,9 +2372,16 @@ impl AgentComponents {
                 version_info.name != env!("AGENT_NAME"),
                 synchronizer,
                 npb_bps_limit.clone(),
+                npb_dedup.clone(),
+                capture_point_dedup.clone(),
+                conntrack_table.clone(),
                 npb_arp_table.clone(),
+                npb_health_checker.clone(),
+                pcap_policy_windows.clone(),
                 rx_leaky_bucket.clone(),
                 policy_getter,
+                policy_hit_stats.clone(),
+                packet_capture.clone(),
                 exception_handler.clone(),
                 local_dispatcher_count,
                 bpf_options.clone(),
@@ -2136,6 +2404,8 @@ impl AgentComponents {
             dispatcher_components.push(dispatcher_component);
         }
         tap_interfaces.sort();
+        // interfaces are duplicated for dispatchers in the same fanout group
+        tap_interfaces.dedup();
         let proc_event_queue_name = "1-proc-event-to-sender";
         #[allow(unused)]
         let (proc_event_sender, proc_event_receiver, counter) = queue::bounded_with_debug(
@@ -2157,6 +2427,7 @@ impl AgentComponents {
             stats_collector.clone(),
             exception_handler.clone(),
             true,
+            Some(bandwidth_quota.clone()),
         );
 
         let profile_queue_name = "1-profile-to-sender";
@@ -2179,6 +2450,7 @@ impl AgentComponents {
             stats_collector.clone(),
             exception_handler.clone(),
             true,
+            Some(bandwidth_quota.clone()),
         );
         let application_log_queue_name = "1-application-log-to-sender";
         let (application_log_sender, application_log_receiver, counter) = queue::bounded_with_debug(
@@ -2200,6 +2472,7 @@ impl AgentComponents {
             stats_collector.clone(),
             exception_handler.clone(),
             true,
+            Some(bandwidth_quota.clone()),
         );
 
         let ebpf_dispatcher_id = dispatcher_components.len();
@@ -2297,6 +2570,8 @@ impl AgentComponents {
                 &queue_debugger,
                 stats_collector.clone(),
                 exception_handler.clone(),
+                #[cfg(target_os = "linux")]
+                api_watcher.clone(),
             ) {
                 Ok(ebpf_collector) => {
                     synchronizer
@@ -2338,6 +2613,7 @@ impl AgentComponents {
             stats_collector.clone(),
             exception_handler.clone(),
             true,
+            Some(bandwidth_quota.clone()),
         );
 
         let otel_dispatcher_id = ebpf_dispatcher_id + 1;
@@ -2386,6 +2662,7 @@ impl AgentComponents {
             stats_collector.clone(),
             exception_handler.clone(),
             true,
+            Some(bandwidth_quota.clone()),
         );
 
         let telegraf_queue_name = "1-telegraf-to-sender";
@@ -2408,6 +2685,7 @@ impl AgentComponents {
             stats_collector.clone(),
             exception_handler.clone(),
             true,
+            Some(bandwidth_quota.clone()),
         );
 
         let compressed_otel_queue_name = "1-compressed-otel-to-sender";
@@ -2430,6 +2708,7 @@ impl AgentComponents {
             stats_collector.clone(),
             exception_handler.clone(),
             true,
+            Some(bandwidth_quota.clone()),
         );
 
         let (external_metrics_server, external_metrics_counter) = MetricServer::new(
@@ -2461,6 +2740,7 @@ impl AgentComponents {
             candidate_config
                 .yaml_config
                 .external_log_integration_disabled,
+            candidate_config.yaml_config.external_jaeger_compact_port,
         );
 
         stats_collector.register_countable(
@@ -2527,8 +2807,15 @@ impl AgentComponents {
             agent_mode,
             policy_setter,
             policy_getter,
+            policy_hit_stats,
+            packet_capture,
             npb_bandwidth_watcher,
             npb_arp_table,
+            npb_health_checker,
+            pcap_policy_windows,
+            npb_dedup,
+            capture_point_dedup,
+            conntrack_table,
             runtime,
             dispatcher_components,
             is_ce_version: version_info.name != env!("AGENT_NAME"),
@@ -2567,7 +2854,9 @@ impl AgentComponents {
 
         // When tap_mode is Analyzer mode and agent is not running in container and agent
         // in the environment where cgroup is not supported, we need to check free memory
-        if self.tap_mode != TapMode::Analyzer
+        if self.config.dispatcher.capture_paused {
+            info!("Packet capture is paused by controller, dispatchers not started.");
+        } else if self.tap_mode != TapMode::Analyzer
             && !running_in_container()
             && !is_kernel_available_for_cgroups()
         {
@@ -2589,7 +2878,11 @@ impl AgentComponents {
 
         #[cfg(any(target_os = "linux", target_os = "android"))]
         if let Some(ebpf_dispatcher_component) = self.ebpf_dispatcher_component.as_mut() {
-            ebpf_dispatcher_component.start();
+            if self.config.ebpf.paused {
+                info!("eBPF collection is paused by controller, ebpf collector not started.");
+            } else {
+                ebpf_dispatcher_component.start();
+            }
         }
         if matches!(self.agent_mode, RunningMode::Managed) {
             self.otel_uniform_sender.start();
@@ -2607,6 +2900,10 @@ impl AgentComponents {
 
         self.npb_bandwidth_watcher.start();
         self.npb_arp_table.start();
+        self.npb_health_checker.start();
+        if let Some(t) = self.conntrack_table.as_ref() {
+            t.start();
+        }
         info!("Started agent components.");
     }
 
@@ -2681,6 +2978,12 @@ impl AgentComponents {
         if let Some(h) = self.npb_arp_table.notify_stop() {
             join_handles.push(h);
         }
+        if let Some(h) = self.npb_health_checker.notify_stop() {
+            join_handles.push(h);
+        }
+        if let Some(t) = self.conntrack_table.as_ref() {
+            t.stop();
+        }
         if let Some(h) = self.stats_collector.notify_stop() {
             join_handles.push(h);
         }
@@ -2724,6 +3027,7 @@ impl Components {
         gateway_vmac_addrs: Vec<MacAddr>,
         agent_mode: RunningMode,
         runtime: Arc<Runtime>,
+        packet_capture: Arc<PacketCapture>,
     ) -> Result<Self> {
         #[cfg(target_os = "linux")]
         if crate::utils::environment::running_in_only_watch_k8s_mode() {
@@ -2748,6 +3052,7 @@ impl Components {
             gateway_vmac_addrs,
             agent_mode,
             runtime,
+            packet_capture,
         )?;
         return Ok(Components::Agent(components));
     }
@@ -2801,6 +3106,62 @@ fn build_pcap_assembler(
     (pcap_assembler, mini_packet_sender)
 }
 
+fn build_pcap_storage(
+    config: &PcapConfig,
+    windows: Arc<PcapPolicyWindows>,
+    stats_collector: &stats::Collector,
+    queue_debugger: &QueueDebugger,
+    id: usize,
+) -> (PcapStorage, DebugSender<MiniPacket>) {
+    let mini_packet_queue = "1-mini-meta-packet-to-pcap-storage";
+    let (mini_packet_sender, mini_packet_receiver, mini_packet_counter) = queue::bounded_with_debug(
+        config.queue_size as usize,
+        mini_packet_queue,
+        &queue_debugger,
+    );
+    let pcap_storage = PcapStorage::new(id, config, windows, mini_packet_receiver);
+    stats_collector.register_countable(
+        &stats::SingleTagModule("pcap_storage", "id", id),
+        Countable::Ref(Arc::downgrade(&pcap_storage.counter) as Weak<dyn RefCountable>),
+    );
+    stats_collector.register_countable(
+        &QueueStats {
+            id,
+            module: mini_packet_queue,
+        },
+        Countable::Owned(Box::new(mini_packet_counter)),
+    );
+    (pcap_storage, mini_packet_sender)
+}
+
+// Dispatchers capturing the interface share packets in a fanout group, with the group id
+// derived from the interface index. Only applicable for af_packet in mirror and analyzer mode.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn get_af_packet_fanout(yaml_config: &YamlConfig, link: &Link) -> Option<(usize, OptFanout)> {
+    if yaml_config.dpdk_enabled || yaml_config.libpcap_enabled {
+        return None;
+    }
+    yaml_config
+        .get_af_packet_fanout(&link.name)
+        .filter(|f| f.thread_count > 1)
+        .map(|f| {
+            (
+                f.thread_count,
+                OptFanout {
+                    group_id: link.if_index as u16,
+                    mode: f.mode.into(),
+                },
+            )
+        })
+}
+
+fn af_packet_fanout_thread_count(_yaml_config: &YamlConfig, _link: &Link) -> usize {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return get_af_packet_fanout(_yaml_config, _link).map_or(1, |f| f.0);
+    #[cfg(target_os = "windows")]
+    return 1;
+}
+
 fn build_dispatchers(
     id: usize,
     links: Vec<Link>,
@@ -2810,9 +3171,16 @@ fn build_dispatchers(
     is_ce_version: bool,
     synchronizer: &Arc<Synchronizer>,
     npb_bps_limit: Arc<LeakyBucket>,
+    npb_dedup: Arc<NpbDedup>,
+    capture_point_dedup: Arc<CapturePointDedup>,
+    conntrack_table: Option<Arc<ConntrackTable>>,
     npb_arp_table: Arc<NpbArpTable>,
+    npb_health_checker: Arc<NpbHealthChecker>,
+    pcap_policy_windows: Arc<PcapPolicyWindows>,
     rx_leaky_bucket: Arc<LeakyBucket>,
     policy_getter: PolicyGetter,
+    policy_hit_stats: Arc<PolicyHitStats>,
+    packet_capture: Arc<PacketCapture>,
     exception_handler: ExceptionHandler,
     local_dispatcher_count: usize,
     bpf_options: Arc<Mutex<BpfOptions>>,
@@ -2920,17 +3288,35 @@ fn build_dispatchers(
         id,
     );
 
-    let handler_builders = Arc::new(Mutex::new(vec![
+    let mut handler_builders = vec![
         PacketHandlerBuilder::Pcap(mini_packet_sender),
         PacketHandlerBuilder::Npb(NpbBuilder::new(
             id,
             &candidate_config.npb,
             &queue_debugger,
             npb_bps_limit.clone(),
+            npb_dedup,
             npb_arp_table.clone(),
+            npb_health_checker,
             stats_collector.clone(),
         )),
-    ]));
+        PacketHandlerBuilder::PolicyHit(policy_hit_stats),
+        PacketHandlerBuilder::Capture(packet_capture),
+    ];
+    let pcap_storage = if yaml_config.pcap.local_storage_enabled {
+        let (pcap_storage, storage_sender) = build_pcap_storage(
+            &yaml_config.pcap,
+            pcap_policy_windows,
+            &stats_collector,
+            &queue_debugger,
+            id,
+        );
+        handler_builders.push(PacketHandlerBuilder::Pcap(storage_sender));
+        Some(pcap_storage)
+    } else {
+        None
+    };
+    let handler_builders = Arc::new(Mutex::new(handler_builders));
 
     let pcap_interfaces =
         if candidate_config.tap_mode == TapMode::Mirror && yaml_config.dpdk_enabled {
@@ -2948,6 +3334,27 @@ fn build_dispatchers(
         .options(Arc::new(Mutex::new(dispatcher::Options {
             #[cfg(any(target_os = "linux", target_os = "android"))]
             af_packet_version: dispatcher_config.af_packet_version,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            af_packet_fanout: if candidate_config.tap_mode != TapMode::Local {
+                get_af_packet_fanout(yaml_config, &src_link).map(|f| f.1)
+            } else {
+                None
+            },
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            af_packet_promisc: if candidate_config.tap_mode != TapMode::Local {
+                yaml_config.get_af_packet_promisc(&src_link.name)
+            } else {
+                None
+            },
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            af_packet_xdp_scope: if candidate_config.tap_mode != TapMode::Local {
+                yaml_config.get_xdp_capture_scope(&src_link.name)
+            } else {
+                None
+            },
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            af_packet_origdev: candidate_config.tap_mode != TapMode::Local
+                && yaml_config.capture_nic_attribution_enabled,
             packet_blocks: dispatcher_config.af_packet_blocks,
             tap_mode: candidate_config.tap_mode,
             tap_mac_script: yaml_config.tap_mac_script.clone(),
@@ -2976,6 +3383,8 @@ fn build_dispatchers(
         .log_output_queue(log_sender.clone())
         .packet_sequence_output_queue(packet_sequence_sender) // Enterprise Edition Feature: packet-sequence
         .stats_collector(stats_collector.clone())
+        .capture_point_dedup(capture_point_dedup)
+        .conntrack_table(conntrack_table)
         .flow_map_config(config_handler.flow())
         .log_parse_config(config_handler.log_parser())
         .collector_config(config_handler.collector())
@@ -3055,6 +3464,7 @@ fn build_dispatchers(
         l7_collector,
         packet_sequence_parser,
         pcap_assembler,
+        pcap_storage,
         handler_builders,
         src_link,
     })
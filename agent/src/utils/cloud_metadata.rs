@@ -0,0 +1,323 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use log::debug;
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+
+use public::proto::trident as tp;
+
+// Link local addresses are not routable outside cloud VMs, a short timeout
+// keeps the query cheap on physical hosts
+const IMDS_TIMEOUT: Duration = Duration::from_secs(2);
+
+const AWS_ENDPOINT: &str = "http://169.254.169.254";
+const AWS_TOKEN_TTL: &str = "60";
+const AZURE_ENDPOINT: &str = "http://169.254.169.254/metadata/instance?api-version=2021-02-01";
+const ALIYUN_ENDPOINT: &str = "http://100.100.100.200";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloudProvider {
+    Aws,
+    Azure,
+    Aliyun,
+}
+
+impl CloudProvider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Aws => "aws",
+            Self::Azure => "azure",
+            Self::Aliyun => "aliyun",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CloudMetadata {
+    pub provider: CloudProvider,
+    pub instance_id: String,
+    pub region: String,
+    pub zone: String,
+    pub vpc_id: String,
+    pub subnet_id: String,
+    // mac and private ip of the primary network interface
+    pub mac: String,
+    pub ip: String,
+    pub account_id: String,
+}
+
+impl CloudMetadata {
+    fn new(provider: CloudProvider) -> Self {
+        Self {
+            provider,
+            instance_id: Default::default(),
+            region: Default::default(),
+            zone: Default::default(),
+            vpc_id: Default::default(),
+            subnet_id: Default::default(),
+            mac: Default::default(),
+            ip: Default::default(),
+            account_id: Default::default(),
+        }
+    }
+}
+
+impl From<&CloudMetadata> for tp::CloudMetadata {
+    fn from(m: &CloudMetadata) -> Self {
+        fn non_empty(s: &str) -> Option<String> {
+            if s.is_empty() {
+                None
+            } else {
+                Some(s.to_owned())
+            }
+        }
+        tp::CloudMetadata {
+            provider: Some(m.provider.as_str().to_owned()),
+            instance_id: Some(m.instance_id.clone()),
+            region: non_empty(&m.region),
+            zone: non_empty(&m.zone),
+            vpc_id: non_empty(&m.vpc_id),
+            subnet_id: non_empty(&m.subnet_id),
+            mac: non_empty(&m.mac),
+            ip: non_empty(&m.ip),
+            account_id: non_empty(&m.account_id),
+        }
+    }
+}
+
+// Query instance metadata service of known cloud providers, returns None if
+// the agent is not running on any of them
+pub async fn query() -> Option<CloudMetadata> {
+    let client = match Client::builder().timeout(IMDS_TIMEOUT).no_proxy().build() {
+        Ok(c) => c,
+        Err(e) => {
+            debug!("failed to build imds client: {}", e);
+            return None;
+        }
+    };
+    for provider in [
+        CloudProvider::Aws,
+        CloudProvider::Azure,
+        CloudProvider::Aliyun,
+    ] {
+        let result = match provider {
+            CloudProvider::Aws => query_aws(&client).await,
+            CloudProvider::Azure => query_azure(&client).await,
+            CloudProvider::Aliyun => query_aliyun(&client).await,
+        };
+        match result {
+            Ok(m) if !m.instance_id.is_empty() => return Some(m),
+            Ok(_) => debug!("{} imds returned empty instance id", provider.as_str()),
+            Err(e) => debug!("{} imds unavailable: {}", provider.as_str(), e),
+        }
+    }
+    None
+}
+
+async fn fetch(req: RequestBuilder) -> reqwest::Result<String> {
+    Ok(req
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?
+        .trim()
+        .to_owned())
+}
+
+// IMDSv2, a session token is required for every request
+async fn query_aws(client: &Client) -> reqwest::Result<CloudMetadata> {
+    let token = fetch(
+        client
+            .put(format!("{}/latest/api/token", AWS_ENDPOINT))
+            .header("X-aws-ec2-metadata-token-ttl-seconds", AWS_TOKEN_TTL),
+    )
+    .await?;
+    let get = |path: &str| {
+        fetch(
+            client
+                .get(format!("{}/latest/meta-data/{}", AWS_ENDPOINT, path))
+                .header("X-aws-ec2-metadata-token", &token),
+        )
+    };
+
+    let mut m = CloudMetadata::new(CloudProvider::Aws);
+    m.instance_id = get("instance-id").await?;
+    m.region = get("placement/region").await.unwrap_or_default();
+    m.zone = get("placement/availability-zone").await.unwrap_or_default();
+    m.ip = get("local-ipv4").await.unwrap_or_default();
+    m.mac = get("mac").await.unwrap_or_default();
+    if !m.mac.is_empty() {
+        let prefix = format!("network/interfaces/macs/{}", m.mac);
+        m.vpc_id = get(&format!("{}/vpc-id", prefix)).await.unwrap_or_default();
+        m.subnet_id = get(&format!("{}/subnet-id", prefix))
+            .await
+            .unwrap_or_default();
+        m.account_id = get(&format!("{}/owner-id", prefix))
+            .await
+            .unwrap_or_default();
+    }
+    Ok(m)
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct AzureInstance {
+    compute: AzureCompute,
+    network: AzureNetwork,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct AzureCompute {
+    vm_id: String,
+    location: String,
+    zone: String,
+    subscription_id: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct AzureNetwork {
+    interface: Vec<AzureInterface>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct AzureInterface {
+    mac_address: String,
+    ipv4: AzureIpv4,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct AzureIpv4 {
+    ip_address: Vec<AzureIpAddress>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct AzureIpAddress {
+    private_ip_address: String,
+}
+
+impl From<AzureInstance> for CloudMetadata {
+    fn from(instance: AzureInstance) -> Self {
+        let mut m = CloudMetadata::new(CloudProvider::Azure);
+        m.instance_id = instance.compute.vm_id;
+        m.region = instance.compute.location;
+        m.zone = instance.compute.zone;
+        m.account_id = instance.compute.subscription_id;
+        if let Some(interface) = instance.network.interface.into_iter().next() {
+            m.mac = format_mac(&interface.mac_address);
+            if let Some(addr) = interface.ipv4.ip_address.into_iter().next() {
+                m.ip = addr.private_ip_address;
+            }
+        }
+        m
+    }
+}
+
+// Azure returns mac address without separators, e.g. 000D3AF806EC
+fn format_mac(mac: &str) -> String {
+    if mac.len() != 12 || mac.contains(':') {
+        return mac.to_lowercase();
+    }
+    mac.as_bytes()
+        .chunks(2)
+        .map(|c| String::from_utf8_lossy(c).to_lowercase())
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+// Azure IMDS does not expose vnet and subnet ids
+async fn query_azure(client: &Client) -> reqwest::Result<CloudMetadata> {
+    let instance = client
+        .get(AZURE_ENDPOINT)
+        .header("Metadata", "true")
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<AzureInstance>()
+        .await?;
+    Ok(instance.into())
+}
+
+async fn query_aliyun(client: &Client) -> reqwest::Result<CloudMetadata> {
+    let get =
+        |path: &str| fetch(client.get(format!("{}/latest/meta-data/{}", ALIYUN_ENDPOINT, path)));
+
+    let mut m = CloudMetadata::new(CloudProvider::Aliyun);
+    m.instance_id = get("instance-id").await?;
+    m.region = get("region-id").await.unwrap_or_default();
+    m.zone = get("zone-id").await.unwrap_or_default();
+    m.vpc_id = get("vpc-id").await.unwrap_or_default();
+    m.subnet_id = get("vswitch-id").await.unwrap_or_default();
+    m.mac = get("mac").await.unwrap_or_default();
+    m.ip = get("private-ipv4").await.unwrap_or_default();
+    m.account_id = get("owner-account-id").await.unwrap_or_default();
+    Ok(m)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn azure_instance() {
+        let instance: AzureInstance = serde_json::from_str(
+            r#"{
+                "compute": {
+                    "location": "westus",
+                    "vmId": "02aab8a4-74ef-476e-8182-f6d2ba4166a6",
+                    "zone": "1",
+                    "subscriptionId": "xxxxxxxx-xxxxx-xxx-xxx-xxxx"
+                },
+                "network": {
+                    "interface": [{
+                        "ipv4": {
+                            "ipAddress": [{
+                                "privateIpAddress": "10.144.133.132",
+                                "publicIpAddress": ""
+                            }],
+                            "subnet": [{"address": "10.144.133.128", "prefix": "26"}]
+                        },
+                        "macAddress": "0011AAFFBB22"
+                    }]
+                }
+            }"#,
+        )
+        .unwrap();
+        let m = CloudMetadata::from(instance);
+        assert_eq!(m.provider, CloudProvider::Azure);
+        assert_eq!(m.instance_id, "02aab8a4-74ef-476e-8182-f6d2ba4166a6");
+        assert_eq!(m.region, "westus");
+        assert_eq!(m.zone, "1");
+        assert_eq!(m.mac, "00:11:aa:ff:bb:22");
+        assert_eq!(m.ip, "10.144.133.132");
+        assert!(m.vpc_id.is_empty());
+    }
+
+    #[test]
+    fn mac_format() {
+        assert_eq!(format_mac("0011AAFFBB22"), "00:11:aa:ff:bb:22");
+        assert_eq!(format_mac("00:11:AA:FF:BB:22"), "00:11:aa:ff:bb:22");
+        assert_eq!(format_mac(""), "");
+    }
+}
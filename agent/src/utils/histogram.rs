@@ -0,0 +1,144 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Sub buckets in each power of two range, relative error of bucket bounds is 1/SUB_BUCKETS
+const SUB_BUCKET_BITS: u32 = 2;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+pub const MAX_BUCKETS: usize = SUB_BUCKETS * (u32::BITS - SUB_BUCKET_BITS + 1) as usize;
+
+// Log-linear histogram of u32 values similar to HdrHistogram:
+// - Values less than SUB_BUCKETS have a bucket each
+// - Each [2^e, 2^(e+1)) range is split into SUB_BUCKETS linear buckets
+// Buckets are allocated up to the largest value recorded, at most MAX_BUCKETS
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Histogram {
+    buckets: Vec<u32>,
+}
+
+impl Histogram {
+    pub fn bucket_index(value: u32) -> usize {
+        if (value as usize) < SUB_BUCKETS {
+            return value as usize;
+        }
+        let exp = u32::BITS - 1 - value.leading_zeros();
+        let shift = exp - SUB_BUCKET_BITS;
+        let sub = (value >> shift) as usize & (SUB_BUCKETS - 1);
+        SUB_BUCKETS + shift as usize * SUB_BUCKETS + sub
+    }
+
+    // Inclusive bounds of values counted in the bucket
+    pub fn bucket_bounds(index: usize) -> (u32, u32) {
+        if index < SUB_BUCKETS {
+            return (index as u32, index as u32);
+        }
+        let shift = (index / SUB_BUCKETS - 1) as u32;
+        let sub = (index % SUB_BUCKETS) as u64;
+        let lower = (SUB_BUCKETS as u64 + sub) << shift;
+        let upper = lower + (1 << shift) - 1;
+        (lower as u32, upper as u32)
+    }
+
+    pub fn record(&mut self, value: u32) {
+        let index = Self::bucket_index(value);
+        if self.buckets.len() <= index {
+            self.buckets.resize(index + 1, 0);
+        }
+        self.buckets[index] = self.buckets[index].saturating_add(1);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        if self.buckets.len() < other.buckets.len() {
+            self.buckets.resize(other.buckets.len(), 0);
+        }
+        for (b, o) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *b = b.saturating_add(*o);
+        }
+    }
+
+    pub fn buckets(&self) -> &[u32] {
+        &self.buckets
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().map(|b| *b as u64).sum()
+    }
+
+    // Upper bound of the bucket where the quantile falls in, q in [0, 1]
+    pub fn quantile(&self, q: f64) -> Option<u32> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((count as f64 * q).ceil() as u64).clamp(1, count);
+        let mut seen = 0;
+        for (i, b) in self.buckets.iter().enumerate() {
+            seen += *b as u64;
+            if seen >= rank {
+                return Some(Self::bucket_bounds(i).1);
+            }
+        }
+        None
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_bounds() {
+        for i in 0..MAX_BUCKETS {
+            let (lower, upper) = Histogram::bucket_bounds(i);
+            assert!(lower <= upper);
+            assert_eq!(Histogram::bucket_index(lower), i);
+            assert_eq!(Histogram::bucket_index(upper), i);
+            if i + 1 < MAX_BUCKETS {
+                assert_eq!(Histogram::bucket_bounds(i + 1).0, upper + 1);
+            }
+        }
+        assert_eq!(Histogram::bucket_index(u32::MAX), MAX_BUCKETS - 1);
+        assert_eq!(Histogram::bucket_bounds(MAX_BUCKETS - 1).1, u32::MAX);
+    }
+
+    #[test]
+    fn record_and_quantile() {
+        let mut h = Histogram::default();
+        assert_eq!(h.quantile(0.5), None);
+        for v in 1..=100 {
+            h.record(v);
+        }
+        assert_eq!(h.count(), 100);
+        assert_eq!(h.buckets().len(), Histogram::bucket_index(100) + 1);
+        // bucket bounds are within 25% of the exact values
+        assert_eq!(h.quantile(0.5), Some(55));
+        assert_eq!(h.quantile(0.99), Some(111));
+        assert_eq!(h.quantile(1.0), Some(111));
+
+        let mut other = Histogram::default();
+        other.record(1000);
+        h.merge(&other);
+        assert_eq!(h.count(), 101);
+        assert_eq!(h.quantile(1.0), Some(1023));
+    }
+}
@@ -14,17 +14,20 @@
  * limitations under the License.
  */
 
+use std::collections::BTreeMap;
 use std::env;
 use std::io;
 use std::process;
 use std::sync::{
     atomic::{AtomicI64, AtomicU32, AtomicU64, AtomicU8, Ordering},
-    Arc, Weak,
+    Arc, Mutex, Weak,
 };
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use arc_swap::access::Access;
-use flexi_logger::{writers::LogWriter, DeferredNow, Level, Record};
+use flexi_logger::{writers::LogWriter, DeferredNow, Level, LoggerHandle, Record};
+use log::{info, warn};
 
 use public::{
     queue,
@@ -122,6 +125,7 @@ impl RemoteLogWriter {
             stats_collector,
             exception_handler,
             true,
+            None,
         );
         uniform_sender.start();
         Self {
@@ -305,3 +309,193 @@ impl LogWriter for LogWriterAdapter {
         self.0.iter().fold(Ok(()), |r, w| r.or(w.flush()))
     }
 }
+
+// Modules with log levels adjustable at runtime, matched by module path prefix
+pub const LOG_MODULES: [&str; 4] = ["dispatcher", "ebpf", "flow_generator", "rpc"];
+
+static LOG_LEVELS: Mutex<LogLevels> = Mutex::new(LogLevels {
+    handle: None,
+    global: Level::Info,
+    modules: BTreeMap::new(),
+});
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ModuleLevel {
+    level: Level,
+    // reverts to the global level after expiration
+    expire_at: Option<Instant>,
+}
+
+struct LogLevels {
+    handle: Option<LoggerHandle>,
+    global: Level,
+    modules: BTreeMap<&'static str, ModuleLevel>,
+}
+
+impl LogLevels {
+    fn spec(&self) -> String {
+        let mut spec = self.global.as_str().to_lowercase();
+        for (module, l) in self.modules.iter() {
+            spec.push_str(&format!(
+                ", {}::{}={}",
+                env!("CARGO_CRATE_NAME"),
+                module,
+                l.level.as_str().to_lowercase()
+            ));
+        }
+        spec
+    }
+
+    fn apply(&mut self) -> Result<(), String> {
+        let spec = self.spec();
+        match self.handle.as_mut() {
+            Some(h) => h.parse_new_spec(&spec).map_err(|e| e.to_string()),
+            None => Err("logger handle not set".to_owned()),
+        }
+    }
+
+    fn expire(&mut self, now: Instant) -> bool {
+        let len = self.modules.len();
+        self.modules
+            .retain(|_, l| l.expire_at.map(|t| t > now).unwrap_or(true));
+        self.modules.len() != len
+    }
+}
+
+fn log_module(module: &str) -> Result<&'static str, String> {
+    LOG_MODULES
+        .iter()
+        .find(|m| **m == module)
+        .copied()
+        .ok_or_else(|| format!("unknown module {}, supported: {:?}", module, LOG_MODULES))
+}
+
+// `level` is the configured global level, kept when module levels are changed
+pub fn set_log_handle(handle: LoggerHandle, level: Level) {
+    let mut levels = LOG_LEVELS.lock().unwrap();
+    levels.handle.replace(handle);
+    levels.global = level;
+}
+
+pub fn set_global_log_level(level: Level) -> Result<(), String> {
+    let mut levels = LOG_LEVELS.lock().unwrap();
+    levels.global = level;
+    levels.apply()
+}
+
+// Sets log level of a module, the level reverts to the global level after `duration`
+// if it is not zero
+pub fn set_module_log_level(module: &str, level: Level, duration: Duration) -> Result<(), String> {
+    let module = log_module(module)?;
+    let expire_at = if duration.is_zero() {
+        None
+    } else {
+        Some(Instant::now() + duration)
+    };
+    let mut levels = LOG_LEVELS.lock().unwrap();
+    levels
+        .modules
+        .insert(module, ModuleLevel { level, expire_at });
+    levels.apply()?;
+    drop(levels);
+    info!(
+        "log level of module {} set to {} for {:?}",
+        module, level, duration
+    );
+
+    if expire_at.is_some() {
+        thread::Builder::new()
+            .name("log-level-revert".to_owned())
+            .spawn(move || {
+                thread::sleep(duration);
+                let mut levels = LOG_LEVELS.lock().unwrap();
+                if levels.expire(Instant::now()) {
+                    if let Err(e) = levels.apply() {
+                        warn!("failed to revert log level: {}", e);
+                    } else {
+                        info!("log level of module {} reverted", module);
+                    }
+                }
+            })
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Resets the module to the global level, or all modules if `module` is None
+pub fn reset_module_log_level(module: Option<&str>) -> Result<(), String> {
+    let mut levels = LOG_LEVELS.lock().unwrap();
+    match module {
+        Some(m) => {
+            let m = log_module(m)?;
+            levels.modules.remove(m);
+        }
+        None => levels.modules.clear(),
+    }
+    levels.apply()
+}
+
+// Returns (module, level, remaining duration of elevation), modules without
+// overridden levels are reported with the global level
+pub fn log_levels() -> Vec<(String, Level, Option<Duration>)> {
+    let levels = LOG_LEVELS.lock().unwrap();
+    let now = Instant::now();
+    let mut result = vec![("global".to_owned(), levels.global, None)];
+    for module in LOG_MODULES {
+        match levels.modules.get(module) {
+            Some(l) => result.push((
+                module.to_owned(),
+                l.level,
+                l.expire_at.map(|t| t.saturating_duration_since(now)),
+            )),
+            None => result.push((module.to_owned(), levels.global, None)),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_spec() {
+        let now = Instant::now();
+        let mut levels = LogLevels {
+            handle: None,
+            global: Level::Warn,
+            modules: BTreeMap::new(),
+        };
+        assert_eq!(levels.spec(), "warn");
+
+        levels.modules.insert(
+            "rpc",
+            ModuleLevel {
+                level: Level::Debug,
+                expire_at: Some(now + Duration::from_secs(10)),
+            },
+        );
+        levels.modules.insert(
+            "ebpf",
+            ModuleLevel {
+                level: Level::Trace,
+                expire_at: None,
+            },
+        );
+        assert_eq!(
+            levels.spec(),
+            "warn, deepflow_agent::ebpf=trace, deepflow_agent::rpc=debug"
+        );
+
+        assert!(!levels.expire(now));
+        assert!(levels.expire(now + Duration::from_secs(10)));
+        assert_eq!(levels.spec(), "warn, deepflow_agent::ebpf=trace");
+        assert!(levels.apply().is_err());
+    }
+
+    #[test]
+    fn unknown_module() {
+        assert!(log_module("rpc").is_ok());
+        assert!(log_module("platform").is_err());
+    }
+}
@@ -15,19 +15,25 @@
  */
 
 pub(crate) mod cgroups;
+pub(crate) mod cloud_metadata;
 pub(crate) mod command;
 pub(crate) mod environment;
 pub(crate) mod guard;
 pub(crate) mod hasher;
+pub(crate) mod histogram;
 pub(crate) mod logger;
 pub(crate) mod lru;
 pub(crate) mod npb_bandwidth_watcher;
 pub(crate) mod possible_host;
+pub(crate) mod pprof;
 pub(crate) mod process;
+pub(crate) mod sketch;
 pub mod stats;
 
 #[cfg(target_os = "linux")]
 pub(crate) mod pid_file;
+#[cfg(target_os = "linux")]
+pub mod self_profiler;
 
 pub use public::bytes;
 
@@ -0,0 +1,468 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use log::info;
+use prost::Message;
+
+use crate::config::PprofExport;
+use public::proto::integration::pprof;
+
+// Folded stacks of eBPF profiles are aggregated in memory, so that they can be
+// exported in pprof format and consumed by pprof tools or pyroscope directly.
+// Data of the current and the previous window are kept.
+static STORE: Mutex<Option<ProfileStore>> = Mutex::new(None);
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProfileKind {
+    OnCpu,
+    OffCpu,
+}
+
+impl TryFrom<&str> for ProfileKind {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s {
+            "on-cpu" => Ok(Self::OnCpu),
+            "off-cpu" => Ok(Self::OffCpu),
+            _ => Err(format!("unknown profile event {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ProfileFilter {
+    pub pid: Option<u32>,
+    pub pod_id: Option<u32>,
+}
+
+#[derive(Debug, Default)]
+struct ProcessStacks {
+    process_name: String,
+    pod_id: u32,
+    // folded stack -> count
+    stacks: HashMap<String, u64>,
+}
+
+#[derive(Debug, Default)]
+struct Window {
+    start: Duration,
+    processes: HashMap<(ProfileKind, u32), ProcessStacks>,
+    stack_count: usize,
+}
+
+#[derive(Debug)]
+struct ProfileStore {
+    window: Duration,
+    max_stacks: usize,
+    // on-cpu sampling frequency in Hz
+    frequency: u32,
+    current: Window,
+    previous: Window,
+}
+
+fn now() -> Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+impl ProfileStore {
+    fn new(conf: &PprofExport, frequency: u32, now: Duration) -> Self {
+        Self {
+            window: conf.window,
+            max_stacks: conf.max_stacks,
+            frequency,
+            current: Window {
+                start: now,
+                ..Default::default()
+            },
+            previous: Window {
+                start: now,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn rotate(&mut self, now: Duration) {
+        if now < self.current.start + self.window {
+            return;
+        }
+        self.previous = std::mem::take(&mut self.current);
+        self.current.start = now;
+    }
+
+    fn record(
+        &mut self,
+        kind: ProfileKind,
+        pid: u32,
+        pod_id: u32,
+        process_name: &str,
+        count: u32,
+        stack: &str,
+        now: Duration,
+    ) {
+        self.rotate(now);
+        let window = &mut self.current;
+        let process = window
+            .processes
+            .entry((kind, pid))
+            .or_insert_with(|| ProcessStacks {
+                process_name: process_name.to_owned(),
+                pod_id,
+                ..Default::default()
+            });
+        if let Some(c) = process.stacks.get_mut(stack) {
+            *c += count as u64;
+        } else if window.stack_count < self.max_stacks {
+            process.stacks.insert(stack.to_owned(), count as u64);
+            window.stack_count += 1;
+        }
+    }
+
+    fn export(&self, kind: ProfileKind, filter: &ProfileFilter, now: Duration) -> pprof::Profile {
+        let mut builder = ProfileBuilder::default();
+        let period = match kind {
+            ProfileKind::OnCpu => NANOS_PER_SEC / self.frequency.max(1) as u64,
+            // off-cpu counts are durations in microseconds
+            ProfileKind::OffCpu => 1000,
+        };
+        let sample_type = match kind {
+            ProfileKind::OnCpu => vec![
+                builder.value_type("samples", "count"),
+                builder.value_type("cpu", "nanoseconds"),
+            ],
+            ProfileKind::OffCpu => vec![builder.value_type("off_cpu", "nanoseconds")],
+        };
+        let period_type = sample_type.last().cloned();
+
+        for window in [&self.previous, &self.current] {
+            for ((k, pid), process) in window.processes.iter() {
+                if *k != kind
+                    || matches!(filter.pid, Some(p) if p != *pid)
+                    || matches!(filter.pod_id, Some(p) if p != process.pod_id)
+                {
+                    continue;
+                }
+                let mut labels = vec![
+                    builder.num_label("pid", *pid as i64),
+                    builder.str_label("process_name", &process.process_name),
+                ];
+                if process.pod_id != 0 {
+                    labels.push(builder.num_label("pod_id", process.pod_id as i64));
+                }
+                for (stack, count) in process.stacks.iter() {
+                    let value = match kind {
+                        ProfileKind::OnCpu => vec![*count as i64, (*count * period) as i64],
+                        ProfileKind::OffCpu => vec![(*count * period) as i64],
+                    };
+                    builder.sample(stack, value, labels.clone());
+                }
+            }
+        }
+
+        let start = self.previous.start.min(self.current.start);
+        pprof::Profile {
+            sample_type,
+            period_type,
+            period: period as i64,
+            time_nanos: start.as_nanos() as i64,
+            duration_nanos: now.saturating_sub(start).as_nanos() as i64,
+            ..builder.build()
+        }
+    }
+}
+
+#[derive(Default)]
+struct ProfileBuilder {
+    strings: Vec<String>,
+    string_ids: HashMap<String, i64>,
+    // each frame has a function and a location with the same id
+    frame_ids: HashMap<String, u64>,
+    functions: Vec<pprof::Function>,
+    locations: Vec<pprof::Location>,
+    samples: Vec<pprof::Sample>,
+}
+
+impl ProfileBuilder {
+    fn string_id(&mut self, s: &str) -> i64 {
+        if self.strings.is_empty() {
+            // string_table[0] must always be ""
+            self.strings.push(String::new());
+            self.string_ids.insert(String::new(), 0);
+        }
+        if let Some(id) = self.string_ids.get(s) {
+            return *id;
+        }
+        let id = self.strings.len() as i64;
+        self.strings.push(s.to_owned());
+        self.string_ids.insert(s.to_owned(), id);
+        id
+    }
+
+    fn value_type(&mut self, ty: &str, unit: &str) -> pprof::ValueType {
+        pprof::ValueType {
+            r#type: self.string_id(ty),
+            unit: self.string_id(unit),
+        }
+    }
+
+    fn num_label(&mut self, key: &str, num: i64) -> pprof::Label {
+        pprof::Label {
+            key: self.string_id(key),
+            num,
+            ..Default::default()
+        }
+    }
+
+    fn str_label(&mut self, key: &str, value: &str) -> pprof::Label {
+        pprof::Label {
+            key: self.string_id(key),
+            str: self.string_id(value),
+            ..Default::default()
+        }
+    }
+
+    fn frame_id(&mut self, frame: &str) -> u64 {
+        if let Some(id) = self.frame_ids.get(frame) {
+            return *id;
+        }
+        let id = self.functions.len() as u64 + 1;
+        let name = self.string_id(frame);
+        self.functions.push(pprof::Function {
+            id,
+            name,
+            system_name: name,
+            ..Default::default()
+        });
+        self.locations.push(pprof::Location {
+            id,
+            line: vec![pprof::Line {
+                function_id: id,
+                line: 0,
+            }],
+            ..Default::default()
+        });
+        self.frame_ids.insert(frame.to_owned(), id);
+        id
+    }
+
+    // folded stacks start from the root frame, while pprof locations start from the leaf
+    fn sample(&mut self, stack: &str, value: Vec<i64>, label: Vec<pprof::Label>) {
+        let location_id = stack
+            .rsplit(';')
+            .filter(|f| !f.is_empty())
+            .map(|f| self.frame_id(f))
+            .collect();
+        self.samples.push(pprof::Sample {
+            location_id,
+            value,
+            label,
+        });
+    }
+
+    fn build(mut self) -> pprof::Profile {
+        self.string_id("");
+        pprof::Profile {
+            sample: self.samples,
+            location: self.locations,
+            function: self.functions,
+            string_table: self.strings,
+            ..Default::default()
+        }
+    }
+}
+
+pub fn set_config(conf: &PprofExport, frequency: u32) {
+    let mut store = STORE.lock().unwrap();
+    match store.as_mut() {
+        Some(s) if conf.enabled => {
+            s.window = conf.window;
+            s.max_stacks = conf.max_stacks;
+            s.frequency = frequency;
+        }
+        None if conf.enabled => {
+            info!("ebpf profile pprof export enabled");
+            *store = Some(ProfileStore::new(conf, frequency, now()));
+        }
+        Some(_) => {
+            info!("ebpf profile pprof export disabled");
+            *store = None;
+        }
+        None => (),
+    }
+}
+
+pub fn record(
+    kind: ProfileKind,
+    pid: u32,
+    pod_id: u32,
+    process_name: &str,
+    count: u32,
+    stack: &str,
+) {
+    // skip the profile rather than blocking the profiler callback
+    let Ok(mut store) = STORE.try_lock() else {
+        return;
+    };
+    if let Some(store) = store.as_mut() {
+        store.record(kind, pid, pod_id, process_name, count, stack, now());
+    }
+}
+
+pub fn enabled() -> bool {
+    STORE.lock().unwrap().is_some()
+}
+
+// Returns the encoded pprof profile, or None if pprof export is disabled
+pub fn export(kind: ProfileKind, filter: &ProfileFilter) -> Option<Vec<u8>> {
+    let profile = {
+        let store = STORE.lock().unwrap();
+        store.as_ref()?.export(kind, filter, now())
+    };
+    Some(profile.encode_to_vec())
+}
+
+// Encodes folded stacks with values of the sample types in pprof format,
+// used for profiles not collected by eBPF, like the agent's own heap profile
+pub fn encode_folded<'a, I>(sample_types: &[(&str, &str)], stacks: I, start: Duration) -> Vec<u8>
+where
+    I: IntoIterator<Item = (&'a str, Vec<i64>)>,
+{
+    let mut builder = ProfileBuilder::default();
+    let sample_type: Vec<_> = sample_types
+        .iter()
+        .map(|(ty, unit)| builder.value_type(ty, unit))
+        .collect();
+    for (stack, value) in stacks {
+        builder.sample(stack, value, vec![]);
+    }
+    pprof::Profile {
+        sample_type,
+        time_nanos: start.as_nanos() as i64,
+        duration_nanos: now().saturating_sub(start).as_nanos() as i64,
+        ..builder.build()
+    }
+    .encode_to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> ProfileStore {
+        ProfileStore::new(
+            &PprofExport {
+                enabled: true,
+                window: Duration::from_secs(60),
+                max_stacks: 3,
+            },
+            100,
+            Duration::from_secs(1000),
+        )
+    }
+
+    #[test]
+    fn export_on_cpu() {
+        let mut s = store();
+        let now = Duration::from_secs(1001);
+        s.record(ProfileKind::OnCpu, 10, 7, "nginx", 2, "main;a;b", now);
+        s.record(ProfileKind::OnCpu, 10, 7, "nginx", 3, "main;a;b", now);
+        s.record(ProfileKind::OnCpu, 10, 7, "nginx", 1, "main;c", now);
+        s.record(ProfileKind::OffCpu, 10, 7, "nginx", 1, "main;d", now);
+        s.record(ProfileKind::OnCpu, 11, 0, "redis", 1, "main;e", now);
+
+        let p = s.export(
+            ProfileKind::OnCpu,
+            &ProfileFilter {
+                pod_id: Some(7),
+                ..Default::default()
+            },
+            now,
+        );
+        assert_eq!(p.string_table[0], "");
+        assert_eq!(p.period, 10_000_000);
+        assert_eq!(p.sample.len(), 2);
+        let sample = p.sample.iter().find(|s| s.value[0] == 5).unwrap();
+        assert_eq!(sample.value[1], 50_000_000);
+        // leaf first
+        let names = sample
+            .location_id
+            .iter()
+            .map(|id| {
+                let f = &p.function[*id as usize - 1];
+                p.string_table[f.name as usize].as_str()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["b", "a", "main"]);
+        // frames are shared between samples
+        assert_eq!(p.function.len(), 4);
+    }
+
+    #[test]
+    fn stack_limit_and_rotation() {
+        let mut s = store();
+        let now = Duration::from_secs(1001);
+        for stack in ["a", "b", "c", "d"] {
+            s.record(ProfileKind::OffCpu, 10, 0, "java", 100, stack, now);
+        }
+        let p = s.export(ProfileKind::OffCpu, &ProfileFilter::default(), now);
+        assert_eq!(p.sample.len(), 3);
+        assert_eq!(p.sample[0].value, [100_000]);
+
+        // previous window is still exported
+        let now = Duration::from_secs(1061);
+        s.record(ProfileKind::OffCpu, 10, 0, "java", 1, "d", now);
+        let p = s.export(ProfileKind::OffCpu, &ProfileFilter::default(), now);
+        assert_eq!(p.sample.len(), 4);
+        assert_eq!(p.time_nanos, 1000 * NANOS_PER_SEC as i64);
+
+        let now = Duration::from_secs(1200);
+        s.record(ProfileKind::OffCpu, 10, 0, "java", 1, "e", now);
+        let p = s.export(
+            ProfileKind::OffCpu,
+            &ProfileFilter {
+                pid: Some(10),
+                ..Default::default()
+            },
+            now,
+        );
+        assert_eq!(p.sample.len(), 2);
+    }
+
+    #[test]
+    fn folded() {
+        let encoded = encode_folded(
+            &[("alloc_objects", "count"), ("alloc_space", "bytes")],
+            [("main;a", vec![1, 1024]), ("main;b", vec![2, 4096])],
+            now(),
+        );
+        let p = pprof::Profile::decode(encoded.as_slice()).unwrap();
+        assert_eq!(p.sample_type.len(), 2);
+        assert_eq!(p.string_table[p.sample_type[1].unit as usize], "bytes");
+        assert_eq!(p.sample.len(), 2);
+        assert_eq!(p.sample[1].value, [2, 4096]);
+        assert_eq!(p.function.len(), 3);
+    }
+}
@@ -0,0 +1,369 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    collections::HashMap,
+    ffi::c_void,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use log::info;
+use pprof::protos::Message;
+
+use super::pprof::encode_folded;
+
+pub const MAX_PROFILE_DURATION: Duration = Duration::from_secs(300);
+pub const DEFAULT_CPU_FREQUENCY: i32 = 99;
+
+// bytes allocated by a thread between two heap samples
+const HEAP_SAMPLE_INTERVAL: isize = 512 << 10;
+const MAX_HEAP_FRAMES: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProfileType {
+    Cpu,
+    Heap,
+}
+
+impl TryFrom<&str> for ProfileType {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s {
+            "cpu" => Ok(Self::Cpu),
+            "heap" => Ok(Self::Heap),
+            _ => Err(format!("unknown profile type {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProfileFormat {
+    Flamegraph,
+    Pprof,
+}
+
+impl TryFrom<&str> for ProfileFormat {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s {
+            "svg" | "flamegraph" => Ok(Self::Flamegraph),
+            "pprof" => Ok(Self::Pprof),
+            _ => Err(format!("unknown profile format {}", s)),
+        }
+    }
+}
+
+// Only one self profile can run at a time, as both the cpu profiler and the heap
+// sampler are process wide
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+struct RunningGuard;
+
+impl RunningGuard {
+    fn acquire() -> Result<Self, String> {
+        RUNNING
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+            .map(|_| Self)
+            .map_err(|_| "another self profile is running".to_owned())
+    }
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::Release);
+    }
+}
+
+// Profiles the agent itself for the duration, blocking the calling thread
+pub fn profile(
+    profile_type: ProfileType,
+    duration: Duration,
+    frequency: i32,
+    format: ProfileFormat,
+) -> Result<Vec<u8>, String> {
+    if duration.is_zero() || duration > MAX_PROFILE_DURATION {
+        return Err(format!(
+            "profile duration should be in (0, {:?}]",
+            MAX_PROFILE_DURATION
+        ));
+    }
+    let _guard = RunningGuard::acquire()?;
+    info!(
+        "self profile {:?} started for {:?}, output format {:?}",
+        profile_type, duration, format
+    );
+    let result = match profile_type {
+        ProfileType::Cpu => cpu(duration, frequency, format),
+        ProfileType::Heap => heap(duration, format),
+    };
+    match result.as_ref() {
+        Ok(data) => info!(
+            "self profile {:?} finished with {} bytes",
+            profile_type,
+            data.len()
+        ),
+        Err(e) => info!("self profile {:?} failed: {}", profile_type, e),
+    }
+    result
+}
+
+fn cpu(duration: Duration, frequency: i32, format: ProfileFormat) -> Result<Vec<u8>, String> {
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(frequency)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()
+        .map_err(|e| format!("start cpu profiler failed: {}", e))?;
+    thread::sleep(duration);
+    let report = guard
+        .report()
+        .build()
+        .map_err(|e| format!("build cpu profile failed: {}", e))?;
+    let mut data = vec![];
+    match format {
+        ProfileFormat::Flamegraph => report
+            .flamegraph(&mut data)
+            .map_err(|e| format!("render cpu flamegraph failed: {}", e))?,
+        ProfileFormat::Pprof => report
+            .pprof()
+            .map_err(|e| format!("build cpu pprof failed: {}", e))?
+            .encode(&mut data)
+            .map_err(|e| format!("encode cpu pprof failed: {}", e))?,
+    }
+    Ok(data)
+}
+
+static HEAP_SAMPLING: AtomicBool = AtomicBool::new(false);
+// instruction pointers of a sampled stack -> (samples, bytes)
+static HEAP_STACKS: Mutex<Option<HashMap<Vec<usize>, (i64, i64)>>> = Mutex::new(None);
+
+thread_local! {
+    // bytes to allocate before the next sample, and whether the thread is sampling,
+    // in which case allocations of the sampler itself are not sampled
+    static SAMPLE_STATE: Cell<(isize, bool)> = const { Cell::new((HEAP_SAMPLE_INTERVAL, false)) };
+}
+
+fn sample_alloc(size: usize) {
+    let _ = SAMPLE_STATE.try_with(|state| {
+        let (remaining, sampling) = state.get();
+        if sampling {
+            return;
+        }
+        let remaining = remaining - size as isize;
+        if remaining > 0 {
+            state.set((remaining, false));
+            return;
+        }
+        state.set((HEAP_SAMPLE_INTERVAL, true));
+
+        let mut ips = [0usize; MAX_HEAP_FRAMES];
+        let mut depth = 0;
+        // SAFETY: the thread is marked as sampling, so that tracing is not reentered
+        // from allocations on this thread
+        unsafe {
+            backtrace::trace_unsynchronized(|frame| {
+                ips[depth] = frame.ip() as usize;
+                depth += 1;
+                depth < MAX_HEAP_FRAMES
+            });
+        }
+        if let Ok(mut stacks) = HEAP_STACKS.lock() {
+            if let Some(stacks) = stacks.as_mut() {
+                let entry = stacks.entry(ips[..depth].to_vec()).or_default();
+                entry.0 += 1;
+                // a sample stands for all bytes allocated since the previous one
+                entry.1 += (HEAP_SAMPLE_INTERVAL - remaining) as i64;
+            }
+        }
+        state.set((HEAP_SAMPLE_INTERVAL, false));
+    });
+}
+
+// The system allocator with allocation sampling for heap profiles, which costs
+// an atomic load per allocation when heap profile is not running
+pub struct SamplingAllocator;
+
+unsafe impl GlobalAlloc for SamplingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if HEAP_SAMPLING.load(Ordering::Relaxed) && !ptr.is_null() {
+            sample_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if HEAP_SAMPLING.load(Ordering::Relaxed) && !ptr.is_null() {
+            sample_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if HEAP_SAMPLING.load(Ordering::Relaxed) && !new_ptr.is_null() {
+            sample_alloc(new_size.saturating_sub(layout.size()));
+        }
+        new_ptr
+    }
+}
+
+fn resolve(ip: usize, frames: &mut Vec<String>) {
+    let start = frames.len();
+    // return addresses point to the instruction after the call
+    backtrace::resolve(ip.saturating_sub(1) as *mut c_void, |symbol| {
+        if let Some(name) = symbol.name() {
+            frames.push(format!("{:#}", name));
+        }
+    });
+    if frames.len() == start {
+        frames.push(format!("{:#x}", ip));
+    }
+}
+
+fn is_sampler_frame(frame: &str) -> bool {
+    frame.contains("self_profiler")
+        || frame.starts_with("backtrace::")
+        || frame.starts_with("__rust")
+        || frame.starts_with("std::thread::local")
+}
+
+// Folds frames from leaf to root into a stack from root to leaf, without the
+// frames of the sampler
+fn fold(frames: &[String]) -> String {
+    let leaf = frames
+        .iter()
+        .position(|f| !is_sampler_frame(f))
+        .unwrap_or(frames.len());
+    let mut stack = String::new();
+    for frame in frames[leaf..].iter().rev() {
+        if !stack.is_empty() {
+            stack.push(';');
+        }
+        // semicolons are separators of folded stacks
+        stack.push_str(&frame.replace(';', ":"));
+    }
+    stack
+}
+
+// Heap profile of allocations during the duration, rather than memory in use,
+// which requires tracking every deallocation
+fn heap(duration: Duration, format: ProfileFormat) -> Result<Vec<u8>, String> {
+    let start = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    *HEAP_STACKS.lock().unwrap() = Some(HashMap::new());
+    HEAP_SAMPLING.store(true, Ordering::Relaxed);
+    thread::sleep(duration);
+    HEAP_SAMPLING.store(false, Ordering::Relaxed);
+    let stacks = HEAP_STACKS.lock().unwrap().take().unwrap_or_default();
+    if stacks.is_empty() {
+        return Err("no heap samples collected".to_owned());
+    }
+
+    let mut folded: HashMap<String, (i64, i64)> = HashMap::new();
+    let mut frames = vec![];
+    for (ips, (samples, bytes)) in stacks {
+        frames.clear();
+        for ip in ips {
+            resolve(ip, &mut frames);
+        }
+        let entry = folded.entry(fold(&frames)).or_default();
+        entry.0 += samples;
+        entry.1 += bytes;
+    }
+
+    match format {
+        ProfileFormat::Flamegraph => {
+            let lines: Vec<String> = folded
+                .iter()
+                .map(|(stack, (_, bytes))| format!("{} {}", stack, bytes))
+                .collect();
+            let mut options = inferno::flamegraph::Options::default();
+            options.title = "Heap Allocations".to_owned();
+            options.count_name = "bytes".to_owned();
+            let mut data = vec![];
+            inferno::flamegraph::from_lines(
+                &mut options,
+                lines.iter().map(String::as_str),
+                &mut data,
+            )
+            .map_err(|e| format!("render heap flamegraph failed: {}", e))?;
+            Ok(data)
+        }
+        ProfileFormat::Pprof => Ok(encode_folded(
+            &[("alloc_objects", "count"), ("alloc_space", "bytes")],
+            folded
+                .iter()
+                .map(|(stack, (samples, bytes))| (stack.as_str(), vec![*samples, *bytes])),
+            start,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fold_frames() {
+        let frames = [
+            "backtrace::backtrace::trace_unsynchronized",
+            "deepflow_agent::utils::self_profiler::sample_alloc",
+            "<deepflow_agent::utils::self_profiler::SamplingAllocator as core::alloc::global::GlobalAlloc>::alloc",
+            "__rust_alloc",
+            "alloc::raw_vec::RawVec<T,A>::allocate_in",
+            "deepflow_agent::flow_generator::perf::l7_rrt::L7RrtCache::add_req_time",
+            "main",
+        ]
+        .map(String::from);
+        assert_eq!(
+            fold(&frames),
+            "main;deepflow_agent::flow_generator::perf::l7_rrt::L7RrtCache::add_req_time;alloc::raw_vec::RawVec<T,A>::allocate_in"
+        );
+        assert_eq!(fold(&frames[..4]), "");
+    }
+
+    #[test]
+    fn profile_options() {
+        assert_eq!(
+            ProfileFormat::try_from("svg"),
+            Ok(ProfileFormat::Flamegraph)
+        );
+        assert_eq!(ProfileType::try_from("heap"), Ok(ProfileType::Heap));
+        assert!(ProfileType::try_from("mutex").is_err());
+        assert!(profile(
+            ProfileType::Cpu,
+            Duration::from_secs(3600),
+            DEFAULT_CPU_FREQUENCY,
+            ProfileFormat::Pprof
+        )
+        .is_err());
+    }
+}
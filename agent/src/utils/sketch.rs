@@ -0,0 +1,250 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+pub const DEFAULT_RELATIVE_ACCURACY: f64 = 0.01;
+// Bins kept inline, values spanning more than gamma^BINS (about 160x with 1% relative
+// accuracy) have the lowest bins collapsed, so that high quantiles stay accurate
+pub const BINS: usize = 256;
+
+// DDSketch of u32 values with logarithmic bins:
+// - Bin i counts values in (gamma^(i-1), gamma^i], where gamma = (1 + a) / (1 - a)
+// - The representative value of each bin is within relative accuracy a of the values in it
+// - Value 0 is counted in zero_count
+// Bins are a fixed size array, so that the sketch is Copy and needs no heap allocation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sketch {
+    relative_accuracy: f64,
+    // 1 / ln(gamma)
+    multiplier: f64,
+    // bin index of bins[0]
+    offset: i32,
+    zero_count: u32,
+    // values counted in bins
+    count: u64,
+    bins: [u32; BINS],
+}
+
+impl Default for Sketch {
+    fn default() -> Self {
+        Self::new(DEFAULT_RELATIVE_ACCURACY)
+    }
+}
+
+impl Sketch {
+    // relative_accuracy in (0, 1)
+    pub fn new(relative_accuracy: f64) -> Self {
+        let gamma = (1.0 + relative_accuracy) / (1.0 - relative_accuracy);
+        Self {
+            relative_accuracy,
+            multiplier: 1.0 / gamma.ln(),
+            offset: 0,
+            zero_count: 0,
+            count: 0,
+            bins: [0; BINS],
+        }
+    }
+
+    pub fn relative_accuracy(&self) -> f64 {
+        self.relative_accuracy
+    }
+
+    fn index(&self, value: f64) -> i32 {
+        (value.ln() * self.multiplier).ceil() as i32
+    }
+
+    fn value(&self, index: i32) -> f64 {
+        let gamma = (1.0 / self.multiplier).exp();
+        2.0 * gamma.powi(index) / (gamma + 1.0)
+    }
+
+    pub fn record(&mut self, value: u32) {
+        if value == 0 {
+            self.zero_count = self.zero_count.saturating_add(1);
+        } else {
+            self.add(self.index(value as f64), 1);
+        }
+    }
+
+    fn add(&mut self, index: i32, n: u32) {
+        if self.count == 0 {
+            self.offset = index - BINS as i32 / 2;
+        }
+        self.count += n as u64;
+        if index < self.offset {
+            self.bins[0] = self.bins[0].saturating_add(n);
+            return;
+        }
+        let mut i = (index - self.offset) as usize;
+        if i >= BINS {
+            self.slide(i - BINS + 1);
+            i = BINS - 1;
+        }
+        self.bins[i] = self.bins[i].saturating_add(n);
+    }
+
+    // Moves the bins window up by shift, collapsing the lowest bins into bins[0]
+    fn slide(&mut self, shift: usize) {
+        let collapsed = self.bins[..=shift.min(BINS - 1)]
+            .iter()
+            .fold(0u32, |s, b| s.saturating_add(*b));
+        if shift < BINS {
+            self.bins.copy_within(shift + 1.., 1);
+            self.bins[BINS - shift..].fill(0);
+        } else {
+            self.bins.fill(0);
+        }
+        self.bins[0] = collapsed;
+        self.offset += shift as i32;
+    }
+
+    pub fn merge(&mut self, other: &Sketch) {
+        let zero_count = self.zero_count.saturating_add(other.zero_count);
+        if self.count == 0 {
+            *self = *other;
+        } else if self.multiplier == other.multiplier {
+            for (i, n) in other.bins.iter().enumerate().filter(|(_, n)| **n > 0) {
+                self.add(other.offset + i as i32, *n);
+            }
+        } else {
+            // remap bins of a sketch with different relative accuracy by representative values
+            for (i, n) in other.bins.iter().enumerate().filter(|(_, n)| **n > 0) {
+                self.add(self.index(other.value(other.offset + i as i32)), *n);
+            }
+        }
+        self.zero_count = zero_count;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0 && self.zero_count == 0
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count + self.zero_count as u64
+    }
+
+    pub fn zero_count(&self) -> u32 {
+        self.zero_count
+    }
+
+    // Bin index of the first bin and bins with leading and trailing empty bins trimmed
+    pub fn bins(&self) -> (i32, &[u32]) {
+        let Some(first) = self.bins.iter().position(|b| *b > 0) else {
+            return (0, &[]);
+        };
+        let last = self.bins.iter().rposition(|b| *b > 0).unwrap();
+        (self.offset + first as i32, &self.bins[first..=last])
+    }
+
+    // Representative value of the bin where the quantile falls in, q in [0, 1]
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((count as f64 * q).ceil() as u64).clamp(1, count);
+        if rank <= self.zero_count as u64 {
+            return Some(0.0);
+        }
+        let mut seen = self.zero_count as u64;
+        for (i, b) in self.bins.iter().enumerate() {
+            seen += *b as u64;
+            if seen >= rank {
+                return Some(self.value(self.offset + i as i32));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_accurate(s: &Sketch, q: f64, expected: f64) {
+        let v = s.quantile(q).unwrap();
+        assert!(
+            (v - expected).abs() <= expected * s.relative_accuracy(),
+            "quantile {} is {}, expected {}",
+            q,
+            v,
+            expected
+        );
+    }
+
+    #[test]
+    fn record_and_quantile() {
+        let mut s = Sketch::default();
+        assert_eq!(s.quantile(0.5), None);
+        for v in 1..=100 {
+            s.record(v);
+        }
+        assert_eq!(s.count(), 100);
+        assert_accurate(&s, 0.5, 50.0);
+        assert_accurate(&s, 0.99, 99.0);
+        assert_accurate(&s, 1.0, 100.0);
+
+        let mut s = Sketch::new(0.05);
+        s.record(0);
+        s.record(1000);
+        assert_eq!(s.zero_count(), 1);
+        assert_eq!(s.quantile(0.5), Some(0.0));
+        assert_accurate(&s, 1.0, 1000.0);
+        let (_, bins) = s.bins();
+        assert_eq!(bins, &[1]);
+    }
+
+    #[test]
+    fn collapse_lowest() {
+        let mut s = Sketch::default();
+        for v in [1, 10, 100, 1000, 10000, 100000] {
+            s.record(v);
+        }
+        assert_eq!(s.count(), 6);
+        // only the lowest values are collapsed
+        assert_accurate(&s, 0.6, 1000.0);
+        assert_accurate(&s, 1.0, 100000.0);
+        assert!(s.quantile(0.1).unwrap() > 1.0);
+    }
+
+    #[test]
+    fn merge() {
+        let mut a = Sketch::default();
+        let mut b = Sketch::default();
+        for v in 1..=50 {
+            a.record(v);
+        }
+        for v in 51..=100 {
+            b.record(v);
+        }
+        b.record(0);
+        a.merge(&b);
+        assert_eq!(a.count(), 101);
+        assert_eq!(a.zero_count(), 1);
+        assert_accurate(&a, 0.99, 99.0);
+
+        let mut c = Sketch::new(0.02);
+        c.record(200);
+        a.merge(&c);
+        assert_eq!(a.count(), 102);
+        // remapped value is within the sum of both relative accuracies
+        let v = a.quantile(1.0).unwrap();
+        assert!((v - 200.0).abs() <= 200.0 * 0.03, "{}", v);
+
+        let mut empty = Sketch::default();
+        empty.merge(&c);
+        assert_eq!(empty, c);
+    }
+}
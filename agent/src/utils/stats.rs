@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+use std::collections::BTreeMap;
 use std::fmt;
 use std::io;
 use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
@@ -52,6 +53,8 @@ struct Source {
     tags: Vec<(&'static str, String)>,
     // countdown to next metrics collection
     skip: i64,
+    // kept for diagnostics
+    last_batch: Option<Arc<Batch>>,
 }
 
 impl PartialEq for Source {
@@ -77,7 +80,70 @@ pub struct Batch {
     timestamp: u32,
 }
 
+impl fmt::Display for Batch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.module)?;
+        for (i, (k, v)) in self.tags.iter().enumerate() {
+            write!(f, "{}{}={}", if i == 0 { "{" } else { "," }, k, v)?;
+        }
+        if !self.tags.is_empty() {
+            write!(f, "}}")?;
+        }
+        for p in self.points.iter() {
+            let name = point_name(p.0, p.1);
+            match p.2 {
+                CounterValue::Signed(i) => write!(f, " {}={}", name, i)?,
+                CounterValue::Unsigned(u) => write!(f, " {}={}", name, u)?,
+                CounterValue::Float(v) => write!(f, " {}={}", name, v)?,
+            }
+        }
+        write!(f, " {}", self.timestamp)
+    }
+}
+
 impl Batch {
+    // Appends points as prometheus samples grouped by metric name. Histograms are
+    // exported as `_bucket` samples with `le` label plus `_sum` and `_count`, and
+    // estimated quantiles as samples with `quantile` label.
+    fn to_prometheus(&self, families: &mut BTreeMap<String, Vec<String>>) {
+        let mut labels = self
+            .tags
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", prometheus_name(k), escape_label_value(v)))
+            .collect::<Vec<_>>();
+        if !self.tags.iter().any(|t| t.0 == "host") {
+            labels.push(format!("host=\"{}\"", escape_label_value(&self.hostname)));
+        }
+
+        for p in self.points.iter() {
+            let base = prometheus_name(&format!("{}_{}_{}", STATS_PREFIX, self.module, p.0));
+            let (name, extra_label) = match p.1 {
+                CounterType::Counted | CounterType::Gauged => (base, None),
+                CounterType::Histogram(HistogramPoint::Bucket(u64::MAX)) => {
+                    (base + "_bucket", Some("le=\"+Inf\"".to_owned()))
+                }
+                CounterType::Histogram(HistogramPoint::Bucket(b)) => {
+                    (base + "_bucket", Some(format!("le=\"{}\"", b)))
+                }
+                CounterType::Histogram(HistogramPoint::Sum) => (base + "_sum", None),
+                CounterType::Histogram(HistogramPoint::Count) => (base + "_count", None),
+                CounterType::Histogram(HistogramPoint::Quantile(q)) => {
+                    (base, Some(format!("quantile=\"{}\"", q as f64 / 100.0)))
+                }
+            };
+            let value = match p.2 {
+                CounterValue::Signed(i) => i.to_string(),
+                CounterValue::Unsigned(u) => u.to_string(),
+                CounterValue::Float(f) => f.to_string(),
+            };
+            let sample = match extra_label {
+                Some(l) => format!("{}{{{},{}}} {}", name, labels.join(","), l, value),
+                None => format!("{}{{{}}} {}", name, labels.join(","), value),
+            };
+            families.entry(name).or_default().push(sample);
+        }
+    }
+
     fn to_stats(&self) -> stats::Stats {
         let mut tag_names = vec![];
         let mut tag_values = vec![];
@@ -98,7 +164,7 @@ impl Batch {
         }
 
         for p in self.points.iter() {
-            metrics_float_names.push(p.0.to_string());
+            metrics_float_names.push(point_name(p.0, p.1).into_owned());
             match p.2 {
                 CounterValue::Signed(i) => metrics_float_values.push(i as f64),
                 CounterValue::Unsigned(u) => metrics_float_values.push(u as f64),
@@ -119,6 +185,19 @@ impl Batch {
     }
 }
 
+fn prometheus_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
 #[derive(Debug)]
 pub struct ArcBatch(Arc<Batch>);
 
@@ -252,6 +331,7 @@ impl Collector {
             countable,
             tags: vec![],
             skip: 0,
+            last_batch: None,
         };
         for tag in module.tags() {
             match tag {
@@ -323,6 +403,40 @@ impl Collector {
         }
     }
 
+    // Writes the last reported counters of all sources without resetting them
+    pub fn write_snapshot<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        let sources = self.sources.lock().unwrap();
+        for s in sources.iter() {
+            if s.countable.closed() {
+                continue;
+            }
+            if let Some(batch) = s.last_batch.as_ref() {
+                writeln!(w, "{}", batch)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Writes the last reported counters of all sources in prometheus text format
+    pub fn write_prometheus<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        // samples of the same metric must be written consecutively
+        let mut families = BTreeMap::new();
+        for s in self.sources.lock().unwrap().iter() {
+            if s.countable.closed() {
+                continue;
+            }
+            if let Some(batch) = s.last_batch.as_ref() {
+                batch.to_prometheus(&mut families);
+            }
+        }
+        for samples in families.values() {
+            for sample in samples {
+                writeln!(w, "{}", sample)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn register_pre_hook(&self, hook: Box<dyn FnMut() + Send>) {
         self.pre_hooks.lock().unwrap().push(hook);
     }
@@ -426,6 +540,7 @@ impl Collector {
                                         points,
                                         timestamp: now,
                                     });
+                                    source.last_batch = Some(batch.clone());
                                     if let Err(_) = sender.send(ArcBatch(batch.clone())) {
                                         debug!(
                                         "stats to send queue failed because queue have terminated"
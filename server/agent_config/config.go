@@ -100,6 +100,7 @@ type AgentGroupConfig struct {
 type StaticConfig struct {
 	ProxyControllerPort                *uint16                      `yaml:"proxy-controller-port,omitempty"`
 	LogLevel                           *string                      `yaml:"log-level,omitempty"`
+	ModuleLogLevels                    []ModuleLogLevel             `yaml:"module-log-levels,omitempty"`
 	Profiler                           *bool                        `yaml:"profiler,omitempty"`
 	AfpacketBlocksEnabled              *bool                        `yaml:"afpacket-blocks-enabled,omitempty"`
 	AfpacketBlocks                     *int                         `yaml:"afpacket-blocks,omitempty"`
@@ -133,8 +134,10 @@ type StaticConfig struct {
 	ToaLruCacheSize                    *int                         `yaml:"toa-lru-cache-size,omitempty"`
 	FlowSenderQueueSize                *int                         `yaml:"flow-sender-queue-size,omitempty"`
 	FlowSenderQueueCount               *int                         `yaml:"flow-sender-queue-count,omitempty"`
+	QueueOverflow                      *QueueOverflowConfig         `yaml:"queue-overflow,omitempty"`
 	SecondFlowExtraDelaySecond         *string                      `yaml:"second-flow-extra-delay-second,omitempty"`
 	PacketDelay                        *string                      `yaml:"packet-delay,omitempty"`
+	PreAggregation                     []PreAggregationRule         `yaml:"pre-aggregation,omitempty"`
 	Triple                             *TripleMapConfig             `yaml:"triple,omitempty"`
 	KubernetesPollerType               *string                      `yaml:"kubernetes-poller-type,omitempty"`
 	DecapErspan                        *bool                        `yaml:"decap-erspan,omitempty"`
@@ -144,6 +147,10 @@ type StaticConfig struct {
 	KubernetesAPIListLimit             *uint32                      `yaml:"kubernetes-api-list-limit,omitempty"`
 	KubernetesAPIListInterval          *string                      `yaml:"kubernetes-api-list-interval,omitempty"`
 	KubernetesResources                []KubernetesResourceConfig   `yaml:"kubernetes-resources,omitempty"`
+	PlatformSyncExcludeInterfaceRegex  *string                      `yaml:"platform-sync-exclude-interface-regex,omitempty"`
+	DockerDiscoveryEnabled             *bool                        `yaml:"docker-discovery-enabled,omitempty"`
+	DockerSocketPath                   *string                      `yaml:"docker-socket-path,omitempty"`
+	PlatformSyncHostIPCidrs            []string                     `yaml:"platform-sync-host-ip-cidrs,omitempty"`
 	IngressFlavour                     *string                      `yaml:"ingress-flavour,omitempty"`
 	GrpcBufferSize                     *int                         `yaml:"grpc-buffer-size,omitempty"`            // 单位：M
 	L7LogSessionAggrTimeout            *string                      `yaml:"l7-log-session-aggr-timeout,omitempty"` // 单位: s
@@ -158,11 +165,13 @@ type StaticConfig struct {
 	PacketSequenceQueueCount           *int                         `yaml:"packet-sequence-queue-count,omitempty"`
 	PacketSequenceFlag                 *uint8                       `yaml:"packet-sequence-flag,omitempty"`
 	L7ProtocolEnabled                  []string                     `yaml:"l7-protocol-enabled,omitempty"`
+	L7ProtocolDisabled                 []string                     `yaml:"l7-protocol-disabled,omitempty"`
 	StandaloneDataFileSize             *uint64                      `yaml:"standalone-data-file-size,omitempty"`
 	StandaloneDataFileDir              *string                      `yaml:"standalone-data-file-dir,omitempty"`
 	LogFile                            *string                      `yaml:"log-file,omitempty"`
 	ExternalAgentHttpProxyCompressed   *bool                        `yaml:"external-agent-http-proxy-compressed,omitempty"`
 	FeatureFlags                       []string                     `yaml:"feature-flags,omitempty"`
+	FeatureFlagRollouts                []FeatureFlagRollout         `yaml:"feature-flag-rollouts,omitempty"`
 	L7ProtocolPorts                    map[string]string            `yaml:"l7-protocol-ports,omitempty"`
 	L7LogBlacklist                     map[string][]*L7LogBlacklist `yaml:"l7-log-blacklist,omitempty"`
 	L7ProtocolAdvancedFeatures         *L7ProtocolAdvancedFeatures  `yaml:"l7-protocol-advanced-features,omitempty"`
@@ -175,6 +184,7 @@ type StaticConfig struct {
 	OsProcRegex                        []*OsProcRegex               `yaml:"os-proc-regex,omitempty"`
 	OsProcSyncEnabled                  *bool                        `yaml:"os-proc-sync-enabled,omitempty"`
 	OsProcSyncTaggedOnly               *bool                        `yaml:"os-proc-sync-tagged-only,omitempty"`
+	OsProcServiceRules                 []*OsProcServiceRule         `yaml:"os-proc-service-rules,omitempty"`
 	GuardInterval                      *string                      `yaml:"guard-interval,omitempty"`
 	CheckCoreFileDisabled              *bool                        `yaml:"check-core-file-disabled,omitempty"`
 	SoPlugins                          []string                     `yaml:"so-plugins,omitempty"`
@@ -190,12 +200,25 @@ type StaticConfig struct {
 	ExternalTraceIntegrationDisabled   *bool                        `yaml:"external-trace-integration-disabled,omitempty"`
 	ExternalMetricIntegrationDisabled  *bool                        `yaml:"external-metric-integration-disabled,omitempty"`
 	ExternalLogIntegrationDisabled     *bool                        `yaml:"external-log-integration-disabled,omitempty"`
+	ExternalJaegerCompactPort          *int                         `yaml:"external-jaeger-compact-port,omitempty"`
 	NtpMaxInterval                     *string                      `yaml:"ntp-max-interval,omitempty"`
 	NtpMinInterval                     *string                      `yaml:"ntp-min-interval,omitempty"`
 	DispatcherQueue                    *bool                        `yaml:"dispatcher-queue,omitempty"`
 	EbpfCollectorQueueSize             *int                         `yaml:"ebpf-collector-queue-size,omitempty"`
 }
 
+type ModuleLogLevel struct {
+	Module   *string `yaml:"module,omitempty"`
+	Level    *string `yaml:"level,omitempty"`
+	Duration *string `yaml:"duration,omitempty"`
+}
+
+type FeatureFlagRollout struct {
+	Name       *string  `yaml:"name,omitempty"`
+	Percentage *int     `yaml:"percentage,omitempty"`
+	Groups     []string `yaml:"groups,omitempty"`
+}
+
 type XflowCollectorConfig struct {
 	SflowPorts   []string `yaml:"sflow-ports,omitempty"`
 	NetflowPorts []string `yaml:"netflow-ports,omitempty"`
@@ -209,6 +232,20 @@ type PCapConfig struct {
 	FlushInterval  *string `yaml:"flush-interval,omitempty"`
 }
 
+type QueueOverflowConfig struct {
+	L4FlowLog             *string `yaml:"l4-flow-log,omitempty"`
+	L7FlowLog             *string `yaml:"l7-flow-log,omitempty"`
+	Metrics               *string `yaml:"metrics,omitempty"`
+	BlockTimeout          *string `yaml:"block-timeout,omitempty"`
+	BackpressureWatermark *uint8  `yaml:"backpressure-watermark,omitempty"`
+	SlowRequestThreshold  *string `yaml:"slow-request-threshold,omitempty"`
+}
+
+type PreAggregationRule struct {
+	ServerPorts    *string  `yaml:"server-ports,omitempty"`
+	DropDimensions []string `yaml:"drop-dimensions,omitempty"`
+}
+
 type TripleMapConfig struct {
 	HashSlots *int `yaml:"hash-slots-size,omitempty"`
 	Capacity  *int `yaml:"capacity,omitempty"`
@@ -221,10 +258,18 @@ type TcpTimeoutConfig struct {
 	OpeningRstTimeout  *string `yaml:"opening-rst-timeout,omitempty"`
 }
 
+type L4TimeoutConfig struct {
+	UdpTimeout           *string `yaml:"udp-timeout,omitempty"`
+	OtherProtocolTimeout *string `yaml:"other-protocol-timeout,omitempty"`
+}
+
 type FlowGeneratorConfig struct {
 	TcpTimeoutConfig `yaml:",inline"`
+	L4TimeoutConfig  `yaml:",inline"`
 	HashSlots        *int    `yaml:"flow-slots-size,omitempty"`
 	Capacity         *int    `yaml:"flow-count-limit,omitempty"`
+	MemoryBudgetMB   *int    `yaml:"memory-budget-mb,omitempty"`
+	EvictionPolicy   *string `yaml:"eviction-policy,omitempty"`
 	FlushInterval    *string `yaml:"flush-interval,omitempty"`
 	AggrQueueSize    *int    `yaml:"flow-aggr-queue-size,omitempty"`
 	MemoryPoolSize   *int    `yaml:"memory-pool-size,omitempty"`
@@ -238,6 +283,8 @@ type EbpfUprobeProcessNameRegexsConfig struct {
 	GolangSymbol *string `yaml:"golang-symbol,omitempty"`
 	Golang       *string `yaml:"golang,omitempty"`
 	Openssl      *string `yaml:"openssl,omitempty"`
+	Usdt         *string `yaml:"usdt,omitempty"`
+	Rustls       *string `yaml:"rustls,omitempty"`
 }
 
 type EbpfKprobePortlist struct {
@@ -245,10 +292,11 @@ type EbpfKprobePortlist struct {
 }
 
 type OnCpuProfile struct {
-	Disabled  *bool   `yaml:"disabled,omitempty"`
-	Frequency *int    `yaml:"frequency,omitempty"`
-	Cpu       *int    `yaml:"cpu,omitempty"`
-	Regex     *string `yaml:"regex,omitempty"`
+	Disabled   *bool   `yaml:"disabled,omitempty"`
+	Frequency  *int    `yaml:"frequency,omitempty"`
+	Cpu        *int    `yaml:"cpu,omitempty"`
+	Regex      *string `yaml:"regex,omitempty"`
+	DwarfRegex *string `yaml:"dwarf-regex,omitempty"`
 }
 
 type OffCpuProfile struct {
@@ -258,6 +306,12 @@ type OffCpuProfile struct {
 	MinBlock *string `yaml:"minblock,omitempty"`
 }
 
+type PprofExport struct {
+	Enabled   *bool   `yaml:"enabled,omitempty"`
+	Window    *string `yaml:"window,omitempty"`
+	MaxStacks *int    `yaml:"max-stacks,omitempty"`
+}
+
 type EbpfConfig struct {
 	Disabled                           *bool                              `yaml:"disabled,omitempty"`
 	GlobalEbpfPpsThreshold             *int                               `yaml:"global-ebpf-pps-threshold,omitempty"`
@@ -276,6 +330,7 @@ type EbpfConfig struct {
 	JavaSymbolFileRefreshDeferInterval *string                            `yaml:"java-symbol-file-refresh-defer-interval,omitempty"`
 	OnCpuProfile                       *OnCpuProfile                      `yaml:"on-cpu-profile,omitempty"`
 	OffCpuProfile                      *OffCpuProfile                     `yaml:"off-cpu-profile,omitempty"`
+	PprofExport                        *PprofExport                       `yaml:"pprof-export,omitempty"`
 	SyscallOutOfOrderReassembly        []string                           `yaml:"syscall-out-of-order-reassembly,omitempty"`
 	SyscallSegmentationReassembly      []string                           `yaml:"syscall-segmentation-reassembly,omitempty"`
 	SyscallOutOfOrderCacheSize         *int                               `yaml:"syscall-out-of-order-cache-size,omitempty"`
@@ -288,6 +343,14 @@ type OsProcRegex struct {
 	RewriteName *string `yaml:"rewrite-name,omitempty"`
 }
 
+type OsProcServiceRule struct {
+	ExeRegex        *string           `yaml:"exe-regex,omitempty"`
+	CmdlineRegex    *string           `yaml:"cmdline-regex,omitempty"`
+	ContainerLabels map[string]string `yaml:"container-labels,omitempty"`
+	ServiceName     *string           `yaml:"service-name,omitempty"`
+	Tags            map[string]string `yaml:"tags,omitempty"`
+}
+
 type PrometheusExtraConfig struct {
 	Enabled     *bool    `yaml:"enabled,omitempty"`
 	Labels      []string `yaml:"labels,omitempty"`
@@ -1641,6 +1641,7 @@ CREATE TABLE IF NOT EXISTS npb_tunnel (
     team_id             INTEGER DEFAULT 1,
     name                CHAR(64) NOT NULL,
     ip                  CHAR(64),
+    backup_ips          TEXT COMMENT 'separated by , in the order to fail over to',
     type                INTEGER COMMENT '(0-VXLAN；1-ERSPAN)',
     vni_input_type      TINYINT(1) DEFAULT 1 COMMENT '1. entire one 2. two parts',
     created_at          TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
@@ -346,6 +346,7 @@ type NpbTunnel struct {
 	ID           int       `gorm:"primaryKey;column:id;type:int;not null" json:"ID"`
 	Name         string    `gorm:"column:name;type:char(64);not null" json:"NAME"`
 	IP           string    `gorm:"column:ip;type:char(64);default:null" json:"IP"`
+	BackupIPs    string    `gorm:"column:backup_ips;type:text;default:null" json:"BACKUP_IPS"`            // separated by , in the order to fail over to
 	Type         int       `gorm:"column:type;type:int;default:0" json:"TYPE"`                            // (0-VXLAN；1-ERSPAN)
 	VNIInputType int       `gorm:"column:vni_input_type;type:tinyint(1);default:1" json:"VNI_INPUT_TYPE"` // 1: entire one, 2: two parts
 	CreatedAt    time.Time `gorm:"column:created_at;type:timestamp;not null;default:CURRENT_TIMESTAMP" json:"CREATED_AT"`
@@ -625,15 +625,22 @@ func (op *PolicyDataOP) generateProtoActions(acl *models.ACL) (map[int][]*triden
 			if npbPolicy.Vni != nil {
 				tunnelID = proto.Uint32(uint32(*npbPolicy.Vni))
 			}
+			var backupTunnelIPs []string
+			for _, ip := range strings.Split(npbTunnel.BackupIPs, ",") {
+				if ip = strings.TrimSpace(ip); ip != "" {
+					backupTunnelIPs = append(backupTunnelIPs, ip)
+				}
+			}
 			npbAction := &trident.NpbAction{
-				TunnelId:      tunnelID,
-				TunnelIp:      proto.String(npbTunnel.IP),
-				TapSide:       &tapSideSRC,
-				TunnelType:    &tunnelType,
-				PayloadSlice:  proto.Uint32(uint32(payloadSlice)),
-				TunnelIpId:    proto.Uint32(uint32(npbTunnel.ID)),
-				NpbAclGroupId: proto.Uint32(uint32(npbPolicy.PolicyACLGroupID)),
-				Direction:     &direction,
+				TunnelId:        tunnelID,
+				TunnelIp:        proto.String(npbTunnel.IP),
+				TapSide:         &tapSideSRC,
+				TunnelType:      &tunnelType,
+				PayloadSlice:    proto.Uint32(uint32(payloadSlice)),
+				TunnelIpId:      proto.Uint32(uint32(npbTunnel.ID)),
+				NpbAclGroupId:   proto.Uint32(uint32(npbPolicy.PolicyACLGroupID)),
+				Direction:       &direction,
+				BackupTunnelIps: backupTunnelIPs,
 			}
 			if len(npbPolicy.VtapIDs) == 0 {
 				allVTapNpbActions = append(allVTapNpbActions, npbAction)